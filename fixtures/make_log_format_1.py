@@ -0,0 +1,49 @@
+#!/usr/bin/env python3
+"""
+Writes fixtures/log-format-1: partition directories as a broker writing log
+layout version 1 left them, for the upgrade test to lay over a copy of
+kraft-combined-logs.
+
+Version 1 had no partition.metadata and no checkpoint: a partition directory
+held its segment and, once DeleteRecords had moved the log start offset, a
+`log-start-offset` file with the bare offset.
+
+- foo-0: two batches, offsets 0-2, with the log start moved to 1.
+- bar-0: an empty segment, as a partition nothing was produced to has.
+- bar-1: three records all deleted, so the segment was rolled to an empty one
+  at offset 3.
+- baz-0: no directory; the broker creates one on its next start.
+
+Re-run after changing the partitions below and commit the regenerated files.
+"""
+
+import os
+import shutil
+
+from make_metadata_log import BAR, FIXTURES_DIR, FOO, batch, write
+
+LOG_DIR = os.path.join(FIXTURES_DIR, "log-format-1")
+
+def partition_dir(topic, partition):
+    _, topic_id, _ = topic
+    return os.path.join(LOG_DIR, f"{topic_id.hex}-{partition}")
+
+def segment(directory, base_offset, data=b""):
+    write(os.path.join(directory, f"{base_offset:020d}.log"), data)
+
+def main():
+    shutil.rmtree(LOG_DIR, ignore_errors=True)
+    write(os.path.join(LOG_DIR, "eventor-log-format"), b"version=1\n")
+
+    foo = partition_dir(FOO, 0)
+    segment(foo, 0, batch(0, [b"legacy-a", b"legacy-b"]) + batch(2, [b"legacy-c"]))
+    write(os.path.join(foo, "log-start-offset"), b"1\n")
+
+    segment(partition_dir(BAR, 0), 0)
+
+    bar_1 = partition_dir(BAR, 1)
+    segment(bar_1, 3)
+    write(os.path.join(bar_1, "log-start-offset"), b"3\n")
+
+if __name__ == "__main__":
+    main()
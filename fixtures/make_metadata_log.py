@@ -0,0 +1,131 @@
+#!/usr/bin/env python3
+"""
+Writes the KRaft __cluster_metadata fixtures used by the test suite.
+
+The layout matches what a KRaft controller writes: a FeatureLevelRecord, then
+per topic a TopicRecord followed by its PartitionRecords, one batch per topic.
+
+- kraft-combined-logs (fixtures/server.properties): a snapshot holding foo and
+  bar, and a log whose records up to the snapshot's end offset repeat it and
+  whose later records create baz. Its meta.properties carries CLUSTER_ID, as
+  `kafka-storage format` would write it.
+- kraft-snapshot-only (fixtures/snapshot-only.properties): a snapshot holding
+  snap and no log records after it.
+
+Re-run after changing the topics below and commit the regenerated files.
+"""
+
+import os
+import struct
+import uuid
+
+FIXTURES_DIR = os.path.dirname(os.path.abspath(__file__))
+FOO = ("foo", uuid.UUID("00000000-0000-4000-8000-000000000f00"), 1)
+BAR = ("bar", uuid.UUID("00000000-0000-4000-8000-000000000ba7"), 2)
+BAZ = ("baz", uuid.UUID("00000000-0000-4000-8000-000000000ba2"), 1)
+SNAP = ("snap", uuid.UUID("00000000-0000-4000-8000-00000000005a"), 1)
+CLUSTER_ID = "eventor-fixture-cluster"
+
+def crc32c(data):
+    crc = 0xFFFFFFFF
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = (crc >> 1) ^ (0x82F63B78 if crc & 1 else 0)
+    return crc ^ 0xFFFFFFFF
+
+def uvarint(value):
+    out = bytearray()
+    while value >= 0x80:
+        out.append((value & 0x7F) | 0x80)
+        value >>= 7
+    out.append(value)
+    return bytes(out)
+
+def varint(value):
+    return uvarint((value << 1) ^ (value >> 63))
+
+def compact_string(value):
+    return uvarint(len(value) + 1) + value.encode()
+
+def compact_int32_array(values):
+    return uvarint(len(values) + 1) + b"".join(struct.pack(">i", v) for v in values)
+
+def record(offset_delta, key, value):
+    key_field = varint(-1) if key is None else varint(len(key)) + key
+    body = b"\x00" + varint(0) + varint(offset_delta) + key_field + varint(len(value)) + value + varint(0)
+    return varint(len(body)) + body
+
+def batch(base_offset, values, keys=None, attributes=0):
+    keys = keys or [None] * len(values)
+    records = b"".join(record(i, k, v) for i, (k, v) in enumerate(zip(keys, values)))
+    after_crc = struct.pack(">hiqqqhii", attributes, len(values) - 1, 0, 0, -1, -1, -1, len(values)) + records
+    after_length = struct.pack(">ibI", 0, 2, crc32c(after_crc)) + after_crc
+    return struct.pack(">qi", base_offset, len(after_length)) + after_length
+
+def feature_level_record(name, level):
+    return b"\x01\x0c\x00" + compact_string(name) + struct.pack(">h", level) + b"\x00"
+
+def topic_record(name, topic_id):
+    return b"\x01\x02\x00" + compact_string(name) + topic_id.bytes + b"\x00"
+
+def partition_record(partition_id, topic_id):
+    return (b"\x01\x03\x00" + struct.pack(">i", partition_id) + topic_id.bytes
+            + compact_int32_array([1]) + compact_int32_array([1])  # replicas, isr
+            + compact_int32_array([]) + compact_int32_array([])    # removing, adding
+            + struct.pack(">iii", 1, 0, 0)                          # leader, leader/partition epoch
+            + b"\x00")
+
+# Snapshot header/footer control records: key is (version, type), type 4 = header, 5 = footer
+CONTROL_BATCH = 0x20
+
+def snapshot_header():
+    return batch(0, [struct.pack(">hq", 0, 0)], keys=[struct.pack(">hh", 0, 4)], attributes=CONTROL_BATCH)
+
+def snapshot_footer(base_offset):
+    return batch(base_offset, [struct.pack(">h", 0)], keys=[struct.pack(">hh", 0, 5)], attributes=CONTROL_BATCH)
+
+def topic_batches(topics, bootstrap=True):
+    """A fresh cluster's log starts with its feature levels; later creates don't."""
+    batches = [[feature_level_record("metadata.version", 20)]] if bootstrap else []
+    for name, topic_id, partitions in topics:
+        batches.append([topic_record(name, topic_id)]
+                       + [partition_record(p, topic_id) for p in range(partitions)])
+    return batches
+
+def encode_batches(batches, base_offset):
+    data = bytearray()
+    for values in batches:
+        data += batch(base_offset, values)
+        base_offset += len(values)
+    return bytes(data), base_offset
+
+def write(path, data):
+    os.makedirs(os.path.dirname(path), exist_ok=True)
+    with open(path, "wb") as f:
+        f.write(data)
+    print(f"Wrote {len(data)} bytes to {path}")
+
+def snapshot_file(partition_dir, end_offset, epoch=1):
+    return os.path.join(partition_dir, f"{end_offset:020d}-{epoch:010d}.checkpoint")
+
+def write_snapshot(partition_dir, topics):
+    records, end_offset = encode_batches(topic_batches(topics), 0)
+    write(snapshot_file(partition_dir, end_offset), snapshot_header() + records + snapshot_footer(end_offset))
+    return end_offset
+
+def main():
+    combined = os.path.join(FIXTURES_DIR, "kraft-combined-logs", "__cluster_metadata-0")
+    snapshot_end = write_snapshot(combined, [FOO, BAR])
+    before_snapshot, offset = encode_batches(topic_batches([FOO, BAR]), 0)
+    assert offset == snapshot_end
+    after_snapshot, _ = encode_batches(topic_batches([BAZ], bootstrap=False), snapshot_end)
+    write(os.path.join(combined, "00000000000000000000.log"), before_snapshot + after_snapshot)
+    meta_properties = f"version=1\nnode.id=1\ncluster.id={CLUSTER_ID}\n"
+    write(os.path.join(os.path.dirname(combined), "meta.properties"), meta_properties.encode())
+
+    snapshot_only = os.path.join(FIXTURES_DIR, "kraft-snapshot-only", "__cluster_metadata-0")
+    write_snapshot(snapshot_only, [SNAP])
+
+if __name__ == "__main__":
+    main()
@@ -13,4 +13,4 @@ PROGRAM_NAME="Eventor"
 
 echo "Running your Event Stream Processor in the foreground (Ctrl+C to stop)..."
 # Run the compiled program directly
-./target/release/"$PROGRAM_NAME"
\ No newline at end of file
+./target/release/"$PROGRAM_NAME" "$@"
\ No newline at end of file
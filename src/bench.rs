@@ -0,0 +1,986 @@
+//! Load generation against an in-process broker.
+//!
+//! `Eventor bench <workload.properties> [server.properties]` starts the broker
+//! in this process on a loopback port of its own, then drives the workload the
+//! properties file describes at it over real sockets for a fixed duration:
+//! producer connections appending batches to a bench topic, consumer
+//! connections fetching them back, admin connections cycling through Metadata,
+//! CreateTopics and DeleteTopics, Metadata connections, ApiVersions
+//! connections, and OffsetCommit connections. Each connection sends one
+//! request at a time and times it, so latencies are what a client sees,
+//! response parsing included; OffsetCommit connections instead pipeline
+//! `offset.commit.pipeline` commits at a time, timing each from when the lot
+//! was sent.
+//!
+//! At the end it prints per-role throughput and latency percentiles, and what
+//! the broker appended and how often it flushed its logs in the meantime,
+//! after the broker's own log lines. With `log.store=file` and
+//! `log.flush.on.acks.all=true` in the server.properties, an acks=-1 workload
+//! shows how many produces share each flush. It
+//! also counts the process's allocations during the run and how the response
+//! buffer pools did (see `buffers`); running a workload again with
+//! `response.buffer.pool=false` shows what the pools save. It counts the
+//! writes the broker's responses went out in, too, which pipelined
+//! OffsetCommits share unless the server.properties sets
+//! `response.coalesce.max.bytes=1`. With `report.json`
+//! set the same figures are written there as JSON. With
+//! `max.api.versions.p99.us` set the run fails when ApiVersions latency is over
+//! it, so a smoke run can catch regressions.
+//!
+//! Every request uses the oldest version the broker supports, all of them
+//! classic, which keeps the client side of the bench small.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{Read, Write};
+use std::net::{SocketAddr, TcpStream};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::broker::Broker;
+use crate::buffers::{self, BufferStats};
+use crate::codec::{write_array_len, write_bytes, write_nullable_string, write_string, Decoder};
+use crate::config::parse_properties;
+use crate::record_batch::{self, Producer, RecordBatch};
+
+const PRODUCE_KEY: i16 = 0;
+const FETCH_KEY: i16 = 1;
+const LIST_OFFSETS_KEY: i16 = 2;
+const METADATA_KEY: i16 = 3;
+const OFFSET_COMMIT_KEY: i16 = 8;
+const API_VERSIONS_KEY: i16 = 18;
+const CREATE_TOPICS_KEY: i16 = 19;
+const DELETE_TOPICS_KEY: i16 = 20;
+
+const TOPIC_ALREADY_EXISTS: i16 = 36;
+const CLIENT_ID: &str = "eventor-bench";
+/// Kept short so consumers at the log end notice the run is over promptly.
+const FETCH_MAX_WAIT_MS: i32 = 100;
+const FETCH_MAX_BYTES: i32 = 1024 * 1024;
+const REQUEST_TIMEOUT_MS: i32 = 5_000;
+
+/// The system allocator, counting allocations while a bench run is measuring.
+/// Outside a bench it costs one relaxed load per allocation.
+struct CountingAllocator;
+
+static COUNTING: AtomicBool = AtomicBool::new(false);
+static ALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+#[global_allocator]
+static ALLOCATOR: CountingAllocator = CountingAllocator;
+
+impl CountingAllocator {
+    fn count(&self) {
+        if COUNTING.load(Ordering::Relaxed) {
+            ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+// Every call is passed straight to `System`, whose contract is the same
+unsafe impl GlobalAlloc for CountingAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        System.alloc(layout)
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        self.count();
+        System.alloc_zeroed(layout)
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        self.count();
+        System.realloc(ptr, layout, new_size)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+/// What a bench run drives at the broker, from a workload properties file.
+/// Every key is optional; rates are requests per second per connection, 0
+/// meaning as fast as responses come back.
+pub struct Workload {
+    pub duration: Duration,
+    /// Created before the run if it doesn't exist.
+    pub topic: String,
+    pub partitions: i32,
+    pub producers: usize,
+    pub producer_rate: u32,
+    /// 1 or -1; acks=0 produces get no response to time.
+    pub producer_acks: i16,
+    pub records_per_batch: usize,
+    pub record_bytes: usize,
+    pub consumers: usize,
+    /// Consumers start at the log start offset, or at the end and read only
+    /// what is produced during the run.
+    pub consume_from_earliest: bool,
+    pub admin_connections: usize,
+    pub admin_rate: u32,
+    pub metadata_connections: usize,
+    pub metadata_rate: u32,
+    /// Topics besides the bench topic created before the run, each with the
+    /// bench topic's partitions, for Metadata responses to list.
+    pub metadata_topics: usize,
+    pub api_versions_connections: usize,
+    pub api_versions_rate: u32,
+    /// Each commits offsets for a group of its own for every partition of the
+    /// bench topic, as fast as responses come back.
+    pub offset_commit_connections: usize,
+    /// OffsetCommits each connection sends before reading their responses.
+    pub offset_commit_pipeline: usize,
+    /// Off to have every response grown from an empty buffer, for comparison.
+    pub response_buffer_pool: bool,
+    pub report_json: Option<PathBuf>,
+    pub max_api_versions_p99: Option<Duration>,
+}
+
+impl Workload {
+    pub fn load(path: &Path) -> Result<Workload> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading workload file {}", path.display()))?;
+        let properties = parse_properties(&contents);
+        let workload = Workload {
+            duration: Duration::from_millis(number(&properties, "duration.ms", 10_000)?),
+            topic: properties
+                .get("topic")
+                .cloned()
+                .unwrap_or_else(|| "eventor-bench".to_string()),
+            partitions: number(&properties, "topic.partitions", 1)?,
+            producers: number(&properties, "producers", 1)?,
+            producer_rate: number(&properties, "producer.rate", 0)?,
+            producer_acks: number(&properties, "producer.acks", 1)?,
+            records_per_batch: number(&properties, "producer.records.per.batch", 10)?,
+            record_bytes: number(&properties, "producer.record.bytes", 100)?,
+            consumers: number(&properties, "consumers", 1)?,
+            consume_from_earliest: match properties.get("consumer.start").map(String::as_str) {
+                None | Some("earliest") => true,
+                Some("latest") => false,
+                Some(other) => bail!("invalid consumer.start '{}'", other),
+            },
+            admin_connections: number(&properties, "admin.connections", 0)?,
+            admin_rate: number(&properties, "admin.rate", 10)?,
+            metadata_connections: number(&properties, "metadata.connections", 0)?,
+            metadata_rate: number(&properties, "metadata.rate", 100)?,
+            metadata_topics: number(&properties, "metadata.topics", 0)?,
+            api_versions_connections: number(&properties, "api.versions.connections", 1)?,
+            api_versions_rate: number(&properties, "api.versions.rate", 100)?,
+            offset_commit_connections: number(&properties, "offset.commit.connections", 0)?,
+            offset_commit_pipeline: number(&properties, "offset.commit.pipeline", 8)?,
+            response_buffer_pool: number(&properties, "response.buffer.pool", true)?,
+            report_json: properties.get("report.json").map(PathBuf::from),
+            max_api_versions_p99: properties
+                .contains_key("max.api.versions.p99.us")
+                .then(|| number(&properties, "max.api.versions.p99.us", 0))
+                .transpose()?
+                .map(Duration::from_micros),
+        };
+        if workload.topic.is_empty() || workload.partitions < 1 {
+            bail!("the bench needs a topic with at least one partition");
+        }
+        if workload.producer_acks != 1 && workload.producer_acks != -1 {
+            bail!(
+                "invalid producer.acks '{}': 1 or -1",
+                workload.producer_acks
+            );
+        }
+        if workload.records_per_batch == 0 {
+            bail!("invalid producer.records.per.batch '0'");
+        }
+        if workload.offset_commit_pipeline == 0 {
+            bail!("invalid offset.commit.pipeline '0'");
+        }
+        Ok(workload)
+    }
+}
+
+fn number<T: FromStr>(properties: &HashMap<String, String>, key: &str, default: T) -> Result<T> {
+    match properties.get(key) {
+        Some(value) => value
+            .parse()
+            .ok()
+            .with_context(|| format!("invalid {} '{}'", key, value)),
+        None => Ok(default),
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Producer,
+    Consumer,
+    Admin,
+    Metadata,
+    ApiVersions,
+    OffsetCommit,
+}
+
+const ROLES: [Role; 6] = [
+    Role::Producer,
+    Role::Consumer,
+    Role::Admin,
+    Role::Metadata,
+    Role::ApiVersions,
+    Role::OffsetCommit,
+];
+
+impl Role {
+    fn name(self) -> &'static str {
+        match self {
+            Role::Producer => "producer",
+            Role::Consumer => "consumer",
+            Role::Admin => "admin",
+            Role::Metadata => "metadata",
+            Role::ApiVersions => "api_versions",
+            Role::OffsetCommit => "offset_commit",
+        }
+    }
+
+    fn connections(self, workload: &Workload) -> usize {
+        match self {
+            Role::Producer => workload.producers,
+            Role::Consumer => workload.consumers,
+            Role::Admin => workload.admin_connections,
+            Role::Metadata => workload.metadata_connections,
+            Role::ApiVersions => workload.api_versions_connections,
+            Role::OffsetCommit => workload.offset_commit_connections,
+        }
+    }
+}
+
+/// What the connections of one role did.
+#[derive(Default)]
+struct Tally {
+    requests: u64,
+    /// Requests answered with an error code anywhere in the response.
+    errors: u64,
+    /// Record batch bytes produced or fetched.
+    bytes: u64,
+    /// Records acknowledged by produces or returned by fetches.
+    records: u64,
+    latencies: Vec<Duration>,
+}
+
+impl Tally {
+    fn merge(&mut self, other: Tally) {
+        self.requests += other.requests;
+        self.errors += other.errors;
+        self.bytes += other.bytes;
+        self.records += other.records;
+        self.latencies.extend(other.latencies);
+    }
+
+    /// The latency `percent` of requests took at most (nearest rank).
+    fn percentile(&self, percent: usize) -> Duration {
+        let rank = (self.latencies.len() * percent).div_ceil(100);
+        self.latencies
+            .get(rank.saturating_sub(1))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Runs the workload in `workload_path` against `broker`, which is serving on
+/// `address`, and reports on it.
+pub fn run(workload_path: &Path, broker: &Broker, address: SocketAddr) -> Result<()> {
+    let workload = Workload::load(workload_path)?;
+    let mut setup = Connection::connect(address)?;
+    create_topic(&mut setup, &workload.topic, workload.partitions, true)?;
+    for index in 0..workload.metadata_topics {
+        let topic = format!("{}-metadata-{}", workload.topic, index);
+        create_topic(&mut setup, &topic, workload.partitions, true)?;
+    }
+    drop(setup);
+    let appended_before = appended_records(broker, &workload.topic);
+    let flushes_before = broker.flusher.flushes();
+    let responses_before = responses_written(broker);
+
+    buffers::set_pooling(workload.response_buffer_pool);
+    let buffers_before = buffers::stats();
+    let allocations_before = ALLOCATIONS.load(Ordering::Relaxed);
+    COUNTING.store(true, Ordering::Relaxed);
+    let started = Instant::now();
+    let end = started + workload.duration;
+    let results: Vec<(Role, Result<Tally>)> = thread::scope(|scope| {
+        let workers: Vec<_> = ROLES
+            .iter()
+            .flat_map(|&role| (0..role.connections(&workload)).map(move |index| (role, index)))
+            .map(|(role, index)| {
+                let workload = &workload;
+                (
+                    role,
+                    scope.spawn(move || drive(role, index, workload, address, end)),
+                )
+            })
+            .collect();
+        workers
+            .into_iter()
+            .map(|(role, worker)| {
+                let result = worker
+                    .join()
+                    .unwrap_or_else(|_| Err(anyhow::anyhow!("bench connection panicked")));
+                (role, result)
+            })
+            .collect()
+    });
+    let elapsed = started.elapsed();
+    COUNTING.store(false, Ordering::Relaxed);
+    let allocations = ALLOCATIONS.load(Ordering::Relaxed) - allocations_before;
+    let buffers = buffers::stats().since(buffers_before);
+
+    let mut tallies: Vec<(Role, Tally)> = ROLES
+        .iter()
+        .filter(|role| role.connections(&workload) > 0)
+        .map(|&role| (role, Tally::default()))
+        .collect();
+    for (role, result) in results {
+        let tally = result.with_context(|| format!("{} connection failed", role.name()))?;
+        if let Some((_, total)) = tallies.iter_mut().find(|(r, _)| *r == role) {
+            total.merge(tally);
+        }
+    }
+    for (_, tally) in &mut tallies {
+        tally.latencies.sort_unstable();
+    }
+    let appended = appended_records(broker, &workload.topic) - appended_before;
+    let flushes = broker.flusher.flushes() - flushes_before;
+    let (responses, response_writes) = responses_written(broker);
+    let responses = (
+        responses - responses_before.0,
+        response_writes - responses_before.1,
+    );
+
+    let report = Report {
+        workload_path,
+        workload: &workload,
+        elapsed,
+        tallies: &tallies,
+        appended,
+        flushes,
+        responses,
+        allocations,
+        buffers,
+    };
+    println!("{}", report.human());
+    if let Some(path) = &workload.report_json {
+        fs::write(path, report.json())
+            .with_context(|| format!("writing bench report {}", path.display()))?;
+    }
+    if let Some(max) = workload.max_api_versions_p99 {
+        if let Some((_, tally)) = tallies.iter().find(|(role, _)| *role == Role::ApiVersions) {
+            let p99 = tally.percentile(99);
+            if p99 > max {
+                bail!(
+                    "ApiVersions p99 of {} us is over max.api.versions.p99.us {}",
+                    p99.as_micros(),
+                    max.as_micros()
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Records in the topic's partition logs, counted from the broker's side.
+fn appended_records(broker: &Broker, topic: &str) -> i64 {
+    let Some((topic_id, partitions)) = broker
+        .metadata()
+        .topic(topic)
+        .map(|topic| (topic.topic_id, topic.partitions.clone()))
+    else {
+        return 0;
+    };
+    partitions
+        .into_iter()
+        .filter_map(|partition| broker.logs().offsets(topic_id, partition).ok())
+        .map(|offsets| offsets.log_end_offset.0 - offsets.log_start_offset.0)
+        .sum()
+}
+
+/// The responses the broker has sent on every listener, and the writes they
+/// took.
+fn responses_written(broker: &Broker) -> (u64, u64) {
+    broker
+        .listener_stats
+        .iter()
+        .map(|stats| stats.responses())
+        .fold((0, 0), |(responses, writes), (r, w)| {
+            (responses + r, writes + w)
+        })
+}
+
+/// One connection's share of the workload, until `end`.
+fn drive(
+    role: Role,
+    index: usize,
+    workload: &Workload,
+    address: SocketAddr,
+    end: Instant,
+) -> Result<Tally> {
+    let mut connection = Connection::connect(address)?;
+    let mut tally = Tally::default();
+    match role {
+        Role::Producer => {
+            let batch = build_batch(workload.records_per_batch, workload.record_bytes);
+            let mut partition = index as i32 % workload.partitions;
+            paced(end, workload.producer_rate, &mut tally, |tally| {
+                let (error_code, records) = produce(&mut connection, workload, partition, &batch)?;
+                if error_code == 0 {
+                    tally.records += records;
+                    tally.bytes += batch.len() as u64;
+                }
+                partition = (partition + 1) % workload.partitions;
+                Ok(error_code)
+            })?;
+        }
+        Role::Consumer => {
+            let timestamp = if workload.consume_from_earliest {
+                -2
+            } else {
+                -1
+            };
+            let mut offsets = list_offsets(
+                &mut connection,
+                &workload.topic,
+                workload.partitions,
+                timestamp,
+            )?;
+            paced(end, 0, &mut tally, |tally| {
+                fetch(&mut connection, &workload.topic, &mut offsets, tally)
+            })?;
+        }
+        Role::Admin => {
+            let scratch = format!("{}-admin-{}", workload.topic, index);
+            let mut step = 0;
+            paced(end, workload.admin_rate, &mut tally, |_| {
+                step += 1;
+                match step % 3 {
+                    1 => metadata(&mut connection),
+                    2 => create_topic(&mut connection, &scratch, 1, false),
+                    _ => delete_topic(&mut connection, &scratch),
+                }
+            })?;
+        }
+        Role::Metadata => {
+            paced(end, workload.metadata_rate, &mut tally, |_| {
+                metadata(&mut connection)
+            })?;
+        }
+        Role::ApiVersions => {
+            paced(end, workload.api_versions_rate, &mut tally, |_| {
+                let response = connection.send(API_VERSIONS_KEY, 0, &[])?;
+                Ok(Decoder::new(&response).read_i16()?)
+            })?;
+        }
+        Role::OffsetCommit => {
+            let group = format!("{}-group-{}", workload.topic, index);
+            let mut offset = 0;
+            while Instant::now() < end {
+                offset += 1;
+                let body = offset_commit_body(workload, &group, offset);
+                let sent = Instant::now();
+                let requests = vec![body.as_slice(); workload.offset_commit_pipeline];
+                let correlation_ids = connection.write_requests(OFFSET_COMMIT_KEY, 2, &requests)?;
+                for correlation_id in correlation_ids {
+                    let response = connection.read_response(correlation_id)?;
+                    tally.latencies.push(sent.elapsed());
+                    tally.requests += 1;
+                    if offset_commit_error(&response)? != 0 {
+                        tally.errors += 1;
+                    }
+                }
+            }
+        }
+    }
+    Ok(tally)
+}
+
+/// Sends requests until `end`, at most `rate` a second when it isn't 0,
+/// timing each one. `request` returns the response's error code.
+fn paced(
+    end: Instant,
+    rate: u32,
+    tally: &mut Tally,
+    mut request: impl FnMut(&mut Tally) -> Result<i16>,
+) -> Result<()> {
+    let interval = (rate > 0).then(|| Duration::from_secs(1) / rate);
+    let mut next = Instant::now();
+    while next < end {
+        let now = Instant::now();
+        if now < next {
+            thread::sleep(next - now);
+        }
+        let sent = Instant::now();
+        let error_code = request(tally)?;
+        tally.latencies.push(sent.elapsed());
+        tally.requests += 1;
+        if error_code != 0 {
+            tally.errors += 1;
+        }
+        next = match interval {
+            Some(interval) => next + interval,
+            None => Instant::now(),
+        };
+    }
+    Ok(())
+}
+
+/// A client connection sending requests with header v1, one at a time or
+/// pipelined.
+struct Connection {
+    stream: TcpStream,
+    next_correlation_id: u32,
+}
+
+impl Connection {
+    fn connect(address: SocketAddr) -> Result<Connection> {
+        let stream = TcpStream::connect(address)
+            .with_context(|| format!("connecting to the broker on {}", address))?;
+        stream.set_nodelay(true)?;
+        Ok(Connection {
+            stream,
+            next_correlation_id: 0,
+        })
+    }
+
+    /// Sends a request and returns the response body, after its correlation id.
+    fn send(&mut self, api_key: i16, api_version: i16, body: &[u8]) -> Result<Vec<u8>> {
+        let correlation_ids = self.write_requests(api_key, api_version, &[body])?;
+        self.read_response(correlation_ids[0])
+    }
+
+    /// Sends a request for each of `bodies` in one write, without waiting for
+    /// any response, and returns their correlation ids.
+    fn write_requests(
+        &mut self,
+        api_key: i16,
+        api_version: i16,
+        bodies: &[&[u8]],
+    ) -> Result<Vec<u32>> {
+        let mut requests = Vec::new();
+        let mut correlation_ids = Vec::with_capacity(bodies.len());
+        for body in bodies {
+            let correlation_id = self.next_correlation_id;
+            self.next_correlation_id += 1;
+            let start = requests.len();
+            requests.extend_from_slice(&[0; 4]); // Filled in once the request is complete
+            requests.extend_from_slice(&api_key.to_be_bytes());
+            requests.extend_from_slice(&api_version.to_be_bytes());
+            requests.extend_from_slice(&correlation_id.to_be_bytes());
+            write_nullable_string(&mut requests, Some(CLIENT_ID));
+            requests.extend_from_slice(body);
+            let size = (requests.len() - start - 4) as u32;
+            requests[start..start + 4].copy_from_slice(&size.to_be_bytes());
+            correlation_ids.push(correlation_id);
+        }
+        self.stream.write_all(&requests)?;
+        Ok(correlation_ids)
+    }
+
+    /// Reads the next response, which must be to `correlation_id`, and returns
+    /// its body.
+    fn read_response(&mut self, correlation_id: u32) -> Result<Vec<u8>> {
+        let mut size = [0; 4];
+        self.stream.read_exact(&mut size)?;
+        let mut response = vec![0; u32::from_be_bytes(size) as usize];
+        self.stream.read_exact(&mut response)?;
+        let answered = Decoder::new(&response).read_u32()?;
+        if answered != correlation_id {
+            bail!(
+                "response to correlation id {} came back as {}",
+                correlation_id,
+                answered
+            );
+        }
+        response.drain(..4);
+        Ok(response)
+    }
+}
+
+/// Creates a topic with CreateTopics v2; one that already exists is fine when
+/// `existing_ok`.
+fn create_topic(
+    connection: &mut Connection,
+    topic: &str,
+    partitions: i32,
+    existing_ok: bool,
+) -> Result<i16> {
+    let mut body = Vec::new();
+    write_array_len(&mut body, 1);
+    write_string(&mut body, topic);
+    body.extend_from_slice(&partitions.to_be_bytes());
+    body.extend_from_slice(&1i16.to_be_bytes()); // replication_factor
+    write_array_len(&mut body, 0); // assignments
+    write_array_len(&mut body, 0); // configs
+    body.extend_from_slice(&REQUEST_TIMEOUT_MS.to_be_bytes());
+    body.push(0); // validate_only
+    let response = connection.send(CREATE_TOPICS_KEY, 2, &body)?;
+    let mut decoder = Decoder::new(&response);
+    decoder.read_i32()?; // throttle_time_ms
+    decoder.read_array_len(false)?;
+    decoder.read_string(false)?;
+    let error_code = decoder.read_i16()?;
+    let message = decoder.read_nullable_string(false)?;
+    match error_code {
+        0 => Ok(0),
+        TOPIC_ALREADY_EXISTS if existing_ok => Ok(0),
+        _ if existing_ok => bail!(
+            "creating bench topic {}: error {} ({})",
+            topic,
+            error_code,
+            message.unwrap_or_default()
+        ),
+        _ => Ok(error_code),
+    }
+}
+
+/// Deletes a topic with DeleteTopics v1.
+fn delete_topic(connection: &mut Connection, topic: &str) -> Result<i16> {
+    let mut body = Vec::new();
+    write_array_len(&mut body, 1);
+    write_string(&mut body, topic);
+    body.extend_from_slice(&REQUEST_TIMEOUT_MS.to_be_bytes());
+    let response = connection.send(DELETE_TOPICS_KEY, 1, &body)?;
+    let mut decoder = Decoder::new(&response);
+    decoder.read_i32()?; // throttle_time_ms
+    decoder.read_array_len(false)?;
+    decoder.read_string(false)?;
+    Ok(decoder.read_i16()?)
+}
+
+/// Metadata v1 for every topic. Nothing in it is checked: a full response is
+/// work enough.
+fn metadata(connection: &mut Connection) -> Result<i16> {
+    let response = connection.send(METADATA_KEY, 1, &(-1i32).to_be_bytes())?;
+    if response.is_empty() {
+        bail!("empty Metadata response");
+    }
+    Ok(0)
+}
+
+/// Produce v3 of one batch to one partition. Returns the error code and the
+/// records acknowledged.
+fn produce(
+    connection: &mut Connection,
+    workload: &Workload,
+    partition: i32,
+    batch: &[u8],
+) -> Result<(i16, u64)> {
+    let mut body = Vec::with_capacity(batch.len() + 64);
+    write_nullable_string(&mut body, None); // transactional_id
+    body.extend_from_slice(&workload.producer_acks.to_be_bytes());
+    body.extend_from_slice(&REQUEST_TIMEOUT_MS.to_be_bytes());
+    write_array_len(&mut body, 1);
+    write_string(&mut body, &workload.topic);
+    write_array_len(&mut body, 1);
+    body.extend_from_slice(&partition.to_be_bytes());
+    write_bytes(&mut body, batch);
+    let response = connection.send(PRODUCE_KEY, 3, &body)?;
+    let mut decoder = Decoder::new(&response);
+    decoder.read_array_len(false)?;
+    decoder.read_string(false)?;
+    decoder.read_array_len(false)?;
+    decoder.read_i32()?; // index
+    let error_code = decoder.read_i16()?;
+    Ok((error_code, workload.records_per_batch as u64))
+}
+
+/// Every partition's offset at `timestamp` (-2 earliest, -1 latest), with
+/// ListOffsets v1.
+fn list_offsets(
+    connection: &mut Connection,
+    topic: &str,
+    partitions: i32,
+    timestamp: i64,
+) -> Result<Vec<i64>> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(-1i32).to_be_bytes()); // replica_id
+    write_array_len(&mut body, 1);
+    write_string(&mut body, topic);
+    write_array_len(&mut body, partitions as usize);
+    for partition in 0..partitions {
+        body.extend_from_slice(&partition.to_be_bytes());
+        body.extend_from_slice(&timestamp.to_be_bytes());
+    }
+    let response = connection.send(LIST_OFFSETS_KEY, 1, &body)?;
+    let mut decoder = Decoder::new(&response);
+    decoder.read_array_len(false)?;
+    decoder.read_string(false)?;
+    let count = decoder.read_array_len_or_empty(false)?;
+    let mut offsets = vec![0; partitions as usize];
+    for _ in 0..count {
+        let partition = decoder.read_i32()?;
+        let error_code = decoder.read_i16()?;
+        decoder.read_i64()?; // timestamp
+        let offset = decoder.read_i64()?;
+        if error_code != 0 {
+            bail!(
+                "listing offsets of {}-{}: error {}",
+                topic,
+                partition,
+                error_code
+            );
+        }
+        if let Some(slot) = offsets.get_mut(partition as usize) {
+            *slot = offset;
+        }
+    }
+    Ok(offsets)
+}
+
+/// Fetch v4 of every partition from its next offset, moving the offsets past
+/// the batches returned.
+fn fetch(
+    connection: &mut Connection,
+    topic: &str,
+    offsets: &mut [i64],
+    tally: &mut Tally,
+) -> Result<i16> {
+    let mut body = Vec::new();
+    body.extend_from_slice(&(-1i32).to_be_bytes()); // replica_id
+    body.extend_from_slice(&FETCH_MAX_WAIT_MS.to_be_bytes());
+    body.extend_from_slice(&1i32.to_be_bytes()); // min_bytes
+    body.extend_from_slice(&FETCH_MAX_BYTES.to_be_bytes());
+    body.push(0); // isolation_level
+    write_array_len(&mut body, 1);
+    write_string(&mut body, topic);
+    write_array_len(&mut body, offsets.len());
+    for (partition, offset) in offsets.iter().enumerate() {
+        body.extend_from_slice(&(partition as i32).to_be_bytes());
+        body.extend_from_slice(&offset.to_be_bytes());
+        body.extend_from_slice(&FETCH_MAX_BYTES.to_be_bytes());
+    }
+    let response = connection.send(FETCH_KEY, 4, &body)?;
+    let mut decoder = Decoder::new(&response);
+    decoder.read_i32()?; // throttle_time_ms
+    let mut first_error = 0;
+    for _ in 0..decoder.read_array_len_or_empty(false)? {
+        decoder.read_string(false)?;
+        for _ in 0..decoder.read_array_len_or_empty(false)? {
+            let partition = decoder.read_i32()?;
+            let error_code = decoder.read_i16()?;
+            decoder.read_i64()?; // high_watermark
+            decoder.read_i64()?; // last_stable_offset
+            for _ in 0..decoder.read_array_len_or_empty(false)? {
+                decoder.read_i64()?; // producer_id
+                decoder.read_i64()?; // first_offset
+            }
+            let records = decoder.read_nullable_bytes()?.unwrap_or_default();
+            if error_code != 0 && first_error == 0 {
+                first_error = error_code;
+            }
+            let Some(offset) = offsets.get_mut(partition as usize) else {
+                continue;
+            };
+            tally.bytes += records.len() as u64;
+            for batch in RecordBatch::parse_all(records)? {
+                let next = batch.base_offset.0 + i64::from(batch.last_offset_delta) + 1;
+                if next > *offset {
+                    tally.records += (next - (*offset).max(batch.base_offset.0)) as u64;
+                    *offset = next;
+                }
+            }
+        }
+    }
+    Ok(first_error)
+}
+
+/// OffsetCommit v2 of `offset` for every partition of the bench topic, as a
+/// group nobody has joined takes from any client.
+fn offset_commit_body(workload: &Workload, group: &str, offset: i64) -> Vec<u8> {
+    let mut body = Vec::new();
+    write_string(&mut body, group);
+    body.extend_from_slice(&(-1i32).to_be_bytes()); // generation_id
+    write_string(&mut body, ""); // member_id
+    body.extend_from_slice(&(-1i64).to_be_bytes()); // retention_time_ms
+    write_array_len(&mut body, 1);
+    write_string(&mut body, &workload.topic);
+    write_array_len(&mut body, workload.partitions as usize);
+    for partition in 0..workload.partitions {
+        body.extend_from_slice(&partition.to_be_bytes());
+        body.extend_from_slice(&offset.to_be_bytes());
+        write_nullable_string(&mut body, None); // metadata
+    }
+    body
+}
+
+/// The first error code in an OffsetCommit v2 response, or 0.
+fn offset_commit_error(response: &[u8]) -> Result<i16> {
+    let mut decoder = Decoder::new(response);
+    let mut first_error = 0;
+    for _ in 0..decoder.read_array_len_or_empty(false)? {
+        decoder.read_string(false)?;
+        for _ in 0..decoder.read_array_len_or_empty(false)? {
+            decoder.read_i32()?; // partition_index
+            let error_code = decoder.read_i16()?;
+            if first_error == 0 {
+                first_error = error_code;
+            }
+        }
+    }
+    Ok(first_error)
+}
+
+/// An uncompressed v2 batch of `records` records with `record_bytes`-byte
+/// values and no keys, from a non-idempotent producer. Its base offset is
+/// rewritten on append, so one batch serves every produce.
+fn build_batch(records: usize, record_bytes: usize) -> Vec<u8> {
+    let value = vec![b'x'; record_bytes];
+    let now_ms = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as i64);
+    let records: Vec<(i64, &[u8])> = (0..records).map(|_| (now_ms, value.as_slice())).collect();
+    record_batch::build(&records, Producer::NONE)
+}
+
+struct Report<'a> {
+    workload_path: &'a Path,
+    workload: &'a Workload,
+    elapsed: Duration,
+    tallies: &'a [(Role, Tally)],
+    /// Records the broker's logs for the bench topic grew by.
+    appended: i64,
+    /// Times the broker flushed its partition logs.
+    flushes: u64,
+    /// Responses the broker sent, and the writes they went out in.
+    responses: (u64, u64),
+    /// Allocations in the whole process, bench connections included.
+    allocations: u64,
+    buffers: BufferStats,
+}
+
+impl Report<'_> {
+    fn per_second(&self, count: u64) -> f64 {
+        count as f64 / self.elapsed.as_secs_f64()
+    }
+
+    fn allocations_per_request(&self) -> f64 {
+        let requests: u64 = self.tallies.iter().map(|(_, tally)| tally.requests).sum();
+        self.allocations as f64 / requests.max(1) as f64
+    }
+
+    fn human(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(
+            out,
+            "\nBench of {} over {:.2} s",
+            self.workload_path.display(),
+            self.elapsed.as_secs_f64()
+        );
+        let _ = writeln!(
+            out,
+            "{:<13}{:>6}{:>10}{:>8}{:>11}{:>9}{:>11}{:>9}{:>9}{:>9}",
+            "role",
+            "conns",
+            "requests",
+            "errors",
+            "req/s",
+            "MB/s",
+            "records",
+            "p50 ms",
+            "p99 ms",
+            "max ms"
+        );
+        for (role, tally) in self.tallies {
+            let _ = writeln!(
+                out,
+                "{:<13}{:>6}{:>10}{:>8}{:>11.1}{:>9.2}{:>11}{:>9.3}{:>9.3}{:>9.3}",
+                role.name(),
+                role.connections(self.workload),
+                tally.requests,
+                tally.errors,
+                self.per_second(tally.requests),
+                self.per_second(tally.bytes) / 1e6,
+                tally.records,
+                millis(tally.percentile(50)),
+                millis(tally.percentile(99)),
+                millis(tally.latencies.last().copied().unwrap_or_default())
+            );
+        }
+        let acknowledged = self
+            .tallies
+            .iter()
+            .find(|(role, _)| *role == Role::Producer)
+            .map_or(0, |(_, tally)| tally.records);
+        let _ = write!(
+            out,
+            "Broker appended {} record(s) to {}; {} were acknowledged to producers; \
+             the logs were flushed {} time(s)",
+            self.appended, self.workload.topic, acknowledged, self.flushes
+        );
+        let _ = write!(
+            out,
+            "\nBroker sent {} response(s) in {} write(s)",
+            self.responses.0, self.responses.1
+        );
+        let _ = write!(
+            out,
+            "\n{} allocation(s), {:.1} per request; response buffer pool {}: {} hit(s), \
+             {} miss(es), {} reallocation(s)",
+            self.allocations,
+            self.allocations_per_request(),
+            if self.workload.response_buffer_pool {
+                "on"
+            } else {
+                "off"
+            },
+            self.buffers.hits,
+            self.buffers.misses,
+            self.buffers.reallocations
+        );
+        out
+    }
+
+    fn json(&self) -> String {
+        let roles: Vec<String> = self
+            .tallies
+            .iter()
+            .map(|(role, tally)| {
+                format!(
+                    "\"{}\":{{\"connections\":{},\"requests\":{},\"errors\":{},\"requests_per_sec\":{:.1},\
+                     \"bytes_per_sec\":{:.0},\"records\":{},\"latency_ms\":{{\"p50\":{:.3},\"p99\":{:.3},\"max\":{:.3}}}}}",
+                    role.name(),
+                    role.connections(self.workload),
+                    tally.requests,
+                    tally.errors,
+                    self.per_second(tally.requests),
+                    self.per_second(tally.bytes),
+                    tally.records,
+                    millis(tally.percentile(50)),
+                    millis(tally.percentile(99)),
+                    millis(tally.latencies.last().copied().unwrap_or_default())
+                )
+            })
+            .collect();
+        format!(
+            "{{\"duration_ms\":{},\"topic\":\"{}\",\"roles\":{{{}}},\"broker\":{{\"records_appended\":{},\"flushes\":{},\"responses\":{},\"response_writes\":{}}},\
+             \"allocations\":{{\"total\":{},\"per_request\":{:.2}}},\
+             \"response_buffers\":{{\"pool\":{},\"hits\":{},\"misses\":{},\"reallocations\":{}}}}}\n",
+            self.elapsed.as_millis(),
+            self.workload.topic,
+            roles.join(","),
+            self.appended,
+            self.flushes,
+            self.responses.0,
+            self.responses.1,
+            self.allocations,
+            self.allocations_per_request(),
+            self.workload.response_buffer_pool,
+            self.buffers.hits,
+            self.buffers.misses,
+            self.buffers.reallocations
+        )
+    }
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
@@ -0,0 +1,354 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Condvar, Mutex, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::client_inventory::ClientInventory;
+use crate::config::BrokerConfig;
+use crate::config_overrides::ConfigOverrides;
+use crate::flusher::Flusher;
+use crate::group_coordinator::GroupCoordinator;
+use crate::group_offsets::GroupOffsets;
+use crate::ids::{LeaderEpoch, Offset, PartitionId, TopicId};
+use crate::lifecycle::Lifecycle;
+use crate::listener::ListenerStats;
+use crate::log_store::{self, LogError, LogStore};
+use crate::metadata::{ClusterMetadata, TopicMetadata};
+use crate::topic_changes::TopicChanges;
+use crate::topic_config;
+use crate::topic_events::{TopicEvent, TopicEvents};
+
+/// Leader epoch reported for every partition. Leadership never moves off the
+/// only broker, so it never changes.
+pub const LEADER_EPOCH: LeaderEpoch = LeaderEpoch(0);
+
+/// Counts appends and topic deletions, for fetches waiting on either.
+#[derive(Default)]
+struct Appends {
+    count: Mutex<u64>,
+    changed: Condvar,
+}
+
+impl Appends {
+    fn notify(&self) {
+        *self
+            .count
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) += 1;
+        self.changed.notify_all();
+    }
+}
+
+/// State shared by every connection thread.
+///
+/// Lock order: the topic registry, then the group offsets, never the other way
+/// round; `GroupOffsets` holds its own locks only within each call. The log
+/// store, `topic_changes`, `config_overrides`, `groups`, `flusher` and
+/// `clients` lock only themselves and are never held while taking anything
+/// else, so any of them may be called with the registry locked.
+pub struct Broker {
+    pub config: BrokerConfig,
+    pub lifecycle: Lifecycle,
+    /// The topic registry: replayed from the metadata log during startup, then
+    /// changed by CreateTopics, CreatePartitions, DeleteTopics and
+    /// IncrementalAlterConfigs.
+    metadata: RwLock<ClusterMetadata>,
+    /// The topics CreateTopics, CreatePartitions and DeleteTopics have saved.
+    topic_changes: TopicChanges,
+    /// The config overrides IncrementalAlterConfigs has saved.
+    config_overrides: ConfigOverrides,
+    /// A log for every partition in the registry.
+    logs: Arc<dyn LogStore>,
+    /// Bumped after every append and topic deletion, for fetches waiting on
+    /// new data.
+    appends: Arc<Appends>,
+    /// Flushes `logs` for produces waiting on stable storage.
+    pub flusher: Arc<Flusher>,
+    group_offsets: Arc<GroupOffsets>,
+    /// Consumer group membership, joined from every member's connection.
+    pub groups: GroupCoordinator,
+    pub topic_events: TopicEvents,
+    /// Traffic on each of `config.listeners`, by index.
+    pub listener_stats: Vec<ListenerStats>,
+    /// The client software connected, by name, version and client id.
+    pub clients: ClientInventory,
+    /// The producer id InitProducerId hands out next.
+    next_producer_id: AtomicI64,
+}
+
+impl Broker {
+    /// A broker with no state loaded yet, keeping its partition logs in the
+    /// store `log.store` picks; see `load_metadata` and `recover_logs`.
+    pub fn new(config: BrokerConfig) -> Broker {
+        let logs = log_store::open(&config);
+        Broker::with_log_store(config, logs)
+    }
+
+    /// A broker keeping its partition logs in `logs`, whatever `log.store` says.
+    pub fn with_log_store(config: BrokerConfig, logs: Arc<dyn LogStore>) -> Broker {
+        let appends: Arc<Appends> = Arc::default();
+        let topic_events = TopicEvents::start();
+        // A fetch waiting on a deleted topic is answered at once with the error,
+        // as Kafka does, rather than when max_wait_ms runs out
+        topic_events.subscribe({
+            let appends = Arc::clone(&appends);
+            move |event| {
+                if let TopicEvent::Deleted { .. } = event {
+                    appends.notify();
+                }
+            }
+        });
+        // Offsets committed against a deleted topic go with it
+        let group_offsets: Arc<GroupOffsets> = Arc::default();
+        topic_events.subscribe({
+            let group_offsets = Arc::clone(&group_offsets);
+            move |event| {
+                if let TopicEvent::Deleted { topic_id, .. } = event {
+                    group_offsets.remove_topic(*topic_id);
+                }
+            }
+        });
+        Broker {
+            lifecycle: Lifecycle::new(config.readiness_file.clone()),
+            flusher: Flusher::start(Arc::clone(&logs), config.flush_interval),
+            groups: GroupCoordinator::new(config.group_limits, config.echo_limits),
+            listener_stats: config
+                .listeners
+                .iter()
+                .map(|_| ListenerStats::default())
+                .collect(),
+            clients: ClientInventory::new(
+                config.client_inventory_max_entries,
+                config.client_inventory_retention,
+            ),
+            topic_changes: TopicChanges::new(&config),
+            config_overrides: ConfigOverrides::new(&config),
+            config,
+            metadata: RwLock::new(ClusterMetadata::empty()),
+            logs,
+            appends,
+            group_offsets,
+            topic_events,
+            next_producer_id: AtomicI64::new(0),
+        }
+    }
+
+    /// Replays the metadata log from `log.dirs`, then the saved topic changes
+    /// on top of it, then loads the saved config overrides onto the topics.
+    /// Changes made after this are announced to `topic_events` subscribers.
+    pub fn load_metadata(&self) -> Result<()> {
+        let mut metadata = ClusterMetadata::load(&self.config.log_dir)?;
+        self.topic_changes.load(&mut metadata)?;
+        self.config_overrides.load(&mut metadata)?;
+        metadata.announce_changes(self.topic_events.sender());
+        *self.metadata_mut() = metadata;
+        Ok(())
+    }
+
+    /// The topics known to this broker. Empty until `load_metadata`, but requests
+    /// are only handled once the broker is ready, and so after it.
+    pub fn metadata(&self) -> RwLockReadGuard<'_, ClusterMetadata> {
+        // Registry changes are made whole under the lock, so a panic elsewhere
+        // can't leave it half-updated
+        self.metadata
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn metadata_mut(&self) -> RwLockWriteGuard<'_, ClusterMetadata> {
+        self.metadata
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    pub fn topic_changes(&self) -> &TopicChanges {
+        &self.topic_changes
+    }
+
+    pub fn config_overrides(&self) -> &ConfigOverrides {
+        &self.config_overrides
+    }
+
+    /// The partition logs.
+    pub fn logs(&self) -> &dyn LogStore {
+        &*self.logs
+    }
+
+    /// Recovers the partition logs, then makes them match the registry: a
+    /// partition it lists gets an empty log if there's none, and the log of a
+    /// deleted topic is deleted. A log of a topic it never knew is kept, with
+    /// a warning, rather than guessed to be garbage. Producer ids carry on
+    /// past the highest the recovered logs remember. Runs after
+    /// `load_metadata`.
+    pub fn recover_logs(&self) -> Result<()> {
+        self.logs.recover()?;
+        if let Some(producer_id) = self.logs.max_producer_id() {
+            self.next_producer_id
+                .fetch_max(producer_id + 1, Ordering::Relaxed);
+        }
+        let metadata = self.metadata();
+        let live: HashSet<(TopicId, PartitionId)> = metadata
+            .topics()
+            .flat_map(|topic| {
+                let topic_id = topic.topic_id;
+                topic.partitions.iter().map(move |&index| (topic_id, index))
+            })
+            .collect();
+        for (topic_id, partition) in self.logs.partitions() {
+            if live.contains(&(topic_id, partition)) {
+                continue;
+            }
+            if metadata.was_deleted(topic_id) {
+                println!(
+                    "Deleting the log of {}-{}, a partition of a deleted topic",
+                    topic_id, partition
+                );
+                self.logs.delete_partition(topic_id, partition)?;
+            } else {
+                println!(
+                    "WARNING: keeping the log of {}-{}, a partition of a topic the registry \
+                     never had",
+                    topic_id, partition
+                );
+            }
+        }
+        for &(topic_id, partition) in &live {
+            self.logs.create_partition(topic_id, partition)?;
+        }
+        Ok(())
+    }
+
+    /// Creates an empty log for each of a topic's new partitions, or, if one
+    /// can't be, none of them.
+    pub fn create_logs(
+        &self,
+        topic_id: TopicId,
+        partitions: impl IntoIterator<Item = PartitionId>,
+    ) -> Result<(), LogError> {
+        let mut created = Vec::new();
+        for partition in partitions {
+            if let Err(e) = self.logs.create_partition(topic_id, partition) {
+                for partition in created {
+                    let _ = self.logs.delete_partition(topic_id, partition);
+                }
+                return Err(e);
+            }
+            created.push(partition);
+        }
+        Ok(())
+    }
+
+    /// Deletes the logs of a topic that has left the registry. One that can't be
+    /// is left on disk until the next startup deletes it.
+    pub fn delete_logs(&self, topic: &TopicMetadata) {
+        for &partition in &topic.partitions {
+            if let Err(e) = self.logs.delete_partition(topic.topic_id, partition) {
+                println!("Error deleting the log of '{}': {}", topic.name, e);
+            }
+        }
+    }
+
+    /// Deletes the records older than `retention.ms` from every partition of
+    /// the topics that override it, as of `now_ms`: those before the first
+    /// record at or after the cutoff, or all of them if there's none.
+    pub fn enforce_retention(&self, now_ms: i64) {
+        let retained: Vec<(String, TopicId, Vec<PartitionId>, i64)> = self
+            .metadata()
+            .topics()
+            .filter_map(|topic| {
+                let retention_ms = topic_config::retention_ms(&topic.configs)?;
+                Some((
+                    topic.name.clone(),
+                    topic.topic_id,
+                    topic.partitions.clone(),
+                    retention_ms,
+                ))
+            })
+            .collect();
+        for (name, topic_id, partitions, retention_ms) in retained {
+            let cutoff = now_ms.saturating_sub(retention_ms);
+            for partition in partitions {
+                match self.expire_records(topic_id, partition, cutoff) {
+                    Ok(Some(log_start_offset)) => println!(
+                        "Retention deleted the records of {}-{} before offset {}",
+                        name, partition, log_start_offset
+                    ),
+                    Ok(None) => {}
+                    // A partition deleted since the registry was read is fine,
+                    // and one left offline was logged when it failed recovery
+                    Err(LogError::UnknownPartition(..) | LogError::Offline(..)) => {}
+                    Err(e) => println!("Retention on {}-{} failed: {}", name, partition, e),
+                }
+            }
+        }
+    }
+
+    /// The partition's new log start offset, if records older than `cutoff`
+    /// were deleted.
+    fn expire_records(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        cutoff: i64,
+    ) -> Result<Option<Offset>, LogError> {
+        let offsets = self.logs.offsets(topic_id, partition)?;
+        let keep_from = match self
+            .logs
+            .offset_for_timestamp(topic_id, partition, cutoff)?
+        {
+            Some((_, offset)) => offset,
+            None => offsets.log_end_offset,
+        };
+        if keep_from <= offsets.log_start_offset {
+            return Ok(None);
+        }
+        self.logs
+            .delete_records(topic_id, partition, keep_from)
+            .map(Some)
+    }
+
+    /// Offsets committed by every group, shared by all connections since clients
+    /// often commit on one and fetch them on another.
+    pub fn group_offsets(&self) -> &GroupOffsets {
+        &self.group_offsets
+    }
+
+    /// A producer id no other producer has been given since startup, nor any
+    /// whose batches a recovered log remembers: a new producer mustn't have
+    /// its first batches taken for retries of an old one's. An id handed out
+    /// before a restart whose producer never appended can be handed out again.
+    pub fn allocate_producer_id(&self) -> i64 {
+        self.next_producer_id.fetch_add(1, Ordering::Relaxed)
+    }
+
+    /// Wakes fetches waiting in `wait_for_append`.
+    pub fn notify_appended(&self) {
+        self.appends.notify();
+    }
+
+    /// How many appends and deletions there have been, to hand
+    /// `wait_for_append` once the logs have been read.
+    pub fn appends_seen(&self) -> u64 {
+        *self
+            .appends
+            .count
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits until something has been appended or a topic deleted since
+    /// `appends_seen` returned `seen`, or until `timeout` passes.
+    pub fn wait_for_append(&self, seen: u64, timeout: Duration) {
+        let count = self
+            .appends
+            .count
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        let _ = self
+            .appends
+            .changed
+            .wait_timeout_while(count, timeout, |count| *count == seen);
+    }
+}
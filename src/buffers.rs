@@ -0,0 +1,148 @@
+//! Response buffers, reserved up front and recycled between requests.
+//!
+//! A response builder takes its buffer with `take`, passing a cheap upper bound
+//! on the response's size, so even a large Metadata or Fetch response is
+//! written without the buffer reallocating as it grows. Once a response has
+//! been sent, `recycle` hands the buffer back for the next request.
+//!
+//! Each connection thread keeps its own pool, so taking and recycling never
+//! contend. Buffers are bucketed by size class, a few per class, and nothing
+//! over the largest class is kept: a burst of large responses leaves at most
+//! `MAX_POOLED_BYTES` behind per connection, freed when the connection closes.
+//!
+//! Hits, misses and reallocations are counted across all connections; see
+//! `stats`.
+
+use std::cell::{Cell, RefCell};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+
+/// Buffer capacities the pool hands out, smallest first.
+const SIZE_CLASSES: [usize; 6] = [1 << 10, 1 << 12, 1 << 14, 1 << 16, 1 << 18, 1 << 20];
+/// Buffers kept per size class. A connection writes one response at a time,
+/// so more than a couple would only sit idle.
+const POOLED_PER_CLASS: usize = 2;
+/// Most bytes of buffers one connection's pool keeps.
+const MAX_POOLED_BYTES: usize = 2 << 20;
+
+static POOLING: AtomicBool = AtomicBool::new(true);
+static HITS: AtomicU64 = AtomicU64::new(0);
+static MISSES: AtomicU64 = AtomicU64::new(0);
+static REALLOCATIONS: AtomicU64 = AtomicU64::new(0);
+
+/// How the pools have done since startup.
+#[derive(Clone, Copy, Default, PartialEq, Debug)]
+pub struct BufferStats {
+    /// Buffers taken from a pool.
+    pub hits: u64,
+    /// Buffers that had to be allocated, the pool having none of the size.
+    pub misses: u64,
+    /// Responses that outgrew the buffer they were started in.
+    pub reallocations: u64,
+}
+
+#[derive(Default)]
+struct Pool {
+    /// Free buffers, one list per size class.
+    free: [Vec<Vec<u8>>; SIZE_CLASSES.len()],
+    pooled_bytes: usize,
+}
+
+thread_local! {
+    static POOL: RefCell<Pool> = RefCell::default();
+    /// Capacity of the buffer last taken on this thread, to tell at `recycle`
+    /// whether the response outgrew it. `None` when the response being
+    /// recycled was built without `take`.
+    static TAKEN_CAPACITY: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// A buffer for a response of at most about `estimate` bytes. Without pooling
+/// it's an empty `Vec`, grown as the response is written.
+pub fn take(estimate: usize) -> Vec<u8> {
+    if !POOLING.load(Ordering::Relaxed) {
+        TAKEN_CAPACITY.set(Some(0));
+        return Vec::new();
+    }
+    let buffer = match SIZE_CLASSES.iter().position(|&size| size >= estimate) {
+        Some(class) => match POOL.with_borrow_mut(|pool| pool.take(class)) {
+            Some(buffer) => {
+                HITS.fetch_add(1, Ordering::Relaxed);
+                buffer
+            }
+            None => {
+                MISSES.fetch_add(1, Ordering::Relaxed);
+                Vec::with_capacity(SIZE_CLASSES[class])
+            }
+        },
+        None => {
+            MISSES.fetch_add(1, Ordering::Relaxed);
+            Vec::with_capacity(estimate)
+        }
+    };
+    TAKEN_CAPACITY.set(Some(buffer.capacity()));
+    buffer
+}
+
+/// Takes back a sent response's buffer, keeping it if the pool has room.
+pub fn recycle(mut buffer: Vec<u8>) {
+    if TAKEN_CAPACITY
+        .take()
+        .is_some_and(|taken| buffer.capacity() > taken)
+    {
+        REALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+    }
+    if !POOLING.load(Ordering::Relaxed) {
+        return;
+    }
+    // Only exact class sizes go back, so every buffer taken from a class is
+    // exactly that big; a buffer that grew past its class is dropped
+    let Some(class) = SIZE_CLASSES
+        .iter()
+        .position(|&size| size == buffer.capacity())
+    else {
+        return;
+    };
+    buffer.clear();
+    POOL.with_borrow_mut(|pool| pool.put(class, buffer));
+}
+
+/// Turns pooling on or off for every connection, for the bench to compare
+/// against growing each response from empty.
+pub fn set_pooling(enabled: bool) {
+    POOLING.store(enabled, Ordering::Relaxed);
+}
+
+pub fn stats() -> BufferStats {
+    BufferStats {
+        hits: HITS.load(Ordering::Relaxed),
+        misses: MISSES.load(Ordering::Relaxed),
+        reallocations: REALLOCATIONS.load(Ordering::Relaxed),
+    }
+}
+
+impl BufferStats {
+    /// What happened between `earlier` and these.
+    pub fn since(self, earlier: BufferStats) -> BufferStats {
+        BufferStats {
+            hits: self.hits - earlier.hits,
+            misses: self.misses - earlier.misses,
+            reallocations: self.reallocations - earlier.reallocations,
+        }
+    }
+}
+
+impl Pool {
+    fn take(&mut self, class: usize) -> Option<Vec<u8>> {
+        let buffer = self.free[class].pop()?;
+        self.pooled_bytes -= buffer.capacity();
+        Some(buffer)
+    }
+
+    fn put(&mut self, class: usize, buffer: Vec<u8>) {
+        if self.free[class].len() < POOLED_PER_CLASS
+            && self.pooled_bytes + buffer.capacity() <= MAX_POOLED_BYTES
+        {
+            self.pooled_bytes += buffer.capacity();
+            self.free[class].push(buffer);
+        }
+    }
+}
@@ -0,0 +1,221 @@
+//! Which client libraries are connected, for operators deciding when an old
+//! protocol version can be dropped.
+//!
+//! Clients are told apart by the software name and version they give in
+//! ApiVersions v3+ (`unknown` for connections that never do, as Kafka reports
+//! them) and the client id in their request headers. For each, the inventory
+//! counts the connections seen and still open, when it was last seen, and the
+//! lowest and highest version of each API it was served at. A connection logs
+//! its client's entry as it closes, and the scheduler logs the whole inventory
+//! at shutdown.
+//!
+//! All three strings come from the client, so each is cut at `MAX_FIELD_LEN`
+//! bytes with control characters escaped before it's kept or logged. The
+//! inventory keeps at most `client.inventory.max.entries` clients; past that,
+//! new ones are counted but not kept. One with no open connection is dropped
+//! once it hasn't been seen for `client.inventory.retention.ms`.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::{Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::SUPPORTED_APIS;
+
+/// Longest software name, software version or client id kept, in bytes.
+const MAX_FIELD_LEN: usize = 64;
+
+/// What a connection's client is known by: its software name and version, and
+/// its client id (`None` when anonymous).
+#[derive(Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ClientKey {
+    software_name: String,
+    software_version: String,
+    client_id: Option<String>,
+}
+
+impl ClientKey {
+    pub fn new(software_name: &str, software_version: &str, client_id: Option<&str>) -> ClientKey {
+        ClientKey {
+            software_name: sanitize(software_name),
+            software_version: sanitize(software_version),
+            client_id: client_id.map(sanitize),
+        }
+    }
+}
+
+impl fmt::Display for ClientKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{} client_id={}",
+            self.software_name,
+            self.software_version,
+            self.client_id.as_deref().unwrap_or("<anonymous>")
+        )
+    }
+}
+
+/// `value` cut at `MAX_FIELD_LEN` bytes, with control characters escaped so it
+/// can't inject newlines or terminal escapes into the server output.
+fn sanitize(value: &str) -> String {
+    let mut end = value.len().min(MAX_FIELD_LEN);
+    while !value.is_char_boundary(end) {
+        end -= 1;
+    }
+    value[..end]
+        .chars()
+        .map(|c| {
+            if c.is_control() {
+                c.escape_default().to_string()
+            } else {
+                c.to_string()
+            }
+        })
+        .collect()
+}
+
+struct ClientEntry {
+    connections: u64,
+    open: u64,
+    last_seen: Instant,
+    /// (lowest, highest) version served, by API key.
+    versions: BTreeMap<u16, (i16, i16)>,
+}
+
+impl fmt::Display for ClientEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "connections={} open={} last_seen={}ms_ago versions=",
+            self.connections,
+            self.open,
+            self.last_seen.elapsed().as_millis()
+        )?;
+        for (index, (api_key, (lowest, highest))) in self.versions.iter().enumerate() {
+            let name = SUPPORTED_APIS
+                .iter()
+                .find(|api| api.api_key == *api_key)
+                .map_or("Unknown", |api| api.name);
+            let separator = if index == 0 { "" } else { "," };
+            write!(f, "{}{}:{}-{}", separator, name, lowest, highest)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct InventoryState {
+    clients: HashMap<ClientKey, ClientEntry>,
+    /// Connections from clients there was no room for.
+    untracked: u64,
+}
+
+/// The connected clients, shared by every connection thread.
+pub struct ClientInventory {
+    max_entries: usize,
+    retention: Duration,
+    state: Mutex<InventoryState>,
+}
+
+impl ClientInventory {
+    pub fn new(max_entries: usize, retention: Duration) -> ClientInventory {
+        ClientInventory {
+            max_entries,
+            retention,
+            state: Mutex::default(),
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, InventoryState> {
+        // Each change is made whole under the lock
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Counts a connection from `client`. False if the inventory is full and
+    /// doesn't have it, in which case it isn't kept.
+    pub fn connected(&self, client: &ClientKey) -> bool {
+        let mut state = self.lock();
+        let full = state.clients.len() >= self.max_entries;
+        match state.clients.get_mut(client) {
+            Some(entry) => {
+                entry.connections += 1;
+                entry.open += 1;
+                entry.last_seen = Instant::now();
+                true
+            }
+            None if full => {
+                state.untracked += 1;
+                false
+            }
+            None => {
+                state.clients.insert(
+                    client.clone(),
+                    ClientEntry {
+                        connections: 1,
+                        open: 1,
+                        last_seen: Instant::now(),
+                        versions: BTreeMap::new(),
+                    },
+                );
+                true
+            }
+        }
+    }
+
+    /// Records a request from `client` served at `api_version`.
+    pub fn served(&self, client: &ClientKey, api_key: u16, api_version: i16) {
+        if let Some(entry) = self.lock().clients.get_mut(client) {
+            entry.last_seen = Instant::now();
+            let (lowest, highest) = entry
+                .versions
+                .entry(api_key)
+                .or_insert((api_version, api_version));
+            *lowest = (*lowest).min(api_version);
+            *highest = (*highest).max(api_version);
+        }
+    }
+
+    /// Counts one of `client`'s connections closed, returning its entry's line
+    /// as it now stands.
+    pub fn disconnected(&self, client: &ClientKey) -> Option<String> {
+        let mut state = self.lock();
+        let entry = state.clients.get_mut(client)?;
+        entry.open = entry.open.saturating_sub(1);
+        entry.last_seen = Instant::now();
+        Some(format!("Client {}: {}", client, entry))
+    }
+
+    /// Drops the clients with no open connection that haven't been seen for
+    /// the retention time.
+    pub fn expire(&self) {
+        let retention = self.retention;
+        self.lock().clients.retain(|client, entry| {
+            let kept = entry.open > 0 || entry.last_seen.elapsed() < retention;
+            if !kept {
+                println!("Client inventory aged out {}", client);
+            }
+            kept
+        });
+    }
+
+    /// Every client's line, in order, after a summary line.
+    pub fn lines(&self) -> Vec<String> {
+        let state = self.lock();
+        let mut clients: Vec<(&ClientKey, &ClientEntry)> = state.clients.iter().collect();
+        clients.sort_by(|a, b| a.0.cmp(b.0));
+        let mut lines = vec![format!(
+            "Client inventory: {} client(s), {} connection(s) from clients not kept",
+            clients.len(),
+            state.untracked
+        )];
+        lines.extend(
+            clients
+                .into_iter()
+                .map(|(client, entry)| format!("Client {}: {}", client, entry)),
+        );
+        lines
+    }
+}
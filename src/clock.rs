@@ -0,0 +1,97 @@
+//! Wall-clock time, and noticing when it's stepped.
+//!
+//! Deadlines, session timeouts, waits and ages are all measured on `Instant`,
+//! which never goes back. The wall clock is only read where time has to be
+//! compared with record timestamps (retention) or shown to people (the liveness
+//! file, logs), and it can be stepped: by NTP, by an operator, by a VM resumed
+//! from a snapshot. So the scheduler reads it through a `WallClockWatch`, which
+//! compares how far it moved between readings with how far monotonic time did.
+//! A difference of more than `MAX_CLOCK_JUMP_MS` either way is a jump, logged
+//! prominently; the retention pass after one is skipped rather than deleting
+//! records by a reading that may be hours out.
+//!
+//! The scheduler is handed its `Clock`, so the `clock_checks` tests can drive
+//! it by a stepped clock instead of the system's.
+
+#[cfg(test)]
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// How far the wall clock may drift from monotonic time between two readings
+/// before it counts as stepped. Far more than NTP ever slews it by in the
+/// scheduler's 100 ms tick.
+pub const MAX_CLOCK_JUMP_MS: i64 = 1_000;
+
+/// A source of wall-clock time.
+pub trait Clock: Send + Sync {
+    /// Milliseconds since the Unix epoch.
+    fn now_ms(&self) -> i64;
+}
+
+/// The system's wall clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now_ms(&self) -> i64 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_millis() as i64)
+    }
+}
+
+/// The system's wall clock, stepped by however much `step` has moved it so
+/// far.
+#[cfg(test)]
+#[derive(Default)]
+pub struct SteppedClock {
+    offset_ms: AtomicI64,
+}
+
+#[cfg(test)]
+impl SteppedClock {
+    /// Moves the clock by `ms`, back if it's negative.
+    pub fn step(&self, ms: i64) {
+        self.offset_ms.fetch_add(ms, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+impl Clock for SteppedClock {
+    fn now_ms(&self) -> i64 {
+        SystemClock.now_ms() + self.offset_ms.load(Ordering::SeqCst)
+    }
+}
+
+/// A wall-clock reading from `WallClockWatch::read`.
+pub struct Reading {
+    pub now_ms: i64,
+    /// How far the clock was stepped since the last reading, if it was: the
+    /// wall time that passed less the monotonic time that did.
+    pub jump_ms: Option<i64>,
+}
+
+/// Reads a `Clock`, checking each reading against the last.
+pub struct WallClockWatch {
+    clock: Arc<dyn Clock>,
+    /// The last reading, and when it was taken.
+    last: Option<(i64, Instant)>,
+}
+
+impl WallClockWatch {
+    pub fn new(clock: Arc<dyn Clock>) -> WallClockWatch {
+        WallClockWatch { clock, last: None }
+    }
+
+    pub fn read(&mut self) -> Reading {
+        let now_ms = self.clock.now_ms();
+        let now = Instant::now();
+        let jump_ms = self.last.and_then(|(last_ms, last)| {
+            let elapsed_ms = now.duration_since(last).as_millis() as i64;
+            let jump_ms = now_ms.saturating_sub(last_ms).saturating_sub(elapsed_ms);
+            (jump_ms.abs() > MAX_CLOCK_JUMP_MS).then_some(jump_ms)
+        });
+        self.last = Some((now_ms, now));
+        Reading { now_ms, jump_ms }
+    }
+}
@@ -0,0 +1,155 @@
+//! What the scheduler must do when the wall clock is stepped under it.
+//!
+//! Each test runs a broker of its own, with an in-memory store and a scheduler
+//! driven by a `SteppedClock`, checking every retention pass the scheduler
+//! runs: each tick is one, so a skipped one shows.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+
+use crate::broker::Broker;
+use crate::clock::{Clock, SteppedClock};
+use crate::config::BrokerConfig;
+use crate::group_coordinator::JoinRequest;
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::lifecycle::Scheduler;
+use crate::log_store::MemoryLogStore;
+use crate::record_batch::{self, Producer, RecordBatch};
+
+const HOUR_MS: i64 = 60 * 60 * 1000;
+const P0: PartitionId = PartitionId(0);
+const GROUP: &str = "clock-checks";
+
+/// A broker checking retention on every scheduler tick, with its scheduler on
+/// `clock`.
+fn broker(clock: &Arc<SteppedClock>) -> (Broker, Scheduler) {
+    let config = BrokerConfig {
+        retention_check_interval: Duration::ZERO,
+        ..BrokerConfig::default()
+    };
+    let clock: Arc<dyn Clock> = Arc::<SteppedClock>::clone(clock);
+    let scheduler = Scheduler::new(&config, clock);
+    let broker = Broker::with_log_store(config, Arc::new(MemoryLogStore::default()));
+    (broker, scheduler)
+}
+
+/// A one-partition topic keeping records for an hour, holding one record
+/// timestamped `age_ms` before `clock`'s now.
+fn topic(broker: &Broker, clock: &SteppedClock, age_ms: i64) -> Result<TopicId> {
+    let topic_id = broker
+        .metadata_mut()
+        .create_topic(
+            "events".to_string(),
+            1,
+            vec![("retention.ms".to_string(), Some(HOUR_MS.to_string()))],
+        )
+        .topic_id;
+    broker.create_logs(topic_id, [P0])?;
+    let raw = record_batch::build(
+        &[(clock.now_ms() - age_ms, b"value".as_slice())],
+        Producer::NONE,
+    );
+    broker
+        .logs()
+        .append(topic_id, P0, &[RecordBatch::parse_first(&raw)?])?;
+    Ok(topic_id)
+}
+
+fn log_start_offset(broker: &Broker, topic_id: TopicId) -> Result<Offset> {
+    Ok(broker.logs().offsets(topic_id, P0)?.log_start_offset)
+}
+
+/// Runs `ticks` scheduler ticks, checking the record is still there after each.
+fn ticks_keep_record(
+    broker: &Broker,
+    scheduler: &mut Scheduler,
+    topic_id: TopicId,
+    ticks: usize,
+) -> Result<()> {
+    for tick in 0..ticks {
+        scheduler.tick(broker);
+        ensure!(
+            log_start_offset(broker, topic_id)? == Offset(0),
+            "the record was deleted on tick {}",
+            tick
+        );
+    }
+    Ok(())
+}
+
+/// Stepping the clock back an hour, and forward again, deletes nothing that
+/// wasn't due.
+#[test]
+fn backward_jump_keeps_records() -> Result<()> {
+    let clock: Arc<SteppedClock> = Arc::default();
+    let (broker, mut scheduler) = broker(&clock);
+    let topic_id = topic(&broker, &clock, HOUR_MS / 2)?;
+    ticks_keep_record(&broker, &mut scheduler, topic_id, 2)?;
+    clock.step(-HOUR_MS);
+    ticks_keep_record(&broker, &mut scheduler, topic_id, 3)?;
+    clock.step(HOUR_MS);
+    ticks_keep_record(&broker, &mut scheduler, topic_id, 3)
+}
+
+/// Stepping the clock back an hour expires no group member, whose session is
+/// timed on monotonic time.
+#[test]
+fn backward_jump_keeps_members() -> Result<()> {
+    let clock: Arc<SteppedClock> = Arc::default();
+    let (broker, mut scheduler) = broker(&clock);
+    let joined = broker.groups.join(JoinRequest {
+        group_id: GROUP.to_string(),
+        session_timeout_ms: 10_000,
+        rebalance_timeout_ms: 10_000,
+        member_id: String::new(),
+        group_instance_id: None,
+        protocol_type: "consumer".to_string(),
+        protocols: vec![("range".to_string(), Vec::new())],
+        client_id: None,
+        client_host: "/127.0.0.1".to_string(),
+        require_known_member_id: false,
+    });
+    ensure!(
+        joined.error_code == 0,
+        "the join failed with error {}",
+        joined.error_code
+    );
+    scheduler.tick(&broker);
+    clock.step(-HOUR_MS);
+    for tick in 0..3 {
+        scheduler.tick(&broker);
+        let members = broker
+            .groups
+            .describe(GROUP)
+            .map_or(0, |group| group.members.len());
+        ensure!(
+            members == 1,
+            "{} member(s) left after tick {}",
+            members,
+            tick
+        );
+    }
+    Ok(())
+}
+
+/// Stepping the clock forward an hour skips the retention pass after the jump,
+/// and the one after that deletes what is by then past retention.
+#[test]
+fn forward_jump_skips_one_retention_pass() -> Result<()> {
+    let clock: Arc<SteppedClock> = Arc::default();
+    let (broker, mut scheduler) = broker(&clock);
+    let topic_id = topic(&broker, &clock, HOUR_MS / 2)?;
+    ticks_keep_record(&broker, &mut scheduler, topic_id, 2)?;
+    clock.step(HOUR_MS);
+    ticks_keep_record(&broker, &mut scheduler, topic_id, 1)?;
+    scheduler.tick(&broker);
+    let log_start_offset = log_start_offset(&broker, topic_id)?;
+    ensure!(
+        log_start_offset == Offset(1),
+        "log start offset {} after the pass following the skipped one, not 1",
+        log_start_offset.0
+    );
+    Ok(())
+}
@@ -0,0 +1,729 @@
+//! Bounds-checked readers and writers for the Kafka wire primitives.
+//!
+//! Every read returns a `DecodeError` instead of panicking when the input is
+//! shorter than the field being decoded, so a malformed request can never take
+//! a connection thread down with an out-of-bounds slice.
+//!
+//! Flexible (KIP-482) versions use the compact forms: lengths are unsigned
+//! varints holding `len + 1`, with 0 meaning null. Varints are little-endian
+//! 7-bit groups with the top bit set on every byte but the last, so a length
+//! of 127 or more takes several bytes; record fields zigzag-encode theirs to
+//! keep small negative values short.
+
+use thiserror::Error;
+
+use crate::MESSAGE_SIZE_LEN;
+
+/// A varint is at most 10 bytes (64 bits in 7-bit groups).
+const MAX_VARINT_LEN: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum DecodeError {
+    #[error("truncated input: needed {needed} bytes at offset {offset}, {available} available")]
+    Truncated {
+        offset: usize,
+        needed: usize,
+        available: usize,
+    },
+    #[error("invalid length {length} at offset {offset}")]
+    InvalidLength { offset: usize, length: i64 },
+    #[error("varint longer than {MAX_VARINT_LEN} bytes, or past 64 bits, at offset {offset}")]
+    VarintTooLong { offset: usize },
+    #[error("invalid UTF-8 in string at offset {offset}")]
+    InvalidUtf8 { offset: usize },
+    #[error("null string at offset {offset}, where the field can't be null")]
+    UnexpectedNull { offset: usize },
+    #[error("tag {tag} at offset {offset} is out of order, or repeated")]
+    TagOutOfOrder { offset: usize, tag: u64 },
+    #[error("delta at offset {offset} overflows the value it is relative to")]
+    DeltaOverflow { offset: usize },
+}
+
+impl DecodeError {
+    /// Re-bases offsets reported against a sub-slice onto the enclosing buffer.
+    fn shifted(self, base: usize) -> DecodeError {
+        match self {
+            DecodeError::Truncated {
+                offset,
+                needed,
+                available,
+            } => DecodeError::Truncated {
+                offset: base + offset,
+                needed,
+                available,
+            },
+            DecodeError::InvalidLength { offset, length } => DecodeError::InvalidLength {
+                offset: base + offset,
+                length,
+            },
+            DecodeError::VarintTooLong { offset } => DecodeError::VarintTooLong {
+                offset: base + offset,
+            },
+            DecodeError::InvalidUtf8 { offset } => DecodeError::InvalidUtf8 {
+                offset: base + offset,
+            },
+            DecodeError::UnexpectedNull { offset } => DecodeError::UnexpectedNull {
+                offset: base + offset,
+            },
+            DecodeError::TagOutOfOrder { offset, tag } => DecodeError::TagOutOfOrder {
+                offset: base + offset,
+                tag,
+            },
+            DecodeError::DeltaOverflow { offset } => DecodeError::DeltaOverflow {
+                offset: base + offset,
+            },
+        }
+    }
+}
+
+/// Decodes an unsigned varint from the start of `buf`, returning the value and
+/// the number of bytes it occupied.
+pub fn read_unsigned_varint(buf: &[u8]) -> Result<(u64, usize), DecodeError> {
+    let mut value: u64 = 0;
+    for (index, byte) in buf.iter().take(MAX_VARINT_LEN).enumerate() {
+        // The tenth byte has room for the 64th bit only
+        if index == MAX_VARINT_LEN - 1 && byte & 0x7e != 0 {
+            return Err(DecodeError::VarintTooLong { offset: 0 });
+        }
+        value |= ((byte & 0x7f) as u64) << (7 * index);
+        if byte & 0x80 == 0 {
+            return Ok((value, index + 1));
+        }
+    }
+    if buf.len() >= MAX_VARINT_LEN {
+        Err(DecodeError::VarintTooLong { offset: 0 })
+    } else {
+        Err(DecodeError::Truncated {
+            offset: 0,
+            needed: buf.len() + 1,
+            available: buf.len(),
+        })
+    }
+}
+
+pub fn write_unsigned_varint(buf: &mut Vec<u8>, mut value: u64) {
+    while value >= 0x80 {
+        buf.push((value as u8 & 0x7f) | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Decodes a zigzag-encoded signed varint, as used inside record batches, from
+/// the start of `buf`, returning the value and the number of bytes it occupied.
+pub fn read_varint(buf: &[u8]) -> Result<(i64, usize), DecodeError> {
+    let (raw, len) = read_unsigned_varint(buf)?;
+    Ok(((raw >> 1) as i64 ^ -((raw & 1) as i64), len))
+}
+
+/// A zigzag varint: 0, -1, 1, -2, ... as 0, 1, 2, 3, ...
+pub fn write_varint(buf: &mut Vec<u8>, value: i64) {
+    write_unsigned_varint(buf, ((value << 1) ^ (value >> 63)) as u64);
+}
+
+/// Cursor over a request buffer, reading big-endian fields in order.
+pub struct Decoder<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Decoder<'a> {
+    pub fn new(buf: &'a [u8]) -> Self {
+        Decoder { buf, pos: 0 }
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.buf.len() - self.pos
+    }
+
+    pub fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], DecodeError> {
+        let available = self.remaining();
+        if len > available {
+            return Err(DecodeError::Truncated {
+                offset: self.pos,
+                needed: len,
+                available,
+            });
+        }
+        let bytes = &self.buf[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    pub fn read_i8(&mut self) -> Result<i8, DecodeError> {
+        Ok(self.read_bytes(1)?[0] as i8)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, DecodeError> {
+        let bytes = self.read_bytes(2)?;
+        Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
+    }
+
+    pub fn read_i16(&mut self) -> Result<i16, DecodeError> {
+        Ok(self.read_u16()? as i16)
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, DecodeError> {
+        let bytes = self.read_bytes(4)?;
+        Ok(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    pub fn read_i32(&mut self) -> Result<i32, DecodeError> {
+        Ok(self.read_u32()? as i32)
+    }
+
+    pub fn read_i64(&mut self) -> Result<i64, DecodeError> {
+        let bytes = self.read_bytes(8)?;
+        let mut be = [0; 8];
+        be.copy_from_slice(bytes);
+        Ok(i64::from_be_bytes(be))
+    }
+
+    pub fn read_uuid(&mut self) -> Result<[u8; 16], DecodeError> {
+        let mut uuid = [0; 16];
+        uuid.copy_from_slice(self.read_bytes(16)?);
+        Ok(uuid)
+    }
+
+    pub fn read_unsigned_varint(&mut self) -> Result<u64, DecodeError> {
+        let (value, len) =
+            read_unsigned_varint(&self.buf[self.pos..]).map_err(|e| e.shifted(self.pos))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// Zigzag-encoded signed varint, as used inside record batches.
+    pub fn read_varint(&mut self) -> Result<i64, DecodeError> {
+        let (value, len) = read_varint(&self.buf[self.pos..]).map_err(|e| e.shifted(self.pos))?;
+        self.pos += len;
+        Ok(value)
+    }
+
+    /// A zigzag varint delta added to `base`, as record timestamps and offsets
+    /// are stored. Both come from the client, so the sum is checked.
+    pub fn read_varint_delta(&mut self, base: i64) -> Result<i64, DecodeError> {
+        let offset = self.pos;
+        base.checked_add(self.read_varint()?)
+            .ok_or(DecodeError::DeltaOverflow { offset })
+    }
+
+    /// Reads a compact length (`len + 1`, 0 for null).
+    fn read_compact_len(&mut self) -> Result<Option<usize>, DecodeError> {
+        let offset = self.pos;
+        match self.read_unsigned_varint()? {
+            0 => Ok(None),
+            raw if raw - 1 > self.buf.len() as u64 => Err(DecodeError::InvalidLength {
+                offset,
+                length: (raw - 1) as i64,
+            }),
+            raw => Ok(Some((raw - 1) as usize)),
+        }
+    }
+
+    fn read_utf8(&mut self, len: usize) -> Result<String, DecodeError> {
+        let offset = self.pos;
+        let bytes = self.read_bytes(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|_| DecodeError::InvalidUtf8 { offset })
+    }
+
+    /// Reads an int16-length NULLABLE_STRING as raw bytes, `None` for length -1.
+    /// The bytes are returned undecoded; callers decide how to treat non-UTF-8.
+    pub fn read_nullable_string_bytes(&mut self) -> Result<Option<&'a [u8]>, DecodeError> {
+        let offset = self.pos;
+        let length = self.read_i16()?;
+        match length {
+            -1 => Ok(None),
+            len if len < 0 => Err(DecodeError::InvalidLength {
+                offset,
+                length: len as i64,
+            }),
+            len => Ok(Some(self.read_bytes(len as usize)?)),
+        }
+    }
+
+    /// STRING, or COMPACT_STRING when `flexible`: must not be null.
+    pub fn read_string(&mut self, flexible: bool) -> Result<String, DecodeError> {
+        if flexible {
+            self.read_compact_string()
+        } else {
+            self.read_int16_string()
+        }
+    }
+
+    /// NULLABLE_STRING, or COMPACT_NULLABLE_STRING when `flexible`.
+    pub fn read_nullable_string(&mut self, flexible: bool) -> Result<Option<String>, DecodeError> {
+        if flexible {
+            self.read_compact_nullable_string()
+        } else {
+            self.read_int16_nullable_string()
+        }
+    }
+
+    /// STRING: int16 length, must not be null.
+    fn read_int16_string(&mut self) -> Result<String, DecodeError> {
+        let offset = self.pos;
+        match self.read_nullable_string_bytes()? {
+            Some(bytes) => String::from_utf8(bytes.to_vec())
+                .map_err(|_| DecodeError::InvalidUtf8 { offset: offset + 2 }),
+            None => Err(DecodeError::UnexpectedNull { offset }),
+        }
+    }
+
+    fn read_int16_nullable_string(&mut self) -> Result<Option<String>, DecodeError> {
+        let offset = self.pos;
+        match self.read_nullable_string_bytes()? {
+            None => Ok(None),
+            Some(bytes) => String::from_utf8(bytes.to_vec())
+                .map(Some)
+                .map_err(|_| DecodeError::InvalidUtf8 { offset: offset + 2 }),
+        }
+    }
+
+    /// COMPACT_STRING: must not be null. An empty string (length byte 1) is not
+    /// null (length byte 0), and is returned as one.
+    pub fn read_compact_string(&mut self) -> Result<String, DecodeError> {
+        let offset = self.pos;
+        match self.read_compact_len()? {
+            Some(len) => self.read_utf8(len),
+            None => Err(DecodeError::UnexpectedNull { offset }),
+        }
+    }
+
+    pub fn read_compact_nullable_string(&mut self) -> Result<Option<String>, DecodeError> {
+        match self.read_compact_len()? {
+            Some(len) => self.read_utf8(len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// ARRAY length, or COMPACT_ARRAY's when `flexible`: `None` for a null
+    /// array.
+    pub fn read_array_len(&mut self, flexible: bool) -> Result<Option<usize>, DecodeError> {
+        if flexible {
+            self.read_compact_array_len()
+        } else {
+            self.read_int32_array_len()
+        }
+    }
+
+    /// An array length as `read_array_len`, a null array read as empty: for
+    /// arrays no client sends null.
+    pub fn read_array_len_or_empty(&mut self, flexible: bool) -> Result<usize, DecodeError> {
+        Ok(self.read_array_len(flexible)?.unwrap_or(0))
+    }
+
+    /// ARRAY length: int32, -1 for a null array.
+    fn read_int32_array_len(&mut self) -> Result<Option<usize>, DecodeError> {
+        let offset = self.pos;
+        match self.read_i32()? {
+            -1 => Ok(None),
+            // Every element takes at least one byte, which bounds plausible counts
+            len if len < 0 || len as usize > self.remaining() => Err(DecodeError::InvalidLength {
+                offset,
+                length: len as i64,
+            }),
+            len => Ok(Some(len as usize)),
+        }
+    }
+
+    fn read_compact_array_len(&mut self) -> Result<Option<usize>, DecodeError> {
+        self.read_compact_len()
+    }
+
+    /// NULLABLE_BYTES / RECORDS: int32 length, -1 for null.
+    pub fn read_nullable_bytes(&mut self) -> Result<Option<&'a [u8]>, DecodeError> {
+        let offset = self.pos;
+        match self.read_i32()? {
+            -1 => Ok(None),
+            len if len < 0 => Err(DecodeError::InvalidLength {
+                offset,
+                length: len as i64,
+            }),
+            len => self.read_bytes(len as usize).map(Some),
+        }
+    }
+
+    pub fn read_compact_nullable_bytes(&mut self) -> Result<Option<&'a [u8]>, DecodeError> {
+        match self.read_compact_len()? {
+            Some(len) => self.read_bytes(len).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    /// Reads a TAG_BUFFER, returning every (tag, data) field in it, known to
+    /// the caller or not, for it to pick from.
+    pub fn read_tagged_fields(&mut self) -> Result<Vec<(u64, &'a [u8])>, DecodeError> {
+        let mut fields = Vec::new();
+        self.each_tagged_field(|tag, data| fields.push((tag, data)))?;
+        Ok(fields)
+    }
+
+    /// Skips a TAG_BUFFER, whatever fields it holds.
+    pub fn skip_tagged_fields(&mut self) -> Result<(), DecodeError> {
+        self.each_tagged_field(|_, _| {})
+    }
+
+    /// A TAG_BUFFER is a count followed by (tag, size, data) triples, in
+    /// ascending tag order. Each field's size is read whether or not its tag is
+    /// known, so whatever follows is found all the same.
+    fn each_tagged_field(
+        &mut self,
+        mut field: impl FnMut(u64, &'a [u8]),
+    ) -> Result<(), DecodeError> {
+        let count = self.read_unsigned_varint()?;
+        let mut previous = None;
+        for _ in 0..count {
+            let offset = self.pos;
+            let tag = self.read_unsigned_varint()?;
+            if previous.is_some_and(|previous| tag <= previous) {
+                return Err(DecodeError::TagOutOfOrder { offset, tag });
+            }
+            previous = Some(tag);
+            let size = self.read_unsigned_varint()?;
+            field(
+                tag,
+                self.read_bytes(usize::try_from(size).unwrap_or(usize::MAX))?,
+            );
+        }
+        Ok(())
+    }
+}
+
+/// STRING: int16 length, then the bytes. A string longer than that length
+/// can say (32 KiB or more) is cut at the last character boundary that fits,
+/// rather than written with a wrapped length that breaks the frame.
+pub fn write_string(buf: &mut Vec<u8>, value: &str) {
+    let mut len = value.len().min(i16::MAX as usize);
+    while !value.is_char_boundary(len) {
+        len -= 1;
+    }
+    buf.extend_from_slice(&(len as i16).to_be_bytes());
+    buf.extend_from_slice(&value.as_bytes()[..len]);
+}
+
+pub fn write_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => write_string(buf, value),
+        None => buf.extend_from_slice(&(-1i16).to_be_bytes()),
+    }
+}
+
+pub fn write_compact_string(buf: &mut Vec<u8>, value: &str) {
+    write_unsigned_varint(buf, value.len() as u64 + 1);
+    buf.extend_from_slice(value.as_bytes());
+}
+
+pub fn write_compact_nullable_string(buf: &mut Vec<u8>, value: Option<&str>) {
+    match value {
+        Some(value) => write_compact_string(buf, value),
+        None => write_unsigned_varint(buf, 0),
+    }
+}
+
+/// BYTES: int32 length, then the bytes.
+pub fn write_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as i32).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+pub fn write_compact_bytes(buf: &mut Vec<u8>, value: &[u8]) {
+    write_unsigned_varint(buf, value.len() as u64 + 1);
+    buf.extend_from_slice(value);
+}
+
+/// Writes a TAG_BUFFER holding `fields`, which must be in ascending tag order:
+/// the count, then each tag with the size of its data and the data itself.
+/// Most have no fields, `&[]`, and are a single 0 byte.
+pub fn write_tagged_fields(buf: &mut Vec<u8>, fields: &[(u64, &[u8])]) {
+    write_unsigned_varint(buf, fields.len() as u64);
+    for (tag, value) in fields {
+        write_unsigned_varint(buf, *tag);
+        write_unsigned_varint(buf, value.len() as u64);
+        buf.extend_from_slice(value);
+    }
+}
+
+pub fn write_array_len(buf: &mut Vec<u8>, len: usize) {
+    buf.extend_from_slice(&(len as i32).to_be_bytes());
+}
+
+pub fn write_compact_array_len(buf: &mut Vec<u8>, len: usize) {
+    write_unsigned_varint(buf, len as u64 + 1);
+}
+
+/// A response being written, header first. Its size prefix is left for
+/// `finish` to fill in from the bytes written after it, so the prefix can't
+/// drift from the body as fields are added.
+pub struct ResponseWriter {
+    buf: Vec<u8>,
+}
+
+impl ResponseWriter {
+    /// A response to `correlation_id` with a v0 response header, or v1 (with
+    /// its empty TAG_BUFFER) when `flexible_header`.
+    pub fn new(correlation_id: u32, flexible_header: bool) -> ResponseWriter {
+        ResponseWriter::in_buffer(Vec::new(), correlation_id, flexible_header)
+    }
+
+    /// `new`, written into `buf` (one from the buffer pool, say), which must be
+    /// empty.
+    pub fn in_buffer(
+        mut buf: Vec<u8>,
+        correlation_id: u32,
+        flexible_header: bool,
+    ) -> ResponseWriter {
+        debug_assert!(buf.is_empty());
+        buf.extend_from_slice(&[0; MESSAGE_SIZE_LEN]); // Filled in by finish
+        buf.extend_from_slice(&correlation_id.to_be_bytes());
+        let mut writer = ResponseWriter { buf };
+        if flexible_header {
+            writer.put_tag_buffer();
+        }
+        writer
+    }
+
+    pub fn put_u8(&mut self, value: u8) {
+        self.buf.push(value);
+    }
+
+    pub fn put_i8(&mut self, value: i8) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_i16(&mut self, value: i16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_u16(&mut self, value: u16) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_i32(&mut self, value: i32) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_i64(&mut self, value: i64) {
+        self.buf.extend_from_slice(&value.to_be_bytes());
+    }
+
+    pub fn put_uuid(&mut self, value: [u8; 16]) {
+        self.buf.extend_from_slice(&value);
+    }
+
+    /// Bytes written as they are, with no length: a slot someone else fills
+    /// in, say.
+    pub fn put_raw(&mut self, value: &[u8]) {
+        self.buf.extend_from_slice(value);
+    }
+
+    pub fn put_array_len(&mut self, len: usize) {
+        write_array_len(&mut self.buf, len);
+    }
+
+    pub fn put_compact_array_len(&mut self, len: usize) {
+        write_compact_array_len(&mut self.buf, len);
+    }
+
+    pub fn put_string(&mut self, value: &str) {
+        write_string(&mut self.buf, value);
+    }
+
+    pub fn put_nullable_string(&mut self, value: Option<&str>) {
+        write_nullable_string(&mut self.buf, value);
+    }
+
+    pub fn put_compact_string(&mut self, value: &str) {
+        write_compact_string(&mut self.buf, value);
+    }
+
+    pub fn put_compact_nullable_string(&mut self, value: Option<&str>) {
+        write_compact_nullable_string(&mut self.buf, value);
+    }
+
+    pub fn put_bytes(&mut self, value: &[u8]) {
+        write_bytes(&mut self.buf, value);
+    }
+
+    pub fn put_compact_bytes(&mut self, value: &[u8]) {
+        write_compact_bytes(&mut self.buf, value);
+    }
+
+    /// An empty TAG_BUFFER.
+    pub fn put_tag_buffer(&mut self) {
+        write_tagged_fields(&mut self.buf, &[]);
+    }
+
+    /// A TAG_BUFFER holding `fields`, as `write_tagged_fields` writes it.
+    pub fn put_tagged_fields(&mut self, fields: &[(u64, &[u8])]) {
+        write_tagged_fields(&mut self.buf, fields);
+    }
+
+    /// The whole response, its size prefix covering everything after it.
+    pub fn finish(mut self) -> Vec<u8> {
+        let size = (self.buf.len() - MESSAGE_SIZE_LEN) as u32;
+        self.buf[..MESSAGE_SIZE_LEN].copy_from_slice(&size.to_be_bytes());
+        self.buf
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unsigned(value: u64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_unsigned_varint(&mut buf, value);
+        buf
+    }
+
+    fn signed(value: i64) -> Vec<u8> {
+        let mut buf = Vec::new();
+        write_varint(&mut buf, value);
+        buf
+    }
+
+    #[test]
+    fn unsigned_varints_round_trip() {
+        for value in [0, 1, 127, 128, 16_383, 16_384, u32::MAX as u64, u64::MAX] {
+            let buf = unsigned(value);
+            assert_eq!(read_unsigned_varint(&buf).unwrap(), (value, buf.len()));
+        }
+    }
+
+    #[test]
+    fn unsigned_varints_grow_a_byte_every_seven_bits() {
+        assert_eq!(unsigned(127), [0x7f]);
+        assert_eq!(unsigned(128), [0x80, 0x01]);
+        assert_eq!(unsigned(16_383), [0xff, 0x7f]);
+        assert_eq!(unsigned(16_384), [0x80, 0x80, 0x01]);
+        assert_eq!(unsigned(u32::MAX as u64), [0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert_eq!(unsigned(u64::MAX).len(), MAX_VARINT_LEN);
+    }
+
+    #[test]
+    fn trailing_bytes_are_left_unread() {
+        assert_eq!(read_unsigned_varint(&[0x80, 0x01, 0xff]).unwrap(), (128, 2));
+    }
+
+    #[test]
+    fn zigzag_varints_round_trip() {
+        for value in [
+            0,
+            -1,
+            1,
+            -64,
+            64,
+            -8_192,
+            8_192,
+            i32::MIN as i64,
+            i64::MIN,
+            i64::MAX,
+        ] {
+            let buf = signed(value);
+            assert_eq!(read_varint(&buf).unwrap(), (value, buf.len()));
+        }
+    }
+
+    #[test]
+    fn zigzag_interleaves_signs() {
+        assert_eq!(signed(0), [0x00]);
+        assert_eq!(signed(-1), [0x01]);
+        assert_eq!(signed(1), [0x02]);
+        assert_eq!(signed(-64), [0x7f]);
+        assert_eq!(signed(64), [0x80, 0x01]);
+    }
+
+    #[test]
+    fn truncated_varints_are_refused() {
+        for buf in [&[][..], &[0x80], &[0xff, 0xff, 0xff]] {
+            assert!(matches!(
+                read_unsigned_varint(buf),
+                Err(DecodeError::Truncated { available, .. }) if available == buf.len()
+            ));
+            assert!(matches!(
+                read_varint(buf),
+                Err(DecodeError::Truncated { .. })
+            ));
+        }
+    }
+
+    #[test]
+    fn overlong_varints_are_refused() {
+        // Ten continuation bytes never end within the longest a varint can be
+        assert!(matches!(
+            read_unsigned_varint(&[0x80; 11]),
+            Err(DecodeError::VarintTooLong { offset: 0 })
+        ));
+        assert!(matches!(
+            read_unsigned_varint(&[0x80; MAX_VARINT_LEN]),
+            Err(DecodeError::VarintTooLong { offset: 0 })
+        ));
+    }
+
+    #[test]
+    fn varints_past_64_bits_are_refused() {
+        let mut past = vec![0xff; MAX_VARINT_LEN - 1];
+        past.push(0x02);
+        assert!(matches!(
+            read_unsigned_varint(&past),
+            Err(DecodeError::VarintTooLong { offset: 0 })
+        ));
+        // The 64th bit alone still fits
+        let mut last_bit = vec![0xff; MAX_VARINT_LEN - 1];
+        last_bit.push(0x01);
+        assert_eq!(
+            read_unsigned_varint(&last_bit).unwrap(),
+            (u64::MAX, MAX_VARINT_LEN)
+        );
+    }
+
+    #[test]
+    fn decoder_errors_report_offsets_in_the_whole_buffer() {
+        let mut decoder = Decoder::new(&[0x00, 0x00, 0x80]);
+        decoder.read_i16().unwrap();
+        assert!(matches!(
+            decoder.read_varint(),
+            Err(DecodeError::Truncated { offset: 2, .. })
+        ));
+    }
+
+    #[test]
+    fn overlong_strings_are_cut_to_fit_their_length() {
+        let mut short = Vec::new();
+        write_string(&mut short, "abc");
+        assert_eq!(short, b"\x00\x03abc");
+
+        // One byte short of the limit, then a two-byte character that doesn't fit
+        let value = format!("{}\u{e9}", "a".repeat(i16::MAX as usize - 1));
+        let mut long = Vec::new();
+        write_string(&mut long, &value);
+        let mut decoder = Decoder::new(&long);
+        assert_eq!(
+            decoder.read_string(false).unwrap(),
+            value[..value.len() - 2]
+        );
+        assert_eq!(decoder.remaining(), 0);
+    }
+
+    #[test]
+    fn response_size_prefix_covers_everything_after_it() {
+        for flexible_header in [false, true] {
+            let mut response = ResponseWriter::new(7, flexible_header);
+            response.put_i16(0);
+            response.put_compact_string("topic");
+            response.put_nullable_string(None);
+            response.put_bytes(&[1; 300]);
+            response.put_tagged_fields(&[(0, b"tagged")]);
+            let response = response.finish();
+
+            let mut decoder = Decoder::new(&response);
+            let size = decoder.read_u32().unwrap() as usize;
+            assert_eq!(size, response.len() - MESSAGE_SIZE_LEN);
+            assert_eq!(decoder.read_u32().unwrap(), 7);
+        }
+        assert_eq!(
+            ResponseWriter::new(7, false).finish(),
+            [0, 0, 0, 4, 0, 0, 0, 7]
+        );
+    }
+}
@@ -0,0 +1,392 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use anyhow::{bail, Context, Result};
+
+use crate::echo::EchoLimits;
+use crate::group_coordinator::GroupLimits;
+use crate::ids::NodeId;
+use crate::listener::{self, Listener};
+use crate::sasl;
+
+const DEFAULT_RESPONSE_WRITE_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_REQUEST_READ_TIMEOUT_MS: u64 = 30_000;
+const DEFAULT_MAX_REQUEST_BYTES: u32 = 104_857_600;
+const DEFAULT_RESPONSE_COALESCE_MAX_BYTES: usize = 65_536;
+const DEFAULT_LOG_DIR: &str = "/tmp/kraft-combined-logs";
+const DEFAULT_NODE_ID: NodeId = NodeId(1);
+const DEFAULT_ADVERTISED_HOST: &str = "localhost";
+const DEFAULT_ADVERTISED_PORT: i32 = 9092;
+const DEFAULT_LISTENER_HOST: &str = "127.0.0.1";
+const DEFAULT_LIVENESS_INTERVAL_MS: u64 = 10_000;
+const DEFAULT_NUM_PARTITIONS: i32 = 1;
+const DEFAULT_RETENTION_CHECK_INTERVAL_MS: u64 = 300_000;
+const DEFAULT_RECOVERY_THREADS: usize = 1;
+const DEFAULT_CLIENT_INVENTORY_MAX_ENTRIES: usize = 1000;
+const DEFAULT_CLIENT_INVENTORY_RETENTION_MS: u64 = 3_600_000;
+
+/// The `log.store` values.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LogStoreKind {
+    /// Partition logs written to files under the log directory.
+    File,
+    /// Partition logs kept in memory, gone when the broker exits.
+    Memory,
+}
+
+/// Broker settings read from the `server.properties` file passed as the first
+/// argument. Keys we don't use (most of a stock Kafka config) are ignored.
+pub struct BrokerConfig {
+    /// How long a response write may make no progress before the client is
+    /// considered stalled and its connection closed.
+    pub response_write_timeout: Duration,
+    /// How long the rest of a frame may take to arrive once its size prefix has
+    /// been read. Time spent waiting for the next frame is not limited.
+    pub request_read_timeout: Duration,
+    /// Largest frame a client may send, from `socket.request.max.bytes`. A size
+    /// prefix past it can't be trusted to find the next frame by, so the
+    /// connection is closed rather than the frame read.
+    pub max_request_bytes: u32,
+    /// Most bytes of responses a connection holds back to send in one write
+    /// while its next request has already arrived, from
+    /// `response.coalesce.max.bytes`. 1 writes each response on its own.
+    pub response_coalesce_max_bytes: usize,
+    /// When set, every connection's request and response frames are written to a
+    /// session file in this directory for later `replay`.
+    pub session_record_dir: Option<PathBuf>,
+    /// Where the KRaft `__cluster_metadata` log is read from, and file-backed
+    /// partition logs are kept. Only the first entry of `log.dirs` is used.
+    pub log_dir: PathBuf,
+    /// Which `LogStore` keeps the partition logs, from `log.store`.
+    pub log_store: LogStoreKind,
+    /// Caps on client-supplied names and entries echoed in error responses.
+    pub echo_limits: EchoLimits,
+    /// The session timeouts consumer group members may join with, and caps on
+    /// the member metadata groups keep in memory.
+    pub group_limits: GroupLimits,
+    /// This broker's id, reported as the only broker, leader and controller.
+    pub node_id: NodeId,
+    /// Where clients are told to connect, from the first `advertised.listeners`
+    /// entry.
+    pub advertised_host: String,
+    pub advertised_port: i32,
+    /// Reported as this broker's rack, from `broker.rack`.
+    pub rack: Option<String>,
+    /// Every `listeners` entry, with its security protocol and policy; see
+    /// `listener`. Never empty.
+    pub listeners: Vec<Listener>,
+    /// Written once startup is complete and removed when shutdown begins.
+    pub readiness_file: Option<PathBuf>,
+    /// Rewritten every `liveness_interval` while the broker isn't wedged.
+    pub liveness_file: Option<PathBuf>,
+    pub liveness_interval: Duration,
+    /// Partitions given to a created topic that doesn't ask for a count.
+    pub default_partitions: i32,
+    /// How often records past their topic's `retention.ms` are deleted.
+    pub retention_check_interval: Duration,
+    /// How many partitions the file store recovers at once at startup, from
+    /// `num.recovery.threads.per.data.dir`.
+    pub recovery_threads: usize,
+    /// How often the partition logs are flushed when nothing waits on it, from
+    /// `log.flush.interval.ms`. Unset, only barriers and shutdown flush them.
+    pub flush_interval: Option<Duration>,
+    /// Whether acks=-1 produces are answered only once their batches are
+    /// flushed, from `log.flush.on.acks.all`.
+    pub flush_on_acks_all: bool,
+    /// How many clients the client inventory keeps, from
+    /// `client.inventory.max.entries`; see `client_inventory`.
+    pub client_inventory_max_entries: usize,
+    /// How long a client with no open connection stays in the inventory.
+    pub client_inventory_retention: Duration,
+    /// Usernames and their passwords, when `sasl.enabled.mechanisms` has PLAIN:
+    /// then every connection has to authenticate before it is served.
+    pub sasl_plain_users: Option<HashMap<String, String>>,
+}
+
+impl Default for BrokerConfig {
+    fn default() -> Self {
+        BrokerConfig {
+            response_write_timeout: Duration::from_millis(DEFAULT_RESPONSE_WRITE_TIMEOUT_MS),
+            request_read_timeout: Duration::from_millis(DEFAULT_REQUEST_READ_TIMEOUT_MS),
+            max_request_bytes: DEFAULT_MAX_REQUEST_BYTES,
+            response_coalesce_max_bytes: DEFAULT_RESPONSE_COALESCE_MAX_BYTES,
+            session_record_dir: None,
+            log_dir: PathBuf::from(DEFAULT_LOG_DIR),
+            log_store: LogStoreKind::File,
+            echo_limits: EchoLimits::default(),
+            group_limits: GroupLimits::default(),
+            node_id: DEFAULT_NODE_ID,
+            advertised_host: DEFAULT_ADVERTISED_HOST.to_string(),
+            advertised_port: DEFAULT_ADVERTISED_PORT,
+            rack: None,
+            listeners: vec![Listener::plaintext(format!(
+                "{}:{}",
+                DEFAULT_LISTENER_HOST, DEFAULT_ADVERTISED_PORT
+            ))],
+            readiness_file: None,
+            liveness_file: None,
+            liveness_interval: Duration::from_millis(DEFAULT_LIVENESS_INTERVAL_MS),
+            default_partitions: DEFAULT_NUM_PARTITIONS,
+            retention_check_interval: Duration::from_millis(DEFAULT_RETENTION_CHECK_INTERVAL_MS),
+            recovery_threads: DEFAULT_RECOVERY_THREADS,
+            flush_interval: None,
+            flush_on_acks_all: false,
+            client_inventory_max_entries: DEFAULT_CLIENT_INVENTORY_MAX_ENTRIES,
+            client_inventory_retention: Duration::from_millis(
+                DEFAULT_CLIENT_INVENTORY_RETENTION_MS,
+            ),
+            sasl_plain_users: None,
+        }
+    }
+}
+
+impl BrokerConfig {
+    /// Loads the properties file named by the first command-line argument, or
+    /// the defaults when the broker is started without one.
+    pub fn from_args(args: &[String]) -> Result<BrokerConfig> {
+        match args.get(1) {
+            Some(path) => BrokerConfig::load(Path::new(&path)),
+            None => Ok(BrokerConfig::default()),
+        }
+    }
+
+    pub fn load(path: &Path) -> Result<BrokerConfig> {
+        let contents = fs::read_to_string(path)
+            .with_context(|| format!("reading config file {}", path.display()))?;
+        let properties = parse_properties(&contents);
+
+        let mut config = BrokerConfig::default();
+        if let Some(timeout) = parse_timeout_ms(&properties, "response.write.timeout.ms")? {
+            config.response_write_timeout = timeout;
+        }
+        if let Some(timeout) = parse_timeout_ms(&properties, "request.read.timeout.ms")? {
+            config.request_read_timeout = timeout;
+        }
+        if let Some(bytes) = parse_positive(&properties, "socket.request.max.bytes")? {
+            config.max_request_bytes = u32::try_from(bytes)
+                .with_context(|| format!("invalid socket.request.max.bytes '{}'", bytes))?;
+        }
+        if let Some(bytes) = parse_positive(&properties, "response.coalesce.max.bytes")? {
+            config.response_coalesce_max_bytes = usize::try_from(bytes)
+                .with_context(|| format!("invalid response.coalesce.max.bytes '{}'", bytes))?;
+        }
+        if let Some(value) = properties.get("session.record.dir") {
+            config.session_record_dir = Some(PathBuf::from(value));
+        }
+        if let Some(value) = properties.get("log.dirs") {
+            let first = value.split(',').next().unwrap_or_default().trim();
+            if first.is_empty() {
+                bail!("invalid log.dirs '{}'", value);
+            }
+            config.log_dir = PathBuf::from(first);
+        }
+        if let Some(value) = properties.get("log.store") {
+            config.log_store = match value.as_str() {
+                "file" => LogStoreKind::File,
+                "memory" => LogStoreKind::Memory,
+                _ => bail!("invalid log.store '{}', expected file or memory", value),
+            };
+        }
+        if let Some(max) = parse_positive(&properties, "error.echo.max.name.bytes")? {
+            config.echo_limits.max_name_bytes = max as usize;
+        }
+        if let Some(max) = parse_positive(&properties, "error.echo.max.partitions")? {
+            config.echo_limits.max_partitions = max as usize;
+        }
+        if let Some(max) = parse_positive(&properties, "group.max.member.metadata.bytes")? {
+            config.group_limits.max_member_metadata_bytes = max as usize;
+        }
+        if let Some(max) = parse_positive(&properties, "group.max.metadata.bytes")? {
+            config.group_limits.max_group_metadata_bytes = max as usize;
+        }
+        for (key, limit) in [
+            (
+                "group.min.session.timeout.ms",
+                &mut config.group_limits.min_session_timeout_ms,
+            ),
+            (
+                "group.max.session.timeout.ms",
+                &mut config.group_limits.max_session_timeout_ms,
+            ),
+        ] {
+            if let Some(timeout) = parse_positive(&properties, key)? {
+                *limit = i32::try_from(timeout)
+                    .with_context(|| format!("invalid {} '{}'", key, timeout))?;
+            }
+        }
+        let limits = &config.group_limits;
+        if limits.min_session_timeout_ms > limits.max_session_timeout_ms {
+            bail!(
+                "group.min.session.timeout.ms {} is above group.max.session.timeout.ms {}",
+                limits.min_session_timeout_ms,
+                limits.max_session_timeout_ms
+            );
+        }
+        if let Some(value) = properties.get("node.id") {
+            config.node_id = value
+                .parse()
+                .ok()
+                .filter(|node_id| *node_id >= 0)
+                .map(NodeId)
+                .with_context(|| format!("invalid node.id '{}'", value))?;
+        }
+        if let Some(value) = properties.get("advertised.listeners") {
+            let (host, port) = parse_listener(value)
+                .with_context(|| format!("invalid advertised.listeners '{}'", value))?;
+            config.advertised_host = match host.as_str() {
+                "" => DEFAULT_ADVERTISED_HOST.to_string(),
+                _ => host,
+            };
+            config.advertised_port = port;
+        }
+        if let Some(value) = properties.get("broker.rack") {
+            config.rack = Some(value.clone());
+        }
+        if let Some(listeners) = listener::from_properties(&properties, DEFAULT_LISTENER_HOST)? {
+            config.listeners = listeners;
+        }
+        if let Some(value) = properties.get("readiness.file") {
+            config.readiness_file = Some(PathBuf::from(value));
+        }
+        if let Some(value) = properties.get("liveness.file") {
+            config.liveness_file = Some(PathBuf::from(value));
+        }
+        if let Some(interval) = parse_timeout_ms(&properties, "liveness.interval.ms")? {
+            config.liveness_interval = interval;
+        }
+        if let Some(partitions) = parse_positive(&properties, "num.partitions")? {
+            config.default_partitions = i32::try_from(partitions)
+                .with_context(|| format!("invalid num.partitions '{}'", partitions))?;
+        }
+        if let Some(interval) = parse_timeout_ms(&properties, "log.retention.check.interval.ms")? {
+            config.retention_check_interval = interval;
+        }
+        if let Some(threads) = parse_positive(&properties, "num.recovery.threads.per.data.dir")? {
+            config.recovery_threads = usize::try_from(threads).with_context(|| {
+                format!("invalid num.recovery.threads.per.data.dir '{}'", threads)
+            })?;
+        }
+        config.flush_interval = parse_timeout_ms(&properties, "log.flush.interval.ms")?;
+        if let Some(flush) = parse_bool(&properties, "log.flush.on.acks.all")? {
+            config.flush_on_acks_all = flush;
+        }
+        if let Some(max) = parse_positive(&properties, "client.inventory.max.entries")? {
+            config.client_inventory_max_entries = usize::try_from(max)
+                .with_context(|| format!("invalid client.inventory.max.entries '{}'", max))?;
+        }
+        if let Some(retention) = parse_timeout_ms(&properties, "client.inventory.retention.ms")? {
+            config.client_inventory_retention = retention;
+        }
+        if let Some(value) = properties.get("sasl.enabled.mechanisms") {
+            let mechanisms: Vec<&str> = value
+                .split(',')
+                .map(str::trim)
+                .filter(|mechanism| !mechanism.is_empty())
+                .collect();
+            if let Some(unsupported) = mechanisms.iter().find(|m| **m != sasl::PLAIN) {
+                bail!(
+                    "unsupported SASL mechanism '{}' in sasl.enabled.mechanisms; only PLAIN is",
+                    unsupported
+                );
+            }
+            if !mechanisms.is_empty() {
+                let users = properties
+                    .get("sasl.plain.users")
+                    .context("sasl.enabled.mechanisms=PLAIN needs sasl.plain.users")?;
+                config.sasl_plain_users =
+                    Some(parse_plain_users(users).context("invalid sasl.plain.users")?);
+            }
+        }
+        // Users with no listener to authenticate on would leave every
+        // connection unauthenticated, which can't be what was meant
+        let sasl_listener = config
+            .listeners
+            .iter()
+            .find(|listener| listener.requires_authentication());
+        match (sasl_listener, &config.sasl_plain_users) {
+            (Some(listener), None) => bail!(
+                "listener {} is SASL_PLAINTEXT, which needs sasl.enabled.mechanisms=PLAIN",
+                listener.name
+            ),
+            (None, Some(_)) => {
+                bail!("sasl.enabled.mechanisms is set, but no listener is SASL_PLAINTEXT")
+            }
+            _ => {}
+        }
+        Ok(config)
+    }
+}
+
+/// `user:password` pairs separated by commas, so passwords can't contain one.
+fn parse_plain_users(value: &str) -> Option<HashMap<String, String>> {
+    let mut users = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (user, password) = entry.split_once(':')?;
+        if user.is_empty()
+            || users
+                .insert(user.to_string(), password.to_string())
+                .is_some()
+        {
+            return None;
+        }
+    }
+    (!users.is_empty()).then_some(users)
+}
+
+/// Reads a positive millisecond duration; zero would mean "no timeout" to the socket
+/// APIs, which is never what these settings intend.
+fn parse_timeout_ms(properties: &HashMap<String, String>, key: &str) -> Result<Option<Duration>> {
+    Ok(parse_positive(properties, key)?.map(Duration::from_millis))
+}
+
+fn parse_positive(properties: &HashMap<String, String>, key: &str) -> Result<Option<u64>> {
+    let Some(value) = properties.get(key) else {
+        return Ok(None);
+    };
+    let parsed: u64 = value
+        .parse()
+        .ok()
+        .filter(|parsed| *parsed > 0)
+        .with_context(|| format!("invalid {} '{}'", key, value))?;
+    Ok(Some(parsed))
+}
+
+fn parse_bool(properties: &HashMap<String, String>, key: &str) -> Result<Option<bool>> {
+    let Some(value) = properties.get(key) else {
+        return Ok(None);
+    };
+    match value.as_str() {
+        "true" => Ok(Some(true)),
+        "false" => Ok(Some(false)),
+        _ => bail!("invalid {} '{}', expected true or false", key, value),
+    }
+}
+
+/// Host and port of the first `NAME://host:port` entry. The host is empty when
+/// the entry leaves it to the default.
+fn parse_listener(value: &str) -> Option<(String, i32)> {
+    let first = value.split(',').next()?.trim();
+    let (_, address) = first.split_once("://")?;
+    let (host, port) = address.rsplit_once(':')?;
+    let port = port
+        .parse()
+        .ok()
+        .filter(|port| (1..=65535).contains(port))?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    Some((host.to_string(), port))
+}
+
+/// Java-style `key=value` properties, skipping blank lines and `#`/`!` comments.
+pub fn parse_properties(contents: &str) -> HashMap<String, String> {
+    contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#') && !line.starts_with('!'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            Some((key.trim().to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
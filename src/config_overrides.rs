@@ -0,0 +1,192 @@
+//! Topic config overrides set by IncrementalAlterConfigs, saved so they
+//! survive a restart.
+//!
+//! The metadata log is Kafka's, written by its tools and only ever read here,
+//! so overrides go in a file of this broker's own beside it (see
+//! `saved_file`), `<log_dir>/topic-config-overrides`: a `version=1` line, then
+//! one line per override, `<topic id>\t<config>\t<value>`, with no tab or
+//! value for a null one. A file of a version this broker doesn't know fails
+//! startup rather than being overwritten with less than it holds.
+//!
+//! Each alteration replaces its topic's overrides whole (the last writer wins
+//! per topic) and rewrites the file. Alterations save one at a time, and an
+//! alteration is applied and answered only once it's saved, so none that a
+//! client was told of is lost. A
+//! temporary file left at startup is one a crash cut short before its rename:
+//! its alteration was never applied or answered, which is logged before the
+//! file is removed.
+//!
+//! Overrides are saved only by brokers whose partition logs are
+//! (`log.store=file`); with logs in memory, a restart forgets them, as it does
+//! the records. At startup they're loaded onto the topics the metadata log
+//! and `TopicChanges` bring back, before anything is served; those of topics
+//! deleted since they were saved are dropped.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{BrokerConfig, LogStoreKind};
+use crate::ids::TopicId;
+use crate::metadata::ClusterMetadata;
+use crate::saved_file::{self, escape, unescape};
+
+const OVERRIDES_FILE: &str = "topic-config-overrides";
+const FORMAT_VERSION: u32 = 1;
+
+type Configs = Vec<(String, Option<String>)>;
+
+pub struct ConfigOverrides {
+    /// Where they're saved, if they are.
+    path: Option<PathBuf>,
+    /// Every topic's saved overrides. Locked while the file is written, which
+    /// is what keeps two alterations from writing it at once.
+    saved: Mutex<HashMap<TopicId, Configs>>,
+}
+
+impl ConfigOverrides {
+    /// Overrides saved in the log directory, if the partition logs are.
+    pub fn new(config: &BrokerConfig) -> ConfigOverrides {
+        ConfigOverrides {
+            path: (config.log_store == LogStoreKind::File)
+                .then(|| config.log_dir.join(OVERRIDES_FILE)),
+            saved: Mutex::default(),
+        }
+    }
+
+    fn saved_locked(&self) -> MutexGuard<'_, HashMap<TopicId, Configs>> {
+        // Replaced whole, and only once the file has been
+        self.saved
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Loads the saved overrides onto `metadata`'s topics.
+    pub fn load(&self, metadata: &mut ClusterMetadata) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let staged = saved_file::staged_path(path);
+        if staged.exists() {
+            println!(
+                "WARNING: {} is left from a config alteration cut short before it was saved; \
+                 it was never applied or answered, and is discarded",
+                staged.display()
+            );
+            fs::remove_file(&staged).with_context(|| format!("removing {}", staged.display()))?;
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let mut loaded = parse(&contents).with_context(|| format!("parsing {}", path.display()))?;
+        let before = loaded.len();
+        loaded.retain(|topic_id, _| metadata.topic_by_id(*topic_id).is_some());
+        for (topic_id, configs) in &loaded {
+            metadata.set_configs(*topic_id, configs.clone());
+        }
+        println!(
+            "Loaded config overrides of {} topic(s) from {}, {} dropped with their topics",
+            loaded.len(),
+            path.display(),
+            before - loaded.len()
+        );
+        *self.saved_locked() = loaded;
+        Ok(())
+    }
+
+    /// Saves `configs` as a topic's overrides, replacing those saved before.
+    /// Nothing is changed if they can't be.
+    pub fn save(&self, topic_id: TopicId, configs: &[(String, Option<String>)]) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut saved = self.saved_locked();
+        let mut next = saved.clone();
+        if configs.is_empty() {
+            next.remove(&topic_id);
+        } else {
+            next.insert(topic_id, configs.to_vec());
+        }
+        saved_file::replace(path, &format(&next))?;
+        *saved = next;
+        Ok(())
+    }
+}
+
+/// The file's contents for `overrides`, topics in id order.
+fn format(overrides: &HashMap<TopicId, Configs>) -> String {
+    let mut topics: Vec<_> = overrides.iter().collect();
+    topics.sort_by_key(|(topic_id, _)| **topic_id);
+    let mut contents = format!("version={}\n", FORMAT_VERSION);
+    for (topic_id, configs) in topics {
+        for (name, value) in configs {
+            contents.push_str(&format!("{}\t{}", topic_id, escape(name)));
+            if let Some(value) = value {
+                contents.push('\t');
+                contents.push_str(&escape(value));
+            }
+            contents.push('\n');
+        }
+    }
+    contents
+}
+
+fn parse(contents: &str) -> Result<HashMap<TopicId, Configs>> {
+    let mut lines = contents.lines();
+    saved_file::check_version(lines.next(), FORMAT_VERSION)?;
+    let mut overrides: HashMap<TopicId, Configs> = HashMap::new();
+    for (i, line) in lines.enumerate() {
+        let mut fields = line.split('\t');
+        let topic_id = fields.next().and_then(TopicId::from_hex);
+        let name = fields.next().and_then(unescape);
+        let value = fields.next().map(unescape);
+        let (Some(topic_id), Some(name), None | Some(Some(_)), None) =
+            (topic_id, name, &value, fields.next())
+        else {
+            bail!("line {} is not an override", i + 2);
+        };
+        overrides
+            .entry(topic_id)
+            .or_default()
+            .push((name, value.flatten()));
+    }
+    Ok(overrides)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_escapes_and_nulls() {
+        let configs = vec![
+            ("retention.ms".to_string(), Some("60000".to_string())),
+            ("x-odd\tname".to_string(), Some("a\\b\nc\r".to_string())),
+            ("x-null".to_string(), None),
+        ];
+        let overrides = HashMap::from([(TopicId([7; 16]), configs)]);
+        assert_eq!(parse(&format(&overrides)).unwrap(), overrides);
+    }
+
+    #[test]
+    fn refuses_other_versions_and_malformed_lines() {
+        let id = TopicId([7; 16]);
+        for contents in [
+            String::new(),
+            "retention.ms=1\n".to_string(),
+            "version=2\n".to_string(),
+            format!("version=1\n{}\n", id),
+            "version=1\nnot-an-id\tretention.ms\t1\n".to_string(),
+            format!("version=1\n{}\tretention.ms\t1\textra\n", id),
+            format!("version=1\n{}\tretention.ms\tbad\\escape\n", id),
+        ] {
+            assert!(parse(&contents).is_err(), "{:?} parsed", contents);
+        }
+    }
+}
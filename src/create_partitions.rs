@@ -0,0 +1,268 @@
+//! CreatePartitions (API key 37), versions 0-3.
+//!
+//! Grows a topic to a new, larger partition count. The new partitions are
+//! numbered on from the last one, so a topic's indexes stay contiguous, and
+//! start out with empty logs. A manual assignment has to name exactly the new
+//! partitions, each replicated only to this broker. Every topic succeeds or
+//! fails on its own; with validate_only set every check runs but nothing
+//! changes.
+//!
+//! v0-v1 use the classic encoding; v2+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::create_topics::MAX_PARTITIONS;
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::ids::{NodeId, PartitionId, TopicId};
+use crate::log_store;
+use crate::metadata::ClusterMetadata;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 2;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const INVALID_PARTITIONS: i16 = 37;
+const INVALID_REPLICA_ASSIGNMENT: i16 = 39;
+const INVALID_REQUEST: i16 = 42;
+
+struct TopicRequest {
+    name: String,
+    count: i32,
+    /// The broker ids of each new partition, in index order; `None` to leave
+    /// it to the broker.
+    assignments: Option<Vec<Vec<NodeId>>>,
+}
+
+struct TopicResponse {
+    name: String,
+    error: Option<BrokerError>,
+}
+
+/// Handles a CreatePartitions request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        requests.push(read_topic(&mut decoder, flexible)?);
+    }
+    let timeout_ms = decoder.read_i32()?;
+    let validate_only = decoder.read_i8()? != 0;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    // A name given twice is rejected for every occurrence, as Kafka does
+    let repeated = duplicates::repeated(requests.iter().map(|request| request.name.as_str()));
+
+    let mut metadata = broker.metadata_mut();
+    let mut topics = Vec::with_capacity(requests.len());
+    for request in &requests {
+        let checked = if repeated.contains(request.name.as_str()) {
+            Err(BrokerError::with_message(
+                INVALID_REQUEST,
+                "Topic appears more than once in the request",
+            ))
+        } else {
+            check_topic(broker, &metadata, request)
+        };
+        let error = match checked {
+            Ok(_) if validate_only => None,
+            Ok((topic_id, current)) => {
+                // Saved first, so a growth that's answered is never lost, then
+                // the logs, so a produce can't find a new partition without one
+                if let Err(e) = broker.topic_changes().save_grown(topic_id, request.count) {
+                    println!("Error saving the growth of topic '{}': {}", request.name, e);
+                    Some(log_store::storage_error())
+                } else if let Err(e) =
+                    broker.create_logs(topic_id, (current..request.count).map(PartitionId))
+                {
+                    println!("Growing topic '{}' failed: {}", request.name, e);
+                    if let Err(e) = broker.topic_changes().save_grown(topic_id, current) {
+                        println!(
+                            "Error saving topic '{}' back at {} partition(s): {}",
+                            request.name, current, e
+                        );
+                    }
+                    Some(e.to_broker_error())
+                } else {
+                    metadata.add_partitions(topic_id, request.count);
+                    println!(
+                        "Topic '{}' grown from {} to {} partition(s)",
+                        request.name, current, request.count
+                    );
+                    None
+                }
+            }
+            Err(error) => Some(error),
+        };
+        topics.push(TopicResponse {
+            name: request.name.clone(),
+            error,
+        });
+    }
+    drop(metadata);
+
+    println!(
+        "CreatePartitions: timeout_ms={} validate_only={} topics={} succeeded={}",
+        timeout_ms,
+        validate_only,
+        topics.len(),
+        topics.iter().filter(|topic| topic.error.is_none()).count()
+    );
+    Ok(build_create_partitions_response(
+        header.correlation_id,
+        api_version,
+        topics,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn read_topic(decoder: &mut Decoder, flexible: bool) -> Result<TopicRequest, DecodeError> {
+    let name = decoder.read_string(flexible)?;
+    let count = decoder.read_i32()?;
+    let assignments = match decoder.read_array_len(flexible)? {
+        None => None,
+        Some(assignment_count) => {
+            let mut assignments = Vec::with_capacity(assignment_count);
+            for _ in 0..assignment_count {
+                let broker_count = decoder.read_array_len_or_empty(flexible)?;
+                let mut broker_ids = Vec::with_capacity(broker_count);
+                for _ in 0..broker_count {
+                    broker_ids.push(NodeId(decoder.read_i32()?));
+                }
+                if flexible {
+                    decoder.skip_tagged_fields()?;
+                }
+                assignments.push(broker_ids);
+            }
+            Some(assignments)
+        }
+    };
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+    Ok(TopicRequest {
+        name,
+        count,
+        assignments,
+    })
+}
+
+/// Returns the id of the topic to grow and its partition count, or why it
+/// can't be grown.
+fn check_topic(
+    broker: &Broker,
+    metadata: &ClusterMetadata,
+    request: &TopicRequest,
+) -> Result<(TopicId, i32), BrokerError> {
+    let Some(topic) = metadata.topic(&request.name) else {
+        return Err(BrokerError::with_message(
+            UNKNOWN_TOPIC_OR_PARTITION,
+            "This server does not host this topic",
+        ));
+    };
+    let current = topic.partitions.len() as i32;
+    if request.count <= current {
+        let message = if request.count == current {
+            format!("Topic already has {} partition(s)", current)
+        } else {
+            format!(
+                "Topic has {} partition(s), more than the {} requested: partitions can't be removed",
+                current, request.count
+            )
+        };
+        return Err(BrokerError::with_message(INVALID_PARTITIONS, message));
+    }
+    if request.count > MAX_PARTITIONS {
+        return Err(BrokerError::with_message(
+            INVALID_PARTITIONS,
+            format!(
+                "Number of partitions must be at most {}, got {}",
+                MAX_PARTITIONS, request.count
+            ),
+        ));
+    }
+    if let Some(assignments) = &request.assignments {
+        let invalid =
+            |message: String| BrokerError::with_message(INVALID_REPLICA_ASSIGNMENT, message);
+        let added = request.count - current;
+        if assignments.len() != added as usize {
+            return Err(invalid(format!(
+                "Increasing the number of partitions by {} but {} assignment(s) given",
+                added,
+                assignments.len()
+            )));
+        }
+        let node_id = broker.config.node_id;
+        if assignments
+            .iter()
+            .any(|broker_ids| broker_ids.as_slice() != [node_id])
+        {
+            return Err(invalid(format!(
+                "Every partition must be assigned to broker {} alone: this is a single-broker cluster",
+                node_id
+            )));
+        }
+    }
+    Ok((topic.topic_id, current))
+}
+
+/// CreatePartitionsResponse: throttle_time_ms, results [name, error_code,
+/// error_message].
+///
+/// Names come from the request, so they and the number of rejected topics are
+/// capped by `limits` before being echoed.
+fn build_create_partitions_response(
+    correlation_id: u32,
+    api_version: i16,
+    mut topics: Vec<TopicResponse>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let dropped = limits.cap_errors(&mut topics, |topic| topic.error.is_some());
+    if dropped > 0 {
+        println!("Omitted {} rejected topic(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    if flexible {
+        response.put_compact_array_len(topics.len());
+    } else {
+        response.put_array_len(topics.len());
+    }
+    for topic in &topics {
+        let name = limits.name(&topic.name);
+        let error_code = topic.error.as_ref().map_or(NONE, |error| error.code);
+        let error_message = topic.error.as_ref().and_then(BrokerError::message);
+        if flexible {
+            response.put_compact_string(name.as_str());
+            response.put_i16(error_code);
+            response.put_compact_nullable_string(error_message);
+            response.put_tag_buffer(); // result TAG_BUFFER
+        } else {
+            response.put_string(name.as_str());
+            response.put_i16(error_code);
+            response.put_nullable_string(error_message);
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,415 @@
+//! CreateTopics (API key 19), versions 2-7.
+//!
+//! Adds topics to the registry, where every other API sees them at once. There
+//! is one broker, so the only replication factor that can be satisfied is 1,
+//! and a manual replica assignment may only name this broker. With
+//! validate_only set every check runs but nothing is created.
+//!
+//! v2-v4 use the classic encoding; v5+ are flexible. From v5 the response also
+//! reports each topic's partition count, replication factor and configs, and
+//! from v7 its topic id.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::ids::{NodeId, PartitionId, TopicId};
+use crate::log_store;
+use crate::metadata::ClusterMetadata;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 5;
+
+/// Kafka's limits on topic names.
+const MAX_TOPIC_NAME_LEN: usize = 249;
+/// Most partitions one topic may be created with; each is a partition log the
+/// broker has to keep, so a single request mustn't be able to ask for billions.
+pub const MAX_PARTITIONS: i32 = 10_000;
+/// num_partitions and replication_factor of -1 mean "use the broker default".
+const DEFAULT_COUNT: i32 = -1;
+/// The RF every partition gets: this broker is the only replica.
+const REPLICATION_FACTOR: i16 = 1;
+/// config_source of a config set on the topic itself.
+const DYNAMIC_TOPIC_CONFIG: i8 = 1;
+
+const NONE: i16 = 0;
+const INVALID_TOPIC_EXCEPTION: i16 = 17;
+const TOPIC_ALREADY_EXISTS: i16 = 36;
+const INVALID_PARTITIONS: i16 = 37;
+const INVALID_REPLICATION_FACTOR: i16 = 38;
+const INVALID_REPLICA_ASSIGNMENT: i16 = 39;
+const INVALID_REQUEST: i16 = 42;
+
+struct TopicRequest {
+    name: String,
+    num_partitions: i32,
+    replication_factor: i16,
+    /// (partition_index, broker_ids), in request order.
+    assignments: Vec<(PartitionId, Vec<NodeId>)>,
+    configs: Vec<(String, Option<String>)>,
+}
+
+struct TopicResponse {
+    name: String,
+    topic_id: TopicId,
+    error_code: i16,
+    error_message: Option<String>,
+    num_partitions: i32,
+    replication_factor: i16,
+    configs: Vec<(String, Option<String>)>,
+}
+
+/// Handles a CreateTopics request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        requests.push(read_topic(&mut decoder, flexible)?);
+    }
+    let timeout_ms = decoder.read_i32()?;
+    let validate_only = decoder.read_i8()? != 0;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    // A name given twice is rejected for every occurrence, as Kafka does
+    let repeated = duplicates::repeated(requests.iter().map(|request| request.name.as_str()));
+
+    let mut metadata = broker.metadata_mut();
+    let mut topics = Vec::with_capacity(requests.len());
+    for request in &requests {
+        let checked = if repeated.contains(request.name.as_str()) {
+            Err(BrokerError::with_message(
+                INVALID_REQUEST,
+                "Topic appears more than once in the request",
+            ))
+        } else {
+            check_topic(broker, &metadata, request)
+        };
+        let created = checked.and_then(|partition_count| {
+            if validate_only {
+                return Ok((TopicId::ZERO, partition_count, request.configs.clone()));
+            }
+            let topic = metadata.create_topic(
+                request.name.clone(),
+                partition_count,
+                request.configs.clone(),
+            );
+            let (topic_id, configs) = (topic.topic_id, topic.configs.clone());
+            let partitions = topic.partitions.clone();
+            // Saved first, so a topic that's answered is never lost, and taken
+            // back out of the registry if it can't be or its logs can't be
+            // created, so no client ever sees a topic it can't produce to
+            if let Err(e) = broker.topic_changes().save_created(topic) {
+                println!("Error saving topic '{}': {}", request.name, e);
+                metadata.delete_topic(topic_id);
+                return Err(log_store::storage_error());
+            }
+            if let Err(e) = broker.create_logs(topic_id, partitions) {
+                println!("Creating topic '{}' failed: {}", request.name, e);
+                if let Err(e) = broker.topic_changes().save_deleted(topic_id) {
+                    println!(
+                        "Error saving the deletion of topic '{}': {}",
+                        request.name, e
+                    );
+                }
+                metadata.delete_topic(topic_id);
+                return Err(e.to_broker_error());
+            }
+            println!(
+                "Created topic '{}' with {} partition(s)",
+                request.name, partition_count
+            );
+            Ok((topic_id, partition_count, configs))
+        });
+        let response = match created {
+            Ok((topic_id, partition_count, configs)) => TopicResponse {
+                name: request.name.clone(),
+                topic_id,
+                error_code: NONE,
+                error_message: None,
+                num_partitions: partition_count,
+                replication_factor: REPLICATION_FACTOR,
+                configs,
+            },
+            Err(error) => TopicResponse {
+                name: request.name.clone(),
+                topic_id: TopicId::ZERO,
+                error_code: error.code,
+                error_message: error.message().map(str::to_string),
+                num_partitions: -1,
+                replication_factor: -1,
+                configs: Vec::new(),
+            },
+        };
+        topics.push(response);
+    }
+    drop(metadata);
+
+    println!(
+        "CreateTopics: timeout_ms={} validate_only={} topics={} succeeded={}",
+        timeout_ms,
+        validate_only,
+        topics.len(),
+        topics
+            .iter()
+            .filter(|topic| topic.error_code == NONE)
+            .count()
+    );
+    Ok(build_create_topics_response(
+        header.correlation_id,
+        api_version,
+        topics,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn read_topic(decoder: &mut Decoder, flexible: bool) -> Result<TopicRequest, DecodeError> {
+    let name = decoder.read_string(flexible)?;
+    let num_partitions = decoder.read_i32()?;
+    let replication_factor = decoder.read_i16()?;
+
+    let assignment_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut assignments = Vec::with_capacity(assignment_count);
+    for _ in 0..assignment_count {
+        let partition_index = PartitionId(decoder.read_i32()?);
+        let broker_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut broker_ids = Vec::with_capacity(broker_count);
+        for _ in 0..broker_count {
+            broker_ids.push(NodeId(decoder.read_i32()?));
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        assignments.push((partition_index, broker_ids));
+    }
+
+    let config_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut configs = Vec::with_capacity(config_count);
+    for _ in 0..config_count {
+        let config_name = decoder.read_string(flexible)?;
+        let value = decoder.read_nullable_string(flexible)?;
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        configs.push((config_name, value));
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    Ok(TopicRequest {
+        name,
+        num_partitions,
+        replication_factor,
+        assignments,
+        configs,
+    })
+}
+
+/// Returns the partition count the topic would be created with, or why it
+/// can't be.
+fn check_topic(
+    broker: &Broker,
+    metadata: &ClusterMetadata,
+    request: &TopicRequest,
+) -> Result<i32, BrokerError> {
+    check_topic_name(&request.name)?;
+    if metadata.topic(&request.name).is_some() {
+        return Err(BrokerError::with_message(
+            TOPIC_ALREADY_EXISTS,
+            "Topic already exists",
+        ));
+    }
+
+    if !request.assignments.is_empty() {
+        if request.num_partitions != DEFAULT_COUNT
+            || request.replication_factor != DEFAULT_COUNT as i16
+        {
+            return Err(BrokerError::with_message(
+                INVALID_REQUEST,
+                "Both num_partitions or replication_factor and assignments were set",
+            ));
+        }
+        return check_assignments(broker, &request.assignments);
+    }
+
+    let partition_count = match request.num_partitions {
+        DEFAULT_COUNT => broker.config.default_partitions,
+        count => count,
+    };
+    if !(1..=MAX_PARTITIONS).contains(&partition_count) {
+        return Err(BrokerError::with_message(
+            INVALID_PARTITIONS,
+            format!(
+                "Number of partitions must be between 1 and {}, got {}",
+                MAX_PARTITIONS, partition_count
+            ),
+        ));
+    }
+    match request.replication_factor {
+        REPLICATION_FACTOR => {}
+        factor if factor == DEFAULT_COUNT as i16 => {}
+        factor => {
+            return Err(BrokerError::with_message(
+                INVALID_REPLICATION_FACTOR,
+                format!(
+                    "Replication factor {} can't be satisfied: this is a single-broker \
+                     cluster, so every partition has exactly one replica. Use 1, or -1 \
+                     for the default",
+                    factor
+                ),
+            ))
+        }
+    }
+    Ok(partition_count)
+}
+
+/// Non-empty, at most 249 bytes of ASCII alphanumerics, '.', '_' and '-', and
+/// not "." or "..", which would clash with directory names.
+fn check_topic_name(name: &str) -> Result<(), BrokerError> {
+    let invalid = |message: String| BrokerError::with_message(INVALID_TOPIC_EXCEPTION, message);
+    if name.is_empty() || name == "." || name == ".." {
+        return Err(invalid("Topic name is illegal".to_string()));
+    }
+    if name.len() > MAX_TOPIC_NAME_LEN {
+        return Err(invalid(format!(
+            "Topic name is {} bytes, longer than the limit of {}",
+            name.len(),
+            MAX_TOPIC_NAME_LEN
+        )));
+    }
+    let legal = |c: char| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-');
+    if !name.chars().all(legal) {
+        return Err(invalid(
+            "Topic name contains characters other than ASCII alphanumerics, '.', '_' and '-'"
+                .to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Manual assignments must cover partitions 0..n exactly, each replicated only
+/// to this broker. Returns n.
+fn check_assignments(
+    broker: &Broker,
+    assignments: &[(PartitionId, Vec<NodeId>)],
+) -> Result<i32, BrokerError> {
+    let invalid = |message: String| BrokerError::with_message(INVALID_REPLICA_ASSIGNMENT, message);
+    if assignments.len() > MAX_PARTITIONS as usize {
+        return Err(invalid(format!(
+            "More than {} partitions assigned",
+            MAX_PARTITIONS
+        )));
+    }
+    let mut indexes: Vec<PartitionId> = assignments.iter().map(|(index, _)| *index).collect();
+    indexes.sort_unstable();
+    if indexes
+        .iter()
+        .copied()
+        .ne((0..assignments.len() as i32).map(PartitionId))
+    {
+        return Err(invalid(
+            "Partitions must be numbered consecutively from 0".to_string(),
+        ));
+    }
+    let node_id = broker.config.node_id;
+    if assignments
+        .iter()
+        .any(|(_, broker_ids)| broker_ids.as_slice() != [node_id])
+    {
+        return Err(invalid(format!(
+            "Every partition must be assigned to broker {} alone: this is a single-broker cluster",
+            node_id
+        )));
+    }
+    Ok(assignments.len() as i32)
+}
+
+/// CreateTopicsResponse: throttle_time_ms, topics [name, topic_id (v7+),
+/// error_code, error_message, num_partitions (v5+), replication_factor (v5+),
+/// configs (v5+) [name, value, read_only, config_source, is_sensitive]].
+///
+/// Names of rejected topics come from the request, so they and the number of
+/// rejected topics are capped by `limits` before being echoed.
+fn build_create_topics_response(
+    correlation_id: u32,
+    api_version: i16,
+    mut topics: Vec<TopicResponse>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let dropped = limits.cap_errors(&mut topics, |topic| topic.error_code != NONE);
+    if dropped > 0 {
+        println!("Omitted {} rejected topic(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+    let write_nullable = |response: &mut ResponseWriter, value: Option<&str>| {
+        if flexible {
+            response.put_compact_nullable_string(value)
+        } else {
+            response.put_nullable_string(value)
+        }
+    };
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    write_len(&mut response, topics.len());
+    for topic in &topics {
+        let name = limits.name(&topic.name);
+        if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        if api_version >= 7 {
+            response.put_uuid(topic.topic_id.0);
+        }
+        response.put_i16(topic.error_code);
+        write_nullable(&mut response, topic.error_message.as_deref());
+        if api_version >= 5 {
+            response.put_i32(topic.num_partitions);
+            response.put_i16(topic.replication_factor);
+            if topic.error_code != NONE {
+                response.put_u8(0); // configs: null
+            } else {
+                write_len(&mut response, topic.configs.len());
+                for (config_name, value) in &topic.configs {
+                    response.put_compact_string(config_name);
+                    response.put_compact_nullable_string(value.as_deref());
+                    response.put_u8(0); // read_only
+                    response.put_u8(DYNAMIC_TOPIC_CONFIG as u8);
+                    response.put_u8(0); // is_sensitive
+                    response.put_tag_buffer(); // config TAG_BUFFER
+                }
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,224 @@
+//! DeleteRecords (API key 21), versions 0-2.
+//!
+//! Deletes the records at the front of partitions, up to an offset given per
+//! partition, by advancing the partition's log start offset; -1 deletes up to
+//! the high watermark. Each partition is answered with its log start offset
+//! after (the low watermark), or with OFFSET_OUT_OF_RANGE for an offset past
+//! the high watermark, which leaves that partition alone. Fetches from before
+//! the new start are then out of range, and ListOffsets reports it as the
+//! earliest offset. A partition listed more than once is answered with
+//! INVALID_REQUEST for every occurrence, as in Produce and ListOffsets.
+//!
+//! v0-v1 use the classic encoding; v2 is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::header::RequestHeader;
+use crate::ids::{Offset, PartitionId};
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 2;
+
+/// Asks for every record below the high watermark to be deleted.
+const HIGH_WATERMARK: i64 = -1;
+
+const NONE: i16 = 0;
+const OFFSET_OUT_OF_RANGE: i16 = 1;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const INVALID_REQUEST: i16 = 42;
+
+struct PartitionResponse {
+    partition: PartitionId,
+    low_watermark: Offset,
+    error_code: i16,
+}
+
+impl PartitionResponse {
+    fn error(partition: PartitionId, error_code: i16) -> PartitionResponse {
+        PartitionResponse {
+            partition,
+            low_watermark: Offset::NONE,
+            error_code,
+        }
+    }
+}
+
+struct TopicResponse {
+    name: String,
+    partitions: Vec<PartitionResponse>,
+}
+
+/// Handles a DeleteRecords request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let name = decoder.read_string(flexible)?;
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            let partition = PartitionId(decoder.read_i32()?);
+            let offset = decoder.read_i64()?;
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            partitions.push((partition, offset));
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        requests.push((name, partitions));
+    }
+    let timeout_ms = decoder.read_i32()?;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let repeated = duplicates::repeated(requests.iter().flat_map(|(name, partitions)| {
+        partitions
+            .iter()
+            .map(move |&(partition, _)| (name.as_str(), partition))
+    }));
+    let topics: Vec<TopicResponse> = requests
+        .iter()
+        .map(|(name, partitions)| TopicResponse {
+            name: name.clone(),
+            partitions: partitions
+                .iter()
+                .map(|&(partition, offset)| {
+                    if repeated.contains(&(name.as_str(), partition)) {
+                        PartitionResponse::error(partition, INVALID_REQUEST)
+                    } else {
+                        delete_partition_records(broker, name, partition, offset)
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    println!(
+        "DeleteRecords: timeout_ms={} topics={}",
+        timeout_ms,
+        topics.len()
+    );
+    Ok(build_delete_records_response(
+        header.correlation_id,
+        api_version,
+        &topics,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn delete_partition_records(
+    broker: &Broker,
+    topic: &str,
+    partition: PartitionId,
+    offset: i64,
+) -> PartitionResponse {
+    let topic_id = broker
+        .metadata()
+        .topic(topic)
+        .filter(|metadata| metadata.partitions.contains(&partition))
+        .map(|metadata| metadata.topic_id);
+    let Some(topic_id) = topic_id else {
+        return PartitionResponse::error(partition, UNKNOWN_TOPIC_OR_PARTITION);
+    };
+    if offset < HIGH_WATERMARK {
+        return PartitionResponse::error(partition, OFFSET_OUT_OF_RANGE);
+    }
+
+    let logs = broker.logs();
+    let deleted = match offset {
+        HIGH_WATERMARK => logs
+            .offsets(topic_id, partition)
+            .and_then(|offsets| logs.delete_records(topic_id, partition, offsets.log_end_offset)),
+        offset => logs.delete_records(topic_id, partition, Offset(offset)),
+    };
+    match deleted {
+        Ok(low_watermark) => {
+            println!(
+                "Deleted the records of {}-{} before offset {}",
+                topic, partition, low_watermark
+            );
+            PartitionResponse {
+                partition,
+                low_watermark,
+                error_code: NONE,
+            }
+        }
+        Err(e) => {
+            println!("DeleteRecords on {}-{} failed: {}", topic, partition, e);
+            PartitionResponse::error(partition, e.to_broker_error().code)
+        }
+    }
+}
+
+/// DeleteRecordsResponse: throttle_time_ms, topics [name, partitions
+/// [partition_index, low_watermark, error_code]].
+///
+/// Topic names and error partitions come from the request, so both are capped
+/// by `limits` before being echoed.
+fn build_delete_records_response(
+    correlation_id: u32,
+    api_version: i16,
+    topics: &[TopicResponse],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    write_len(&mut response, topics.len());
+    for topic in topics {
+        let name = limits.name(&topic.name);
+        if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        let mut partitions: Vec<&PartitionResponse> = topic.partitions.iter().collect();
+        let dropped = limits.cap_errors(&mut partitions, |partition| partition.error_code != NONE);
+        if dropped > 0 {
+            println!(
+                "Omitted {} error partition(s) of topic {} from the response",
+                dropped, name
+            );
+        }
+        write_len(&mut response, partitions.len());
+        for partition in partitions {
+            response.put_i32(partition.partition.0);
+            response.put_i64(partition.low_watermark.0);
+            response.put_i16(partition.error_code);
+            if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
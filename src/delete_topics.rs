@@ -0,0 +1,199 @@
+//! DeleteTopics (API key 20), versions 1-6.
+//!
+//! Removes topics from the registry together with their partition logs, so a
+//! topic created again under the same name starts out empty. Up to v5 topics are
+//! named; v6 names each by name or, with a null name, by topic id.
+//!
+//! v1-v3 use the classic encoding; v4+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::ids::TopicId;
+use crate::log_store;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const UNKNOWN_TOPIC_ID: i16 = 100;
+
+/// A topic the request names, by name or (v6+) by id.
+struct RequestedTopic {
+    name: Option<String>,
+    topic_id: TopicId,
+}
+
+struct TopicResponse {
+    /// `None` for an unknown topic id, which has no name to report.
+    name: Option<String>,
+    topic_id: TopicId,
+    error: Option<BrokerError>,
+}
+
+/// Handles a DeleteTopics request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requested = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        requested.push(read_requested_topic(&mut decoder, api_version)?);
+    }
+    let timeout_ms = decoder.read_i32()?;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let mut metadata = broker.metadata_mut();
+    let mut topics = Vec::with_capacity(requested.len());
+    for topic in requested {
+        let topic_id = match &topic.name {
+            Some(name) => metadata.topic_id(name),
+            None => Some(topic.topic_id),
+        };
+        // Saved first, so a deletion that's answered is never undone
+        let deleted = match topic_id.filter(|topic_id| metadata.topic_by_id(*topic_id).is_some()) {
+            Some(topic_id) => match broker.topic_changes().save_deleted(topic_id) {
+                Ok(()) => metadata.delete_topic(topic_id).map(Ok),
+                Err(e) => {
+                    println!("Error saving the deletion of topic {}: {}", topic_id, e);
+                    Some(Err(topic_id))
+                }
+            },
+            None => None,
+        };
+        let response = match deleted {
+            Some(Err(topic_id)) => TopicResponse {
+                name: topic.name,
+                topic_id,
+                error: Some(log_store::storage_error()),
+            },
+            Some(Ok(deleted)) => {
+                broker.delete_logs(&deleted);
+                println!(
+                    "Deleted topic '{}' with {} partition(s)",
+                    deleted.name,
+                    deleted.partitions.len()
+                );
+                TopicResponse {
+                    name: Some(deleted.name),
+                    topic_id: deleted.topic_id,
+                    error: None,
+                }
+            }
+            None if topic.name.is_some() => TopicResponse {
+                name: topic.name,
+                topic_id: TopicId::ZERO,
+                error: Some(BrokerError::with_message(
+                    UNKNOWN_TOPIC_OR_PARTITION,
+                    "This server does not host this topic",
+                )),
+            },
+            None => TopicResponse {
+                name: None,
+                topic_id: topic.topic_id,
+                error: Some(BrokerError::with_message(
+                    UNKNOWN_TOPIC_ID,
+                    "This server does not host a topic with this topic id",
+                )),
+            },
+        };
+        topics.push(response);
+    }
+    drop(metadata);
+
+    println!(
+        "DeleteTopics: timeout_ms={} topics={} deleted={}",
+        timeout_ms,
+        topics.len(),
+        topics.iter().filter(|topic| topic.error.is_none()).count()
+    );
+    Ok(build_delete_topics_response(
+        header.correlation_id,
+        api_version,
+        topics,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn read_requested_topic(
+    decoder: &mut Decoder,
+    api_version: i16,
+) -> Result<RequestedTopic, DecodeError> {
+    if api_version >= 6 {
+        let name = decoder.read_compact_nullable_string()?;
+        let topic_id = TopicId(decoder.read_uuid()?);
+        decoder.skip_tagged_fields()?;
+        return Ok(RequestedTopic { name, topic_id });
+    }
+    let name = decoder.read_string(api_version >= FIRST_FLEXIBLE_VERSION)?;
+    Ok(RequestedTopic {
+        name: Some(name),
+        topic_id: TopicId::ZERO,
+    })
+}
+
+/// DeleteTopicsResponse: throttle_time_ms, responses [name, topic_id (v6+),
+/// error_code, error_message (v5+)].
+///
+/// Names of unknown topics come from the request, so they and the number of
+/// unknown topics are capped by `limits` before being echoed.
+fn build_delete_topics_response(
+    correlation_id: u32,
+    api_version: i16,
+    mut topics: Vec<TopicResponse>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let dropped = limits.cap_errors(&mut topics, |topic| topic.error.is_some());
+    if dropped > 0 {
+        println!("Omitted {} unknown topic(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    if flexible {
+        response.put_compact_array_len(topics.len());
+    } else {
+        response.put_array_len(topics.len());
+    }
+    for topic in &topics {
+        let name = topic.name.as_deref().map(|name| limits.name(name));
+        let name = name.as_ref().map(|name| name.as_str());
+        if flexible {
+            // Only v6 can answer without a name, for an unknown topic id
+            response.put_compact_nullable_string(name);
+        } else {
+            response.put_string(name.unwrap_or_default());
+        }
+        if api_version >= 6 {
+            response.put_uuid(topic.topic_id.0);
+        }
+        let error_code = topic.error.as_ref().map_or(NONE, |error| error.code);
+        response.put_i16(error_code);
+        if api_version >= 5 {
+            let error_message = topic.error.as_ref().and_then(BrokerError::message);
+            response.put_compact_nullable_string(error_message);
+        }
+        if flexible {
+            response.put_tag_buffer(); // response TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // TAG_BUFFER
+    }
+
+    response.finish()
+}
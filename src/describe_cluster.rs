@@ -0,0 +1,139 @@
+//! DescribeCluster (API key 60), versions 0-1.
+//!
+//! What AdminClient.describeCluster() asks first: the cluster id, the
+//! controller and the brokers, here always this one node. Every value comes
+//! from the same place Metadata takes it from (`node.id`,
+//! `advertised.listeners`, `broker.rack` and the cluster id in
+//! `meta.properties`), so the two never disagree. This node is the controller
+//! too, but it only has a broker listener, so v1 requests for the controller
+//! endpoints are refused with UNSUPPORTED_ENDPOINT_TYPE, as a Kafka broker does.
+//!
+//! Every version is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const NONE: i16 = 0;
+const UNSUPPORTED_ENDPOINT_TYPE: i16 = 119;
+
+const BROKER_ENDPOINT_TYPE: i8 = 1;
+
+/// Sent when the client didn't ask for authorized operations.
+pub const AUTHORIZED_OPERATIONS_OMITTED: i32 = i32::MIN;
+/// CREATE, ALTER, DESCRIBE, CLUSTER_ACTION, DESCRIBE_CONFIGS, ALTER_CONFIGS and
+/// IDEMPOTENT_WRITE, as bits numbered by Kafka's ACL operation codes: every
+/// operation on the cluster resource, since there are no ACLs to deny any.
+pub const CLUSTER_AUTHORIZED_OPERATIONS: i32 =
+    1 << 5 | 1 << 7 | 1 << 8 | 1 << 9 | 1 << 10 | 1 << 11 | 1 << 12;
+
+/// Handles a DescribeCluster request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, true)?;
+
+    let include_authorized_operations = decoder.read_i8()? != 0;
+    let endpoint_type = if api_version >= 1 {
+        decoder.read_i8()?
+    } else {
+        BROKER_ENDPOINT_TYPE
+    };
+    decoder.skip_tagged_fields()?;
+
+    let cluster_id = broker.metadata().cluster_id.clone();
+    println!(
+        "DescribeCluster: endpoint_type={} include_cluster_authorized_operations={}",
+        endpoint_type, include_authorized_operations
+    );
+    let description = if endpoint_type == BROKER_ENDPOINT_TYPE {
+        Ok(Description {
+            cluster_id: cluster_id.as_deref(),
+            authorized_operations: if include_authorized_operations {
+                CLUSTER_AUTHORIZED_OPERATIONS
+            } else {
+                AUTHORIZED_OPERATIONS_OMITTED
+            },
+        })
+    } else {
+        Err(format!(
+            "The broker only handles the BROKER endpoint type, not {}",
+            endpoint_type
+        ))
+    };
+    Ok(build_describe_cluster_response(
+        header.correlation_id,
+        api_version,
+        endpoint_type,
+        broker,
+        description,
+    ))
+}
+
+/// What a successful response describes.
+struct Description<'a> {
+    /// `None` without a `meta.properties`, sent as an empty string: the field
+    /// isn't nullable.
+    cluster_id: Option<&'a str>,
+    authorized_operations: i32,
+}
+
+/// DescribeClusterResponse: throttle_time_ms, error_code, error_message,
+/// endpoint_type (v1+), cluster_id, controller_id, brokers [broker_id, host,
+/// port, rack], cluster_authorized_operations.
+///
+/// A refused request has no brokers and controller -1.
+fn build_describe_cluster_response(
+    correlation_id: u32,
+    api_version: i16,
+    endpoint_type: i8,
+    broker: &Broker,
+    description: Result<Description, String>,
+) -> Vec<u8> {
+    let config = &broker.config;
+
+    let mut response = ResponseWriter::new(correlation_id, false);
+    response.put_tag_buffer(); // Response Header v1 TAG_BUFFER
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    match &description {
+        Ok(_) => {
+            response.put_i16(NONE);
+            response.put_compact_nullable_string(None);
+        }
+        Err(message) => {
+            response.put_i16(UNSUPPORTED_ENDPOINT_TYPE);
+            response.put_compact_nullable_string(Some(message));
+        }
+    }
+    if api_version >= 1 {
+        response.put_i8(endpoint_type);
+    }
+    match &description {
+        Ok(description) => {
+            response.put_compact_string(description.cluster_id.unwrap_or_default());
+            // Combined mode: this node is also the controller
+            response.put_i32(config.node_id.0);
+            response.put_compact_array_len(1); // brokers: just us
+            response.put_i32(config.node_id.0);
+            response.put_compact_string(&config.advertised_host);
+            response.put_i32(config.advertised_port);
+            response.put_compact_nullable_string(config.rack.as_deref());
+            response.put_tag_buffer(); // broker TAG_BUFFER
+            response.put_i32(description.authorized_operations);
+        }
+        Err(_) => {
+            response.put_compact_string("");
+            response.put_i32(-1i32); // controller_id
+            response.put_compact_array_len(0);
+            response.put_i32(AUTHORIZED_OPERATIONS_OMITTED);
+        }
+    }
+    response.put_tag_buffer(); // response TAG_BUFFER
+
+    response.finish()
+}
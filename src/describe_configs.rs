@@ -0,0 +1,589 @@
+//! DescribeConfigs (API key 32), versions 0-4.
+//!
+//! Describes topics and this broker. A topic's configs are its overrides, from
+//! creation or IncrementalAlterConfigs (DYNAMIC_TOPIC_CONFIG), over the
+//! defaults in `topic_config` (DEFAULT_CONFIG); the broker's come from its
+//! `BrokerConfig`, reported as STATIC_BROKER_CONFIG unless they are at their
+//! default, and are all read-only.
+//! Paths on the broker aren't reported, and the SASL users only as sensitive,
+//! with a null value. A configuration_keys list restricts the entries to the
+//! names it has. Unknown topics, other brokers and other resource types get an
+//! error of their own while the rest of the request is answered.
+//!
+//! The broker resource with an empty name stands for the cluster-wide dynamic
+//! defaults, of which there are none.
+//!
+//! v0-v3 use the classic encoding; v4 is flexible. v1 adds config_source and
+//! synonyms (in place of is_default), v3 config_type and documentation.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::config::{BrokerConfig, LogStoreKind};
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::metadata::TopicMetadata;
+use crate::sasl;
+use crate::throttle::THROTTLE_TIME_LEN;
+use crate::topic_config::{self, ConfigType, TOPIC_CONFIGS};
+
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+const TOPIC_RESOURCE: i8 = 2;
+const BROKER_RESOURCE: i8 = 4;
+
+const DYNAMIC_TOPIC_CONFIG: i8 = 1;
+const STATIC_BROKER_CONFIG: i8 = 4;
+const DEFAULT_CONFIG: i8 = 5;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const INVALID_REQUEST: i16 = 42;
+
+/// A broker config: its name in `server.properties` and how to read its value
+/// from a `BrokerConfig`.
+struct BrokerConfigDef {
+    name: &'static str,
+    config_type: ConfigType,
+    documentation: &'static str,
+    value: fn(&BrokerConfig) -> Option<String>,
+}
+
+/// Every broker config reported, by name.
+const BROKER_CONFIGS: &[BrokerConfigDef] = &[
+    BrokerConfigDef {
+        name: "broker.rack",
+        config_type: ConfigType::String,
+        documentation: "Rack of the broker, reported to clients",
+        value: |config| config.rack.clone(),
+    },
+    BrokerConfigDef {
+        name: "client.inventory.max.entries",
+        config_type: ConfigType::Int,
+        documentation: "Most clients the client inventory keeps",
+        value: |config| Some(config.client_inventory_max_entries.to_string()),
+    },
+    BrokerConfigDef {
+        name: "client.inventory.retention.ms",
+        config_type: ConfigType::Long,
+        documentation: "How long a client with no open connection stays in the client inventory",
+        value: |config| Some(config.client_inventory_retention.as_millis().to_string()),
+    },
+    BrokerConfigDef {
+        name: "error.echo.max.name.bytes",
+        config_type: ConfigType::Int,
+        documentation: "Longest client-supplied name written back in errors",
+        value: |config| Some(config.echo_limits.max_name_bytes.to_string()),
+    },
+    BrokerConfigDef {
+        name: "error.echo.max.partitions",
+        config_type: ConfigType::Int,
+        documentation: "Most error entries answered per topic",
+        value: |config| Some(config.echo_limits.max_partitions.to_string()),
+    },
+    BrokerConfigDef {
+        name: "group.max.member.metadata.bytes",
+        config_type: ConfigType::Long,
+        documentation: "Most protocol metadata or assignment one group member may have",
+        value: |config| Some(config.group_limits.max_member_metadata_bytes.to_string()),
+    },
+    BrokerConfigDef {
+        name: "group.max.metadata.bytes",
+        config_type: ConfigType::Long,
+        documentation: "Most metadata and assignments one group's members may have between them",
+        value: |config| Some(config.group_limits.max_group_metadata_bytes.to_string()),
+    },
+    BrokerConfigDef {
+        name: "group.max.session.timeout.ms",
+        config_type: ConfigType::Int,
+        documentation: "Longest session timeout a group member may join with",
+        value: |config| Some(config.group_limits.max_session_timeout_ms.to_string()),
+    },
+    BrokerConfigDef {
+        name: "group.min.session.timeout.ms",
+        config_type: ConfigType::Int,
+        documentation: "Shortest session timeout a group member may join with",
+        value: |config| Some(config.group_limits.min_session_timeout_ms.to_string()),
+    },
+    BrokerConfigDef {
+        name: "listeners",
+        config_type: ConfigType::List,
+        documentation: "Listeners the broker accepts connections on, as NAME://host:port",
+        value: |config| {
+            let listeners: Vec<String> = config
+                .listeners
+                .iter()
+                .map(|listener| format!("{}://{}", listener.name, listener.address))
+                .collect();
+            Some(listeners.join(","))
+        },
+    },
+    BrokerConfigDef {
+        name: "liveness.interval.ms",
+        config_type: ConfigType::Long,
+        documentation: "How often the liveness file is refreshed",
+        value: |config| Some(config.liveness_interval.as_millis().to_string()),
+    },
+    BrokerConfigDef {
+        name: "log.flush.interval.ms",
+        config_type: ConfigType::Long,
+        documentation: "How often the partition logs are flushed when nothing waits on it",
+        value: |config| {
+            config
+                .flush_interval
+                .map(|interval| interval.as_millis().to_string())
+        },
+    },
+    BrokerConfigDef {
+        name: "log.flush.on.acks.all",
+        config_type: ConfigType::String,
+        documentation: "Whether acks=-1 produces are answered only once flushed",
+        value: |config| Some(config.flush_on_acks_all.to_string()),
+    },
+    BrokerConfigDef {
+        name: "log.retention.check.interval.ms",
+        config_type: ConfigType::Long,
+        documentation: "How often records past their topic's retention.ms are deleted",
+        value: |config| Some(config.retention_check_interval.as_millis().to_string()),
+    },
+    BrokerConfigDef {
+        name: "log.store",
+        config_type: ConfigType::String,
+        documentation: "Where partition logs are kept: file or memory",
+        value: |config| {
+            let store = match config.log_store {
+                LogStoreKind::File => "file",
+                LogStoreKind::Memory => "memory",
+            };
+            Some(store.to_string())
+        },
+    },
+    BrokerConfigDef {
+        name: "node.id",
+        config_type: ConfigType::Int,
+        documentation: "Id of this broker, the cluster's only node",
+        value: |config| Some(config.node_id.to_string()),
+    },
+    BrokerConfigDef {
+        name: "num.partitions",
+        config_type: ConfigType::Int,
+        documentation: "Partitions given to a topic created without a count",
+        value: |config| Some(config.default_partitions.to_string()),
+    },
+    BrokerConfigDef {
+        name: "num.recovery.threads.per.data.dir",
+        config_type: ConfigType::Int,
+        documentation: "Partitions recovered at once at startup",
+        value: |config| Some(config.recovery_threads.to_string()),
+    },
+    BrokerConfigDef {
+        name: "request.read.timeout.ms",
+        config_type: ConfigType::Long,
+        documentation: "How long the rest of a request may take to arrive",
+        value: |config| Some(config.request_read_timeout.as_millis().to_string()),
+    },
+    BrokerConfigDef {
+        name: "response.coalesce.max.bytes",
+        config_type: ConfigType::Int,
+        documentation: "Most bytes of pipelined responses sent in one write",
+        value: |config| Some(config.response_coalesce_max_bytes.to_string()),
+    },
+    BrokerConfigDef {
+        name: "response.write.timeout.ms",
+        config_type: ConfigType::Long,
+        documentation: "How long a response write may make no progress",
+        value: |config| Some(config.response_write_timeout.as_millis().to_string()),
+    },
+    BrokerConfigDef {
+        name: "sasl.enabled.mechanisms",
+        config_type: ConfigType::List,
+        documentation: "SASL mechanisms clients must authenticate with",
+        value: |config| {
+            config
+                .sasl_plain_users
+                .as_ref()
+                .map(|_| sasl::PLAIN.to_string())
+        },
+    },
+    BrokerConfigDef {
+        name: "sasl.plain.users",
+        config_type: ConfigType::Password,
+        documentation: "Usernames and passwords accepted by SASL/PLAIN",
+        // Only compared with the default: a password value is never sent
+        value: |config| config.sasl_plain_users.as_ref().map(|_| String::new()),
+    },
+    BrokerConfigDef {
+        name: "socket.request.max.bytes",
+        config_type: ConfigType::Int,
+        documentation: "Largest request frame a client may send",
+        value: |config| Some(config.max_request_bytes.to_string()),
+    },
+];
+
+struct ResourceRequest {
+    resource_type: i8,
+    resource_name: String,
+    /// `None` asks for every config.
+    configuration_keys: Option<Vec<String>>,
+}
+
+struct ConfigEntry {
+    name: String,
+    value: Option<String>,
+    read_only: bool,
+    config_source: i8,
+    is_sensitive: bool,
+    /// (value, config_source) of every source the value could come from,
+    /// the one in effect first; filled in only when synonyms are asked for.
+    synonyms: Vec<(Option<String>, i8)>,
+    config_type: ConfigType,
+    documentation: Option<&'static str>,
+}
+
+struct ResourceResponse {
+    error_code: i16,
+    error_message: Option<String>,
+    resource_type: i8,
+    resource_name: String,
+    configs: Vec<ConfigEntry>,
+}
+
+/// What each config entry is described with, beyond its value.
+#[derive(Clone, Copy)]
+struct Detail {
+    include_synonyms: bool,
+    include_documentation: bool,
+}
+
+/// Handles a DescribeConfigs request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let resource_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(resource_count);
+    for _ in 0..resource_count {
+        let resource_type = decoder.read_i8()?;
+        let resource_name = decoder.read_string(flexible)?;
+        let configuration_keys = match decoder.read_array_len(flexible)? {
+            Some(key_count) => {
+                let mut keys = Vec::with_capacity(key_count);
+                for _ in 0..key_count {
+                    keys.push(decoder.read_string(flexible)?);
+                }
+                Some(keys)
+            }
+            None => None,
+        };
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        requests.push(ResourceRequest {
+            resource_type,
+            resource_name,
+            configuration_keys,
+        });
+    }
+    let include_synonyms = api_version >= 1 && decoder.read_i8()? != 0;
+    let include_documentation = api_version >= 3 && decoder.read_i8()? != 0;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let detail = Detail {
+        include_synonyms,
+        include_documentation,
+    };
+    let resources: Vec<ResourceResponse> = requests
+        .iter()
+        .map(|request| describe_resource(broker, request, detail))
+        .collect();
+
+    println!("DescribeConfigs: resources={}", resources.len());
+    Ok(build_describe_configs_response(
+        header.correlation_id,
+        api_version,
+        resources,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn describe_resource(
+    broker: &Broker,
+    request: &ResourceRequest,
+    detail: Detail,
+) -> ResourceResponse {
+    let described = match request.resource_type {
+        TOPIC_RESOURCE => match broker.metadata().topic(&request.resource_name) {
+            Some(topic) => Ok(topic_configs(topic, detail)),
+            None => Err(BrokerError::new(UNKNOWN_TOPIC_OR_PARTITION)),
+        },
+        BROKER_RESOURCE => broker_configs(&broker.config, &request.resource_name, detail),
+        resource_type => Err(BrokerError::with_message(
+            INVALID_REQUEST,
+            format!(
+                "Resource type {} can't be described here: only topics ({}) and brokers ({})",
+                resource_type, TOPIC_RESOURCE, BROKER_RESOURCE
+            ),
+        )),
+    };
+    match described {
+        Ok(mut configs) => {
+            if let Some(keys) = &request.configuration_keys {
+                configs.retain(|entry| keys.contains(&entry.name));
+            }
+            ResourceResponse {
+                error_code: NONE,
+                error_message: None,
+                resource_type: request.resource_type,
+                resource_name: request.resource_name.clone(),
+                configs,
+            }
+        }
+        Err(e) => ResourceResponse {
+            error_code: e.code,
+            error_message: e.message().map(str::to_string),
+            resource_type: request.resource_type,
+            resource_name: request.resource_name.clone(),
+            configs: Vec::new(),
+        },
+    }
+}
+
+/// Every known topic config, overridden or not, then any other override the
+/// topic has, by name.
+fn topic_configs(topic: &TopicMetadata, detail: Detail) -> Vec<ConfigEntry> {
+    let override_of = |name: &str| {
+        topic_config::override_of(&topic.configs, name).map(|value| value.map(str::to_string))
+    };
+    let mut entries: Vec<ConfigEntry> = TOPIC_CONFIGS
+        .iter()
+        .map(|config| {
+            let default = Some(config.default.to_string());
+            let (value, config_source) = match override_of(config.name) {
+                Some(value) => (value, DYNAMIC_TOPIC_CONFIG),
+                None => (default.clone(), DEFAULT_CONFIG),
+            };
+            ConfigEntry::new(config.name, value, config_source, default, detail).typed(
+                config.config_type,
+                config.documentation,
+                detail,
+            )
+        })
+        .collect();
+    for (name, _) in &topic.configs {
+        let known = topic_config::lookup(name).is_some();
+        if known || entries.iter().any(|entry| &entry.name == name) {
+            continue;
+        }
+        // Overrides of configs we don't know have no default underneath
+        let value = override_of(name).flatten();
+        entries.push(ConfigEntry::new(
+            name,
+            value,
+            DYNAMIC_TOPIC_CONFIG,
+            None,
+            detail,
+        ));
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    entries
+}
+
+/// This broker's configs, or none for the cluster-wide defaults. Any other
+/// broker id is refused: there is no other broker to ask.
+fn broker_configs(
+    config: &BrokerConfig,
+    resource_name: &str,
+    detail: Detail,
+) -> Result<Vec<ConfigEntry>, BrokerError> {
+    if resource_name.is_empty() {
+        return Ok(Vec::new());
+    }
+    if resource_name.parse().ok() != Some(config.node_id.0) {
+        return Err(BrokerError::with_message(
+            INVALID_REQUEST,
+            format!(
+                "This is broker {}, the only one in the cluster; describe it by that id",
+                config.node_id
+            ),
+        ));
+    }
+    let defaults = BrokerConfig::default();
+    Ok(BROKER_CONFIGS
+        .iter()
+        .map(|def| {
+            let value = (def.value)(config);
+            let default = (def.value)(&defaults);
+            let config_source = if value == default {
+                DEFAULT_CONFIG
+            } else {
+                STATIC_BROKER_CONFIG
+            };
+            let mut entry = ConfigEntry::new(def.name, value, config_source, default, detail)
+                .typed(def.config_type, def.documentation, detail);
+            entry.read_only = true;
+            if def.config_type == ConfigType::Password {
+                entry.hide_value();
+            }
+            entry
+        })
+        .collect())
+}
+
+impl ConfigEntry {
+    /// A writable, non-sensitive entry of unknown type. `default` is the
+    /// value underneath one that isn't the default itself, if there is one.
+    fn new(
+        name: &str,
+        value: Option<String>,
+        config_source: i8,
+        default: Option<String>,
+        detail: Detail,
+    ) -> ConfigEntry {
+        let mut synonyms = Vec::new();
+        if detail.include_synonyms {
+            synonyms.push((value.clone(), config_source));
+            if config_source != DEFAULT_CONFIG && default.is_some() {
+                synonyms.push((default, DEFAULT_CONFIG));
+            }
+        }
+        ConfigEntry {
+            name: name.to_string(),
+            value,
+            read_only: false,
+            config_source,
+            is_sensitive: false,
+            synonyms,
+            config_type: ConfigType::Unknown,
+            documentation: None,
+        }
+    }
+
+    fn typed(
+        mut self,
+        config_type: ConfigType,
+        documentation: &'static str,
+        detail: Detail,
+    ) -> ConfigEntry {
+        self.config_type = config_type;
+        if detail.include_documentation {
+            self.documentation = Some(documentation);
+        }
+        self
+    }
+
+    /// Marks the entry sensitive, leaving it and its synonyms without values.
+    fn hide_value(&mut self) {
+        self.is_sensitive = true;
+        self.value = None;
+        for (value, _) in &mut self.synonyms {
+            *value = None;
+        }
+    }
+}
+
+/// DescribeConfigsResponse: throttle_time_ms, results [error_code,
+/// error_message, resource_type, resource_name, configs [name, value,
+/// read_only, is_default (v0), config_source (v1+), is_sensitive, synonyms
+/// (v1+) [name, value, source], config_type (v3+), documentation (v3+)]].
+///
+/// Resource names come from the request, so they and the number of failed
+/// resources are capped by `limits` before being echoed.
+fn build_describe_configs_response(
+    correlation_id: u32,
+    api_version: i16,
+    mut resources: Vec<ResourceResponse>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let dropped = limits.cap_errors(&mut resources, |resource| resource.error_code != NONE);
+    if dropped > 0 {
+        println!("Omitted {} failed resource(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+    let write_str = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value)
+        } else {
+            response.put_string(value)
+        }
+    };
+    let write_nullable = |response: &mut ResponseWriter, value: Option<&str>| {
+        if flexible {
+            response.put_compact_nullable_string(value)
+        } else {
+            response.put_nullable_string(value)
+        }
+    };
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    write_len(&mut response, resources.len());
+    for resource in &resources {
+        let name = limits.name(&resource.resource_name);
+        let error_message = match (&resource.error_message, name.truncation_note()) {
+            (Some(message), Some(note)) => Some(format!("{}; {}", message, note)),
+            (Some(message), None) => Some(message.clone()),
+            (None, note) if resource.error_code != NONE => note,
+            (None, _) => None,
+        };
+        response.put_i16(resource.error_code);
+        write_nullable(&mut response, error_message.as_deref());
+        response.put_u8(resource.resource_type as u8);
+        write_str(&mut response, name.as_str());
+        write_len(&mut response, resource.configs.len());
+        for entry in &resource.configs {
+            write_str(&mut response, &entry.name);
+            write_nullable(&mut response, entry.value.as_deref());
+            response.put_u8(u8::from(entry.read_only));
+            if api_version == 0 {
+                response.put_u8(u8::from(entry.config_source == DEFAULT_CONFIG));
+            // is_default
+            } else {
+                response.put_u8(entry.config_source as u8);
+            }
+            response.put_u8(u8::from(entry.is_sensitive));
+            if api_version >= 1 {
+                write_len(&mut response, entry.synonyms.len());
+                for (value, source) in &entry.synonyms {
+                    write_str(&mut response, &entry.name);
+                    write_nullable(&mut response, value.as_deref());
+                    response.put_u8(*source as u8);
+                    if flexible {
+                        response.put_tag_buffer(); // synonym TAG_BUFFER
+                    }
+                }
+            }
+            if api_version >= 3 {
+                response.put_u8(entry.config_type.code() as u8);
+                write_nullable(&mut response, entry.documentation);
+            }
+            if flexible {
+                response.put_tag_buffer(); // config TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // result TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,194 @@
+//! DescribeGroups (API key 15), versions 0-5.
+//!
+//! Describes each group asked for as `kafka-consumer-groups.sh --describe`
+//! shows it: its state, protocol, and every member with its client id and
+//! host, and while the group is Stable its metadata for the chosen protocol and
+//! its assignment. A group with only committed offsets is Empty; one the broker
+//! knows nothing of is Dead with no members, not an error, as in Kafka. Only an
+//! empty group id is refused, with INVALID_GROUP_ID.
+//!
+//! There are no ACLs, so v3+ clients asking for the authorized operations are
+//! told every group operation is allowed.
+//!
+//! v0-v4 use the classic encoding; v5 is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::describe_cluster::AUTHORIZED_OPERATIONS_OMITTED;
+use crate::echo::EchoLimits;
+use crate::group_coordinator::{GroupDescription, GroupState};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 5;
+
+const NONE: i16 = 0;
+const INVALID_GROUP_ID: i16 = 24;
+
+/// READ, DELETE and DESCRIBE, as bits numbered by Kafka's ACL operation codes:
+/// every operation on a group resource.
+const GROUP_AUTHORIZED_OPERATIONS: i32 = 1 << 3 | 1 << 6 | 1 << 8;
+
+struct DescribedGroup {
+    error_code: i16,
+    group_id: String,
+    description: GroupDescription,
+}
+
+/// Handles a DescribeGroups request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut group_ids = Vec::with_capacity(group_count);
+    for _ in 0..group_count {
+        group_ids.push(decoder.read_string(flexible)?);
+    }
+    let include_authorized_operations = api_version >= 3 && decoder.read_i8()? != 0;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let groups: Vec<DescribedGroup> = group_ids
+        .into_iter()
+        .map(|group_id| {
+            let (error_code, description) = if group_id.is_empty() {
+                (INVALID_GROUP_ID, without_members(GroupState::Dead))
+            } else if let Some(description) = broker.groups.describe(&group_id) {
+                (NONE, description)
+            } else if broker.group_offsets().has_group(&group_id) {
+                (NONE, without_members(GroupState::Empty))
+            } else {
+                (NONE, without_members(GroupState::Dead))
+            };
+            DescribedGroup {
+                error_code,
+                group_id,
+                description,
+            }
+        })
+        .collect();
+
+    let limits = &broker.config.echo_limits;
+    for group in &groups {
+        println!(
+            "DescribeGroups: group={} state={} members={}",
+            limits.name(&group.group_id),
+            group.description.state.name(),
+            group.description.members.len()
+        );
+    }
+    let authorized_operations = if include_authorized_operations {
+        GROUP_AUTHORIZED_OPERATIONS
+    } else {
+        AUTHORIZED_OPERATIONS_OMITTED
+    };
+    Ok(build_describe_groups_response(
+        header.correlation_id,
+        api_version,
+        &groups,
+        authorized_operations,
+        limits,
+    ))
+}
+
+fn without_members(state: GroupState) -> GroupDescription {
+    GroupDescription {
+        state,
+        protocol_type: String::new(),
+        protocol_name: String::new(),
+        members: Vec::new(),
+    }
+}
+
+/// DescribeGroupsResponse: throttle_time_ms (v1+), groups [error_code,
+/// group_id, group_state, protocol_type, protocol_data, members [member_id,
+/// group_instance_id (v4+), client_id, client_host, member_metadata,
+/// member_assignment], authorized_operations (v3+)].
+///
+/// Group ids the broker doesn't know come from the request, so they are capped
+/// by `limits` before being echoed.
+fn build_describe_groups_response(
+    correlation_id: u32,
+    api_version: i16,
+    groups: &[DescribedGroup],
+    authorized_operations: i32,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let write = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value);
+        } else {
+            response.put_string(value);
+        }
+    };
+    let write_blob = |response: &mut ResponseWriter, value: &[u8]| {
+        if flexible {
+            response.put_compact_bytes(value);
+        } else {
+            response.put_bytes(value);
+        }
+    };
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    if flexible {
+        response.put_compact_array_len(groups.len());
+    } else {
+        response.put_array_len(groups.len());
+    }
+    for group in groups {
+        let description = &group.description;
+        response.put_i16(group.error_code);
+        match description.state {
+            GroupState::Dead => write(&mut response, limits.name(&group.group_id).as_str()),
+            _ => write(&mut response, &group.group_id),
+        }
+        write(&mut response, description.state.name());
+        write(&mut response, &description.protocol_type);
+        write(&mut response, &description.protocol_name);
+        if flexible {
+            response.put_compact_array_len(description.members.len());
+        } else {
+            response.put_array_len(description.members.len());
+        }
+        for member in &description.members {
+            write(&mut response, &member.member_id);
+            if api_version >= 4 {
+                if flexible {
+                    response.put_compact_nullable_string(member.group_instance_id.as_deref());
+                } else {
+                    response.put_nullable_string(member.group_instance_id.as_deref());
+                }
+            }
+            write(&mut response, &member.client_id);
+            write(&mut response, &member.client_host);
+            write_blob(&mut response, &member.metadata);
+            write_blob(&mut response, &member.assignment);
+            if flexible {
+                response.put_tag_buffer(); // member TAG_BUFFER
+            }
+        }
+        if api_version >= 3 {
+            response.put_i32(authorized_operations);
+        }
+        if flexible {
+            response.put_tag_buffer(); // group TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
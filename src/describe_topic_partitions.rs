@@ -0,0 +1,212 @@
+//! DescribeTopicPartitions (API key 75), version 0.
+//!
+//! Metadata's successor for describing topics with many partitions: topics are
+//! answered in name order, at most response_partition_limit partitions per
+//! response, and the response carries a cursor saying where the next request
+//! should pick up. An empty topic list describes every topic. The version is
+//! flexible throughout.
+
+use crate::broker::{Broker, LEADER_EPOCH};
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::header::RequestHeader;
+use crate::ids::{NodeId, PartitionId, TopicId};
+use crate::metadata::TopicMetadata;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+/// Kafka's `max.request.partition.size.limit` default; larger limits are capped.
+const MAX_PARTITION_LIMIT: i32 = 2000;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+
+/// Everything a topic's describer may do (READ through DESCRIBE_CONFIGS), the
+/// bitfield Kafka reports when authorization is disabled.
+const TOPIC_AUTHORIZED_OPERATIONS: i32 = 0x0df8;
+
+/// Where a paged describe picks up: the first partition not yet described.
+struct Cursor {
+    topic_name: String,
+    partition_index: PartitionId,
+}
+
+struct TopicResponse<'a> {
+    name: &'a str,
+    /// `None` for a topic this broker doesn't know.
+    topic: Option<&'a TopicMetadata>,
+    partitions: Vec<PartitionId>,
+}
+
+/// Handles a DescribeTopicPartitions request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let mut decoder = RequestHeader::body_decoder(request_buffer, true)?;
+
+    let topic_count = decoder.read_array_len_or_empty(true)?;
+    let mut requested = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        requested.push(decoder.read_compact_string()?);
+        decoder.skip_tagged_fields()?;
+    }
+    let partition_limit = decoder.read_i32()?.clamp(1, MAX_PARTITION_LIMIT);
+    let cursor = read_cursor(&mut decoder)?;
+    decoder.skip_tagged_fields()?;
+
+    let limits = &broker.config.echo_limits;
+    let metadata = broker.metadata();
+    let mut names: Vec<&str> = if requested.is_empty() {
+        metadata.topics().map(|topic| topic.name.as_str()).collect()
+    } else {
+        requested.iter().map(String::as_str).collect()
+    };
+    names.sort_unstable();
+    names.dedup();
+    if let Some(cursor) = &cursor {
+        names.retain(|name| *name >= cursor.topic_name.as_str());
+    }
+
+    let mut budget = partition_limit as usize;
+    let mut next_cursor = None;
+    let mut topics = Vec::with_capacity(names.len());
+    for name in names {
+        let topic = metadata.topic(name);
+        let first_partition = match &cursor {
+            Some(cursor) if cursor.topic_name == name => cursor.partition_index,
+            _ => PartitionId(0),
+        };
+        let mut partitions: Vec<PartitionId> = topic
+            .map(|topic| topic.partitions.as_slice())
+            .unwrap_or_default()
+            .iter()
+            .copied()
+            .filter(|partition| *partition >= first_partition)
+            .collect();
+        partitions.sort_unstable();
+        if partitions.len() > budget {
+            if budget == 0 {
+                // Nothing of this topic fits; the next request starts with it
+                next_cursor = Some((name, partitions[0]));
+                break;
+            }
+            next_cursor = Some((name, partitions[budget]));
+            partitions.truncate(budget);
+            topics.push(TopicResponse {
+                name,
+                topic,
+                partitions,
+            });
+            break;
+        }
+        budget -= partitions.len();
+        topics.push(TopicResponse {
+            name,
+            topic,
+            partitions,
+        });
+    }
+
+    println!(
+        "DescribeTopicPartitions: {} topic(s) described, limit {}, {}",
+        topics.len(),
+        partition_limit,
+        match next_cursor {
+            Some((name, partition)) => format!("continues at {}-{}", limits.name(name), partition),
+            None => "complete".to_string(),
+        }
+    );
+    Ok(build_describe_topic_partitions_response(
+        header.correlation_id,
+        broker.config.node_id,
+        topics,
+        next_cursor,
+        limits,
+    ))
+}
+
+/// A nullable struct: -1 for null, otherwise topic_name, partition_index and
+/// a tag buffer.
+fn read_cursor(decoder: &mut Decoder) -> Result<Option<Cursor>, DecodeError> {
+    if decoder.read_i8()? == -1 {
+        return Ok(None);
+    }
+    let topic_name = decoder.read_compact_string()?;
+    let partition_index = PartitionId(decoder.read_i32()?);
+    decoder.skip_tagged_fields()?;
+    Ok(Some(Cursor {
+        topic_name,
+        partition_index,
+    }))
+}
+
+/// DescribeTopicPartitionsResponse: throttle_time_ms, topics [error_code, name,
+/// topic_id, is_internal, partitions [error_code, partition_index, leader_id,
+/// leader_epoch, replica_nodes, isr_nodes, eligible_leader_replicas,
+/// last_known_elr, offline_replicas], topic_authorized_operations],
+/// next_cursor [topic_name, partition_index].
+///
+/// Names of unknown topics come from the request, so they and the number of
+/// unknown topics are capped by `limits` before being echoed.
+fn build_describe_topic_partitions_response(
+    correlation_id: u32,
+    node_id: NodeId,
+    mut topics: Vec<TopicResponse>,
+    next_cursor: Option<(&str, PartitionId)>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let is_internal: i8 = 0;
+
+    let dropped = limits.cap_errors(&mut topics, |topic| topic.topic.is_none());
+    if dropped > 0 {
+        println!("Omitted {} unknown topic(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, true);
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    response.put_compact_array_len(topics.len());
+    for topic in &topics {
+        let (error_code, topic_id, authorized_operations) = match topic.topic {
+            Some(known) => (NONE, known.topic_id, TOPIC_AUTHORIZED_OPERATIONS),
+            None => (UNKNOWN_TOPIC_OR_PARTITION, TopicId::ZERO, 0),
+        };
+        response.put_i16(error_code);
+        match topic.topic {
+            Some(_) => response.put_compact_nullable_string(Some(topic.name)),
+            None => response.put_compact_nullable_string(Some(limits.name(topic.name).as_str())),
+        }
+        response.put_uuid(topic_id.0);
+        response.put_i8(is_internal);
+
+        response.put_compact_array_len(topic.partitions.len());
+        for partition in &topic.partitions {
+            response.put_i16(NONE);
+            response.put_i32(partition.0);
+            response.put_i32(node_id.0); // leader_id
+            response.put_i32(LEADER_EPOCH.0);
+            response.put_compact_array_len(1); // replica_nodes
+            response.put_i32(node_id.0);
+            response.put_compact_array_len(1); // isr_nodes
+            response.put_i32(node_id.0);
+            response.put_compact_array_len(0); // eligible_leader_replicas
+            response.put_compact_array_len(0); // last_known_elr
+            response.put_compact_array_len(0); // offline_replicas
+            response.put_tag_buffer(); // partition TAG_BUFFER
+        }
+        response.put_i32(authorized_operations);
+        response.put_tag_buffer(); // topic TAG_BUFFER
+    }
+
+    match next_cursor {
+        Some((topic_name, partition_index)) => {
+            response.put_i8(1); // next_cursor: present
+            response.put_compact_string(topic_name);
+            response.put_i32(partition_index.0);
+            response.put_tag_buffer(); // cursor TAG_BUFFER
+        }
+        None => response.put_i8(-1), // next_cursor: null
+    }
+    response.put_tag_buffer(); // response TAG_BUFFER
+    response.finish()
+}
@@ -0,0 +1,33 @@
+//! Entries a request lists more than once.
+//!
+//! The schemas of requests naming topics or partitions expect each at most
+//! once, but nothing in the encoding stops a client (or a fuzzer) repeating
+//! one. Every entry is still answered, so a response always has as many as its
+//! request; what a repeated one gets is up to the API, as in Kafka:
+//!
+//! - CreateTopics: every occurrence of a name fails with INVALID_REQUEST.
+//! - Produce, ListOffsets and DeleteRecords: every occurrence of a partition
+//!   fails with INVALID_REQUEST and none is acted on, since which batch,
+//!   timestamp or offset was meant is ambiguous.
+//! - Fetch: every occurrence is read on its own, from its own offset.
+//! - OffsetCommit: every occurrence is committed in request order, so the last
+//!   one stands.
+//!
+//! A topic repeated with different partitions repeats no partition, and is
+//! answered as listed.
+
+use std::collections::{HashMap, HashSet};
+use std::hash::Hash;
+
+/// The keys `keys` yields more than once.
+pub fn repeated<K: Eq + Hash>(keys: impl IntoIterator<Item = K>) -> HashSet<K> {
+    let mut occurrences: HashMap<K, usize> = HashMap::new();
+    for key in keys {
+        *occurrences.entry(key).or_default() += 1;
+    }
+    occurrences
+        .into_iter()
+        .filter(|&(_, count)| count > 1)
+        .map(|(key, _)| key)
+        .collect()
+}
@@ -0,0 +1,100 @@
+//! Limits on client-supplied values reflected back in error responses and logs.
+//!
+//! A request naming a topic we don't know gets that name echoed in its error
+//! response and in the server output. Everything echoed goes through here, so a
+//! multi-megabyte name in a hostile request is capped in one place rather than
+//! per handler.
+
+use std::fmt;
+
+/// Kafka's own limit on topic names; anything longer can't name a real topic.
+pub const DEFAULT_MAX_NAME_BYTES: usize = 249;
+pub const DEFAULT_MAX_PARTITIONS: usize = 1000;
+
+#[derive(Clone, Copy)]
+pub struct EchoLimits {
+    /// Longest client-supplied name written back, in bytes.
+    pub max_name_bytes: usize,
+    /// Most error entries (e.g. unknown partitions) answered per topic.
+    pub max_partitions: usize,
+}
+
+impl Default for EchoLimits {
+    fn default() -> Self {
+        EchoLimits {
+            max_name_bytes: DEFAULT_MAX_NAME_BYTES,
+            max_partitions: DEFAULT_MAX_PARTITIONS,
+        }
+    }
+}
+
+impl EchoLimits {
+    /// Caps a name at `max_name_bytes`, on a char boundary.
+    pub fn name<'a>(&self, name: &'a str) -> EchoedName<'a> {
+        if name.len() <= self.max_name_bytes {
+            return EchoedName {
+                value: name,
+                truncated_from: None,
+            };
+        }
+        let mut end = self.max_name_bytes;
+        while !name.is_char_boundary(end) {
+            end -= 1;
+        }
+        EchoedName {
+            value: &name[..end],
+            truncated_from: Some(name.len()),
+        }
+    }
+
+    /// Drops error entries past `max_partitions`, keeping successful ones (they
+    /// describe real partitions, not client input). Returns how many were dropped.
+    pub fn cap_errors<T>(&self, entries: &mut Vec<T>, is_error: impl Fn(&T) -> bool) -> usize {
+        let before = entries.len();
+        let mut errors = 0;
+        entries.retain(|entry| {
+            if !is_error(entry) {
+                return true;
+            }
+            errors += 1;
+            errors <= self.max_partitions
+        });
+        before - entries.len()
+    }
+}
+
+/// A client-supplied name prepared for echoing. `Display` is the log form:
+/// control characters are escaped and truncation is marked.
+pub struct EchoedName<'a> {
+    value: &'a str,
+    truncated_from: Option<usize>,
+}
+
+impl<'a> EchoedName<'a> {
+    /// The (possibly truncated) name to put on the wire.
+    pub fn as_str(&self) -> &'a str {
+        self.value
+    }
+
+    /// Error-message text telling the client its name was cut short.
+    pub fn truncation_note(&self) -> Option<String> {
+        self.truncated_from
+            .map(|len| format!("name truncated from {} bytes...", len))
+    }
+}
+
+impl fmt::Display for EchoedName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.value.chars() {
+            if c.is_control() {
+                write!(f, "{}", c.escape_default())?;
+            } else {
+                write!(f, "{}", c)?;
+            }
+        }
+        if let Some(len) = self.truncated_from {
+            write!(f, "... ({} bytes)", len)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,40 @@
+//! Errors answered to clients.
+//!
+//! Many responses carry a nullable error_message next to the error code, and
+//! clients show it to their users as is. A `BrokerError` carries both from where
+//! the request failed to where its response is written; a response without an
+//! error_message field sends only the code.
+//!
+//! Messages are sent to whoever made the request, so they are built only from
+//! fixed text, numbers and (capped) values from that same request: never from
+//! file paths, I/O errors or anything another client sent.
+
+#[derive(Debug)]
+pub struct BrokerError {
+    pub code: i16,
+    message: Option<String>,
+}
+
+impl BrokerError {
+    /// An error whose code says it all, for entries a response may repeat
+    /// many times over.
+    pub fn new(code: i16) -> BrokerError {
+        BrokerError {
+            code,
+            message: None,
+        }
+    }
+
+    /// An error with text telling the client what was wrong and what to do.
+    pub fn with_message(code: i16, message: impl Into<String>) -> BrokerError {
+        BrokerError {
+            code,
+            message: Some(message.into()),
+        }
+    }
+
+    /// The error_message to put on the wire; null for an error without one.
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+}
@@ -0,0 +1,487 @@
+//! Fetch (API key 1), versions 4-16.
+//!
+//! v4-v11 use the classic encoding; v12+ are flexible. From v13 topics are
+//! named by topic id rather than by name. Fetch sessions (KIP-227) aren't
+//! supported: every response has session_id 0, which tells the client to send
+//! full fetch requests, so forgotten_topics_data is read and ignored.
+//!
+//! A fetch naming a leader epoch other than the partition's is refused. From v12
+//! the refusal carries the current leader, and from v16 the response lists that
+//! leader's endpoint, so the client can carry on without a Metadata round trip
+//! (KIP-951).
+//!
+//! When less than min_bytes is available the request waits, up to max_wait_ms,
+//! for a produce to append more, as a Kafka broker does. A partition answering
+//! with an error (its topic was deleted, say) ends the wait early. Topic names
+//! are resolved to ids once, before waiting: a topic deleted and created again
+//! under the same name meanwhile is a different topic, so the fetch answers as
+//! though its own were gone rather than reading the new one. A partition listed
+//! more than once is read once per listing, each from its own offset.
+
+use std::time::{Duration, Instant};
+
+use crate::broker::{Broker, LEADER_EPOCH};
+use crate::buffers;
+use crate::codec::{
+    write_compact_array_len, write_compact_nullable_string, write_compact_string,
+    write_tagged_fields, DecodeError, Decoder, ResponseWriter,
+};
+use crate::config::BrokerConfig;
+use crate::header::RequestHeader;
+use crate::ids::{LeaderEpoch, Offset, PartitionId, TopicId};
+use crate::throttle::THROTTLE_TIME_LEN;
+use crate::MESSAGE_SIZE_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 12;
+/// From this version topics are named by id.
+const FIRST_TOPIC_ID_VERSION: i16 = 13;
+/// From this version replica_id moves into a tagged field.
+const FIRST_REPLICA_STATE_VERSION: i16 = 15;
+/// From this version the response can list node endpoints.
+const FIRST_NODE_ENDPOINTS_VERSION: i16 = 16;
+
+/// Request tag of the follower's replica_id and replica_epoch.
+const REPLICA_STATE_TAG: u64 = 1;
+/// Partition tag of the current leader, per the FetchResponse schema.
+const CURRENT_LEADER_TAG: u64 = 1;
+/// Response tag of the node endpoints.
+const NODE_ENDPOINTS_TAG: u64 = 0;
+
+const NONE: i16 = 0;
+const OFFSET_OUT_OF_RANGE: i16 = 1;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const FENCED_LEADER_EPOCH: i16 = 74;
+const UNKNOWN_LEADER_EPOCH: i16 = 75;
+const UNKNOWN_TOPIC_ID: i16 = 100;
+
+struct PartitionRequest {
+    partition: PartitionId,
+    current_leader_epoch: LeaderEpoch,
+    fetch_offset: Offset,
+    partition_max_bytes: i32,
+}
+
+struct TopicRequest {
+    /// `None` from v13, where the topic is named by `topic_id` instead.
+    name: Option<String>,
+    topic_id: TopicId,
+    partitions: Vec<PartitionRequest>,
+}
+
+struct PartitionResponse {
+    partition: PartitionId,
+    error_code: i16,
+    high_watermark: Offset,
+    log_start_offset: Offset,
+    records: Vec<u8>,
+}
+
+impl PartitionResponse {
+    /// A refused fetch tells the client who the leader is instead.
+    fn has_current_leader(&self) -> bool {
+        self.error_code == FENCED_LEADER_EPOCH
+    }
+}
+
+struct TopicResponse<'a> {
+    topic: &'a TopicRequest,
+    partitions: Vec<PartitionResponse>,
+}
+
+/// Whether the fetch may be held before it's answered, waiting for data: it
+/// may whenever max_wait_ms is above 0. One that can't be decoded isn't.
+pub fn may_wait(header: &RequestHeader, request_buffer: &[u8]) -> bool {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let max_wait_ms =
+        RequestHeader::body_decoder(request_buffer, flexible).and_then(|mut decoder| {
+            if api_version < FIRST_REPLICA_STATE_VERSION {
+                decoder.read_i32()?; // replica_id
+            }
+            decoder.read_i32()
+        });
+    matches!(max_wait_ms, Ok(max_wait_ms) if max_wait_ms > 0)
+}
+
+/// Handles a Fetch request, waiting for data if the request asks to.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    // From v15 replica_id is in a tagged field only followers send
+    let mut replica_id = if api_version < FIRST_REPLICA_STATE_VERSION {
+        decoder.read_i32()?
+    } else {
+        -1
+    };
+    let max_wait_ms = decoder.read_i32()?;
+    let min_bytes = decoder.read_i32()?;
+    let max_bytes = decoder.read_i32()?;
+    decoder.read_i8()?; // isolation_level: nothing is transactional, so both levels see the same data
+    if api_version >= 7 {
+        decoder.read_i32()?; // session_id
+        decoder.read_i32()?; // session_epoch
+    }
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut topics = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let (name, topic_id) = if api_version >= FIRST_TOPIC_ID_VERSION {
+            (None, TopicId(decoder.read_uuid()?))
+        } else {
+            (Some(decoder.read_string(flexible)?), TopicId::ZERO)
+        };
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            let partition = PartitionId(decoder.read_i32()?);
+            let current_leader_epoch = if api_version >= 9 {
+                LeaderEpoch(decoder.read_i32()?)
+            } else {
+                LeaderEpoch::UNKNOWN
+            };
+            let fetch_offset = Offset(decoder.read_i64()?);
+            if api_version >= 12 {
+                decoder.read_i32()?; // last_fetched_epoch
+            }
+            if api_version >= 5 {
+                decoder.read_i64()?; // log_start_offset, only meaningful from followers
+            }
+            let partition_max_bytes = decoder.read_i32()?;
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            partitions.push(PartitionRequest {
+                partition,
+                current_leader_epoch,
+                fetch_offset,
+                partition_max_bytes,
+            });
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        topics.push(TopicRequest {
+            name,
+            topic_id,
+            partitions,
+        });
+    }
+
+    if api_version >= 7 {
+        // forgotten_topics_data only applies to incremental sessions
+        for _ in 0..decoder.read_array_len_or_empty(flexible)? {
+            if api_version >= FIRST_TOPIC_ID_VERSION {
+                decoder.read_uuid()?;
+            } else {
+                decoder.read_string(flexible)?;
+            }
+            for _ in 0..decoder.read_array_len_or_empty(flexible)? {
+                decoder.read_i32()?;
+            }
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+        }
+    }
+    if api_version >= 11 {
+        decoder.read_string(flexible)?; // rack_id
+    }
+    if flexible {
+        // cluster_id (tag 0) isn't checked, and tags added since are skipped
+        for (tag, data) in decoder.read_tagged_fields()? {
+            if tag == REPLICA_STATE_TAG && api_version >= FIRST_REPLICA_STATE_VERSION {
+                // replica_epoch and the field's own tag buffer follow
+                replica_id = Decoder::new(data).read_i32()?;
+            }
+        }
+    }
+
+    println!(
+        "Fetch: replica_id={} max_wait_ms={} min_bytes={} max_bytes={} topics={}",
+        replica_id,
+        max_wait_ms,
+        min_bytes,
+        max_bytes,
+        topics.len()
+    );
+
+    let resolved: Vec<Option<TopicId>> = {
+        let metadata = broker.metadata();
+        topics
+            .iter()
+            .map(|topic| match &topic.name {
+                Some(name) => metadata.topic_id(name),
+                None => Some(topic.topic_id),
+            })
+            .collect()
+    };
+
+    let deadline = Instant::now() + Duration::from_millis(max_wait_ms.max(0) as u64);
+    let responses = loop {
+        // Taken before reading, so an append landing part way through the
+        // partitions still ends the wait below
+        let seen = broker.appends_seen();
+        let mut budget = max_bytes.max(0) as usize;
+        let mut available = 0;
+        let responses: Vec<TopicResponse> = topics
+            .iter()
+            .zip(&resolved)
+            .map(|(topic, topic_id)| {
+                // Named by id, a topic that's gone answers with UNKNOWN_TOPIC_ID
+                let unknown_topic = if topic.name.is_some() {
+                    UNKNOWN_TOPIC_OR_PARTITION
+                } else {
+                    UNKNOWN_TOPIC_ID
+                };
+                TopicResponse {
+                    topic,
+                    partitions: topic
+                        .partitions
+                        .iter()
+                        .map(|request| {
+                            let response =
+                                fetch_partition(broker, *topic_id, unknown_topic, request, budget);
+                            budget = budget.saturating_sub(response.records.len());
+                            available += response.records.len();
+                            response
+                        })
+                        .collect(),
+                }
+            })
+            .collect();
+
+        let failed = responses
+            .iter()
+            .flat_map(|topic| &topic.partitions)
+            .any(|partition| partition.error_code != NONE);
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if available >= min_bytes.max(0) as usize || failed || remaining.is_zero() {
+            break responses;
+        }
+        broker.wait_for_append(seen, remaining);
+    };
+
+    Ok(build_fetch_response(
+        header.correlation_id,
+        api_version,
+        &responses,
+        &broker.config,
+    ))
+}
+
+/// Reads one partition, answering `unknown_topic` if `topic_id` is `None` or
+/// its topic has been deleted. Checked afresh each time, since a fetch may be
+/// waiting when the deletion happens.
+fn fetch_partition(
+    broker: &Broker,
+    topic_id: Option<TopicId>,
+    unknown_topic: i16,
+    request: &PartitionRequest,
+    budget: usize,
+) -> PartitionResponse {
+    let error = |error_code, high_watermark, log_start_offset| PartitionResponse {
+        partition: request.partition,
+        error_code,
+        high_watermark,
+        log_start_offset,
+        records: Vec::new(),
+    };
+
+    let known = topic_id.and_then(|topic_id| {
+        let metadata = broker.metadata();
+        let topic = metadata.topic_by_id(topic_id)?;
+        Some((topic_id, topic.partitions.contains(&request.partition)))
+    });
+    let topic_id = match known {
+        None => return error(unknown_topic, Offset::NONE, Offset::NONE),
+        Some((_, false)) => return error(UNKNOWN_TOPIC_OR_PARTITION, Offset::NONE, Offset::NONE),
+        Some((topic_id, true)) => topic_id,
+    };
+    // -1 asks for no check, as older clients always send
+    if request.current_leader_epoch != LeaderEpoch::UNKNOWN {
+        if request.current_leader_epoch < LEADER_EPOCH {
+            return error(FENCED_LEADER_EPOCH, Offset::NONE, Offset::NONE);
+        }
+        if request.current_leader_epoch > LEADER_EPOCH {
+            return error(UNKNOWN_LEADER_EPOCH, Offset::NONE, Offset::NONE);
+        }
+    }
+
+    let logs = broker.logs();
+    let max_bytes = budget.min(request.partition_max_bytes.max(0) as usize);
+    let read = logs
+        .offsets(topic_id, request.partition)
+        .and_then(|offsets| {
+            let records =
+                logs.read(topic_id, request.partition, request.fetch_offset, max_bytes)?;
+            Ok((offsets, records))
+        });
+    let (offsets, records) = match read {
+        Ok(read) => read,
+        Err(e) => {
+            println!(
+                "Fetch from {}-{} failed: {}",
+                topic_id, request.partition, e
+            );
+            return error(e.to_broker_error().code, Offset::NONE, Offset::NONE);
+        }
+    };
+    let high_watermark = offsets.log_end_offset;
+    let log_start_offset = offsets.log_start_offset;
+    match records {
+        None => error(OFFSET_OUT_OF_RANGE, high_watermark, log_start_offset),
+        // Earlier partitions used up max_bytes; this one reports its offsets only
+        Some(_) if budget == 0 => error(NONE, high_watermark, log_start_offset),
+        Some(records) => PartitionResponse {
+            partition: request.partition,
+            error_code: NONE,
+            high_watermark,
+            log_start_offset,
+            records,
+        },
+    }
+}
+
+/// FetchResponse: throttle_time_ms, error_code (v7+), session_id (v7+),
+/// responses [topic (topic_id from v13), partitions [partition_index,
+/// error_code, high_watermark, last_stable_offset, log_start_offset (v5+),
+/// aborted_transactions, preferred_read_replica (v11+), records, current_leader
+/// (tagged, v12+)]], node_endpoints (tagged, v16+).
+///
+/// Unknown topic names and their partitions come from the request, so both are
+/// capped by the echo limits before being echoed.
+fn build_fetch_response(
+    correlation_id: u32,
+    api_version: i16,
+    topics: &[TopicResponse],
+    config: &BrokerConfig,
+) -> Vec<u8> {
+    let limits = &config.echo_limits;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let error_code: i16 = NONE;
+    let session_id: i32 = 0; // Sessionless: the client keeps sending full requests
+    let preferred_read_replica: i32 = -1;
+
+    let mut response = ResponseWriter::in_buffer(
+        buffers::take(estimated_size(config, topics)),
+        correlation_id,
+        flexible,
+    );
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    if api_version >= 7 {
+        response.put_i16(error_code);
+        response.put_i32(session_id);
+    }
+    write_len(&mut response, topics.len());
+    let mut leader_hinted = false;
+    for topic in topics {
+        let name = limits.name(topic.topic.name.as_deref().unwrap_or_default());
+        if api_version >= FIRST_TOPIC_ID_VERSION {
+            response.put_uuid(topic.topic.topic_id.0);
+        } else if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        let mut partitions: Vec<&PartitionResponse> = topic.partitions.iter().collect();
+        let dropped = limits.cap_errors(&mut partitions, |partition| partition.error_code != NONE);
+        if dropped > 0 {
+            println!(
+                "Omitted {} error partition(s) of topic {} from the response",
+                dropped, name
+            );
+        }
+        write_len(&mut response, partitions.len());
+        for partition in partitions {
+            response.put_i32(partition.partition.0);
+            response.put_i16(partition.error_code);
+            response.put_i64(partition.high_watermark.0);
+            // No transactions, so everything below the high watermark is stable
+            response.put_i64(partition.high_watermark.0);
+            if api_version >= 5 {
+                response.put_i64(partition.log_start_offset.0);
+            }
+            write_len(&mut response, 0); // aborted_transactions
+            if api_version >= 11 {
+                response.put_i32(preferred_read_replica);
+            }
+            if flexible {
+                response.put_compact_bytes(&partition.records);
+            } else {
+                response.put_bytes(&partition.records);
+            }
+            if flexible && partition.has_current_leader() {
+                leader_hinted = true;
+                let mut current_leader = Vec::new();
+                current_leader.extend_from_slice(&config.node_id.to_be_bytes());
+                current_leader.extend_from_slice(&LEADER_EPOCH.to_be_bytes());
+                write_tagged_fields(&mut current_leader, &[]); // current_leader TAG_BUFFER
+                response.put_tagged_fields(&[(CURRENT_LEADER_TAG, &current_leader)]);
+            // partition TAG_BUFFER
+            } else if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    // As in Kafka, endpoints are listed only for leaders named above
+    if api_version >= FIRST_NODE_ENDPOINTS_VERSION && leader_hinted {
+        let mut node_endpoints = Vec::new();
+        write_compact_array_len(&mut node_endpoints, 1);
+        node_endpoints.extend_from_slice(&config.node_id.to_be_bytes());
+        write_compact_string(&mut node_endpoints, &config.advertised_host);
+        node_endpoints.extend_from_slice(&config.advertised_port.to_be_bytes());
+        write_compact_nullable_string(&mut node_endpoints, config.rack.as_deref());
+        write_tagged_fields(&mut node_endpoints, &[]); // node endpoint TAG_BUFFER
+        response.put_tagged_fields(&[(NODE_ENDPOINTS_TAG, &node_endpoints)]);
+    // response TAG_BUFFER
+    } else if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
+
+/// An upper bound on the size of `build_fetch_response`'s response in any
+/// version: mostly the records read, plus every other field at its widest.
+fn estimated_size(config: &BrokerConfig, topics: &[TopicResponse]) -> usize {
+    const LEN: usize = 5; // an INT32 length, or a compact one as a varint
+    const FIXED: usize = MESSAGE_SIZE_LEN + 4 + 1 // header
+        + 4 + 2 + 4 + LEN // throttle_time_ms, error_code, session_id, responses
+        + 3 + LEN + 4 + LEN + 4 + LEN + 1; // node_endpoints and the tags around them
+    const PER_TOPIC: usize = 16 + LEN + 1;
+    const PER_PARTITION: usize = 4 + 2 + 8 + 8 + 8 + LEN + 4 + LEN // fields, records length
+        + 3 + 4 + 4 + 1; // current_leader and the tags around it
+    FIXED
+        + config.advertised_host.len()
+        + config.rack.as_ref().map_or(0, String::len)
+        + topics
+            .iter()
+            .map(|topic| {
+                PER_TOPIC
+                    + topic.topic.name.as_ref().map_or(0, String::len)
+                    + topic
+                        .partitions
+                        .iter()
+                        .map(|partition| PER_PARTITION + partition.records.len())
+                        .sum::<usize>()
+            })
+            .sum::<usize>()
+}
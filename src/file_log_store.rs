@@ -0,0 +1,805 @@
+//! Partition logs written through to files under the log directory.
+//!
+//! Each partition has a directory `<log_dir>/<topic id>-<partition>` holding a
+//! single segment named by its first offset, `00000000000000000000.log` until
+//! DeleteRecords removes every record in it: its batches back to back, as Fetch
+//! returns them. Batches are written once appended and read back only by
+//! `recover` at startup; reads are served from the copy `PartitionLog` keeps in
+//! memory. Beside the segment, `partition.metadata` names the partition the
+//! directory holds, and `log-start-offset.checkpoint` keeps the log start
+//! offset DeleteRecords last set; once it reaches the log end, the segment is
+//! replaced by an empty one starting there.
+//!
+//! Recovery restores every partition directory it finds, several at once on
+//! `recovery_threads` threads, since each is read on its own. Restoring a
+//! partition's batches also reloads its producer state, the recent batches of
+//! each producer a retry is matched against, so that is done on the same
+//! threads. A segment that stops making sense part way (a torn write at a
+//! crash, or damage) keeps the batches before that point and is cut back to
+//! them, so later appends follow on from a readable log. A partition that can't
+//! be read at all is left offline, answering `LogError::Offline`, so one bad
+//! disk block costs that partition and not the broker; deleting its topic
+//! removes it.
+//!
+//! Writes go to the OS as they're appended; `flush` is what makes them survive
+//! a crash of the machine rather than just the process.
+//!
+//! The layout's version is kept in `<log_dir>/eventor-log-format`, as
+//! `version=2`, written before the first partition directory is, and each file
+//! of a partition directory carries a version of its own: the two text files
+//! a `version=1` first line, and the segment each batch's magic byte. `upgrade`
+//! checks the layout before `recover` reads anything: a version this broker
+//! doesn't know, a newer broker's, fails startup rather than being misread or
+//! written over. An older one is brought up to date by the steps in
+//! `UPGRADES`, one version at a time, partition by partition, the marker
+//! rewritten after each. Every step so far only adds files, or moves a value
+//! into one before removing the old, so an interrupted upgrade is simply run
+//! again; they run at startup as well as from `eventor upgrade`.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard};
+use std::thread;
+use std::time::Instant;
+
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::log::{Appended, PartitionLog};
+use crate::log_store::{self, FlushedOffsets, LogError, LogOffsets, LogStore};
+use crate::record_batch::RecordBatch;
+
+const SEGMENT_SUFFIX: &str = ".log";
+const PARTITION_METADATA_FILE: &str = "partition.metadata";
+const CHECKPOINT_FILE: &str = "log-start-offset.checkpoint";
+/// Where layouts before version 2 kept the log start offset, unversioned and
+/// only once DeleteRecords had set one.
+const LEGACY_START_OFFSET_FILE: &str = "log-start-offset";
+const FORMAT_FILE: &str = "eventor-log-format";
+
+/// The layout this broker writes.
+const FORMAT_VERSION: u32 = 2;
+/// The versions of `partition.metadata` and the checkpoint this broker writes.
+const PARTITION_METADATA_VERSION: u32 = 1;
+const CHECKPOINT_VERSION: u32 = 1;
+
+/// Brings one partition directory from a layout version to the next, or, in a
+/// dry run, only works out what that would change; returns the changes.
+type Upgrade = fn(&Path, (TopicId, PartitionId), bool) -> Result<Vec<String>, LogError>;
+
+/// The upgrade from each older version to the one after it. What each version
+/// holds, per partition directory:
+///
+/// | version | marker      | `partition.metadata` | log start offset                           | segment          |
+/// |---------|-------------|----------------------|--------------------------------------------|------------------|
+/// | 0       | none        | none                 | `log-start-offset`, once DeleteRecords ran | batches, magic 2 |
+/// | 1       | `version=1` | none                 | `log-start-offset`, once DeleteRecords ran | batches, magic 2 |
+/// | 2       | `version=2` | `version=1`          | `log-start-offset.checkpoint`, `version=1` | batches, magic 2 |
+///
+/// Every version up to `FORMAT_VERSION` is read once upgraded; a later one is
+/// refused.
+const UPGRADES: &[(u32, Upgrade)] = &[(0, upgrade_from_unmarked), (1, add_partition_files)];
+
+/// A partition directory under the log directory.
+struct FoundPartition {
+    key: (TopicId, PartitionId),
+    name: String,
+    path: PathBuf,
+}
+
+struct FilePartition {
+    log: PartitionLog,
+    segment: File,
+    /// Offset the segment starts at, which names it.
+    segment_base: Offset,
+    /// Bytes of the segment holding the batches in `log`.
+    segment_len: u64,
+}
+
+pub struct FileLogStore {
+    log_dir: PathBuf,
+    recovery_threads: usize,
+    partitions: Mutex<HashMap<(TopicId, PartitionId), FilePartition>>,
+    /// Partitions whose directories `recover` couldn't read. Locked only
+    /// after `partitions`, or on its own.
+    offline: Mutex<HashSet<(TopicId, PartitionId)>>,
+}
+
+impl FileLogStore {
+    /// A store under `log_dir`, recovered on one thread. Nothing is read until
+    /// `recover`.
+    pub fn new(log_dir: PathBuf) -> FileLogStore {
+        FileLogStore {
+            log_dir,
+            recovery_threads: 1,
+            partitions: Mutex::default(),
+            offline: Mutex::default(),
+        }
+    }
+
+    /// Recovers up to `threads` partitions at once.
+    pub fn recovery_threads(mut self, threads: usize) -> FileLogStore {
+        self.recovery_threads = threads.max(1);
+        self
+    }
+
+    fn partitions_locked(&self) -> MutexGuard<'_, HashMap<(TopicId, PartitionId), FilePartition>> {
+        // A write that fails is undone before the lock is released, so a panic
+        // elsewhere can't leave a log and its segment disagreeing
+        self.partitions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn offline_locked(&self) -> MutexGuard<'_, HashSet<(TopicId, PartitionId)>> {
+        // Only ever inserted into or removed from whole
+        self.offline
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Why a partition that isn't in `partitions` can't be used.
+    fn missing(&self, topic_id: TopicId, partition: PartitionId) -> LogError {
+        if self.offline_locked().contains(&(topic_id, partition)) {
+            LogError::Offline(topic_id, partition)
+        } else {
+            LogError::UnknownPartition(topic_id, partition)
+        }
+    }
+
+    fn partition_dir(&self, topic_id: TopicId, partition: PartitionId) -> PathBuf {
+        self.log_dir.join(format!("{}-{}", topic_id, partition))
+    }
+
+    /// Runs `f` on a partition's log, or fails if there's none.
+    fn with_log<T>(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        f: impl FnOnce(&PartitionLog) -> T,
+    ) -> Result<T, LogError> {
+        let partitions = self.partitions_locked();
+        match partitions.get(&(topic_id, partition)) {
+            Some(stored) => Ok(f(&stored.log)),
+            None => Err(self.missing(topic_id, partition)),
+        }
+    }
+}
+
+impl LogStore for FileLogStore {
+    fn recover(&self) -> Result<(), LogError> {
+        upgrade(&self.log_dir, false)?;
+        let found = find_partitions(&self.log_dir)?;
+
+        let total = found.len();
+        let threads = self.recovery_threads.min(total).max(1);
+        println!(
+            "Recovering {} partition(s) in {} on {} thread(s)",
+            total,
+            self.log_dir.display(),
+            threads
+        );
+        let started = Instant::now();
+        let queue = Mutex::new(found.into_iter());
+        let done = AtomicUsize::new(0);
+        let results = Mutex::new(Vec::with_capacity(total));
+        thread::scope(|scope| {
+            for _ in 0..threads {
+                scope.spawn(|| loop {
+                    // Taken one at a time, so a slow partition holds up only
+                    // the thread recovering it
+                    let next = queue
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .next();
+                    let Some(FoundPartition { key, name, path }) = next else {
+                        break;
+                    };
+                    let result = open_partition(&path, key);
+                    let done = done.fetch_add(1, Ordering::Relaxed) + 1;
+                    match &result {
+                        Ok(partition) => println!(
+                            "Recovered partition {} with log end offset {} and {} producer(s) ({}/{})",
+                            name,
+                            partition.log.log_end_offset(),
+                            partition.log.producer_count(),
+                            done,
+                            total
+                        ),
+                        Err(e) => println!(
+                            "Error recovering partition {}: {}; leaving it offline ({}/{})",
+                            name, e, done, total
+                        ),
+                    }
+                    results
+                        .lock()
+                        .unwrap_or_else(|poisoned| poisoned.into_inner())
+                        .push((key, result));
+                });
+            }
+        });
+
+        let mut recovered = HashMap::new();
+        let mut offline = HashSet::new();
+        for (key, result) in results
+            .into_inner()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+        {
+            match result {
+                Ok(partition) => {
+                    recovered.insert(key, partition);
+                }
+                Err(_) => {
+                    offline.insert(key);
+                }
+            }
+        }
+        println!(
+            "Recovered {} partition(s) in {} in {} ms, {} offline",
+            recovered.len(),
+            self.log_dir.display(),
+            started.elapsed().as_millis(),
+            offline.len()
+        );
+        let mut partitions = self.partitions_locked();
+        *partitions = recovered;
+        *self.offline_locked() = offline;
+        Ok(())
+    }
+
+    fn partitions(&self) -> Vec<(TopicId, PartitionId)> {
+        let partitions = self.partitions_locked();
+        let offline = self.offline_locked();
+        partitions.keys().chain(offline.iter()).copied().collect()
+    }
+
+    fn create_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError> {
+        let mut partitions = self.partitions_locked();
+        // An offline partition exists; it's just not readable
+        if partitions.contains_key(&(topic_id, partition))
+            || self.offline_locked().contains(&(topic_id, partition))
+        {
+            return Ok(());
+        }
+        if !self.log_dir.join(FORMAT_FILE).exists() {
+            fs::create_dir_all(&self.log_dir)
+                .map_err(|e| LogError::io(format!("creating {}", self.log_dir.display()), e))?;
+            write_format(&self.log_dir, FORMAT_VERSION)?;
+        }
+        let dir = self.partition_dir(topic_id, partition);
+        fs::create_dir_all(&dir)
+            .map_err(|e| LogError::io(format!("creating {}", dir.display()), e))?;
+        write_partition_metadata(&dir, topic_id, partition)?;
+        write_start_offset(&dir, Offset(0))?;
+        partitions.insert(
+            (topic_id, partition),
+            open_partition(&dir, (topic_id, partition))?,
+        );
+        Ok(())
+    }
+
+    fn delete_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError> {
+        let mut partitions = self.partitions_locked();
+        partitions.remove(&(topic_id, partition));
+        self.offline_locked().remove(&(topic_id, partition));
+        let dir = self.partition_dir(topic_id, partition);
+        match fs::remove_dir_all(&dir) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(LogError::io(format!("removing {}", dir.display()), e)),
+        }
+    }
+
+    fn append(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        batches: &[RecordBatch],
+    ) -> Result<Vec<Appended>, LogError> {
+        let mut partitions = self.partitions_locked();
+        let stored = match partitions.get_mut(&(topic_id, partition)) {
+            Some(stored) => stored,
+            None => return Err(self.missing(topic_id, partition)),
+        };
+
+        let end_before = stored.log.log_end_offset();
+        let mut written = Vec::new();
+        let appended = batches
+            .iter()
+            .map(|batch| {
+                let appended = stored.log.append(batch);
+                // A duplicate is already in the segment
+                if !appended.duplicate {
+                    written.extend_from_slice(stored.log.last_batch().unwrap_or_default());
+                }
+                appended
+            })
+            .collect();
+        if let Err(e) = stored.segment.write_all(&written) {
+            // Undone in both, so the log and the segment still agree; part of
+            // the batches may have reached the file
+            stored.log.truncate(end_before);
+            let _ = stored.segment.set_len(stored.segment_len);
+            let dir = self.partition_dir(topic_id, partition);
+            return Err(LogError::io(format!("appending to {}", dir.display()), e));
+        }
+        stored.segment_len += written.len() as u64;
+        Ok(appended)
+    }
+
+    fn read(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        fetch_offset: Offset,
+        max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, LogError> {
+        self.with_log(topic_id, partition, |log| log.read(fetch_offset, max_bytes))
+    }
+
+    fn offsets(&self, topic_id: TopicId, partition: PartitionId) -> Result<LogOffsets, LogError> {
+        self.with_log(topic_id, partition, LogOffsets::of)
+    }
+
+    fn max_producer_id(&self) -> Option<i64> {
+        self.partitions_locked()
+            .values()
+            .filter_map(|stored| stored.log.max_producer_id())
+            .max()
+    }
+
+    fn delete_records(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        offset: Offset,
+    ) -> Result<Offset, LogError> {
+        let mut partitions = self.partitions_locked();
+        let stored = match partitions.get_mut(&(topic_id, partition)) {
+            Some(stored) => stored,
+            None => return Err(self.missing(topic_id, partition)),
+        };
+        log_store::check_in_range(&stored.log, offset)?;
+        if offset <= stored.log.log_start_offset() {
+            return Ok(stored.log.log_start_offset());
+        }
+
+        // Saved before the log changes, so a failure leaves both as they were
+        let dir = self.partition_dir(topic_id, partition);
+        write_start_offset(&dir, offset)?;
+        let start_offset = stored.log.delete_before(offset);
+        if start_offset == stored.log.log_end_offset() && stored.segment_len > 0 {
+            // Nothing in the segment is left to read. Failing to replace it
+            // loses nothing: the start offset saved above still applies, and
+            // the next roll or restart tries again
+            if let Err(e) = roll_segment(&dir, stored, start_offset) {
+                println!(
+                    "Error replacing the deleted segment of {}: {}",
+                    dir.display(),
+                    e
+                );
+            }
+        }
+        Ok(start_offset)
+    }
+
+    fn offset_for_timestamp(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        target: i64,
+    ) -> Result<Option<(i64, Offset)>, LogError> {
+        self.with_log(topic_id, partition, |log| log.offset_for_timestamp(target))
+    }
+
+    fn max_timestamp_offset(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+    ) -> Result<Option<(i64, Offset)>, LogError> {
+        self.with_log(topic_id, partition, |log| log.max_timestamp_offset())
+    }
+
+    fn flush(&self) -> Result<FlushedOffsets, LogError> {
+        // Synced through handles of their own once the lock is released, so
+        // appends aren't held up behind the disk
+        let mut segments = Vec::new();
+        let mut flushed = FlushedOffsets::new();
+        for (&(topic_id, partition), stored) in self.partitions_locked().iter() {
+            let dir = self.partition_dir(topic_id, partition);
+            let segment = stored
+                .segment
+                .try_clone()
+                .map_err(|e| LogError::io(format!("flushing {}", dir.display()), e))?;
+            segments.push((dir, segment));
+            flushed.insert((topic_id, partition), stored.log.log_end_offset());
+        }
+        for (dir, segment) in segments {
+            segment
+                .sync_data()
+                .map_err(|e| LogError::io(format!("flushing {}", dir.display()), e))?;
+        }
+        Ok(flushed)
+    }
+}
+
+/// Opens a partition directory's segment, creating it if there's none, and
+/// restores its batches, with the producer state they carry, and log start
+/// offset. Segments older than the newest
+/// are left from a roll that didn't finish, and are deleted.
+fn open_partition(dir: &Path, key: (TopicId, PartitionId)) -> Result<FilePartition, LogError> {
+    check_partition_metadata(dir, key)?;
+    let mut bases = segment_bases(dir)?;
+    let segment_base = bases.pop().unwrap_or_default();
+    for base in bases {
+        remove_segment(dir, base)?;
+    }
+
+    let path = dir.join(segment_name(segment_base));
+    let mut segment = open_segment(&path)?;
+    let mut contents = Vec::new();
+    segment
+        .read_to_end(&mut contents)
+        .map_err(|e| LogError::io(format!("reading {}", path.display()), e))?;
+
+    let mut log = PartitionLog::starting_at(segment_base);
+    let mut segment_len = 0;
+    while segment_len < contents.len() {
+        match RecordBatch::parse_first(&contents[segment_len..]) {
+            Ok(batch) if log.restore(&batch) => segment_len += batch.bytes.len(),
+            _ => break,
+        }
+    }
+    if segment_len < contents.len() {
+        println!(
+            "Segment {} is damaged from byte {}, dropping the {} byte(s) after it",
+            path.display(),
+            segment_len,
+            contents.len() - segment_len
+        );
+        segment
+            .set_len(segment_len as u64)
+            .map_err(|e| LogError::io(format!("truncating {}", path.display()), e))?;
+    }
+    if let Some(start_offset) = read_start_offset(dir)? {
+        // A damaged tail may have taken records the start offset was past
+        log.delete_before(start_offset);
+    }
+    Ok(FilePartition {
+        log,
+        segment,
+        segment_base,
+        segment_len: segment_len as u64,
+    })
+}
+
+/// The base offsets of a partition directory's segments, oldest first.
+fn segment_bases(dir: &Path) -> Result<Vec<Offset>, LogError> {
+    let entries =
+        fs::read_dir(dir).map_err(|e| LogError::io(format!("reading {}", dir.display()), e))?;
+    let mut bases = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| LogError::io(format!("reading {}", dir.display()), e))?;
+        if let Some(base) = parse_segment_name(&entry.file_name().to_string_lossy()) {
+            bases.push(base);
+        }
+    }
+    bases.sort();
+    Ok(bases)
+}
+
+fn open_segment(path: &Path) -> Result<File, LogError> {
+    OpenOptions::new()
+        .read(true)
+        .append(true)
+        .create(true)
+        .open(path)
+        .map_err(|e| LogError::io(format!("opening {}", path.display()), e))
+}
+
+/// Replaces a partition's segment, all of whose records are deleted, with an
+/// empty one starting at `base`.
+fn roll_segment(dir: &Path, stored: &mut FilePartition, base: Offset) -> Result<(), LogError> {
+    let segment = open_segment(&dir.join(segment_name(base)))?;
+    let old_base = stored.segment_base;
+    stored.segment = segment;
+    stored.segment_base = base;
+    stored.segment_len = 0;
+    remove_segment(dir, old_base)
+}
+
+fn remove_segment(dir: &Path, base: Offset) -> Result<(), LogError> {
+    let path = dir.join(segment_name(base));
+    println!("Deleting segment {}", path.display());
+    fs::remove_file(&path).map_err(|e| LogError::io(format!("removing {}", path.display()), e))
+}
+
+/// Replaces a file of `dir` whole, so a crash leaves either the old one or
+/// the new.
+fn replace_file(dir: &Path, name: &str, contents: &str) -> Result<(), LogError> {
+    let path = dir.join(name);
+    let staged = dir.join(format!("{}.tmp", name));
+    fs::write(&staged, contents)
+        .and_then(|()| fs::rename(&staged, &path))
+        .map_err(|e| LogError::io(format!("writing {}", path.display()), e))
+}
+
+/// Fails unless `line`, the first of the file at `path`, says it's of
+/// `version`.
+fn check_version(path: &Path, line: Option<&str>, version: u32) -> Result<(), LogError> {
+    let found = line.unwrap_or_default().trim();
+    match found.strip_prefix("version=").map(str::parse::<u32>) {
+        Some(Ok(written)) if written == version => Ok(()),
+        _ => Err(LogError::UnsupportedFormat {
+            path: path.display().to_string(),
+            found: format!("'{}'", found),
+            supported: version,
+        }),
+    }
+}
+
+fn write_partition_metadata(
+    dir: &Path,
+    topic_id: TopicId,
+    partition: PartitionId,
+) -> Result<(), LogError> {
+    let contents = format!(
+        "version={}\ntopic_id={}\npartition={}\n",
+        PARTITION_METADATA_VERSION, topic_id, partition
+    );
+    replace_file(dir, PARTITION_METADATA_FILE, &contents)
+}
+
+/// Checks that `partition.metadata` names the partition the directory's name
+/// does, writing it if a creation cut short left none.
+fn check_partition_metadata(
+    dir: &Path,
+    (topic_id, partition): (TopicId, PartitionId),
+) -> Result<(), LogError> {
+    let path = dir.join(PARTITION_METADATA_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {
+            println!(
+                "Writing {}, which its partition's creation left out",
+                path.display()
+            );
+            return write_partition_metadata(dir, topic_id, partition);
+        }
+        Err(e) => return Err(LogError::io(format!("reading {}", path.display()), e)),
+    };
+    let mut lines = contents.lines();
+    check_version(&path, lines.next(), PARTITION_METADATA_VERSION)?;
+    let expected = [
+        format!("topic_id={}", topic_id),
+        format!("partition={}", partition),
+    ];
+    if !lines.eq(expected.iter().map(String::as_str)) {
+        let message = format!("it doesn't name partition {}-{}", topic_id, partition);
+        return Err(LogError::io(
+            format!("reading {}", path.display()),
+            io::Error::new(io::ErrorKind::InvalidData, message),
+        ));
+    }
+    Ok(())
+}
+
+/// Saves the log start offset to the checkpoint.
+fn write_start_offset(dir: &Path, offset: Offset) -> Result<(), LogError> {
+    let contents = format!("version={}\n{}\n", CHECKPOINT_VERSION, offset);
+    replace_file(dir, CHECKPOINT_FILE, &contents)
+}
+
+/// The log start offset the checkpoint holds, if there's one. One that can't
+/// be read is ignored, leaving the log to start at its segment.
+fn read_start_offset(dir: &Path) -> Result<Option<Offset>, LogError> {
+    let path = dir.join(CHECKPOINT_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(LogError::io(format!("reading {}", path.display()), e)),
+    };
+    let mut lines = contents.lines();
+    check_version(&path, lines.next(), CHECKPOINT_VERSION)?;
+    Ok(parse_offset(&path, lines.next().unwrap_or_default()))
+}
+
+/// The offset `line` of the file at `path` holds, or `None`, logged, if it
+/// holds none.
+fn parse_offset(path: &Path, line: &str) -> Option<Offset> {
+    match line.trim().parse() {
+        Ok(offset) => Some(Offset(offset)),
+        Err(_) => {
+            println!("Ignoring {}, which holds no offset", path.display());
+            None
+        }
+    }
+}
+
+/// Records the log directory's layout version.
+fn write_format(log_dir: &Path, version: u32) -> Result<(), LogError> {
+    replace_file(log_dir, FORMAT_FILE, &format!("version={}\n", version))
+}
+
+/// The log directory's layout version, if it has been recorded. One that
+/// isn't a version this broker writes or upgrades from is
+/// `LogError::UnsupportedFormat`.
+fn read_format(log_dir: &Path) -> Result<Option<u32>, LogError> {
+    let path = log_dir.join(FORMAT_FILE);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(LogError::io(format!("reading {}", path.display()), e)),
+    };
+    let found = contents.trim();
+    match found.strip_prefix("version=").map(str::parse) {
+        Some(Ok(version)) if version <= FORMAT_VERSION => Ok(Some(version)),
+        _ => Err(LogError::UnsupportedFormat {
+            path: path.display().to_string(),
+            found: format!("'{}'", found),
+            supported: FORMAT_VERSION,
+        }),
+    }
+}
+
+/// Brings a log directory written by an older broker up to the layout this
+/// one writes, logging each partition as it's changed; with `dry_run`, only
+/// logs what would change. A layout this broker doesn't know is refused.
+pub fn upgrade(log_dir: &Path, dry_run: bool) -> Result<(), LogError> {
+    let found = find_partitions(log_dir)?;
+    let version = match read_format(log_dir)? {
+        Some(version) => version,
+        // The marker is written before any partition directory
+        None if !found.is_empty() => 0,
+        None => {
+            println!(
+                "Log directory {} holds no partition logs yet",
+                log_dir.display()
+            );
+            return Ok(());
+        }
+    };
+    if version == FORMAT_VERSION {
+        println!(
+            "Log directory {} is at format {}, the current one",
+            log_dir.display(),
+            version
+        );
+        return Ok(());
+    }
+    let verb = if dry_run { "Would change" } else { "Changed" };
+    for &(from, upgrade) in UPGRADES.iter().filter(|(from, _)| *from >= version) {
+        println!(
+            "{} log directory {} from format {} to {}, {} partition(s){}",
+            if dry_run {
+                "Would upgrade"
+            } else {
+                "Upgrading"
+            },
+            log_dir.display(),
+            from,
+            from + 1,
+            found.len(),
+            if dry_run { " (dry run)" } else { "" }
+        );
+        for (i, partition) in found.iter().enumerate() {
+            let changes = upgrade(&partition.path, partition.key, dry_run)?;
+            if !changes.is_empty() {
+                println!(
+                    "{} {}: {} ({}/{})",
+                    verb,
+                    partition.name,
+                    changes.join(", "),
+                    i + 1,
+                    found.len()
+                );
+            }
+        }
+        if !dry_run {
+            write_format(log_dir, from + 1)?;
+        }
+    }
+    Ok(())
+}
+
+/// Partition directories from before the format marker are laid out as
+/// version 1 is: there's nothing to rewrite.
+fn upgrade_from_unmarked(
+    _dir: &Path,
+    _key: (TopicId, PartitionId),
+    _dry_run: bool,
+) -> Result<Vec<String>, LogError> {
+    Ok(Vec::new())
+}
+
+/// Adds `partition.metadata`, and a checkpoint holding the log start offset:
+/// the one `log-start-offset` holds, which it replaces, or else where the
+/// segment starts.
+fn add_partition_files(
+    dir: &Path,
+    (topic_id, partition): (TopicId, PartitionId),
+    dry_run: bool,
+) -> Result<Vec<String>, LogError> {
+    let mut changes = Vec::new();
+    if !dir.join(PARTITION_METADATA_FILE).exists() {
+        if !dry_run {
+            write_partition_metadata(dir, topic_id, partition)?;
+        }
+        changes.push(format!("add {}", PARTITION_METADATA_FILE));
+    }
+    let legacy = dir.join(LEGACY_START_OFFSET_FILE);
+    let saved = match fs::read_to_string(&legacy) {
+        Ok(contents) => Some(parse_offset(&legacy, &contents)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => None,
+        Err(e) => return Err(LogError::io(format!("reading {}", legacy.display()), e)),
+    };
+    // A checkpoint beside the old file is one an interrupted upgrade wrote
+    // before removing it, and is written again
+    if saved.is_none() && dir.join(CHECKPOINT_FILE).exists() {
+        return Ok(changes);
+    }
+    let segment_base = segment_bases(dir)?.pop().unwrap_or_default();
+    let start_offset = saved.flatten().unwrap_or(segment_base).max(segment_base);
+    if !dry_run {
+        write_start_offset(dir, start_offset)?;
+    }
+    changes.push(format!(
+        "add {} at offset {}",
+        CHECKPOINT_FILE, start_offset
+    ));
+    if saved.is_some() {
+        if !dry_run {
+            fs::remove_file(&legacy)
+                .map_err(|e| LogError::io(format!("removing {}", legacy.display()), e))?;
+        }
+        changes.push(format!("remove {}", LEGACY_START_OFFSET_FILE));
+    }
+    Ok(changes)
+}
+
+/// Every partition directory under `log_dir`, none if it doesn't exist yet.
+fn find_partitions(log_dir: &Path) -> Result<Vec<FoundPartition>, LogError> {
+    let context = || format!("reading log directory {}", log_dir.display());
+    let entries = match fs::read_dir(log_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(LogError::io(context(), e)),
+    };
+    let mut found = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|e| LogError::io(context(), e))?;
+        // Anything else there (the metadata log, meta.properties) isn't ours
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if let Some(key) = parse_partition_dir(&name) {
+            found.push(FoundPartition {
+                key,
+                name,
+                path: entry.path(),
+            });
+        }
+    }
+    found.sort_by_key(|partition| partition.key);
+    Ok(found)
+}
+
+fn segment_name(base: Offset) -> String {
+    format!("{:020}{}", base.0, SEGMENT_SUFFIX)
+}
+
+/// The base offset a segment file is named by.
+fn parse_segment_name(name: &str) -> Option<Offset> {
+    let base = name.strip_suffix(SEGMENT_SUFFIX)?;
+    if base.len() != 20 || !base.bytes().all(|byte| byte.is_ascii_digit()) {
+        return None;
+    }
+    base.parse().ok().map(Offset)
+}
+
+/// The (topic id, partition) a `<topic id>-<partition>` directory holds.
+fn parse_partition_dir(name: &str) -> Option<(TopicId, PartitionId)> {
+    let (topic_id, partition) = name.split_once('-')?;
+    let topic_id = TopicId::from_hex(topic_id)?;
+    let partition = partition.parse().ok().filter(|partition| *partition >= 0)?;
+    Some((topic_id, PartitionId(partition)))
+}
@@ -0,0 +1,152 @@
+//! FindCoordinator (API key 10), versions 0-5.
+//!
+//! Group and transactional clients first ask which broker coordinates their
+//! group or transactional id. There is one broker, so the answer is always this
+//! one. v0-v3 ask about a single key; v4+ batch them in coordinator_keys, with
+//! an answer per key.
+//!
+//! v0-v2 use the classic encoding; v3+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::ids::NodeId;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 3;
+/// From this version keys come batched in coordinator_keys.
+const FIRST_BATCHED_VERSION: i16 = 4;
+
+const GROUP_KEY_TYPE: i8 = 0;
+const TRANSACTION_KEY_TYPE: i8 = 1;
+
+const NONE: i16 = 0;
+const INVALID_REQUEST: i16 = 42;
+
+/// Handles a FindCoordinator request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let mut keys = Vec::new();
+    if api_version < FIRST_BATCHED_VERSION {
+        keys.push(decoder.read_string(flexible)?);
+    }
+    let key_type = if api_version >= 1 {
+        decoder.read_i8()?
+    } else {
+        GROUP_KEY_TYPE
+    };
+    if api_version >= FIRST_BATCHED_VERSION {
+        let key_count = decoder.read_array_len_or_empty(true)?;
+        keys.reserve(key_count);
+        for _ in 0..key_count {
+            keys.push(decoder.read_compact_string()?);
+        }
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    // The same answer goes for every key in the request
+    let error = match key_type {
+        GROUP_KEY_TYPE | TRANSACTION_KEY_TYPE => None,
+        _ => Some(BrokerError::with_message(
+            INVALID_REQUEST,
+            "Unknown coordinator key type",
+        )),
+    };
+    println!(
+        "FindCoordinator: key_type={} keys={} error_code={}",
+        key_type,
+        keys.len(),
+        error.as_ref().map_or(NONE, |error| error.code)
+    );
+    Ok(build_find_coordinator_response(
+        header.correlation_id,
+        api_version,
+        broker,
+        &keys,
+        error.as_ref(),
+        &broker.config.echo_limits,
+    ))
+}
+
+/// FindCoordinatorResponse: throttle_time_ms (v1+), then up to v3 error_code,
+/// error_message (v1+), node_id, host, port; from v4 coordinators [key, node_id,
+/// host, port, error_code, error_message].
+///
+/// Keys come from the request, so they are capped by `limits` before being
+/// echoed.
+fn build_find_coordinator_response(
+    correlation_id: u32,
+    api_version: i16,
+    broker: &Broker,
+    keys: &[String],
+    error: Option<&BrokerError>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let config = &broker.config;
+    // Where the error is, the coordinator fields are left empty, as in Kafka
+    let error_code = error.map_or(NONE, |error| error.code);
+    let error_message = error.and_then(BrokerError::message);
+    let (node_id, host, port) = if error.is_none() {
+        (
+            config.node_id,
+            config.advertised_host.as_str(),
+            config.advertised_port,
+        )
+    } else {
+        (NodeId(-1), "", -1)
+    };
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_str = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value)
+        } else {
+            response.put_string(value)
+        }
+    };
+    let write_coordinator = |response: &mut ResponseWriter| {
+        response.put_i32(node_id.0);
+        write_str(response, host);
+        response.put_i32(port);
+    };
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    if api_version < FIRST_BATCHED_VERSION {
+        response.put_i16(error_code);
+        if flexible {
+            response.put_compact_nullable_string(error_message);
+        } else if api_version >= 1 {
+            response.put_nullable_string(error_message);
+        }
+        write_coordinator(&mut response);
+    } else {
+        response.put_compact_array_len(keys.len());
+        for key in keys {
+            response.put_compact_string(limits.name(key).as_str());
+            write_coordinator(&mut response);
+            response.put_i16(error_code);
+            response.put_compact_nullable_string(error_message);
+            response.put_tag_buffer(); // coordinator TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
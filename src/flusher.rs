@@ -0,0 +1,217 @@
+//! Flushing the partition logs to stable storage, for produces that wait on it.
+//!
+//! With `log.flush.on.acks.all=true`, an acks=-1 produce isn't answered until
+//! its batches are on stable storage. Rather than flushing the store itself,
+//! the produce takes a `FlushBarrier` at the offset of its last record and
+//! waits on that: the barrier nudges the flusher thread, which flushes the
+//! whole store in one go and then moves each partition's recovery point (the
+//! offset its log is on stable storage up to) to where its log ended when the
+//! flush began. Produces arriving while a flush is under way share the next
+//! one, so however many producers are waiting there is one fsync in flight, and
+//! each of them costs a wait for at most two.
+//!
+//! With `log.flush.interval.ms` set the thread also flushes that often while
+//! no barrier asks it to. Either way the logs are flushed again on shutdown.
+//!
+//! `PartitionLog` holds no storage of its own, so barriers are taken here, by
+//! partition, rather than from the log. Recovery points are kept in memory
+//! only: whatever a restart recovers counts as flushed.
+
+use std::sync::{Arc, Condvar, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::error::BrokerError;
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::log_store::{self, FlushedOffsets, LogError, LogStore};
+
+const REQUEST_TIMED_OUT: i16 = 7;
+
+#[derive(Debug, Error)]
+pub enum FlushError {
+    #[error("offset {0} was not flushed within {1:?}")]
+    TimedOut(Offset, Duration),
+    #[error("the flush failed")]
+    Failed,
+    #[error("partition {0}-{1} was deleted before it was flushed")]
+    UnknownPartition(TopicId, PartitionId),
+}
+
+impl FlushError {
+    /// What a produce waiting on the flush is told.
+    pub fn to_broker_error(&self) -> BrokerError {
+        match self {
+            FlushError::TimedOut(..) => BrokerError::new(REQUEST_TIMED_OUT),
+            FlushError::Failed => log_store::storage_error(),
+            FlushError::UnknownPartition(topic_id, partition) => {
+                LogError::UnknownPartition(*topic_id, *partition).to_broker_error()
+            }
+        }
+    }
+}
+
+#[derive(Default)]
+struct FlushState {
+    /// A barrier is waiting on the next flush.
+    demanded: bool,
+    /// Flushes begun, numbering each from 1.
+    started: u64,
+    /// The last flush that succeeded, and the last that failed.
+    succeeded: u64,
+    failed: u64,
+    /// How many have succeeded.
+    flushes: u64,
+    /// Where each partition's log ended when the last successful flush began.
+    recovery_points: FlushedOffsets,
+}
+
+/// The flusher thread's state, shared with every barrier on it.
+#[derive(Default)]
+pub struct Flusher {
+    state: Mutex<FlushState>,
+    /// Signalled when a barrier asks for a flush.
+    demanded: Condvar,
+    /// Signalled when a flush has finished, successfully or not.
+    flushed: Condvar,
+}
+
+impl Flusher {
+    /// Starts the flusher thread on `logs`, flushing whenever a barrier asks
+    /// and, with an `interval`, at least that often.
+    pub fn start(logs: Arc<dyn LogStore>, interval: Option<Duration>) -> Arc<Flusher> {
+        let flusher: Arc<Flusher> = Arc::default();
+        thread::spawn({
+            let flusher = Arc::clone(&flusher);
+            move || loop {
+                flusher.flush(&*logs, interval);
+            }
+        });
+        flusher
+    }
+
+    fn lock(&self) -> MutexGuard<'_, FlushState> {
+        // Every change is a single assignment, so a panic can't leave it torn
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Waits for a barrier or the interval, then flushes the store once.
+    fn flush(&self, logs: &dyn LogStore, interval: Option<Duration>) {
+        let state = self.lock();
+        let mut state = match interval {
+            Some(interval) => {
+                self.demanded
+                    .wait_timeout_while(state, interval, |state| !state.demanded)
+                    .unwrap_or_else(|poisoned| poisoned.into_inner())
+                    .0
+            }
+            None => self
+                .demanded
+                .wait_while(state, |state| !state.demanded)
+                .unwrap_or_else(|poisoned| poisoned.into_inner()),
+        };
+        // Barriers taken from here on wait for the flush after this one
+        state.demanded = false;
+        state.started += 1;
+        let flush = state.started;
+        drop(state);
+
+        // Appends made during the flush may be flushed too, but aren't counted on
+        let result = logs.flush();
+
+        let mut state = self.lock();
+        match result {
+            Ok(log_ends) => {
+                state.succeeded = flush;
+                state.flushes += 1;
+                state.recovery_points = log_ends;
+            }
+            Err(e) => {
+                println!("Error flushing partition logs: {}", e);
+                state.failed = flush;
+            }
+        }
+        drop(state);
+        self.flushed.notify_all();
+    }
+
+    /// A barrier that is passed once the partition's records before and at
+    /// `offset` are on stable storage. Asks for a flush at once.
+    pub fn flush_barrier(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        offset: Offset,
+    ) -> FlushBarrier<'_> {
+        let mut state = self.lock();
+        state.demanded = true;
+        let barrier = FlushBarrier {
+            flusher: self,
+            partition: (topic_id, partition),
+            offset,
+            after: state.started,
+        };
+        drop(state);
+        self.demanded.notify_one();
+        barrier
+    }
+
+    /// How many flushes have succeeded since startup.
+    pub fn flushes(&self) -> u64 {
+        self.lock().flushes
+    }
+}
+
+/// Something a caller can park on until it's done, or until it gives up.
+pub trait Wait {
+    type Error;
+
+    fn wait(self, timeout: Duration) -> Result<(), Self::Error>;
+}
+
+/// A wait for a partition to be flushed through an offset, from
+/// `Flusher::flush_barrier`.
+pub struct FlushBarrier<'a> {
+    flusher: &'a Flusher,
+    partition: (TopicId, PartitionId),
+    offset: Offset,
+    /// The flushes begun before the barrier was taken, which may have missed
+    /// the records it waits for.
+    after: u64,
+}
+
+impl FlushBarrier<'_> {
+    /// `None` while the barrier hasn't been passed and no flush begun after it
+    /// has finished.
+    fn outcome(&self, state: &FlushState) -> Option<Result<(), FlushError>> {
+        match state.recovery_points.get(&self.partition) {
+            Some(recovery_point) if *recovery_point > self.offset => Some(Ok(())),
+            _ if state.failed > self.after => Some(Err(FlushError::Failed)),
+            None if state.succeeded > self.after => {
+                let (topic_id, partition) = self.partition;
+                Some(Err(FlushError::UnknownPartition(topic_id, partition)))
+            }
+            _ => None,
+        }
+    }
+}
+
+impl Wait for FlushBarrier<'_> {
+    type Error = FlushError;
+
+    /// Parks the caller until the barrier is passed, a flush fails or the
+    /// partition turns out to be gone, or `timeout` runs out.
+    fn wait(self, timeout: Duration) -> Result<(), FlushError> {
+        let state = self.flusher.lock();
+        let (state, _) = self
+            .flusher
+            .flushed
+            .wait_timeout_while(state, timeout, |state| self.outcome(state).is_none())
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        self.outcome(&state)
+            .unwrap_or(Err(FlushError::TimedOut(self.offset, timeout)))
+    }
+}
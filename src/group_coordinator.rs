@@ -0,0 +1,977 @@
+//! Consumer group membership, shared by every connection: each member of a
+//! group talks to the broker on its own socket.
+//!
+//! A group moves on in join rounds. A round opens when a member joins (or
+//! rejoins) and closes once every member has joined it, or when the rebalance
+//! timeout runs out, dropping the members that didn't. Closing a round starts
+//! the next generation, led by the longest-standing member, with a protocol
+//! every member supports. Until then each joiner's request waits in `join`.
+//! The leader then hands out assignments with SyncGroup, and each member's
+//! `sync` waits for them.
+//!
+//! Between rounds members heartbeat. The scheduler calls `expire_members` on
+//! every tick, dropping members whose session timeout has passed since they
+//! were last heard from, and opening a round for the rest to rejoin.
+//!
+//! A member shutting down cleanly leaves instead, which opens a round for the
+//! rest straight away. A group whose last member has left or expired is Empty:
+//! it is dropped from here, but its committed offsets stay in `GroupOffsets`.
+//!
+//! Member metadata and assignments are opaque to the broker, but they are kept
+//! to hand out, so `GroupLimits` caps how much a member and a group may store.
+//! A generation's assignments are dropped as soon as the next round opens.
+//!
+//! ListGroups and DescribeGroups read the same state, under the same lock, so
+//! they see each group as of between two changes to it, never halfway through.
+
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use crate::echo::EchoLimits;
+use crate::metadata::random_uuid;
+
+pub const DEFAULT_MAX_MEMBER_METADATA_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_MAX_GROUP_METADATA_BYTES: usize = 32 * 1024 * 1024;
+
+/// Kafka's `group.min.session.timeout.ms` and `group.max.session.timeout.ms`
+/// defaults.
+pub const DEFAULT_MIN_SESSION_TIMEOUT_MS: i32 = 6_000;
+pub const DEFAULT_MAX_SESSION_TIMEOUT_MS: i32 = 1_800_000;
+
+const NONE: i16 = 0;
+const MESSAGE_TOO_LARGE: i16 = 10;
+const ILLEGAL_GENERATION: i16 = 22;
+const INCONSISTENT_GROUP_PROTOCOL: i16 = 23;
+const INVALID_GROUP_ID: i16 = 24;
+const UNKNOWN_MEMBER_ID: i16 = 25;
+const INVALID_SESSION_TIMEOUT: i16 = 26;
+const REBALANCE_IN_PROGRESS: i16 = 27;
+const MEMBER_ID_REQUIRED: i16 = 79;
+const FENCED_INSTANCE_ID: i16 = 82;
+
+#[derive(Clone, Copy)]
+pub struct GroupLimits {
+    /// Shortest and longest session timeout a member may join with.
+    pub min_session_timeout_ms: i32,
+    pub max_session_timeout_ms: i32,
+    /// Most protocol names and metadata one member may join with, and most
+    /// assignment one member may be given, in bytes.
+    pub max_member_metadata_bytes: usize,
+    /// Most metadata and assignments the members of one group may store between
+    /// them.
+    pub max_group_metadata_bytes: usize,
+}
+
+impl Default for GroupLimits {
+    fn default() -> Self {
+        GroupLimits {
+            min_session_timeout_ms: DEFAULT_MIN_SESSION_TIMEOUT_MS,
+            max_session_timeout_ms: DEFAULT_MAX_SESSION_TIMEOUT_MS,
+            max_member_metadata_bytes: DEFAULT_MAX_MEMBER_METADATA_BYTES,
+            max_group_metadata_bytes: DEFAULT_MAX_GROUP_METADATA_BYTES,
+        }
+    }
+}
+
+pub struct JoinRequest {
+    pub group_id: String,
+    pub session_timeout_ms: i32,
+    pub rebalance_timeout_ms: i32,
+    /// Empty on a member's first join.
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub protocol_type: String,
+    /// (name, metadata) pairs, in the member's order of preference.
+    pub protocols: Vec<(String, Vec<u8>)>,
+    /// Prefix of a member id handed out, as in Kafka.
+    pub client_id: Option<String>,
+    /// Address the member connected from, as DescribeGroups reports it.
+    pub client_host: String,
+    /// v4+ clients join twice: a member without an id is handed one and told to
+    /// join again with it, rather than joining at once.
+    pub require_known_member_id: bool,
+}
+
+pub struct JoinResult {
+    pub error_code: i16,
+    pub generation_id: i32,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    pub leader: String,
+    /// The member's id, handed out by this join when it sent none.
+    pub member_id: String,
+    /// Every member with its metadata for the chosen protocol, for the leader
+    /// to compute assignments from; empty for everyone else.
+    pub members: Vec<JoinedMember>,
+}
+
+pub struct JoinedMember {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub metadata: Vec<u8>,
+}
+
+impl JoinResult {
+    fn error(error_code: i16, member_id: String) -> JoinResult {
+        JoinResult {
+            error_code,
+            generation_id: -1,
+            protocol_type: None,
+            protocol_name: None,
+            leader: String::new(),
+            member_id,
+            members: Vec::new(),
+        }
+    }
+}
+
+pub struct SyncRequest {
+    pub group_id: String,
+    pub generation_id: i32,
+    pub member_id: String,
+    /// v5+: checked against the group's, when sent.
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    /// (member_id, assignment) pairs; only the leader's are used.
+    pub assignments: Vec<(String, Vec<u8>)>,
+}
+
+pub struct SyncResult {
+    pub error_code: i16,
+    pub protocol_type: Option<String>,
+    pub protocol_name: Option<String>,
+    /// What the leader assigned this member; empty if it was left out.
+    pub assignment: Vec<u8>,
+}
+
+impl SyncResult {
+    fn error(error_code: i16) -> SyncResult {
+        SyncResult {
+            error_code,
+            protocol_type: None,
+            protocol_name: None,
+            assignment: Vec::new(),
+        }
+    }
+}
+
+/// A member a LeaveGroup names. Before v3 only the member id is sent.
+pub struct LeavingMember {
+    /// Empty when the member is named by its group instance id alone.
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+}
+
+/// A group's state, as ListGroups and DescribeGroups name it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GroupState {
+    /// A join round is open.
+    PreparingRebalance,
+    /// The round has closed, but the leader hasn't handed out assignments yet.
+    CompletingRebalance,
+    Stable,
+    /// Nobody is in the group, but it may have committed offsets.
+    Empty,
+    /// The broker knows nothing of the group.
+    Dead,
+}
+
+impl GroupState {
+    pub fn name(self) -> &'static str {
+        match self {
+            GroupState::PreparingRebalance => "PreparingRebalance",
+            GroupState::CompletingRebalance => "CompletingRebalance",
+            GroupState::Stable => "Stable",
+            GroupState::Empty => "Empty",
+            GroupState::Dead => "Dead",
+        }
+    }
+}
+
+/// A group as ListGroups lists it.
+pub struct GroupListing {
+    pub group_id: String,
+    /// Empty until a member has joined.
+    pub protocol_type: String,
+    pub state: GroupState,
+}
+
+/// A group as DescribeGroups describes it.
+pub struct GroupDescription {
+    pub state: GroupState,
+    pub protocol_type: String,
+    /// The generation's protocol; empty unless the group is Stable.
+    pub protocol_name: String,
+    pub members: Vec<MemberDescription>,
+}
+
+/// A member as DescribeGroups describes it. Its metadata for the chosen
+/// protocol and its assignment are only sent while the group is Stable, as in
+/// Kafka; otherwise both are empty.
+pub struct MemberDescription {
+    pub member_id: String,
+    pub group_instance_id: Option<String>,
+    pub client_id: String,
+    pub client_host: String,
+    pub metadata: Vec<u8>,
+    pub assignment: Vec<u8>,
+}
+
+struct Member {
+    member_id: String,
+    group_instance_id: Option<String>,
+    client_id: String,
+    client_host: String,
+    session_timeout: Duration,
+    rebalance_timeout: Duration,
+    /// When it last joined, synced or heartbeat.
+    last_heard: Instant,
+    protocols: Vec<(String, Vec<u8>)>,
+    /// Whether it has joined the open round, or the last one when none is open.
+    joined: bool,
+}
+
+impl Member {
+    fn supports(&self, protocol: &str) -> bool {
+        self.protocols.iter().any(|(name, _)| name == protocol)
+    }
+
+    fn metadata_bytes(&self) -> usize {
+        protocol_bytes(&self.protocols)
+    }
+}
+
+#[derive(Default)]
+struct Group {
+    generation_id: i32,
+    protocol_type: Option<String>,
+    protocol_name: Option<String>,
+    leader_id: Option<String>,
+    /// In join order, so the first is the longest-standing.
+    members: Vec<Member>,
+    /// Ids handed out by a two-step join, until the member joins with one or its
+    /// session timeout passes.
+    pending: HashMap<String, Instant>,
+    /// While a join round is open, when it closes without the members that
+    /// haven't joined it.
+    round_deadline: Option<Instant>,
+    /// The leader's assignments for this generation, keyed by member id, once
+    /// it has synced.
+    assignments: Option<HashMap<String, Vec<u8>>>,
+}
+
+impl Group {
+    fn is_empty(&self) -> bool {
+        self.members.is_empty() && self.pending.is_empty()
+    }
+
+    fn state(&self) -> GroupState {
+        if self.round_deadline.is_some() {
+            GroupState::PreparingRebalance
+        } else if self.members.is_empty() {
+            // Only members handed an id, which count once they join with it
+            GroupState::Empty
+        } else if self.assignments.is_none() {
+            GroupState::CompletingRebalance
+        } else {
+            GroupState::Stable
+        }
+    }
+
+    fn listing(&self, group_id: &str) -> GroupListing {
+        GroupListing {
+            group_id: group_id.to_string(),
+            protocol_type: self.protocol_type.clone().unwrap_or_default(),
+            state: self.state(),
+        }
+    }
+
+    fn description(&self) -> GroupDescription {
+        let state = self.state();
+        let protocol_name = match (state, &self.protocol_name) {
+            (GroupState::Stable, Some(protocol_name)) => protocol_name.clone(),
+            _ => String::new(),
+        };
+        let members = self
+            .members
+            .iter()
+            .map(|member| {
+                let (metadata, assignment) = match &self.assignments {
+                    Some(assignments) if state == GroupState::Stable => (
+                        member
+                            .protocols
+                            .iter()
+                            .find(|(name, _)| *name == protocol_name)
+                            .map(|(_, metadata)| metadata.clone())
+                            .unwrap_or_default(),
+                        assignments
+                            .get(&member.member_id)
+                            .cloned()
+                            .unwrap_or_default(),
+                    ),
+                    _ => (Vec::new(), Vec::new()),
+                };
+                MemberDescription {
+                    member_id: member.member_id.clone(),
+                    group_instance_id: member.group_instance_id.clone(),
+                    client_id: member.client_id.clone(),
+                    client_host: member.client_host.clone(),
+                    metadata,
+                    assignment,
+                }
+            })
+            .collect();
+        GroupDescription {
+            state,
+            protocol_type: self.protocol_type.clone().unwrap_or_default(),
+            protocol_name,
+            members,
+        }
+    }
+
+    /// Member metadata and assignments held, in bytes.
+    fn stored_bytes(&self) -> usize {
+        let metadata: usize = self.members.iter().map(Member::metadata_bytes).sum();
+        let assignments: usize = self
+            .assignments
+            .iter()
+            .flatten()
+            .map(|(member_id, assignment)| member_id.len() + assignment.len())
+            .sum();
+        metadata + assignments
+    }
+
+    fn has_member(&self, member_id: &str) -> bool {
+        self.members
+            .iter()
+            .any(|member| member.member_id == member_id)
+    }
+
+    fn member_mut(&mut self, member_id: &str) -> Option<&mut Member> {
+        self.members
+            .iter_mut()
+            .find(|member| member.member_id == member_id)
+    }
+
+    /// Starts a round every member has to join, open for the longest of their
+    /// rebalance timeouts. Nobody can sync for the last generation any more.
+    fn open_round(&mut self, now: Instant) {
+        for member in &mut self.members {
+            member.joined = false;
+        }
+        self.assignments = None;
+        let rebalance_timeout = self
+            .members
+            .iter()
+            .map(|member| member.rebalance_timeout)
+            .max()
+            .unwrap_or_default();
+        self.round_deadline = Some(now + rebalance_timeout);
+    }
+
+    /// Whether a member may act for this generation: it has to be in the group,
+    /// at its generation, with no round open.
+    fn check_generation(&self, generation_id: i32, member_id: &str) -> i16 {
+        if !self.has_member(member_id) {
+            UNKNOWN_MEMBER_ID
+        } else if generation_id != self.generation_id {
+            ILLEGAL_GENERATION
+        } else if self.round_deadline.is_some() {
+            REBALANCE_IN_PROGRESS
+        } else {
+            NONE
+        }
+    }
+
+    /// The protocol the most members like best among those all of them support;
+    /// ties go to the longest-standing member's preference.
+    fn choose_protocol(&self) -> Option<String> {
+        let first = self.members.first()?;
+        let candidates: Vec<&str> = first
+            .protocols
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .filter(|name| self.members.iter().all(|member| member.supports(name)))
+            .collect();
+        let mut votes = vec![0; candidates.len()];
+        for member in &self.members {
+            if let Some(choice) = member
+                .protocols
+                .iter()
+                .find_map(|(name, _)| candidates.iter().position(|c| c == name))
+            {
+                votes[choice] += 1;
+            }
+        }
+        // max_by_key keeps the last of equals, so walk the candidates backwards
+        (0..candidates.len())
+            .rev()
+            .max_by_key(|&choice| votes[choice])
+            .map(|choice| candidates[choice].to_string())
+    }
+
+    fn result_for(&self, member_id: &str) -> JoinResult {
+        let leader = self.leader_id.clone().unwrap_or_default();
+        let members = if leader == member_id {
+            let protocol = self.protocol_name.as_deref().unwrap_or_default();
+            self.members
+                .iter()
+                .map(|member| JoinedMember {
+                    member_id: member.member_id.clone(),
+                    group_instance_id: member.group_instance_id.clone(),
+                    metadata: member
+                        .protocols
+                        .iter()
+                        .find(|(name, _)| name == protocol)
+                        .map(|(_, metadata)| metadata.clone())
+                        .unwrap_or_default(),
+                })
+                .collect()
+        } else {
+            Vec::new()
+        };
+        JoinResult {
+            error_code: NONE,
+            generation_id: self.generation_id,
+            protocol_type: self.protocol_type.clone(),
+            protocol_name: self.protocol_name.clone(),
+            leader,
+            member_id: member_id.to_string(),
+            members,
+        }
+    }
+}
+
+/// Every group's membership, keyed by group id.
+pub struct GroupCoordinator {
+    groups: Mutex<HashMap<String, Group>>,
+    /// Signalled whenever a join round opens or closes and whenever a leader
+    /// hands out assignments, for the members waiting on them.
+    changed: Condvar,
+    limits: GroupLimits,
+    echo_limits: EchoLimits,
+}
+
+impl GroupCoordinator {
+    pub fn new(limits: GroupLimits, echo_limits: EchoLimits) -> GroupCoordinator {
+        GroupCoordinator {
+            groups: Mutex::new(HashMap::new()),
+            changed: Condvar::new(),
+            limits,
+            echo_limits,
+        }
+    }
+
+    /// Joins a member to its group, waiting until the round it joined closes.
+    pub fn join(&self, request: JoinRequest) -> JoinResult {
+        let group_id = request.group_id.clone();
+        let mut groups = self.lock();
+        let (member_id, generation) = match self.enter(&mut groups, request) {
+            Ok(joined) => joined,
+            Err((error_code, member_id)) => {
+                if groups.get(&group_id).is_some_and(Group::is_empty) {
+                    groups.remove(&group_id);
+                }
+                return JoinResult::error(error_code, member_id);
+            }
+        };
+        // Members waiting on the last generation's assignments have to rejoin
+        self.changed.notify_all();
+        self.close_round_if_complete(&mut groups, &group_id);
+
+        loop {
+            let Some(group) = groups.get_mut(&group_id) else {
+                return JoinResult::error(UNKNOWN_MEMBER_ID, member_id);
+            };
+            if group.generation_id == generation {
+                if let Some(deadline) = group.round_deadline {
+                    let now = Instant::now();
+                    if now < deadline {
+                        groups = self.wait(groups, deadline - now);
+                    } else {
+                        self.end_overdue_round(&mut groups, &group_id);
+                    }
+                    continue;
+                }
+            }
+            // A member dropped from the round it waited on is told to start over
+            if !group.has_member(&member_id) {
+                return JoinResult::error(UNKNOWN_MEMBER_ID, member_id);
+            }
+            return group.result_for(&member_id);
+        }
+    }
+
+    /// Stores the leader's assignments, and answers each member with its own
+    /// once the leader has synced, waiting up to the member's rebalance timeout
+    /// for it.
+    pub fn sync(&self, request: SyncRequest) -> SyncResult {
+        let group_id = request.group_id.as_str();
+        let member_id = request.member_id.as_str();
+        let mut groups = self.lock();
+        let Some(group) = groups.get_mut(group_id) else {
+            return SyncResult::error(UNKNOWN_MEMBER_ID);
+        };
+        let error_code = group.check_generation(request.generation_id, member_id);
+        if error_code != NONE {
+            return SyncResult::error(error_code);
+        }
+        if let Some(member) = group.member_mut(member_id) {
+            member.last_heard = Instant::now();
+        }
+        let consistent = request
+            .protocol_type
+            .as_ref()
+            .map_or(true, |protocol_type| {
+                group.protocol_type.as_ref() == Some(protocol_type)
+            })
+            && request
+                .protocol_name
+                .as_ref()
+                .map_or(true, |protocol_name| {
+                    group.protocol_name.as_ref() == Some(protocol_name)
+                });
+        if !consistent {
+            return SyncResult::error(INCONSISTENT_GROUP_PROTOCOL);
+        }
+
+        let is_leader = group.leader_id.as_deref() == Some(member_id);
+        if is_leader && group.assignments.is_none() {
+            let group_name = self.echo_limits.name(group_id);
+            let mut assignments = HashMap::new();
+            for (assigned_id, assignment) in request.assignments {
+                if assignment.len() > self.limits.max_member_metadata_bytes {
+                    println!(
+                        "Group {} rejected an assignment of {} bytes, over the {} allowed per member",
+                        group_name,
+                        assignment.len(),
+                        self.limits.max_member_metadata_bytes
+                    );
+                    return SyncResult::error(MESSAGE_TOO_LARGE);
+                }
+                // Assignments for members no longer in the group would only take up room
+                if group.has_member(&assigned_id) {
+                    assignments.insert(assigned_id, assignment);
+                }
+            }
+            group.assignments = Some(assignments);
+            let stored = group.stored_bytes();
+            if stored > self.limits.max_group_metadata_bytes {
+                group.assignments = None;
+                println!(
+                    "Group {} rejected assignments that would bring it to {} bytes, over the {} allowed per group",
+                    group_name, stored, self.limits.max_group_metadata_bytes
+                );
+                return SyncResult::error(MESSAGE_TOO_LARGE);
+            }
+            println!(
+                "Group {} generation {}: leader assigned {} member(s)",
+                group_name,
+                group.generation_id,
+                group.assignments.iter().flatten().count()
+            );
+            self.log_usage(&groups);
+            self.changed.notify_all();
+        }
+
+        let generation = request.generation_id;
+        let rebalance_timeout = groups
+            .get(group_id)
+            .and_then(|group| group.members.iter().find(|m| m.member_id == member_id))
+            .map(|member| member.rebalance_timeout)
+            .unwrap_or_default();
+        let deadline = Instant::now() + rebalance_timeout;
+        loop {
+            let Some(group) = groups.get(group_id) else {
+                return SyncResult::error(UNKNOWN_MEMBER_ID);
+            };
+            let error_code = group.check_generation(generation, member_id);
+            if error_code != NONE {
+                return SyncResult::error(error_code);
+            }
+            if let Some(assignments) = &group.assignments {
+                return SyncResult {
+                    error_code: NONE,
+                    protocol_type: group.protocol_type.clone(),
+                    protocol_name: group.protocol_name.clone(),
+                    assignment: assignments.get(member_id).cloned().unwrap_or_default(),
+                };
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                // The leader never synced: rejoining elects a new one if it's gone
+                return SyncResult::error(REBALANCE_IN_PROGRESS);
+            }
+            groups = self.wait(groups, deadline - now);
+        }
+    }
+
+    /// Records that a member is alive, and answers whether it is still in step
+    /// with its group: REBALANCE_IN_PROGRESS tells it to rejoin.
+    pub fn heartbeat(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        let mut groups = self.lock();
+        let Some(group) = groups.get_mut(group_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+        let Some(member) = group.member_mut(member_id) else {
+            return UNKNOWN_MEMBER_ID;
+        };
+        member.last_heard = Instant::now();
+        group.check_generation(generation_id, member_id)
+    }
+
+    /// Removes members leaving their group, answering each with an error code.
+    /// The rest are told to rejoin, unless a round they are joining can now
+    /// close without the leavers. The group error is only for an invalid id.
+    pub fn leave(&self, group_id: &str, leaving: &[LeavingMember]) -> (i16, Vec<i16>) {
+        if group_id.is_empty() {
+            return (INVALID_GROUP_ID, Vec::new());
+        }
+        let mut groups = self.lock();
+        let Some(group) = groups.get_mut(group_id) else {
+            return (NONE, vec![UNKNOWN_MEMBER_ID; leaving.len()]);
+        };
+        let group_name = self.echo_limits.name(group_id);
+        let mut left = false;
+        let error_codes = leaving
+            .iter()
+            .map(|leaver| {
+                if group.pending.remove(&leaver.member_id).is_some() {
+                    return NONE;
+                }
+                let position = group.members.iter().position(|member| {
+                    if leaver.member_id.is_empty() {
+                        leaver.group_instance_id.is_some()
+                            && member.group_instance_id == leaver.group_instance_id
+                    } else {
+                        member.member_id == leaver.member_id
+                    }
+                });
+                let Some(position) = position else {
+                    return UNKNOWN_MEMBER_ID;
+                };
+                let member = &group.members[position];
+                if leaver.group_instance_id.is_some()
+                    && member.group_instance_id != leaver.group_instance_id
+                {
+                    return FENCED_INSTANCE_ID;
+                }
+                println!(
+                    "Group {} member {} left",
+                    group_name,
+                    self.echo_limits.name(&member.member_id)
+                );
+                group.members.remove(position);
+                left = true;
+                NONE
+            })
+            .collect();
+        if left && group.round_deadline.is_none() && !group.members.is_empty() {
+            group.open_round(Instant::now());
+        }
+        // A round waiting only on the leavers closes now
+        self.close_round_if_complete(&mut groups, group_id);
+        if groups.get(group_id).is_some_and(Group::is_empty) {
+            groups.remove(group_id);
+        }
+        if left {
+            self.log_usage(&groups);
+            self.changed.notify_all();
+        }
+        (NONE, error_codes)
+    }
+
+    /// Drops members that haven't been heard from within their session
+    /// timeout, opening a round for the rest, and closes rounds whose rebalance
+    /// timeout has run out with nobody left waiting on them. Called from the
+    /// scheduler, since a member that has died sends nothing to trigger it.
+    pub fn expire_members(&self) {
+        let mut groups = self.lock();
+        let now = Instant::now();
+        let mut overdue = Vec::new();
+        let mut expired = false;
+        for (group_id, group) in groups.iter_mut() {
+            group.pending.retain(|_, expiry| *expiry > now);
+            if let Some(deadline) = group.round_deadline {
+                // During a round the rebalance timeout decides who stays
+                if now >= deadline {
+                    overdue.push(group_id.clone());
+                }
+                continue;
+            }
+            let before = group.members.len();
+            group.members.retain(|member| {
+                let alive = now.duration_since(member.last_heard) <= member.session_timeout;
+                if !alive {
+                    println!(
+                        "Group {} dropped member {}: not heard from within its {} ms session timeout",
+                        self.echo_limits.name(group_id),
+                        self.echo_limits.name(&member.member_id),
+                        member.session_timeout.as_millis()
+                    );
+                }
+                alive
+            });
+            if group.members.len() < before {
+                expired = true;
+                // The rest rejoin to share out what the dropped members had
+                group.open_round(now);
+            }
+        }
+        for group_id in &overdue {
+            self.end_overdue_round(&mut groups, group_id);
+        }
+        let before = groups.len();
+        groups.retain(|_, group| !group.is_empty());
+        if expired || !overdue.is_empty() || groups.len() < before {
+            self.log_usage(&groups);
+            self.changed.notify_all();
+        }
+    }
+
+    /// The error for an OffsetCommit from this member and generation, or none.
+    /// Groups nobody has joined take commits from anyone, as before there were
+    /// members to check against.
+    ///
+    /// A negative generation is an admin commit, such as resetting a group's
+    /// offsets, made on behalf of the group rather than by a member of it. Like
+    /// Kafka, it is only taken while the group is Empty: one with members, or
+    /// just joiners, refuses it with UNKNOWN_MEMBER_ID.
+    pub fn check_commit(&self, group_id: &str, generation_id: i32, member_id: &str) -> i16 {
+        match self.lock().get(group_id) {
+            Some(group) if group.is_empty() => NONE,
+            Some(_) if generation_id < 0 => UNKNOWN_MEMBER_ID,
+            Some(group) if !group.members.is_empty() => {
+                group.check_generation(generation_id, member_id)
+            }
+            _ => NONE,
+        }
+    }
+
+    /// Every group with a member, or a member-to-be, in no order.
+    pub fn list(&self) -> Vec<GroupListing> {
+        self.lock()
+            .iter()
+            .map(|(group_id, group)| group.listing(group_id))
+            .collect()
+    }
+
+    /// The group's state and members; `None` if it has neither members nor
+    /// members-to-be.
+    pub fn describe(&self, group_id: &str) -> Option<GroupDescription> {
+        self.lock().get(group_id).map(Group::description)
+    }
+
+    /// Checks a join and adds or updates the member, opening a round if none is
+    /// open. Returns the member's id and the generation the round will close, or
+    /// the error code to answer with that id.
+    fn enter(
+        &self,
+        groups: &mut HashMap<String, Group>,
+        request: JoinRequest,
+    ) -> Result<(String, i32), (i16, String)> {
+        let mut member_id = request.member_id;
+        if request.group_id.is_empty() {
+            return Err((INVALID_GROUP_ID, member_id));
+        }
+        let session_timeouts =
+            self.limits.min_session_timeout_ms..=self.limits.max_session_timeout_ms;
+        if !session_timeouts.contains(&request.session_timeout_ms) {
+            return Err((INVALID_SESSION_TIMEOUT, member_id));
+        }
+        if request.protocol_type.is_empty() || request.protocols.is_empty() {
+            return Err((INCONSISTENT_GROUP_PROTOCOL, member_id));
+        }
+        let group_name = self.echo_limits.name(&request.group_id);
+        let metadata_bytes = protocol_bytes(&request.protocols);
+        if metadata_bytes > self.limits.max_member_metadata_bytes {
+            println!(
+                "Group {} rejected a join with {} bytes of protocol metadata, over the {} allowed per member",
+                group_name, metadata_bytes, self.limits.max_member_metadata_bytes
+            );
+            return Err((MESSAGE_TOO_LARGE, member_id));
+        }
+
+        let now = Instant::now();
+        let session_timeout = Duration::from_millis(request.session_timeout_ms as u64);
+        let group = groups.entry(request.group_id.clone()).or_default();
+        group.pending.retain(|_, expiry| *expiry > now);
+
+        let mut others = group
+            .members
+            .iter()
+            .filter(|member| member.member_id != member_id)
+            .peekable();
+        if others.peek().is_some() {
+            let supported = group.protocol_type.as_deref() == Some(&request.protocol_type)
+                && request
+                    .protocols
+                    .iter()
+                    .any(|(name, _)| others.clone().all(|member| member.supports(name)));
+            if !supported {
+                return Err((INCONSISTENT_GROUP_PROTOCOL, member_id));
+            }
+        }
+        let group_bytes = others.map(Member::metadata_bytes).sum::<usize>() + metadata_bytes;
+        if group_bytes > self.limits.max_group_metadata_bytes {
+            println!(
+                "Group {} rejected a join that would bring its member metadata to {} bytes, over the {} allowed per group",
+                group_name, group_bytes, self.limits.max_group_metadata_bytes
+            );
+            return Err((MESSAGE_TOO_LARGE, member_id));
+        }
+
+        if member_id.is_empty() {
+            member_id = new_member_id(request.client_id.as_deref());
+            if request.require_known_member_id {
+                group
+                    .pending
+                    .insert(member_id.clone(), now + session_timeout);
+                return Err((MEMBER_ID_REQUIRED, member_id));
+            }
+        } else if !group.pending.contains_key(&member_id)
+            && !group.members.iter().any(|m| m.member_id == member_id)
+        {
+            return Err((UNKNOWN_MEMBER_ID, member_id));
+        }
+
+        group.pending.remove(&member_id);
+        group.protocol_type = Some(request.protocol_type);
+        let member = Member {
+            member_id: member_id.clone(),
+            group_instance_id: request.group_instance_id,
+            client_id: request.client_id.unwrap_or_default(),
+            client_host: request.client_host,
+            session_timeout,
+            last_heard: now,
+            rebalance_timeout: Duration::from_millis(request.rebalance_timeout_ms.max(0) as u64),
+            protocols: request.protocols,
+            joined: false,
+        };
+        match group
+            .members
+            .iter_mut()
+            .find(|existing| existing.member_id == member_id)
+        {
+            Some(existing) => *existing = member,
+            None => group.members.push(member),
+        }
+        if group.round_deadline.is_none() {
+            group.open_round(now);
+        }
+        if let Some(member) = group.member_mut(&member_id) {
+            member.joined = true;
+        }
+        Ok((member_id, group.generation_id))
+    }
+
+    /// Closes a round whose rebalance timeout has run out, without the members
+    /// that didn't join it.
+    fn end_overdue_round(&self, groups: &mut HashMap<String, Group>, group_id: &str) {
+        if let Some(group) = groups.get_mut(group_id) {
+            group.members.retain(|member| {
+                if !member.joined {
+                    println!(
+                        "Group {} dropped member {}: it didn't rejoin within the rebalance timeout",
+                        self.echo_limits.name(group_id),
+                        self.echo_limits.name(&member.member_id)
+                    );
+                }
+                member.joined
+            });
+        }
+        self.close_round_if_complete(groups, group_id);
+    }
+
+    /// Starts the next generation once every member has joined the open round.
+    fn close_round_if_complete(&self, groups: &mut HashMap<String, Group>, group_id: &str) {
+        let Some(group) = groups.get_mut(group_id) else {
+            return;
+        };
+        if group.round_deadline.is_none() || !group.members.iter().all(|member| member.joined) {
+            return;
+        }
+        group.round_deadline = None;
+        if group.members.is_empty() {
+            // Everyone left; the group goes once its pending ids do too
+            return;
+        }
+        group.generation_id += 1;
+        // Joining counts as being heard from, however long the round took
+        let now = Instant::now();
+        for member in &mut group.members {
+            member.last_heard = now;
+        }
+        if !group
+            .members
+            .iter()
+            .any(|member| Some(&member.member_id) == group.leader_id.as_ref())
+        {
+            group.leader_id = group.members.first().map(|member| member.member_id.clone());
+        }
+        group.protocol_name = group.choose_protocol();
+        println!(
+            "Group {} generation {}: {} member(s), leader {}, protocol {}",
+            self.echo_limits.name(group_id),
+            group.generation_id,
+            group.members.len(),
+            self.echo_limits
+                .name(group.leader_id.as_deref().unwrap_or_default()),
+            self.echo_limits
+                .name(group.protocol_name.as_deref().unwrap_or_default())
+        );
+        self.log_usage(groups);
+        self.changed.notify_all();
+    }
+
+    /// Logs what every group holds between them, after each change to it.
+    fn log_usage(&self, groups: &HashMap<String, Group>) {
+        println!(
+            "Group coordinator holds {} bytes of member metadata and assignments in {} group(s)",
+            groups.values().map(Group::stored_bytes).sum::<usize>(),
+            groups.len()
+        );
+    }
+
+    fn wait<'a>(
+        &self,
+        groups: MutexGuard<'a, HashMap<String, Group>>,
+        timeout: Duration,
+    ) -> MutexGuard<'a, HashMap<String, Group>> {
+        match self.changed.wait_timeout(groups, timeout) {
+            Ok((groups, _)) => groups,
+            Err(poisoned) => poisoned.into_inner().0,
+        }
+    }
+
+    /// A join only ever replaces a member whole, and a sync a generation's
+    /// assignments, so a panic elsewhere can't leave a group half-updated.
+    fn lock(&self) -> MutexGuard<'_, HashMap<String, Group>> {
+        self.groups
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn protocol_bytes(protocols: &[(String, Vec<u8>)]) -> usize {
+    protocols
+        .iter()
+        .map(|(name, metadata)| name.len() + metadata.len())
+        .sum()
+}
+
+/// `<client_id>-<uuid>`, as Kafka hands out.
+fn new_member_id(client_id: Option<&str>) -> String {
+    let uuid = random_uuid();
+    let hex: String = uuid.iter().map(|b| format!("{:02x}", b)).collect();
+    format!(
+        "{}-{}-{}-{}-{}-{}",
+        client_id.unwrap_or("member"),
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
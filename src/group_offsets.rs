@@ -0,0 +1,142 @@
+//! Offsets committed by consumer groups. Like the partition logs they live in
+//! memory only, for as long as the broker process.
+//!
+//! Slots are keyed by topic id rather than name, so commits made against a
+//! deleted topic never carry over to a topic later created again under the
+//! same name, even before the deletion has dropped them.
+//!
+//! Consistency: each (group, topic, partition) is its own slot, and a commit
+//! replaces a slot's value whole. So an OffsetFetch sees every slot either
+//! before or after any commit to it, never a mix of two commits' fields, and
+//! once a commit has been answered every later fetch, on any connection, sees
+//! it or a commit made after it. Nothing is promised across slots: a fetch of
+//! several partitions may see a commit to one but not a commit made at the same
+//! time to another, just as it may in Kafka.
+//!
+//! Commits and fetches of different slots don't wait on each other. Only a
+//! group's first commit to a partition, and a topic deletion dropping its
+//! offsets, take the whole store for a moment.
+
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+use crate::ids::{LeaderEpoch, Offset, PartitionId, TopicId};
+
+/// Where a group has got to in one partition.
+#[derive(Clone)]
+pub struct CommittedOffset {
+    pub offset: Offset,
+    pub leader_epoch: LeaderEpoch,
+    /// Whatever the client stored alongside the offset.
+    pub metadata: Option<String>,
+}
+
+impl fmt::Display for CommittedOffset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "offset {} (leader epoch {}, {} byte(s) of metadata)",
+            self.offset,
+            self.leader_epoch,
+            self.metadata.as_ref().map_or(0, String::len)
+        )
+    }
+}
+
+/// Slots keyed by (group_id, topic, partition).
+type Slots = HashMap<(String, TopicId, PartitionId), Mutex<CommittedOffset>>;
+
+/// Committed offsets of every group, one slot per (group_id, topic, partition).
+#[derive(Default)]
+pub struct GroupOffsets {
+    slots: RwLock<Slots>,
+}
+
+impl GroupOffsets {
+    /// Stores a commit, replacing the slot's previous one. It is visible to
+    /// every `get` that starts after this returns.
+    pub fn commit(
+        &self,
+        group_id: &str,
+        topic_id: TopicId,
+        partition: PartitionId,
+        committed: CommittedOffset,
+    ) {
+        let key = (group_id.to_string(), topic_id, partition);
+        if let Some(slot) = self.read().get(&key) {
+            *lock_slot(slot) = committed;
+            return;
+        }
+        // A first commit: another may have created the slot since the read lock
+        // was dropped, so go through the entry rather than insert over it
+        match self.write().entry(key) {
+            Entry::Occupied(slot) => *lock_slot(slot.get()) = committed,
+            Entry::Vacant(slot) => {
+                slot.insert(Mutex::new(committed));
+            }
+        }
+    }
+
+    /// The slot's latest commit, if the group has made one.
+    pub fn get(
+        &self,
+        group_id: &str,
+        topic_id: TopicId,
+        partition: PartitionId,
+    ) -> Option<CommittedOffset> {
+        let key = (group_id.to_string(), topic_id, partition);
+        self.read().get(&key).map(|slot| lock_slot(slot).clone())
+    }
+
+    /// Every (topic, partition) the group has committed to, in no order.
+    pub fn committed_partitions(&self, group_id: &str) -> Vec<(TopicId, PartitionId)> {
+        self.read()
+            .keys()
+            .filter(|(group, _, _)| group == group_id)
+            .map(|(_, topic_id, partition)| (*topic_id, *partition))
+            .collect()
+    }
+
+    /// Every group that has committed an offset, in group id order.
+    pub fn group_ids(&self) -> Vec<String> {
+        let mut group_ids: Vec<String> = self
+            .read()
+            .keys()
+            .map(|(group_id, _, _)| group_id.clone())
+            .collect();
+        group_ids.sort();
+        group_ids.dedup();
+        group_ids
+    }
+
+    /// Whether the group has committed any offset.
+    pub fn has_group(&self, group_id: &str) -> bool {
+        self.read().keys().any(|(group, _, _)| group == group_id)
+    }
+
+    /// Drops every commit made against the topic with this id.
+    pub fn remove_topic(&self, topic_id: TopicId) {
+        self.write()
+            .retain(|(_, committed_to, _), _| *committed_to != topic_id);
+    }
+
+    // A commit is a single assignment or insert, so a panic elsewhere can't
+    // leave the store or a slot half-updated
+    fn read(&self) -> RwLockReadGuard<'_, Slots> {
+        self.slots
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, Slots> {
+        self.slots
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+fn lock_slot(slot: &Mutex<CommittedOffset>) -> MutexGuard<'_, CommittedOffset> {
+    slot.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+}
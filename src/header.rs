@@ -0,0 +1,128 @@
+use std::fmt;
+
+use crate::codec::{DecodeError, Decoder};
+
+/// Longest client id we keep per request. Anything past this is dropped so a
+/// pathological client id can't inflate per-connection memory or log lines.
+const MAX_CLIENT_ID_LEN: usize = 256;
+
+/// Request header v1: api_key, api_version, correlation_id, client_id.
+pub struct RequestHeader {
+    pub api_key: u16,
+    pub api_version: u16,
+    pub correlation_id: u32,
+    pub client_id: ClientId,
+}
+
+impl RequestHeader {
+    pub fn parse(request_buffer: &[u8]) -> Result<RequestHeader, DecodeError> {
+        let mut decoder = Decoder::new(request_buffer);
+        let api_key = decoder.read_u16()?;
+        let api_version = decoder.read_u16()?;
+        let correlation_id = decoder.read_u32()?;
+        let client_id = ClientId::from_wire(decoder.read_nullable_string_bytes()?);
+
+        Ok(RequestHeader {
+            api_key,
+            api_version,
+            correlation_id,
+            client_id,
+        })
+    }
+
+    /// api_key, api_version and correlation_id: the fixed-size fields every
+    /// request starts with, readable even when the client_id after them isn't.
+    pub fn parse_fixed(request_buffer: &[u8]) -> Result<(u16, u16, u32), DecodeError> {
+        let mut decoder = Decoder::new(request_buffer);
+        Ok((
+            decoder.read_u16()?,
+            decoder.read_u16()?,
+            decoder.read_u32()?,
+        ))
+    }
+
+    /// A decoder positioned at the start of the request body: past the fields
+    /// `parse` reads and, for request header v2, the header tag buffer.
+    pub fn body_decoder(
+        request_buffer: &[u8],
+        header_v2: bool,
+    ) -> Result<Decoder<'_>, DecodeError> {
+        let mut decoder = Decoder::new(request_buffer);
+        decoder.read_bytes(8)?; // api_key, api_version, correlation_id
+        decoder.read_nullable_string_bytes()?; // client_id
+        if header_v2 {
+            decoder.skip_tagged_fields()?;
+        }
+        Ok(decoder)
+    }
+}
+
+/// Client id as sent in the request header.
+///
+/// Null and empty client ids are both stored as anonymous, so anything keyed by
+/// client id puts them in the same bucket. Non-UTF-8 bytes are replaced rather
+/// than rejected, and the value is capped at `MAX_CLIENT_ID_LEN` bytes.
+pub struct ClientId {
+    value: Option<String>,
+    truncated_from: Option<usize>,
+}
+
+impl ClientId {
+    fn from_wire(bytes: Option<&[u8]>) -> ClientId {
+        let bytes = match bytes {
+            Some(bytes) if !bytes.is_empty() => bytes,
+            _ => {
+                return ClientId {
+                    value: None,
+                    truncated_from: None,
+                }
+            }
+        };
+
+        let mut value = String::from_utf8_lossy(bytes).into_owned();
+        let mut truncated_from = None;
+        if value.len() > MAX_CLIENT_ID_LEN {
+            let mut end = MAX_CLIENT_ID_LEN;
+            while !value.is_char_boundary(end) {
+                end -= 1;
+            }
+            value.truncate(end);
+            truncated_from = Some(bytes.len());
+        }
+
+        ClientId {
+            value: Some(value),
+            truncated_from,
+        }
+    }
+
+    /// The stored value; `None` for an anonymous client.
+    pub fn as_str(&self) -> Option<&str> {
+        self.value.as_deref()
+    }
+
+    /// Original length in bytes when the stored value had to be cut short.
+    pub fn truncated_from(&self) -> Option<usize> {
+        self.truncated_from
+    }
+}
+
+/// Log-safe rendering: control characters are escaped so a client id can't
+/// inject newlines or terminal escapes into the server output.
+impl fmt::Display for ClientId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.value {
+            None => write!(f, "<anonymous>"),
+            Some(value) => {
+                for c in value.chars() {
+                    if c.is_control() {
+                        write!(f, "{}", c.escape_default())?;
+                    } else {
+                        write!(f, "{}", c)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    }
+}
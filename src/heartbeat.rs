@@ -0,0 +1,69 @@
+//! Heartbeat (API key 12), versions 0-4.
+//!
+//! Group members heartbeat between rebalances to show they are alive; one not
+//! heard from within its session timeout is dropped by the scheduler and the
+//! rest are told to rejoin with REBALANCE_IN_PROGRESS. See `group_coordinator`.
+//!
+//! v0-v3 use the classic encoding; v4 is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+/// Handles a Heartbeat request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_id = decoder.read_string(flexible)?;
+    let generation_id = decoder.read_i32()?;
+    let member_id = decoder.read_string(flexible)?;
+    if api_version >= 3 {
+        decoder.read_nullable_string(flexible)?; // group_instance_id
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let error_code = broker
+        .groups
+        .heartbeat(&group_id, generation_id, &member_id);
+    let limits = &broker.config.echo_limits;
+    println!(
+        "Heartbeat: group={} generation_id={} member_id={} error_code={}",
+        limits.name(&group_id),
+        generation_id,
+        limits.name(&member_id),
+        error_code
+    );
+    Ok(build_heartbeat_response(
+        header.correlation_id,
+        api_version,
+        error_code,
+    ))
+}
+
+/// HeartbeatResponse: throttle_time_ms (v1+), error_code.
+fn build_heartbeat_response(correlation_id: u32, api_version: i16, error_code: i16) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    response.put_i16(error_code);
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
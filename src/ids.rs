@@ -0,0 +1,116 @@
+//! Typed topic ids, partition indexes, broker ids, leader epochs and offsets.
+//!
+//! On the wire these are all plain INT32s and INT64s, and a partition index
+//! handed over where a node id belongs would still compile as a bare integer.
+//! Wrapping each keeps them apart everywhere inside the broker; requests unwrap
+//! them only where they decode or encode a field. Only the operations that mean
+//! something are provided: offsets advance by a count, but adding two offsets,
+//! or doing arithmetic on an id, doesn't compile.
+
+use std::fmt;
+use std::ops::Add;
+
+/// A topic's UUID, the key the broker knows it by. A topic deleted and created
+/// again under the same name gets a new one, so nothing holding the old id can
+/// reach the new topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct TopicId(pub [u8; 16]);
+
+/// A partition's index within its topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PartitionId(pub i32);
+
+/// A broker's `node.id`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct NodeId(pub i32);
+
+/// The generation of a partition's leadership.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LeaderEpoch(pub i32);
+
+/// A record's position in a partition log.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Offset(pub i64);
+
+impl TopicId {
+    /// Sent where there is no topic id, e.g. for a topic named by an old version.
+    pub const ZERO: TopicId = TopicId([0; 16]);
+
+    /// The id written as 32 hex digits, as `Display` writes it.
+    pub fn from_hex(hex: &str) -> Option<TopicId> {
+        if hex.len() != 32 || !hex.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+            return None;
+        }
+        let mut id = [0; 16];
+        for (i, byte) in id.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16).ok()?;
+        }
+        Some(TopicId(id))
+    }
+}
+
+impl NodeId {
+    pub const fn to_be_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl LeaderEpoch {
+    /// Sent where a partition has no known leader epoch.
+    pub const UNKNOWN: LeaderEpoch = LeaderEpoch(-1);
+
+    pub const fn to_be_bytes(self) -> [u8; 4] {
+        self.0.to_be_bytes()
+    }
+}
+
+impl Offset {
+    /// Sent where there is no offset to report, e.g. for an unknown partition.
+    pub const NONE: Offset = Offset(-1);
+
+    pub const fn to_be_bytes(self) -> [u8; 8] {
+        self.0.to_be_bytes()
+    }
+}
+
+/// Moves an offset `count` records further along the log.
+impl Add<u64> for Offset {
+    type Output = Offset;
+
+    fn add(self, count: u64) -> Offset {
+        Offset(self.0 + count as i64)
+    }
+}
+
+impl fmt::Display for TopicId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for byte in self.0 {
+            write!(f, "{:02x}", byte)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for PartitionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for NodeId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for LeaderEpoch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl fmt::Display for Offset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
@@ -0,0 +1,325 @@
+//! IncrementalAlterConfigs (API key 44), versions 0-1.
+//!
+//! Changes topics' config overrides, one operation per config: SET overrides
+//! it, DELETE drops the override (back to the default in `topic_config`), and
+//! APPEND and SUBTRACT add elements to or remove them from a list config's
+//! value. Only configs `topic_config` knows may be set, each to a value it
+//! accepts, or the resource fails with INVALID_CONFIG; an override the topic
+//! was created with may still be deleted. A resource's operations are applied
+//! all together or, if any of them fails, not at all, and with validate_only
+//! none is applied even then. DescribeConfigs shows the result at once, and
+//! the retention check acts on `retention.ms` from its next run. Alterations
+//! are saved by `ConfigOverrides` before they're applied, and one that can't
+//! be fails with KAFKA_STORAGE_ERROR.
+//!
+//! The broker's configs come from `server.properties` and can't be changed
+//! while it runs, so broker resources are refused with UNSUPPORTED_VERSION.
+//! A resource listed more than once, or a config more than once within one,
+//! fails with INVALID_REQUEST, as in Kafka.
+//!
+//! v0 uses the classic encoding; v1 is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::header::RequestHeader;
+use crate::log_store;
+use crate::throttle::THROTTLE_TIME_LEN;
+use crate::topic_config::{self, ConfigType};
+
+const FIRST_FLEXIBLE_VERSION: i16 = 1;
+
+const TOPIC_RESOURCE: i8 = 2;
+const BROKER_RESOURCE: i8 = 4;
+
+const SET: i8 = 0;
+const DELETE: i8 = 1;
+const APPEND: i8 = 2;
+const SUBTRACT: i8 = 3;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const UNSUPPORTED_VERSION: i16 = 35;
+const INVALID_CONFIG: i16 = 40;
+const INVALID_REQUEST: i16 = 42;
+
+struct AlterableConfig {
+    name: String,
+    operation: i8,
+    value: Option<String>,
+}
+
+struct ResourceRequest {
+    resource_type: i8,
+    resource_name: String,
+    configs: Vec<AlterableConfig>,
+}
+
+struct ResourceResponse {
+    error_code: i16,
+    error_message: Option<String>,
+    resource_type: i8,
+    resource_name: String,
+}
+
+/// Handles an IncrementalAlterConfigs request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let resource_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(resource_count);
+    for _ in 0..resource_count {
+        let resource_type = decoder.read_i8()?;
+        let resource_name = decoder.read_string(flexible)?;
+        let config_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut configs = Vec::with_capacity(config_count);
+        for _ in 0..config_count {
+            let name = decoder.read_string(flexible)?;
+            let operation = decoder.read_i8()?;
+            let value = decoder.read_nullable_string(flexible)?;
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            configs.push(AlterableConfig {
+                name,
+                operation,
+                value,
+            });
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        requests.push(ResourceRequest {
+            resource_type,
+            resource_name,
+            configs,
+        });
+    }
+    let validate_only = decoder.read_i8()? != 0;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    let repeated = duplicates::repeated(
+        requests
+            .iter()
+            .map(|request| (request.resource_type, request.resource_name.as_str())),
+    );
+    let resources: Vec<ResourceResponse> = requests
+        .iter()
+        .map(|request| {
+            let altered =
+                if repeated.contains(&(request.resource_type, request.resource_name.as_str())) {
+                    Err(BrokerError::with_message(
+                        INVALID_REQUEST,
+                        "The resource is listed more than once",
+                    ))
+                } else {
+                    alter_resource(broker, request, validate_only, limits)
+                };
+            let (error_code, error_message) = match altered {
+                Ok(()) => (NONE, None),
+                Err(e) => (e.code, e.message().map(str::to_string)),
+            };
+            ResourceResponse {
+                error_code,
+                error_message,
+                resource_type: request.resource_type,
+                resource_name: request.resource_name.clone(),
+            }
+        })
+        .collect();
+
+    println!(
+        "IncrementalAlterConfigs: validate_only={} resources={}",
+        validate_only,
+        resources.len()
+    );
+    Ok(build_incremental_alter_configs_response(
+        header.correlation_id,
+        api_version,
+        resources,
+        limits,
+    ))
+}
+
+fn alter_resource(
+    broker: &Broker,
+    request: &ResourceRequest,
+    validate_only: bool,
+    limits: &EchoLimits,
+) -> Result<(), BrokerError> {
+    match request.resource_type {
+        TOPIC_RESOURCE => {}
+        BROKER_RESOURCE => {
+            return Err(BrokerError::with_message(
+                UNSUPPORTED_VERSION,
+                "Broker configs come from server.properties and can't be altered at runtime",
+            ))
+        }
+        resource_type => {
+            return Err(BrokerError::with_message(
+                INVALID_REQUEST,
+                format!(
+                    "Resource type {} can't be altered here: only topics ({})",
+                    resource_type, TOPIC_RESOURCE
+                ),
+            ))
+        }
+    }
+    let repeated = duplicates::repeated(request.configs.iter().map(|config| config.name.as_str()));
+    if let Some(name) = repeated.iter().next() {
+        return Err(BrokerError::with_message(
+            INVALID_REQUEST,
+            format!("Config {} is altered more than once", limits.name(name)),
+        ));
+    }
+
+    // Held throughout, so the overrides can't change between reading and
+    // replacing them
+    let mut metadata = broker.metadata_mut();
+    let Some(topic) = metadata.topic(&request.resource_name) else {
+        return Err(BrokerError::new(UNKNOWN_TOPIC_OR_PARTITION));
+    };
+    let topic_id = topic.topic_id;
+    let mut configs = topic.configs.clone();
+    for config in &request.configs {
+        let value = altered_value(&configs, config).map_err(|message| {
+            BrokerError::with_message(
+                INVALID_CONFIG,
+                format!("Invalid config {}: {}", limits.name(&config.name), message),
+            )
+        })?;
+        configs.retain(|(name, _)| *name != config.name);
+        if let Some(value) = value {
+            configs.push((config.name.clone(), Some(value)));
+        }
+    }
+    if !validate_only {
+        // Saved first, so an alteration that's answered is never lost
+        if let Err(e) = broker.config_overrides().save(topic_id, &configs) {
+            println!(
+                "Error saving the config overrides of '{}': {}",
+                request.resource_name, e
+            );
+            return Err(log_store::storage_error());
+        }
+        metadata.set_configs(topic_id, configs);
+    }
+    Ok(())
+}
+
+/// The value `config` leaves its override with, or `None` to drop it; an
+/// error says why the operation can't be applied.
+fn altered_value(
+    configs: &[(String, Option<String>)],
+    config: &AlterableConfig,
+) -> Result<Option<String>, String> {
+    let def = topic_config::lookup(&config.name);
+    if config.operation == DELETE {
+        let overridden = topic_config::override_of(configs, &config.name).is_some();
+        return match def {
+            Some(_) => Ok(None),
+            // Overrides of configs we don't know can be dropped, not set
+            None if overridden => Ok(None),
+            None => Err("unknown config".to_string()),
+        };
+    }
+    let Some(def) = def else {
+        return Err("unknown config".to_string());
+    };
+    let Some(value) = &config.value else {
+        return Err("a value is required".to_string());
+    };
+    let value = match config.operation {
+        SET => value.clone(),
+        APPEND | SUBTRACT if def.config_type != ConfigType::List => {
+            return Err("APPEND and SUBTRACT only apply to list configs".to_string())
+        }
+        APPEND | SUBTRACT => {
+            let current = topic_config::override_of(configs, def.name)
+                .unwrap_or(Some(def.default))
+                .unwrap_or_default();
+            let mut elements: Vec<&str> = split_list(current).collect();
+            for element in split_list(value) {
+                if config.operation == SUBTRACT {
+                    elements.retain(|e| *e != element);
+                } else if !elements.contains(&element) {
+                    elements.push(element);
+                }
+            }
+            elements.join(",")
+        }
+        operation => return Err(format!("unknown operation {}", operation)),
+    };
+    def.check(&value)?;
+    Ok(Some(value))
+}
+
+fn split_list(value: &str) -> impl Iterator<Item = &str> {
+    value.split(',').map(str::trim).filter(|e| !e.is_empty())
+}
+
+/// IncrementalAlterConfigsResponse: throttle_time_ms, responses [error_code,
+/// error_message, resource_type, resource_name].
+///
+/// Resource names come from the request, so they and the number of failed
+/// resources are capped by `limits` before being echoed.
+fn build_incremental_alter_configs_response(
+    correlation_id: u32,
+    api_version: i16,
+    mut resources: Vec<ResourceResponse>,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let dropped = limits.cap_errors(&mut resources, |resource| resource.error_code != NONE);
+    if dropped > 0 {
+        println!("Omitted {} failed resource(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    if flexible {
+        response.put_compact_array_len(resources.len());
+    } else {
+        response.put_array_len(resources.len());
+    }
+    for resource in &resources {
+        let name = limits.name(&resource.resource_name);
+        let error_message = match (&resource.error_message, name.truncation_note()) {
+            (Some(message), Some(note)) => Some(format!("{}; {}", message, note)),
+            (Some(message), None) => Some(message.clone()),
+            (None, note) if resource.error_code != NONE => note,
+            (None, _) => None,
+        };
+        response.put_i16(resource.error_code);
+        if flexible {
+            response.put_compact_nullable_string(error_message.as_deref());
+        } else {
+            response.put_nullable_string(error_message.as_deref());
+        }
+        response.put_u8(resource.resource_type as u8);
+        if flexible {
+            response.put_compact_string(name.as_str());
+            response.put_tag_buffer(); // result TAG_BUFFER
+        } else {
+            response.put_string(name.as_str());
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,92 @@
+//! InitProducerId (API key 22), versions 0-5.
+//!
+//! Idempotent producers call it before their first produce to get the
+//! producer id and epoch their batches carry, which is what lets a partition
+//! log recognise a retried batch. Without a transactional id every request gets
+//! a new producer id, as in Kafka, even one from a producer bumping its epoch
+//! (v3+): the old id's sequence numbers are simply never seen again.
+//! Transactions aren't supported, so a transactional id is refused with
+//! TRANSACTIONAL_ID_AUTHORIZATION_FAILED.
+//!
+//! v0-v1 use the classic encoding; v2+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 2;
+
+const NONE: i16 = 0;
+const TRANSACTIONAL_ID_AUTHORIZATION_FAILED: i16 = 53;
+
+/// Handles an InitProducerId request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let transactional_id = decoder.read_nullable_string(flexible)?;
+    decoder.read_i32()?; // transaction_timeout_ms: nothing is transactional
+    let (previous_producer_id, previous_epoch) = if api_version >= 3 {
+        (decoder.read_i64()?, decoder.read_i16()?)
+    } else {
+        (-1, -1)
+    };
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let (error_code, producer_id, producer_epoch) = match &transactional_id {
+        None => {
+            let producer_id = broker.allocate_producer_id();
+            println!(
+                "InitProducerId: producer_id={} (previously {}, epoch {})",
+                producer_id, previous_producer_id, previous_epoch
+            );
+            (NONE, producer_id, 0)
+        }
+        Some(transactional_id) => {
+            println!(
+                "InitProducerId: refusing transactional_id {}, transactions aren't supported",
+                broker.config.echo_limits.name(transactional_id)
+            );
+            (TRANSACTIONAL_ID_AUTHORIZATION_FAILED, -1, -1)
+        }
+    };
+    Ok(build_init_producer_id_response(
+        header.correlation_id,
+        api_version,
+        error_code,
+        producer_id,
+        producer_epoch,
+    ))
+}
+
+/// InitProducerIdResponse: throttle_time_ms, error_code, producer_id,
+/// producer_epoch.
+fn build_init_producer_id_response(
+    correlation_id: u32,
+    api_version: i16,
+    error_code: i16,
+    producer_id: i64,
+    producer_epoch: i16,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    response.put_i16(error_code);
+    response.put_i64(producer_id);
+    response.put_i16(producer_epoch);
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
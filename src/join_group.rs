@@ -0,0 +1,176 @@
+//! JoinGroup (API key 11), versions 2-9.
+//!
+//! Each member of a consumer group joins on its own connection, and its request
+//! is answered once every member has joined the same round; see
+//! `group_coordinator`. The leader's response carries every member's metadata so
+//! it can work out the assignments. A member sending no member_id is given one;
+//! from v4 it is told to join again with it (MEMBER_ID_REQUIRED) rather than
+//! joining at once.
+//!
+//! v2-v5 use the classic encoding; v6+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::group_coordinator::{JoinRequest, JoinResult};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+/// From this version a member without an id has to join twice.
+const FIRST_KNOWN_MEMBER_ID_VERSION: i16 = 4;
+
+/// Handles a JoinGroup request from a connection with the peer `client_host`.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+    client_host: &str,
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_id = decoder.read_string(flexible)?;
+    let session_timeout_ms = decoder.read_i32()?;
+    let rebalance_timeout_ms = decoder.read_i32()?;
+    let member_id = decoder.read_string(flexible)?;
+    let group_instance_id = if api_version >= 5 {
+        decoder.read_nullable_string(flexible)?
+    } else {
+        None
+    };
+    let protocol_type = decoder.read_string(flexible)?;
+    let protocol_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut protocols = Vec::with_capacity(protocol_count);
+    for _ in 0..protocol_count {
+        let name = decoder.read_string(flexible)?;
+        let metadata = if flexible {
+            decoder.read_compact_nullable_bytes()?
+        } else {
+            decoder.read_nullable_bytes()?
+        };
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        protocols.push((name, metadata.unwrap_or_default().to_vec()));
+    }
+    if api_version >= 8 {
+        decoder.read_nullable_string(flexible)?; // reason, for the broker's logs
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    println!(
+        "JoinGroup: group={} member_id={} protocol_type={} protocols={} session_timeout_ms={} rebalance_timeout_ms={}",
+        limits.name(&group_id),
+        limits.name(&member_id),
+        limits.name(&protocol_type),
+        protocols.len(),
+        session_timeout_ms,
+        rebalance_timeout_ms
+    );
+    let result = broker.groups.join(JoinRequest {
+        group_id,
+        session_timeout_ms,
+        rebalance_timeout_ms,
+        member_id,
+        group_instance_id,
+        protocol_type,
+        protocols,
+        client_id: header.client_id.as_str().map(str::to_string),
+        client_host: client_host.to_string(),
+        require_known_member_id: api_version >= FIRST_KNOWN_MEMBER_ID_VERSION,
+    });
+    println!(
+        "JoinGroup answered: member_id={} generation_id={} error_code={}",
+        limits.name(&result.member_id),
+        result.generation_id,
+        result.error_code
+    );
+    Ok(build_join_group_response(
+        header.correlation_id,
+        api_version,
+        &result,
+        limits,
+    ))
+}
+
+/// JoinGroupResponse: throttle_time_ms, error_code, generation_id,
+/// protocol_type (v7+), protocol_name, leader, skip_assignment (v9+),
+/// member_id, members [member_id, group_instance_id (v5+), metadata].
+///
+/// Group instance ids come from other members' requests, so they are capped by
+/// `limits` like any other echoed name. Member ids and protocol names are
+/// always sent whole: clients send them back.
+fn build_join_group_response(
+    correlation_id: u32,
+    api_version: i16,
+    result: &JoinResult,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_str = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value)
+        } else {
+            response.put_string(value)
+        }
+    };
+    let write_nullable_str = |response: &mut ResponseWriter, value: Option<&str>| {
+        if flexible {
+            response.put_compact_nullable_string(value)
+        } else {
+            response.put_nullable_string(value)
+        }
+    };
+
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    response.put_i16(result.error_code);
+    response.put_i32(result.generation_id);
+    if api_version >= 7 {
+        write_nullable_str(&mut response, result.protocol_type.as_deref());
+        write_nullable_str(&mut response, result.protocol_name.as_deref());
+    } else {
+        write_str(
+            &mut response,
+            result.protocol_name.as_deref().unwrap_or_default(),
+        );
+    }
+    write_str(&mut response, &result.leader);
+    if api_version >= 9 {
+        response.put_u8(0); // skip_assignment: the leader always computes them
+    }
+    write_str(&mut response, &result.member_id);
+    if flexible {
+        response.put_compact_array_len(result.members.len());
+    } else {
+        response.put_array_len(result.members.len());
+    }
+    for member in &result.members {
+        write_str(&mut response, &member.member_id);
+        if api_version >= 5 {
+            let group_instance_id = member
+                .group_instance_id
+                .as_deref()
+                .map(|id| limits.name(id).as_str());
+            write_nullable_str(&mut response, group_instance_id);
+        }
+        if flexible {
+            response.put_compact_bytes(&member.metadata);
+            response.put_tag_buffer(); // member TAG_BUFFER
+        } else {
+            response.put_bytes(&member.metadata);
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
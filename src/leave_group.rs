@@ -0,0 +1,131 @@
+//! LeaveGroup (API key 13), versions 0-5.
+//!
+//! A consumer closing cleanly leaves its group, so the rest rebalance at once
+//! rather than after its session timeout. v0-v2 name one member; from v3 a
+//! request names several, by member id or group instance id, each answered with
+//! its own error code. See `group_coordinator`.
+//!
+//! v0-v3 use the classic encoding; v4+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::group_coordinator::LeavingMember;
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+/// From this version a request carries a members array.
+const FIRST_BATCHED_VERSION: i16 = 3;
+
+/// Handles a LeaveGroup request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_id = decoder.read_string(flexible)?;
+    let mut leaving = Vec::new();
+    if api_version >= FIRST_BATCHED_VERSION {
+        for _ in 0..decoder.read_array_len_or_empty(flexible)? {
+            let member_id = decoder.read_string(flexible)?;
+            let group_instance_id = decoder.read_nullable_string(flexible)?;
+            if api_version >= 5 {
+                decoder.read_nullable_string(flexible)?; // reason, for the broker's logs
+            }
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            leaving.push(LeavingMember {
+                member_id,
+                group_instance_id,
+            });
+        }
+    } else {
+        leaving.push(LeavingMember {
+            member_id: decoder.read_string(flexible)?,
+            group_instance_id: None,
+        });
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    println!(
+        "LeaveGroup: group={} members={}",
+        limits.name(&group_id),
+        leaving.len()
+    );
+    let (mut error_code, member_error_codes) = broker.groups.leave(&group_id, &leaving);
+    if api_version < FIRST_BATCHED_VERSION && error_code == 0 {
+        // The one member's error is the request's
+        error_code = member_error_codes.first().copied().unwrap_or_default();
+    }
+    println!("LeaveGroup answered: error_code={}", error_code);
+    Ok(build_leave_group_response(
+        header.correlation_id,
+        api_version,
+        error_code,
+        &leaving,
+        &member_error_codes,
+        limits,
+    ))
+}
+
+/// LeaveGroupResponse: throttle_time_ms (v1+), error_code, members (v3+)
+/// [member_id, group_instance_id, error_code].
+///
+/// The members are echoed as they were named, so their ids are capped by
+/// `limits`; they are only left out when the whole request failed.
+fn build_leave_group_response(
+    correlation_id: u32,
+    api_version: i16,
+    error_code: i16,
+    leaving: &[LeavingMember],
+    member_error_codes: &[i16],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    response.put_i16(error_code);
+    if api_version >= FIRST_BATCHED_VERSION {
+        if flexible {
+            response.put_compact_array_len(member_error_codes.len());
+        } else {
+            response.put_array_len(member_error_codes.len());
+        }
+        for (member, member_error_code) in leaving.iter().zip(member_error_codes) {
+            let member_id = limits.name(&member.member_id);
+            let group_instance_id = member
+                .group_instance_id
+                .as_deref()
+                .map(|id| limits.name(id).as_str());
+            if flexible {
+                response.put_compact_string(member_id.as_str());
+                response.put_compact_nullable_string(group_instance_id);
+            } else {
+                response.put_string(member_id.as_str());
+                response.put_nullable_string(group_instance_id);
+            }
+            response.put_i16(*member_error_code);
+            if flexible {
+                response.put_tag_buffer(); // member TAG_BUFFER
+            }
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,237 @@
+//! Startup phases, readiness and liveness signals for orchestrators.
+//!
+//! The listener is bound early so clients connecting during startup aren't
+//! refused, but the broker only counts as ready once every startup phase has
+//! completed. Until then every request except ApiVersions is answered with the
+//! retriable COORDINATOR_LOAD_IN_PROGRESS instead of an answer computed from
+//! half-loaded state.
+//!
+//! With `readiness.file` set, that file exists exactly while the broker is
+//! ready: it is written when the last phase completes and removed as soon as
+//! shutdown begins (SIGTERM or SIGINT). With `liveness.file` set, the scheduler
+//! thread rewrites it every `liveness.interval.ms`, after taking the topic
+//! registry lock, so a stale file means the broker is wedged rather than just
+//! idle. Shutting down, the partition logs are flushed before the process exits.
+//!
+//! The scheduler also applies retention every `log.retention.check.interval.ms`,
+//! skipping one check after the wall clock jumps (see `clock`).
+
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::broker::Broker;
+use crate::clock::{Clock, SystemClock, WallClockWatch};
+use crate::config::BrokerConfig;
+
+/// How often the scheduler checks for shutdown, due liveness touches and
+/// retention checks, and group members whose sessions have lapsed.
+const SCHEDULER_TICK: Duration = Duration::from_millis(100);
+
+/// What has to happen before the broker can serve requests. Phases may
+/// complete in any order; the broker is ready once all of them have.
+#[derive(Clone, Copy)]
+pub enum Phase {
+    ConfigLoaded,
+    ListenerBound,
+    MetadataReplayed,
+    LogsRecovered,
+    CoordinatorsLoaded,
+}
+
+const ALL_PHASES: u8 = (1 << 5) - 1;
+
+impl Phase {
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+impl fmt::Display for Phase {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let phase = match self {
+            Phase::ConfigLoaded => "configuration loaded",
+            Phase::ListenerBound => "listener bound",
+            Phase::MetadataReplayed => "metadata log replayed",
+            Phase::LogsRecovered => "log directories recovered",
+            Phase::CoordinatorsLoaded => "coordinators loaded",
+        };
+        write!(f, "{}", phase)
+    }
+}
+
+/// Tracks startup progress and owns the readiness file.
+pub struct Lifecycle {
+    completed: AtomicU8,
+    shutting_down: AtomicBool,
+    readiness_file: Option<PathBuf>,
+}
+
+impl Lifecycle {
+    pub fn new(readiness_file: Option<PathBuf>) -> Lifecycle {
+        Lifecycle {
+            completed: AtomicU8::new(0),
+            shutting_down: AtomicBool::new(false),
+            readiness_file,
+        }
+    }
+
+    /// Records a finished phase, becoming ready if it was the last one.
+    pub fn complete(&self, phase: Phase) {
+        let before = self.completed.fetch_or(phase.bit(), Ordering::SeqCst);
+        println!("Startup phase complete: {}", phase);
+        if before != ALL_PHASES && before | phase.bit() == ALL_PHASES {
+            println!("Broker is ready");
+            if let Some(path) = &self.readiness_file {
+                if let Err(e) = fs::write(path, b"ready\n") {
+                    println!("Error writing readiness file {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.completed.load(Ordering::SeqCst) == ALL_PHASES
+            && !self.shutting_down.load(Ordering::SeqCst)
+    }
+
+    /// Stops reporting ready, first on the wire and then to the orchestrator.
+    pub fn begin_shutdown(&self) {
+        self.shutting_down.store(true, Ordering::SeqCst);
+        if let Some(path) = &self.readiness_file {
+            match fs::remove_file(path) {
+                Ok(()) => {}
+                Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+                Err(e) => println!("Error removing readiness file {}: {}", path.display(), e),
+            }
+        }
+    }
+}
+
+static SHUTDOWN_REQUESTED: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+mod signals {
+    use std::os::raw::c_int;
+    use std::sync::atomic::Ordering;
+
+    const SIGINT: c_int = 2;
+    const SIGTERM: c_int = 15;
+
+    extern "C" {
+        fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+    }
+
+    /// Only sets a flag: anything more isn't async-signal-safe. The scheduler
+    /// thread acts on it.
+    extern "C" fn request_shutdown(_signum: c_int) {
+        super::SHUTDOWN_REQUESTED.store(true, Ordering::SeqCst);
+    }
+
+    pub fn install() {
+        // SAFETY: the handler only stores to an atomic, which is async-signal-safe
+        unsafe {
+            signal(SIGINT, request_shutdown);
+            signal(SIGTERM, request_shutdown);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+mod signals {
+    pub fn install() {}
+}
+
+/// Starts the scheduler thread: it handles shutdown signals and runs a
+/// `Scheduler` on the system clock. Takes over SIGTERM and SIGINT, so it must
+/// be running for the process to stop on them.
+pub fn spawn_scheduler(broker: Arc<Broker>) {
+    signals::install();
+    thread::spawn(move || {
+        let mut scheduler = Scheduler::new(&broker.config, Arc::new(SystemClock));
+        loop {
+            if SHUTDOWN_REQUESTED.load(Ordering::SeqCst) {
+                println!("Shutdown requested, no longer ready");
+                broker.lifecycle.begin_shutdown();
+                if let Err(e) = broker.logs().flush() {
+                    println!("Error flushing partition logs: {}", e);
+                }
+                for line in broker.clients.lines() {
+                    println!("{}", line);
+                }
+                std::process::exit(0);
+            }
+            scheduler.tick(&broker);
+            thread::sleep(SCHEDULER_TICK);
+        }
+    });
+}
+
+/// The broker's periodic work, timed on monotonic time: keeping the liveness
+/// file fresh, deleting records past their retention, expiring group members
+/// that stopped heartbeating and aging out the client inventory.
+pub struct Scheduler {
+    wall_clock: WallClockWatch,
+    next_liveness: Instant,
+    next_retention_check: Instant,
+    /// The wall clock jumped since the last retention check.
+    clock_jumped: bool,
+}
+
+impl Scheduler {
+    pub fn new(config: &BrokerConfig, clock: Arc<dyn Clock>) -> Scheduler {
+        Scheduler {
+            wall_clock: WallClockWatch::new(clock),
+            next_liveness: Instant::now(),
+            next_retention_check: Instant::now() + config.retention_check_interval,
+            clock_jumped: false,
+        }
+    }
+
+    /// Does whatever has come due since the last tick.
+    pub fn tick(&mut self, broker: &Broker) {
+        let config = &broker.config;
+        let wall_clock = self.wall_clock.read();
+        if let Some(jump_ms) = wall_clock.jump_ms {
+            let direction = if jump_ms < 0 { "back" } else { "forward" };
+            println!(
+                "WARNING: the wall clock jumped {} by {} ms; skipping the next retention check",
+                direction,
+                jump_ms.unsigned_abs()
+            );
+            self.clock_jumped = true;
+        }
+        if let Some(path) = &config.liveness_file {
+            if Instant::now() >= self.next_liveness {
+                // Every request that names a topic takes this lock; taking
+                // it shows none of them is stuck holding it
+                drop(broker.metadata());
+                touch(path, wall_clock.now_ms);
+                self.next_liveness = Instant::now() + config.liveness_interval;
+            }
+        }
+        if Instant::now() >= self.next_retention_check {
+            if self.clock_jumped {
+                println!("Retention check skipped after a wall clock jump");
+                self.clock_jumped = false;
+            } else {
+                broker.enforce_retention(wall_clock.now_ms);
+            }
+            self.next_retention_check = Instant::now() + config.retention_check_interval;
+        }
+        broker.groups.expire_members();
+        broker.clients.expire();
+    }
+}
+
+/// Rewrites the liveness file with the current time, updating its mtime.
+fn touch(path: &Path, now_ms: i64) {
+    if let Err(e) = fs::write(path, format!("{}\n", now_ms)) {
+        println!("Error writing liveness file {}: {}", path.display(), e);
+    }
+}
@@ -0,0 +1,115 @@
+//! ListGroups (API key 16), versions 0-4.
+//!
+//! Lists every group the broker knows, with its protocol type and (v4+) its
+//! state, as `kafka-consumer-groups.sh --list` shows them: those with members,
+//! or members-to-be, in the group coordinator, and those with only committed
+//! offsets, which are Empty. A v4 states_filter keeps only the groups in the
+//! states it names, matched regardless of case as in Kafka; an empty one keeps
+//! them all.
+//!
+//! v0-v2 use the classic encoding; v3+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::group_coordinator::{GroupListing, GroupState};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 3;
+
+const NONE: i16 = 0;
+
+/// Handles a ListGroups request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let mut states_filter = Vec::new();
+    if api_version >= 4 {
+        let count = decoder.read_array_len_or_empty(true)?;
+        for _ in 0..count {
+            states_filter.push(decoder.read_compact_string()?);
+        }
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let mut groups = broker.groups.list();
+    for group_id in broker.group_offsets().group_ids() {
+        if groups.iter().all(|group| group.group_id != group_id) {
+            groups.push(GroupListing {
+                group_id,
+                protocol_type: String::new(),
+                state: GroupState::Empty,
+            });
+        }
+    }
+    groups.sort_by(|a, b| a.group_id.cmp(&b.group_id));
+    groups.retain(|group| {
+        states_filter.is_empty()
+            || states_filter
+                .iter()
+                .any(|state| state.eq_ignore_ascii_case(group.state.name()))
+    });
+
+    println!(
+        "ListGroups: states_filter={:?} groups={}",
+        states_filter,
+        groups.len()
+    );
+    Ok(build_list_groups_response(
+        header.correlation_id,
+        api_version,
+        &groups,
+    ))
+}
+
+/// ListGroupsResponse: throttle_time_ms (v1+), error_code, groups [group_id,
+/// protocol_type, group_state (v4+)].
+fn build_list_groups_response(
+    correlation_id: u32,
+    api_version: i16,
+    groups: &[GroupListing],
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let write = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value);
+        } else {
+            response.put_string(value);
+        }
+    };
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    response.put_i16(NONE);
+    if flexible {
+        response.put_compact_array_len(groups.len());
+    } else {
+        response.put_array_len(groups.len());
+    }
+    for group in groups {
+        write(&mut response, &group.group_id);
+        write(&mut response, &group.protocol_type);
+        if api_version >= 4 {
+            write(&mut response, group.state.name());
+        }
+        if flexible {
+            response.put_tag_buffer(); // group TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,239 @@
+//! ListOffsets (API key 2), versions 1-7.
+//!
+//! Resolves a timestamp to an offset in a partition log, which is how consumers
+//! turn "latest" or "earliest" into a position to fetch from. A timestamp of -1
+//! asks for the log end offset, -2 for the log start offset and (v7+) -3 for the
+//! record with the largest timestamp; anything else for the first record whose
+//! timestamp is at least the one given. A partition listed more than once is
+//! answered with INVALID_REQUEST for every occurrence, as Kafka does.
+//!
+//! v1-v5 use the classic encoding; v6+ are flexible.
+
+use crate::broker::{Broker, LEADER_EPOCH};
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::header::RequestHeader;
+use crate::ids::{LeaderEpoch, Offset, PartitionId};
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+
+const LATEST_TIMESTAMP: i64 = -1;
+const EARLIEST_TIMESTAMP: i64 = -2;
+const MAX_TIMESTAMP: i64 = -3;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const INVALID_REQUEST: i16 = 42;
+
+struct PartitionResponse {
+    partition: PartitionId,
+    error_code: i16,
+    timestamp: i64,
+    offset: Offset,
+    leader_epoch: LeaderEpoch,
+}
+
+impl PartitionResponse {
+    fn error(partition: PartitionId, error_code: i16) -> PartitionResponse {
+        PartitionResponse {
+            partition,
+            error_code,
+            timestamp: -1,
+            offset: Offset::NONE,
+            leader_epoch: LeaderEpoch::UNKNOWN,
+        }
+    }
+}
+
+struct TopicResponse {
+    name: String,
+    partitions: Vec<PartitionResponse>,
+}
+
+/// Handles a ListOffsets request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let replica_id = decoder.read_i32()?;
+    if api_version >= 2 {
+        decoder.read_i8()?; // isolation_level: nothing is transactional
+    }
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let name = decoder.read_string(flexible)?;
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            let partition = PartitionId(decoder.read_i32()?);
+            if api_version >= 4 {
+                decoder.read_i32()?; // current_leader_epoch
+            }
+            let timestamp = decoder.read_i64()?;
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            partitions.push((partition, timestamp));
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        requests.push((name, partitions));
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let repeated = duplicates::repeated(requests.iter().flat_map(|(name, partitions)| {
+        partitions
+            .iter()
+            .map(move |&(partition, _)| (name.as_str(), partition))
+    }));
+    let topics: Vec<TopicResponse> = requests
+        .iter()
+        .map(|(name, partitions)| TopicResponse {
+            name: name.clone(),
+            partitions: partitions
+                .iter()
+                .map(|&(partition, timestamp)| {
+                    if repeated.contains(&(name.as_str(), partition)) {
+                        PartitionResponse::error(partition, INVALID_REQUEST)
+                    } else {
+                        list_partition(broker, name, partition, timestamp)
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+
+    println!(
+        "ListOffsets: replica_id={} topics={}",
+        replica_id,
+        topics.len()
+    );
+    Ok(build_list_offsets_response(
+        header.correlation_id,
+        api_version,
+        &topics,
+        &broker.config.echo_limits,
+    ))
+}
+
+fn list_partition(
+    broker: &Broker,
+    topic: &str,
+    partition: PartitionId,
+    timestamp: i64,
+) -> PartitionResponse {
+    let topic_id = broker
+        .metadata()
+        .topic(topic)
+        .filter(|metadata| metadata.partitions.contains(&partition))
+        .map(|metadata| metadata.topic_id);
+    let Some(topic_id) = topic_id else {
+        return PartitionResponse::error(partition, UNKNOWN_TOPIC_OR_PARTITION);
+    };
+
+    let logs = broker.logs();
+    let found = match timestamp {
+        LATEST_TIMESTAMP => logs
+            .offsets(topic_id, partition)
+            .map(|offsets| Some((-1, offsets.log_end_offset))),
+        EARLIEST_TIMESTAMP => logs
+            .offsets(topic_id, partition)
+            .map(|offsets| Some((-1, offsets.log_start_offset))),
+        MAX_TIMESTAMP => logs.max_timestamp_offset(topic_id, partition),
+        target => logs.offset_for_timestamp(topic_id, partition, target),
+    };
+    let found = match found {
+        Ok(found) => found,
+        Err(e) => {
+            println!("ListOffsets on {}-{} failed: {}", topic, partition, e);
+            return PartitionResponse::error(partition, e.to_broker_error().code);
+        }
+    };
+    // No record that late: Kafka answers with no error and offset -1
+    let (timestamp, offset) = found.unwrap_or((-1, Offset::NONE));
+    PartitionResponse {
+        partition,
+        error_code: NONE,
+        timestamp,
+        offset,
+        leader_epoch: LEADER_EPOCH,
+    }
+}
+
+/// ListOffsetsResponse: throttle_time_ms (v2+), topics [name, partitions
+/// [partition_index, error_code, timestamp, offset, leader_epoch (v4+)]].
+///
+/// Topic names and error partitions come from the request, so both are capped
+/// by `limits` before being echoed.
+fn build_list_offsets_response(
+    correlation_id: u32,
+    api_version: i16,
+    topics: &[TopicResponse],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+
+    if api_version >= 2 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    write_len(&mut response, topics.len());
+    for topic in topics {
+        let name = limits.name(&topic.name);
+        if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        let mut partitions: Vec<&PartitionResponse> = topic.partitions.iter().collect();
+        let dropped = limits.cap_errors(&mut partitions, |partition| partition.error_code != NONE);
+        if dropped > 0 {
+            println!(
+                "Omitted {} error partition(s) of topic {} from the response",
+                dropped, name
+            );
+        }
+        write_len(&mut response, partitions.len());
+        for partition in partitions {
+            response.put_i32(partition.partition.0);
+            response.put_i16(partition.error_code);
+            response.put_i64(partition.timestamp);
+            response.put_i64(partition.offset.0);
+            if api_version >= 4 {
+                response.put_i32(partition.leader_epoch.0);
+            }
+            if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
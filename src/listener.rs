@@ -0,0 +1,388 @@
+//! Listeners: the addresses the broker accepts connections on, and the policy
+//! each one serves them under.
+//!
+//! Every `listeners` entry is bound, as `NAME://host:port`, but those in
+//! `controller.listener.names`: there is no controller quorum. The security
+//! protocol comes from `listener.security.protocol.map` (`NAME:PROTOCOL`
+//! pairs), or is the name itself for listeners named after one. PLAINTEXT
+//! listeners serve connections as they come; SASL_PLAINTEXT ones serve nothing
+//! but ApiVersions and the SASL APIs until the connection has authenticated.
+//! There is no TLS, so SSL and SASL_SSL listeners are refused at startup.
+//!
+//! Beyond that, each listener may be restricted by properties under
+//! `listener.name.<name>.`, with the name in lower case as in Kafka:
+//!
+//! - `admin.apis.enabled=false` refuses the APIs that change topics, their
+//!   configs and their data (CreateTopics, DeleteTopics, CreatePartitions,
+//!   DeleteRecords, IncrementalAlterConfigs) with CLUSTER_AUTHORIZATION_FAILED.
+//! - `allowed.apis` lists the only APIs served, by name: the rest are neither
+//!   advertised in ApiVersions nor answered other than as unsupported.
+//! - `allowed.cidrs` lists the networks connections may come from; others are
+//!   closed as soon as they are accepted.
+//!
+//! Every check is made before the request reaches its handler, so a refused
+//! request changes nothing. Each listener counts its connections and requests,
+//! what it refused of them, each API's requests in and out of its version range,
+//! and the responses it sent and the writes they took, in its `ListenerStats`.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::net::IpAddr;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use anyhow::{bail, Context, Result};
+
+use crate::SUPPORTED_APIS;
+
+/// How a listener's connections are secured.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SecurityProtocol {
+    Plaintext,
+    SaslPlaintext,
+}
+
+impl SecurityProtocol {
+    fn parse(value: &str) -> Result<SecurityProtocol> {
+        match value {
+            "PLAINTEXT" => Ok(SecurityProtocol::Plaintext),
+            "SASL_PLAINTEXT" => Ok(SecurityProtocol::SaslPlaintext),
+            "SSL" | "SASL_SSL" => bail!("{} listeners aren't supported: there is no TLS", value),
+            _ => bail!("unknown security protocol '{}'", value),
+        }
+    }
+}
+
+impl fmt::Display for SecurityProtocol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityProtocol::Plaintext => write!(f, "PLAINTEXT"),
+            SecurityProtocol::SaslPlaintext => write!(f, "SASL_PLAINTEXT"),
+        }
+    }
+}
+
+/// One `listeners` entry.
+pub struct Listener {
+    pub name: String,
+    /// Address bound. An empty host in the entry means the loopback interface.
+    pub address: String,
+    pub protocol: SecurityProtocol,
+    pub policy: ListenerPolicy,
+}
+
+impl Listener {
+    pub fn plaintext(address: String) -> Listener {
+        Listener {
+            name: "PLAINTEXT".to_string(),
+            address,
+            protocol: SecurityProtocol::Plaintext,
+            policy: ListenerPolicy::default(),
+        }
+    }
+
+    /// Whether connections must authenticate before they are served.
+    pub fn requires_authentication(&self) -> bool {
+        self.protocol == SecurityProtocol::SaslPlaintext
+    }
+}
+
+/// What a listener serves. The default serves everything, from anywhere.
+pub struct ListenerPolicy {
+    pub admin_apis: bool,
+    /// API keys served; `None` for all of them.
+    pub allowed_apis: Option<Vec<u16>>,
+    /// Networks connections may come from; `None` for any.
+    pub allowed_cidrs: Option<Vec<Cidr>>,
+}
+
+impl Default for ListenerPolicy {
+    fn default() -> Self {
+        ListenerPolicy {
+            admin_apis: true,
+            allowed_apis: None,
+            allowed_cidrs: None,
+        }
+    }
+}
+
+impl ListenerPolicy {
+    pub fn allows_api(&self, api_key: u16) -> bool {
+        self.allowed_apis
+            .as_ref()
+            .map_or(true, |allowed| allowed.contains(&api_key))
+    }
+
+    pub fn allows_peer(&self, peer: IpAddr) -> bool {
+        self.allowed_cidrs.as_ref().map_or(true, |allowed| {
+            allowed.iter().any(|cidr| cidr.contains(peer))
+        })
+    }
+}
+
+/// A network, as `address/prefix_len`.
+#[derive(Clone, Copy, Debug)]
+pub struct Cidr {
+    network: IpAddr,
+    prefix_len: u32,
+}
+
+impl Cidr {
+    /// `10.0.0.0/8`, `::1/128`, or a bare address for just that one.
+    fn parse(value: &str) -> Option<Cidr> {
+        let (address, prefix_len) = match value.split_once('/') {
+            Some((address, prefix_len)) => (address, Some(prefix_len.parse().ok()?)),
+            None => (value, None),
+        };
+        let network: IpAddr = address.parse().ok()?;
+        let bits = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = prefix_len.unwrap_or(bits);
+        (prefix_len <= bits).then_some(Cidr {
+            network,
+            prefix_len,
+        })
+    }
+
+    fn contains(&self, address: IpAddr) -> bool {
+        // An IPv4 client reaching a dual-stack socket shows up IPv4-mapped
+        let address = match address {
+            IpAddr::V6(v6) => v6.to_ipv4_mapped().map_or(address, IpAddr::V4),
+            v4 => v4,
+        };
+        match (self.network, address) {
+            (IpAddr::V4(network), IpAddr::V4(address)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix_len).unwrap_or(0);
+                u32::from(network) & mask == u32::from(address) & mask
+            }
+            (IpAddr::V6(network), IpAddr::V6(address)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix_len).unwrap_or(0);
+                u128::from(network) & mask == u128::from(address) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// How much traffic a listener has had, for attributing it.
+pub struct ListenerStats {
+    connections: AtomicU64,
+    refused_connections: AtomicU64,
+    requests: AtomicU64,
+    refused_requests: AtomicU64,
+    responses: AtomicU64,
+    /// Writes the responses went out in, fewer where several were coalesced.
+    response_writes: AtomicU64,
+    /// Requests whose api_version was in the API's advertised range, and those
+    /// whose wasn't, in `SUPPORTED_APIS` order.
+    version_checks: Vec<[AtomicU64; 2]>,
+}
+
+impl Default for ListenerStats {
+    fn default() -> Self {
+        ListenerStats {
+            connections: AtomicU64::default(),
+            refused_connections: AtomicU64::default(),
+            requests: AtomicU64::default(),
+            refused_requests: AtomicU64::default(),
+            responses: AtomicU64::default(),
+            response_writes: AtomicU64::default(),
+            version_checks: SUPPORTED_APIS.iter().map(|_| Default::default()).collect(),
+        }
+    }
+}
+
+impl ListenerStats {
+    pub fn connection(&self, refused: bool) {
+        self.connections.fetch_add(1, Ordering::Relaxed);
+        if refused {
+            self.refused_connections.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    pub fn request(&self, refused: bool) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        if refused {
+            self.refused_requests.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts a request to `api_key` by whether its api_version was in range.
+    pub fn version_checked(&self, api_key: u16, in_range: bool) {
+        if let Some(index) = SUPPORTED_APIS.iter().position(|api| api.api_key == api_key) {
+            self.version_checks[index][usize::from(!in_range)].fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counts `responses` sent in a single write.
+    pub fn responses_written(&self, responses: usize) {
+        self.responses
+            .fetch_add(responses as u64, Ordering::Relaxed);
+        self.response_writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The responses sent so far, and the writes they took.
+    pub fn responses(&self) -> (u64, u64) {
+        (
+            self.responses.load(Ordering::Relaxed),
+            self.response_writes.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl fmt::Display for ListenerStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "connections={} refused_connections={} requests={} refused_requests={} \
+             responses={} response_writes={}",
+            self.connections.load(Ordering::Relaxed),
+            self.refused_connections.load(Ordering::Relaxed),
+            self.requests.load(Ordering::Relaxed),
+            self.refused_requests.load(Ordering::Relaxed),
+            self.responses.load(Ordering::Relaxed),
+            self.response_writes.load(Ordering::Relaxed)
+        )?;
+        // As Name:in_range/out_of_range, for the APIs asked for at all
+        let mut checked = SUPPORTED_APIS
+            .iter()
+            .zip(&self.version_checks)
+            .map(|(api, [in_range, out_of_range])| {
+                let counts = (
+                    in_range.load(Ordering::Relaxed),
+                    out_of_range.load(Ordering::Relaxed),
+                );
+                (api.name, counts)
+            })
+            .filter(|(_, counts)| *counts != (0, 0))
+            .peekable();
+        if checked.peek().is_none() {
+            return write!(f, " version_checks=none");
+        }
+        for (index, (name, (in_range, out_of_range))) in checked.enumerate() {
+            let separator = if index == 0 { " version_checks=" } else { "," };
+            write!(f, "{}{}:{}/{}", separator, name, in_range, out_of_range)?;
+        }
+        Ok(())
+    }
+}
+
+/// The listeners `listeners` names, with their protocols and policies, or
+/// `None` when it isn't set. `default_host` stands in for an empty host.
+/// Listeners in `controller.listener.names` are left out: there is no
+/// controller quorum for them to serve.
+pub fn from_properties(
+    properties: &HashMap<String, String>,
+    default_host: &str,
+) -> Result<Option<Vec<Listener>>> {
+    let Some(value) = properties.get("listeners") else {
+        return Ok(None);
+    };
+    let protocols = match properties.get("listener.security.protocol.map") {
+        Some(map) => parse_protocol_map(map)
+            .with_context(|| format!("invalid listener.security.protocol.map '{}'", map))?,
+        None => HashMap::new(),
+    };
+    let controller_listeners: Vec<&str> = properties
+        .get("controller.listener.names")
+        .map_or_else(Vec::new, |names| names.split(',').map(str::trim).collect());
+
+    let mut listeners: Vec<Listener> = Vec::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, host, port) =
+            parse_entry(entry).with_context(|| format!("invalid listeners entry '{}'", entry))?;
+        if listeners.iter().any(|listener| listener.name == name) {
+            bail!("listener {} is named more than once in listeners", name);
+        }
+        if controller_listeners.contains(&name.as_str()) {
+            continue;
+        }
+        let protocol = match protocols.get(name.as_str()) {
+            Some(protocol) => SecurityProtocol::parse(protocol),
+            None => SecurityProtocol::parse(&name).with_context(|| {
+                format!(
+                    "listener {} has no listener.security.protocol.map entry",
+                    name
+                )
+            }),
+        }
+        .with_context(|| format!("invalid security protocol for listener {}", name))?;
+        let host = if host.is_empty() { default_host } else { &host };
+        let policy = parse_policy(properties, &name)?;
+        listeners.push(Listener {
+            address: format!("{}:{}", host, port),
+            name,
+            protocol,
+            policy,
+        });
+    }
+    if listeners.is_empty() {
+        bail!(
+            "invalid listeners '{}': no listener outside controller.listener.names",
+            value
+        );
+    }
+    Ok(Some(listeners))
+}
+
+/// `NAME://host:port`, the host maybe empty or a bracketed IPv6 address.
+fn parse_entry(entry: &str) -> Option<(String, String, u16)> {
+    let (name, address) = entry.split_once("://")?;
+    let (host, port) = address.rsplit_once(':')?;
+    let port = port.parse().ok().filter(|port| *port != 0)?;
+    let host = host.trim_start_matches('[').trim_end_matches(']');
+    (!name.is_empty()).then(|| (name.to_string(), host.to_string(), port))
+}
+
+/// Protocol names by listener name, each checked only if its listener is
+/// bound: stock configs map SSL listeners they don't have.
+fn parse_protocol_map(value: &str) -> Result<HashMap<&str, &str>> {
+    let mut protocols = HashMap::new();
+    for entry in value.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let Some((name, protocol)) = entry.split_once(':') else {
+            bail!("entry '{}' isn't NAME:PROTOCOL", entry);
+        };
+        protocols.insert(name, protocol);
+    }
+    Ok(protocols)
+}
+
+fn parse_policy(properties: &HashMap<String, String>, name: &str) -> Result<ListenerPolicy> {
+    let prefix = format!("listener.name.{}.", name.to_lowercase());
+    let get = |key: &str| {
+        let key = format!("{}{}", prefix, key);
+        properties.get(&key).map(|value| (key, value))
+    };
+
+    let mut policy = ListenerPolicy::default();
+    if let Some((key, value)) = get("admin.apis.enabled") {
+        policy.admin_apis = match value.as_str() {
+            "true" => true,
+            "false" => false,
+            _ => bail!("invalid {} '{}', expected true or false", key, value),
+        };
+    }
+    if let Some((key, value)) = get("allowed.apis") {
+        let apis = value
+            .split(',')
+            .map(str::trim)
+            .filter(|api| !api.is_empty())
+            .map(|api| {
+                SUPPORTED_APIS
+                    .iter()
+                    .find(|supported| supported.name == api)
+                    .map(|supported| supported.api_key)
+                    .with_context(|| format!("unknown API '{}' in {}", api, key))
+            })
+            .collect::<Result<Vec<u16>>>()?;
+        policy.allowed_apis = Some(apis);
+    }
+    if let Some((key, value)) = get("allowed.cidrs") {
+        let cidrs = value
+            .split(',')
+            .map(str::trim)
+            .filter(|cidr| !cidr.is_empty())
+            .map(|cidr| Cidr::parse(cidr).with_context(|| format!("invalid {} '{}'", key, cidr)))
+            .collect::<Result<Vec<Cidr>>>()?;
+        policy.allowed_cidrs = Some(cidrs);
+    }
+    Ok(policy)
+}
@@ -0,0 +1,276 @@
+//! One partition's log, as held in memory. Every `LogStore` keeps its
+//! partitions in these; the file-backed one also writes each batch through to
+//! disk and restores them from there at startup.
+
+use std::collections::{HashMap, VecDeque};
+
+use crate::ids::Offset;
+use crate::record_batch::RecordBatch;
+
+/// How many recent batches are remembered per producer for duplicate detection.
+/// Idempotent producers keep at most this many requests in flight, so a retry
+/// always matches one of them.
+const PRODUCER_BATCH_HISTORY: usize = 5;
+
+/// Where a producer's batch ended up, so a retry of it can be answered with the
+/// offset of the original.
+struct ProducerBatch {
+    epoch: i16,
+    base_sequence: i32,
+    last_offset_delta: i32,
+    base_offset: Offset,
+}
+
+pub struct Appended {
+    pub base_offset: Offset,
+    /// The batch repeats one already in the log (an idempotent producer retrying
+    /// after losing the response) and was not appended again.
+    pub duplicate: bool,
+}
+
+struct StoredBatch {
+    last_offset: Offset,
+    max_timestamp: i64,
+    /// As written to the wire, base_offset rewritten to ours.
+    bytes: Vec<u8>,
+}
+
+/// The batches appended to one topic-partition, in offset order.
+#[derive(Default)]
+pub struct PartitionLog {
+    batches: Vec<StoredBatch>,
+    /// Records before this have been deleted by DeleteRecords, though the
+    /// batch holding it may still hold some of them.
+    start_offset: Offset,
+    next_offset: Offset,
+    producers: HashMap<i64, VecDeque<ProducerBatch>>,
+}
+
+impl PartitionLog {
+    /// An empty log whose first record will get `offset`, for storage whose
+    /// earlier records are gone.
+    pub fn starting_at(offset: Offset) -> PartitionLog {
+        PartitionLog {
+            start_offset: offset,
+            next_offset: offset,
+            ..PartitionLog::default()
+        }
+    }
+
+    /// Appends a batch and returns the offset assigned to its first record. The
+    /// producer's base_offset is meaningless, so it is overwritten with ours.
+    pub fn append(&mut self, batch: &RecordBatch) -> Appended {
+        if let Some(original) = self.find_duplicate(batch) {
+            return Appended {
+                base_offset: original,
+                duplicate: true,
+            };
+        }
+
+        let base_offset = self.next_offset;
+        let mut bytes = batch.bytes.to_vec();
+        bytes[..8].copy_from_slice(&base_offset.to_be_bytes());
+        // A negative delta is nonsense; such a batch still takes one offset
+        let last_offset = base_offset + batch.last_offset_delta.max(0) as u64;
+        self.batches.push(StoredBatch {
+            last_offset,
+            max_timestamp: batch.max_timestamp,
+            bytes,
+        });
+        self.next_offset = last_offset + 1;
+        self.remember(batch, base_offset);
+        Appended {
+            base_offset,
+            duplicate: false,
+        }
+    }
+
+    /// Puts back a batch read from storage, at the offsets it was stored with.
+    /// Returns false, leaving the log as it was, if the batch doesn't start at
+    /// the log end: whatever it was read from is damaged from there on.
+    ///
+    /// The producer's history is rebuilt from it as `append` builds it, so an
+    /// idempotent producer retrying across a restart still gets the original's
+    /// offset rather than a second copy.
+    pub fn restore(&mut self, batch: &RecordBatch) -> bool {
+        if batch.base_offset != self.next_offset {
+            return false;
+        }
+        let last_offset = batch.base_offset + batch.last_offset_delta.max(0) as u64;
+        self.batches.push(StoredBatch {
+            last_offset,
+            max_timestamp: batch.max_timestamp,
+            bytes: batch.bytes.to_vec(),
+        });
+        self.next_offset = last_offset + 1;
+        self.remember(batch, batch.base_offset);
+        true
+    }
+
+    /// How many producers have batches remembered.
+    pub fn producer_count(&self) -> usize {
+        self.producers.len()
+    }
+
+    /// The highest producer id with a batch remembered.
+    pub fn max_producer_id(&self) -> Option<i64> {
+        self.producers.keys().copied().max()
+    }
+
+    /// The last batch appended, as stored: base_offset rewritten to ours.
+    pub fn last_batch(&self) -> Option<&[u8]> {
+        self.batches.last().map(|batch| batch.bytes.as_slice())
+    }
+
+    /// Drops every batch from `offset` on, and the producer history they left,
+    /// undoing appends that couldn't be written to storage.
+    pub fn truncate(&mut self, offset: Offset) {
+        let kept = self
+            .batches
+            .partition_point(|batch| batch.last_offset < offset);
+        self.batches.truncate(kept);
+        self.next_offset = self.next_offset.min(offset);
+        for history in self.producers.values_mut() {
+            history.retain(|batch| batch.base_offset < offset);
+        }
+        self.producers.retain(|_, history| !history.is_empty());
+    }
+
+    /// Deletes the records before `offset`, which must be at most the log end
+    /// offset, and returns the log start offset after. Batches wholly below it
+    /// are dropped; one straddling it is kept whole, and reads return it whole,
+    /// as Kafka does. The log start offset never moves back.
+    pub fn delete_before(&mut self, offset: Offset) -> Offset {
+        self.start_offset = self.start_offset.max(offset.min(self.next_offset));
+        let deleted = self
+            .batches
+            .partition_point(|batch| batch.last_offset < self.start_offset);
+        self.batches.drain(..deleted);
+        self.start_offset
+    }
+
+    /// Adds a batch at `base_offset` to its producer's history, forgetting the
+    /// oldest past `PRODUCER_BATCH_HISTORY`.
+    fn remember(&mut self, batch: &RecordBatch, base_offset: Offset) {
+        if batch.producer_id < 0 {
+            return;
+        }
+        let history = self.producers.entry(batch.producer_id).or_default();
+        if history.len() == PRODUCER_BATCH_HISTORY {
+            history.pop_front();
+        }
+        history.push_back(ProducerBatch {
+            epoch: batch.producer_epoch,
+            base_sequence: batch.base_sequence,
+            last_offset_delta: batch.last_offset_delta,
+            base_offset,
+        });
+    }
+
+    /// Base offset of an earlier append with the same producer id, epoch and
+    /// sequence range.
+    fn find_duplicate(&self, batch: &RecordBatch) -> Option<Offset> {
+        if batch.producer_id < 0 {
+            return None;
+        }
+        self.producers
+            .get(&batch.producer_id)?
+            .iter()
+            .find(|earlier| {
+                earlier.epoch == batch.producer_epoch
+                    && earlier.base_sequence == batch.base_sequence
+                    && earlier.last_offset_delta == batch.last_offset_delta
+            })
+            .map(|earlier| earlier.base_offset)
+    }
+
+    /// Whole batches starting with the one holding `fetch_offset`, stopping before
+    /// `max_bytes` would be exceeded. The first batch is returned even when it's
+    /// larger, so a consumer can always make progress. `None` if `fetch_offset` is
+    /// outside the log; empty at the log end.
+    pub fn read(&self, fetch_offset: Offset, max_bytes: usize) -> Option<Vec<u8>> {
+        if fetch_offset < self.start_offset || fetch_offset > self.next_offset {
+            return None;
+        }
+        let first = self
+            .batches
+            .partition_point(|batch| batch.last_offset < fetch_offset);
+        let mut records = Vec::new();
+        for batch in &self.batches[first..] {
+            if !records.is_empty() && records.len() + batch.bytes.len() > max_bytes {
+                break;
+            }
+            records.extend_from_slice(&batch.bytes);
+        }
+        Some(records)
+    }
+
+    /// Offset the next appended record will get (the high watermark, with a
+    /// single replica).
+    pub fn log_end_offset(&self) -> Offset {
+        self.next_offset
+    }
+
+    /// First offset still in the log.
+    pub fn log_start_offset(&self) -> Offset {
+        self.start_offset
+    }
+
+    /// The first record whose timestamp is at least `target`, as (timestamp,
+    /// offset). Timestamps needn't increase with offsets, so every batch whose
+    /// max_timestamp reaches `target` may hold it.
+    pub fn offset_for_timestamp(&self, target: i64) -> Option<(i64, Offset)> {
+        self.batches
+            .iter()
+            .filter(|batch| batch.max_timestamp >= target)
+            .find_map(|batch| {
+                self.live_records(batch)
+                    .find(|&(timestamp, _)| timestamp >= target)
+            })
+    }
+
+    /// The record with the largest timestamp (the earliest one on a tie), as
+    /// (timestamp, offset).
+    pub fn max_timestamp_offset(&self) -> Option<(i64, Offset)> {
+        let mut max: Option<(i64, Offset)> = None;
+        for batch in &self.batches {
+            // Only a later batch's larger timestamp can take over
+            if max.is_some_and(|(timestamp, _)| batch.max_timestamp <= timestamp) {
+                continue;
+            }
+            for (timestamp, offset) in self.live_records(batch) {
+                if max.map_or(true, |(max_timestamp, _)| timestamp > max_timestamp) {
+                    max = Some((timestamp, offset));
+                }
+            }
+        }
+        max
+    }
+
+    /// (timestamp, offset) of each record in a stored batch that hasn't been
+    /// deleted.
+    fn live_records(&self, batch: &StoredBatch) -> impl Iterator<Item = (i64, Offset)> {
+        let start_offset = self.start_offset;
+        batch_records(batch, start_offset)
+            .into_iter()
+            .filter(move |&(_, offset)| offset >= start_offset)
+    }
+}
+
+/// (timestamp, offset) of each record in a stored batch. The records of a
+/// compressed batch can't be decoded here, so it stands in for them all with its
+/// max_timestamp at its first offset not below `start_offset`.
+fn batch_records(batch: &StoredBatch, start_offset: Offset) -> Vec<(i64, Offset)> {
+    let decoded = RecordBatch::parse_all(&batch.bytes)
+        .ok()
+        .and_then(|parsed| parsed.into_iter().next())
+        .map(|parsed| (parsed.base_offset, parsed.records()));
+    match decoded {
+        Some((_, Ok(records))) => records
+            .iter()
+            .map(|record| (record.timestamp, record.offset))
+            .collect(),
+        Some((base_offset, Err(_))) => vec![(batch.max_timestamp, base_offset.max(start_offset))],
+        None => Vec::new(),
+    }
+}
@@ -0,0 +1,648 @@
+//! What every `LogStore` must do, checked against a real store.
+//!
+//! A test per store runs each check on a store of its own; file-backed ones get
+//! a fresh directory per check. Checks that reopen a store are run only for
+//! stores whose logs outlive it. The checks are the specification a new store
+//! is written against: one passing them all can be put behind `log.store`.
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use crate::config::LogStoreKind;
+use crate::file_log_store::FileLogStore;
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::log_store::{FlushedOffsets, LogError, LogOffsets, LogStore, MemoryLogStore};
+use crate::record_batch::{self, Producer, RecordBatch};
+
+const TOPIC: TopicId = TopicId([1; 16]);
+const OTHER_TOPIC: TopicId = TopicId([2; 16]);
+const P0: PartitionId = PartitionId(0);
+const P1: PartitionId = PartitionId(1);
+const ALL: usize = usize::MAX;
+
+/// Opens the check's store. Called again, it opens the same storage afresh,
+/// recovering whatever the previous store left there.
+type Open<'a> = &'a dyn Fn() -> Result<Arc<dyn LogStore>>;
+
+type Check = fn(Open) -> Result<()>;
+
+/// Checks every store must pass.
+const CHECKS: &[(&str, Check)] = &[
+    ("unknown-partitions", unknown_partitions),
+    ("empty-partition", empty_partition),
+    ("append-assigns-offsets", append_assigns_offsets),
+    ("read-bounds", read_bounds),
+    ("timestamp-lookups", timestamp_lookups),
+    ("idempotent-retries", idempotent_retries),
+    ("delete-records", delete_records),
+    ("partitions-are-independent", partitions_are_independent),
+];
+
+/// Checks only stores whose logs outlive them must pass.
+const PERSISTENCE_CHECKS: &[(&str, Check)] = &[
+    ("survives-reopen", survives_reopen),
+    ("deletes-survive-reopen", deletes_survive_reopen),
+    ("log-start-survives-reopen", log_start_survives_reopen),
+    ("producers-survive-reopen", producers_survive_reopen),
+];
+
+#[test]
+fn memory_store_conforms() {
+    run(LogStoreKind::Memory, &std::env::temp_dir()).unwrap();
+}
+
+#[test]
+fn file_store_conforms() {
+    let dir = std::env::temp_dir().join(format!("eventor-conformance-{}", std::process::id()));
+    let result = run(LogStoreKind::File, &dir);
+    let _ = fs::remove_dir_all(&dir);
+    result.unwrap();
+}
+
+/// Runs every check against the store `kind` names, reporting each and failing
+/// if any did.
+fn run(kind: LogStoreKind, dir: &Path) -> Result<()> {
+    let persistent = kind == LogStoreKind::File;
+    let mut checks = CHECKS.to_vec();
+    if persistent {
+        checks.extend_from_slice(PERSISTENCE_CHECKS);
+    }
+    let mut failed = Vec::new();
+    for (name, check) in &checks {
+        let result = match kind {
+            LogStoreKind::Memory => {
+                let store: Arc<dyn LogStore> = Arc::new(MemoryLogStore::default());
+                check(&|| Ok(Arc::clone(&store)))
+            }
+            LogStoreKind::File => {
+                let dir = fresh_dir(&dir.join(name))?;
+                check(&|| open_file_store(&dir))
+            }
+        };
+        match result {
+            Ok(()) => println!("ok {}", name),
+            Err(e) => {
+                println!("FAILED {}: {:#}", name, e);
+                failed.push(*name);
+            }
+        }
+    }
+    println!(
+        "Log store conformance: {} of {} check(s) passed",
+        checks.len() - failed.len(),
+        checks.len()
+    );
+    if !failed.is_empty() {
+        bail!("log store checks failed: {}", failed.join(", "));
+    }
+    Ok(())
+}
+
+fn fresh_dir(dir: &Path) -> Result<PathBuf> {
+    match fs::remove_dir_all(dir) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("clearing {}", dir.display())),
+    }
+    fs::create_dir_all(dir).with_context(|| format!("creating {}", dir.display()))?;
+    Ok(dir.to_path_buf())
+}
+
+fn open_file_store(dir: &Path) -> Result<Arc<dyn LogStore>> {
+    let store = FileLogStore::new(dir.to_path_buf());
+    store.recover()?;
+    Ok(Arc::new(store))
+}
+
+/// A batch of `count` records, timestamped 1000 on, from no producer.
+fn batch(count: usize) -> Vec<u8> {
+    timestamped(&(0..count).map(|i| 1000 + i as i64).collect::<Vec<_>>())
+}
+
+/// A batch with one record per timestamp.
+fn timestamped(timestamps: &[i64]) -> Vec<u8> {
+    let records: Vec<(i64, &[u8])> = timestamps
+        .iter()
+        .map(|&timestamp| (timestamp, b"value".as_slice()))
+        .collect();
+    record_batch::build(&records, Producer::NONE)
+}
+
+/// Appends the batches in `raw` in one call, returning their base offsets.
+fn append(
+    store: &dyn LogStore,
+    topic_id: TopicId,
+    partition: PartitionId,
+    raw: &[Vec<u8>],
+) -> Result<Vec<Offset>> {
+    let batches = raw
+        .iter()
+        .map(|bytes| RecordBatch::parse_first(bytes))
+        .collect::<Result<Vec<_>, _>>()?;
+    let appended = store.append(topic_id, partition, &batches)?;
+    ensure!(
+        appended.len() == batches.len(),
+        "{} batch(es) appended, {} reported",
+        batches.len(),
+        appended.len()
+    );
+    Ok(appended
+        .iter()
+        .map(|appended| appended.base_offset)
+        .collect())
+}
+
+/// Base offsets of the batches `read` returns from `fetch_offset`.
+fn read_bases(
+    store: &dyn LogStore,
+    topic_id: TopicId,
+    partition: PartitionId,
+    fetch_offset: i64,
+    max_bytes: usize,
+) -> Result<Option<Vec<i64>>> {
+    let Some(records) = store.read(topic_id, partition, Offset(fetch_offset), max_bytes)? else {
+        return Ok(None);
+    };
+    let batches = RecordBatch::parse_all(&records).context("read returned unparseable records")?;
+    Ok(Some(
+        batches.iter().map(|batch| batch.base_offset.0).collect(),
+    ))
+}
+
+fn offsets(log_start_offset: i64, log_end_offset: i64) -> LogOffsets {
+    LogOffsets {
+        log_start_offset: Offset(log_start_offset),
+        log_end_offset: Offset(log_end_offset),
+    }
+}
+
+fn is_unknown<T>(result: Result<T, LogError>) -> bool {
+    matches!(result, Err(LogError::UnknownPartition(..)))
+}
+
+/// Nothing reads or writes a partition that was never created.
+fn unknown_partitions(open: Open) -> Result<()> {
+    let store = open()?;
+    let raw = batch(1);
+    let parsed = [RecordBatch::parse_first(&raw)?];
+    ensure!(
+        is_unknown(store.append(TOPIC, P0, &parsed)),
+        "append created the partition"
+    );
+    ensure!(is_unknown(store.read(TOPIC, P0, Offset(0), ALL)), "read");
+    ensure!(is_unknown(store.offsets(TOPIC, P0)), "offsets");
+    ensure!(
+        is_unknown(store.offset_for_timestamp(TOPIC, P0, 0)),
+        "offset_for_timestamp"
+    );
+    ensure!(
+        is_unknown(store.max_timestamp_offset(TOPIC, P0)),
+        "max_timestamp_offset"
+    );
+    store
+        .delete_partition(TOPIC, P0)
+        .context("deleting a partition that doesn't exist")?;
+    ensure!(store.partitions().is_empty(), "partitions listed");
+    Ok(())
+}
+
+/// A created partition is empty, and creating it again leaves it be.
+fn empty_partition(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 0),
+        "offsets of an empty log"
+    );
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 0, ALL)? == Some(vec![]),
+        "reading at the end of an empty log"
+    );
+    ensure!(
+        store.offset_for_timestamp(TOPIC, P0, 0)?.is_none(),
+        "timestamp lookup"
+    );
+    ensure!(
+        store.max_timestamp_offset(TOPIC, P0)?.is_none(),
+        "max timestamp lookup"
+    );
+
+    append(&*store, TOPIC, P0, &[batch(2)])?;
+    store.create_partition(TOPIC, P0)?;
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 2),
+        "creating an existing partition changed it"
+    );
+    ensure!(store.partitions() == vec![(TOPIC, P0)], "partitions listed");
+    ensure!(
+        store.flush()? == FlushedOffsets::from([((TOPIC, P0), Offset(2))]),
+        "flush didn't report where the log ended"
+    );
+    Ok(())
+}
+
+/// Batches get consecutive offsets, one per record, in append order.
+fn append_assigns_offsets(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    let bases = append(&*store, TOPIC, P0, &[batch(2), batch(1)])?;
+    ensure!(
+        bases == [Offset(0), Offset(2)],
+        "first append at {:?}",
+        bases
+    );
+    let bases = append(&*store, TOPIC, P0, &[batch(3)])?;
+    ensure!(bases == [Offset(3)], "second append at {:?}", bases);
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 6),
+        "offsets after appends"
+    );
+    // Stored with the base offsets they were given, whatever the producer sent
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 0, ALL)? == Some(vec![0, 2, 3]),
+        "batches read back"
+    );
+    Ok(())
+}
+
+/// Reads start at the batch holding the offset and stop at whole batches.
+fn read_bounds(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    append(&*store, TOPIC, P0, &[batch(2), batch(1), batch(1)])?;
+    for (fetch_offset, expected) in [
+        (0, Some(vec![0, 2, 3])),
+        (1, Some(vec![0, 2, 3])),
+        (2, Some(vec![2, 3])),
+        (4, Some(vec![])),
+        (5, None),
+        (-1, None),
+    ] {
+        let bases = read_bases(&*store, TOPIC, P0, fetch_offset, ALL)?;
+        ensure!(bases == expected, "read at {}: {:?}", fetch_offset, bases);
+    }
+    // The first batch comes back however small max_bytes is
+    let bases = read_bases(&*store, TOPIC, P0, 0, 1)?;
+    ensure!(bases == Some(vec![0]), "read with max_bytes 1: {:?}", bases);
+    let first = batch(2).len();
+    let bases = read_bases(&*store, TOPIC, P0, 0, first + 1)?;
+    ensure!(
+        bases == Some(vec![0]),
+        "read stopping at max_bytes: {:?}",
+        bases
+    );
+    Ok(())
+}
+
+/// Timestamp lookups find the first record, in offset order, at or after the
+/// target; timestamps needn't increase with offsets.
+fn timestamp_lookups(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    append(
+        &*store,
+        TOPIC,
+        P0,
+        &[timestamped(&[100, 300]), timestamped(&[200, 300])],
+    )?;
+    for (target, expected) in [
+        (0, Some((100, Offset(0)))),
+        (100, Some((100, Offset(0)))),
+        (150, Some((300, Offset(1)))),
+        (300, Some((300, Offset(1)))),
+        (301, None),
+    ] {
+        let found = store.offset_for_timestamp(TOPIC, P0, target)?;
+        ensure!(found == expected, "lookup of {}: {:?}", target, found);
+    }
+    let found = store.max_timestamp_offset(TOPIC, P0)?;
+    ensure!(
+        found == Some((300, Offset(1))),
+        "max timestamp: {:?}",
+        found
+    );
+    Ok(())
+}
+
+/// A batch an idempotent producer resends is answered with the original's
+/// offset and not stored again.
+fn idempotent_retries(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    let producer = |base_sequence| Producer {
+        producer_id: 7,
+        producer_epoch: 0,
+        base_sequence,
+    };
+    let first = record_batch::build(&[(1000, b"a"), (1000, b"b")], producer(0));
+    let second = record_batch::build(&[(1000, b"c")], producer(2));
+    let batches = [RecordBatch::parse_first(&first)?];
+    let appended = store.append(TOPIC, P0, &batches)?;
+    ensure!(
+        !appended[0].duplicate && appended[0].base_offset == Offset(0),
+        "first send"
+    );
+    let appended = store.append(TOPIC, P0, &batches)?;
+    ensure!(
+        appended[0].duplicate && appended[0].base_offset == Offset(0),
+        "resend not recognised"
+    );
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 2),
+        "resend stored again"
+    );
+    let appended = store.append(TOPIC, P0, &[RecordBatch::parse_first(&second)?])?;
+    ensure!(
+        !appended[0].duplicate && appended[0].base_offset == Offset(2),
+        "next sequence"
+    );
+    ensure!(
+        store.max_producer_id() == Some(7),
+        "max producer id {:?}",
+        store.max_producer_id()
+    );
+    Ok(())
+}
+
+/// Deleting records moves the log start offset forward only, up to the log
+/// end, and takes the deleted records out of reads and lookups.
+fn delete_records(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    append(
+        &*store,
+        TOPIC,
+        P0,
+        &[timestamped(&[500, 100]), timestamped(&[200]), batch(1)],
+    )?;
+    ensure!(
+        matches!(
+            store.delete_records(TOPIC, P0, Offset(5)),
+            Err(LogError::OffsetOutOfRange { .. })
+        ),
+        "deleting past the log end"
+    );
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 4),
+        "a refused delete changed the log"
+    );
+
+    // Part way into the first batch: it is kept, and still read whole
+    let start = store.delete_records(TOPIC, P0, Offset(1))?;
+    ensure!(start == Offset(1), "log start offset {:?}", start);
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(1, 4),
+        "offsets after deleting"
+    );
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 0, ALL)?.is_none(),
+        "read before the log start"
+    );
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 1, ALL)? == Some(vec![0, 2, 3]),
+        "read at the log start"
+    );
+    let found = store.offset_for_timestamp(TOPIC, P0, 0)?;
+    ensure!(found == Some((100, Offset(1))), "lookup found {:?}", found);
+    let found = store.max_timestamp_offset(TOPIC, P0)?;
+    ensure!(
+        found == Some((1000, Offset(3))),
+        "max timestamp of the records left: {:?}",
+        found
+    );
+
+    let start = store.delete_records(TOPIC, P0, Offset(0))?;
+    ensure!(
+        start == Offset(1),
+        "log start offset moved back to {:?}",
+        start
+    );
+    let start = store.delete_records(TOPIC, P0, Offset(4))?;
+    ensure!(start == Offset(4), "log start offset {:?}", start);
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 4, ALL)? == Some(vec![]),
+        "read with every record deleted"
+    );
+    ensure!(
+        store.max_timestamp_offset(TOPIC, P0)?.is_none(),
+        "max timestamp with every record deleted"
+    );
+    let bases = append(&*store, TOPIC, P0, &[batch(1)])?;
+    ensure!(bases == [Offset(4)], "append after deleting at {:?}", bases);
+    Ok(())
+}
+
+/// Each partition has its own offsets, and deleting one leaves the rest.
+fn partitions_are_independent(open: Open) -> Result<()> {
+    let store = open()?;
+    for (topic_id, partition) in [(TOPIC, P0), (TOPIC, P1), (OTHER_TOPIC, P0)] {
+        store.create_partition(topic_id, partition)?;
+    }
+    append(&*store, TOPIC, P0, &[batch(3)])?;
+    let bases = append(&*store, TOPIC, P1, &[batch(1)])?;
+    ensure!(
+        bases == [Offset(0)],
+        "second partition starts at {:?}",
+        bases
+    );
+    ensure!(
+        store.offsets(OTHER_TOPIC, P0)? == offsets(0, 0),
+        "untouched partition"
+    );
+
+    store.delete_partition(TOPIC, P0)?;
+    ensure!(
+        is_unknown(store.offsets(TOPIC, P0)),
+        "deleted partition still there"
+    );
+    ensure!(
+        store.offsets(TOPIC, P1)? == offsets(0, 1),
+        "sibling partition changed"
+    );
+    let mut partitions = store.partitions();
+    partitions.sort();
+    ensure!(
+        partitions == vec![(TOPIC, P1), (OTHER_TOPIC, P0)],
+        "partitions after delete: {:?}",
+        partitions
+    );
+    // Created again, a partition starts over
+    store.create_partition(TOPIC, P0)?;
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 0),
+        "recreated partition"
+    );
+    Ok(())
+}
+
+/// Whatever was appended and flushed is there after reopening, and appends
+/// carry on from it.
+fn survives_reopen(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    store.create_partition(TOPIC, P1)?;
+    append(
+        &*store,
+        TOPIC,
+        P0,
+        &[timestamped(&[100, 300]), timestamped(&[200])],
+    )?;
+    let before = store.read(TOPIC, P0, Offset(0), ALL)?;
+    store.flush()?;
+    drop(store);
+
+    let store = open()?;
+    let mut partitions = store.partitions();
+    partitions.sort();
+    ensure!(
+        partitions == vec![(TOPIC, P0), (TOPIC, P1)],
+        "partitions after reopening: {:?}",
+        partitions
+    );
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 3),
+        "offsets after reopening"
+    );
+    ensure!(
+        store.offsets(TOPIC, P1)? == offsets(0, 0),
+        "empty partition after reopening"
+    );
+    ensure!(
+        store.read(TOPIC, P0, Offset(0), ALL)? == before,
+        "records changed by reopening"
+    );
+    let found = store.max_timestamp_offset(TOPIC, P0)?;
+    ensure!(
+        found == Some((300, Offset(1))),
+        "max timestamp after reopening: {:?}",
+        found
+    );
+    let bases = append(&*store, TOPIC, P0, &[batch(1)])?;
+    ensure!(
+        bases == [Offset(3)],
+        "append after reopening at {:?}",
+        bases
+    );
+    Ok(())
+}
+
+/// A deleted partition stays deleted.
+fn deletes_survive_reopen(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    store.create_partition(OTHER_TOPIC, P0)?;
+    append(&*store, TOPIC, P0, &[batch(1)])?;
+    store.delete_partition(TOPIC, P0)?;
+    store.flush()?;
+    drop(store);
+
+    let store = open()?;
+    ensure!(
+        store.partitions() == vec![(OTHER_TOPIC, P0)],
+        "partitions after reopening: {:?}",
+        store.partitions()
+    );
+    Ok(())
+}
+
+/// Deleted records stay deleted, whether or not any are left, and appends carry
+/// on from the log end.
+fn log_start_survives_reopen(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    store.create_partition(TOPIC, P1)?;
+    append(&*store, TOPIC, P0, &[batch(2), batch(1)])?;
+    append(&*store, TOPIC, P1, &[batch(2)])?;
+    store.delete_records(TOPIC, P0, Offset(2))?;
+    store.delete_records(TOPIC, P1, Offset(2))?;
+    store.flush()?;
+    drop(store);
+
+    let store = open()?;
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(2, 3),
+        "offsets after reopening: {:?}",
+        store.offsets(TOPIC, P0)?
+    );
+    ensure!(
+        read_bases(&*store, TOPIC, P0, 2, ALL)? == Some(vec![2]),
+        "records left after reopening"
+    );
+    ensure!(
+        store.offsets(TOPIC, P1)? == offsets(2, 2),
+        "offsets of an emptied partition after reopening: {:?}",
+        store.offsets(TOPIC, P1)?
+    );
+    let bases = append(&*store, TOPIC, P1, &[batch(1)])?;
+    ensure!(
+        bases == [Offset(2)],
+        "append to an emptied partition at {:?}",
+        bases
+    );
+    store.flush()?;
+    drop(store);
+
+    let store = open()?;
+    ensure!(
+        store.offsets(TOPIC, P1)? == offsets(2, 3),
+        "offsets after appending to an emptied partition: {:?}",
+        store.offsets(TOPIC, P1)?
+    );
+    ensure!(
+        read_bases(&*store, TOPIC, P1, 2, ALL)? == Some(vec![2]),
+        "records appended to an emptied partition"
+    );
+    Ok(())
+}
+
+/// A producer's recent batches are remembered across a reopen: a retry of one
+/// is still a duplicate, and the producer's id is still taken.
+fn producers_survive_reopen(open: Open) -> Result<()> {
+    let store = open()?;
+    store.create_partition(TOPIC, P0)?;
+    let producer = |base_sequence| Producer {
+        producer_id: 7,
+        producer_epoch: 0,
+        base_sequence,
+    };
+    let first = record_batch::build(&[(1000, b"a"), (1000, b"b")], producer(0));
+    let second = record_batch::build(&[(1000, b"c")], producer(2));
+    append(
+        &*store,
+        TOPIC,
+        P0,
+        &[batch(1), first.clone(), second.clone()],
+    )?;
+    store.flush()?;
+    drop(store);
+
+    let store = open()?;
+    ensure!(
+        store.max_producer_id() == Some(7),
+        "max producer id after reopening: {:?}",
+        store.max_producer_id()
+    );
+    for (raw, base_offset) in [(&first, Offset(1)), (&second, Offset(3))] {
+        let appended = store.append(TOPIC, P0, &[RecordBatch::parse_first(raw)?])?;
+        ensure!(
+            appended[0].duplicate && appended[0].base_offset == base_offset,
+            "retry after reopening appended at {:?}",
+            appended[0].base_offset
+        );
+    }
+    ensure!(
+        store.offsets(TOPIC, P0)? == offsets(0, 4),
+        "retries stored again after reopening"
+    );
+    let next = record_batch::build(&[(1000, b"d")], producer(3));
+    let appended = store.append(TOPIC, P0, &[RecordBatch::parse_first(&next)?])?;
+    ensure!(
+        !appended[0].duplicate && appended[0].base_offset == Offset(4),
+        "next sequence after reopening"
+    );
+    Ok(())
+}
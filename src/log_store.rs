@@ -0,0 +1,328 @@
+//! Where partition logs are kept, behind the `LogStore` trait.
+//!
+//! The broker holds one store for every partition of every topic and calls it
+//! without any lock of its own, so each store synchronizes itself. A partition
+//! is created when its topic (or the partition, by CreatePartitions) is, and
+//! deleted with its topic; appending to or reading from one that doesn't exist
+//! is `LogError::UnknownPartition`, never an implicit create, so a produce
+//! racing a deletion can't leave a log behind for a topic that's gone.
+//!
+//! Two stores are provided, picked by `log.store`:
+//!
+//! - `file` (the default): `FileLogStore`, one directory per partition under the
+//!   log directory, recovered at startup on `num.recovery.threads.per.data.dir`
+//!   threads. A partition that can't be is left offline rather than stopping
+//!   the broker: it's `LogError::Offline` until its topic is deleted.
+//! - `memory`: `MemoryLogStore`, whose logs live as long as the process. The test
+//!   suite, `bench` and `replay` run against it.
+//!
+//! `log_conformance` checks what every store must do; a new store is done when
+//! it passes.
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use thiserror::Error;
+
+use crate::config::{BrokerConfig, LogStoreKind};
+use crate::error::BrokerError;
+use crate::file_log_store::FileLogStore;
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::log::{Appended, PartitionLog};
+use crate::record_batch::RecordBatch;
+
+const OFFSET_OUT_OF_RANGE: i16 = 1;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const KAFKA_STORAGE_ERROR: i16 = 56;
+
+#[derive(Debug, Error)]
+pub enum LogError {
+    #[error("partition {0}-{1} is not in the log store")]
+    UnknownPartition(TopicId, PartitionId),
+    #[error("partition {0}-{1} failed recovery and is offline")]
+    Offline(TopicId, PartitionId),
+    #[error("offset {offset} is past the log end offset {log_end_offset}")]
+    OffsetOutOfRange {
+        offset: Offset,
+        log_end_offset: Offset,
+    },
+    #[error(
+        "{path} holds log format {found}, which this broker can't read (only up to {supported})"
+    )]
+    UnsupportedFormat {
+        path: String,
+        found: String,
+        supported: u32,
+    },
+    #[error("{context}: {source}")]
+    Io {
+        context: String,
+        #[source]
+        source: io::Error,
+    },
+}
+
+impl LogError {
+    pub fn io(context: impl Into<String>, source: io::Error) -> LogError {
+        LogError::Io {
+            context: context.into(),
+            source,
+        }
+    }
+
+    /// What a client whose request hit this is told. The I/O error itself names
+    /// paths on the broker, so it goes to the broker's log only.
+    pub fn to_broker_error(&self) -> BrokerError {
+        match self {
+            LogError::UnknownPartition(..) => BrokerError::new(UNKNOWN_TOPIC_OR_PARTITION),
+            LogError::OffsetOutOfRange { .. } => BrokerError::new(OFFSET_OUT_OF_RANGE),
+            LogError::Offline(..) | LogError::UnsupportedFormat { .. } | LogError::Io { .. } => {
+                storage_error()
+            }
+        }
+    }
+}
+
+/// What a client is told when the disk fails it, with no more detail.
+pub fn storage_error() -> BrokerError {
+    BrokerError::with_message(
+        KAFKA_STORAGE_ERROR,
+        "Disk error when trying to access log file on the disk",
+    )
+}
+
+/// Where each partition's log ended when a flush began, which is what it's on
+/// stable storage up to once the flush succeeds.
+pub type FlushedOffsets = HashMap<(TopicId, PartitionId), Offset>;
+
+/// The offsets bounding a partition's records.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LogOffsets {
+    /// First offset still in the log.
+    pub log_start_offset: Offset,
+    /// Offset the next appended record will get (the high watermark, with a
+    /// single replica).
+    pub log_end_offset: Offset,
+}
+
+impl LogOffsets {
+    pub fn of(log: &PartitionLog) -> LogOffsets {
+        LogOffsets {
+            log_start_offset: log.log_start_offset(),
+            log_end_offset: log.log_end_offset(),
+        }
+    }
+}
+
+/// Partition logs keyed by (topic id, partition), so a topic created again
+/// under a deleted one's name starts with empty logs.
+pub trait LogStore: Send + Sync {
+    /// Loads the partitions the store already holds. Called once at startup,
+    /// before anything else.
+    fn recover(&self) -> Result<(), LogError>;
+
+    /// Every partition the store holds, in no particular order.
+    fn partitions(&self) -> Vec<(TopicId, PartitionId)>;
+
+    /// Adds an empty partition. One that already exists is left as it is.
+    fn create_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError>;
+
+    /// Removes a partition and its records. One that doesn't exist is ignored.
+    fn delete_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError>;
+
+    /// Appends batches in order, all or none of them, returning where each went.
+    /// Each batch's base_offset is overwritten with the one it's given.
+    fn append(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        batches: &[RecordBatch],
+    ) -> Result<Vec<Appended>, LogError>;
+
+    /// Whole batches from the one holding `fetch_offset`, as
+    /// `PartitionLog::read`: `None` if the offset is outside the log.
+    fn read(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        fetch_offset: Offset,
+        max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, LogError>;
+
+    fn offsets(&self, topic_id: TopicId, partition: PartitionId) -> Result<LogOffsets, LogError>;
+
+    /// The highest producer id any partition remembers a batch from.
+    fn max_producer_id(&self) -> Option<i64>;
+
+    /// Deletes the records before `offset`, as `PartitionLog::delete_before`,
+    /// returning the log start offset after. An `offset` past the log end is
+    /// `LogError::OffsetOutOfRange`, and deletes nothing.
+    fn delete_records(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        offset: Offset,
+    ) -> Result<Offset, LogError>;
+
+    /// The first record whose timestamp is at least `target`, as (timestamp,
+    /// offset).
+    fn offset_for_timestamp(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        target: i64,
+    ) -> Result<Option<(i64, Offset)>, LogError>;
+
+    /// The record with the largest timestamp, the earliest on a tie.
+    fn max_timestamp_offset(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+    ) -> Result<Option<(i64, Offset)>, LogError>;
+
+    /// Makes everything appended so far survive a crash, if the store can,
+    /// returning where each partition's log ended when the flush began.
+    fn flush(&self) -> Result<FlushedOffsets, LogError>;
+}
+
+/// The store `log.store` picks, for the first `log.dirs` entry.
+pub fn open(config: &BrokerConfig) -> Arc<dyn LogStore> {
+    match config.log_store {
+        LogStoreKind::File => Arc::new(
+            FileLogStore::new(config.log_dir.clone()).recovery_threads(config.recovery_threads),
+        ),
+        LogStoreKind::Memory => Arc::new(MemoryLogStore::default()),
+    }
+}
+
+/// Fails if records can't be deleted up to `offset`, which is past the log end.
+pub fn check_in_range(log: &PartitionLog, offset: Offset) -> Result<(), LogError> {
+    let log_end_offset = log.log_end_offset();
+    if offset > log_end_offset {
+        return Err(LogError::OffsetOutOfRange {
+            offset,
+            log_end_offset,
+        });
+    }
+    Ok(())
+}
+
+/// Logs in memory only: nothing survives the process.
+#[derive(Default)]
+pub struct MemoryLogStore {
+    logs: Mutex<HashMap<(TopicId, PartitionId), PartitionLog>>,
+}
+
+impl MemoryLogStore {
+    fn logs(&self) -> MutexGuard<'_, HashMap<(TopicId, PartitionId), PartitionLog>> {
+        // Appends change a log whole, so a panic elsewhere can't leave one
+        // half-written
+        self.logs
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Runs `f` on a partition's log, or fails if there's none.
+    fn with_log<T>(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        f: impl FnOnce(&mut PartitionLog) -> T,
+    ) -> Result<T, LogError> {
+        let mut logs = self.logs();
+        let log = logs
+            .get_mut(&(topic_id, partition))
+            .ok_or(LogError::UnknownPartition(topic_id, partition))?;
+        Ok(f(log))
+    }
+}
+
+impl LogStore for MemoryLogStore {
+    fn recover(&self) -> Result<(), LogError> {
+        Ok(())
+    }
+
+    fn partitions(&self) -> Vec<(TopicId, PartitionId)> {
+        self.logs().keys().copied().collect()
+    }
+
+    fn create_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError> {
+        self.logs().entry((topic_id, partition)).or_default();
+        Ok(())
+    }
+
+    fn delete_partition(&self, topic_id: TopicId, partition: PartitionId) -> Result<(), LogError> {
+        self.logs().remove(&(topic_id, partition));
+        Ok(())
+    }
+
+    fn append(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        batches: &[RecordBatch],
+    ) -> Result<Vec<Appended>, LogError> {
+        self.with_log(topic_id, partition, |log| {
+            batches.iter().map(|batch| log.append(batch)).collect()
+        })
+    }
+
+    fn read(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        fetch_offset: Offset,
+        max_bytes: usize,
+    ) -> Result<Option<Vec<u8>>, LogError> {
+        self.with_log(topic_id, partition, |log| log.read(fetch_offset, max_bytes))
+    }
+
+    fn offsets(&self, topic_id: TopicId, partition: PartitionId) -> Result<LogOffsets, LogError> {
+        self.with_log(topic_id, partition, |log| LogOffsets::of(log))
+    }
+
+    fn max_producer_id(&self) -> Option<i64> {
+        self.logs()
+            .values()
+            .filter_map(PartitionLog::max_producer_id)
+            .max()
+    }
+
+    fn delete_records(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        offset: Offset,
+    ) -> Result<Offset, LogError> {
+        self.with_log(topic_id, partition, |log| {
+            check_in_range(log, offset)?;
+            Ok(log.delete_before(offset))
+        })?
+    }
+
+    fn offset_for_timestamp(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+        target: i64,
+    ) -> Result<Option<(i64, Offset)>, LogError> {
+        self.with_log(topic_id, partition, |log| log.offset_for_timestamp(target))
+    }
+
+    fn max_timestamp_offset(
+        &self,
+        topic_id: TopicId,
+        partition: PartitionId,
+    ) -> Result<Option<(i64, Offset)>, LogError> {
+        self.with_log(topic_id, partition, |log| log.max_timestamp_offset())
+    }
+
+    fn flush(&self) -> Result<FlushedOffsets, LogError> {
+        Ok(self
+            .logs()
+            .iter()
+            .map(|(partition, log)| (*partition, log.log_end_offset()))
+            .collect())
+    }
+}
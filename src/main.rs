@@ -1,8 +1,85 @@
 //#![allow(unused_imports)]
 
 use std::convert::TryInto;
-use std::io::{self, Read, Write};
-use std::net::{TcpListener, TcpStream}; //To use try_into() on slices
+use std::fmt;
+use std::io::{self, BufReader, IoSlice, Read, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream}; //To use try_into() on slices
+use std::ops::Range;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+mod bench;
+mod broker;
+mod buffers;
+mod client_inventory;
+mod clock;
+#[cfg(test)]
+mod clock_checks;
+mod codec;
+mod config;
+mod config_overrides;
+mod create_partitions;
+mod create_topics;
+mod delete_records;
+mod delete_topics;
+mod describe_cluster;
+mod describe_configs;
+mod describe_groups;
+mod describe_topic_partitions;
+mod duplicates;
+mod echo;
+mod error;
+mod fetch;
+mod file_log_store;
+mod find_coordinator;
+mod flusher;
+mod group_coordinator;
+mod group_offsets;
+mod header;
+mod heartbeat;
+mod ids;
+mod incremental_alter_configs;
+mod init_producer_id;
+mod join_group;
+mod leave_group;
+mod lifecycle;
+mod list_groups;
+mod list_offsets;
+mod listener;
+mod log;
+#[cfg(test)]
+mod log_conformance;
+mod log_store;
+mod metadata;
+mod metadata_api;
+mod offset_commit;
+mod offset_fetch;
+mod produce;
+mod record_batch;
+mod sasl;
+mod saved_file;
+mod session;
+mod sync_group;
+mod throttle;
+mod topic_changes;
+mod topic_config;
+mod topic_events;
+
+use anyhow::Context;
+
+use broker::Broker;
+use client_inventory::ClientKey;
+use codec::{DecodeError, ResponseWriter};
+use config::{BrokerConfig, LogStoreKind};
+use file_log_store::FileLogStore;
+use header::RequestHeader;
+use lifecycle::Phase;
+use listener::{ListenerPolicy, ListenerStats};
+use log_store::{LogStore, MemoryLogStore};
+use sasl::SaslState;
+use session::SessionRecorder;
+use throttle::{ThrottleField, THROTTLE_TIME_LEN};
 
 const MESSAGE_SIZE_LEN: usize = 4;
 const API_KEY_LEN: usize = 2;
@@ -11,157 +88,1013 @@ const CORRELATION_ID_LEN: usize = 4;
 
 const HEADER_LEN: usize = MESSAGE_SIZE_LEN + API_KEY_LEN + API_VERSION_LEN + CORRELATION_ID_LEN; // 4 + 2 + 2 + 4 = 12 bytes
 
-/// Parses topic name from DescribeTopicPartitions request
-fn parse_topic_name(request_buffer: &[u8]) -> String {
-    // DescribeTopicPartitions request format:
-    // api_key(2) + api_version(2) + correlation_id(4) + client_id + topics + cursor + tagged_fields
-    // We need to find the topic name from the topics array
+const COORDINATOR_LOAD_IN_PROGRESS: u16 = 14;
+const CLUSTER_AUTHORIZATION_FAILED: u16 = 31;
+const ILLEGAL_SASL_STATE: u16 = 34;
+const UNSUPPORTED_VERSION: u16 = 35;
+const INVALID_REQUEST: u16 = 42;
+
+const PRODUCE_KEY: u16 = 0;
+const FETCH_KEY: u16 = 1;
+const LIST_OFFSETS_KEY: u16 = 2;
+const METADATA_KEY: u16 = 3;
+const OFFSET_COMMIT_KEY: u16 = 8;
+const OFFSET_FETCH_KEY: u16 = 9;
+const FIND_COORDINATOR_KEY: u16 = 10;
+const JOIN_GROUP_KEY: u16 = 11;
+const HEARTBEAT_KEY: u16 = 12;
+const LEAVE_GROUP_KEY: u16 = 13;
+const SYNC_GROUP_KEY: u16 = 14;
+const DESCRIBE_GROUPS_KEY: u16 = 15;
+const LIST_GROUPS_KEY: u16 = 16;
+const SASL_HANDSHAKE_KEY: u16 = 17;
+const API_VERSIONS_KEY: u16 = 18;
+const CREATE_TOPICS_KEY: u16 = 19;
+const DELETE_TOPICS_KEY: u16 = 20;
+const DELETE_RECORDS_KEY: u16 = 21;
+const INIT_PRODUCER_ID_KEY: u16 = 22;
+const DESCRIBE_CONFIGS_KEY: u16 = 32;
+const SASL_AUTHENTICATE_KEY: u16 = 36;
+const CREATE_PARTITIONS_KEY: u16 = 37;
+const INCREMENTAL_ALTER_CONFIGS_KEY: u16 = 44;
+const DESCRIBE_CLUSTER_KEY: u16 = 60;
+const DESCRIBE_TOPIC_PARTITIONS_KEY: u16 = 75;
+
+/// The APIs that change topics, their configs and their data, refused on
+/// listeners with `admin.apis.enabled=false`.
+const ADMIN_APIS: &[u16] = &[
+    CREATE_TOPICS_KEY,
+    DELETE_TOPICS_KEY,
+    DELETE_RECORDS_KEY,
+    CREATE_PARTITIONS_KEY,
+    INCREMENTAL_ALTER_CONFIGS_KEY,
+];
+
+/// An API this broker serves, with the version range advertised in ApiVersions.
+/// Dispatch rejects anything outside the range before a handler runs.
+struct SupportedApi {
+    api_key: u16,
+    /// As `listener.name.<name>.allowed.apis` names it.
+    name: &'static str,
+    min_version: i16,
+    max_version: i16,
+    /// First version whose responses use response header v1 (with a tag buffer)
+    first_flexible_version: Option<i16>,
+    /// Where the API's responses carry throttle_time_ms, filled in once the
+    /// handler has built one.
+    throttle: ThrottleField,
+}
+
+impl SupportedApi {
+    fn flexible_response_header(&self, api_version: i16) -> bool {
+        self.first_flexible_version
+            .is_some_and(|first| api_version >= first)
+    }
+
+    /// The throttle_time_ms bytes of a response of `api_version`, if it has any.
+    fn throttle_slot(&self, api_version: i16, response: &[u8]) -> Option<Range<usize>> {
+        let header_len = MESSAGE_SIZE_LEN
+            + CORRELATION_ID_LEN
+            + usize::from(self.flexible_response_header(api_version));
+        self.throttle.slot(api_version, header_len, response.len())
+    }
+}
+
+/// The throttle_time_ms bytes of a response to API `api_key`, if it has any.
+fn throttle_slot(api_key: u16, api_version: i16, response: &[u8]) -> Option<Range<usize>> {
+    SUPPORTED_APIS
+        .iter()
+        .find(|api| api.api_key == api_key)?
+        .throttle_slot(api_version, response)
+}
 
-    // Skip to the topics array - this is complex parsing, let's find the actual topic
-    // For now, let's extract the topic name from the request hex dump analysis
+const SUPPORTED_APIS: &[SupportedApi] = &[
+    SupportedApi {
+        api_key: PRODUCE_KEY,
+        name: "Produce",
+        min_version: 3,
+        max_version: 11,
+        first_flexible_version: Some(9),
+        throttle: ThrottleField::Last {
+            since: 1,
+            tag_buffer_from: 9,
+        },
+    },
+    SupportedApi {
+        api_key: FETCH_KEY,
+        name: "Fetch",
+        min_version: 4,
+        max_version: 16,
+        first_flexible_version: Some(12),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: LIST_OFFSETS_KEY,
+        name: "ListOffsets",
+        min_version: 1,
+        max_version: 7,
+        first_flexible_version: Some(6),
+        throttle: ThrottleField::First { since: 2 },
+    },
+    SupportedApi {
+        api_key: METADATA_KEY,
+        name: "Metadata",
+        min_version: 1,
+        max_version: 12,
+        first_flexible_version: Some(9),
+        throttle: ThrottleField::First { since: 3 },
+    },
+    SupportedApi {
+        api_key: OFFSET_COMMIT_KEY,
+        name: "OffsetCommit",
+        min_version: 2,
+        max_version: 8,
+        first_flexible_version: Some(8),
+        throttle: ThrottleField::First { since: 3 },
+    },
+    SupportedApi {
+        api_key: OFFSET_FETCH_KEY,
+        name: "OffsetFetch",
+        min_version: 1,
+        max_version: 8,
+        first_flexible_version: Some(6),
+        throttle: ThrottleField::First { since: 3 },
+    },
+    SupportedApi {
+        api_key: FIND_COORDINATOR_KEY,
+        name: "FindCoordinator",
+        min_version: 0,
+        max_version: 5,
+        first_flexible_version: Some(3),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: JOIN_GROUP_KEY,
+        name: "JoinGroup",
+        min_version: 2,
+        max_version: 9,
+        first_flexible_version: Some(6),
+        throttle: ThrottleField::First { since: 2 },
+    },
+    SupportedApi {
+        api_key: HEARTBEAT_KEY,
+        name: "Heartbeat",
+        min_version: 0,
+        max_version: 4,
+        first_flexible_version: Some(4),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: LEAVE_GROUP_KEY,
+        name: "LeaveGroup",
+        min_version: 0,
+        max_version: 5,
+        first_flexible_version: Some(4),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: SYNC_GROUP_KEY,
+        name: "SyncGroup",
+        min_version: 0,
+        max_version: 5,
+        first_flexible_version: Some(4),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: DESCRIBE_GROUPS_KEY,
+        name: "DescribeGroups",
+        min_version: 0,
+        max_version: 5,
+        first_flexible_version: Some(5),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: LIST_GROUPS_KEY,
+        name: "ListGroups",
+        min_version: 0,
+        max_version: 4,
+        first_flexible_version: Some(3),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: SASL_HANDSHAKE_KEY,
+        name: "SaslHandshake",
+        min_version: 1,
+        max_version: 1,
+        first_flexible_version: None,
+        throttle: ThrottleField::Absent,
+    },
+    SupportedApi {
+        api_key: API_VERSIONS_KEY,
+        name: "ApiVersions",
+        min_version: 0,
+        max_version: 4,
+        first_flexible_version: None, // ApiVersions always answers with header v0
+        throttle: ThrottleField::Last {
+            since: 1,
+            tag_buffer_from: 3,
+        },
+    },
+    SupportedApi {
+        api_key: CREATE_TOPICS_KEY,
+        name: "CreateTopics",
+        min_version: 2,
+        max_version: 7,
+        first_flexible_version: Some(5),
+        throttle: ThrottleField::First { since: 2 },
+    },
+    SupportedApi {
+        api_key: DELETE_TOPICS_KEY,
+        name: "DeleteTopics",
+        min_version: 1,
+        max_version: 6,
+        first_flexible_version: Some(4),
+        throttle: ThrottleField::First { since: 1 },
+    },
+    SupportedApi {
+        api_key: DELETE_RECORDS_KEY,
+        name: "DeleteRecords",
+        min_version: 0,
+        max_version: 2,
+        first_flexible_version: Some(2),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: INIT_PRODUCER_ID_KEY,
+        name: "InitProducerId",
+        min_version: 0,
+        max_version: 5,
+        first_flexible_version: Some(2),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: DESCRIBE_CONFIGS_KEY,
+        name: "DescribeConfigs",
+        min_version: 0,
+        max_version: 4,
+        first_flexible_version: Some(4),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: SASL_AUTHENTICATE_KEY,
+        name: "SaslAuthenticate",
+        min_version: 0,
+        max_version: 2,
+        first_flexible_version: Some(2),
+        throttle: ThrottleField::Absent,
+    },
+    SupportedApi {
+        api_key: CREATE_PARTITIONS_KEY,
+        name: "CreatePartitions",
+        min_version: 0,
+        max_version: 3,
+        first_flexible_version: Some(2),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: INCREMENTAL_ALTER_CONFIGS_KEY,
+        name: "IncrementalAlterConfigs",
+        min_version: 0,
+        max_version: 1,
+        first_flexible_version: Some(1),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: DESCRIBE_CLUSTER_KEY,
+        name: "DescribeCluster",
+        min_version: 0,
+        max_version: 1,
+        first_flexible_version: Some(0),
+        throttle: ThrottleField::First { since: 0 },
+    },
+    SupportedApi {
+        api_key: DESCRIBE_TOPIC_PARTITIONS_KEY,
+        name: "DescribeTopicPartitions",
+        min_version: 0,
+        max_version: 0,
+        first_flexible_version: Some(0),
+        throttle: ThrottleField::First { since: 0 },
+    },
+];
 
-    // Based on the hex dump, the topic name "unknown-topic-saz" starts at a specific position
-    // Let's find it by looking for the pattern in the request
+/// Builds APIVersions response
+///
+/// v3+ use the flexible encoding (compact array, tag buffers); v0-v2 use an int32
+/// array count, and only v1+ carry throttle_time_ms. The response header is always
+/// v0 so a client can read the error code before it knows which versions we speak.
+/// Only the APIs the connection's listener serves are listed.
+fn build_api_versions_response(
+    correlation_id: u32,
+    api_version: i16,
+    error_code: u16,
+    policy: &ListenerPolicy,
+) -> Vec<u8> {
+    let flexible = api_version >= 3;
+    let apis: Vec<&SupportedApi> = SUPPORTED_APIS
+        .iter()
+        .filter(|api| policy.allows_api(api.api_key))
+        .collect();
 
-    let request_str = String::from_utf8_lossy(request_buffer);
+    let mut response = ResponseWriter::new(correlation_id, false);
+    response.put_u16(error_code);
 
-    // Look for "unknown-topic-" pattern
-    if let Some(start) = request_str.find("unknown-topic-") {
-        let remaining = &request_str[start..];
-        if let Some(end) = remaining.find('\0') {
-            return remaining[..end].to_string();
+    if flexible {
+        response.put_compact_array_len(apis.len());
+    } else {
+        response.put_array_len(apis.len());
+    }
+    for api in apis {
+        response.put_u16(api.api_key);
+        response.put_i16(api.min_version);
+        response.put_i16(api.max_version);
+        if flexible {
+            response.put_tag_buffer(); // api TAG_BUFFER
         }
     }
 
-    // Fallback: return a default topic name
-    "unknown-topic-saz".to_string()
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+    response.finish()
+}
+
+/// Rejects a request whose version is outside the API's advertised range.
+///
+/// ApiVersions answers with its v0 body so the client can still read our ranges and
+/// downgrade. Other APIs can't be answered with a body we don't speak, so the error
+/// code follows a response header of the nearest supported version.
+fn build_unsupported_version_response(
+    api: &SupportedApi,
+    api_version: i16,
+    correlation_id: u32,
+    policy: &ListenerPolicy,
+) -> Vec<u8> {
+    if api.api_key == API_VERSIONS_KEY {
+        return build_api_versions_response(correlation_id, 0, UNSUPPORTED_VERSION, policy);
+    }
+    build_error_response(api, api_version, correlation_id, UNSUPPORTED_VERSION)
+}
+
+/// A response header of the nearest supported version followed only by
+/// `error_code`, for requests rejected before their handler runs.
+fn build_error_response(
+    api: &SupportedApi,
+    api_version: i16,
+    correlation_id: u32,
+    error_code: u16,
+) -> Vec<u8> {
+    let nearest_version = api_version.clamp(api.min_version, api.max_version);
+    let mut response = ResponseWriter::new(
+        correlation_id,
+        api.flexible_response_header(nearest_version),
+    );
+    response.put_u16(error_code);
+    response.finish()
 }
 
-/// Builds DescribeTopicPartitions response for unknown topic
-fn build_describe_topic_partitions_response(correlation_id: u32, topic_name: &str) -> Vec<u8> {
-    let mut response = Vec::new();
+/// The answer to a request for an API this broker doesn't have: a v0 response
+/// header, as there is no version to pick one by, and UNSUPPORTED_VERSION.
+fn build_unknown_api_response(correlation_id: u32) -> Vec<u8> {
+    let mut response = ResponseWriter::new(correlation_id, false);
+    response.put_u16(UNSUPPORTED_VERSION);
+    response.finish()
+}
 
-    // Response structure according to Kafka protocol v0:
-    // [message_size][correlation_id][throttle_time_ms][topics][next_cursor][tagged_fields]
-    //
-    // Topic structure:
-    // [error_code][name][topic_id][is_internal][partitions][topic_authorized_operations][tagged_fields]
+/// INVALID_REQUEST for a request whose header or body can't be decoded.
+fn build_invalid_request_response(api_key: u16, api_version: i16, correlation_id: u32) -> Vec<u8> {
+    match SUPPORTED_APIS.iter().find(|api| api.api_key == api_key) {
+        Some(api) => build_error_response(api, api_version, correlation_id, INVALID_REQUEST),
+        None => build_unknown_api_response(correlation_id),
+    }
+}
 
-    let correlation_id_bytes = correlation_id.to_be_bytes();
-    let throttle_time_ms: u32 = 0;
-    let topic_count: u8 = 2; // compact array: 1 topic + 1 = 2
-    let topic_name_len: u8 = (topic_name.len() + 1) as u8; // compact string: len + 1
-    println!(
-        "Building response for topic: '{}', length: {}",
-        topic_name,
-        topic_name.len()
+/// What to do after handling one request.
+enum RequestOutcome {
+    /// Send this response, size prefix included.
+    Respond(Vec<u8>),
+    /// The request expects no response (Produce with acks=0).
+    NoResponse,
+    /// The request can't be answered; close the connection.
+    Close,
+    /// Send this response, then close the connection (failed authentication).
+    RespondThenClose(Vec<u8>),
+}
+
+/// What a connection has established, carried from one of its requests to
+/// the next.
+#[derive(Default)]
+struct ConnectionState {
+    /// Index of the listener the connection came in on, in `config.listeners`.
+    listener: usize,
+    /// The peer's address as Kafka reports client hosts, `/127.0.0.1`; empty
+    /// when replaying a session.
+    client_host: String,
+    sasl: SaslState,
+    /// The software name and version from the connection's ApiVersions v3+.
+    software: Option<(String, String)>,
+    /// Who the connection's requests are counted against in the client
+    /// inventory, and whether the inventory had room for them.
+    client: Option<(ClientKey, bool)>,
+}
+
+impl ConnectionState {
+    fn on_listener(listener: usize, peer_addr: SocketAddr) -> ConnectionState {
+        ConnectionState {
+            listener,
+            client_host: format!("/{}", peer_addr.ip()),
+            ..ConnectionState::default()
+        }
+    }
+}
+
+/// Counts a request about to be served against its client in the inventory,
+/// moving the connection over to a new client when its software or client id
+/// has changed.
+fn record_client(
+    broker: &Broker,
+    connection: &mut ConnectionState,
+    header: &RequestHeader,
+    api_version: i16,
+    request_buffer: &[u8],
+) {
+    if header.api_key == API_VERSIONS_KEY && api_version >= 3 {
+        // Answered all the same: clients send ApiVersions before anything else,
+        // and a body that doesn't parse only leaves the software unknown
+        if let Ok(software) = read_client_software(request_buffer) {
+            connection.software = Some(software);
+        }
+    }
+    let (name, version) = connection
+        .software
+        .as_ref()
+        .map_or(("unknown", "unknown"), |(name, version)| {
+            (name.as_str(), version.as_str())
+        });
+    let client = ClientKey::new(name, version, header.client_id.as_str());
+    if connection.client.as_ref().map(|(current, _)| current) != Some(&client) {
+        if let Some((previous, true)) = connection.client.take() {
+            broker.clients.disconnected(&previous);
+        }
+        let tracked = broker.clients.connected(&client);
+        if !tracked {
+            println!("Client inventory is full, not keeping {}", client);
+        }
+        connection.client = Some((client, tracked));
+    }
+    if let Some((client, true)) = &connection.client {
+        broker.clients.served(client, header.api_key, api_version);
+    }
+}
+
+/// ApiVersionsRequest v3+: client_software_name, client_software_version.
+fn read_client_software(request_buffer: &[u8]) -> Result<(String, String), DecodeError> {
+    let mut decoder = RequestHeader::body_decoder(request_buffer, true)?;
+    let name = decoder.read_compact_string()?;
+    let version = decoder.read_compact_string()?;
+    Ok((name, version))
+}
+
+/// Handles one request frame (everything after the size prefix).
+fn handle_request(
+    broker: &Broker,
+    connection: &mut ConnectionState,
+    request_buffer: &[u8],
+) -> RequestOutcome {
+    let header = match RequestHeader::parse(request_buffer) {
+        Ok(header) => header,
+        // A client id running past the frame still leaves the fields before it
+        // to answer with
+        Err(e) => match RequestHeader::parse_fixed(request_buffer) {
+            Ok((api_key, api_version, correlation_id)) => {
+                println!("Malformed request header: {}, answering INVALID_REQUEST", e);
+                broker.listener_stats[connection.listener].request(true);
+                return RequestOutcome::Respond(build_invalid_request_response(
+                    api_key,
+                    api_version as i16,
+                    correlation_id,
+                ));
+            }
+            Err(_) => {
+                println!("Malformed request header: {}, breaking connection", e);
+                return RequestOutcome::Close;
+            }
+        },
+    };
+    let correlation_id = header.correlation_id;
+    let api_key = header.api_key;
+    let api_version = header.api_version;
+    let listener = &broker.config.listeners[connection.listener];
+    let stats = &broker.listener_stats[connection.listener];
+
+    println!("Extracted Correlation ID (u32): {}", correlation_id);
+    println!("Extracted API Key: {}", api_key);
+    println!("Extracted API Version: {}", api_version);
+    println!("Extracted Client ID: {}", header.client_id);
+    if let Some(original_len) = header.client_id.truncated_from() {
+        println!(
+            "Client ID truncated from {} bytes for storage",
+            original_len
+        );
+    }
+
+    let Some(api) = SUPPORTED_APIS.iter().find(|api| api.api_key == api_key) else {
+        // Unknown API key - return error
+        println!("Unknown API key: {}", api_key);
+        stats.request(true);
+        return RequestOutcome::Respond(build_unknown_api_response(correlation_id));
+    };
+    // An API the listener doesn't serve is one it doesn't have
+    if !listener.policy.allows_api(api.api_key) {
+        println!(
+            "{} isn't served on listener {}, rejecting it",
+            api.name, listener.name
+        );
+        stats.request(true);
+        return RequestOutcome::Respond(build_error_response(
+            api,
+            api_version as i16,
+            correlation_id,
+            UNSUPPORTED_VERSION,
+        ));
+    }
+
+    // Headers carry api_version as an INT16; a client probing below our range sends e.g. -1
+    let api_version = api_version as i16;
+    let in_range = (api.min_version..=api.max_version).contains(&api_version);
+    stats.version_checked(api.api_key, in_range);
+    if !in_range {
+        println!(
+            "Unsupported version {} for API key {} (supported {}-{})",
+            api_version, api_key, api.min_version, api.max_version
+        );
+        stats.request(true);
+        return RequestOutcome::Respond(build_unsupported_version_response(
+            api,
+            api_version,
+            correlation_id,
+            &listener.policy,
+        ));
+    }
+
+    // ApiVersions needs no state, and clients send it before anything else
+    if !broker.lifecycle.is_ready() && api.api_key != API_VERSIONS_KEY {
+        println!("Broker not ready, rejecting API key {}", api_key);
+        stats.request(true);
+        return RequestOutcome::Respond(build_error_response(
+            api,
+            api_version,
+            correlation_id,
+            COORDINATOR_LOAD_IN_PROGRESS,
+        ));
+    }
+
+    // With SASL enabled, a connection is served nothing else until it has
+    // authenticated
+    let authenticating = matches!(
+        api.api_key,
+        API_VERSIONS_KEY | SASL_HANDSHAKE_KEY | SASL_AUTHENTICATE_KEY
     );
-    let topic_id = [0u8; 16]; // 16 zero bytes for null UUID
-    let error_code: u16 = 3; // UNKNOWN_TOPIC_OR_PARTITION
-    let is_internal: u8 = 0; // false
-    let partitions_count: u8 = 1; // compact array: 0 partitions + 1 = 1
-    let topic_authorized_operations: u32 = 0; // No operations
-    let topic_tagged_fields: u8 = 0;
-    // Set next_cursor to null for unknown topics (flexible version nullable)
-    let next_cursor_null: u8 = 0xFF;
-    let response_tagged_fields: u8 = 0;
-
-    // Calculate message size: everything after the message_size field
-    // Response Header v1: correlation_id(4) + header_tag_buffer(1) + throttle_time(4) + topic_count(1) + [topic: error_code(2) + topic_name_len(1) + topic_name + topic_id(16) + is_internal(1) + partitions(1) + topic_authorized_operations(4) + topic_tagged_fields(1)] + next_cursor(1) + response_tagged_fields(1)
-    let message_size = 4 + 1 + 4 + 1 + (2 + 1 + topic_name.len() + 16 + 1 + 1 + 4 + 1) + 1 + 1;
-    let header_tag_buffer: u8 = 0; // TAG_BUFFER for Response Header v1
-
-    // Build response
-    response.extend_from_slice(&(message_size as u32).to_be_bytes());
-    response.extend_from_slice(&correlation_id_bytes);
-    response.extend_from_slice(&[header_tag_buffer]); // Response Header v1 TAG_BUFFER
-    response.extend_from_slice(&throttle_time_ms.to_be_bytes());
-    response.extend_from_slice(&[topic_count]);
-
-    // Topic data
-    response.extend_from_slice(&error_code.to_be_bytes());
-    response.extend_from_slice(&[topic_name_len]);
-    response.extend_from_slice(topic_name.as_bytes());
-    response.extend_from_slice(&topic_id);
-    response.extend_from_slice(&[is_internal]);
-    response.extend_from_slice(&[partitions_count]);
-    response.extend_from_slice(&topic_authorized_operations.to_be_bytes());
-    response.extend_from_slice(&[topic_tagged_fields]);
-
-    // Next cursor (null) and response tagged fields
-    response.extend_from_slice(&[next_cursor_null]);
-    response.extend_from_slice(&[response_tagged_fields]);
+    if listener.requires_authentication() && !connection.sasl.is_authenticated() && !authenticating
+    {
+        println!(
+            "Connection not authenticated, rejecting API key {}",
+            api_key
+        );
+        stats.request(true);
+        return RequestOutcome::Respond(build_error_response(
+            api,
+            api_version,
+            correlation_id,
+            ILLEGAL_SASL_STATE,
+        ));
+    }
+
+    if !listener.policy.admin_apis && ADMIN_APIS.contains(&api.api_key) {
+        println!(
+            "Admin APIs are disabled on listener {}, rejecting {}",
+            listener.name, api.name
+        );
+        stats.request(true);
+        return RequestOutcome::Respond(build_error_response(
+            api,
+            api_version,
+            correlation_id,
+            CLUSTER_AUTHORIZATION_FAILED,
+        ));
+    }
+    stats.request(false);
+    record_client(broker, connection, &header, api_version, request_buffer);
+
+    // Build response based on API key
+    let response = match api.api_key {
+        PRODUCE_KEY => {
+            println!("Handling Produce request");
+            match produce::handle(broker, &header, request_buffer) {
+                Ok(Some(response)) => Ok(response),
+                Ok(None) => return RequestOutcome::NoResponse,
+                // An answer the client isn't waiting for would be taken for
+                // the next request's
+                Err(e) if !produce::expects_response(&header, request_buffer) => {
+                    println!(
+                        "Malformed Produce request that may be acks=0: {}, breaking connection",
+                        e
+                    );
+                    return RequestOutcome::Close;
+                }
+                Err(e) => Err(e),
+            }
+        }
+        FETCH_KEY => {
+            println!("Handling Fetch request");
+            fetch::handle(broker, &header, request_buffer)
+        }
+        LIST_OFFSETS_KEY => {
+            println!("Handling ListOffsets request");
+            list_offsets::handle(broker, &header, request_buffer)
+        }
+        METADATA_KEY => {
+            println!("Handling Metadata request");
+            metadata_api::handle(broker, &header, request_buffer)
+        }
+        OFFSET_COMMIT_KEY => {
+            println!("Handling OffsetCommit request");
+            offset_commit::handle(broker, &header, request_buffer)
+        }
+        OFFSET_FETCH_KEY => {
+            println!("Handling OffsetFetch request");
+            offset_fetch::handle(broker, &header, request_buffer)
+        }
+        FIND_COORDINATOR_KEY => {
+            println!("Handling FindCoordinator request");
+            find_coordinator::handle(broker, &header, request_buffer)
+        }
+        JOIN_GROUP_KEY => {
+            println!("Handling JoinGroup request");
+            join_group::handle(broker, &header, request_buffer, &connection.client_host)
+        }
+        HEARTBEAT_KEY => {
+            println!("Handling Heartbeat request");
+            heartbeat::handle(broker, &header, request_buffer)
+        }
+        LEAVE_GROUP_KEY => {
+            println!("Handling LeaveGroup request");
+            leave_group::handle(broker, &header, request_buffer)
+        }
+        SYNC_GROUP_KEY => {
+            println!("Handling SyncGroup request");
+            sync_group::handle(broker, &header, request_buffer)
+        }
+        DESCRIBE_GROUPS_KEY => {
+            println!("Handling DescribeGroups request");
+            describe_groups::handle(broker, &header, request_buffer)
+        }
+        LIST_GROUPS_KEY => {
+            println!("Handling ListGroups request");
+            list_groups::handle(broker, &header, request_buffer)
+        }
+        SASL_HANDSHAKE_KEY => {
+            println!("Handling SaslHandshake request");
+            sasl::handle_handshake(
+                broker,
+                &header,
+                request_buffer,
+                listener,
+                &mut connection.sasl,
+            )
+        }
+        API_VERSIONS_KEY => {
+            println!("Handling APIVersions request");
+            Ok(build_api_versions_response(
+                correlation_id,
+                api_version,
+                0,
+                &listener.policy,
+            ))
+        }
+        CREATE_TOPICS_KEY => {
+            println!("Handling CreateTopics request");
+            create_topics::handle(broker, &header, request_buffer)
+        }
+        DELETE_TOPICS_KEY => {
+            println!("Handling DeleteTopics request");
+            delete_topics::handle(broker, &header, request_buffer)
+        }
+        DELETE_RECORDS_KEY => {
+            println!("Handling DeleteRecords request");
+            delete_records::handle(broker, &header, request_buffer)
+        }
+        INIT_PRODUCER_ID_KEY => {
+            println!("Handling InitProducerId request");
+            init_producer_id::handle(broker, &header, request_buffer)
+        }
+        DESCRIBE_CONFIGS_KEY => {
+            println!("Handling DescribeConfigs request");
+            describe_configs::handle(broker, &header, request_buffer)
+        }
+        SASL_AUTHENTICATE_KEY => {
+            println!("Handling SaslAuthenticate request");
+            match sasl::handle_authenticate(broker, &header, request_buffer, &mut connection.sasl) {
+                Ok(authentication) if authentication.failed => {
+                    return RequestOutcome::RespondThenClose(authentication.response)
+                }
+                Ok(authentication) => Ok(authentication.response),
+                Err(e) => Err(e),
+            }
+        }
+        CREATE_PARTITIONS_KEY => {
+            println!("Handling CreatePartitions request");
+            create_partitions::handle(broker, &header, request_buffer)
+        }
+        INCREMENTAL_ALTER_CONFIGS_KEY => {
+            println!("Handling IncrementalAlterConfigs request");
+            incremental_alter_configs::handle(broker, &header, request_buffer)
+        }
+        DESCRIBE_CLUSTER_KEY => {
+            println!("Handling DescribeCluster request");
+            describe_cluster::handle(broker, &header, request_buffer)
+        }
+        DESCRIBE_TOPIC_PARTITIONS_KEY => {
+            println!("Handling DescribeTopicPartitions request");
+            describe_topic_partitions::handle(broker, &header, request_buffer)
+        }
+        _ => unreachable!("every SUPPORTED_APIS entry has a handler"),
+    };
+
+    match response {
+        Ok(response) => RequestOutcome::Respond(with_throttle_time(api, api_version, response)),
+        Err(e) => {
+            // Handlers decode the whole body before acting on any of it, so
+            // nothing was done; and the frame was read whole, so the next one
+            // starts where it ends
+            println!(
+                "Malformed {} request: {}, answering INVALID_REQUEST",
+                api.name, e
+            );
+            RequestOutcome::Respond(build_error_response(
+                api,
+                api_version,
+                correlation_id,
+                INVALID_REQUEST,
+            ))
+        }
+    }
+}
 
+/// Fills in a handler's response's throttle_time_ms with the delay applied to
+/// it. No quotas are enforced, so nothing is ever held back.
+fn with_throttle_time(api: &SupportedApi, api_version: i16, mut response: Vec<u8>) -> Vec<u8> {
+    if let Some(slot) = api.throttle_slot(api_version, &response) {
+        throttle::fill(&mut response, slot, Duration::ZERO);
+    }
     response
 }
 
-/// Builds APIVersions response
-fn build_api_versions_response(correlation_id: u32, api_version: u16) -> Vec<u8> {
-    let error_code: u16 = if api_version <= 4 { 0 } else { 35 };
-
-    let response_message_size: u32 = 26;
-    let response_message_size_bytes = response_message_size.to_be_bytes();
-    let correlation_id_response_bytes = correlation_id.to_be_bytes();
-    let error_code_bytes = error_code.to_be_bytes();
-
-    let api_count_array: u8 = 3; // 2 APIs + 1 = 3
-
-    // First API: APIVersions
-    let api_key_1: u16 = 18;
-    let min_version_1: u16 = 0;
-    let max_version_1: u16 = 4;
-    let api_tagged_fields_1: u8 = 0;
-
-    // Second API: DescribeTopicPartitions
-    let api_key_2: u16 = 75;
-    let min_version_2: u16 = 0;
-    let max_version_2: u16 = 0;
-    let api_tagged_fields_2: u8 = 0;
-
-    let throttle_time_ms: u32 = 0;
-    let response_tagged_fields: u8 = 0;
-
-    let mut response = Vec::new();
-    response.extend_from_slice(&response_message_size_bytes);
-    response.extend_from_slice(&correlation_id_response_bytes);
-    response.extend_from_slice(&error_code_bytes);
-    response.extend_from_slice(&[api_count_array]);
-
-    // First API: APIVersions
-    response.extend_from_slice(&api_key_1.to_be_bytes());
-    response.extend_from_slice(&min_version_1.to_be_bytes());
-    response.extend_from_slice(&max_version_1.to_be_bytes());
-    response.extend_from_slice(&[api_tagged_fields_1]);
-
-    // Second API: DescribeTopicPartitions
-    response.extend_from_slice(&api_key_2.to_be_bytes());
-    response.extend_from_slice(&min_version_2.to_be_bytes());
-    response.extend_from_slice(&max_version_2.to_be_bytes());
-    response.extend_from_slice(&[api_tagged_fields_2]);
-
-    response.extend_from_slice(&throttle_time_ms.to_be_bytes());
-    response.extend_from_slice(&[response_tagged_fields]);
+/// Most responses sent in one write, each one entry of its iovec.
+const MAX_COALESCED_RESPONSES: usize = 64;
 
-    response
+/// Responses a connection has built but not yet sent, in request order.
+///
+/// While the connection's next request has already arrived whole, its response
+/// can follow the ones before it in a single write, so a client pipelining
+/// small requests isn't answered with a write per response. Nothing is held
+/// back waiting for a request: as soon as none is buffered, the next may itself
+/// be held (see `may_wait`), or the batch reaches `response.coalesce.max.bytes`,
+/// what's pending is written.
+#[derive(Default)]
+struct PendingResponses {
+    responses: Vec<Vec<u8>>,
+    bytes: usize,
+}
+
+impl PendingResponses {
+    fn push(&mut self, response: Vec<u8>) {
+        self.bytes += response.len();
+        self.responses.push(response);
+    }
+
+    /// Whether to hold the pending responses back for the request in
+    /// `buffered`, the bytes read from the socket but not yet consumed.
+    fn can_wait(&self, buffered: &[u8], max_bytes: usize) -> bool {
+        let next_request = buffered
+            .get(..MESSAGE_SIZE_LEN)
+            .map(|size| u32::from_be_bytes(size.try_into().unwrap_or([0; 4])) as usize)
+            .and_then(|size| buffered.get(MESSAGE_SIZE_LEN..MESSAGE_SIZE_LEN + size));
+        next_request.is_some_and(|request| !may_wait(request))
+            && self.responses.len() < MAX_COALESCED_RESPONSES
+            && self.bytes < max_bytes
+    }
+
+    /// Writes every pending response and counts them against the listener.
+    fn send(&mut self, stream: &TcpStream, stats: &ListenerStats) -> io::Result<()> {
+        if self.responses.is_empty() {
+            return Ok(());
+        }
+        write_responses(stream, &self.responses)?;
+        match self.responses.len() {
+            1 => println!("Response sent."),
+            count => println!(
+                "{} responses sent in one write ({} bytes).",
+                count, self.bytes
+            ),
+        }
+        stats.responses_written(self.responses.len());
+        for response in self.responses.drain(..) {
+            buffers::recycle(response);
+        }
+        self.bytes = 0;
+        Ok(())
+    }
+}
+
+/// Whether `request` may be held before it's answered, so that responses
+/// pending behind it would be held too: a Fetch waiting for data, an acks=-1
+/// Produce waiting on a flush, or a JoinGroup or SyncGroup waiting on the rest
+/// of its group.
+fn may_wait(request: &[u8]) -> bool {
+    let Ok(header) = RequestHeader::parse(request) else {
+        return false;
+    };
+    match header.api_key {
+        FETCH_KEY => fetch::may_wait(&header, request),
+        PRODUCE_KEY => produce::may_wait(&header, request),
+        JOIN_GROUP_KEY | SYNC_GROUP_KEY => true,
+        _ => false,
+    }
+}
+
+/// Writes whole responses, in order, resuming after partial writes. Each write
+/// is vectored over what is left of them, so several responses need no copying
+/// into one buffer to go out together.
+///
+/// The socket's write timeout bounds how long a single write may make no progress,
+/// so a client that stops reading gets disconnected instead of pinning this thread
+/// (and its memory) forever. Reads from that client naturally stop while we wait.
+fn write_responses(mut stream: &TcpStream, responses: &[Vec<u8>]) -> io::Result<()> {
+    let total: usize = responses.iter().map(Vec::len).sum();
+    let mut written = 0;
+    while written < total {
+        let mut skip = written;
+        let unwritten = responses.iter().filter_map(|response| {
+            if skip >= response.len() {
+                skip -= response.len();
+                return None;
+            }
+            let rest = &response[skip..];
+            skip = 0;
+            Some(rest)
+        });
+        let mut slices = [IoSlice::new(&[]); MAX_COALESCED_RESPONSES];
+        let mut count = 0;
+        for (slice, rest) in slices.iter_mut().zip(unwritten) {
+            *slice = IoSlice::new(rest);
+            count += 1;
+        }
+        match stream.write_vectored(&slices[..count]) {
+            Ok(0) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WriteZero,
+                    format!(
+                        "socket closed after {} of {} response bytes",
+                        written, total
+                    ),
+                ))
+            }
+            Ok(n) => written += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!(
+                        "client stopped reading, {} of {} response bytes written",
+                        written, total
+                    ),
+                ))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// Reads the body of a frame whose size prefix has already arrived. The whole body
+/// must arrive before `timeout` elapses: the deadline covers the frame, not each
+/// read, so a client trickling bytes can't hold the connection open indefinitely.
+fn read_request_body(
+    stream: &mut BufReader<&TcpStream>,
+    buffer: &mut [u8],
+    timeout: Duration,
+) -> io::Result<()> {
+    let deadline = Instant::now() + timeout;
+    let mut filled = 0;
+    while filled < buffer.len() {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        if remaining.is_zero() {
+            return Err(request_read_timed_out(filled, buffer.len()));
+        }
+        stream.get_ref().set_read_timeout(Some(remaining))?;
+        match stream.read(&mut buffer[filled..]) {
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut
+                ) =>
+            {
+                return Err(request_read_timed_out(filled, buffer.len()))
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    // Waiting for the next frame's size prefix is unbounded again
+    stream.get_ref().set_read_timeout(None)
 }
 
-fn handle_client(mut stream: TcpStream) -> io::Result<()> {
-    println!("Handling connection from: {}", stream.peer_addr()?);
+fn request_read_timed_out(filled: usize, len: usize) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::TimedOut,
+        format!("request stalled after {} of {} body bytes", filled, len),
+    )
+}
+
+/// Why a connection's request loop ended.
+enum CloseReason {
+    ClientDisconnected,
+    InvalidFrameSize,
+    FrameTooLarge,
+    TruncatedFrame,
+    RequestReadTimeout,
+    RequestReadFailed,
+    MalformedRequest,
+    ResponseWriteFailed,
+    AuthenticationFailed,
+}
+
+impl fmt::Display for CloseReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            CloseReason::ClientDisconnected => "client disconnected",
+            CloseReason::InvalidFrameSize => "invalid frame size",
+            CloseReason::FrameTooLarge => "frame larger than socket.request.max.bytes",
+            CloseReason::TruncatedFrame => "client disconnected mid-frame",
+            CloseReason::RequestReadTimeout => {
+                "request body not received within request.read.timeout.ms"
+            }
+            CloseReason::RequestReadFailed => "error reading request body",
+            CloseReason::MalformedRequest => "malformed request",
+            CloseReason::ResponseWriteFailed => "error writing response",
+            CloseReason::AuthenticationFailed => "SASL authentication failed",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+fn handle_client(stream: TcpStream, broker: &Broker, listener: usize) -> io::Result<()> {
+    let config = &broker.config;
+    let peer_addr = stream.peer_addr()?;
+    let listener_name = &config.listeners[listener].name;
+    println!(
+        "Handling connection from: {} on listener {}",
+        peer_addr, listener_name
+    );
+    stream.set_write_timeout(Some(config.response_write_timeout))?;
+
+    let mut recorder = config
+        .session_record_dir
+        .as_deref()
+        .and_then(|dir| SessionRecorder::create(dir, peer_addr));
+
+    let mut connection = ConnectionState::on_listener(listener, peer_addr);
+    let stats = &broker.listener_stats[listener];
+    // Buffered, so requests pipelined together are read in one go, and one
+    // already here can be seen without a read
+    let mut reader = BufReader::new(&stream);
+    let mut pending = PendingResponses::default();
+    let close_reason = loop {
+        if !pending.can_wait(reader.buffer(), config.response_coalesce_max_bytes) {
+            if let Err(e) = pending.send(&stream, stats) {
+                println!("Error writing response: {}, closing connection", e);
+                break CloseReason::ResponseWriteFailed;
+            }
+        }
 
-    loop {
         //Initial buffer to read just the message_size
         let mut initial_bytes = vec![0; MESSAGE_SIZE_LEN];
 
-        let total_message_size = match stream.read_exact(&mut initial_bytes) {
-            Ok(()) => u32::from_be_bytes(
-                initial_bytes
-                    .as_slice()
-                    .try_into()
-                    .unwrap_or_else(|_| [0; 4]),
-            ),
+        let total_message_size = match reader.read_exact(&mut initial_bytes) {
+            Ok(()) => u32::from_be_bytes(initial_bytes.as_slice().try_into().unwrap_or([0; 4])),
             Err(e) => {
                 println!("Client disconnected: {}", e);
-                break;
+                break CloseReason::ClientDisconnected;
             }
         };
         println!("Total message size indicated: {} bytes", total_message_size);
@@ -173,7 +1106,16 @@ fn handle_client(mut stream: TcpStream) -> io::Result<()> {
                 total_message_size,
                 HEADER_LEN - MESSAGE_SIZE_LEN
             );
-            break;
+            break CloseReason::InvalidFrameSize;
+        }
+        // Nothing past the size prefix is read: a frame this size is more
+        // likely a misread prefix than a request
+        if total_message_size > config.max_request_bytes {
+            println!(
+                "Message size {} exceeds socket.request.max.bytes {}, breaking connection",
+                total_message_size, config.max_request_bytes
+            );
+            break CloseReason::FrameTooLarge;
         }
 
         //Now, read the rest of the message (api_key, api_version, correlation_id, and if any a body)
@@ -181,126 +1123,105 @@ fn handle_client(mut stream: TcpStream) -> io::Result<()> {
         let remaining_bytes = total_message_size as usize;
         let mut full_request_buffer = vec![0; remaining_bytes];
 
-        if let Err(e) = stream.read_exact(&mut full_request_buffer) {
+        if let Err(e) = read_request_body(
+            &mut reader,
+            &mut full_request_buffer,
+            config.request_read_timeout,
+        ) {
             println!("Error reading request body: {}", e);
-            break;
+            match e.kind() {
+                io::ErrorKind::TimedOut => break CloseReason::RequestReadTimeout,
+                io::ErrorKind::UnexpectedEof => break CloseReason::TruncatedFrame,
+                _ => break CloseReason::RequestReadFailed,
+            }
         }
 
-        let correlation_id_offset_in_remaining = API_KEY_LEN + API_VERSION_LEN;
-
-        // Verify bounds before slicing to prevent panic
-        if remaining_bytes < correlation_id_offset_in_remaining + CORRELATION_ID_LEN {
-            println!("Message too short to contain correlation ID, breaking connection");
-            break;
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record_request(&full_request_buffer);
         }
 
-        //Extract the correlation ID from bytes 8-11
-        let correlation_id_bytes_slice = &full_request_buffer[correlation_id_offset_in_remaining
-            ..correlation_id_offset_in_remaining + CORRELATION_ID_LEN];
-
-        let correlation_id = match correlation_id_bytes_slice.try_into() {
-            Ok(bytes) => u32::from_be_bytes(bytes),
-            Err(_) => {
-                println!("Failed to get 4 bytes for correlation ID, breaking connection");
-                break;
-            }
-        };
-
-        //Extract the API key
-        let api_key_bytes = &full_request_buffer[0..API_KEY_LEN];
-        let api_key = match api_key_bytes.try_into() {
-            Ok(bytes) => u16::from_be_bytes(bytes),
-            Err(_) => {
-                println!("Failed to get 2 bytes for API key, breaking connection");
-                break;
-            }
-        };
-
-        //Extract the API version
-        let api_version_offset = API_KEY_LEN; //Skip API key, those are 2 bytes
-        let api_version_bytes =
-            &full_request_buffer[api_version_offset..api_version_offset + API_VERSION_LEN];
-
-        let api_version = match api_version_bytes.try_into() {
-            Ok(bytes) => u16::from_be_bytes(bytes),
-            Err(_) => {
-                println!("Failed to get 2 bytes for API version, breaking connection");
-                break;
-            }
-        };
-
-        println!("Extracted Correlation ID (u32): {}", correlation_id);
-        println!(
-            "Extracted Correlation ID (bytes): {:?}",
-            correlation_id_bytes_slice
-        );
-        println!("Extracted API Key: {}", api_key);
-        println!("Extracted API Version: {}", api_version);
-
-        // Build response based on API key
-        let response = if api_key == 18 {
-            // APIVersions request
-            println!("Handling APIVersions request");
-            build_api_versions_response(correlation_id, api_version)
-        } else if api_key == 75 {
-            // DescribeTopicPartitions request
-            println!("Handling DescribeTopicPartitions request");
-            let topic_name = parse_topic_name(&full_request_buffer);
-            println!("Parsed topic name: '{}'", topic_name);
-            build_describe_topic_partitions_response(correlation_id, &topic_name)
-        } else {
-            // Unknown API key - return error
-            println!("Unknown API key: {}", api_key);
-            vec![
-                0,
-                0,
-                0,
-                8,
-                (correlation_id >> 24) as u8,
-                (correlation_id >> 16) as u8,
-                (correlation_id >> 8) as u8,
-                correlation_id as u8,
-                0,
-                35,
-                0,
-                0,
-            ] // Error code 35 = UNSUPPORTED_VERSION
-        };
-
-        println!("Sending response: {:?}", response);
+        let (response, close_after) =
+            match handle_request(broker, &mut connection, &full_request_buffer) {
+                RequestOutcome::Respond(response) => (response, false),
+                RequestOutcome::RespondThenClose(response) => (response, true),
+                RequestOutcome::NoResponse => continue,
+                RequestOutcome::Close => break CloseReason::MalformedRequest,
+            };
 
-        //Send the response back to the client
-        if let Err(e) = stream.write_all(&response) {
-            println!("Error writing response: {}", e);
-            break;
+        if let Some(recorder) = recorder.as_mut() {
+            recorder.record_response(&response);
         }
 
-        //Just to pass the fucking test, those idiots set a rule up their fucking asshole, 5:08 AM
-        //dealing with this shit
-        if let Err(e) = stream.flush() {
-            println!("Error flushing stream: {}", e);
-            break;
+        println!("Sending response: {:?}", response);
+        pending.push(response);
+        if close_after {
+            break CloseReason::AuthenticationFailed;
         }
-        println!("Response sent.");
 
         //stream.shutdown(Shutdown::Both)?; // Shutdown both read and write, commented out since now we
         //will handle multiple requests in the client.
+    };
+    // Whatever was answered before the connection ended is still owed
+    if !matches!(close_reason, CloseReason::ResponseWriteFailed) {
+        if let Err(e) = pending.send(&stream, stats) {
+            println!("Error writing response: {}, closing connection", e);
+        }
+    }
+    println!("Closing connection from {}: {}", peer_addr, close_reason);
+    println!(
+        "Listener {}: {}",
+        listener_name, broker.listener_stats[listener]
+    );
+    if let Some((client, true)) = &connection.client {
+        if let Some(line) = broker.clients.disconnected(client) {
+            println!("{}", line);
+        }
     }
     Ok(())
 }
 
-fn main() -> io::Result<()> {
-    let listener = TcpListener::bind("127.0.0.1:9092").unwrap();
-    println!("Server listening on: {}", listener.local_addr()?);
+/// The startup phases that load state, in order. Requests are answered with
+/// COORDINATOR_LOAD_IN_PROGRESS until they (and binding the listener) are done.
+fn load_state(broker: &Broker) -> anyhow::Result<()> {
+    broker.load_metadata()?;
+    broker.lifecycle.complete(Phase::MetadataReplayed);
+    // After the registry, which says which partitions' logs to keep
+    broker.recover_logs()?;
+    broker.lifecycle.complete(Phase::LogsRecovered);
+    // No group or transaction coordinators keep state yet
+    broker.lifecycle.complete(Phase::CoordinatorsLoaded);
+    Ok(())
+}
 
-    //Accept incoming connections
-    for stream in listener.incoming() {
+/// Accepts connections on the `listener`th listener, each served on its own
+/// thread. Connections from outside the listener's allowed networks are closed
+/// at once.
+fn serve(socket: TcpListener, broker: Arc<Broker>, listener: usize) {
+    let config = &broker.config.listeners[listener];
+    for stream in socket.incoming() {
         match stream {
             Ok(stream) => {
+                let allowed = stream
+                    .peer_addr()
+                    .is_ok_and(|peer| config.policy.allows_peer(peer.ip()));
+                broker.listener_stats[listener].connection(!allowed);
+                if !allowed {
+                    println!(
+                        "Refusing connection from {:?} on listener {}: not in allowed.cidrs",
+                        stream.peer_addr().ok(),
+                        config.name
+                    );
+                    println!(
+                        "Listener {}: {}",
+                        config.name, broker.listener_stats[listener]
+                    );
+                    continue;
+                }
                 //spawn a new thread to handle each connection
                 //This allows the server to handle multiple clients concurrently
+                let broker = Arc::clone(&broker);
                 std::thread::spawn(move || {
-                    if let Err(e) = handle_client(stream) {
+                    if let Err(e) = handle_client(stream, &broker, listener) {
                         eprintln!("Error handling client: {}", e);
                     }
                 });
@@ -311,5 +1232,140 @@ fn main() -> io::Result<()> {
             }
         }
     }
+}
+
+fn main() -> anyhow::Result<()> {
+    let args: Vec<String> = std::env::args().collect();
+    if args.get(1).map(String::as_str) == Some("replay") {
+        let Some(path) = args.get(2) else {
+            anyhow::bail!(
+                "usage: {} replay <session file> [server.properties]",
+                args[0]
+            );
+        };
+        // Replay against the same topics the recording broker had, but not its
+        // logs: a replay starts from empty ones, as the recording did
+        let config = match args.get(3) {
+            Some(properties) => BrokerConfig::load(Path::new(properties))?,
+            None => BrokerConfig::default(),
+        };
+        let broker = Broker::with_log_store(config, Arc::new(MemoryLogStore::default()));
+        broker.lifecycle.complete(Phase::ConfigLoaded);
+        // Replay feeds requests straight to handle_request; there is no listener
+        broker.lifecycle.complete(Phase::ListenerBound);
+        load_state(&broker)?;
+        // A session file is one connection's requests, replayed as if on the
+        // first listener
+        let mut connection = ConnectionState::default();
+        return session::replay(Path::new(path), |request| {
+            match handle_request(&broker, &mut connection, request) {
+                RequestOutcome::Respond(response) | RequestOutcome::RespondThenClose(response) => {
+                    Some(response)
+                }
+                RequestOutcome::NoResponse | RequestOutcome::Close => None,
+            }
+        });
+    }
+
+    if args.get(1).map(String::as_str) == Some("upgrade") {
+        let usage = || {
+            anyhow::anyhow!(
+                "usage: {} upgrade --log-dirs <dir>[,<dir>...] [--dry-run]",
+                args[0]
+            )
+        };
+        let mut log_dirs = None;
+        let mut dry_run = false;
+        let mut options = args[2..].iter();
+        while let Some(option) = options.next() {
+            match option.as_str() {
+                "--log-dirs" => log_dirs = Some(options.next().ok_or_else(usage)?),
+                "--dry-run" => dry_run = true,
+                _ => return Err(usage()),
+            }
+        }
+        // The same upgrade a broker runs on its log directory at startup, for
+        // one that has stopped; a dry run changes nothing
+        for log_dir in log_dirs.ok_or_else(usage)?.split(',') {
+            file_log_store::upgrade(Path::new(log_dir), dry_run)?;
+        }
+        return Ok(());
+    }
+
+    if args.get(1).map(String::as_str) == Some("bench") {
+        let Some(workload) = args.get(2) else {
+            anyhow::bail!(
+                "usage: {} bench <workload.properties> [server.properties]",
+                args[0]
+            );
+        };
+        let mut config = match args.get(3) {
+            Some(properties) => BrokerConfig::load(Path::new(properties))?,
+            None => BrokerConfig::default(),
+        };
+        // A port of its own, so a bench can run beside a real broker, and no
+        // readiness file to pass for one
+        config.listeners.truncate(1);
+        config.listeners[0].address = "127.0.0.1:0".to_string();
+        config.readiness_file = None;
+        // The bench measures the request path, and leaves no logs behind: with
+        // a server.properties asking for log.store=file they go to a scratch
+        // directory, removed afterwards, so flushes cost what they would
+        let scratch_dir = (args.len() > 3 && config.log_store == LogStoreKind::File)
+            .then(|| std::env::temp_dir().join(format!("eventor-bench-{}", std::process::id())));
+        let logs: Arc<dyn LogStore> = match &scratch_dir {
+            Some(dir) => Arc::new(FileLogStore::new(dir.clone())),
+            None => Arc::new(MemoryLogStore::default()),
+        };
+        // Nor the topic changes and config overrides a file store's broker
+        // saves beside the metadata log
+        config.log_store = LogStoreKind::Memory;
+        let broker = Arc::new(Broker::with_log_store(config, logs));
+        broker.lifecycle.complete(Phase::ConfigLoaded);
+        let listener = &broker.config.listeners[0];
+        let socket = TcpListener::bind(&listener.address)
+            .with_context(|| format!("binding listener to {}", listener.address))?;
+        let address = socket.local_addr()?;
+        std::thread::spawn({
+            let broker = Arc::clone(&broker);
+            move || serve(socket, broker, 0)
+        });
+        broker.lifecycle.complete(Phase::ListenerBound);
+        load_state(&broker)?;
+        let result = bench::run(Path::new(workload), &broker, address);
+        if let Some(dir) = &scratch_dir {
+            let _ = std::fs::remove_dir_all(dir);
+        }
+        return result;
+    }
+
+    let broker = Arc::new(Broker::new(BrokerConfig::from_args(&args)?));
+    broker.lifecycle.complete(Phase::ConfigLoaded);
+    lifecycle::spawn_scheduler(Arc::clone(&broker));
+
+    // Bound before loading so clients connecting now are told to retry rather
+    // than refused
+    let mut servers = Vec::new();
+    for (index, listener) in broker.config.listeners.iter().enumerate() {
+        let socket = TcpListener::bind(&listener.address).with_context(|| {
+            format!("binding listener {} to {}", listener.name, listener.address)
+        })?;
+        println!(
+            "Server listening on: {} ({}, {})",
+            socket.local_addr()?,
+            listener.name,
+            listener.protocol
+        );
+        servers.push(std::thread::spawn({
+            let broker = Arc::clone(&broker);
+            move || serve(socket, broker, index)
+        }));
+    }
+    broker.lifecycle.complete(Phase::ListenerBound);
+
+    load_state(&broker)?;
+    for server in servers {
+        let _ = server.join();
+    }
     Ok(())
 }
@@ -0,0 +1,438 @@
+//! Topic metadata read from the KRaft `__cluster_metadata` log at startup.
+//!
+//! Each record value in that log is a metadata record:
+//! `frame_version: i8 | record_type: i8 | record_version: i8 | fields...`, with
+//! the fields in flexible (compact) encoding. Only the record types needed to
+//! know which topics and partitions exist are decoded; the rest are skipped.
+//!
+//! The controller periodically compacts the log into a snapshot,
+//! `<end_offset>-<epoch>.checkpoint`, holding the state as of every record
+//! before `end_offset` between a SnapshotHeader and a SnapshotFooter control
+//! batch. Loading starts from the latest snapshot and replays only the log
+//! records at or after its end offset, since earlier ones are already in it
+//! (and their segments may have been deleted).
+//!
+//! The cluster id isn't in the log: `kafka-storage format` writes it to
+//! `meta.properties` at the top of the log directory.
+//!
+//! Topics created through CreateTopics are added to the same registry,
+//! CreatePartitions grows them, IncrementalAlterConfigs changes their configs
+//! and DeleteTopics removes them, but none of it is written back to the log.
+//! `TopicChanges` saves the topics created, grown and deleted, and
+//! `ConfigOverrides` the configs altered, each in a file of its own, and a
+//! restart replays both onto the topics the log has. Those changes, unlike
+//! the replays, are announced as `TopicEvent`s.
+//!
+//! Topics are keyed by their id. Names are an index onto the ids: no two live
+//! topics share a name, and a name deleted and created again points at the new
+//! topic's id from then on. Everything else in the broker that refers to a
+//! topic (partition logs, committed offsets, parked fetches) holds its id, so a
+//! reference to a deleted topic finds nothing rather than its successor. The
+//! name-based APIs resolve a name once, with `topic_id`, and carry the id from
+//! there.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::Sender;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+
+use crate::codec::{DecodeError, Decoder};
+use crate::config::parse_properties;
+use crate::ids::{LeaderEpoch, Offset, PartitionId, TopicId};
+use crate::record_batch::RecordBatch;
+use crate::topic_events::TopicEvent;
+
+const METADATA_DIR: &str = "__cluster_metadata-0";
+const LOG_SUFFIX: &str = ".log";
+const SNAPSHOT_SUFFIX: &str = ".checkpoint";
+const META_PROPERTIES: &str = "meta.properties";
+
+const TOPIC_RECORD: i8 = 2;
+const PARTITION_RECORD: i8 = 3;
+const REMOVE_TOPIC_RECORD: i8 = 9;
+
+pub struct TopicMetadata {
+    pub name: String,
+    pub topic_id: TopicId,
+    pub partitions: Vec<PartitionId>,
+    /// Config overrides given at creation, in request order, as since altered.
+    /// Topics loaded from the log have none.
+    pub configs: Vec<(String, Option<String>)>,
+}
+
+/// The topics known to this broker.
+pub struct ClusterMetadata {
+    /// `cluster.id` from `meta.properties`; `None` for an unformatted log directory.
+    pub cluster_id: Option<String>,
+    /// Every live topic.
+    topics: HashMap<TopicId, TopicMetadata>,
+    /// The id each live topic's name points at.
+    names: HashMap<String, TopicId>,
+    /// Live topic ids, in the order their topics were created.
+    created: Vec<TopicId>,
+    /// Ids of the topics deleted, by the log or since.
+    deleted: HashSet<TopicId>,
+    /// Where changes are announced, once `announce_changes` has been called.
+    events: Option<Sender<TopicEvent>>,
+}
+
+/// A `<end_offset>-<epoch>.checkpoint` file. The snapshot holds every record
+/// with an offset below `end_offset`.
+struct Snapshot {
+    end_offset: Offset,
+    epoch: LeaderEpoch,
+    path: PathBuf,
+}
+
+impl Snapshot {
+    fn from_file_name(dir: &Path, name: &str) -> Option<Snapshot> {
+        let (end_offset, epoch) = name.strip_suffix(SNAPSHOT_SUFFIX)?.split_once('-')?;
+        Some(Snapshot {
+            end_offset: Offset(end_offset.parse().ok()?),
+            epoch: LeaderEpoch(epoch.parse().ok()?),
+            path: dir.join(name),
+        })
+    }
+}
+
+impl ClusterMetadata {
+    pub fn empty() -> ClusterMetadata {
+        ClusterMetadata {
+            cluster_id: None,
+            topics: HashMap::new(),
+            names: HashMap::new(),
+            created: Vec::new(),
+            deleted: HashSet::new(),
+            events: None,
+        }
+    }
+
+    /// Loads `<log_dir>/__cluster_metadata-0`. A missing directory means a fresh
+    /// broker with no topics; one that can't be parsed is a startup error, since
+    /// serving with half the topics missing would be worse than not serving.
+    pub fn load(log_dir: &Path) -> Result<ClusterMetadata> {
+        let mut metadata = ClusterMetadata {
+            cluster_id: load_cluster_id(log_dir)?,
+            ..ClusterMetadata::empty()
+        };
+        let dir = log_dir.join(METADATA_DIR);
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {
+                println!(
+                    "No cluster metadata log at {}, starting with no topics",
+                    dir.display()
+                );
+                return Ok(metadata);
+            }
+            Err(e) => {
+                return Err(e).with_context(|| format!("reading metadata log {}", dir.display()))
+            }
+        };
+
+        let mut snapshots = Vec::new();
+        let mut segments = Vec::new();
+        for entry in entries {
+            let entry = entry.with_context(|| format!("reading metadata log {}", dir.display()))?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if let Some(snapshot) = Snapshot::from_file_name(&dir, &name) {
+                snapshots.push(snapshot);
+            } else if let Some(base_offset) = name
+                .strip_suffix(LOG_SUFFIX)
+                .and_then(|base_offset| base_offset.parse().ok().map(Offset))
+            {
+                segments.push((base_offset, entry.path()));
+            }
+        }
+        segments.sort();
+
+        let mut replay_from = Offset(0);
+        let latest = snapshots
+            .into_iter()
+            .max_by_key(|snapshot| (snapshot.end_offset, snapshot.epoch));
+        if let Some(snapshot) = latest {
+            metadata.apply_file(&snapshot.path, Offset(0))?;
+            println!(
+                "Loaded metadata snapshot {} (end offset {})",
+                snapshot.path.display(),
+                snapshot.end_offset
+            );
+            replay_from = snapshot.end_offset;
+        }
+        for (_, path) in &segments {
+            metadata.apply_file(path, replay_from)?;
+        }
+
+        for topic in metadata.topics() {
+            println!(
+                "Loaded topic '{}' with {} partition(s)",
+                topic.name,
+                topic.partitions.len()
+            );
+        }
+        Ok(metadata)
+    }
+
+    /// Applies the records in a snapshot or log segment whose offset is at least
+    /// `from_offset`. Control batches (snapshot header and footer) carry no state.
+    fn apply_file(&mut self, path: &Path, from_offset: Offset) -> Result<()> {
+        let contents =
+            fs::read(path).with_context(|| format!("reading metadata log {}", path.display()))?;
+        let batches = RecordBatch::parse_all(&contents)
+            .with_context(|| format!("parsing metadata log {}", path.display()))?;
+        for batch in batches.iter().filter(|batch| !batch.is_control()) {
+            let records = batch
+                .records()
+                .with_context(|| format!("parsing metadata log {}", path.display()))?;
+            for record in records.iter().filter(|record| record.offset >= from_offset) {
+                let Some(value) = record.value else {
+                    continue;
+                };
+                self.apply(value).with_context(|| {
+                    format!(
+                        "decoding metadata record at offset {} of {}",
+                        record.offset,
+                        path.display()
+                    )
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// The id the name points at, for the name-based APIs to carry on with.
+    pub fn topic_id(&self, name: &str) -> Option<TopicId> {
+        self.names.get(name).copied()
+    }
+
+    /// Looks a topic up by name; the same as `topic_by_id(topic_id(name))`.
+    pub fn topic(&self, name: &str) -> Option<&TopicMetadata> {
+        self.topic_by_id(self.topic_id(name)?)
+    }
+
+    pub fn topic_by_id(&self, topic_id: TopicId) -> Option<&TopicMetadata> {
+        self.topics.get(&topic_id)
+    }
+
+    /// Whether the topic was deleted, rather than never known. Its partition
+    /// logs are still deleted if a restart finds them.
+    pub fn was_deleted(&self, topic_id: TopicId) -> bool {
+        self.deleted.contains(&topic_id)
+    }
+
+    /// Every topic, in the order the log (or CreateTopics) created them.
+    pub fn topics(&self) -> impl Iterator<Item = &TopicMetadata> {
+        self.created.iter().map(|topic_id| &self.topics[topic_id])
+    }
+
+    /// Registers a new topic with partitions `0..partition_count` under a fresh
+    /// random topic id. The caller has already checked the name is free.
+    pub fn create_topic(
+        &mut self,
+        name: String,
+        partition_count: i32,
+        configs: Vec<(String, Option<String>)>,
+    ) -> &TopicMetadata {
+        let topic_id = loop {
+            // Never reuse an id, even one whose topic is gone, so a stale
+            // reference can't come to point at a new topic
+            let topic_id = TopicId(random_uuid());
+            if topic_id != TopicId::ZERO
+                && self.topic_by_id(topic_id).is_none()
+                && !self.was_deleted(topic_id)
+            {
+                break topic_id;
+            }
+        };
+        self.insert(TopicMetadata {
+            name,
+            topic_id,
+            partitions: (0..partition_count).map(PartitionId).collect(),
+            configs,
+        });
+        let topic = &self.topics[&topic_id];
+        if let Some(events) = &self.events {
+            let _ = events.send(TopicEvent::Created {
+                name: topic.name.clone(),
+                topic_id,
+                partition_count: topic.partitions.len(),
+            });
+        }
+        topic
+    }
+
+    /// Adds a topic CreateTopics created before a restart, under the id it
+    /// had, unless the log has it, a topic of its name, or deleted it since.
+    pub fn restore_topic(&mut self, topic: TopicMetadata) {
+        if self.topic_by_id(topic.topic_id).is_none()
+            && self.topic_id(&topic.name).is_none()
+            && !self.was_deleted(topic.topic_id)
+        {
+            self.insert(topic);
+        }
+    }
+
+    /// Takes out a topic DeleteTopics deleted before a restart if the log
+    /// brought it back, and counts it as deleted either way.
+    pub fn restore_deletion(&mut self, topic_id: TopicId) {
+        self.remove(topic_id);
+        self.deleted.insert(topic_id);
+    }
+
+    /// Removes a topic from the registry, returning it if it existed. Its name
+    /// is free again at once.
+    pub fn delete_topic(&mut self, topic_id: TopicId) -> Option<TopicMetadata> {
+        let topic = self.remove(topic_id)?;
+        if let Some(events) = &self.events {
+            let _ = events.send(TopicEvent::Deleted {
+                name: topic.name.clone(),
+                topic_id: topic.topic_id,
+            });
+        }
+        Some(topic)
+    }
+
+    /// Grows a topic to `partition_count` partitions, numbering the new ones
+    /// on from its last. The caller has already checked the count is larger.
+    pub fn add_partitions(
+        &mut self,
+        topic_id: TopicId,
+        partition_count: i32,
+    ) -> Option<&TopicMetadata> {
+        let topic = self.topics.get_mut(&topic_id)?;
+        let current = topic.partitions.len() as i32;
+        topic
+            .partitions
+            .extend((current..partition_count).map(PartitionId));
+        if let Some(events) = &self.events {
+            let _ = events.send(TopicEvent::PartitionsAdded {
+                name: topic.name.clone(),
+                topic_id,
+                partition_count: topic.partitions.len(),
+            });
+        }
+        Some(topic)
+    }
+
+    /// Replaces a topic's config overrides. The caller has already checked
+    /// every one of them.
+    pub fn set_configs(
+        &mut self,
+        topic_id: TopicId,
+        configs: Vec<(String, Option<String>)>,
+    ) -> Option<&TopicMetadata> {
+        let topic = self.topics.get_mut(&topic_id)?;
+        topic.configs = configs;
+        if let Some(events) = &self.events {
+            let _ = events.send(TopicEvent::ConfigsAltered {
+                name: topic.name.clone(),
+                topic_id,
+                config_count: topic.configs.len(),
+            });
+        }
+        Some(topic)
+    }
+
+    /// Adds a topic, pointing its name at it. A live topic already holding the
+    /// name is dropped: only replaying a log that deletes a topic without a
+    /// RemoveTopicRecord could get here with one, and one name can't serve two.
+    fn insert(&mut self, topic: TopicMetadata) {
+        if let Some(replaced) = self.names.insert(topic.name.clone(), topic.topic_id) {
+            println!(
+                "Topic '{}' ({}) replaced by {} without being removed",
+                topic.name, replaced, topic.topic_id
+            );
+            // The name already points at the new id, so this leaves it be
+            self.remove(replaced);
+        }
+        self.created.push(topic.topic_id);
+        self.topics.insert(topic.topic_id, topic);
+    }
+
+    fn remove(&mut self, topic_id: TopicId) -> Option<TopicMetadata> {
+        let topic = self.topics.remove(&topic_id)?;
+        if self.names.get(&topic.name) == Some(&topic_id) {
+            self.names.remove(&topic.name);
+        }
+        self.created.retain(|created| *created != topic_id);
+        self.deleted.insert(topic_id);
+        Some(topic)
+    }
+
+    /// Sends a `TopicEvent` to `events` for every later change. Sending
+    /// happens under the caller's write lock, which is what orders the events.
+    pub fn announce_changes(&mut self, events: Sender<TopicEvent>) {
+        self.events = Some(events);
+    }
+
+    fn apply(&mut self, value: &[u8]) -> Result<(), DecodeError> {
+        let mut decoder = Decoder::new(value);
+        decoder.read_i8()?; // frame_version
+        let record_type = decoder.read_i8()?;
+        decoder.read_i8()?; // record_version
+
+        match record_type {
+            TOPIC_RECORD => {
+                let name = decoder.read_compact_string()?;
+                let topic_id = TopicId(decoder.read_uuid()?);
+                self.insert(TopicMetadata {
+                    name,
+                    topic_id,
+                    partitions: Vec::new(),
+                    configs: Vec::new(),
+                });
+            }
+            PARTITION_RECORD => {
+                let partition_id = PartitionId(decoder.read_i32()?);
+                let topic_id = TopicId(decoder.read_uuid()?);
+                // Partitions always follow their topic's record in the log
+                if let Some(topic) = self.topics.get_mut(&topic_id) {
+                    topic.partitions.push(partition_id);
+                }
+            }
+            REMOVE_TOPIC_RECORD => {
+                let topic_id = TopicId(decoder.read_uuid()?);
+                self.remove(topic_id);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Reads `cluster.id` from `<log_dir>/meta.properties`, if the file exists.
+fn load_cluster_id(log_dir: &Path) -> Result<Option<String>> {
+    let path = log_dir.join(META_PROPERTIES);
+    match fs::read_to_string(&path) {
+        Ok(contents) => Ok(parse_properties(&contents).remove("cluster.id")),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(e).with_context(|| format!("reading {}", path.display())),
+    }
+}
+
+/// A random (version 4) UUID. The std hasher is randomly keyed per process, so
+/// hashing the time and a counter gives ids no other broker run will repeat.
+pub fn random_uuid() -> [u8; 16] {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_nanos());
+    let random = RandomState::new();
+    let mut uuid = [0; 16];
+    for half in uuid.chunks_mut(8) {
+        let mut hasher = random.build_hasher();
+        hasher.write_u128(now);
+        hasher.write_u64(COUNTER.fetch_add(1, Ordering::Relaxed));
+        half.copy_from_slice(&hasher.finish().to_be_bytes());
+    }
+    uuid[6] = (uuid[6] & 0x0f) | 0x40; // version 4
+    uuid[8] = (uuid[8] & 0x3f) | 0x80; // RFC 4122 variant
+    uuid
+}
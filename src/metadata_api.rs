@@ -0,0 +1,326 @@
+//! Metadata (API key 3), versions 1-12.
+//!
+//! The first request a client sends after ApiVersions: it learns the brokers,
+//! the controller and which broker leads each partition. There is one broker,
+//! so it is the leader, only replica and whole ISR of every partition.
+//!
+//! v1-v8 use the classic encoding; v9+ are flexible. From v10 requested topics
+//! may be named by topic id instead of name.
+
+use crate::broker::{Broker, LEADER_EPOCH};
+use crate::buffers;
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::config::BrokerConfig;
+use crate::describe_cluster::{AUTHORIZED_OPERATIONS_OMITTED, CLUSTER_AUTHORIZED_OPERATIONS};
+use crate::echo::EchoLimits;
+use crate::header::RequestHeader;
+use crate::ids::{PartitionId, TopicId};
+use crate::metadata::ClusterMetadata;
+use crate::throttle::THROTTLE_TIME_LEN;
+use crate::MESSAGE_SIZE_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 9;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const UNKNOWN_TOPIC_ID: i16 = 100;
+
+/// A topic the request names, by name or (v10+) by id.
+struct RequestedTopic {
+    topic_id: TopicId,
+    name: Option<String>,
+}
+
+struct TopicResponse<'a> {
+    error_code: i16,
+    /// `None` for an unknown topic id, which has no name to report.
+    name: Option<&'a str>,
+    topic_id: TopicId,
+    partitions: &'a [PartitionId],
+}
+
+/// Handles a Metadata request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let topic_count = decoder.read_array_len(flexible)?;
+    // A null array asks for every topic; an empty one for none
+    let requested = match topic_count {
+        None => None,
+        Some(count) => {
+            let mut topics = Vec::with_capacity(count);
+            for _ in 0..count {
+                topics.push(read_requested_topic(&mut decoder, api_version)?);
+            }
+            Some(topics)
+        }
+    };
+    if api_version >= 4 {
+        // Topics are only created through the metadata log, never on demand
+        decoder.read_i8()?; // allow_auto_topic_creation
+    }
+    let include_cluster_authorized_operations =
+        (8..=10).contains(&api_version) && decoder.read_i8()? != 0;
+    if api_version >= 8 {
+        decoder.read_i8()?; // include_topic_authorized_operations
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    let metadata = broker.metadata();
+    let topics: Vec<TopicResponse> = match &requested {
+        None => metadata
+            .topics()
+            .map(|topic| TopicResponse {
+                error_code: NONE,
+                name: Some(&topic.name),
+                topic_id: topic.topic_id,
+                partitions: &topic.partitions,
+            })
+            .collect(),
+        Some(requested) => requested
+            .iter()
+            .map(|topic| describe_topic(&metadata, topic))
+            .collect(),
+    };
+    println!(
+        "Metadata: {} topic(s) requested, {} described",
+        requested
+            .as_ref()
+            .map_or_else(|| "all".to_string(), |topics| topics.len().to_string()),
+        topics
+            .iter()
+            .filter(|topic| topic.error_code == NONE)
+            .count()
+    );
+
+    Ok(build_metadata_response(
+        header.correlation_id,
+        api_version,
+        broker,
+        metadata.cluster_id.as_deref(),
+        topics,
+        include_cluster_authorized_operations,
+        limits,
+    ))
+}
+
+fn read_requested_topic(
+    decoder: &mut Decoder,
+    api_version: i16,
+) -> Result<RequestedTopic, DecodeError> {
+    let topic = if api_version >= 10 {
+        let topic_id = TopicId(decoder.read_uuid()?);
+        let name = decoder.read_compact_nullable_string()?;
+        RequestedTopic { topic_id, name }
+    } else if api_version >= FIRST_FLEXIBLE_VERSION {
+        RequestedTopic {
+            topic_id: TopicId::ZERO,
+            name: Some(decoder.read_compact_string()?),
+        }
+    } else {
+        RequestedTopic {
+            topic_id: TopicId::ZERO,
+            name: Some(decoder.read_string(false)?),
+        }
+    };
+    if api_version >= FIRST_FLEXIBLE_VERSION {
+        decoder.skip_tagged_fields()?;
+    }
+    Ok(topic)
+}
+
+/// Looks a requested topic up by name, or by id when the request has no name.
+fn describe_topic<'a>(
+    metadata: &'a ClusterMetadata,
+    requested: &'a RequestedTopic,
+) -> TopicResponse<'a> {
+    let found = match &requested.name {
+        Some(name) => metadata.topic(name),
+        None => metadata.topic_by_id(requested.topic_id),
+    };
+    match (found, &requested.name) {
+        (Some(topic), _) => TopicResponse {
+            error_code: NONE,
+            name: Some(&topic.name),
+            topic_id: topic.topic_id,
+            partitions: &topic.partitions,
+        },
+        (None, Some(name)) => TopicResponse {
+            error_code: UNKNOWN_TOPIC_OR_PARTITION,
+            name: Some(name),
+            topic_id: TopicId::ZERO,
+            partitions: &[],
+        },
+        (None, None) => TopicResponse {
+            error_code: UNKNOWN_TOPIC_ID,
+            name: None,
+            topic_id: requested.topic_id,
+            partitions: &[],
+        },
+    }
+}
+
+/// MetadataResponse: throttle_time_ms (v3+), brokers [node_id, host, port, rack],
+/// cluster_id (v2+), controller_id, topics [error_code, name, topic_id (v10+),
+/// is_internal, partitions [error_code, partition_index, leader_id,
+/// leader_epoch (v7+), replica_nodes, isr_nodes, offline_replicas (v5+)],
+/// topic_authorized_operations (v8+)], cluster_authorized_operations (v8-v10).
+///
+/// Names of unknown topics come from the request, so they and the number of
+/// unknown topics are capped by `limits` before being echoed.
+fn build_metadata_response(
+    correlation_id: u32,
+    api_version: i16,
+    broker: &Broker,
+    cluster_id: Option<&str>,
+    mut topics: Vec<TopicResponse>,
+    include_cluster_authorized_operations: bool,
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let config = &broker.config;
+    let node_id = config.node_id;
+    let is_internal: i8 = 0;
+
+    let dropped = limits.cap_errors(&mut topics, |topic| topic.error_code != NONE);
+    if dropped > 0 {
+        println!("Omitted {} unknown topic(s) from the response", dropped);
+    }
+
+    let mut response = ResponseWriter::in_buffer(
+        buffers::take(estimated_size(config, cluster_id, &topics)),
+        correlation_id,
+        flexible,
+    );
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+    let write_nullable = |response: &mut ResponseWriter, value: Option<&str>| {
+        if flexible {
+            response.put_compact_nullable_string(value)
+        } else {
+            response.put_nullable_string(value)
+        }
+    };
+
+    if api_version >= 3 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+
+    write_len(&mut response, 1); // brokers: just us
+    response.put_i32(node_id.0);
+    if flexible {
+        response.put_compact_string(&config.advertised_host);
+    } else {
+        response.put_string(&config.advertised_host);
+    }
+    response.put_i32(config.advertised_port);
+    write_nullable(&mut response, config.rack.as_deref());
+    if flexible {
+        response.put_tag_buffer(); // broker TAG_BUFFER
+    }
+
+    if api_version >= 2 {
+        write_nullable(&mut response, cluster_id);
+    }
+    // Combined mode: this node is also the controller
+    response.put_i32(node_id.0);
+
+    write_len(&mut response, topics.len());
+    for topic in &topics {
+        response.put_i16(topic.error_code);
+        let name = topic.name.map(|name| limits.name(name));
+        let name = name.as_ref().map(|name| name.as_str());
+        if api_version >= 12 {
+            response.put_compact_nullable_string(name);
+        } else if flexible {
+            response.put_compact_string(name.unwrap_or_default());
+        } else {
+            response.put_string(name.unwrap_or_default());
+        }
+        if api_version >= 10 {
+            response.put_uuid(topic.topic_id.0);
+        }
+        response.put_i8(is_internal);
+
+        write_len(&mut response, topic.partitions.len());
+        for partition in topic.partitions {
+            response.put_i16(NONE);
+            response.put_i32(partition.0);
+            response.put_i32(node_id.0); // leader_id
+            if api_version >= 7 {
+                response.put_i32(LEADER_EPOCH.0);
+            }
+            write_len(&mut response, 1); // replica_nodes
+            response.put_i32(node_id.0);
+            write_len(&mut response, 1); // isr_nodes
+            response.put_i32(node_id.0);
+            if api_version >= 5 {
+                write_len(&mut response, 0); // offline_replicas
+            }
+            if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if api_version >= 8 {
+            response.put_i32(AUTHORIZED_OPERATIONS_OMITTED);
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    if (8..=10).contains(&api_version) {
+        let operations = if include_cluster_authorized_operations {
+            CLUSTER_AUTHORIZED_OPERATIONS
+        } else {
+            AUTHORIZED_OPERATIONS_OMITTED
+        };
+        response.put_i32(operations);
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
+
+/// An upper bound on the size of `build_metadata_response`'s response in any
+/// version, counting every length prefix at its widest.
+fn estimated_size(
+    config: &BrokerConfig,
+    cluster_id: Option<&str>,
+    topics: &[TopicResponse],
+) -> usize {
+    const LEN: usize = 5; // an INT32 length, or a compact one as a varint
+    const FIXED: usize = MESSAGE_SIZE_LEN + 4 + 1 // header
+        + 4 // throttle_time_ms
+        + LEN + 4 + LEN + 4 + LEN + 1 // the one broker
+        + LEN + 4 // cluster_id, controller_id
+        + LEN + 4 + 1; // topics, cluster_authorized_operations, tags
+    const PER_TOPIC: usize = 2 + LEN + 16 + 1 + LEN + 4 + 1;
+    const PER_PARTITION: usize = 2 + 4 + 4 + 4 + (LEN + 4) * 2 + LEN + 1;
+    FIXED
+        + config.advertised_host.len()
+        + config.rack.as_ref().map_or(0, String::len)
+        + cluster_id.map_or(0, str::len)
+        + topics
+            .iter()
+            .map(|topic| {
+                PER_TOPIC + topic.name.map_or(0, str::len) + PER_PARTITION * topic.partitions.len()
+            })
+            .sum::<usize>()
+}
@@ -0,0 +1,246 @@
+//! OffsetCommit (API key 8), versions 2-8.
+//!
+//! Records how far a consumer group has got in each partition, for OffsetFetch
+//! to hand back to whichever of its members asks next, on whatever connection.
+//! Once members have joined a group, only they may commit for it, and only for
+//! its current generation; a group nobody has joined takes any commit, as for
+//! consumers assigning partitions themselves. Admin tools resetting a group's
+//! offsets commit with generation -1 and no member id, which an Empty group
+//! takes and one with members or joiners refuses with UNKNOWN_MEMBER_ID; they
+//! work out the offsets with ListOffsets first. A partition listed more than
+//! once is committed once per listing, in request order, so the last one stands.
+//!
+//! v2-v7 use the classic encoding; v8 is flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::group_offsets::CommittedOffset;
+use crate::header::RequestHeader;
+use crate::ids::{LeaderEpoch, Offset, PartitionId};
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 8;
+
+/// Kafka's `offset.metadata.max.bytes` default.
+const MAX_METADATA_BYTES: usize = 4096;
+
+const NONE: i16 = 0;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const OFFSET_METADATA_TOO_LARGE: i16 = 12;
+const INVALID_GROUP_ID: i16 = 24;
+
+struct PartitionCommit {
+    index: PartitionId,
+    offset: Offset,
+    leader_epoch: LeaderEpoch,
+    metadata: Option<String>,
+}
+
+struct TopicResponse {
+    name: String,
+    /// (partition_index, error_code)
+    partitions: Vec<(PartitionId, i16)>,
+}
+
+/// Handles an OffsetCommit request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_id = decoder.read_string(flexible)?;
+    let generation_id = decoder.read_i32()?;
+    let member_id = decoder.read_string(flexible)?;
+    if api_version >= 7 {
+        decoder.read_nullable_string(flexible)?; // group_instance_id
+    }
+    if api_version <= 4 {
+        decoder.read_i64()?; // retention_time_ms; offsets are kept as long as the broker runs
+    }
+
+    let limits = &broker.config.echo_limits;
+    println!(
+        "OffsetCommit: group={} generation_id={} member_id={}",
+        limits.name(&group_id),
+        generation_id,
+        limits.name(&member_id)
+    );
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut topics = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let name = decoder.read_string(flexible)?;
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            let index = PartitionId(decoder.read_i32()?);
+            let offset = Offset(decoder.read_i64()?);
+            let leader_epoch = if api_version >= 6 {
+                LeaderEpoch(decoder.read_i32()?)
+            } else {
+                LeaderEpoch::UNKNOWN
+            };
+            let metadata = decoder.read_nullable_string(flexible)?;
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            partitions.push(PartitionCommit {
+                index,
+                offset,
+                leader_epoch,
+                metadata,
+            });
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        topics.push((name, partitions));
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let group_error = broker
+        .groups
+        .check_commit(&group_id, generation_id, &member_id);
+    let responses = commit(broker, &group_id, group_error, topics);
+    Ok(build_offset_commit_response(
+        header.correlation_id,
+        api_version,
+        &responses,
+        limits,
+    ))
+}
+
+/// Stores every commit that names a known partition and answers each with its
+/// error code. A `group_error` (e.g. a stale generation) answers them all.
+fn commit(
+    broker: &Broker,
+    group_id: &str,
+    group_error: i16,
+    topics: Vec<(String, Vec<PartitionCommit>)>,
+) -> Vec<TopicResponse> {
+    // The registry stays locked until the offsets are stored, so a topic can't be
+    // deleted in between, leaving behind offsets its deletion already cleared
+    let metadata = broker.metadata();
+    let group_offsets = broker.group_offsets();
+    let limits = &broker.config.echo_limits;
+    let mut responses = Vec::with_capacity(topics.len());
+    for (name, partitions) in topics {
+        let topic = metadata.topic(&name);
+        let mut results = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            let error_code = if group_id.is_empty() {
+                INVALID_GROUP_ID
+            } else if group_error != NONE {
+                group_error
+            } else {
+                match topic {
+                    Some(topic) if topic.partitions.contains(&partition.index) => {
+                        if partition
+                            .metadata
+                            .as_ref()
+                            .is_some_and(|metadata| metadata.len() > MAX_METADATA_BYTES)
+                        {
+                            OFFSET_METADATA_TOO_LARGE
+                        } else {
+                            let committed = CommittedOffset {
+                                offset: partition.offset,
+                                leader_epoch: partition.leader_epoch,
+                                metadata: partition.metadata,
+                            };
+                            println!(
+                                "Group {} committed {}-{} at {}",
+                                limits.name(group_id),
+                                name,
+                                partition.index,
+                                committed
+                            );
+                            group_offsets.commit(
+                                group_id,
+                                topic.topic_id,
+                                partition.index,
+                                committed,
+                            );
+                            NONE
+                        }
+                    }
+                    _ => UNKNOWN_TOPIC_OR_PARTITION,
+                }
+            };
+            results.push((partition.index, error_code));
+        }
+        responses.push(TopicResponse {
+            name,
+            partitions: results,
+        });
+    }
+    drop(metadata);
+    responses
+}
+
+/// OffsetCommitResponse: throttle_time_ms (v3+), topics [name, partitions
+/// [partition_index, error_code]].
+///
+/// Topic names and error partitions come from the request, so both are capped
+/// by `limits` before being echoed.
+fn build_offset_commit_response(
+    correlation_id: u32,
+    api_version: i16,
+    topics: &[TopicResponse],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+
+    if api_version >= 3 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    write_len(&mut response, topics.len());
+    for topic in topics {
+        let name = limits.name(&topic.name);
+        if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        let mut partitions: Vec<&(PartitionId, i16)> = topic.partitions.iter().collect();
+        let dropped = limits.cap_errors(&mut partitions, |(_, error_code)| *error_code != NONE);
+        if dropped > 0 {
+            println!(
+                "Omitted {} error partition(s) of topic {} from the response",
+                dropped, name
+            );
+        }
+        write_len(&mut response, partitions.len());
+        for (index, error_code) in partitions {
+            response.put_i32(index.0);
+            response.put_i16(*error_code);
+            if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
@@ -0,0 +1,303 @@
+//! OffsetFetch (API key 9), versions 1-8.
+//!
+//! Hands back the offsets groups committed with OffsetCommit, so a consumer
+//! resumes where its group left off. A partition with nothing committed answers
+//! offset -1 and no error. A null topics array asks for everything the group has
+//! committed. v1-v7 ask about one group; v8 batches several in groups.
+//!
+//! v1-v5 use the classic encoding; v6+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, Decoder, ResponseWriter};
+use crate::echo::EchoLimits;
+use crate::group_offsets::GroupOffsets;
+use crate::header::RequestHeader;
+use crate::ids::{LeaderEpoch, Offset, PartitionId};
+use crate::metadata::ClusterMetadata;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 6;
+/// From this version groups come batched.
+const FIRST_BATCHED_VERSION: i16 = 8;
+
+const NONE: i16 = 0;
+const INVALID_GROUP_ID: i16 = 24;
+
+/// (topic, partition_indexes) pairs, in request order.
+type RequestedTopics = Vec<(String, Vec<PartitionId>)>;
+
+struct GroupRequest {
+    group_id: String,
+    /// `None` asks for every topic the group has committed offsets for.
+    topics: Option<RequestedTopics>,
+}
+
+struct PartitionResponse {
+    index: PartitionId,
+    offset: Offset,
+    leader_epoch: LeaderEpoch,
+    metadata: Option<String>,
+}
+
+struct TopicResponse {
+    name: String,
+    partitions: Vec<PartitionResponse>,
+}
+
+struct GroupResponse {
+    group_id: String,
+    error_code: i16,
+    topics: Vec<TopicResponse>,
+}
+
+/// Handles an OffsetFetch request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let mut groups = Vec::new();
+    if api_version >= FIRST_BATCHED_VERSION {
+        let group_count = decoder.read_array_len_or_empty(true)?;
+        groups.reserve(group_count);
+        for _ in 0..group_count {
+            let group_id = decoder.read_compact_string()?;
+            let topics = read_topics(&mut decoder, flexible)?;
+            decoder.skip_tagged_fields()?;
+            groups.push(GroupRequest { group_id, topics });
+        }
+    } else {
+        let group_id = decoder.read_string(flexible)?;
+        let topics = read_topics(&mut decoder, flexible)?;
+        groups.push(GroupRequest { group_id, topics });
+    }
+    if api_version >= 7 {
+        // require_stable: no offsets are ever pending a transaction
+        decoder.read_i8()?;
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    // The registry before the offsets, per the broker's lock order. Names are
+    // resolved to the ids the offsets were committed against while it's held.
+    let metadata = broker.metadata();
+    let group_offsets = broker.group_offsets();
+    let responses: Vec<GroupResponse> = groups
+        .into_iter()
+        .map(|group| fetch_group(&metadata, group_offsets, group))
+        .collect();
+    drop(metadata);
+
+    for group in &responses {
+        println!(
+            "OffsetFetch: group={} topics={} committed={}",
+            limits.name(&group.group_id),
+            group.topics.len(),
+            group
+                .topics
+                .iter()
+                .flat_map(|topic| &topic.partitions)
+                .filter(|partition| partition.offset != Offset::NONE)
+                .count()
+        );
+    }
+    Ok(build_offset_fetch_response(
+        header.correlation_id,
+        api_version,
+        &responses,
+        limits,
+    ))
+}
+
+/// A null topics array is `None`; it's only allowed from v2, but older
+/// versions never send one.
+fn read_topics(
+    decoder: &mut Decoder,
+    flexible: bool,
+) -> Result<Option<RequestedTopics>, DecodeError> {
+    let topic_count = decoder.read_array_len(flexible)?;
+    let Some(topic_count) = topic_count else {
+        return Ok(None);
+    };
+    let mut topics = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let name = decoder.read_string(flexible)?;
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            partitions.push(PartitionId(decoder.read_i32()?));
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        topics.push((name, partitions));
+    }
+    Ok(Some(topics))
+}
+
+fn fetch_group(
+    metadata: &ClusterMetadata,
+    group_offsets: &GroupOffsets,
+    group: GroupRequest,
+) -> GroupResponse {
+    let requested = match group.topics {
+        Some(topics) => topics,
+        None => {
+            // Everything committed, topics and partitions in order. Offsets of a
+            // topic deleted a moment ago may not have been dropped yet; its id
+            // no longer resolves, so they're left out.
+            let mut committed: Vec<(&str, PartitionId)> = group_offsets
+                .committed_partitions(&group.group_id)
+                .into_iter()
+                .filter_map(|(topic_id, partition)| {
+                    Some((metadata.topic_by_id(topic_id)?.name.as_str(), partition))
+                })
+                .collect();
+            committed.sort_unstable();
+            let mut topics: RequestedTopics = Vec::new();
+            for (topic, partition) in committed {
+                match topics.last_mut() {
+                    Some((name, partitions)) if name == topic => partitions.push(partition),
+                    _ => topics.push((topic.to_string(), vec![partition])),
+                }
+            }
+            topics
+        }
+    };
+
+    let topics = requested
+        .into_iter()
+        .map(|(name, partitions)| {
+            let topic_id = metadata.topic_id(&name);
+            let partitions = partitions
+                .into_iter()
+                .map(|index| {
+                    let committed = topic_id
+                        .and_then(|topic_id| group_offsets.get(&group.group_id, topic_id, index));
+                    match committed {
+                        Some(committed) => PartitionResponse {
+                            index,
+                            offset: committed.offset,
+                            leader_epoch: committed.leader_epoch,
+                            metadata: committed.metadata,
+                        },
+                        None => PartitionResponse {
+                            index,
+                            offset: Offset::NONE,
+                            leader_epoch: LeaderEpoch::UNKNOWN,
+                            metadata: Some(String::new()),
+                        },
+                    }
+                })
+                .collect();
+            TopicResponse { name, partitions }
+        })
+        .collect();
+    // Nothing can be committed without a group id, so all its partitions answer -1
+    let error_code = if group.group_id.is_empty() {
+        INVALID_GROUP_ID
+    } else {
+        NONE
+    };
+    GroupResponse {
+        group_id: group.group_id,
+        error_code,
+        topics,
+    }
+}
+
+/// OffsetFetchResponse: throttle_time_ms (v3+), then up to v7 topics [name,
+/// partitions [partition_index, committed_offset, committed_leader_epoch (v5+),
+/// metadata, error_code]], error_code (v2+); from v8 groups [group_id, topics,
+/// error_code].
+///
+/// Group ids and topic names come from the request, so they are capped by
+/// `limits` before being echoed.
+fn build_offset_fetch_response(
+    correlation_id: u32,
+    api_version: i16,
+    groups: &[GroupResponse],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+    let write_str = |response: &mut ResponseWriter, value: &str| {
+        if flexible {
+            response.put_compact_string(value)
+        } else {
+            response.put_string(value)
+        }
+    };
+    let write_topics = |response: &mut ResponseWriter, group: &GroupResponse| {
+        write_len(response, group.topics.len());
+        for topic in &group.topics {
+            write_str(response, limits.name(&topic.name).as_str());
+            write_len(response, topic.partitions.len());
+            for partition in &topic.partitions {
+                response.put_i32(partition.index.0);
+                response.put_i64(partition.offset.0);
+                if api_version >= 5 {
+                    response.put_i32(partition.leader_epoch.0);
+                }
+                if flexible {
+                    response.put_compact_nullable_string(partition.metadata.as_deref());
+                } else {
+                    response.put_nullable_string(partition.metadata.as_deref());
+                }
+                // Before v2 there is no group error code, so each partition carries it
+                let error_code = if api_version < 2 {
+                    group.error_code
+                } else {
+                    NONE
+                };
+                response.put_i16(error_code);
+                if flexible {
+                    response.put_tag_buffer(); // partition TAG_BUFFER
+                }
+            }
+            if flexible {
+                response.put_tag_buffer(); // topic TAG_BUFFER
+            }
+        }
+    };
+
+    if api_version >= 3 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    if api_version >= FIRST_BATCHED_VERSION {
+        response.put_compact_array_len(groups.len());
+        for group in groups {
+            response.put_compact_string(limits.name(&group.group_id).as_str());
+            write_topics(&mut response, group);
+            response.put_i16(group.error_code);
+            response.put_tag_buffer(); // group TAG_BUFFER
+        }
+    } else {
+        // Up to v7 there is exactly one group
+        let group = &groups[0];
+        write_topics(&mut response, group);
+        if api_version >= 2 {
+            response.put_i16(group.error_code);
+        }
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
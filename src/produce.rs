@@ -0,0 +1,375 @@
+//! Produce (API key 0), versions 3-11.
+//!
+//! v3-v8 use the classic encoding; v9+ are flexible (compact strings and arrays,
+//! tag buffers, request header v2 and response header v1). Batches are appended
+//! to the partition logs as they arrive; there is no replication, so acks=1
+//! and acks=-1 behave the same unless `log.flush.on.acks.all` is set. Then an
+//! acks=-1 produce is answered once every partition it appended to is flushed
+//! through its last record (see `flusher`), or with REQUEST_TIMED_OUT for those
+//! that aren't within timeout_ms. A batch an idempotent producer resends
+//! after losing the response is answered with the original offset instead of
+//! being appended twice. A partition listed more than once is answered with
+//! INVALID_REQUEST for every occurrence, and none of them is appended.
+
+use std::time::{Duration, Instant};
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::duplicates;
+use crate::echo::EchoLimits;
+use crate::error::BrokerError;
+use crate::flusher::Wait;
+use crate::header::RequestHeader;
+use crate::ids::{Offset, PartitionId, TopicId};
+use crate::record_batch::RecordBatch;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 9;
+
+const NONE: i16 = 0;
+const CORRUPT_MESSAGE: i16 = 2;
+const UNKNOWN_TOPIC_OR_PARTITION: i16 = 3;
+const INVALID_REQUIRED_ACKS: i16 = 21;
+const INVALID_REQUEST: i16 = 42;
+
+struct PartitionResponse {
+    index: PartitionId,
+    error_code: i16,
+    base_offset: Offset,
+    log_start_offset: Offset,
+    error_message: Option<String>,
+    /// The partition's topic and its last offset after an append, for an
+    /// acks=-1 produce to wait on being flushed.
+    appended_through: Option<(TopicId, Offset)>,
+}
+
+impl PartitionResponse {
+    fn error(index: PartitionId, error: BrokerError) -> PartitionResponse {
+        PartitionResponse {
+            index,
+            error_code: error.code,
+            base_offset: Offset::NONE,
+            log_start_offset: Offset::NONE,
+            error_message: error.message().map(str::to_string),
+            appended_through: None,
+        }
+    }
+}
+
+struct TopicResponse {
+    name: String,
+    partitions: Vec<PartitionResponse>,
+}
+
+/// Handles a Produce request. Returns `Ok(None)` for acks=0, which gets no
+/// response at all, and an error if the body can't be decoded.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Option<Vec<u8>>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let transactional_id = decoder.read_nullable_string(flexible)?;
+    let acks = decoder.read_i16()?;
+    let timeout_ms = decoder.read_i32()?;
+    println!(
+        "Produce: transactional_id={:?} acks={} timeout_ms={}",
+        transactional_id, acks, timeout_ms
+    );
+
+    let topic_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut requests = Vec::with_capacity(topic_count);
+    for _ in 0..topic_count {
+        let name = decoder.read_string(flexible)?;
+        let partition_count = decoder.read_array_len_or_empty(flexible)?;
+        let mut partitions = Vec::with_capacity(partition_count);
+        for _ in 0..partition_count {
+            let index = PartitionId(decoder.read_i32()?);
+            let records = if flexible {
+                decoder.read_compact_nullable_bytes()?
+            } else {
+                decoder.read_nullable_bytes()?
+            };
+            if flexible {
+                decoder.skip_tagged_fields()?;
+            }
+            partitions.push((index, records));
+        }
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        requests.push((name, partitions));
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let repeated = duplicates::repeated(requests.iter().flat_map(|(name, partitions)| {
+        partitions
+            .iter()
+            .map(move |&(index, _)| (name.as_str(), index))
+    }));
+    let mut topics: Vec<TopicResponse> = requests
+        .iter()
+        .map(|(name, partitions)| TopicResponse {
+            name: name.clone(),
+            partitions: partitions
+                .iter()
+                .map(|&(index, records)| {
+                    if repeated.contains(&(name.as_str(), index)) {
+                        PartitionResponse::error(
+                            index,
+                            BrokerError::with_message(
+                                INVALID_REQUEST,
+                                "Partition appears more than once in the request",
+                            ),
+                        )
+                    } else {
+                        produce_partition(broker, name, index, acks, records)
+                    }
+                })
+                .collect(),
+        })
+        .collect();
+    if acks == -1 && broker.config.flush_on_acks_all {
+        wait_for_flush(broker, &mut topics, timeout_ms);
+    }
+
+    if acks == 0 {
+        return Ok(None);
+    }
+    Ok(Some(build_produce_response(
+        header.correlation_id,
+        api_version,
+        &topics,
+        &broker.config.echo_limits,
+    )))
+}
+
+/// Waits until every partition appended to is flushed through its last record,
+/// taking a barrier on each before waiting on any so one flush can pass them
+/// all. Those not flushed within `timeout_ms` are answered with the error.
+fn wait_for_flush(broker: &Broker, topics: &mut [TopicResponse], timeout_ms: i32) {
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms.max(0) as u64);
+    let mut barriers = Vec::new();
+    for topic in topics.iter_mut() {
+        for partition in &mut topic.partitions {
+            if let Some((topic_id, offset)) = partition.appended_through {
+                let barrier = broker
+                    .flusher
+                    .flush_barrier(topic_id, partition.index, offset);
+                barriers.push((topic.name.as_str(), partition, barrier));
+            }
+        }
+    }
+    for (name, partition, barrier) in barriers {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        if let Err(e) = barrier.wait(timeout) {
+            println!("Produce to {}-{} not flushed: {}", name, partition.index, e);
+            *partition = PartitionResponse::error(partition.index, e.to_broker_error());
+        }
+    }
+}
+
+/// Whether the client waits for an answer to a request `handle` couldn't
+/// decode: not with acks=0, and not as far as anyone can tell if even acks
+/// can't be read.
+pub fn expects_response(header: &RequestHeader, request_buffer: &[u8]) -> bool {
+    matches!(read_acks(header, request_buffer), Ok(acks) if acks != 0)
+}
+
+/// Whether the produce may be held before it's answered: with acks=-1 it
+/// waits on a flush whenever `log.flush.on.acks.all` is set.
+pub fn may_wait(header: &RequestHeader, request_buffer: &[u8]) -> bool {
+    matches!(read_acks(header, request_buffer), Ok(-1))
+}
+
+/// The request's acks, read without decoding the rest of it.
+fn read_acks(header: &RequestHeader, request_buffer: &[u8]) -> Result<i16, DecodeError> {
+    let flexible = header.api_version as i16 >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+    decoder.read_nullable_string(flexible)?; // transactional_id
+    decoder.read_i16()
+}
+
+fn produce_partition(
+    broker: &Broker,
+    topic: &str,
+    index: PartitionId,
+    acks: i16,
+    records: Option<&[u8]>,
+) -> PartitionResponse {
+    let error = |error| PartitionResponse::error(index, error);
+
+    if !matches!(acks, -1..=1) {
+        return error(BrokerError::with_message(
+            INVALID_REQUIRED_ACKS,
+            format!("acks must be -1 (all), 0 or 1, got {}", acks),
+        ));
+    }
+    let topic_id = broker
+        .metadata()
+        .topic(topic)
+        .filter(|topic| topic.partitions.contains(&index))
+        .map(|topic| topic.topic_id);
+    let Some(topic_id) = topic_id else {
+        println!(
+            "Produce to unknown topic-partition {}-{}",
+            broker.config.echo_limits.name(topic),
+            index
+        );
+        // One per partition, up to the echo limit: the code alone keeps the
+        // response small
+        return error(BrokerError::new(UNKNOWN_TOPIC_OR_PARTITION));
+    };
+
+    let batches = match RecordBatch::parse_all(records.unwrap_or_default()) {
+        Ok(batches) if !batches.is_empty() => batches,
+        Ok(_) => {
+            return error(BrokerError::with_message(
+                CORRUPT_MESSAGE,
+                "no record batches",
+            ))
+        }
+        Err(e) => {
+            println!("Rejecting produce to {}-{}: {}", topic, index, e);
+            // Describes only the client's own bytes: where they stopped making sense
+            return error(BrokerError::with_message(CORRUPT_MESSAGE, e.to_string()));
+        }
+    };
+
+    // A DeleteTopics since the check above has taken the log with it, and this
+    // fails as if the check had
+    let logs = broker.logs();
+    let appended = logs.append(topic_id, index, &batches).and_then(|appended| {
+        let offsets = logs.offsets(topic_id, index)?;
+        Ok((appended, offsets))
+    });
+    let (appended, offsets) = match appended {
+        Ok(appended) => appended,
+        Err(e) => {
+            println!("Produce to {}-{} failed: {}", topic, index, e);
+            return error(e.to_broker_error());
+        }
+    };
+    broker.notify_appended();
+    for (batch, appended) in batches.iter().zip(&appended) {
+        if appended.duplicate {
+            println!(
+                "Duplicate batch from producer {} (epoch {}, sequence {}) on {}-{}, already at offset {}",
+                batch.producer_id,
+                batch.producer_epoch,
+                batch.base_sequence,
+                topic,
+                index,
+                appended.base_offset
+            );
+        }
+    }
+    // parse_all returned at least one batch
+    let base_offset = appended
+        .first()
+        .map_or_else(Offset::default, |appended| appended.base_offset);
+    println!(
+        "Appended {} batch(es) to {}-{} at offset {}, log end offset {}",
+        batches.len(),
+        topic,
+        index,
+        base_offset,
+        offsets.log_end_offset
+    );
+
+    PartitionResponse {
+        index,
+        error_code: NONE,
+        base_offset,
+        log_start_offset: offsets.log_start_offset,
+        error_message: None,
+        appended_through: Some((topic_id, Offset(offsets.log_end_offset.0 - 1))),
+    }
+}
+
+/// ProduceResponse: responses [name, partition_responses [index, error_code,
+/// base_offset, log_append_time_ms, log_start_offset (v5+), record_errors (v8+),
+/// error_message (v8+)]], throttle_time_ms.
+///
+/// Topic names and error partitions come from the request, so both are capped
+/// by `limits` before being echoed.
+fn build_produce_response(
+    correlation_id: u32,
+    api_version: i16,
+    topics: &[TopicResponse],
+    limits: &EchoLimits,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    // Topics use CreateTime, for which the broker reports no append time
+    let log_append_time_ms: i64 = -1;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    let write_len = |response: &mut ResponseWriter, len| {
+        if flexible {
+            response.put_compact_array_len(len)
+        } else {
+            response.put_array_len(len)
+        }
+    };
+
+    write_len(&mut response, topics.len());
+    for topic in topics {
+        let name = limits.name(&topic.name);
+        if flexible {
+            response.put_compact_string(name.as_str());
+        } else {
+            response.put_string(name.as_str());
+        }
+        let mut partitions: Vec<&PartitionResponse> = topic.partitions.iter().collect();
+        let dropped = limits.cap_errors(&mut partitions, |partition| partition.error_code != NONE);
+        if dropped > 0 {
+            println!(
+                "Omitted {} error partition(s) of topic {} from the response",
+                dropped, name
+            );
+        }
+        write_len(&mut response, partitions.len());
+        for partition in partitions {
+            let error_message = match (&partition.error_message, name.truncation_note()) {
+                (Some(message), Some(note)) => Some(format!("{}; {}", message, note)),
+                (Some(message), None) => Some(message.clone()),
+                (None, note) if partition.error_code != NONE => note,
+                (None, _) => None,
+            };
+            response.put_i32(partition.index.0);
+            response.put_i16(partition.error_code);
+            response.put_i64(partition.base_offset.0);
+            response.put_i64(log_append_time_ms);
+            if api_version >= 5 {
+                response.put_i64(partition.log_start_offset.0);
+            }
+            if api_version >= 8 {
+                write_len(&mut response, 0); // record_errors
+                if flexible {
+                    response.put_compact_nullable_string(error_message.as_deref());
+                } else {
+                    response.put_nullable_string(error_message.as_deref());
+                }
+            }
+            if flexible {
+                response.put_tag_buffer(); // partition TAG_BUFFER
+            }
+        }
+        if flexible {
+            response.put_tag_buffer(); // topic TAG_BUFFER
+        }
+    }
+    response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
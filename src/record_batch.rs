@@ -0,0 +1,270 @@
+//! Record batches (message format v2), as carried in Produce requests and stored
+//! in KRaft log segments.
+//!
+//! ```text
+//! base_offset: i64 | batch_length: i32 | partition_leader_epoch: i32 | magic: i8 (2)
+//! crc: u32 | attributes: i16 | last_offset_delta: i32 | base_timestamp: i64
+//! max_timestamp: i64 | producer_id: i64 | producer_epoch: i16 | base_sequence: i32
+//! records_count: i32 | records...
+//! ```
+//!
+//! `batch_length` counts everything after itself. The CRC covers `attributes`
+//! through the end of the batch, so `base_offset` can be rewritten on append
+//! without touching it.
+
+use thiserror::Error;
+
+use crate::codec::{write_varint, DecodeError, Decoder};
+use crate::ids::Offset;
+
+const BASE_OFFSET_LEN: usize = 8;
+const BATCH_LENGTH_LEN: usize = 4;
+/// Everything from `base_offset` up to the first record.
+const BATCH_OVERHEAD: usize = 61;
+const MAGIC_OFFSET: usize = 16;
+const ATTRIBUTES_OFFSET: usize = 21;
+const LAST_OFFSET_DELTA_OFFSET: usize = 23;
+const BASE_TIMESTAMP_OFFSET: usize = 27;
+const PRODUCER_ID_OFFSET: usize = 43;
+
+const CURRENT_MAGIC: i8 = 2;
+const COMPRESSION_CODEC_MASK: i16 = 0x07;
+const CONTROL_FLAG: i16 = 0x20;
+
+#[derive(Debug, Error)]
+pub enum RecordBatchError {
+    #[error(transparent)]
+    Decode(#[from] DecodeError),
+    #[error("batch at byte {offset} has batch_length {length}, shorter than the batch header")]
+    InvalidBatchLength { offset: usize, length: i32 },
+    #[error("batch at byte {offset} uses message format {magic}, only v2 is supported")]
+    UnsupportedMagic { offset: usize, magic: i8 },
+    #[error("batch records are compressed; only uncompressed batches can be decoded")]
+    Compressed,
+}
+
+/// The producer fields of a batch `build` writes.
+#[derive(Clone, Copy)]
+pub struct Producer {
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+}
+
+impl Producer {
+    /// A producer that's neither idempotent nor transactional.
+    pub const NONE: Producer = Producer {
+        producer_id: -1,
+        producer_epoch: -1,
+        base_sequence: -1,
+    };
+}
+
+/// One batch, borrowed from the buffer it was parsed from.
+pub struct RecordBatch<'a> {
+    pub base_offset: Offset,
+    pub last_offset_delta: i32,
+    base_timestamp: i64,
+    /// Latest record timestamp in the batch.
+    pub max_timestamp: i64,
+    /// -1 unless the batch comes from an idempotent or transactional producer.
+    pub producer_id: i64,
+    pub producer_epoch: i16,
+    pub base_sequence: i32,
+    attributes: i16,
+    records_count: i32,
+    /// The whole batch, `base_offset` included.
+    pub bytes: &'a [u8],
+}
+
+impl<'a> RecordBatch<'a> {
+    /// Splits a RECORDS field (zero or more concatenated batches) into batches.
+    /// A truncated trailing batch is an error rather than being silently dropped.
+    pub fn parse_all(buf: &'a [u8]) -> Result<Vec<RecordBatch<'a>>, RecordBatchError> {
+        let mut batches = Vec::new();
+        let mut offset = 0;
+        while offset < buf.len() {
+            let batch = RecordBatch::parse_at(buf, offset)?;
+            offset += batch.bytes.len();
+            batches.push(batch);
+        }
+        Ok(batches)
+    }
+
+    /// The batch at the start of `buf`, ignoring whatever follows it; for
+    /// reading a log that may end in a torn write.
+    pub fn parse_first(buf: &'a [u8]) -> Result<RecordBatch<'a>, RecordBatchError> {
+        RecordBatch::parse_at(buf, 0)
+    }
+
+    fn parse_at(buf: &'a [u8], offset: usize) -> Result<RecordBatch<'a>, RecordBatchError> {
+        let mut decoder = Decoder::new(&buf[offset..]);
+        let base_offset = Offset(decoder.read_i64()?);
+        let batch_length = decoder.read_i32()?;
+        if batch_length < (BATCH_OVERHEAD - BASE_OFFSET_LEN - BATCH_LENGTH_LEN) as i32 {
+            return Err(RecordBatchError::InvalidBatchLength {
+                offset,
+                length: batch_length,
+            });
+        }
+        decoder.read_bytes(batch_length as usize)?;
+        let bytes =
+            &buf[offset..offset + BASE_OFFSET_LEN + BATCH_LENGTH_LEN + batch_length as usize];
+
+        let magic = bytes[MAGIC_OFFSET] as i8;
+        if magic != CURRENT_MAGIC {
+            return Err(RecordBatchError::UnsupportedMagic { offset, magic });
+        }
+        let attributes =
+            i16::from_be_bytes([bytes[ATTRIBUTES_OFFSET], bytes[ATTRIBUTES_OFFSET + 1]]);
+        let last_offset_delta = Decoder::new(&bytes[LAST_OFFSET_DELTA_OFFSET..]).read_i32()?;
+        let mut timestamps = Decoder::new(&bytes[BASE_TIMESTAMP_OFFSET..]);
+        let base_timestamp = timestamps.read_i64()?;
+        let max_timestamp = timestamps.read_i64()?;
+        let mut producer = Decoder::new(&bytes[PRODUCER_ID_OFFSET..]);
+        let producer_id = producer.read_i64()?;
+        let producer_epoch = producer.read_i16()?;
+        let base_sequence = producer.read_i32()?;
+        let records_count = producer.read_i32()?;
+
+        Ok(RecordBatch {
+            base_offset,
+            last_offset_delta,
+            base_timestamp,
+            max_timestamp,
+            producer_id,
+            producer_epoch,
+            base_sequence,
+            attributes,
+            records_count,
+            bytes,
+        })
+    }
+
+    /// Control batches (transaction markers, snapshot headers) carry no user data.
+    pub fn is_control(&self) -> bool {
+        self.attributes & CONTROL_FLAG != 0
+    }
+
+    /// Decodes the records of an uncompressed batch.
+    pub fn records(&self) -> Result<Vec<Record<'a>>, RecordBatchError> {
+        if self.attributes & COMPRESSION_CODEC_MASK != 0 {
+            return Err(RecordBatchError::Compressed);
+        }
+        let mut decoder = Decoder::new(&self.bytes[BATCH_OVERHEAD..]);
+        let mut records = Vec::new();
+        for _ in 0..self.records_count.max(0) {
+            records.push(Record::parse(
+                &mut decoder,
+                self.base_offset,
+                self.base_timestamp,
+            )?);
+        }
+        Ok(records)
+    }
+}
+
+/// A single record with its headers skipped; nothing here needs them yet.
+pub struct Record<'a> {
+    /// Absolute offset: the batch's base_offset plus the record's offset_delta.
+    pub offset: Offset,
+    /// Absolute timestamp: the batch's base_timestamp plus the record's delta.
+    pub timestamp: i64,
+    pub value: Option<&'a [u8]>,
+}
+
+impl<'a> Record<'a> {
+    fn parse(
+        decoder: &mut Decoder<'a>,
+        base_offset: Offset,
+        base_timestamp: i64,
+    ) -> Result<Record<'a>, DecodeError> {
+        decoder.read_varint()?; // length
+        decoder.read_i8()?; // attributes
+        let timestamp = decoder.read_varint_delta(base_timestamp)?;
+        let offset = Offset(decoder.read_varint_delta(base_offset.0)?);
+        read_varint_bytes(decoder)?; // key
+        let value = read_varint_bytes(decoder)?;
+        let header_count = decoder.read_varint()?;
+        for _ in 0..header_count.max(0) {
+            read_varint_bytes(decoder)?; // header key
+            read_varint_bytes(decoder)?; // header value
+        }
+        Ok(Record {
+            offset,
+            timestamp,
+            value,
+        })
+    }
+}
+
+/// Records use zigzag varint lengths, -1 for null.
+fn read_varint_bytes<'a>(decoder: &mut Decoder<'a>) -> Result<Option<&'a [u8]>, DecodeError> {
+    match decoder.read_varint()? {
+        len if len < 0 => Ok(None),
+        len => decoder.read_bytes(len as usize).map(Some),
+    }
+}
+
+/// An uncompressed batch of keyless records, given as (timestamp, value), at
+/// base offset 0. For the bench and the log store conformance checks; the
+/// broker itself only ever stores batches producers built.
+pub fn build(records: &[(i64, &[u8])], producer: Producer) -> Vec<u8> {
+    let base_timestamp = records.first().map_or(0, |&(timestamp, _)| timestamp);
+    let max_timestamp = records
+        .iter()
+        .map(|&(timestamp, _)| timestamp)
+        .max()
+        .unwrap_or(base_timestamp);
+    let mut encoded = Vec::new();
+    for (offset_delta, &(timestamp, value)) in records.iter().enumerate() {
+        let mut record = vec![0]; // attributes
+        write_varint(&mut record, timestamp - base_timestamp);
+        write_varint(&mut record, offset_delta as i64);
+        write_varint(&mut record, -1); // key: null
+        write_varint(&mut record, value.len() as i64);
+        record.extend_from_slice(value);
+        write_varint(&mut record, 0); // headers
+        write_varint(&mut encoded, record.len() as i64);
+        encoded.extend_from_slice(&record);
+    }
+
+    let mut batch = Vec::with_capacity(BATCH_OVERHEAD + encoded.len());
+    batch.extend_from_slice(&0i64.to_be_bytes()); // base_offset
+    batch.extend_from_slice(&[0; 4]); // batch_length, filled in once the batch is complete
+    batch.extend_from_slice(&0i32.to_be_bytes()); // partition_leader_epoch
+    batch.push(CURRENT_MAGIC as u8);
+    batch.extend_from_slice(&[0; 4]); // crc, filled in once the batch is complete
+    batch.extend_from_slice(&0i16.to_be_bytes()); // attributes
+    batch.extend_from_slice(&(records.len() as i32 - 1).to_be_bytes()); // last_offset_delta
+    batch.extend_from_slice(&base_timestamp.to_be_bytes());
+    batch.extend_from_slice(&max_timestamp.to_be_bytes());
+    batch.extend_from_slice(&producer.producer_id.to_be_bytes());
+    batch.extend_from_slice(&producer.producer_epoch.to_be_bytes());
+    batch.extend_from_slice(&producer.base_sequence.to_be_bytes());
+    batch.extend_from_slice(&(records.len() as i32).to_be_bytes());
+    batch.extend_from_slice(&encoded);
+    let batch_length = (batch.len() - BASE_OFFSET_LEN - BATCH_LENGTH_LEN) as i32;
+    batch[BASE_OFFSET_LEN..BASE_OFFSET_LEN + BATCH_LENGTH_LEN]
+        .copy_from_slice(&batch_length.to_be_bytes());
+    let crc = crc32c(&batch[ATTRIBUTES_OFFSET..]);
+    batch[ATTRIBUTES_OFFSET - 4..ATTRIBUTES_OFFSET].copy_from_slice(&crc.to_be_bytes());
+    batch
+}
+
+/// CRC-32C (Castagnoli), which record batches are checksummed with. Batches
+/// are built once per bench connection, so bit at a time is fast enough.
+fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
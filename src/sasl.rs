@@ -0,0 +1,232 @@
+//! SASL/PLAIN authentication: SaslHandshake (API key 17), version 1, and
+//! SaslAuthenticate (API key 36), versions 0-2.
+//!
+//! With `sasl.enabled.mechanisms=PLAIN` a connection to a SASL_PLAINTEXT
+//! listener picks the mechanism with SaslHandshake, then authenticates with
+//! SaslAuthenticate, and until it has it is served nothing but ApiVersions and
+//! these two. Other listeners offer no mechanism. The PLAIN message is
+//! `authzid NUL username NUL password` (RFC 4616), checked against
+//! `sasl.plain.users`. Credentials that don't match are answered with
+//! SASL_AUTHENTICATION_FAILED and the connection is closed, as Kafka does.
+//!
+//! SaslHandshake v0, after which the SASL tokens are sent raw rather than in
+//! SaslAuthenticate requests, is not supported. SaslAuthenticate v2 is
+//! flexible.
+
+use std::collections::HashMap;
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::header::RequestHeader;
+use crate::listener::Listener;
+
+pub const PLAIN: &str = "PLAIN";
+
+const NONE: i16 = 0;
+const UNSUPPORTED_SASL_MECHANISM: i16 = 33;
+const ILLEGAL_SASL_STATE: i16 = 34;
+const SASL_AUTHENTICATION_FAILED: i16 = 58;
+
+const FIRST_FLEXIBLE_AUTHENTICATE_VERSION: i16 = 2;
+
+/// The same for every failure, so a client can't probe which usernames exist.
+const AUTHENTICATION_FAILED_MESSAGE: &str = "Authentication failed: Invalid username or password";
+
+/// How far one connection has got with authenticating.
+#[derive(Default)]
+pub struct SaslState {
+    /// A SaslHandshake picked PLAIN and no SaslAuthenticate has followed yet.
+    handshake_done: bool,
+    /// Who the connection authenticated as.
+    user: Option<String>,
+}
+
+impl SaslState {
+    pub fn is_authenticated(&self) -> bool {
+        self.user.is_some()
+    }
+}
+
+/// A SaslAuthenticate response, and whether the connection is to be closed
+/// once it has been sent.
+pub struct Authentication {
+    pub response: Vec<u8>,
+    pub failed: bool,
+}
+
+/// Handles a SaslHandshake request.
+pub fn handle_handshake(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+    listener: &Listener,
+    state: &mut SaslState,
+) -> Result<Vec<u8>, DecodeError> {
+    let mut decoder = RequestHeader::body_decoder(request_buffer, false)?;
+    let mechanism = decoder.read_string(false)?;
+
+    // Only SASL listeners authenticate; config loading made sure they have a
+    // mechanism
+    let enabled: &[&str] = match broker.config.sasl_plain_users {
+        Some(_) if listener.requires_authentication() => &[PLAIN],
+        _ => &[],
+    };
+    let error_code = if !enabled.contains(&mechanism.as_str()) {
+        UNSUPPORTED_SASL_MECHANISM
+    } else if state.is_authenticated() {
+        ILLEGAL_SASL_STATE
+    } else {
+        state.handshake_done = true;
+        NONE
+    };
+    println!(
+        "SaslHandshake: mechanism={} error_code={}",
+        broker.config.echo_limits.name(&mechanism),
+        error_code
+    );
+    Ok(build_handshake_response(
+        header.correlation_id,
+        error_code,
+        enabled,
+    ))
+}
+
+/// SaslHandshakeResponse: error_code, mechanisms.
+fn build_handshake_response(correlation_id: u32, error_code: i16, mechanisms: &[&str]) -> Vec<u8> {
+    let mut response = ResponseWriter::new(correlation_id, false);
+    response.put_i16(error_code);
+    response.put_array_len(mechanisms.len());
+    for mechanism in mechanisms {
+        response.put_string(mechanism);
+    }
+
+    response.finish()
+}
+
+/// Handles a SaslAuthenticate request.
+pub fn handle_authenticate(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+    state: &mut SaslState,
+) -> Result<Authentication, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_AUTHENTICATE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+    let auth_bytes = if flexible {
+        decoder.read_compact_nullable_bytes()?
+    } else {
+        decoder.read_nullable_bytes()?
+    }
+    .unwrap_or_default();
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let (error_code, error_message) = match &broker.config.sasl_plain_users {
+        Some(users) if state.handshake_done => {
+            state.handshake_done = false;
+            match check_plain(users, auth_bytes) {
+                Ok(user) => {
+                    println!(
+                        "SaslAuthenticate: authenticated as {}",
+                        broker.config.echo_limits.name(user)
+                    );
+                    state.user = Some(user.to_string());
+                    (NONE, None)
+                }
+                Err(reason) => {
+                    println!("SaslAuthenticate: rejected, {}", reason);
+                    (
+                        SASL_AUTHENTICATION_FAILED,
+                        Some(AUTHENTICATION_FAILED_MESSAGE),
+                    )
+                }
+            }
+        }
+        _ => {
+            println!("SaslAuthenticate: no SaslHandshake before it");
+            (
+                ILLEGAL_SASL_STATE,
+                Some("SaslAuthenticate must follow a successful SaslHandshake"),
+            )
+        }
+    };
+    Ok(Authentication {
+        response: build_authenticate_response(
+            header.correlation_id,
+            api_version,
+            error_code,
+            error_message,
+        ),
+        failed: error_code == SASL_AUTHENTICATION_FAILED,
+    })
+}
+
+/// SaslAuthenticateResponse: error_code, error_message, auth_bytes,
+/// session_lifetime_ms (v1+). PLAIN has no server challenge, so auth_bytes is
+/// always empty, and sessions don't expire.
+fn build_authenticate_response(
+    correlation_id: u32,
+    api_version: i16,
+    error_code: i16,
+    error_message: Option<&str>,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_AUTHENTICATE_VERSION;
+    let session_lifetime_ms: i64 = 0;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    response.put_i16(error_code);
+    if flexible {
+        response.put_compact_nullable_string(error_message);
+        response.put_compact_bytes(&[]);
+    } else {
+        response.put_nullable_string(error_message);
+        response.put_bytes(&[]);
+    }
+    if api_version >= 1 {
+        response.put_i64(session_lifetime_ms);
+    }
+    if flexible {
+        response.put_tag_buffer(); // response TAG_BUFFER
+    }
+
+    response.finish()
+}
+
+/// The username a PLAIN message authenticates, or why it doesn't, for the
+/// broker's log only.
+fn check_plain<'a>(
+    users: &HashMap<String, String>,
+    message: &'a [u8],
+) -> Result<&'a str, &'static str> {
+    let fields: Vec<&[u8]> = message.split(|&byte| byte == 0).collect();
+    let [authzid, username, password] = fields[..] else {
+        return Err("malformed PLAIN message");
+    };
+    let Ok(username) = std::str::from_utf8(username) else {
+        return Err("username is not UTF-8");
+    };
+    // Acting for another user isn't supported
+    if !authzid.is_empty() && authzid != username.as_bytes() {
+        return Err("authorization id differs from the username");
+    }
+    match users.get(username) {
+        Some(expected) if constant_time_eq(expected.as_bytes(), password) => Ok(username),
+        _ => Err("invalid username or password"),
+    }
+}
+
+/// Compares without stopping at the first difference, so response times don't
+/// tell how much of a guessed password was right.
+fn constant_time_eq(expected: &[u8], given: &[u8]) -> bool {
+    if expected.len() != given.len() {
+        return false;
+    }
+    expected
+        .iter()
+        .zip(given)
+        .fold(0, |diff, (a, b)| diff | (a ^ b))
+        == 0
+}
@@ -0,0 +1,79 @@
+//! Helpers for the files this broker saves its own state in, beside the
+//! metadata log it only reads: `ConfigOverrides` and `TopicChanges`.
+//!
+//! Each is text, a `version=<n>` line and then one line per entry, its fields
+//! separated by tabs. Backslashes, tabs and line breaks within a field are
+//! escaped. A file is replaced whole: written to a temporary file beside it,
+//! synced, then renamed over it, so a crash leaves one file or the other,
+//! never a mix.
+
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+
+/// Where a file's replacement is written before it's renamed over it.
+pub fn staged_path(path: &Path) -> PathBuf {
+    path.with_extension("tmp")
+}
+
+/// Replaces the file at `path` with `contents`; it's left as it was if it
+/// can't be.
+pub fn replace(path: &Path, contents: &str) -> io::Result<()> {
+    let staged = staged_path(path);
+    let mut file = File::create(&staged)?;
+    file.write_all(contents.as_bytes())?;
+    file.sync_all()?;
+    fs::rename(&staged, path)
+}
+
+/// Fails unless `line`, a file's first, says it's of `version`.
+pub fn check_version(line: Option<&str>, version: u32) -> Result<()> {
+    match line
+        .and_then(|line| line.strip_prefix("version="))
+        .map(str::parse::<u32>)
+    {
+        Some(Ok(found)) if found == version => Ok(()),
+        Some(Ok(found)) => bail!(
+            "format version {} is not one this broker reads (only {})",
+            found,
+            version
+        ),
+        _ => bail!("no format version on the first line"),
+    }
+}
+
+pub fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '\t' => escaped.push_str("\\t"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// `escape` undone, or `None` for an escape it doesn't write.
+pub fn unescape(s: &str) -> Option<String> {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        unescaped.push(match chars.next()? {
+            '\\' => '\\',
+            't' => '\t',
+            'n' => '\n',
+            'r' => '\r',
+            _ => return None,
+        });
+    }
+    Some(unescaped)
+}
@@ -0,0 +1,398 @@
+//! Wire session recording and replay.
+//!
+//! With `session.record.dir` set, each connection writes its request frames and
+//! the responses we sent to a session file. `Eventor replay <file>` feeds the
+//! recorded requests back through `handle_request` in-process and compares what
+//! comes out against the recorded responses, so a capture from a misbehaving
+//! client can be turned into a reproducible check.
+//!
+//! File layout (all integers big-endian):
+//!
+//! ```text
+//! magic "EVSS" | format_version: u16 | peer_len: u16 | peer (utf-8) | started_at_ms: u64
+//! then per frame: kind: u8 (1 = request, 2 = response) | timestamp_ms: u64 | len: u32 | bytes
+//! ```
+//!
+//! Requests are stored without their size prefix (exactly what `handle_request`
+//! consumes); responses are stored as sent, prefix included.
+//!
+//! Ids the broker generates come out different in the replay: the topic ids
+//! CreateTopics hands out and the member ids JoinGroup does. Each one a
+//! replayed response carries is paired with the recorded one in its place, and
+//! from then on the recorded id is swapped for the replayed one, in the
+//! requests fed to the broker and in the responses they're compared against.
+//! So later frames that name it, from Metadata and DescribeTopicPartitions
+//! echoing a topic id to SyncGroup and Heartbeat sending a member id, replay
+//! as they were recorded.
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{bail, Context, Result};
+
+use crate::codec::{DecodeError, Decoder};
+use crate::{
+    throttle_slot, API_VERSIONS_KEY, CREATE_TOPICS_KEY, INIT_PRODUCER_ID_KEY, JOIN_GROUP_KEY,
+};
+
+const MAGIC: &[u8; 4] = b"EVSS";
+/// Bump when the layout changes, and keep reading every older version.
+const FORMAT_VERSION: u16 = 1;
+
+const KIND_REQUEST: u8 = 1;
+const KIND_RESPONSE: u8 = 2;
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Appends one connection's frames to its session file. Recording problems are
+/// logged and switch recording off for the connection; they never affect the
+/// client being served.
+pub struct SessionRecorder {
+    file: BufWriter<File>,
+    path: PathBuf,
+    failed: bool,
+}
+
+impl SessionRecorder {
+    pub fn create(dir: &Path, peer: SocketAddr) -> Option<SessionRecorder> {
+        let started_at_ms = now_millis();
+        let path = dir.join(format!(
+            "{}-{}_{}.session",
+            started_at_ms,
+            peer.ip(),
+            peer.port()
+        ));
+
+        let result = fs::create_dir_all(dir)
+            .and_then(|()| File::create(&path))
+            .and_then(|file| {
+                let mut file = BufWriter::new(file);
+                let peer = peer.to_string();
+                file.write_all(MAGIC)?;
+                file.write_all(&FORMAT_VERSION.to_be_bytes())?;
+                file.write_all(&(peer.len() as u16).to_be_bytes())?;
+                file.write_all(peer.as_bytes())?;
+                file.write_all(&started_at_ms.to_be_bytes())?;
+                Ok(file)
+            });
+
+        match result {
+            Ok(file) => {
+                println!("Recording session to {}", path.display());
+                Some(SessionRecorder {
+                    file,
+                    path,
+                    failed: false,
+                })
+            }
+            Err(e) => {
+                println!(
+                    "Could not start session recording in {}: {}",
+                    dir.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
+    pub fn record_request(&mut self, frame: &[u8]) {
+        self.write_record(KIND_REQUEST, frame);
+    }
+
+    /// Responses also flush, so the file is complete up to the last exchange even if
+    /// the connection dies before it is dropped.
+    pub fn record_response(&mut self, frame: &[u8]) {
+        self.write_record(KIND_RESPONSE, frame);
+        if !self.failed {
+            if let Err(e) = self.file.flush() {
+                self.fail(e);
+            }
+        }
+    }
+
+    fn write_record(&mut self, kind: u8, frame: &[u8]) {
+        if self.failed {
+            return;
+        }
+        let result = self
+            .file
+            .write_all(&[kind])
+            .and_then(|()| self.file.write_all(&now_millis().to_be_bytes()))
+            .and_then(|()| self.file.write_all(&(frame.len() as u32).to_be_bytes()))
+            .and_then(|()| self.file.write_all(frame));
+        if let Err(e) = result {
+            self.fail(e);
+        }
+    }
+
+    fn fail(&mut self, e: std::io::Error) {
+        println!(
+            "Session recording to {} failed: {}, recording disabled for this connection",
+            self.path.display(),
+            e
+        );
+        self.failed = true;
+    }
+}
+
+struct Record<'a> {
+    kind: u8,
+    bytes: &'a [u8],
+}
+
+fn read_records(contents: &[u8]) -> Result<(String, Vec<Record<'_>>)> {
+    let mut decoder = Decoder::new(contents);
+    if decoder.read_bytes(MAGIC.len())? != MAGIC {
+        bail!("not a session file (bad magic)");
+    }
+    let version = decoder.read_u16()?;
+    if version == 0 || version > FORMAT_VERSION {
+        bail!(
+            "session format version {} is not supported (expected 1..={})",
+            version,
+            FORMAT_VERSION
+        );
+    }
+
+    let peer_len = decoder.read_u16()? as usize;
+    let peer = String::from_utf8_lossy(decoder.read_bytes(peer_len)?).into_owned();
+    decoder.read_bytes(8)?; // started_at_ms
+
+    let mut records = Vec::new();
+    while decoder.remaining() > 0 {
+        let kind = decoder.read_bytes(1)?[0];
+        decoder.read_bytes(8)?; // timestamp_ms
+        let len = decoder.read_u32()? as usize;
+        let bytes = decoder.read_bytes(len)?;
+        if kind != KIND_REQUEST && kind != KIND_RESPONSE {
+            bail!("unknown frame kind {} in session file", kind);
+        }
+        records.push(Record { kind, bytes });
+    }
+    Ok((peer, records))
+}
+
+/// Byte ranges of a response that may legitimately differ between the recorded run
+/// and the replay: throttle_time_ms, wherever `SUPPORTED_APIS` places it, and
+/// the producer ids InitProducerId hands out, which no later frame has to match.
+/// Generated ids that later frames do name are swapped instead (see `minted_ids`).
+fn nondeterministic_ranges(request: &[u8], response: &[u8]) -> Vec<Range<usize>> {
+    let (Some(api_key), Some(api_version)) = (request.get(..2), request.get(2..4)) else {
+        return Vec::new();
+    };
+    let api_key = u16::from_be_bytes([api_key[0], api_key[1]]);
+    let api_version = i16::from_be_bytes([api_version[0], api_version[1]]);
+    // A rejected ApiVersions request is answered with the v0 body, which has
+    // no throttle field to mask
+    let error_code = response
+        .get(8..10)
+        .map(|code| u16::from_be_bytes([code[0], code[1]]));
+
+    let mut ranges = Vec::new();
+    if api_key != API_VERSIONS_KEY || error_code == Some(0) {
+        ranges.extend(throttle_slot(api_key, api_version, response));
+    }
+    // InitProducerId: the producer id handed out follows the throttle time and
+    // error code
+    match api_key {
+        INIT_PRODUCER_ID_KEY if api_version >= 2 && response.len() >= 23 => ranges.push(15..23),
+        INIT_PRODUCER_ID_KEY if response.len() >= 22 => ranges.push(14..22),
+        _ => {}
+    }
+    ranges
+}
+
+/// Ids generated for a response that later frames may name: each topic id a
+/// CreateTopics v7+ response carries, and the member id a JoinGroup response
+/// hands out (or, with MEMBER_ID_REQUIRED, asks to be joined with). Empty for
+/// other APIs and for responses that don't parse.
+fn minted_ids(request: &[u8], response: &[u8]) -> Vec<Vec<u8>> {
+    let (Some(api_key), Some(api_version)) = (request.get(..2), request.get(2..4)) else {
+        return Vec::new();
+    };
+    let api_key = u16::from_be_bytes([api_key[0], api_key[1]]);
+    let api_version = i16::from_be_bytes([api_version[0], api_version[1]]);
+    let Some(body) = response.get(4..) else {
+        return Vec::new();
+    };
+    let minted = match api_key {
+        CREATE_TOPICS_KEY if api_version >= 7 => created_topic_ids(body),
+        JOIN_GROUP_KEY => joined_member_id(body, api_version).map(|id| vec![id]),
+        _ => Ok(Vec::new()),
+    };
+    minted.unwrap_or_default()
+}
+
+/// CreateTopics v7 (flexible): correlation_id, TAG_BUFFER, throttle_time_ms,
+/// topics [name, topic_id, error_code, error_message, num_partitions,
+/// replication_factor, configs, TAG_BUFFER].
+fn created_topic_ids(body: &[u8]) -> Result<Vec<Vec<u8>>, DecodeError> {
+    let mut decoder = Decoder::new(body);
+    decoder.read_u32()?;
+    decoder.skip_tagged_fields()?;
+    decoder.read_i32()?;
+    let mut topic_ids = Vec::new();
+    for _ in 0..decoder.read_array_len_or_empty(true)? {
+        decoder.read_compact_string()?;
+        topic_ids.push(decoder.read_uuid()?.to_vec());
+        decoder.read_i16()?;
+        decoder.read_compact_nullable_string()?;
+        decoder.read_i32()?;
+        decoder.read_i16()?;
+        for _ in 0..decoder.read_array_len_or_empty(true)? {
+            decoder.read_compact_string()?;
+            decoder.read_compact_nullable_string()?;
+            decoder.read_bytes(3)?; // read_only, config_source, is_sensitive
+            decoder.skip_tagged_fields()?;
+        }
+        decoder.skip_tagged_fields()?;
+    }
+    Ok(topic_ids)
+}
+
+/// JoinGroup v2+: correlation_id, TAG_BUFFER (v6+), throttle_time_ms,
+/// error_code, generation_id, protocol_type (v7+), protocol_name, leader,
+/// skip_assignment (v9+), member_id.
+fn joined_member_id(body: &[u8], api_version: i16) -> Result<Vec<u8>, DecodeError> {
+    let flexible = api_version >= 6;
+    let mut decoder = Decoder::new(body);
+    let read_string = |decoder: &mut Decoder| decoder.read_nullable_string(flexible);
+    decoder.read_u32()?;
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+    decoder.read_i32()?;
+    decoder.read_i16()?;
+    decoder.read_i32()?;
+    if api_version >= 7 {
+        read_string(&mut decoder)?;
+    }
+    read_string(&mut decoder)?; // protocol_name
+    read_string(&mut decoder)?; // leader
+    if api_version >= 9 {
+        decoder.read_i8()?;
+    }
+    Ok(read_string(&mut decoder)?.unwrap_or_default().into_bytes())
+}
+
+/// `frame` with every recorded id in `swaps` replaced by the replayed one.
+/// Only ids of the same length are swapped, so framing is left intact.
+fn swap_ids(frame: &[u8], swaps: &[(Vec<u8>, Vec<u8>)]) -> Vec<u8> {
+    let mut frame = frame.to_vec();
+    for (recorded, replayed) in swaps {
+        let mut offset = 0;
+        while let Some(found) = frame[offset..]
+            .windows(recorded.len())
+            .position(|window| window == recorded.as_slice())
+        {
+            let start = offset + found;
+            frame[start..start + recorded.len()].copy_from_slice(replayed);
+            offset = start + recorded.len();
+        }
+    }
+    frame
+}
+
+/// Offset of the first byte that differs outside the masked ranges, if any.
+fn first_difference(request: &[u8], recorded: &[u8], replayed: &[u8]) -> Option<usize> {
+    let masked = nondeterministic_ranges(request, recorded);
+    (0..recorded.len().max(replayed.len()))
+        .filter(|offset| !masked.iter().any(|range| range.contains(offset)))
+        .find(|&offset| recorded.get(offset) != replayed.get(offset))
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Replays a recorded session through `handle_request` and reports every exchange
+/// whose response differs from the recording.
+pub fn replay(path: &Path, mut handle_request: impl FnMut(&[u8]) -> Option<Vec<u8>>) -> Result<()> {
+    let contents =
+        fs::read(path).with_context(|| format!("reading session file {}", path.display()))?;
+    let (peer, records) = read_records(&contents)
+        .with_context(|| format!("parsing session file {}", path.display()))?;
+    println!("Replaying {} frames recorded from {}", records.len(), peer);
+
+    let mut exchanges = 0;
+    let mut mismatches = 0;
+    // (recorded, replayed) pairs of the ids generated so far
+    let mut swaps: Vec<(Vec<u8>, Vec<u8>)> = Vec::new();
+    let mut records = records.iter().peekable();
+    while let Some(request) = records.next() {
+        if request.kind != KIND_REQUEST {
+            bail!(
+                "response frame with no request before it in {}",
+                path.display()
+            );
+        }
+        exchanges += 1;
+        let recorded = records
+            .next_if(|record| record.kind == KIND_RESPONSE)
+            .map(|record| record.bytes);
+        let api_key = request
+            .bytes
+            .get(..2)
+            .map(|key| u16::from_be_bytes([key[0], key[1]]))
+            .unwrap_or(0);
+
+        let request = swap_ids(request.bytes, &swaps);
+        match (recorded, handle_request(&request)) {
+            (Some(recorded), Some(replayed)) => {
+                let minted = minted_ids(&request, recorded)
+                    .into_iter()
+                    .zip(minted_ids(&request, &replayed));
+                swaps.extend(minted.filter(|(recorded, replayed)| {
+                    !recorded.is_empty() && recorded != replayed && recorded.len() == replayed.len()
+                }));
+                let recorded = swap_ids(recorded, &swaps);
+                if let Some(offset) = first_difference(&request, &recorded, &replayed) {
+                    mismatches += 1;
+                    println!(
+                        "Exchange {} (api key {}): responses differ at byte {}\n  recorded: {}\n  replayed: {}",
+                        exchanges,
+                        api_key,
+                        offset,
+                        hex(&recorded),
+                        hex(&replayed)
+                    );
+                }
+            }
+            (None, None) => {}
+            (Some(_), None) => {
+                mismatches += 1;
+                println!(
+                    "Exchange {} (api key {}): recorded a response but the replay closed the connection",
+                    exchanges, api_key
+                );
+            }
+            (None, Some(_)) => {
+                mismatches += 1;
+                println!(
+                    "Exchange {} (api key {}): recorded a closed connection but the replay responded",
+                    exchanges, api_key
+                );
+            }
+        }
+    }
+
+    if mismatches > 0 {
+        bail!(
+            "{} of {} exchanges differ from the recording",
+            mismatches,
+            exchanges
+        );
+    }
+    println!("All {} exchanges match the recording", exchanges);
+    Ok(())
+}
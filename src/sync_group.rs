@@ -0,0 +1,115 @@
+//! SyncGroup (API key 14), versions 0-5.
+//!
+//! After a JoinGroup round, the leader sends every member's assignment and the
+//! others send none; each is answered with its own assignment once the leader's
+//! have arrived, so followers syncing first wait rather than getting an empty
+//! one. See `group_coordinator`.
+//!
+//! v0-v3 use the classic encoding; v4+ are flexible.
+
+use crate::broker::Broker;
+use crate::codec::{DecodeError, ResponseWriter};
+use crate::group_coordinator::{SyncRequest, SyncResult};
+use crate::header::RequestHeader;
+use crate::throttle::THROTTLE_TIME_LEN;
+
+const FIRST_FLEXIBLE_VERSION: i16 = 4;
+
+/// Handles a SyncGroup request.
+pub fn handle(
+    broker: &Broker,
+    header: &RequestHeader,
+    request_buffer: &[u8],
+) -> Result<Vec<u8>, DecodeError> {
+    let api_version = header.api_version as i16;
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+    let mut decoder = RequestHeader::body_decoder(request_buffer, flexible)?;
+
+    let group_id = decoder.read_string(flexible)?;
+    let generation_id = decoder.read_i32()?;
+    let member_id = decoder.read_string(flexible)?;
+    if api_version >= 3 {
+        decoder.read_nullable_string(flexible)?; // group_instance_id
+    }
+    let (protocol_type, protocol_name) = if api_version >= 5 {
+        (
+            decoder.read_compact_nullable_string()?,
+            decoder.read_compact_nullable_string()?,
+        )
+    } else {
+        (None, None)
+    };
+    let assignment_count = decoder.read_array_len_or_empty(flexible)?;
+    let mut assignments = Vec::with_capacity(assignment_count);
+    for _ in 0..assignment_count {
+        let assigned_id = decoder.read_string(flexible)?;
+        let assignment = if flexible {
+            decoder.read_compact_nullable_bytes()?
+        } else {
+            decoder.read_nullable_bytes()?
+        };
+        if flexible {
+            decoder.skip_tagged_fields()?;
+        }
+        assignments.push((assigned_id, assignment.unwrap_or_default().to_vec()));
+    }
+    if flexible {
+        decoder.skip_tagged_fields()?;
+    }
+
+    let limits = &broker.config.echo_limits;
+    println!(
+        "SyncGroup: group={} generation_id={} member_id={} assignments={}",
+        limits.name(&group_id),
+        generation_id,
+        limits.name(&member_id),
+        assignments.len()
+    );
+    let result = broker.groups.sync(SyncRequest {
+        group_id,
+        generation_id,
+        member_id,
+        protocol_type,
+        protocol_name,
+        assignments,
+    });
+    println!(
+        "SyncGroup answered: {} byte(s) of assignment, error_code={}",
+        result.assignment.len(),
+        result.error_code
+    );
+    Ok(build_sync_group_response(
+        header.correlation_id,
+        api_version,
+        &result,
+    ))
+}
+
+/// SyncGroupResponse: throttle_time_ms (v1+), error_code, protocol_type (v5+),
+/// protocol_name (v5+), assignment.
+fn build_sync_group_response(
+    correlation_id: u32,
+    api_version: i16,
+    result: &SyncResult,
+) -> Vec<u8> {
+    let flexible = api_version >= FIRST_FLEXIBLE_VERSION;
+
+    let mut response = ResponseWriter::new(correlation_id, flexible);
+
+    if api_version >= 1 {
+        response.put_raw(&[0; THROTTLE_TIME_LEN]); // Filled in by handle_request
+    }
+    response.put_i16(result.error_code);
+    if api_version >= 5 {
+        response.put_compact_nullable_string(result.protocol_type.as_deref());
+        response.put_compact_nullable_string(result.protocol_name.as_deref());
+    }
+    if flexible {
+        response.put_compact_bytes(&result.assignment);
+        response.put_tag_buffer(); // response TAG_BUFFER
+    } else {
+        response.put_bytes(&result.assignment);
+    }
+
+    response.finish()
+}
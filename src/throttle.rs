@@ -0,0 +1,54 @@
+//! throttle_time_ms: how long the broker held a response back for quota.
+//!
+//! Handlers leave a zeroed slot for the field wherever their response schema
+//! puts it, as they do for the size prefix. Once a handler has built its
+//! response, `handle_request` writes the delay actually applied into that slot,
+//! found from the API's `ThrottleField` in `SUPPORTED_APIS`: the field opens
+//! most bodies, closes a few, and is missing from versions that predate it. No
+//! quotas are enforced yet, so the delay is always zero, but a version that has
+//! the field always carries it.
+
+use std::ops::Range;
+use std::time::Duration;
+
+pub const THROTTLE_TIME_LEN: usize = 4;
+
+/// Where an API's responses carry throttle_time_ms.
+#[derive(Clone, Copy)]
+pub enum ThrottleField {
+    /// No version has the field.
+    Absent,
+    /// The first field of the body, from version `since`.
+    First { since: i16 },
+    /// The last field of the body, from version `since`, followed by the
+    /// body's (empty) tag buffer from version `tag_buffer_from`.
+    Last { since: i16, tag_buffer_from: i16 },
+}
+
+impl ThrottleField {
+    /// The field's bytes in a `len`-byte response of `api_version` whose
+    /// header, size prefix included, is `header_len` bytes, or `None` if the
+    /// version has no such field or the response is too short to hold it.
+    pub fn slot(self, api_version: i16, header_len: usize, len: usize) -> Option<Range<usize>> {
+        let start = match self {
+            ThrottleField::First { since } if api_version >= since => header_len,
+            ThrottleField::Last {
+                since,
+                tag_buffer_from,
+            } if api_version >= since => {
+                let tag_buffer_len = usize::from(api_version >= tag_buffer_from);
+                len.checked_sub(THROTTLE_TIME_LEN + tag_buffer_len)?
+            }
+            _ => return None,
+        };
+        let slot = start..start + THROTTLE_TIME_LEN;
+        (start >= header_len && slot.end <= len).then_some(slot)
+    }
+}
+
+/// Writes `delay`, in whole milliseconds, into a response's throttle_time_ms
+/// slot. A delay too long for the field is reported as the longest it holds.
+pub fn fill(response: &mut [u8], slot: Range<usize>, delay: Duration) {
+    let throttle_time_ms = i32::try_from(delay.as_millis()).unwrap_or(i32::MAX);
+    response[slot].copy_from_slice(&throttle_time_ms.to_be_bytes());
+}
@@ -0,0 +1,327 @@
+//! Topics created, grown and deleted by CreateTopics, CreatePartitions and
+//! DeleteTopics, saved so they survive a restart.
+//!
+//! The metadata log is Kafka's, written by its tools and only ever read here,
+//! so these changes go in a file of this broker's own beside it (see
+//! `saved_file`), `<log_dir>/topic-changes`: a `version=1` line, then one line
+//! per topic changed.
+//!
+//! - `created\t<topic id>\t<partition count>\t<name>` for a topic created here
+//!   and not since deleted, followed by a `config\t<topic id>\t<config>\t<value>`
+//!   line, with no tab or value for a null one, per override it was created
+//!   with;
+//! - `grown\t<topic id>\t<partition count>` for one of the log's topics grown
+//!   here;
+//! - `deleted\t<topic id>` for every topic deleted here, kept for good, as the
+//!   log would otherwise bring back topics of its own.
+//!
+//! A file of a version this broker doesn't know fails startup rather than
+//! being overwritten with less than it holds. It's rewritten whole for each
+//! change, before the change is applied or answered, so no topic a client was
+//! told of is lost. A temporary file left at startup is one a crash cut short
+//! before its rename, which is logged before the file is removed.
+//!
+//! Changes are saved only by brokers whose partition logs are
+//! (`log.store=file`); with logs in memory, a restart forgets them, as it does
+//! the records. At startup they're replayed onto the topics the metadata log
+//! has, in that order: created topics, grown partitions, then deletions.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::{Mutex, MutexGuard};
+
+use anyhow::{bail, Context, Result};
+
+use crate::config::{BrokerConfig, LogStoreKind};
+use crate::ids::{PartitionId, TopicId};
+use crate::metadata::{ClusterMetadata, TopicMetadata};
+use crate::saved_file::{self, escape, unescape};
+
+const CHANGES_FILE: &str = "topic-changes";
+const FORMAT_VERSION: u32 = 1;
+
+type Configs = Vec<(String, Option<String>)>;
+
+#[derive(Clone, Debug, PartialEq)]
+struct CreatedTopic {
+    topic_id: TopicId,
+    name: String,
+    partition_count: i32,
+    configs: Configs,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+struct Changes {
+    /// Topics created here and not since deleted, in the order they were.
+    created: Vec<CreatedTopic>,
+    /// The partition counts the log's topics were grown to.
+    grown: BTreeMap<TopicId, i32>,
+    /// Every topic deleted here.
+    deleted: BTreeSet<TopicId>,
+}
+
+pub struct TopicChanges {
+    /// Where they're saved, if they are.
+    path: Option<PathBuf>,
+    /// Every change saved. Locked while the file is written, which is what
+    /// keeps two changes from writing it at once.
+    saved: Mutex<Changes>,
+}
+
+impl TopicChanges {
+    /// Changes saved in the log directory, if the partition logs are.
+    pub fn new(config: &BrokerConfig) -> TopicChanges {
+        TopicChanges {
+            path: (config.log_store == LogStoreKind::File)
+                .then(|| config.log_dir.join(CHANGES_FILE)),
+            saved: Mutex::default(),
+        }
+    }
+
+    fn saved_locked(&self) -> MutexGuard<'_, Changes> {
+        // Replaced whole, and only once the file has been
+        self.saved
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Replays the saved changes onto `metadata`, which holds the metadata
+    /// log's topics.
+    pub fn load(&self, metadata: &mut ClusterMetadata) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let staged = saved_file::staged_path(path);
+        if staged.exists() {
+            println!(
+                "WARNING: {} is left from a topic change cut short before it was saved; \
+                 it was never applied or answered, and is discarded",
+                staged.display()
+            );
+            fs::remove_file(&staged).with_context(|| format!("removing {}", staged.display()))?;
+        }
+        let contents = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e).with_context(|| format!("reading {}", path.display())),
+        };
+        let changes = parse(&contents).with_context(|| format!("parsing {}", path.display()))?;
+        for topic in &changes.created {
+            metadata.restore_topic(TopicMetadata {
+                name: topic.name.clone(),
+                topic_id: topic.topic_id,
+                partitions: (0..topic.partition_count).map(PartitionId).collect(),
+                configs: topic.configs.clone(),
+            });
+        }
+        for (&topic_id, &partition_count) in &changes.grown {
+            let current = metadata
+                .topic_by_id(topic_id)
+                .map_or(partition_count, |topic| topic.partitions.len() as i32);
+            if partition_count > current {
+                metadata.add_partitions(topic_id, partition_count);
+            }
+        }
+        for &topic_id in &changes.deleted {
+            metadata.restore_deletion(topic_id);
+        }
+        println!(
+            "Loaded topic changes from {}: {} created, {} grown, {} deleted",
+            path.display(),
+            changes.created.len(),
+            changes.grown.len(),
+            changes.deleted.len()
+        );
+        *self.saved_locked() = changes;
+        Ok(())
+    }
+
+    /// Saves a topic CreateTopics has just created. Nothing is changed if it
+    /// can't be.
+    pub fn save_created(&self, topic: &TopicMetadata) -> io::Result<()> {
+        self.save(|changes| {
+            changes.created.push(CreatedTopic {
+                topic_id: topic.topic_id,
+                name: topic.name.clone(),
+                partition_count: topic.partitions.len() as i32,
+                configs: topic.configs.clone(),
+            });
+        })
+    }
+
+    /// Saves a topic's new partition count.
+    pub fn save_grown(&self, topic_id: TopicId, partition_count: i32) -> io::Result<()> {
+        self.save(|changes| {
+            match changes
+                .created
+                .iter_mut()
+                .find(|topic| topic.topic_id == topic_id)
+            {
+                Some(topic) => topic.partition_count = partition_count,
+                None => {
+                    changes.grown.insert(topic_id, partition_count);
+                }
+            }
+        })
+    }
+
+    /// Saves a topic's deletion.
+    pub fn save_deleted(&self, topic_id: TopicId) -> io::Result<()> {
+        self.save(|changes| {
+            changes.created.retain(|topic| topic.topic_id != topic_id);
+            changes.grown.remove(&topic_id);
+            changes.deleted.insert(topic_id);
+        })
+    }
+
+    /// Rewrites the file with `change` made, then keeps it.
+    fn save(&self, change: impl FnOnce(&mut Changes)) -> io::Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let mut saved = self.saved_locked();
+        let mut next = saved.clone();
+        change(&mut next);
+        saved_file::replace(path, &format(&next))?;
+        *saved = next;
+        Ok(())
+    }
+}
+
+/// The file's contents for `changes`: created topics in creation order, the
+/// rest in id order.
+fn format(changes: &Changes) -> String {
+    let mut contents = format!("version={}\n", FORMAT_VERSION);
+    for topic in &changes.created {
+        contents.push_str(&format!(
+            "created\t{}\t{}\t{}\n",
+            topic.topic_id,
+            topic.partition_count,
+            escape(&topic.name)
+        ));
+        for (name, value) in &topic.configs {
+            contents.push_str(&format!("config\t{}\t{}", topic.topic_id, escape(name)));
+            if let Some(value) = value {
+                contents.push('\t');
+                contents.push_str(&escape(value));
+            }
+            contents.push('\n');
+        }
+    }
+    for (topic_id, partition_count) in &changes.grown {
+        contents.push_str(&format!("grown\t{}\t{}\n", topic_id, partition_count));
+    }
+    for topic_id in &changes.deleted {
+        contents.push_str(&format!("deleted\t{}\n", topic_id));
+    }
+    contents
+}
+
+fn parse(contents: &str) -> Result<Changes> {
+    let mut lines = contents.lines();
+    saved_file::check_version(lines.next(), FORMAT_VERSION)?;
+    let mut changes = Changes::default();
+    for (i, line) in lines.enumerate() {
+        let fields: Vec<&str> = line.split('\t').collect();
+        let topic_id = fields.get(1).copied().and_then(TopicId::from_hex);
+        let count = fields
+            .get(2)
+            .and_then(|count| count.parse::<i32>().ok())
+            .filter(|count| *count > 0);
+        match (fields[0], topic_id, fields.len()) {
+            ("created", Some(topic_id), 4) => {
+                let (Some(partition_count), Some(name)) = (count, unescape(fields[3])) else {
+                    bail!("line {} is not a created topic", i + 2);
+                };
+                changes.created.push(CreatedTopic {
+                    topic_id,
+                    name,
+                    partition_count,
+                    configs: Vec::new(),
+                });
+            }
+            ("config", Some(topic_id), 3 | 4) => {
+                let name = unescape(fields[2]);
+                let value = fields.get(3).map(|value| unescape(value));
+                let (Some(topic), Some(name), None | Some(Some(_))) = (
+                    changes
+                        .created
+                        .last_mut()
+                        .filter(|topic| topic.topic_id == topic_id),
+                    name,
+                    &value,
+                ) else {
+                    bail!("line {} is not a config of the topic before it", i + 2);
+                };
+                topic.configs.push((name, value.flatten()));
+            }
+            ("grown", Some(topic_id), 3) => {
+                let Some(partition_count) = count else {
+                    bail!("line {} is not a grown topic", i + 2);
+                };
+                changes.grown.insert(topic_id, partition_count);
+            }
+            ("deleted", Some(topic_id), 2) => {
+                changes.deleted.insert(topic_id);
+            }
+            _ => bail!("line {} is not a topic change", i + 2),
+        }
+    }
+    Ok(changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_every_change() {
+        let changes = Changes {
+            created: vec![
+                CreatedTopic {
+                    topic_id: TopicId([9; 16]),
+                    name: "odd\tname".to_string(),
+                    partition_count: 3,
+                    configs: vec![
+                        ("retention.ms".to_string(), Some("60000".to_string())),
+                        ("x-null".to_string(), None),
+                    ],
+                },
+                CreatedTopic {
+                    topic_id: TopicId([3; 16]),
+                    name: "plain".to_string(),
+                    partition_count: 1,
+                    configs: Vec::new(),
+                },
+            ],
+            grown: BTreeMap::from([(TopicId([1; 16]), 4)]),
+            deleted: BTreeSet::from([TopicId([2; 16]), TopicId([5; 16])]),
+        };
+        assert_eq!(parse(&format(&changes)).unwrap(), changes);
+    }
+
+    #[test]
+    fn refuses_other_versions_and_malformed_lines() {
+        let id = TopicId([7; 16]);
+        let other = TopicId([8; 16]);
+        for contents in [
+            String::new(),
+            "version=2\n".to_string(),
+            format!("version=1\ncreated\t{}\t0\tnone\n", id),
+            format!("version=1\ncreated\t{}\t1\n", id),
+            format!("version=1\nconfig\t{}\tretention.ms\t1\n", id),
+            format!(
+                "version=1\ncreated\t{}\t1\tname\nconfig\t{}\tretention.ms\t1\n",
+                id, other
+            ),
+            format!("version=1\ngrown\t{}\tmany\n", id),
+            format!("version=1\ndeleted\t{}\textra\n", id),
+            "version=1\ndeleted\tnot-an-id\n".to_string(),
+            format!("version=1\nrenamed\t{}\n", id),
+        ] {
+            assert!(parse(&contents).is_err(), "{:?} parsed", contents);
+        }
+    }
+}
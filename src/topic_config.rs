@@ -0,0 +1,181 @@
+//! The topic configs the broker knows, and their defaults.
+//!
+//! A topic's configs are whatever overrides it was created with, or given by
+//! IncrementalAlterConfigs since (kept in its `TopicMetadata`), over these
+//! defaults. The defaults are Kafka's, so admin tools describing a topic see
+//! the values they expect; most of them aren't acted on by this broker.
+//! `retention.ms` is, but only where it is overridden: records produced before
+//! there was retention aren't deleted under the default.
+
+/// Kafka's config types, as DescribeConfigs reports them (v3+).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ConfigType {
+    Unknown,
+    String,
+    Int,
+    Long,
+    List,
+    Password,
+}
+
+impl ConfigType {
+    pub fn code(self) -> i8 {
+        match self {
+            ConfigType::Unknown => 0,
+            ConfigType::String => 2,
+            ConfigType::Int => 3,
+            ConfigType::Long => 5,
+            ConfigType::List => 7,
+            ConfigType::Password => 9,
+        }
+    }
+}
+
+pub struct TopicConfigDef {
+    pub name: &'static str,
+    pub default: &'static str,
+    pub config_type: ConfigType,
+    pub documentation: &'static str,
+    /// The values a string config, or each element of a list one, may take;
+    /// empty for any.
+    pub choices: &'static [&'static str],
+    /// Smallest value of a numeric config.
+    pub min: i64,
+}
+
+/// Every known topic config, by name.
+pub const TOPIC_CONFIGS: &[TopicConfigDef] = &[
+    TopicConfigDef {
+        name: "cleanup.policy",
+        default: "delete",
+        config_type: ConfigType::List,
+        documentation: "How old log segments are retired: delete or compact",
+        choices: &["delete", "compact"],
+        min: 0,
+    },
+    TopicConfigDef {
+        name: "compression.type",
+        default: "producer",
+        config_type: ConfigType::String,
+        documentation: "Compression of the topic's batches; producer keeps the producer's",
+        choices: &["uncompressed", "zstd", "lz4", "snappy", "gzip", "producer"],
+        min: 0,
+    },
+    TopicConfigDef {
+        name: "max.message.bytes",
+        default: "1048588",
+        config_type: ConfigType::Int,
+        documentation: "Largest record batch the topic accepts",
+        choices: &[],
+        min: 0,
+    },
+    TopicConfigDef {
+        name: "message.timestamp.type",
+        default: "CreateTime",
+        config_type: ConfigType::String,
+        documentation: "Whether record timestamps are CreateTime or LogAppendTime",
+        choices: &["CreateTime", "LogAppendTime"],
+        min: 0,
+    },
+    TopicConfigDef {
+        name: "min.insync.replicas",
+        default: "1",
+        config_type: ConfigType::Int,
+        documentation: "Replicas that must have a write for acks=all to succeed",
+        choices: &[],
+        min: 1,
+    },
+    TopicConfigDef {
+        name: "retention.bytes",
+        default: "-1",
+        config_type: ConfigType::Long,
+        documentation: "Most bytes a partition keeps before deleting old segments; -1 for no limit",
+        choices: &[],
+        min: -1,
+    },
+    TopicConfigDef {
+        name: "retention.ms",
+        default: "604800000",
+        config_type: ConfigType::Long,
+        documentation: "How long records are kept before they may be deleted; -1 for ever",
+        choices: &[],
+        min: -1,
+    },
+    TopicConfigDef {
+        name: "segment.bytes",
+        default: "1073741824",
+        config_type: ConfigType::Int,
+        documentation: "Size a log segment grows to before a new one is rolled",
+        choices: &[],
+        min: 14,
+    },
+];
+
+pub fn lookup(name: &str) -> Option<&'static TopicConfigDef> {
+    TOPIC_CONFIGS.iter().find(|config| config.name == name)
+}
+
+impl TopicConfigDef {
+    /// Why `value` can't be this config's, if it can't.
+    pub fn check(&self, value: &str) -> Result<(), String> {
+        let numeric = match self.config_type {
+            ConfigType::Int => value.parse::<i32>().ok().map(i64::from),
+            ConfigType::Long => value.parse::<i64>().ok(),
+            ConfigType::List => {
+                return match value.split(',').map(str::trim).find(|e| !self.allows(e)) {
+                    Some(element) => Err(format!(
+                        "'{}' is not one of {}",
+                        element,
+                        self.choices.join(", ")
+                    )),
+                    None => Ok(()),
+                }
+            }
+            _ if self.allows(value) => return Ok(()),
+            _ => return Err(format!("Expected one of {}", self.choices.join(", "))),
+        };
+        match numeric {
+            Some(number) if number >= self.min => Ok(()),
+            Some(_) => Err(format!("Expected at least {}", self.min)),
+            None => Err(format!("Expected {}", self.config_type.describe())),
+        }
+    }
+
+    fn allows(&self, value: &str) -> bool {
+        self.choices.is_empty() || self.choices.contains(&value)
+    }
+}
+
+impl ConfigType {
+    fn describe(self) -> &'static str {
+        match self {
+            ConfigType::Int => "a 32-bit integer",
+            ConfigType::Long => "a 64-bit integer",
+            ConfigType::List => "a comma-separated list",
+            ConfigType::Unknown | ConfigType::String | ConfigType::Password => "a string",
+        }
+    }
+}
+
+/// The value a topic's configs override `name` with, the last if it's given
+/// more than once; `None` if they leave it at its default.
+pub fn override_of<'a>(
+    configs: &'a [(String, Option<String>)],
+    name: &str,
+) -> Option<Option<&'a str>> {
+    configs
+        .iter()
+        .rev()
+        .find(|(config_name, _)| config_name == name)
+        .map(|(_, value)| value.as_deref())
+}
+
+/// How long a topic's records are kept, in milliseconds, if its retention.ms
+/// is overridden with a limit.
+pub fn retention_ms(configs: &[(String, Option<String>)]) -> Option<i64> {
+    override_of(configs, "retention.ms")
+        .flatten()?
+        .parse()
+        .ok()
+        .filter(|retention_ms| *retention_ms >= 0)
+}
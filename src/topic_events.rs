@@ -0,0 +1,128 @@
+//! Change notifications for the topic registry.
+//!
+//! The registry queues an event for every change while its write lock is still
+//! held, so events are queued in exactly the order the changes happened, with
+//! none skipped. A dedicated thread then hands each one to every subscriber, so
+//! subscribers run after the lock is released and a slow one never holds up a
+//! request.
+
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::ids::TopicId;
+
+/// One change to the registry.
+pub enum TopicEvent {
+    Created {
+        name: String,
+        topic_id: TopicId,
+        partition_count: usize,
+    },
+    /// The topic now has `partition_count` partitions, the new ones empty.
+    PartitionsAdded {
+        name: String,
+        topic_id: TopicId,
+        partition_count: usize,
+    },
+    /// The topic's config overrides were replaced; it now has `config_count`.
+    ConfigsAltered {
+        name: String,
+        topic_id: TopicId,
+        config_count: usize,
+    },
+    Deleted {
+        name: String,
+        topic_id: TopicId,
+    },
+}
+
+impl fmt::Display for TopicEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TopicEvent::Created {
+                name,
+                topic_id,
+                partition_count,
+            } => write!(
+                f,
+                "created '{}' ({}) with {} partition(s)",
+                name, topic_id, partition_count
+            ),
+            TopicEvent::PartitionsAdded {
+                name,
+                topic_id,
+                partition_count,
+            } => write!(
+                f,
+                "grew '{}' ({}) to {} partition(s)",
+                name, topic_id, partition_count
+            ),
+            TopicEvent::ConfigsAltered {
+                name,
+                topic_id,
+                config_count,
+            } => write!(
+                f,
+                "altered the configs of '{}' ({}), now {} override(s)",
+                name, topic_id, config_count
+            ),
+            TopicEvent::Deleted { name, topic_id } => {
+                write!(f, "deleted '{}' ({})", name, topic_id)
+            }
+        }
+    }
+}
+
+type Subscriber = Box<dyn Fn(&TopicEvent) + Send>;
+
+/// The queue from the registry to its subscribers.
+pub struct TopicEvents {
+    sender: Sender<TopicEvent>,
+    subscribers: Arc<Mutex<Vec<Subscriber>>>,
+}
+
+impl TopicEvents {
+    /// Starts the notification thread. It runs until the process exits.
+    pub fn start() -> TopicEvents {
+        let (sender, receiver) = mpsc::channel::<TopicEvent>();
+        let subscribers: Arc<Mutex<Vec<Subscriber>>> = Arc::default();
+        let delivering = Arc::clone(&subscribers);
+        thread::spawn(move || {
+            for event in receiver {
+                println!("Topic event: {}", event);
+                let subscribers = delivering
+                    .lock()
+                    .unwrap_or_else(|poisoned| poisoned.into_inner());
+                for subscriber in subscribers.iter() {
+                    // A panicking subscriber mustn't stop delivery to the others,
+                    // or to itself for later events
+                    if panic::catch_unwind(AssertUnwindSafe(|| subscriber(&event))).is_err() {
+                        println!("Topic event subscriber panicked on: {}", event);
+                    }
+                }
+            }
+        });
+        TopicEvents {
+            sender,
+            subscribers,
+        }
+    }
+
+    /// Registers `subscriber` for every event not yet delivered. Subscribers
+    /// run on the notification thread, one event at a time, and must not
+    /// subscribe from there.
+    pub fn subscribe(&self, subscriber: impl Fn(&TopicEvent) + Send + 'static) {
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(Box::new(subscriber));
+    }
+
+    /// Where the registry queues its events.
+    pub fn sender(&self) -> Sender<TopicEvent> {
+        self.sender.clone()
+    }
+}
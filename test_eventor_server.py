@@ -0,0 +1,6333 @@
+#!/usr/bin/env python3
+"""
+Quick test script for the Eventor server implementation.
+"""
+
+import errno
+import glob
+import json
+import os
+import random
+import re
+import shutil
+import socket
+import struct
+import subprocess
+import tempfile
+import time
+import threading
+
+EVENTOR_BINARY = "./target/release/Eventor"
+SESSION_DIR = "/tmp/eventor-sessions"  # session.record.dir in fixtures/server.properties
+ECHO_MAX_NAME_BYTES = 64                # error.echo.max.name.bytes in fixtures/server.properties
+ECHO_MAX_PARTITIONS = 16                # error.echo.max.partitions in fixtures/server.properties
+STARTUP_TEST_PORT = 9192                # listeners of the broker test_requests_before_readiness starts
+GROUP_MEMORY_TEST_PORT = 9193           # listeners of the broker test_group_memory_stays_flat starts
+SASL_TEST_PORT = 9194                   # listeners of the broker test_sasl_plain starts
+DESCRIBE_CLUSTER_TEST_PORT = 9195       # listeners of the broker test_describe_cluster starts
+FILE_LOG_STORE_TEST_PORT = 9196         # listeners of the broker test_file_log_store starts
+DESCRIBE_CONFIGS_TEST_PORT = 9197       # listeners of the broker test_describe_configs starts
+LISTENER_TEST_PORTS = (9198, 9199, 9200, 9201)  # the four listeners of the broker test_listener_policy starts
+RETENTION_TEST_PORT = 9202              # listeners of the broker test_incremental_alter_configs starts
+FLUSH_TEST_PORT = 9203                  # listeners of the broker test_flush_on_acks_all starts
+CLIENT_INVENTORY_TEST_PORT = 9204       # listeners of the broker test_client_inventory starts
+RECOVERY_TEST_PORT = 9205               # listeners of the broker test_parallel_log_recovery starts
+CONFIG_OVERRIDES_TEST_PORT = 9206       # listeners of the broker test_config_overrides_survive_restart starts
+
+class EventorTestClient:
+    def __init__(self, host="127.0.0.1", port=9092):
+        self.host = host
+        self.port = port
+        self.correlation_id_counter = 1
+    
+    def connect(self):
+        sock = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+        sock.connect((self.host, self.port))
+        return sock
+    
+    def get_next_correlation_id(self):
+        correlation_id = self.correlation_id_counter
+        self.correlation_id_counter += 1
+        return correlation_id
+    
+    def encode_client_id(self, client_id):
+        # NULLABLE_STRING: int16 length (-1 for null) followed by the raw bytes
+        if client_id is None:
+            return struct.pack(">h", -1)
+        if isinstance(client_id, str):
+            client_id = client_id.encode('utf-8')
+        return struct.pack(">h", len(client_id)) + client_id
+    
+    def build_api_versions_request(self, api_version=3, client_id="test-client",
+                                   software_name="eventor-test", software_version="1.0"):
+        correlation_id = self.get_next_correlation_id()
+        api_key = 18  # APIVersions
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">H", api_key))
+        request_body.extend(struct.pack(">h", api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id(client_id))
+        request_body.extend(struct.pack(">B", 0))  # Header tagged fields
+        for software in (software_name, software_version):  # client_software_name, client_software_version
+            software = software.encode('utf-8')
+            request_body.extend(encode_uvarint(len(software) + 1))
+            request_body.extend(software)
+        request_body.extend(struct.pack(">B", 0))  # Tagged fields
+        
+        message_size = len(request_body)
+        full_request = struct.pack(">I", message_size) + request_body
+        return full_request, correlation_id
+    
+    def build_describe_topic_partitions_request(self, topic_name="test-topic", api_version=0,
+                                                partition_limit=2000, cursor=None, client_id="test-client",
+                                                header_tags=b"\x00"):
+        """topic_name: one name or a list of them, where None is sent as a null name;
+        cursor: None or (topic_name, partition_index)"""
+        correlation_id = self.get_next_correlation_id()
+        api_key = 75
+        topics = [topic_name] if isinstance(topic_name, str) else topic_name
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">H", api_key))
+        request_body.extend(struct.pack(">h", api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id(client_id))
+        request_body.extend(header_tags)  # Header tagged fields
+        
+        request_body.extend(encode_uvarint(len(topics) + 1))
+        for name in topics:
+            if name is None:
+                request_body.extend(encode_uvarint(0))
+            else:
+                data = name.encode('utf-8')
+                request_body.extend(encode_uvarint(len(data) + 1))
+                request_body.extend(data)
+            request_body.extend(struct.pack(">B", 0))  # Tagged fields
+        request_body.extend(struct.pack(">i", partition_limit))
+        if cursor is None:
+            request_body.extend(struct.pack(">B", 0xff))  # Null cursor
+        else:
+            data = cursor[0].encode('utf-8')
+            request_body.extend(struct.pack(">B", 1))
+            request_body.extend(encode_uvarint(len(data) + 1))
+            request_body.extend(data)
+            request_body.extend(struct.pack(">iB", cursor[1], 0))
+        request_body.extend(struct.pack(">B", 0))  # Tagged fields
+        
+        message_size = len(request_body)
+        full_request = struct.pack(">I", message_size) + request_body
+        return full_request, correlation_id
+    
+    def build_create_topics_request(self, topics, api_version=7, validate_only=False, timeout_ms=5000):
+        """topics: [(name, num_partitions, replication_factor)] or
+        [(name, num_partitions, replication_factor, assignments, configs)] with
+        assignments [(partition_index, [broker_id])] and configs [(name, value or None)]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 5
+        
+        def string(value):
+            data = value.encode('utf-8')
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def nullable_string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            return string(value)
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 19, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(array_len(len(topics)))
+        for topic in topics:
+            name, num_partitions, replication_factor = topic[:3]
+            assignments, configs = topic[3:] if len(topic) > 3 else ([], [])
+            request_body.extend(string(name))
+            request_body.extend(struct.pack(">ih", num_partitions, replication_factor))
+            request_body.extend(array_len(len(assignments)))
+            for partition_index, broker_ids in assignments:
+                request_body.extend(struct.pack(">i", partition_index))
+                request_body.extend(array_len(len(broker_ids)))
+                for broker_id in broker_ids:
+                    request_body.extend(struct.pack(">i", broker_id))
+                request_body.extend(tags)
+            request_body.extend(array_len(len(configs)))
+            for config_name, value in configs:
+                request_body.extend(string(config_name))
+                request_body.extend(nullable_string(value))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(struct.pack(">iB", timeout_ms, 1 if validate_only else 0))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_delete_topics_request(self, topics, api_version=6, timeout_ms=5000):
+        """topics: names (str) or, for v6, topic ids (16 bytes)"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 4
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 20, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(encode_uvarint(len(topics) + 1) if flexible else struct.pack(">i", len(topics)))
+        for topic in topics:
+            if isinstance(topic, bytes):
+                request_body.extend(b"\x00" + topic + tags)  # null name, topic_id
+                continue
+            data = topic.encode("utf-8")
+            request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+            request_body.extend(data)
+            if api_version >= 6:
+                request_body.extend(bytes(16) + tags)  # topic_id unset
+        request_body.extend(struct.pack(">i", timeout_ms))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_create_partitions_request(self, topics, api_version=3, validate_only=False, timeout_ms=5000):
+        """topics: [(name, count)] or [(name, count, assignments)] with assignments
+        [[broker_id]] per new partition, or None"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 2
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 37, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(array_len(len(topics)))
+        for topic in topics:
+            name, count = topic[:2]
+            assignments = topic[2] if len(topic) > 2 else None
+            data = name.encode("utf-8")
+            request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+            request_body.extend(data)
+            request_body.extend(struct.pack(">i", count))
+            if assignments is None:
+                request_body.extend(b"\x00" if flexible else struct.pack(">i", -1))
+            else:
+                request_body.extend(array_len(len(assignments)))
+                for broker_ids in assignments:
+                    request_body.extend(array_len(len(broker_ids)))
+                    for broker_id in broker_ids:
+                        request_body.extend(struct.pack(">i", broker_id))
+                    request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(struct.pack(">iB", timeout_ms, 1 if validate_only else 0))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_delete_records_request(self, topics, api_version=2, timeout_ms=5000):
+        """topics: [(name, [(partition, offset)])]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 2
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 21, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(array_len(len(topics)))
+        for name, partitions in topics:
+            data = name.encode("utf-8")
+            request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+            request_body.extend(data)
+            request_body.extend(array_len(len(partitions)))
+            for partition, offset in partitions:
+                request_body.extend(struct.pack(">iq", partition, offset))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(struct.pack(">i", timeout_ms))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_offset_commit_request(self, group_id, topics, api_version=8, generation_id=-1, member_id=""):
+        """topics: [(name, [(partition_index, offset, metadata)])]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 8
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 8, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(string(group_id))
+        request_body.extend(struct.pack(">i", generation_id))
+        request_body.extend(string(member_id))
+        if api_version >= 7:
+            request_body.extend(string(None))  # group_instance_id
+        if api_version <= 4:
+            request_body.extend(struct.pack(">q", -1))  # retention_time_ms
+        request_body.extend(array_len(len(topics)))
+        for name, partitions in topics:
+            request_body.extend(string(name))
+            request_body.extend(array_len(len(partitions)))
+            for partition_index, offset, metadata in partitions:
+                request_body.extend(struct.pack(">iq", partition_index, offset))
+                if api_version >= 6:
+                    request_body.extend(struct.pack(">i", 0))  # committed_leader_epoch
+                request_body.extend(string(metadata))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_sync_group_request(self, group_id, generation_id, member_id, assignments=(), api_version=5,
+                                 protocol_type=None, protocol_name=None):
+        """assignments: [(member_id, assignment bytes)], sent by the leader only"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 4
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 14, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(string(group_id))
+        request_body.extend(struct.pack(">i", generation_id))
+        request_body.extend(string(member_id))
+        if api_version >= 3:
+            request_body.extend(string(None))  # group_instance_id
+        if api_version >= 5:
+            request_body.extend(string(protocol_type))
+            request_body.extend(string(protocol_name))
+        request_body.extend(array_len(len(assignments)))
+        for assigned_id, assignment in assignments:
+            request_body.extend(string(assigned_id))
+            request_body.extend(array_len(len(assignment)) if flexible else struct.pack(">i", len(assignment)))
+            request_body.extend(assignment)
+            request_body.extend(tags)
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_offset_fetch_request(self, groups, api_version=8, require_stable=False):
+        """groups: [(group_id, topics)], topics being [(name, [partition_index])] or None for
+        all; before v8 exactly one group"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 6
+        
+        def string(value):
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            if count is None:
+                return b"\x00" if flexible else struct.pack(">i", -1)
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        def topics_field(topics):
+            field = bytearray(array_len(None if topics is None else len(topics)))
+            for name, partitions in topics or []:
+                field.extend(string(name))
+                field.extend(array_len(len(partitions)))
+                for partition in partitions:
+                    field.extend(struct.pack(">i", partition))
+                field.extend(tags)
+            return field
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 9, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        if api_version >= 8:
+            request_body.extend(array_len(len(groups)))
+            for group_id, topics in groups:
+                request_body.extend(string(group_id))
+                request_body.extend(topics_field(topics))
+                request_body.extend(tags)
+        else:
+            assert len(groups) == 1, "Only v8+ batch groups"
+            group_id, topics = groups[0]
+            request_body.extend(string(group_id))
+            request_body.extend(topics_field(topics))
+        if api_version >= 7:
+            request_body.extend(struct.pack(">b", 1 if require_stable else 0))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_join_group_request(self, group_id, member_id="", protocols=(("range", b"meta"),), api_version=9,
+                                 session_timeout_ms=10000, rebalance_timeout_ms=10000, protocol_type="consumer",
+                                 group_instance_id=None):
+        """protocols: [(name, metadata bytes)] in order of preference"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 6
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 11, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(string(group_id))
+        request_body.extend(struct.pack(">ii", session_timeout_ms, rebalance_timeout_ms))
+        request_body.extend(string(member_id))
+        if api_version >= 5:
+            request_body.extend(string(group_instance_id))
+        request_body.extend(string(protocol_type))
+        request_body.extend(array_len(len(protocols)))
+        for name, metadata in protocols:
+            request_body.extend(string(name))
+            request_body.extend(array_len(len(metadata)) if flexible else struct.pack(">i", len(metadata)))
+            request_body.extend(metadata)
+            request_body.extend(tags)
+        if api_version >= 8:
+            request_body.extend(string("test"))  # reason
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_heartbeat_request(self, group_id, generation_id, member_id, api_version=4):
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 4
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 12, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(string(group_id))
+        request_body.extend(struct.pack(">i", generation_id))
+        request_body.extend(string(member_id))
+        if api_version >= 3:
+            request_body.extend(string(None))  # group_instance_id
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_leave_group_request(self, group_id, members, api_version=5):
+        """members: [(member_id, group_instance_id)]; before v3 exactly one, by member_id"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 4
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 13, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(string(group_id))
+        if api_version >= 3:
+            request_body.extend(encode_uvarint(len(members) + 1) if flexible else struct.pack(">i", len(members)))
+            for member_id, group_instance_id in members:
+                request_body.extend(string(member_id))
+                request_body.extend(string(group_instance_id))
+                if api_version >= 5:
+                    request_body.extend(string("shutting down"))  # reason
+                request_body.extend(tags)
+        else:
+            request_body.extend(string(members[0][0]))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_describe_groups_request(self, group_ids, api_version=5, include_authorized_operations=False):
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 5
+        
+        def string(value):
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 15, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(encode_uvarint(len(group_ids) + 1) if flexible else struct.pack(">i", len(group_ids)))
+        for group_id in group_ids:
+            request_body.extend(string(group_id))
+        if api_version >= 3:
+            request_body.extend(struct.pack(">b", 1 if include_authorized_operations else 0))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_list_groups_request(self, api_version=4, states_filter=()):
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 3
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 16, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        if api_version >= 4:
+            request_body.extend(encode_uvarint(len(states_filter) + 1))
+            for state in states_filter:
+                data = state.encode("utf-8")
+                request_body.extend(encode_uvarint(len(data) + 1) + data)
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_sasl_handshake_request(self, mechanism, api_version=1):
+        correlation_id = self.get_next_correlation_id()
+        data = mechanism.encode("utf-8")
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 17, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(struct.pack(">h", len(data)) + data)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_sasl_authenticate_request(self, auth_bytes, api_version=2):
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 2
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 36, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        if flexible:
+            request_body.extend(b"\x00")  # Header tagged fields (v2)
+            request_body.extend(encode_uvarint(len(auth_bytes) + 1))
+        else:
+            request_body.extend(struct.pack(">i", len(auth_bytes)))
+        request_body.extend(auth_bytes)
+        if flexible:
+            request_body.extend(b"\x00")
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_init_producer_id_request(self, transactional_id=None, api_version=5, transaction_timeout_ms=60000,
+                                       producer_id=-1, producer_epoch=-1):
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 2
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 22, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        if flexible:
+            request_body.extend(b"\x00")  # Header tagged fields (v2)
+        if transactional_id is None:
+            request_body.extend(b"\x00" if flexible else struct.pack(">h", -1))
+        else:
+            data = transactional_id.encode("utf-8")
+            request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+            request_body.extend(data)
+        request_body.extend(struct.pack(">i", transaction_timeout_ms))
+        if api_version >= 3:
+            request_body.extend(struct.pack(">qh", producer_id, producer_epoch))
+        if flexible:
+            request_body.extend(b"\x00")
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_describe_cluster_request(self, api_version=1, include_cluster_authorized_operations=False,
+                                       endpoint_type=1):
+        correlation_id = self.get_next_correlation_id()
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 60, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(b"\x00")  # Header tagged fields (v2)
+        request_body.extend(struct.pack(">b", 1 if include_cluster_authorized_operations else 0))
+        if api_version >= 1:
+            request_body.extend(struct.pack(">b", endpoint_type))
+        request_body.extend(b"\x00")
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_describe_configs_request(self, resources, api_version=4, include_synonyms=False,
+                                       include_documentation=False):
+        """resources: [(resource_type, resource_name, configuration_keys or None)]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 4
+        
+        def string(value):
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            if count is None:
+                return b"\x00" if flexible else struct.pack(">i", -1)
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 32, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(array_len(len(resources)))
+        for resource_type, resource_name, configuration_keys in resources:
+            request_body.extend(struct.pack(">b", resource_type))
+            request_body.extend(string(resource_name))
+            request_body.extend(array_len(None if configuration_keys is None else len(configuration_keys)))
+            for key in configuration_keys or []:
+                request_body.extend(string(key))
+            request_body.extend(tags)
+        if api_version >= 1:
+            request_body.extend(struct.pack(">b", 1 if include_synonyms else 0))
+        if api_version >= 3:
+            request_body.extend(struct.pack(">b", 1 if include_documentation else 0))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_incremental_alter_configs_request(self, resources, api_version=1, validate_only=False):
+        """resources: [(resource_type, resource_name, [(name, operation, value or None)])]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 1
+        
+        def string(value):
+            if value is None:
+                return b"\x00" if flexible else struct.pack(">h", -1)
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 44, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(array_len(len(resources)))
+        for resource_type, resource_name, configs in resources:
+            request_body.extend(struct.pack(">b", resource_type))
+            request_body.extend(string(resource_name))
+            request_body.extend(array_len(len(configs)))
+            for name, operation, value in configs:
+                request_body.extend(string(name))
+                request_body.extend(struct.pack(">b", operation))
+                request_body.extend(string(value))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(struct.pack(">?", validate_only))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_find_coordinator_request(self, keys, key_type=0, api_version=5):
+        """keys: group or transactional ids; before v4 exactly one"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 3
+        
+        def string(value):
+            data = value.encode("utf-8")
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 10, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        if api_version < 4:
+            assert len(keys) == 1, "Only v4+ batch coordinator keys"
+            request_body.extend(string(keys[0]))
+        if api_version >= 1:
+            request_body.extend(struct.pack(">b", key_type))
+        if api_version >= 4:
+            request_body.extend(encode_uvarint(len(keys) + 1))
+            for key in keys:
+                request_body.extend(string(key))
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_produce_request(self, topics, api_version=9, acks=-1, transactional_id=None):
+        """topics: [(name, [(partition_index, records_bytes)])]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 9
+        
+        def string(value):
+            data = value.encode('utf-8')
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 0, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        if transactional_id is None:
+            request_body.extend(b"\x00" if flexible else struct.pack(">h", -1))
+        else:
+            request_body.extend(string(transactional_id))
+        request_body.extend(struct.pack(">hi", acks, 5000))
+        request_body.extend(array_len(len(topics)))
+        for name, partitions in topics:
+            request_body.extend(string(name))
+            request_body.extend(array_len(len(partitions)))
+            for index, records in partitions:
+                request_body.extend(struct.pack(">i", index))
+                if flexible:
+                    request_body.extend(encode_uvarint(len(records) + 1))
+                else:
+                    request_body.extend(struct.pack(">i", len(records)))
+                request_body.extend(records)
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_metadata_request(self, topics=None, api_version=12):
+        """topics: None for all topics, else names (str) or v10+ topic ids (16 bytes)"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 9
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 3, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        if topics is None:
+            request_body.extend(b"\x00" if flexible else struct.pack(">i", -1))
+        else:
+            request_body.extend(encode_uvarint(len(topics) + 1) if flexible else struct.pack(">i", len(topics)))
+            for topic in topics:
+                if api_version >= 10:
+                    if isinstance(topic, bytes):
+                        request_body.extend(topic + b"\x00" + tags)  # topic_id, null name
+                        continue
+                    request_body.extend(bytes(16))
+                data = topic.encode("utf-8")
+                request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+                request_body.extend(data)
+                request_body.extend(tags)
+        if api_version >= 4:
+            request_body.extend(b"\x00")  # allow_auto_topic_creation
+        if 8 <= api_version <= 10:
+            request_body.extend(b"\x00")  # include_cluster_authorized_operations
+        if api_version >= 8:
+            request_body.extend(b"\x00")  # include_topic_authorized_operations
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_list_offsets_request(self, topics, api_version=7):
+        """topics: [(name, [(partition, timestamp)])]"""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 6
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 2, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(tags)  # Header tagged fields (v2)
+        request_body.extend(struct.pack(">i", -1))  # replica_id: a consumer
+        if api_version >= 2:
+            request_body.extend(b"\x00")  # isolation_level
+        request_body.extend(array_len(len(topics)))
+        for name, partitions in topics:
+            data = name.encode("utf-8")
+            request_body.extend(encode_uvarint(len(data) + 1) if flexible else struct.pack(">h", len(data)))
+            request_body.extend(data)
+            request_body.extend(array_len(len(partitions)))
+            for partition, timestamp in partitions:
+                request_body.extend(struct.pack(">i", partition))
+                if api_version >= 4:
+                    request_body.extend(struct.pack(">i", -1))  # current_leader_epoch
+                request_body.extend(struct.pack(">q", timestamp))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        request_body.extend(tags)
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def build_fetch_request(self, topics, api_version=12, max_wait_ms=0, min_bytes=1, max_bytes=1 << 20,
+                            header_tags=b"\x00", request_tags=b"\x00"):
+        """topics: [(name, [(partition, fetch_offset, partition_max_bytes)])], named by topic
+        id (16 bytes) from v13. A partition may add a fourth item, its current_leader_epoch.
+        header_tags and request_tags are the raw header and top-level tag buffers (v12+)."""
+        correlation_id = self.get_next_correlation_id()
+        flexible = api_version >= 12
+        
+        def string(value):
+            data = value.encode('utf-8')
+            return encode_uvarint(len(data) + 1) + data if flexible else struct.pack(">h", len(data)) + data
+        
+        def array_len(count):
+            return encode_uvarint(count + 1) if flexible else struct.pack(">i", count)
+        
+        tags = b"\x00" if flexible else b""
+        
+        request_body = bytearray()
+        request_body.extend(struct.pack(">Hh", 1, api_version))
+        request_body.extend(struct.pack(">I", correlation_id))
+        request_body.extend(self.encode_client_id("test-client"))
+        request_body.extend(header_tags if flexible else b"")  # Header tagged fields (v2)
+        if api_version < 15:
+            request_body.extend(struct.pack(">i", -1))  # replica_id
+        request_body.extend(struct.pack(">iiib", max_wait_ms, min_bytes, max_bytes, 0))
+        if api_version >= 7:
+            request_body.extend(struct.pack(">ii", 0, -1))  # session_id, session_epoch: no session
+        request_body.extend(array_len(len(topics)))
+        for name, partitions in topics:
+            request_body.extend(name if api_version >= 13 else string(name))
+            request_body.extend(array_len(len(partitions)))
+            for partition, fetch_offset, partition_max_bytes, *epoch in partitions:
+                request_body.extend(struct.pack(">i", partition))
+                if api_version >= 9:
+                    request_body.extend(struct.pack(">i", epoch[0] if epoch else -1))  # current_leader_epoch
+                request_body.extend(struct.pack(">q", fetch_offset))
+                if api_version >= 12:
+                    request_body.extend(struct.pack(">i", -1))  # last_fetched_epoch
+                if api_version >= 5:
+                    request_body.extend(struct.pack(">q", -1))  # log_start_offset
+                request_body.extend(struct.pack(">i", partition_max_bytes))
+                request_body.extend(tags)
+            request_body.extend(tags)
+        if api_version >= 7:
+            request_body.extend(array_len(0))  # forgotten_topics_data
+        if api_version >= 11:
+            request_body.extend(string(""))  # rack_id
+        request_body.extend(request_tags if flexible else b"")
+        
+        full_request = struct.pack(">I", len(request_body)) + request_body
+        return full_request, correlation_id
+    
+    def send_request_and_get_response(self, request, correlation_id):
+        connection = EventorConnection(self)
+        try:
+            connection.send(request, correlation_id)
+            return connection.read_response()
+        finally:
+            connection.close()
+
+def encode_uvarint(value):
+    out = bytearray()
+    while value >= 0x80:
+        out.append((value & 0x7F) | 0x80)
+        value >>= 7
+    out.append(value)
+    return bytes(out)
+
+def encode_varint(value):
+    return encode_uvarint((value << 1) ^ (value >> 63))
+
+def crc32c(data):
+    crc = 0xFFFFFFFF
+    for byte in data:
+        crc ^= byte
+        for _ in range(8):
+            crc = (crc >> 1) ^ (0x82F63B78 if crc & 1 else 0)
+    return crc ^ 0xFFFFFFFF
+
+def build_record_batch(values, producer_id=-1, producer_epoch=-1, base_sequence=-1, timestamps=None,
+                       max_timestamp=None):
+    """An uncompressed v2 record batch holding one keyless record per value,
+    with the given record timestamps (all 0 by default). max_timestamp defaults
+    to the latest of them."""
+    timestamps = timestamps or [0] * len(values)
+    base_timestamp = timestamps[0]
+    records = bytearray()
+    for offset_delta, (value, timestamp) in enumerate(zip(values, timestamps)):
+        record = (b"\x00" + encode_varint(timestamp - base_timestamp) + encode_varint(offset_delta)
+                  + encode_varint(-1) + encode_varint(len(value)) + value + encode_varint(0))
+        records.extend(encode_varint(len(record)) + record)
+    max_timestamp = max(timestamps) if max_timestamp is None else max_timestamp
+    after_crc = struct.pack(">hiqqqhii", 0, len(values) - 1, base_timestamp, max_timestamp,
+                            producer_id, producer_epoch, base_sequence, len(values)) + records
+    after_length = struct.pack(">ibI", 0, 2, crc32c(after_crc)) + after_crc
+    return struct.pack(">qi", 0, len(after_length)) + after_length
+
+def hexdump(data):
+    lines = []
+    for offset in range(0, len(data), 16):
+        chunk = data[offset:offset + 16]
+        hex_part = " ".join(f"{b:02x}" for b in chunk)
+        text = "".join(chr(b) if 32 <= b < 127 else "." for b in chunk)
+        lines.append(f"{offset:08x}  {hex_part:<47}  {text}")
+    return "\n".join(lines)
+
+class FramingError(AssertionError):
+    def __init__(self, message, data=b""):
+        super().__init__(f"{message}\n{hexdump(data)}")
+
+class EventorConnection:
+    """A broker connection whose reader enforces the framing contract: every
+    response answers the oldest outstanding request, the size prefix matches
+    the bytes that follow, and nothing trails the last response."""
+
+    def __init__(self, client):
+        self.sock = client.connect()
+        self.outstanding = []
+    
+    def send(self, request, correlation_id):
+        self.sock.sendall(request)
+        self.outstanding.append(correlation_id)
+    
+    def recv_exact(self, count, received=b""):
+        data = b""
+        while len(data) < count:
+            chunk = self.sock.recv(count - len(data))
+            if not chunk:
+                raise FramingError(
+                    f"Connection closed after {len(data)} of {count} expected bytes",
+                    received + data)
+            data += chunk
+        return data
+    
+    def read_response(self):
+        if not self.outstanding:
+            raise FramingError("Reading a response with no outstanding request")
+        
+        size_bytes = self.recv_exact(4)
+        message_size = struct.unpack(">I", size_bytes)[0]
+        body = self.recv_exact(message_size, size_bytes)
+        response = size_bytes + body
+        if message_size < 4:
+            raise FramingError(f"Frame of {message_size} bytes has no room for a correlation id", response)
+        
+        correlation_id = struct.unpack(">I", body[:4])[0]
+        expected_correlation_id = self.outstanding.pop(0)
+        if correlation_id != expected_correlation_id:
+            if correlation_id in self.outstanding:
+                message = (f"Response for correlation id {correlation_id} arrived before "
+                           f"the one for {expected_correlation_id}")
+            else:
+                message = (f"Correlation ID mismatch: expected {expected_correlation_id}, "
+                           f"got {correlation_id}")
+            raise FramingError(message, response)
+        
+        if not self.outstanding:
+            self.assert_no_trailing_bytes(response)
+        return response
+    
+    def assert_no_trailing_bytes(self, last_response):
+        self.sock.settimeout(0.05)
+        try:
+            extra = self.sock.recv(4096, socket.MSG_PEEK)
+        except socket.timeout:
+            extra = b""
+        finally:
+            self.sock.settimeout(None)
+        if extra:
+            raise FramingError(f"{len(extra)} unexpected bytes after the last response",
+                               last_response + extra)
+    
+    def close(self):
+        self.sock.close()
+
+def test_api_versions():
+    print("🧪 Testing APIVersions...")
+    client = EventorTestClient()
+    request, expected_correlation_id = client.build_api_versions_request(api_version=3)
+    response = client.send_request_and_get_response(request, expected_correlation_id)
+    
+    # Parse correlation ID from response
+    correlation_id = struct.unpack(">I", response[4:8])[0]
+    error_code = struct.unpack(">H", response[8:10])[0]
+    
+    assert correlation_id == expected_correlation_id, f"Correlation ID mismatch"
+    assert error_code == 0, f"Expected error code 0, got {error_code}"
+    print("✅ APIVersions test passed")
+
+def test_describe_topic_partitions():
+    print("🧪 Testing DescribeTopicPartitions...")
+    client = EventorTestClient()
+    request, expected_correlation_id = client.build_describe_topic_partitions_request("unknown-topic")
+    response = client.send_request_and_get_response(request, expected_correlation_id)
+    
+    # Parse correlation ID from response
+    correlation_id = struct.unpack(">I", response[4:8])[0]
+    assert correlation_id == expected_correlation_id, f"Correlation ID mismatch"
+    print("✅ DescribeTopicPartitions test passed")
+
+def test_client_id_edge_cases():
+    print("🧪 Testing client id edge cases...")
+    client = EventorTestClient()
+    cases = [
+        ("null", None),
+        ("empty", ""),
+        ("max-length", "c" * 256),
+        ("over-length", "c" * 10000),
+        ("non-UTF-8", b"\xff\xfe\xc3\x28bad"),
+        ("control characters", "evil\nclient\x1b[31m"),
+    ]
+    for name, client_id in cases:
+        request, expected_correlation_id = client.build_api_versions_request(client_id=client_id)
+        response = client.send_request_and_get_response(request, expected_correlation_id)
+        correlation_id = struct.unpack(">I", response[4:8])[0]
+        error_code = struct.unpack(">H", response[8:10])[0]
+        assert correlation_id == expected_correlation_id, f"Correlation ID mismatch for {name} client id"
+        assert error_code == 0, f"Expected error code 0 for {name} client id, got {error_code}"
+        # The body starts past the client id, whatever its length, and in header v2
+        # past the tag buffer after it
+        for header_tags in (b"\x00", encode_uvarint(2) + b"\x00\x03abc" + b"\x07\x00"):
+            request, corr_id = client.build_describe_topic_partitions_request(
+                "foo", client_id=client_id, header_tags=header_tags)
+            topics, _ = parse_describe_topic_partitions_response(client.send_request_and_get_response(request, corr_id))
+            assert [(error_code, topic) for error_code, topic, _, _, _ in topics] == [(0, "foo")], \
+                f"DescribeTopicPartitions after a {name} client id and tags {header_tags.hex()}: {topics}"
+    print("✅ Client id edge cases test passed")
+
+def test_concurrent_connections():
+    print("🧪 Testing concurrent connections...")
+    
+    def worker(worker_id, results):
+        try:
+            client = EventorTestClient()
+            request, corr_id = client.build_api_versions_request()
+            response = client.send_request_and_get_response(request, corr_id)
+            correlation_id = struct.unpack(">I", response[4:8])[0]
+            results.append(correlation_id == corr_id)
+        except Exception as e:
+            results.append(False)
+    
+    results = []
+    threads = []
+    
+    for i in range(5):
+        thread = threading.Thread(target=worker, args=(i, results))
+        threads.append(thread)
+        thread.start()
+    
+    for thread in threads:
+        thread.join()
+    
+    successful = sum(results)
+    print(f"✅ Concurrent test: {successful}/5 connections successful")
+    assert successful == 5, f"Not all concurrent connections succeeded"
+
+def test_multiple_requests():
+    print("🧪 Testing multiple requests on same connection...")
+    
+    client = EventorTestClient()
+    connection = EventorConnection(client)
+    
+    try:
+        # First request
+        request1, corr_id1 = client.build_api_versions_request(api_version=3)
+        connection.send(request1, corr_id1)
+        connection.read_response()
+        
+        # Second request
+        request2, corr_id2 = client.build_describe_topic_partitions_request("test-topic")
+        connection.send(request2, corr_id2)
+        connection.read_response()
+        
+        print("✅ Multiple requests test passed")
+        
+    finally:
+        connection.close()
+
+def test_pipelined_requests():
+    print("🧪 Testing pipelined requests are answered in order...")
+    
+    client = EventorTestClient()
+    connection = EventorConnection(client)
+    
+    try:
+        # Send everything before reading anything back
+        request, corr_id = client.build_api_versions_request(api_version=3)
+        connection.send(request, corr_id)
+        request, corr_id = client.build_describe_topic_partitions_request("test-topic")
+        connection.send(request, corr_id)
+        request, corr_id = client.build_api_versions_request(api_version=7)
+        connection.send(request, corr_id)
+        
+        for _ in range(3):
+            connection.read_response()
+        
+        print("✅ Pipelined requests test passed")
+        
+    finally:
+        connection.close()
+
+def test_malformed_frames_keep_connection():
+    print("🧪 Testing malformed bodies are answered INVALID_REQUEST on the same connection...")
+    client = EventorTestClient()
+    
+    def reframed(request, cut=0, body=None):
+        """The request with its body cut short by `cut` bytes, or replaced, in a
+        frame whose size prefix matches."""
+        body = request[4:len(request) - cut] if body is None else body
+        return struct.pack(">I", len(body)) + body
+    
+    connection = EventorConnection(client)
+    try:
+        # Good, bad, good, pipelined: the bad one fails alone
+        request, first = client.build_api_versions_request(api_version=3)
+        connection.send(request, first)
+        request, bad = client.build_metadata_request(["foo"], api_version=12)
+        connection.send(reframed(request, cut=6), bad)
+        request, third = client.build_metadata_request(["foo"], api_version=12)
+        connection.send(request, third)
+        # A client id running past its frame, and a Produce that isn't acks=0
+        client_id_past_frame = struct.pack(">HhIh", 3, 1, 9001, 500) + b"short"
+        connection.send(reframed(b"", body=client_id_past_frame), 9001)
+        request, produce = client.build_produce_request(
+            [("foo", [(0, build_record_batch([b"cut"]))])], acks=1)
+        connection.send(reframed(request, cut=10), produce)
+        request, last = client.build_api_versions_request(api_version=3)
+        connection.send(request, last)
+        
+        response = connection.read_response()
+        assert struct.unpack(">h", response[8:10])[0] == 0, "First ApiVersions failed"
+        response = connection.read_response()
+        assert struct.unpack(">h", response[9:11])[0] == 42, f"Truncated Metadata: {response.hex()}"
+        response = connection.read_response()
+        [(error_code, name, _, _)] = parse_metadata_response(response, 12)[3]
+        assert (error_code, name) == (0, "foo"), f"Metadata after the bad one: {error_code} {name}"
+        response = connection.read_response()
+        assert struct.unpack(">h", response[8:10])[0] == 42, f"Client id past the frame: {response.hex()}"
+        response = connection.read_response()
+        assert struct.unpack(">h", response[9:11])[0] == 42, f"Truncated Produce: {response.hex()}"
+        response = connection.read_response()
+        assert struct.unpack(">h", response[8:10])[0] == 0, "Last ApiVersions failed"
+    finally:
+        connection.close()
+    
+    # Where the frame itself can't be trusted, or the client may not be waiting, the connection closes
+    request, _ = client.build_produce_request([("foo", [(0, build_record_batch([b"cut"]))])], acks=0)
+    oversized = struct.pack(">I", 0x7FFFFFFF)
+    truncated_at_eof = struct.pack(">I", 100) + b"x" * 20
+    for name, frame, close_write in (("acks=0 Produce", reframed(request, cut=10), False),
+                                      ("Size past socket.request.max.bytes", oversized, False),
+                                      ("Frame cut short by EOF", truncated_at_eof, True)):
+        sock = client.connect()
+        try:
+            sock.sendall(frame)
+            if close_write:
+                sock.shutdown(socket.SHUT_WR)
+            # Well inside request.read.timeout.ms: closing mustn't wait on a body
+            sock.settimeout(0.5)
+            try:
+                assert sock.recv(1) == b"", f"{name}: connection answered"
+            except ConnectionResetError:
+                pass
+        finally:
+            sock.close()
+    print("✅ Malformed frame recovery test passed")
+
+def test_multi_byte_varints():
+    print("🧪 Testing lengths, counts and tags past one varint byte, and broken varints...")
+    client = EventorTestClient()
+    
+    # A 200-byte name: a two-byte compact length in every request naming it. Responses
+    # echo it capped by error.echo.max.name.bytes, so each is looked up as the only topic.
+    name = "v" * 200
+    request, corr_id = client.build_create_topics_request([(name, 1, 1)])
+    [created] = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7).values()
+    assert created[0] == 0, f"CreateTopics of a 200-byte name: {created}"
+    try:
+        request, corr_id = client.build_metadata_request([name])
+        [(error_code, _, _, partitions)] = parse_metadata_response(
+            client.send_request_and_get_response(request, corr_id), 12)[3]
+        assert (error_code, list(partitions)) == (0, [0]), \
+            f"Metadata of a 200-byte name: {error_code} {partitions}"
+        request, corr_id = client.build_describe_topic_partitions_request(name)
+        [(error_code, described, _, partitions, _)], _ = parse_describe_topic_partitions_response(
+            client.send_request_and_get_response(request, corr_id))
+        assert (error_code, described, len(partitions)) == (0, name, 1), \
+            f"DescribeTopicPartitions of a 200-byte name: {error_code} {described}"
+        # Record lengths of 200 and 16384, and timestamp deltas below zero and past two bytes
+        values = [b"a" * 200, b"b" * 16384, b"c"]
+        request, corr_id = client.build_produce_request(
+            [(name, [(0, build_record_batch(values, timestamps=[5000, 4000, 25000]))])])
+        [(error_code, base_offset, _)] = parse_produce_response(
+            client.send_request_and_get_response(request, corr_id), 9).values()
+        assert error_code == 0, f"Produce with multi-byte record varints failed with {error_code}"
+        request, corr_id = client.build_fetch_request([(name, [(0, 0, 1 << 20)])])
+        [(error_code, _, records)] = parse_fetch_response(
+            client.send_request_and_get_response(request, corr_id), 12).values()
+        assert (error_code, decode_record_batches(records)) == (0, [(base_offset, values)]), \
+            f"Fetch of multi-byte record varints: {error_code}"
+        for target, expected in ((4000, (5000, base_offset)), (20000, (25000, base_offset + 2))):
+            request, corr_id = client.build_list_offsets_request([(name, [(0, target)])])
+            [(error_code, timestamp, offset, _)] = parse_list_offsets_response(
+                client.send_request_and_get_response(request, corr_id), 7).values()
+            assert (error_code, (timestamp, offset)) == (0, expected), \
+                f"ListOffsets for {target}: {error_code} {timestamp} {offset}"
+    finally:
+        request, corr_id = client.build_delete_topics_request([name])
+        client.send_request_and_get_response(request, corr_id)
+    
+    def with_header_tags(request, tags):
+        """A Metadata v12 request from build_metadata_request with its header tag
+        buffer (after the 11-byte client id) replaced by `tags`."""
+        header_end = 4 + 8 + 2 + len("test-client")
+        body = request[4:header_end] + tags + request[header_end + 1:]
+        return struct.pack(">I", len(body)) + body
+    
+    def metadata_with_body(body):
+        """A Metadata v12 frame with `body` after the request header."""
+        request, corr_id = client.build_metadata_request([])
+        header_end = 4 + 8 + 2 + len("test-client") + 1
+        frame = request[4:header_end] + body
+        return struct.pack(">I", len(frame)) + frame, corr_id
+    
+    # 130 header tags, with a two-byte tag number and sizes of 127, 128 and 16384
+    fields = [(0, 127), (200, 128), (16384, 16384)] + [(20000 + i, 0) for i in range(127)]
+    tags = encode_uvarint(len(fields)) + b"".join(
+        encode_uvarint(tag) + encode_uvarint(size) + bytes(size) for tag, size in fields)
+    connection = EventorConnection(client)
+    try:
+        request, corr_id = client.build_metadata_request(["foo"])
+        connection.send(with_header_tags(request, tags), corr_id)
+        [(error_code, described, _, _)] = parse_metadata_response(connection.read_response(), 12)[3]
+        assert (error_code, described) == (0, "foo"), f"Metadata past multi-byte header tags: {error_code}"
+        
+        # Cut short, too long, past 64 bits, and a u32::MAX length: answered, not fatal
+        for label, body in (("A count cut mid-varint", b"\x82"),
+                            ("An 11-byte varint", b"\xff" * 11),
+                            ("A varint past 64 bits", b"\xff" * 9 + b"\x02"),
+                            ("A u32::MAX name length", encode_uvarint(2) + bytes(16) + encode_uvarint(0xFFFFFFFF))):
+            frame, corr_id = metadata_with_body(body)
+            connection.send(frame, corr_id)
+            response = connection.read_response()
+            assert struct.unpack(">h", response[9:11])[0] == 42, f"{label}: {response.hex()}"
+        request, corr_id = client.build_metadata_request(["foo"])
+        connection.send(request, corr_id)
+        [(error_code, described, _, _)] = parse_metadata_response(connection.read_response(), 12)[3]
+        assert (error_code, described) == (0, "foo"), "Metadata after the broken varints"
+    finally:
+        connection.close()
+    print("✅ Multi-byte varint test passed")
+
+def test_tagged_fields():
+    print("🧪 Testing tag buffers with known, unknown and misordered fields...")
+    client = EventorTestClient()
+    
+    def tag_buffer(fields):
+        return encode_uvarint(len(fields)) + b"".join(
+            encode_uvarint(tag) + encode_uvarint(len(data)) + data for tag, data in fields)
+    
+    name = "tagged-fields"
+    request, corr_id = client.build_create_topics_request([(name, 1, 1)])
+    [created] = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7).values()
+    assert created[0] == 0, f"CreateTopics of {name}: {created}"
+    try:
+        values = [b"one", b"two"]
+        request, corr_id = client.build_produce_request([(name, [(0, build_record_batch(values))])])
+        [(error_code, base_offset, _)] = parse_produce_response(
+            client.send_request_and_get_response(request, corr_id), 9).values()
+        assert error_code == 0, f"Produce to {name} failed with {error_code}"
+        request, corr_id = client.build_metadata_request([name])
+        [(_, _, topic_id, _)] = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)[3]
+        
+        # cluster_id and replica_state (a consumer's replica_id and epoch), then tags no
+        # version defines: each is stepped over by its size, known or not
+        cluster_id = encode_uvarint(len("other-cluster") + 1) + b"other-cluster"
+        replica_state = struct.pack(">iq", -1, -1) + b"\x00"
+        unknown = [(7, b""), (300, bytes(200)), (70000, b"\x00" * 3)]
+        header_tags = tag_buffer([(0, b"\x01"), (9, bytes(130))])
+        for api_version, topic in ((12, name), (15, topic_id), (16, topic_id)):
+            request, corr_id = client.build_fetch_request(
+                [(topic, [(0, 0, 1 << 20)])], api_version=api_version, header_tags=header_tags,
+                request_tags=tag_buffer([(0, cluster_id), (1, replica_state)] + unknown))
+            [(error_code, _, records)] = parse_fetch_response(
+                client.send_request_and_get_response(request, corr_id), api_version).values()
+            assert (error_code, decode_record_batches(records)) == (0, [(base_offset, values)]), \
+                f"Fetch v{api_version} past known and unknown tags: {error_code}"
+        
+        # Kafka writes tags in ascending order, once each; anything else is a broken
+        # request, answered with INVALID_REQUEST on a connection that carries on
+        connection = EventorConnection(client)
+        try:
+            for label, request_tags, tags in (("Tags out of order", tag_buffer([(3, b""), (1, replica_state)]), b"\x00"),
+                                               ("A repeated tag", tag_buffer([(2, b"a"), (2, b"b")]), b"\x00"),
+                                               ("Repeated header tags", b"\x00", tag_buffer([(5, b""), (5, b"")]))):
+                request, corr_id = client.build_fetch_request(
+                    [(name, [(0, 0, 1 << 20)])], header_tags=tags, request_tags=request_tags)
+                connection.send(request, corr_id)
+                response = connection.read_response()
+                assert struct.unpack(">h", response[9:11])[0] == 42, f"{label}: {response.hex()}"
+            request, corr_id = client.build_fetch_request([(name, [(0, 0, 1 << 20)])])
+            connection.send(request, corr_id)
+            [(error_code, _, records)] = parse_fetch_response(connection.read_response(), 12).values()
+            assert (error_code, decode_record_batches(records)) == (0, [(base_offset, values)]), \
+                "Fetch after the misordered tags"
+        finally:
+            connection.close()
+    finally:
+        request, corr_id = client.build_delete_topics_request([name])
+        client.send_request_and_get_response(request, corr_id)
+    print("✅ Tagged fields test passed")
+
+def parse_legacy_api_versions_response(response, api_version):
+    """Decodes an ApiVersions v0-v2 response (int32 array count, no tag buffers)."""
+    error_code = struct.unpack(">h", response[8:10])[0]
+    api_count = struct.unpack(">i", response[10:14])[0]
+    offset = 14
+    api_keys = {}
+    for _ in range(api_count):
+        api_key, min_version, max_version = struct.unpack(">hhh", response[offset:offset + 6])
+        api_keys[api_key] = (min_version, max_version)
+        offset += 6
+    if api_version >= 1:
+        throttle_time_ms = struct.unpack(">i", response[offset:offset + 4])[0]
+        assert throttle_time_ms == 0, f"Expected throttle_time_ms 0, got {throttle_time_ms}"
+        offset += 4
+    assert len(response) == offset, f"ApiVersions v{api_version} response has {len(response) - offset} extra bytes"
+    return error_code, api_keys
+
+def test_api_versions_legacy_layouts():
+    print("🧪 Testing ApiVersions v0-v2 use the non-flexible layout...")
+    client = EventorTestClient()
+    for api_version in (0, 1, 2):
+        request, expected_correlation_id = client.build_api_versions_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, expected_correlation_id)
+        error_code, api_keys = parse_legacy_api_versions_response(response, api_version)
+        assert error_code == 0, f"Expected error code 0 for v{api_version}, got {error_code}"
+        assert api_keys.get(18) == (0, 4), f"Unexpected ApiVersions range {api_keys.get(18)}"
+    print("✅ Legacy ApiVersions layout test passed")
+
+def test_version_range_rejections():
+    print("🧪 Testing versions outside each advertised range are rejected uniformly...")
+    client = EventorTestClient()
+    
+    # ApiVersions answers out-of-range versions with its v0 body so clients can downgrade
+    for api_version in (-1, 5):
+        request, expected_correlation_id = client.build_api_versions_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, expected_correlation_id)
+        error_code, api_keys = parse_legacy_api_versions_response(response, 0)
+        assert error_code == 35, f"Expected UNSUPPORTED_VERSION for ApiVersions v{api_version}, got {error_code}"
+        assert 18 in api_keys and 75 in api_keys, f"Rejection should still list our APIs, got {api_keys}"
+    
+    # Other APIs get their response header followed by the error code
+    for api_version in (-1, 1):
+        request, expected_correlation_id = client.build_describe_topic_partitions_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, expected_correlation_id)
+        expected = struct.pack(">IIBh", 7, expected_correlation_id, 0, 35)
+        assert_bytes_equal(expected, response, f"DescribeTopicPartitions v{api_version} rejection")
+    print("✅ Version range rejection test passed")
+
+def test_response_size_prefixes():
+    print("🧪 Testing every response's size prefix against the bytes that follow it...")
+    client = EventorTestClient()
+    
+    # Every ApiVersions version and a rejected one, DescribeTopicPartitions of known and
+    # unknown topics and with a cursor, and an API the broker doesn't have, sent at once
+    requests = [client.build_api_versions_request(api_version=v) for v in range(0, 5)]
+    requests.append(client.build_api_versions_request(api_version=9))
+    requests.append(client.build_describe_topic_partitions_request(["foo", "bar", "no-such-topic"]))
+    requests.append(client.build_describe_topic_partitions_request(["bar", "foo"], partition_limit=1))
+    corr_id = client.get_next_correlation_id()
+    header = struct.pack(">HhI", 1000, 0, corr_id) + client.encode_client_id("test-client")
+    requests.append((struct.pack(">I", len(header)) + header, corr_id))
+    
+    sock = client.connect()
+    try:
+        sock.sendall(b"".join(request for request, _ in requests))
+        sock.shutdown(socket.SHUT_WR)
+        received = bytearray()
+        while chunk := sock.recv(65536):
+            received.extend(chunk)
+    finally:
+        sock.close()
+    
+    # Walked frame by frame, each prefix has to land exactly on the next one
+    frames = []
+    offset = 0
+    while offset < len(received):
+        assert offset + 4 <= len(received), f"A size prefix cut short at byte {offset}: {received.hex()}"
+        size = struct.unpack_from(">I", received, offset)[0]
+        frames.append(bytes(received[offset:offset + 4 + size]))
+        offset += 4 + size
+    assert offset == len(received), f"The last prefix claims {offset - len(received)} bytes past the end"
+    assert [struct.unpack_from(">I", frame, 4)[0] for frame in frames] == [c for _, c in requests], \
+        f"Frames don't line up with the requests: {[frame.hex() for frame in frames]}"
+    
+    # Each frame is exactly what its decoder reads, with nothing left over
+    for api_version, frame in enumerate(frames[:3]):
+        error_code, api_keys = parse_legacy_api_versions_response(frame, api_version)
+        assert error_code == 0 and 75 in api_keys, f"ApiVersions v{api_version}: {frame.hex()}"
+    for frame in frames[3:5]:
+        error_code, api_keys = parse_api_versions_response(frame)
+        assert error_code == 0 and 75 in api_keys, f"ApiVersions v3+: {frame.hex()}"
+    error_code, api_keys = parse_legacy_api_versions_response(frames[5], 0)
+    assert error_code == 35 and 18 in api_keys, f"ApiVersions v9: {frames[5].hex()}"
+    topics, cursor = parse_describe_topic_partitions_response(frames[6])
+    assert [topic[:2] for topic in topics] == [(0, "bar"), (0, "foo"), (3, "no-such-topic")] and cursor is None, \
+        f"DescribeTopicPartitions: {topics} {cursor}"
+    topics, cursor = parse_describe_topic_partitions_response(frames[7])
+    assert [topic[:2] for topic in topics] == [(0, "bar")] and cursor == ("bar", 1), \
+        f"DescribeTopicPartitions with a cursor: {topics} {cursor}"
+    assert_bytes_equal(struct.pack(">IIh", 6, corr_id, 35), frames[8], "Unknown API key")
+    print("✅ Response size prefix test passed")
+
+def test_stalled_reader_is_disconnected():
+    print("🧪 Testing a client that stops reading is disconnected...")
+    
+    client = EventorTestClient()
+    stalled = socket.socket(socket.AF_INET, socket.SOCK_STREAM)
+    stalled.setsockopt(socket.SOL_SOCKET, socket.SO_RCVBUF, 4096)
+    stalled.connect((client.host, client.port))
+    request, _ = client.build_api_versions_request()
+    payload = request * 1000
+    disconnected = threading.Event()
+    
+    def flood():
+        # Keep pipelining requests without ever reading the responses
+        try:
+            while True:
+                stalled.sendall(payload)
+        except OSError:
+            disconnected.set()
+    
+    flooder = threading.Thread(target=flood, daemon=True)
+    flooder.start()
+    
+    try:
+        time.sleep(0.5)
+        # Other connections keep being served while the stalled one is blocked
+        request, expected_correlation_id = client.build_api_versions_request()
+        client.send_request_and_get_response(request, expected_correlation_id)
+        
+        assert disconnected.wait(30), "Stalled connection was never closed by the broker"
+        print("✅ Stalled reader test passed")
+    finally:
+        stalled.close()
+
+def test_mid_request_stall_is_reaped():
+    print("🧪 Testing a request body that never arrives is timed out...")
+    
+    client = EventorTestClient()
+    request, _ = client.build_api_versions_request()
+    stalled = client.connect()
+    try:
+        # Size prefix plus a few body bytes, then nothing more
+        stalled.sendall(request[:8])
+        started = time.monotonic()
+        
+        # A slow but complete request on another connection is still served
+        request, expected_correlation_id = client.build_api_versions_request()
+        connection = EventorConnection(client)
+        try:
+            connection.sock.sendall(request[:6])
+            time.sleep(0.3)
+            connection.send(request[6:], expected_correlation_id)
+            connection.read_response()
+        finally:
+            connection.close()
+        
+        stalled.settimeout(10)
+        try:
+            leftover = stalled.recv(1)
+        except ConnectionResetError:
+            leftover = b""
+        elapsed = time.monotonic() - started
+        assert leftover == b"", "Broker answered a request it never fully received"
+        # request.read.timeout.ms is 1000 in fixtures/server.properties
+        assert 0.8 <= elapsed <= 5, f"Stalled request was reaped after {elapsed:.2f}s"
+        print("✅ Mid-request stall test passed")
+    finally:
+        stalled.close()
+
+def test_session_record_and_replay():
+    print("🧪 Testing recorded sessions replay identically...")
+    
+    client = EventorTestClient()
+    connection = EventorConnection(client)
+    try:
+        port = connection.sock.getsockname()[1]
+        for request, corr_id in (client.build_api_versions_request(api_version=3),
+                                 client.build_api_versions_request(api_version=7),
+                                 client.build_describe_topic_partitions_request("unknown-topic-abc")):
+            connection.send(request, corr_id)
+            connection.read_response()
+    finally:
+        connection.close()
+    
+    sessions = glob.glob(f"{SESSION_DIR}/*-127.0.0.1_{port}.session")
+    assert len(sessions) == 1, f"Expected one session file for port {port}, found {sessions}"
+    
+    result = subprocess.run([EVENTOR_BINARY, "replay", sessions[0]], capture_output=True, text=True)
+    assert result.returncode == 0, f"Replay reported differences:\n{result.stdout}{result.stderr}"
+    assert "All 3 exchanges match" in result.stdout, result.stdout
+    
+    # A tampered response must be reported rather than silently accepted
+    with open(sessions[0], "rb") as f:
+        tampered = bytearray(f.read())
+    tampered[-1] ^= 0xff
+    tampered_path = sessions[0] + ".tampered"
+    with open(tampered_path, "wb") as f:
+        f.write(tampered)
+    result = subprocess.run([EVENTOR_BINARY, "replay", tampered_path], capture_output=True, text=True)
+    assert result.returncode != 0, "Replay accepted a session whose response was modified"
+    assert "Exchange 3 (api key 75): responses differ" in result.stdout, result.stdout
+    
+    # Topic and member ids are generated afresh in the replay, and swapped for
+    # the recorded ones wherever later frames name them
+    topic = f"replayed-{random.getrandbits(32):08x}"
+    group = f"replayed-group-{random.getrandbits(32):08x}"
+    connection = EventorConnection(client)
+    try:
+        port = connection.sock.getsockname()[1]
+        
+        def exchange(built):
+            connection.send(*built)
+            return connection.read_response()
+        
+        created = parse_create_topics_response(exchange(client.build_create_topics_request([(topic, 1, 1)])), 7)
+        assert created[topic][0] == 0, f"CreateTopics failed: {created}"
+        exchange(client.build_metadata_request(topics=[topic]))
+        exchange(client.build_describe_topic_partitions_request(topic))
+        required = parse_join_group_response(exchange(client.build_join_group_request(group)), 9)
+        assert required["error_code"] == 79, f"Expected MEMBER_ID_REQUIRED, got {required}"
+        joined = parse_join_group_response(
+            exchange(client.build_join_group_request(group, member_id=required["member_id"])), 9)
+        assert joined["error_code"] == 0, f"Join failed: {joined}"
+        exchange(client.build_sync_group_request(group, joined["generation_id"], joined["member_id"],
+                                                 [(joined["member_id"], b"assigned")]))
+        exchange(client.build_heartbeat_request(group, joined["generation_id"], joined["member_id"]))
+    finally:
+        connection.close()
+    sessions = glob.glob(f"{SESSION_DIR}/*-127.0.0.1_{port}.session")
+    assert len(sessions) == 1, f"Expected one session file for port {port}, found {sessions}"
+    result = subprocess.run([EVENTOR_BINARY, "replay", sessions[0], "fixtures/server.properties"],
+                            capture_output=True, text=True)
+    assert result.returncode == 0, f"Replay with generated ids reported differences:\n{result.stdout}{result.stderr}"
+    assert "All 7 exchanges match" in result.stdout, result.stdout
+    request, corr_id = client.build_delete_topics_request([topic])
+    client.send_request_and_get_response(request, corr_id)
+    print("✅ Session replay test passed")
+
+def parse_produce_response(response, api_version, messages=None, entries=None):
+    """Decodes a ProduceResponse into {(topic, partition): (error_code, base_offset, log_start_offset)}.
+    Given a dict, messages collects each partition's error_message (v8+). Given a list,
+    entries collects every partition's (topic, partition, error_code) in response order."""
+    flexible = api_version >= 9
+    offset = 9 if flexible else 8  # size, correlation id and the v1 header tag buffer
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read_len():
+        nonlocal offset
+        if flexible:
+            return read_uvarint() - 1
+        value = struct.unpack(">i", response[offset:offset + 4])[0]
+        offset += 4
+        return value
+    
+    def read_string():
+        nonlocal offset
+        if flexible:
+            length = read_uvarint() - 1
+        else:
+            length = struct.unpack(">h", response[offset:offset + 2])[0]
+            offset += 2
+        if length < 0:
+            return None
+        value = response[offset:offset + length].decode('utf-8')
+        offset += length
+        return value
+    
+    results = {}
+    for _ in range(read_len()):
+        name = read_string()
+        for _ in range(read_len()):
+            index, error_code, base_offset, log_append_time = struct.unpack(
+                ">ihqq", response[offset:offset + 22])
+            offset += 22
+            assert log_append_time == -1, f"Expected log_append_time -1, got {log_append_time}"
+            log_start_offset = None
+            if api_version >= 5:
+                log_start_offset = struct.unpack(">q", response[offset:offset + 8])[0]
+                offset += 8
+            if api_version >= 8:
+                assert read_len() == 0, "Expected no record_errors"
+                error_message = read_string()
+                if messages is not None:
+                    messages[(name, index)] = error_message
+            if flexible:
+                assert response[offset] == 0, "Expected an empty partition tag buffer"
+                offset += 1
+            results[(name, index)] = (error_code, base_offset, log_start_offset)
+            if entries is not None:
+                entries.append((name, index, error_code))
+        if flexible:
+            assert response[offset] == 0, "Expected an empty topic tag buffer"
+            offset += 1
+    throttle_time_ms = struct.unpack(">i", response[offset:offset + 4])[0]
+    offset += 4
+    assert throttle_time_ms == 0, f"Expected throttle_time_ms 0, got {throttle_time_ms}"
+    if flexible:
+        assert response[offset] == 0, "Expected an empty response tag buffer"
+        offset += 1
+    assert offset == len(response), f"Produce response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_produce_assigns_offsets():
+    print("🧪 Testing Produce appends batches and assigns offsets...")
+    client = EventorTestClient()
+    # foo-0 is only written by this test, so offsets start at 0: each base offset
+    # is the previous one plus the previous batch's record count
+    expected_base_offset = 0
+    for api_version, record_count in ((3, 2), (7, 3), (9, 1), (11, 4)):
+        batch = build_record_batch([f"v{api_version}-{i}".encode() for i in range(record_count)])
+        request, corr_id = client.build_produce_request([("foo", [(0, batch)])], api_version=api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        results = parse_produce_response(response, api_version)
+        error_code, base_offset, log_start_offset = results[("foo", 0)]
+        assert error_code == 0, f"v{api_version}: expected error 0, got {error_code}"
+        assert base_offset == expected_base_offset, \
+            f"v{api_version}: expected base_offset {expected_base_offset}, got {base_offset}"
+        assert log_start_offset in (None, 0), f"v{api_version}: log_start_offset {log_start_offset}"
+        expected_base_offset += record_count
+    print("✅ Produce offsets test passed")
+
+def test_produce_unknown_and_invalid_partitions():
+    print("🧪 Testing Produce per-partition errors...")
+    client = EventorTestClient()
+    batch = build_record_batch([b"hello"])
+    for api_version in (3, 9):
+        request, corr_id = client.build_produce_request([
+            ("bar", [(1, batch), (7, batch)]),
+            ("no-such-topic", [(0, batch)]),
+            ("bar", [(0, b"\x00" * 20)]),
+        ], api_version=api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        results = parse_produce_response(response, api_version)
+        assert results[("bar", 1)][0] == 0, f"v{api_version}: bar-1 should accept the batch"
+        assert results[("bar", 7)][:2] == (3, -1), f"v{api_version}: unknown partition {results[('bar', 7)]}"
+        assert results[("no-such-topic", 0)][:2] == (3, -1), \
+            f"v{api_version}: unknown topic {results[('no-such-topic', 0)]}"
+        assert results[("bar", 0)][:2] == (2, -1), f"v{api_version}: corrupt batch {results[('bar', 0)]}"
+    print("✅ Produce error test passed")
+
+def test_produce_acks_zero_gets_no_response():
+    print("🧪 Testing Produce with acks=0 is not answered...")
+    client = EventorTestClient()
+    connection = EventorConnection(client)
+    try:
+        request, _ = client.build_produce_request([("bar", [(0, build_record_batch([b"fire-and-forget"]))])],
+                                                  acks=0)
+        connection.sock.sendall(request)  # Not outstanding: no response may come back for it
+        request, corr_id = client.build_api_versions_request(api_version=3)
+        connection.send(request, corr_id)
+        connection.read_response()
+    finally:
+        connection.close()
+    print("✅ Produce acks=0 test passed")
+
+def test_produce_retry_after_unclean_disconnect():
+    print("🧪 Testing an idempotent resend after a dropped connection is not appended twice...")
+    client = EventorTestClient()
+    
+    def produce(batch):
+        request, corr_id = client.build_produce_request([("baz", [(0, batch)])])
+        response = client.send_request_and_get_response(request, corr_id)
+        error_code, base_offset, _ = parse_produce_response(response, 9)[("baz", 0)]
+        assert error_code == 0, f"Expected error 0, got {error_code}"
+        return base_offset
+    
+    # A plain batch marks where the log ends before the idempotent one goes in
+    start = produce(build_record_batch([b"before"])) + 1
+    batch = build_record_batch([b"retried-0", b"retried-1", b"retried-2"],
+                               producer_id=4242, producer_epoch=0, base_sequence=0)
+    
+    # Send, then drop the connection without reading the response
+    request, _ = client.build_produce_request([("baz", [(0, batch)])])
+    dropped = client.connect()
+    dropped.sendall(request)
+    dropped.close()
+    
+    # The producer retries the identical batch on a new connection
+    assert produce(batch) == start, "Resend was not answered with the original base offset"
+    assert produce(batch) == start, "Second resend was not answered with the original base offset"
+    # Exactly one copy: the next append lands right after the three retried records
+    after = produce(build_record_batch([b"after"]))
+    assert after == start + 3, f"Expected the next batch at offset {start + 3}, got {after}"
+    
+    # The same sequence under a new epoch is a new batch, not a duplicate
+    bumped = build_record_batch([b"new-epoch"], producer_id=4242, producer_epoch=1, base_sequence=0)
+    assert produce(bumped) == after + 1, "Batch from a bumped epoch was treated as a duplicate"
+    print("✅ Produce retry deduplication test passed")
+
+def parse_init_producer_id_response(response, api_version):
+    """Decodes an InitProducerIdResponse into (error_code, producer_id, producer_epoch)."""
+    flexible = api_version >= 2
+    offset = 9 if flexible else 8
+    throttle_time_ms, error_code, producer_id, producer_epoch = struct.unpack_from(">ihqh", response, offset)
+    assert throttle_time_ms == 0, f"Expected throttle_time_ms 0, got {throttle_time_ms}"
+    offset += 16
+    if flexible:
+        assert response[offset] == 0, "Expected an empty tag buffer"
+        offset += 1
+    assert offset == len(response), f"InitProducerId response has {len(response) - offset} trailing bytes"
+    return error_code, producer_id, producer_epoch
+
+def test_init_producer_id():
+    print("🧪 Testing InitProducerId hands idempotent producers their ids...")
+    client = EventorTestClient()
+    
+    def init(api_version=5, **kwargs):
+        request, corr_id = client.build_init_producer_id_request(api_version=api_version, **kwargs)
+        return parse_init_producer_id_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    request, corr_id = client.build_api_versions_request()
+    api_keys = parse_api_versions_response(client.send_request_and_get_response(request, corr_id))[1]
+    assert api_keys.get(22) == (0, 5), f"InitProducerId advertised as {api_keys.get(22)}"
+    
+    # Every version gets a fresh id at epoch 0, increasing from one request to the next,
+    # including a producer that already has one and asks again to bump its epoch
+    ids = []
+    for api_version in range(0, 6):
+        error_code, producer_id, producer_epoch = init(api_version)
+        assert (error_code, producer_epoch) == (0, 0) and producer_id >= 0, \
+            f"v{api_version}: {(error_code, producer_id, producer_epoch)}"
+        ids.append(producer_id)
+    error_code, producer_id, producer_epoch = init(producer_id=ids[-1], producer_epoch=0)
+    assert (error_code, producer_epoch) == (0, 0), f"Re-init: {(error_code, producer_id, producer_epoch)}"
+    ids.append(producer_id)
+    assert ids == sorted(set(ids)), f"Producer ids not increasing: {ids}"
+    
+    # Transactions aren't supported
+    for api_version in (0, 2, 5):
+        assert init(api_version, transactional_id="txn-1") == (53, -1, -1), f"v{api_version} transactional"
+    
+    # Handed out concurrently, no two producers share an id
+    allocated = []
+    
+    def allocate():
+        own = EventorTestClient()
+        for _ in range(20):
+            request, corr_id = own.build_init_producer_id_request()
+            allocated.append(parse_init_producer_id_response(own.send_request_and_get_response(request, corr_id), 5)[1])
+    
+    threads = [threading.Thread(target=allocate) for _ in range(5)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert len(set(allocated)) == 100 and min(allocated) > max(ids), "Concurrent allocations collided"
+    
+    # End to end, as a default-configured idempotent client: its retried batch is deduplicated
+    _, producer_id, producer_epoch = init()
+    batch = build_record_batch([b"idempotent"], producer_id=producer_id, producer_epoch=producer_epoch,
+                               base_sequence=0)
+    request, corr_id = client.build_produce_request([("baz", [(0, batch)])])
+    first = parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[("baz", 0)]
+    request, corr_id = client.build_produce_request([("baz", [(0, batch)])])
+    retried = parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[("baz", 0)]
+    assert first[0] == 0 and retried[:2] == first[:2], f"Retry answered {retried}, first {first}"
+    assert produce_one(client, "baz", 0, [b"next"]) == first[1] + 1, "Retried batch appended twice"
+    print("✅ InitProducerId test passed")
+
+def parse_fetch_response(response, api_version, hints=None, entries=None, log_starts=None):
+    """Decodes a FetchResponse into {(topic, partition): (error_code, high_watermark, records)};
+    topics are ids from v13. Given a dict, hints collects the raw tagged fields: each
+    partition's under (topic, partition) and the response's under None. Given a list,
+    entries collects every partition's (topic, partition, error_code, records) in response order.
+    Given a dict, log_starts collects each partition's log_start_offset (v5+); otherwise
+    nothing may have been deleted."""
+    flexible = api_version >= 12
+    offset = 9 if flexible else 8
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        return read_uvarint() - 1 if flexible else read(">i")
+    
+    def read_tags():
+        if flexible:
+            assert read_uvarint() == 0, "Expected an empty tag buffer"
+    
+    def read_tagged_fields():
+        nonlocal offset
+        fields = {}
+        for _ in range(read_uvarint()):
+            tag, size = read_uvarint(), read_uvarint()
+            fields[tag] = response[offset:offset + size]
+            offset += size
+        return fields
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    if api_version >= 7:
+        error_code, session_id = read(">hi")
+        assert (error_code, session_id) == (0, 0), f"Top-level error {error_code}, session {session_id}"
+    results = {}
+    for _ in range(read_len()):
+        if api_version >= 13:
+            name = read("16s")
+        else:
+            name_len = read_len() if flexible else read(">h")
+            name = response[offset:offset + name_len].decode("utf-8")
+            offset += name_len
+        for _ in range(read_len()):
+            partition, error_code, high_watermark, last_stable_offset = read(">ihqq")
+            assert last_stable_offset == high_watermark, "last_stable_offset differs from the high watermark"
+            if api_version >= 5:
+                log_start_offset = read(">q")
+                if log_starts is None:
+                    assert log_start_offset in (0, -1), "Unexpected log_start_offset"
+                else:
+                    log_starts[(name, partition)] = log_start_offset
+            assert read_len() <= 0, "Expected no aborted transactions"
+            if api_version >= 11:
+                assert read(">i") == -1, "Expected no preferred read replica"
+            records_len = read_len()
+            records = response[offset:offset + records_len]
+            offset += records_len
+            if hints is None:
+                read_tags()
+            elif flexible:
+                hints[(name, partition)] = read_tagged_fields()
+            results[(name, partition)] = (error_code, high_watermark, records)
+            if entries is not None:
+                entries.append((name, partition, error_code, records))
+        read_tags()
+    if hints is None:
+        read_tags()
+    elif flexible:
+        hints[None] = read_tagged_fields()
+    assert offset == len(response), f"Fetch response has {len(response) - offset} trailing bytes"
+    return results
+
+def decode_record_batches(records):
+    """(base_offset, [values]) for each batch in a RECORDS blob."""
+    batches = []
+    offset = 0
+    
+    def read_varint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = records[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return (value >> 1) ^ -(value & 1)
+            shift += 7
+    
+    while offset < len(records):
+        base_offset, batch_length = struct.unpack_from(">qi", records, offset)
+        end = offset + 12 + batch_length
+        crc = struct.unpack_from(">I", records, offset + 17)[0]
+        assert crc == crc32c(records[offset + 21:end]), "Fetched batch CRC does not match"
+        count = struct.unpack_from(">i", records, offset + 57)[0]
+        offset += 61
+        values = []
+        for _ in range(count):
+            read_varint()            # length
+            offset += 1              # attributes
+            read_varint()            # timestamp_delta
+            read_varint()            # offset_delta
+            key_len = read_varint()
+            offset += max(key_len, 0)
+            value_len = read_varint()
+            values.append(records[offset:offset + value_len])
+            offset += value_len
+            for _ in range(read_varint()):
+                offset += max(read_varint(), 0)
+                offset += max(read_varint(), 0)
+        assert offset == end, "Record batch length does not match its records"
+        batches.append((base_offset, values))
+    return batches
+
+def produce_one(client, topic, partition, values):
+    request, corr_id = client.build_produce_request([(topic, [(partition, build_record_batch(values))])])
+    response = client.send_request_and_get_response(request, corr_id)
+    error_code, base_offset, _ = parse_produce_response(response, 9)[(topic, partition)]
+    assert error_code == 0, f"Produce to {topic}-{partition} failed with {error_code}"
+    return base_offset
+
+def test_fetch_returns_produced_records():
+    print("🧪 Testing Fetch reads back produced batches...")
+    client = EventorTestClient()
+    first = produce_one(client, "bar", 1, [b"fetch-a", b"fetch-b"])
+    second = produce_one(client, "bar", 1, [b"fetch-c"])
+    assert second == first + 2
+    log_end = second + 1
+    
+    for api_version in (4, 11, 12):
+        request, corr_id = client.build_fetch_request([
+            ("bar", [(1, first, 1 << 20), (0, 10 ** 9, 1 << 20)]),
+            ("no-such-topic", [(0, 0, 1 << 20)]),
+        ], api_version=api_version)
+        results = parse_fetch_response(client.send_request_and_get_response(request, corr_id), api_version)
+        
+        error_code, high_watermark, records = results[("bar", 1)]
+        assert (error_code, high_watermark) == (0, log_end), f"v{api_version}: bar-1 {error_code} hw={high_watermark}"
+        batches = decode_record_batches(records)
+        assert batches[:2] == [(first, [b"fetch-a", b"fetch-b"]), (second, [b"fetch-c"])], \
+            f"v{api_version}: fetched {batches}"
+        assert results[("bar", 0)][0] == 1, f"v{api_version}: expected OFFSET_OUT_OF_RANGE past the log end"
+        assert results[("no-such-topic", 0)][0] == 3, f"v{api_version}: expected UNKNOWN_TOPIC_OR_PARTITION"
+        
+        # At the log end: no records, but the high watermark tells the consumer where it is
+        request, corr_id = client.build_fetch_request([("bar", [(1, log_end, 1 << 20)])], api_version=api_version)
+        results = parse_fetch_response(client.send_request_and_get_response(request, corr_id), api_version)
+        assert results[("bar", 1)] == (0, log_end, b""), \
+            f"v{api_version}: fetch at log end returned {results[('bar', 1)]}"
+    
+    # A fetch from the middle of the log starts at the batch holding that offset
+    request, corr_id = client.build_fetch_request([("bar", [(1, second, 1 << 20)])])
+    results = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 12)
+    assert decode_record_batches(results[("bar", 1)][2])[0] == (second, [b"fetch-c"])
+    print("✅ Fetch test passed")
+
+def test_fetch_waits_for_new_data():
+    print("🧪 Testing Fetch waits up to max_wait_ms for min_bytes...")
+    client = EventorTestClient()
+    log_end = produce_one(client, "bar", 1, [b"wait-start"]) + 1
+    
+    # Nothing arrives: the fetch is answered once max_wait_ms has passed
+    request, corr_id = client.build_fetch_request([("bar", [(1, log_end, 1 << 20)])], max_wait_ms=300)
+    started = time.monotonic()
+    results = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 12)
+    elapsed = time.monotonic() - started
+    assert results[("bar", 1)] == (0, log_end, b""), f"Empty fetch returned {results[('bar', 1)]}"
+    assert 0.25 <= elapsed < 2, f"Empty fetch returned after {elapsed:.2f}s, expected about 0.3s"
+    
+    # A produce during the wait answers the fetch early
+    request, corr_id = client.build_fetch_request([("bar", [(1, log_end, 1 << 20)])], max_wait_ms=5000)
+    connection = EventorConnection(client)
+    try:
+        connection.send(request, corr_id)
+        time.sleep(0.2)
+        started = time.monotonic()
+        produced_at = produce_one(EventorTestClient(), "bar", 1, [b"wake-up"])
+        response = connection.read_response()
+        elapsed = time.monotonic() - started
+    finally:
+        connection.close()
+    assert elapsed < 2, f"Waiting fetch took {elapsed:.2f}s to see the new batch"
+    batches = decode_record_batches(parse_fetch_response(response, 12)[("bar", 1)][2])
+    assert (produced_at, [b"wake-up"]) in batches, f"Waiting fetch returned {batches}"
+    print("✅ Fetch wait test passed")
+
+def test_fetch_leader_hints():
+    print("🧪 Testing Fetch by topic id and current-leader hints...")
+    client = EventorTestClient()
+    
+    def fetch(topics, api_version, hints=None):
+        request, corr_id = client.build_fetch_request(topics, api_version=api_version)
+        return parse_fetch_response(client.send_request_and_get_response(request, corr_id), api_version, hints)
+    
+    request, corr_id = client.build_metadata_request(["bar"], api_version=12)
+    _, _, _, metadata = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)
+    bar_id = metadata[0][2]
+    log_end = produce_one(client, "bar", 0, [b"hinted"]) + 1
+    
+    # From v13 topics go by id; an id nobody knows is UNKNOWN_TOPIC_ID
+    for api_version in (13, 15, 16):
+        results = fetch([(bar_id, [(0, 0, 1 << 20)]), (bytes(range(16)), [(0, 0, 1024)])], api_version)
+        error_code, high_watermark, records = results[(bar_id, 0)]
+        assert (error_code, high_watermark) == (0, log_end) and records, f"v{api_version}: {results}"
+        assert results[(bytes(range(16)), 0)][0] == 100, f"v{api_version}: {results}"
+    
+    # The only leader epoch is 0: an older one is fenced, a newer one unknown
+    for api_version in (9, 11):
+        results = fetch([("bar", [(0, 0, 1024, -1), (1, 0, 1024, 0)]), ("baz", [(0, 0, 1024, 3)])], api_version)
+        assert [results[key][0] for key in (("bar", 0), ("bar", 1), ("baz", 0))] == [0, 0, 75], \
+            f"v{api_version} epochs: {results}"
+    
+    # v12+ fenced partitions carry the current leader (tag 1): leader_id 1, leader_epoch 0
+    current_leader = struct.pack(">ii", 1, 0) + b"\x00"
+    for api_version in (12, 13, 16):
+        topic = "bar" if api_version < 13 else bar_id
+        hints = {}
+        results = fetch([(topic, [(0, 0, 1024, -5), (1, 0, 1024, 0)])], api_version, hints)
+        assert results[(topic, 0)][0] == 74, f"v{api_version} fenced: {results}"
+        assert hints[(topic, 0)] == {1: current_leader}, f"v{api_version} current_leader: {hints}"
+        assert hints[(topic, 1)] == {}, f"v{api_version}: unfenced partition hinted {hints}"
+        # v16 adds the leader's endpoint (tag 0): node 1 at localhost:9092, no rack
+        expected = {0: b"\x02" + struct.pack(">i", 1) + b"\x0alocalhost" + struct.pack(">i", 9092) + b"\x00\x00"} \
+            if api_version >= 16 else {}
+        assert hints[None] == expected, f"v{api_version} node_endpoints: {hints[None]}"
+    
+    hints = {}
+    fetch([(bar_id, [(0, 0, 1024)])], 16, hints)
+    assert hints[None] == {} and hints[(bar_id, 0)] == {}, f"Hints without a fenced partition: {hints}"
+    print("✅ Fetch leader hints test passed")
+
+def test_throttle_time_layouts():
+    print("🧪 Testing throttle_time_ms placement in ApiVersions and Fetch responses...")
+    client = EventorTestClient()
+    
+    # The advertised ranges, in response order, to lay each version's body out from
+    request, corr_id = client.build_api_versions_request(api_version=4)
+    _, api_keys = parse_api_versions_response(client.send_request_and_get_response(request, corr_id))
+    for api_version in (1, 2, 3):
+        request, corr_id = client.build_api_versions_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        flexible = api_version >= 3
+        body = bytearray(struct.pack(">Ih", corr_id, 0))  # Response header v0, error_code
+        body.extend(encode_uvarint(len(api_keys) + 1) if flexible else struct.pack(">i", len(api_keys)))
+        for api_key, (min_version, max_version) in api_keys.items():
+            body.extend(struct.pack(">hhh", api_key, min_version, max_version))
+            if flexible:
+                body.extend(b"\x00")              # api_keys entry tag buffer
+        body.extend(struct.pack(">i", 0))         # throttle_time_ms: last, nothing held back
+        if flexible:
+            body.extend(b"\x00")                  # Response tag buffer
+        expected = struct.pack(">I", len(body)) + bytes(body)
+        assert_bytes_equal(expected, response, f"ApiVersions v{api_version} response")
+    
+    log_end = produce_one(client, "foo", 0, [b"throttled"]) + 1
+    request, corr_id = client.build_metadata_request(["foo"], api_version=12)
+    _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)
+    (_, _, foo_id, _), = topics
+    
+    # At the log end, so the partition reads no records
+    request, corr_id = client.build_fetch_request([("foo", [(0, log_end, 1 << 20)])], api_version=11)
+    response = client.send_request_and_get_response(request, corr_id)
+    body = bytearray(struct.pack(">I", corr_id))  # Response header v0
+    body.extend(struct.pack(">i", 0))             # throttle_time_ms: first, nothing held back
+    body.extend(struct.pack(">hi", 0, 0))         # error_code, session_id
+    body.extend(struct.pack(">i", 1))             # responses
+    body.extend(struct.pack(">h", 3) + b"foo")
+    body.extend(struct.pack(">i", 1))             # partitions
+    body.extend(struct.pack(">ihqqq", 0, 0, log_end, log_end, 0))  # index, error_code, offsets
+    body.extend(struct.pack(">i", 0))             # aborted_transactions
+    body.extend(struct.pack(">i", -1))            # preferred_read_replica
+    body.extend(struct.pack(">i", 0))             # records
+    expected = struct.pack(">I", len(body)) + bytes(body)
+    assert_bytes_equal(expected, response, "Fetch v11 response")
+    
+    request, corr_id = client.build_fetch_request([(foo_id, [(0, log_end, 1 << 20)])], api_version=16)
+    response = client.send_request_and_get_response(request, corr_id)
+    body = bytearray(struct.pack(">I", corr_id))
+    body.extend(b"\x00")                          # Response header v1 tag buffer
+    body.extend(struct.pack(">i", 0))             # throttle_time_ms: still first, after the tag buffer
+    body.extend(struct.pack(">hi", 0, 0))         # error_code, session_id
+    body.extend(b"\x02" + foo_id)                 # responses: 1 entry (compact), topic_id
+    body.extend(b"\x02")                          # partitions: 1 entry (compact)
+    body.extend(struct.pack(">ihqqq", 0, 0, log_end, log_end, 0))  # index, error_code, offsets
+    body.extend(b"\x01")                          # aborted_transactions: empty (compact)
+    body.extend(struct.pack(">i", -1))            # preferred_read_replica
+    body.extend(b"\x01")                          # records: empty (compact)
+    body.extend(b"\x00")                          # Partition tag buffer: no current_leader
+    body.extend(b"\x00")                          # Topic tag buffer
+    body.extend(b"\x00")                          # Response tag buffer: no node_endpoints
+    expected = struct.pack(">I", len(body)) + bytes(body)
+    assert_bytes_equal(expected, response, "Fetch v16 response")
+    print("✅ throttle_time_ms layout test passed")
+
+def test_error_echo_limits_fuzz():
+    print("🧪 Fuzzing how much client input error responses echo back...")
+    rng = random.Random(253)
+    alphabet = "abcXYZ019-_.\x00\n\r\x1b\u00e9\u65e5\U0001f600"
+    client = EventorTestClient()
+    batch = build_record_batch([b"x"])
+    for iteration in range(150):
+        api_version = rng.choice((3, 8, 9, 11))
+        length = rng.choice((0, 1, 63, 64, 65, rng.randint(0, 400), 5000, 8000))
+        name = "".join(rng.choice(alphabet) for _ in range(length))
+        encoded = name.encode("utf-8")
+        partition_count = rng.randint(1, 40)
+        request, corr_id = client.build_produce_request(
+            [(name, [(index, batch) for index in range(partition_count)])], api_version=api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        
+        context = f"iteration {iteration} (v{api_version}, {len(encoded)}-byte name, {partition_count} partitions)"
+        results = parse_produce_response(response, api_version)
+        assert len(results) <= ECHO_MAX_PARTITIONS, f"{context}: {len(results)} error partitions echoed"
+        for (echoed, _), (error_code, _, _) in results.items():
+            echoed = echoed.encode("utf-8")
+            assert len(echoed) <= ECHO_MAX_NAME_BYTES, f"{context}: {len(echoed)}-byte name echoed"
+            assert encoded.startswith(echoed), f"{context}: echoed name is not a prefix"
+            assert error_code == 3, f"{context}: expected UNKNOWN_TOPIC_OR_PARTITION, got {error_code}"
+        # Fixed overhead plus the capped name and partitions, whatever the request size
+        assert len(response) < 2048, f"{context}: {len(response)}-byte response"
+        
+        fetch_version = rng.choice((4, 11, 12))
+        request, corr_id = client.build_fetch_request(
+            [(name, [(index, 0, 1024) for index in range(partition_count)])], api_version=fetch_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        results = parse_fetch_response(response, fetch_version)
+        assert len(results) <= ECHO_MAX_PARTITIONS, f"{context}: {len(results)} fetch error partitions echoed"
+        for (echoed, _), (error_code, _, _) in results.items():
+            echoed = echoed.encode("utf-8")
+            assert len(echoed) <= ECHO_MAX_NAME_BYTES, f"{context}: {len(echoed)}-byte name echoed by Fetch"
+            assert error_code == 3, f"{context}: expected UNKNOWN_TOPIC_OR_PARTITION from Fetch, got {error_code}"
+        assert len(response) < 2048, f"{context}: {len(response)}-byte fetch response"
+    
+    topic_name = "unknown-topic-" + "q" * 100
+    request, corr_id = client.build_describe_topic_partitions_request(topic_name)
+    response = client.send_request_and_get_response(request, corr_id)
+    echoed = topic_name[:ECHO_MAX_NAME_BYTES].encode("utf-8")
+    # Size, correlation id, header tags, throttle, topic count and error code precede the name
+    assert response[16:17 + len(echoed)] == bytes([len(echoed) + 1]) + echoed, \
+        "DescribeTopicPartitions echoed the topic name past the limit"
+    print("✅ Error echo limits fuzz test passed")
+
+def parse_list_offsets_response(response, api_version, entries=None):
+    """Decodes a ListOffsetsResponse into {(topic, partition): (error_code, timestamp, offset, leader_epoch)};
+    leader_epoch is None before v4. Given a list, entries collects every partition's
+    (topic, partition, error_code) in response order."""
+    flexible = api_version >= 6
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        # Every count in these responses fits in one varint byte
+        return read(">B") - 1 if flexible else read(">i")
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    if api_version >= 2:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = {}
+    for _ in range(read_len()):
+        name_len = read_len() if flexible else read(">h")
+        name = response[offset:offset + name_len].decode("utf-8")
+        offset += name_len
+        for _ in range(read_len()):
+            partition, error_code, timestamp, found_offset = read(">ihqq")
+            leader_epoch = read(">i") if api_version >= 4 else None
+            read_tags()
+            results[(name, partition)] = (error_code, timestamp, found_offset, leader_epoch)
+            if entries is not None:
+                entries.append((name, partition, error_code))
+        read_tags()
+    read_tags()
+    assert offset == len(response), f"ListOffsets response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_list_offsets():
+    print("🧪 Testing ListOffsets latest, earliest and timestamp lookups...")
+    client = EventorTestClient()
+    base = 1_700_000_000_000  # Later than any timestamp other tests produce
+    # Timestamps needn't grow with offsets: the second batch starts before the first one ends
+    request, corr_id = client.build_produce_request([("bar", [(0, build_record_batch(
+        [b"t100", b"t300"], timestamps=[base + 100, base + 300]))])])
+    first = parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[("bar", 0)][1]
+    request, corr_id = client.build_produce_request([("bar", [(0, build_record_batch(
+        [b"t200", b"t500"], timestamps=[base + 200, base + 500]))])])
+    second = parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[("bar", 0)][1]
+    assert second == first + 2
+    log_end = second + 2
+    
+    lookups = [
+        (-1, (-1, log_end)),                  # latest
+        (-2, (-1, 0)),                        # earliest
+        (base + 50, (base + 100, first)),
+        (base + 150, (base + 300, first + 1)),  # first in offset order, not the closest timestamp
+        (base + 400, (base + 500, second + 1)),
+        (base + 600, (-1, -1)),               # nothing that late
+    ]
+    for api_version in (1, 4, 6, 7):
+        context = f"ListOffsets v{api_version}"
+        version_lookups = lookups + ([(-3, (base + 500, second + 1))] if api_version >= 7 else [])
+        request, corr_id = client.build_list_offsets_request([
+            ("bar", [(0, -1)]),
+            ("no-such-topic", [(0, -1)]),
+        ], api_version=api_version)
+        results = parse_list_offsets_response(client.send_request_and_get_response(request, corr_id), api_version)
+        expected_epoch = (0, -1) if api_version >= 4 else (None, None)
+        assert results[("bar", 0)] == (0, -1, log_end, expected_epoch[0]), f"{context}: latest {results[('bar', 0)]}"
+        assert results[("no-such-topic", 0)] == (3, -1, -1, expected_epoch[1]), \
+            f"{context}: unknown topic {results[('no-such-topic', 0)]}"
+        
+        for timestamp, expected in version_lookups:
+            request, corr_id = client.build_list_offsets_request([("bar", [(0, timestamp)])], api_version=api_version)
+            results = parse_list_offsets_response(client.send_request_and_get_response(request, corr_id), api_version)
+            assert results[("bar", 0)][:3] == (0,) + expected, \
+                f"{context}: timestamp {timestamp} resolved to {results[('bar', 0)]}, expected {expected}"
+    
+    # Fetch agrees: its high watermark is the latest offset, and fetching at a looked-up offset works
+    request, corr_id = client.build_fetch_request([("bar", [(0, first + 1, 1 << 20)])])
+    error_code, high_watermark, records = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 12)[("bar", 0)]
+    assert (error_code, high_watermark) == (0, log_end), f"Fetch saw {error_code} hw={high_watermark}"
+    assert decode_record_batches(records)[0] == (first, [b"t100", b"t300"])
+    
+    # A record whose timestamp delta overflows its batch's base timestamp can't
+    # be decoded, so the batch stands in for its records with its max_timestamp
+    request, corr_id = client.build_create_topics_request([("overflowing-timestamps", 1, 1)])
+    client.send_request_and_get_response(request, corr_id)
+    i64_max = (1 << 63) - 1
+    request, corr_id = client.build_produce_request([("overflowing-timestamps", [(0, build_record_batch(
+        [b"late", b"later"], timestamps=[i64_max - 1, i64_max + 4], max_timestamp=i64_max))])])
+    produced = parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)
+    assert produced[("overflowing-timestamps", 0)][0] == 0, f"Produce failed: {produced}"
+    request, corr_id = client.build_list_offsets_request([("overflowing-timestamps", [(0, i64_max)])], api_version=7)
+    results = parse_list_offsets_response(client.send_request_and_get_response(request, corr_id), 7)
+    assert results[("overflowing-timestamps", 0)][:3] == (0, i64_max, 0), \
+        f"Overflowing timestamp resolved to {results[('overflowing-timestamps', 0)]}"
+    print("✅ ListOffsets test passed")
+
+def parse_metadata_response(response, api_version, racks=None):
+    """Decodes a MetadataResponse into (brokers, cluster_id, controller_id, topics), where
+    topics is [(error_code, name, topic_id, {partition: (leader, replicas, isr)})]. Given a
+    dict, racks collects each broker's rack by node id; otherwise there must be none."""
+    flexible = api_version >= 9
+    offset = 9 if flexible else 8
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        return read_uvarint() - 1 if flexible else read(">i")
+    
+    def read_string():
+        length = read_uvarint() - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read_uvarint() == 0, "Expected an empty tag buffer"
+    
+    if api_version >= 3:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    brokers = []
+    for _ in range(read_len()):
+        node_id = read(">i")
+        host = read_string()
+        port = read(">i")
+        rack = read_string()
+        if racks is None:
+            assert rack is None, "Expected no rack"
+        else:
+            racks[node_id] = rack
+        read_tags()
+        brokers.append((node_id, host, port))
+    cluster_id = read_string() if api_version >= 2 else None
+    controller_id = read(">i")
+    topics = []
+    for _ in range(read_len()):
+        error_code = read(">h")
+        name = read_string()
+        topic_id = read("16s") if api_version >= 10 else None
+        assert read(">b") == 0, "Expected is_internal false"
+        partitions = {}
+        for _ in range(read_len()):
+            partition_error, index, leader = read(">hii")
+            assert partition_error == 0, f"Partition {index} has error {partition_error}"
+            if api_version >= 7:
+                read(">i")  # leader_epoch
+            replicas = [read(">i") for _ in range(read_len())]
+            isr = [read(">i") for _ in range(read_len())]
+            if api_version >= 5:
+                assert read_len() == 0, "Expected no offline replicas"
+            read_tags()
+            partitions[index] = (leader, replicas, isr)
+        if api_version >= 8:
+            read(">i")  # topic_authorized_operations
+        read_tags()
+        topics.append((error_code, name, topic_id, partitions))
+    if 8 <= api_version <= 10:
+        read(">i")  # cluster_authorized_operations
+    read_tags()
+    assert offset == len(response), f"Metadata response has {len(response) - offset} trailing bytes"
+    return brokers, cluster_id, controller_id, topics
+
+def test_metadata_api():
+    print("🧪 Testing Metadata requests across versions...")
+    client = EventorTestClient()
+    single_broker = {0: (1, [1], [1])}
+    bar_id = bytes.fromhex("00000000000040008000000000000ba7")  # fixtures/make_metadata_log.py
+    
+    for api_version in (1, 2, 4, 7, 8, 9, 10, 12):
+        context = f"Metadata v{api_version}"
+        request, corr_id = client.build_metadata_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        brokers, cluster_id, controller_id, topics = parse_metadata_response(response, api_version)
+        assert brokers == [(1, "localhost", 9092)], f"{context}: brokers {brokers}"
+        expected_cluster_id = "eventor-fixture-cluster" if api_version >= 2 else None
+        assert cluster_id == expected_cluster_id, f"{context}: cluster_id {cluster_id!r}"
+        assert controller_id == 1, f"{context}: controller_id {controller_id}"
+        by_name = {name: (error_code, partitions) for error_code, name, _, partitions in topics}
+        assert by_name.get("foo") == (0, single_broker), f"{context}: foo {by_name.get('foo')}"
+        assert by_name.get("bar") == (0, {0: (1, [1], [1]), 1: (1, [1], [1])}), f"{context}: bar {by_name.get('bar')}"
+        assert "baz" in by_name, f"{context}: topics {sorted(by_name)}"
+        
+        # Only the requested topics come back, unknown ones with an error
+        request, corr_id = client.build_metadata_request(["bar", "no-such-topic"], api_version=api_version)
+        _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), api_version)
+        assert [(error_code, name) for error_code, name, _, _ in topics] == [(0, "bar"), (3, "no-such-topic")], \
+            f"{context}: requested topics returned {topics}"
+        if api_version >= 10:
+            assert topics[0][2] == bar_id, f"{context}: bar topic id {topics[0][2].hex()}"
+        
+        request, corr_id = client.build_metadata_request([], api_version=api_version)
+        _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), api_version)
+        assert topics == [], f"{context}: an empty topic list returned {topics}"
+    
+    # v10+ can name topics by id; an unknown id has no name to echo
+    unknown_id = bytes(range(1, 17))
+    request, corr_id = client.build_metadata_request([bar_id, unknown_id], api_version=12)
+    _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)
+    assert [(error_code, name, topic_id) for error_code, name, topic_id, _ in topics] == \
+        [(0, "bar", bar_id), (100, None, unknown_id)], f"Lookup by topic id returned {topics}"
+    
+    # Unknown names are client input: capped in length and count
+    names = [f"missing-{index}-" + "m" * 100 for index in range(ECHO_MAX_PARTITIONS + 10)]
+    request, corr_id = client.build_metadata_request(["foo"] + names, api_version=9)
+    _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 9)
+    unknown = [name for error_code, name, _, _ in topics if error_code == 3]
+    assert len(unknown) == ECHO_MAX_PARTITIONS, f"{len(unknown)} unknown topics echoed"
+    assert all(len(name) <= ECHO_MAX_NAME_BYTES for name in unknown), "Unknown topic name echoed in full"
+    assert topics[0][:2] == (0, "foo"), "Known topic dropped along with unknown ones"
+    print("✅ Metadata test passed")
+
+def parse_describe_cluster_response(response, api_version):
+    """Decodes a DescribeClusterResponse into a dict of its fields; brokers is
+    [(broker_id, host, port, rack)]."""
+    offset = 9
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        # Every length in these responses fits in one varint byte
+        nonlocal offset
+        length = read(">B") - 1
+        if length < 0:
+            return None
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    result = {"error_code": read(">h"), "error_message": read_string()}
+    if api_version >= 1:
+        result["endpoint_type"] = read(">b")
+    result["cluster_id"] = read_string()
+    result["controller_id"] = read(">i")
+    brokers = []
+    for _ in range(read(">B") - 1):
+        broker_id = read(">i")
+        host = read_string()
+        port = read(">i")
+        rack = read_string()
+        assert read(">B") == 0, "Expected an empty broker tag buffer"
+        brokers.append((broker_id, host, port, rack))
+    result["brokers"] = brokers
+    result["cluster_authorized_operations"] = read(">i")
+    assert read(">B") == 0, "Expected an empty response tag buffer"
+    assert offset == len(response), f"DescribeCluster response has {len(response) - offset} trailing bytes"
+    return result
+
+def test_describe_cluster():
+    print("🧪 Testing DescribeCluster agrees with Metadata...")
+    client = EventorTestClient()
+    
+    def describe(client, api_version=1, **kwargs):
+        request, corr_id = client.build_describe_cluster_request(api_version, **kwargs)
+        return parse_describe_cluster_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    def metadata(client, api_version=12):
+        request, corr_id = client.build_metadata_request([], api_version=api_version)
+        racks = {}
+        brokers, cluster_id, controller_id, _ = parse_metadata_response(
+            client.send_request_and_get_response(request, corr_id), api_version, racks)
+        return [(node_id, host, port, racks[node_id]) for node_id, host, port in brokers], cluster_id, controller_id
+    
+    for api_version in (0, 1):
+        described = describe(client, api_version)
+        assert (described["error_code"], described["error_message"]) == (0, None), f"v{api_version}: {described}"
+        assert described.get("endpoint_type", 1) == 1, f"v{api_version}: {described}"
+        assert (described["brokers"], described["cluster_id"], described["controller_id"]) == metadata(client), \
+            f"v{api_version}: {described} against Metadata {metadata(client)}"
+        assert described["cluster_id"] == "eventor-fixture-cluster", f"v{api_version}: {described}"
+        assert described["cluster_authorized_operations"] == -2**31, f"v{api_version}: {described}"
+        # Every cluster operation: CREATE, ALTER, DESCRIBE, CLUSTER_ACTION, DESCRIBE_CONFIGS,
+        # ALTER_CONFIGS and IDEMPOTENT_WRITE
+        described = describe(client, api_version, include_cluster_authorized_operations=True)
+        assert described["cluster_authorized_operations"] == sum(1 << op for op in (5, 7, 8, 9, 10, 11, 12)), \
+            f"v{api_version}: {described}"
+    
+    # Only the broker endpoints are served here
+    described = describe(client, endpoint_type=2)
+    assert described["error_code"] == 119 and described["error_message"], f"Controller endpoints: {described}"
+    assert (described["endpoint_type"], described["brokers"], described["controller_id"]) == (2, [], -1), \
+        f"Controller endpoints: {described}"
+    
+    # Everything reported comes from the broker's configuration
+    workdir = tempfile.mkdtemp(prefix="eventor-describe-cluster-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{DESCRIBE_CLUSTER_TEST_PORT}\n"
+                "advertised.listeners=PLAINTEXT://broker-7.example:19092\n"
+                "node.id=7\n"
+                "broker.rack=rack-a\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                f"readiness.file={readiness_file}\n")
+    log = open(os.path.join(workdir, "server.log"), "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        configured = EventorTestClient(port=DESCRIBE_CLUSTER_TEST_PORT)
+        described = describe(configured)
+        expected = ([(7, "broker-7.example", 19092, "rack-a")], "eventor-fixture-cluster", 7)
+        assert (described["brokers"], described["cluster_id"], described["controller_id"]) == expected, \
+            f"Configured broker: {described}"
+        for api_version in (1, 9, 12):
+            assert metadata(configured, api_version)[::2] == (expected[0], 7), \
+                f"Configured broker, Metadata v{api_version}: {metadata(configured, api_version)}"
+    finally:
+        server.terminate()
+        server.wait(timeout=5)
+        log.close()
+    print("✅ DescribeCluster test passed")
+
+def parse_describe_configs_response(response, api_version):
+    """Decodes a DescribeConfigsResponse into [(error_code, error_message, resource_type,
+    resource_name, configs)] with configs {name: entry}; each entry is a dict of the
+    fields the version has, synonyms as [(name, value, source)]."""
+    flexible = api_version >= 4
+    offset = 9 if flexible else 8
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        nonlocal offset
+        length = read_uvarint() - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_array_len():
+        return read_uvarint() - 1 if flexible else read(">i")
+    
+    def skip_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    for _ in range(read_array_len()):
+        error_code = read(">h")
+        error_message = read_string()
+        resource_type = read(">b")
+        resource_name = read_string()
+        configs = {}
+        for _ in range(read_array_len()):
+            name = read_string()
+            entry = {"value": read_string(), "read_only": read(">?")}
+            if api_version == 0:
+                entry["is_default"] = read(">?")
+            else:
+                entry["config_source"] = read(">b")
+            entry["is_sensitive"] = read(">?")
+            if api_version >= 1:
+                synonyms = []
+                for _ in range(read_array_len()):
+                    synonyms.append((read_string(), read_string(), read(">b")))
+                    skip_tags()
+                entry["synonyms"] = synonyms
+            if api_version >= 3:
+                entry["config_type"] = read(">b")
+                entry["documentation"] = read_string()
+            skip_tags()
+            assert name not in configs, f"{name} described twice"
+            configs[name] = entry
+        skip_tags()
+        results.append((error_code, error_message, resource_type, resource_name, configs))
+    skip_tags()
+    assert offset == len(response), f"DescribeConfigs response has {len(response) - offset} trailing bytes"
+    return results
+
+def parse_incremental_alter_configs_response(response, api_version):
+    """Decodes an IncrementalAlterConfigsResponse into [(error_code, error_message,
+    resource_type, resource_name)]."""
+    flexible = api_version >= 1
+    offset = 9 if flexible else 8
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        nonlocal offset
+        length = read_uvarint() - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def skip_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    for _ in range(read_uvarint() - 1 if flexible else read(">i")):
+        results.append((read(">h"), read_string(), read(">b"), read_string()))
+        skip_tags()
+    skip_tags()
+    assert offset == len(response), \
+        f"IncrementalAlterConfigs response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_describe_configs():
+    print("🧪 Testing DescribeConfigs for topics and the broker...")
+    client = EventorTestClient()
+    TOPIC, BROKER = 2, 4
+    DYNAMIC_TOPIC_CONFIG, STATIC_BROKER_CONFIG, DEFAULT_CONFIG = 1, 4, 5
+    
+    def describe(client, resources, api_version=4, **kwargs):
+        request, corr_id = client.build_describe_configs_request(resources, api_version, **kwargs)
+        return parse_describe_configs_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    request, corr_id = client.build_create_topics_request(
+        [("configured", 1, 1, [], [("retention.ms", "60000"), ("x-custom", "yes")])])
+    response = client.send_request_and_get_response(request, corr_id)
+    assert parse_create_topics_response(response, 7)["configured"][0] == 0, "Creating configured"
+    
+    # Overrides over defaults, with every field each version has
+    for api_version in range(5):
+        [(error_code, error_message, resource_type, name, configs)] = describe(
+            client, [(TOPIC, "configured", None)], api_version)
+        assert (error_code, error_message, resource_type, name) == (0, None, TOPIC, "configured"), \
+            f"v{api_version}: {error_code} {error_message}"
+        retention, cleanup, custom = configs["retention.ms"], configs["cleanup.policy"], configs["x-custom"]
+        assert (retention["value"], cleanup["value"], custom["value"]) == ("60000", "delete", "yes"), \
+            f"v{api_version}: {configs}"
+        assert not any(entry["read_only"] or entry["is_sensitive"] for entry in configs.values()), \
+            f"v{api_version}: {configs}"
+        if api_version == 0:
+            assert (retention["is_default"], cleanup["is_default"]) == (False, True), f"v0: {configs}"
+        else:
+            assert (retention["config_source"], cleanup["config_source"], custom["config_source"]) == \
+                (DYNAMIC_TOPIC_CONFIG, DEFAULT_CONFIG, DYNAMIC_TOPIC_CONFIG), f"v{api_version}: {configs}"
+            assert retention["synonyms"] == [], f"v{api_version}: synonyms not asked for"
+        if api_version >= 3:
+            # LONG, LIST and UNKNOWN; documentation only when asked for
+            assert (retention["config_type"], cleanup["config_type"], custom["config_type"]) == (5, 7, 0), \
+                f"v{api_version}: {configs}"
+            assert retention["documentation"] is None, f"v{api_version}: documentation not asked for"
+        assert list(configs) == sorted(configs), f"v{api_version}: entries out of order {list(configs)}"
+    
+    [(_, _, _, _, configs)] = describe(client, [(TOPIC, "configured", None)],
+                                       include_synonyms=True, include_documentation=True)
+    assert configs["retention.ms"]["synonyms"] == [
+        ("retention.ms", "60000", DYNAMIC_TOPIC_CONFIG), ("retention.ms", "604800000", DEFAULT_CONFIG)], \
+        f"Synonyms: {configs['retention.ms']}"
+    assert configs["cleanup.policy"]["synonyms"] == [("cleanup.policy", "delete", DEFAULT_CONFIG)], \
+        f"Synonyms: {configs['cleanup.policy']}"
+    assert configs["retention.ms"]["documentation"], f"Documentation: {configs['retention.ms']}"
+    
+    # configuration_keys restricts the entries; names nobody has are left out
+    [(_, _, _, _, configs)] = describe(client, [(TOPIC, "configured", ["segment.bytes", "x-custom", "nope"])])
+    assert sorted(configs) == ["segment.bytes", "x-custom"], f"Filtered: {configs}"
+    [(_, _, _, _, configs)] = describe(client, [(TOPIC, "configured", [])])
+    assert configs == {}, f"Empty filter: {configs}"
+    
+    # A bad resource fails alone, with the name echoed capped
+    long_name = "u" * (ECHO_MAX_NAME_BYTES + 50)
+    results = describe(client, [(TOPIC, "missing", None), (TOPIC, "configured", ["retention.ms"]),
+                                (BROKER, "2", None), (8, "1", None), (TOPIC, long_name, None),
+                                (BROKER, "1", ["node.id"]), (BROKER, "", None)])
+    assert [result[0] for result in results] == [3, 0, 42, 42, 3, 0, 0], f"Per-resource errors: {results}"
+    assert results[1][4]["retention.ms"]["value"] == "60000", f"Topic next to failures: {results[1]}"
+    assert results[2][1] and results[3][1], f"Refusals explain themselves: {results[2:4]}"
+    assert results[4][3] == long_name[:ECHO_MAX_NAME_BYTES] and "truncated" in results[4][1], \
+        f"Long name echoed: {results[4][1:4]}"
+    assert results[5][4] == {"node.id": {"value": "1", "read_only": True, "config_source": DEFAULT_CONFIG,
+                                         "is_sensitive": False, "synonyms": [], "config_type": 3,
+                                         "documentation": None}}, f"Broker node.id: {results[5]}"
+    assert results[6][4] == {}, f"Cluster-wide defaults: {results[6]}"
+    assert all(result[4] == {} for result in results if result[0] != 0), f"Failures with configs: {results}"
+    
+    # The broker's values come from its configuration: set ones are static, the rest defaults
+    workdir = tempfile.mkdtemp(prefix="eventor-describe-configs-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{DESCRIBE_CONFIGS_TEST_PORT}\n"
+                "node.id=7\n"
+                "num.partitions=3\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                f"readiness.file={readiness_file}\n")
+    log = open(os.path.join(workdir, "server.log"), "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        configured = EventorTestClient(port=DESCRIBE_CONFIGS_TEST_PORT)
+        response = describe(configured, [(BROKER, "7", None), (BROKER, "1", None)], include_synonyms=True)
+        [(error_code, _, _, _, configs), refused] = response
+        assert error_code == 0 and refused[0] == 42, f"Configured broker: {response}"
+        assert all(entry["read_only"] for entry in configs.values()), f"Broker configs: {configs}"
+        sources = {name: (entry["value"], entry["config_source"]) for name, entry in configs.items()}
+        assert sources["node.id"] == ("7", STATIC_BROKER_CONFIG), f"node.id: {sources}"
+        assert sources["num.partitions"] == ("3", STATIC_BROKER_CONFIG), f"num.partitions: {sources}"
+        assert sources["log.store"] == ("memory", STATIC_BROKER_CONFIG), f"log.store: {sources}"
+        assert sources["group.max.session.timeout.ms"][1] == DEFAULT_CONFIG, f"Defaults: {sources}"
+        assert configs["num.partitions"]["synonyms"] == [
+            ("num.partitions", "3", STATIC_BROKER_CONFIG), ("num.partitions", "1", DEFAULT_CONFIG)], \
+            f"Synonyms: {configs['num.partitions']}"
+        assert not any("kraft-combined-logs" in str(entry["value"]) for entry in configs.values()), \
+            f"Paths on the broker reported: {configs}"
+    finally:
+        server.terminate()
+        server.wait(timeout=5)
+        log.close()
+    
+    request, corr_id = client.build_delete_topics_request(["configured"])
+    client.send_request_and_get_response(request, corr_id)
+    print("✅ DescribeConfigs test passed")
+
+def test_incremental_alter_configs():
+    print("🧪 Testing IncrementalAlterConfigs changes topic configs and retention applies them...")
+    client = EventorTestClient()
+    TOPIC, BROKER = 2, 4
+    SET, DELETE, APPEND, SUBTRACT = 0, 1, 2, 3
+    DYNAMIC_TOPIC_CONFIG, DEFAULT_CONFIG = 1, 5
+    
+    def alter(client, resources, api_version=1, validate_only=False):
+        request, corr_id = client.build_incremental_alter_configs_request(resources, api_version, validate_only)
+        return parse_incremental_alter_configs_response(
+            client.send_request_and_get_response(request, corr_id), api_version)
+    
+    def described(client, topic, name):
+        request, corr_id = client.build_describe_configs_request([(TOPIC, topic, [name])])
+        [(error_code, _, _, _, configs)] = parse_describe_configs_response(
+            client.send_request_and_get_response(request, corr_id), 4)
+        assert error_code == 0, f"Describing {topic} failed with {error_code}"
+        return configs[name]["value"], configs[name]["config_source"]
+    
+    request, corr_id = client.build_create_topics_request([("altered", 1, 1, [], [("x-custom", "yes")])])
+    response = client.send_request_and_get_response(request, corr_id)
+    assert parse_create_topics_response(response, 7)["altered"][0] == 0, "Creating altered"
+    
+    # validate_only checks without changing; then the change shows in DescribeConfigs
+    for api_version in range(2):
+        assert alter(client, [(TOPIC, "altered", [("retention.ms", SET, "60000")])], api_version,
+                     validate_only=True) == [(0, None, TOPIC, "altered")], f"v{api_version} validate_only"
+        assert described(client, "altered", "retention.ms") == ("604800000", DEFAULT_CONFIG), \
+            f"v{api_version}: validate_only changed the topic"
+    assert alter(client, [(TOPIC, "altered", [("retention.ms", SET, "60000")])], 0) == \
+        [(0, None, TOPIC, "altered")], "v0 SET"
+    assert described(client, "altered", "retention.ms") == ("60000", DYNAMIC_TOPIC_CONFIG), \
+        described(client, "altered", "retention.ms")
+    
+    # APPEND and SUBTRACT edit list configs element by element
+    assert alter(client, [(TOPIC, "altered", [("cleanup.policy", APPEND, "compact")])])[0][0] == 0
+    assert described(client, "altered", "cleanup.policy") == ("delete,compact", DYNAMIC_TOPIC_CONFIG)
+    assert alter(client, [(TOPIC, "altered", [("cleanup.policy", SUBTRACT, "delete")])])[0][0] == 0
+    assert described(client, "altered", "cleanup.policy") == ("compact", DYNAMIC_TOPIC_CONFIG)
+    
+    # One bad operation fails its whole resource, explaining why, and changes nothing
+    for configs in ([("segment.bytes", SET, "2048"), ("no.such.config", SET, "1")],
+                    [("segment.bytes", SET, "2048"), ("retention.ms", SET, "soon")],
+                    [("min.insync.replicas", SET, "0")],
+                    [("compression.type", SET, "brotli")],
+                    [("cleanup.policy", APPEND, "shred")],
+                    [("retention.ms", APPEND, "1")],
+                    [("retention.ms", SET, None)],
+                    [("segment.bytes", 9, "2048")]):
+        [(error_code, error_message, _, _)] = alter(client, [(TOPIC, "altered", configs)])
+        assert error_code == 40 and error_message, f"{configs}: {error_code} {error_message}"
+    assert described(client, "altered", "segment.bytes") == ("1073741824", DEFAULT_CONFIG), \
+        "A failed resource was partly applied"
+    assert described(client, "altered", "retention.ms") == ("60000", DYNAMIC_TOPIC_CONFIG)
+    
+    # Other resources fail on their own; the broker's configs can't be altered at runtime
+    results = alter(client, [(TOPIC, "missing", [("retention.ms", SET, "1")]),
+                             (BROKER, "1", [("num.partitions", SET, "3")]),
+                             (8, "x", []),
+                             (TOPIC, "altered", [("segment.bytes", SET, "1"), ("segment.bytes", DELETE, None)]),
+                             (TOPIC, "twice", []), (TOPIC, "twice", [])])
+    assert [result[0] for result in results] == [3, 35, 42, 42, 42, 42], f"Per-resource errors: {results}"
+    assert all(result[1] for result in results[1:]), f"Refusals explain themselves: {results}"
+    
+    # DELETE goes back to the default; an override the topic was created with can be dropped
+    assert alter(client, [(TOPIC, "altered", [("retention.ms", DELETE, None), ("x-custom", DELETE, None)])]) == \
+        [(0, None, TOPIC, "altered")], "DELETE"
+    assert described(client, "altered", "retention.ms") == ("604800000", DEFAULT_CONFIG)
+    request, corr_id = client.build_describe_configs_request([(TOPIC, "altered", None)])
+    [(_, _, _, _, configs)] = parse_describe_configs_response(client.send_request_and_get_response(request, corr_id), 4)
+    assert "x-custom" not in configs, f"Deleted override still described: {configs}"
+    assert alter(client, [(TOPIC, "altered", [("x-custom", DELETE, None)])])[0][0] == 40, \
+        "Deleting an unknown config nobody overrides"
+    request, corr_id = client.build_delete_topics_request(["altered"])
+    client.send_request_and_get_response(request, corr_id)
+    
+    # An altered retention.ms deletes the records older than it at the next check
+    workdir = tempfile.mkdtemp(prefix="eventor-retention-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{RETENTION_TEST_PORT}\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                "log.retention.check.interval.ms=100\n"
+                f"readiness.file={readiness_file}\n")
+    log = open(os.path.join(workdir, "server.log"), "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        retained = EventorTestClient(port=RETENTION_TEST_PORT)
+        
+        def earliest(topic):
+            request, corr_id = retained.build_list_offsets_request([(topic, [(0, -2)])])
+            error_code, _, offset, _ = parse_list_offsets_response(
+                retained.send_request_and_get_response(request, corr_id), 7)[(topic, 0)]
+            assert error_code == 0, f"ListOffsets earliest of {topic} failed with {error_code}"
+            return offset
+        
+        request, corr_id = retained.build_create_topics_request([("expiring", 1, 1), ("kept", 1, 1)])
+        retained.send_request_and_get_response(request, corr_id)
+        now_ms = int(time.time() * 1000)
+        for topic in ("expiring", "kept"):
+            assert produce_one(retained, topic, 0, [b"old"]) == 0  # timestamp 0
+            request, corr_id = retained.build_produce_request(
+                [(topic, [(0, build_record_batch([b"new"], timestamps=[now_ms]))])])
+            assert parse_produce_response(retained.send_request_and_get_response(request, corr_id), 9)[(topic, 0)][0] == 0
+        time.sleep(0.3)
+        assert earliest("expiring") == 0 and earliest("kept") == 0, "Records deleted under the default retention"
+        
+        assert alter(retained, [(TOPIC, "expiring", [("retention.ms", SET, "60000")])])[0][0] == 0
+        deadline = time.monotonic() + 5
+        while earliest("expiring") != 1:
+            assert time.monotonic() < deadline, f"Retention never applied: earliest {earliest('expiring')}"
+            time.sleep(0.05)
+        time.sleep(0.3)
+        assert earliest("expiring") == 1 and earliest("kept") == 0, \
+            f"Retention deleted too much: {earliest('expiring')} {earliest('kept')}"
+    finally:
+        server.terminate()
+        server.wait(timeout=5)
+        log.close()
+    print("✅ IncrementalAlterConfigs test passed")
+
+def test_config_overrides_survive_restart():
+    print("🧪 Testing altered topic configs survive restarts, kill -9 included...")
+    TOPIC, SET, DELETE, APPEND = 2, 0, 1, 2
+    DYNAMIC_TOPIC_CONFIG, DEFAULT_CONFIG = 1, 5
+    workdir = tempfile.mkdtemp(prefix="eventor-config-overrides-")
+    log_dir = os.path.join(workdir, "logs")
+    shutil.copytree("fixtures/kraft-combined-logs", log_dir)
+    overrides_file = os.path.join(log_dir, "topic-config-overrides")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{CONFIG_OVERRIDES_TEST_PORT}\n"
+                f"log.dirs={log_dir}\n"
+                "log.store=file\n"
+                f"readiness.file={readiness_file}\n")
+    log_path = os.path.join(workdir, "server.log")
+    
+    def run_broker(check, kill=False):
+        log = open(log_path, "w")
+        server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+        try:
+            deadline = time.monotonic() + 5
+            while not os.path.exists(readiness_file):
+                assert server.poll() is None, f"Broker exited during startup:\n{open(log_path).read()}"
+                assert time.monotonic() < deadline, "Broker never became ready"
+                time.sleep(0.05)
+            check(EventorTestClient(port=CONFIG_OVERRIDES_TEST_PORT))
+            if kill:
+                server.kill()
+                server.wait()
+                os.remove(readiness_file)
+            else:
+                server.terminate()
+                assert server.wait(timeout=5) == 0, "Broker did not shut down cleanly on SIGTERM"
+        finally:
+            if server.poll() is None:
+                server.kill()
+                server.wait()
+            log.close()
+        with open(log_path) as f:
+            return f.read()
+    
+    def alter(client, topic, configs):
+        request, corr_id = client.build_incremental_alter_configs_request([(TOPIC, topic, configs)])
+        [(error_code, error_message, _, _)] = parse_incremental_alter_configs_response(
+            client.send_request_and_get_response(request, corr_id), 1)
+        assert error_code == 0, f"Altering {topic} failed with {error_code}: {error_message}"
+    
+    def described(client, topic, name):
+        request, corr_id = client.build_describe_configs_request([(TOPIC, topic, [name])])
+        [(error_code, _, _, _, configs)] = parse_describe_configs_response(
+            client.send_request_and_get_response(request, corr_id), 4)
+        assert error_code == 0, f"Describing {topic} failed with {error_code}"
+        return configs[name]["value"], configs[name]["config_source"]
+    
+    # Answered alterations are saved, so even kill -9 right after keeps them
+    def first_run(client):
+        alter(client, "foo", [("retention.ms", SET, "60000"), ("cleanup.policy", APPEND, "compact")])
+        alter(client, "bar", [("retention.ms", SET, "120000")])
+        request, corr_id = client.build_create_topics_request([("forgotten", 1, 1)])
+        client.send_request_and_get_response(request, corr_id)
+        alter(client, "forgotten", [("retention.ms", SET, "1000")])
+    
+    run_broker(first_run, kill=True)
+    
+    def after_kill(client):
+        assert described(client, "foo", "retention.ms") == ("60000", DYNAMIC_TOPIC_CONFIG), \
+            f"foo after kill -9: {described(client, 'foo', 'retention.ms')}"
+        assert described(client, "foo", "cleanup.policy") == ("delete,compact", DYNAMIC_TOPIC_CONFIG)
+        assert described(client, "bar", "retention.ms") == ("120000", DYNAMIC_TOPIC_CONFIG)
+        alter(client, "bar", [("retention.ms", DELETE, None)])
+    
+    output = run_broker(after_kill)
+    # The created topic is forgotten at a restart, and its overrides with it
+    assert "Loaded config overrides of 2 topic(s)" in output and "1 dropped with their topics" in output, output
+    
+    # An alteration cut short before its rename left only the staged file: it's discarded, with a warning
+    with open(overrides_file + ".tmp", "w") as f:
+        f.write("version=1\n")
+    
+    def after_interrupted_save(client):
+        assert described(client, "foo", "retention.ms") == ("60000", DYNAMIC_TOPIC_CONFIG)
+        assert described(client, "bar", "retention.ms") == ("604800000", DEFAULT_CONFIG), \
+            f"bar after DELETE and restart: {described(client, 'bar', 'retention.ms')}"
+    
+    output = run_broker(after_interrupted_save)
+    assert "cut short before it was saved" in output, output
+    assert not os.path.exists(overrides_file + ".tmp"), "Staged overrides file left behind"
+    
+    # A format this broker doesn't know fails startup instead of being overwritten
+    with open(overrides_file) as f:
+        saved = f.read()
+    assert saved.startswith("version=1\n"), saved
+    with open(overrides_file, "w") as f:
+        f.write(saved.replace("version=1", "version=2", 1))
+    with open(log_path, "w") as log:
+        result = subprocess.run([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT, timeout=10)
+    output = open(log_path).read()
+    assert result.returncode != 0 and "format version 2" in output, output
+    print("✅ Config overrides restart test passed")
+
+def test_metadata_snapshot_and_log_topics():
+    print("🧪 Testing topics load from the metadata snapshot and the log after it...")
+    client = EventorTestClient()
+    batch = build_record_batch([b"loaded"])
+    # foo and bar come from the snapshot, baz from log records past its end offset
+    request, corr_id = client.build_produce_request(
+        [("bar", [(1, batch)]), ("baz", [(0, batch)]), ("snap", [(0, batch)])])
+    response = client.send_request_and_get_response(request, corr_id)
+    results = parse_produce_response(response, 9)
+    assert results[("bar", 1)][0] == 0, f"Snapshot topic bar: {results[('bar', 1)]}"
+    assert results[("baz", 0)][0] == 0, f"Post-snapshot topic baz: {results[('baz', 0)]}"
+    assert results[("snap", 0)][0] == 3, f"snap only exists in the other fixture: {results[('snap', 0)]}"
+    print("✅ Snapshot and log metadata test passed")
+
+def parse_create_topics_response(response, api_version):
+    """Decodes a CreateTopicsResponse into {name: (error_code, error_message, topic_id,
+    num_partitions, replication_factor, configs)}; the last four are None where the version
+    lacks them, and configs is {name: value} or None."""
+    flexible = api_version >= 5
+    offset = 9 if flexible else 8
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        return read_uvarint() - 1 if flexible else read(">i")
+    
+    def read_string():
+        length = read_uvarint() - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read_uvarint() == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = {}
+    for _ in range(read_len()):
+        name = read_string()
+        topic_id = read("16s") if api_version >= 7 else None
+        error_code = read(">h")
+        error_message = read_string()
+        num_partitions = replication_factor = configs = None
+        if api_version >= 5:
+            num_partitions, replication_factor = read(">ih")
+            config_count = read_len()
+            if config_count >= 0:
+                configs = {}
+                for _ in range(config_count):
+                    config_name = read_string()
+                    value = read_string()
+                    read_only, config_source, is_sensitive = read(">bbb")
+                    assert (read_only, config_source, is_sensitive) == (0, 1, 0), \
+                        f"Config {config_name}: unexpected flags {(read_only, config_source, is_sensitive)}"
+                    read_tags()
+                    configs[config_name] = value
+        read_tags()
+        results[name] = (error_code, error_message, topic_id, num_partitions, replication_factor, configs)
+    read_tags()
+    assert offset == len(response), f"CreateTopics response has {len(response) - offset} trailing bytes"
+    return results
+
+def parse_describe_topic_partitions_response(response):
+    """Decodes a DescribeTopicPartitionsResponse into (topics, next_cursor), where topics is
+    [(error_code, name, topic_id, partitions, authorized_operations)] with partitions
+    [(index, leader, replicas, isr)] and next_cursor None or (topic_name, partition_index)."""
+    offset = 9
+    
+    def read_uvarint():
+        nonlocal offset
+        value, shift = 0, 0
+        while True:
+            byte = response[offset]
+            offset += 1
+            value |= (byte & 0x7F) << shift
+            if not byte & 0x80:
+                return value
+            shift += 7
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        length = read_uvarint() - 1
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        assert read_uvarint() == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    topics = []
+    for _ in range(read_uvarint() - 1):
+        error_code = read(">h")
+        name = read_string()
+        topic_id = read("16s")
+        assert read(">b") == 0, "Expected is_internal false"
+        partitions = []
+        for _ in range(read_uvarint() - 1):
+            partition_error, index, leader, _leader_epoch = read(">hiii")
+            assert partition_error == 0, f"Partition {index} has error {partition_error}"
+            replicas = [read(">i") for _ in range(read_uvarint() - 1)]
+            isr = [read(">i") for _ in range(read_uvarint() - 1)]
+            for field in ("eligible_leader_replicas", "last_known_elr", "offline_replicas"):
+                assert read_uvarint() - 1 == 0, f"Expected empty {field}"
+            read_tags()
+            partitions.append((index, leader, replicas, isr))
+        authorized_operations = read(">i")
+        read_tags()
+        topics.append((error_code, name, topic_id, partitions, authorized_operations))
+    next_cursor = None
+    if read(">B") != 0xff:
+        next_cursor = (read_string(), read(">i"))
+        read_tags()
+    read_tags()
+    assert offset == len(response), f"DescribeTopicPartitions response has {len(response) - offset} trailing bytes"
+    return topics, next_cursor
+
+def test_create_topics():
+    print("🧪 Testing CreateTopics and describing the created topics...")
+    client = EventorTestClient()
+    
+    def create(topics, api_version=7, validate_only=False):
+        request, corr_id = client.build_create_topics_request(topics, api_version, validate_only)
+        return parse_create_topics_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    def describe(names, **kwargs):
+        request, corr_id = client.build_describe_topic_partitions_request(names, **kwargs)
+        return parse_describe_topic_partitions_response(client.send_request_and_get_response(request, corr_id))
+    
+    # validate_only runs the checks but creates nothing
+    results = create([("created-dry-run", 3, 1)], validate_only=True)
+    assert results["created-dry-run"][:2] == (0, None), f"validate_only: {results}"
+    assert results["created-dry-run"][2] == bytes(16), "validate_only reported a topic id"
+    topics, _ = describe(["created-dry-run"])
+    assert topics[0][0] == 3, f"validate_only created the topic: {topics}"
+    
+    results = create([("created-three", 3, 1, [], [("cleanup.policy", "compact"), ("retention.ms", None)]),
+                      ("created-default", -1, -1)])
+    error_code, _, topic_id, num_partitions, replication_factor, configs = results["created-three"]
+    assert (error_code, num_partitions, replication_factor) == (0, 3, 1), f"created-three: {results}"
+    assert configs == {"cleanup.policy": "compact", "retention.ms": None}, f"created-three configs: {configs}"
+    assert topic_id != bytes(16) and topic_id[6] >> 4 == 4, f"Expected a v4 UUID, got {topic_id.hex()}"
+    # num.partitions=1 in the fixture
+    assert results["created-default"][0] == 0 and results["created-default"][3:5] == (1, 1), \
+        f"created-default: {results['created-default']}"
+    
+    topics, next_cursor = describe(["created-three"])
+    assert next_cursor is None, f"Unexpected cursor {next_cursor}"
+    error_code, name, described_id, partitions, authorized_operations = topics[0]
+    assert (error_code, name, described_id) == (0, "created-three", topic_id), f"Describe: {topics}"
+    assert partitions == [(index, 1, [1], [1]) for index in range(3)], f"Partitions: {partitions}"
+    assert authorized_operations == 0x0df8, f"authorized operations {authorized_operations:#x}"
+    
+    # The rest of the broker sees the topic too
+    request, corr_id = client.build_metadata_request([topic_id], api_version=12)
+    _, _, _, metadata = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)
+    assert [(error_code, name) for error_code, name, _, _ in metadata] == [(0, "created-three")], metadata
+    assert produce_one(client, "created-three", 2, [b"into-created"]) == 0
+    
+    # Creating again, in either encoding, is an error per topic
+    for api_version in (2, 4, 5, 7):
+        results = create([("created-three", 1, 1), (f"created-v{api_version}", 1, 1)], api_version)
+        assert results["created-three"][0] == 36, f"v{api_version} duplicate: {results}"
+        assert results[f"created-v{api_version}"][0] == 0, f"v{api_version} create: {results}"
+        if api_version >= 5:
+            assert results["created-three"][3:] == (-1, -1, None), f"v{api_version} error details: {results}"
+    
+    rejected = create([("bad-zero-partitions", 0, 1), ("bad-negative-partitions", -5, 1),
+                       ("bad-zero-replicas", 1, 0), ("bad-three-replicas", 1, 3),
+                       ("bad/name", 1, 1), ("twice", 1, 1), ("twice", 1, 1),
+                       ("assigned", -1, -1, [(1, [1]), (0, [1])], []),
+                       ("bad-assignment", -1, -1, [(0, [2])], [])])
+    expected = {"bad-zero-partitions": 37, "bad-negative-partitions": 37, "bad-zero-replicas": 38,
+                "bad-three-replicas": 38, "bad/name": 17, "twice": 42, "assigned": 0,
+                "bad-assignment": 39}
+    assert {name: result[0] for name, result in rejected.items()} == expected, f"Rejections: {rejected}"
+    topics, _ = describe(["assigned", "bad-zero-partitions"])
+    assert [(error_code, name, len(partitions)) for error_code, name, _, partitions, _ in topics] == \
+        [(0, "assigned", 2), (3, "bad-zero-partitions", 0)], f"After rejections: {topics}"
+    print("✅ CreateTopics test passed")
+
+def test_describe_topic_partitions_paging():
+    print("🧪 Testing DescribeTopicPartitions ordering and cursor paging...")
+    client = EventorTestClient()
+    
+    def describe(names, **kwargs):
+        request, corr_id = client.build_describe_topic_partitions_request(names, **kwargs)
+        return parse_describe_topic_partitions_response(client.send_request_and_get_response(request, corr_id))
+    
+    # Answered in name order whatever the request order
+    topics, next_cursor = describe(["foo", "missing", "bar"])
+    assert [(error_code, name) for error_code, name, _, _, _ in topics] == [(0, "bar"), (0, "foo"), (3, "missing")], \
+        f"Order: {topics}"
+    assert next_cursor is None
+    
+    # A limit of 2 over bar (2 partitions), baz (1) and foo (1) takes three pages
+    seen, cursor, pages = [], None, 0
+    while True:
+        topics, cursor = describe(["foo", "baz", "bar"], partition_limit=2, cursor=cursor)
+        pages += 1
+        seen += [(name, index) for _, name, _, partitions, _ in topics for index, _, _, _ in partitions]
+        if cursor is None:
+            break
+        assert pages < 5, "Paging never finished"
+    assert pages == 2, f"Expected 2 pages, got {pages}"
+    assert seen == [("bar", 0), ("bar", 1), ("baz", 0), ("foo", 0)], f"Paged partitions: {seen}"
+    
+    # An empty name is a topic that doesn't exist; a null one is no name at all,
+    # and the request is refused
+    topics, _ = describe(["", "foo"])
+    assert [(error_code, name) for error_code, name, _, _, _ in topics] == [(3, ""), (0, "foo")], \
+        f"Empty name: {topics}"
+    request, corr_id = client.build_describe_topic_partitions_request([None, "foo"])
+    response = client.send_request_and_get_response(request, corr_id)
+    assert struct.unpack(">h", response[9:11])[0] == 42, f"Null name: {response.hex()}"
+    
+    topics, cursor = describe(["bar", "baz"], partition_limit=1)
+    assert cursor == ("bar", 1) and [name for _, name, _, _, _ in topics] == ["bar"], f"{topics} {cursor}"
+    topics, cursor = describe(["bar", "baz"], partition_limit=1, cursor=cursor)
+    assert cursor == ("baz", 0), f"Second page cursor {cursor}"
+    assert [(name, [p[0] for p in partitions]) for _, name, _, partitions, _ in topics] == [("bar", [1])], topics
+    print("✅ DescribeTopicPartitions paging test passed")
+
+def parse_create_partitions_response(response, api_version, messages=None):
+    """Decodes a CreatePartitionsResponse into [(name, error_code)]. Given a list, messages
+    collects each topic's error_message."""
+    flexible = api_version >= 2
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        # Every length in these responses fits in one varint byte
+        length = read(">B") - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    for _ in range(read(">B") - 1 if flexible else read(">i")):
+        name = read_string()
+        error_code = read(">h")
+        error_message = read_string()
+        assert (error_message is None) == (error_code == 0), \
+            f"error_message {error_message!r} with error {error_code}"
+        if messages is not None:
+            messages.append(error_message)
+        read_tags()
+        results.append((name, error_code))
+    read_tags()
+    assert offset == len(response), f"CreatePartitions response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_create_partitions():
+    print("🧪 Testing CreatePartitions grows topics and rejects each bad topic on its own...")
+    client = EventorTestClient()
+    
+    def exchange(request, corr_id):
+        return client.send_request_and_get_response(request, corr_id)
+    
+    def grow(topics, api_version=3, validate_only=False, messages=None):
+        return parse_create_partitions_response(
+            exchange(*client.build_create_partitions_request(topics, api_version, validate_only)),
+            api_version, messages)
+    
+    def partition_indexes(name):
+        topics, _ = parse_describe_topic_partitions_response(
+            exchange(*client.build_describe_topic_partitions_request(name)))
+        return [partition[0] for partition in topics[0][3]]
+    
+    exchange(*client.build_create_topics_request([("grown", 2, 1), ("grown-other", 3, 1)]))
+    base_offset = produce_one(client, "grown", 1, [b"before growing"])
+    
+    # validate_only runs the checks but leaves the topic as it was
+    assert grow([("grown", 4)], validate_only=True) == [("grown", 0)]
+    assert partition_indexes("grown") == [0, 1], "validate_only grew the topic"
+    
+    assert grow([("grown", 4)]) == [("grown", 0)]
+    assert partition_indexes("grown") == [0, 1, 2, 3], "New partitions aren't numbered on"
+    assert produce_one(client, "grown", 3, [b"new partition"]) == 0, "New partition isn't empty"
+    assert produce_one(client, "grown", 1, [b"after growing"]) == base_offset + 1, \
+        "Growing touched an existing partition's log"
+    
+    # Every bad topic is rejected on its own; the good one still grows
+    messages = []
+    results = grow([
+        ("grown-other", 3),
+        ("grown", 2),
+        ("never-existed", 5),
+        ("twice", 2), ("twice", 3),
+        ("grown-more", 10_001),
+        ("grown-most", 2),
+    ], messages=messages)
+    assert results == [
+        ("grown-other", 37),
+        ("grown", 37),
+        ("never-existed", 3),
+        ("twice", 42), ("twice", 42),
+        ("grown-more", 3),
+        ("grown-most", 3),
+    ], f"Per-topic errors: {results}"
+    assert "already has 3" in messages[0] and "can't be removed" in messages[1], messages
+    assert partition_indexes("grown") == [0, 1, 2, 3], "A shrink changed the topic"
+    results = grow([("grown", 6, [[1], [1], [1]]), ("grown-other", 10_001)])
+    assert results == [("grown", 39), ("grown-other", 37)], f"Wrong assignment count and limit: {results}"
+    assert grow([("grown", 5, [[1, 2]])]) == [("grown", 39)], "Assignment to another broker"
+    assert partition_indexes("grown") == [0, 1, 2, 3], "A rejected topic grew"
+    assert grow([("grown", 5, [[1]]), ("twice", 2)]) == [("grown", 0), ("twice", 3)]
+    assert partition_indexes("grown") == [0, 1, 2, 3, 4]
+    
+    # v0 and v1 use the classic encoding
+    for api_version in (0, 1):
+        assert grow([("grown-other", 4 + api_version), ("never-existed", 1)], api_version) == \
+            [("grown-other", 0), ("never-existed", 3)], f"v{api_version}"
+    assert partition_indexes("grown-other") == [0, 1, 2, 3, 4]
+    
+    exchange(*client.build_delete_topics_request(["grown", "grown-other"]))
+    print("✅ CreatePartitions test passed")
+
+def parse_delete_topics_response(response, api_version, messages=None):
+    """Decodes a DeleteTopicsResponse into [(name, topic_id, error_code)]; topic_id is None
+    before v6. Given a list, messages collects each topic's error_message (v5+)."""
+    flexible = api_version >= 4
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        # Every length in these responses fits in one varint byte
+        length = read(">B") - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    for _ in range(read(">B") - 1 if flexible else read(">i")):
+        name = read_string()
+        topic_id = read("16s") if api_version >= 6 else None
+        error_code = read(">h")
+        if api_version >= 5:
+            error_message = read_string()
+            assert (error_message is None) == (error_code == 0), \
+                f"error_message {error_message!r} with error {error_code}"
+            if messages is not None:
+                messages.append(error_message)
+        read_tags()
+        results.append((name, topic_id, error_code))
+    read_tags()
+    assert offset == len(response), f"DeleteTopics response has {len(response) - offset} trailing bytes"
+    return results
+
+def parse_delete_records_response(response, api_version):
+    """Decodes a DeleteRecordsResponse into [(topic, partition, low_watermark, error_code)],
+    in response order."""
+    flexible = api_version >= 2
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    # Every length in these responses fits in one varint byte
+    for _ in range(read(">B") - 1 if flexible else read(">i")):
+        length = read(">B") - 1 if flexible else read(">h")
+        name = response[offset:offset + length].decode("utf-8")
+        offset += length
+        for _ in range(read(">B") - 1 if flexible else read(">i")):
+            partition, low_watermark, error_code = read(">iqh")
+            read_tags()
+            results.append((name, partition, low_watermark, error_code))
+        read_tags()
+    read_tags()
+    assert offset == len(response), f"DeleteRecords response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_delete_records():
+    print("🧪 Testing DeleteRecords advances log start offsets...")
+    client = EventorTestClient()
+    
+    def exchange(request, corr_id):
+        return client.send_request_and_get_response(request, corr_id)
+    
+    def delete(topics, api_version=2):
+        return parse_delete_records_response(
+            exchange(*client.build_delete_records_request(topics, api_version)), api_version)
+    
+    def earliest(partition):
+        results = parse_list_offsets_response(
+            exchange(*client.build_list_offsets_request([("trimmed", [(partition, -2)])])), 7)
+        error_code, _, offset, _ = results[("trimmed", partition)]
+        assert error_code == 0, f"ListOffsets earliest of trimmed-{partition} failed with {error_code}"
+        return offset
+    
+    def fetch(fetch_offset):
+        request, corr_id = client.build_fetch_request([("trimmed", [(0, fetch_offset, 1 << 20)])], api_version=12)
+        log_starts = {}
+        error_code, _, records = parse_fetch_response(exchange(request, corr_id), 12, log_starts=log_starts)[("trimmed", 0)]
+        # Refusals report the log start too, so the consumer can reset to it
+        assert log_starts[("trimmed", 0)] == earliest(0), f"Fetch log_start_offset {log_starts}"
+        return error_code, [base for base, _ in decode_record_batches(records)]
+    
+    exchange(*client.build_create_topics_request([("trimmed", 2, 1)]))
+    assert produce_one(client, "trimmed", 0, [b"a", b"b"]) == 0
+    assert produce_one(client, "trimmed", 0, [b"c"]) == 2
+    assert produce_one(client, "trimmed", 0, [b"d"]) == 3
+    
+    # Up to an offset: earlier records become unreachable
+    assert delete([("trimmed", [(0, 2)])], api_version=0) == [("trimmed", 0, 2, 0)]
+    assert earliest(0) == 2, f"Earliest offset after deleting: {earliest(0)}"
+    assert fetch(0) == (1, []), f"Fetch below the log start: {fetch(0)}"
+    assert fetch(2) == (0, [2, 3]), f"Fetch at the log start: {fetch(2)}"
+    
+    # Past the high watermark only that partition is refused; -1 on an empty one is a no-op
+    assert delete([("trimmed", [(0, 5), (1, -1)])], api_version=1) == \
+        [("trimmed", 0, -1, 1), ("trimmed", 1, 0, 0)]
+    assert earliest(0) == 2, "A refused delete moved the log start"
+    
+    # -1 deletes everything below the high watermark; the log start never moves back
+    assert delete([("trimmed", [(0, -1)])]) == [("trimmed", 0, 4, 0)]
+    assert delete([("trimmed", [(0, 1)])]) == [("trimmed", 0, 4, 0)]
+    assert earliest(0) == 4 and fetch(4) == (0, []) and fetch(3) == (1, []), \
+        f"After deleting everything: earliest {earliest(0)}, fetches {fetch(4)} {fetch(3)}"
+    assert produce_one(client, "trimmed", 0, [b"e"]) == 4, "Appends don't carry on from the log end"
+    
+    # Unknown topics and partitions, offsets below -1 and repeated partitions fail on their own
+    assert delete([("no-such-topic", [(0, 0)]), ("trimmed", [(9, 0), (1, -2), (0, 5), (0, 5)])]) == [
+        ("no-such-topic", 0, -1, 3),
+        ("trimmed", 9, -1, 3),
+        ("trimmed", 1, -1, 1),
+        ("trimmed", 0, -1, 42),
+        ("trimmed", 0, -1, 42),
+    ]
+    assert earliest(0) == 4 and earliest(1) == 0, "A failed delete moved a log start"
+    print("✅ DeleteRecords test passed")
+
+def test_delete_topics():
+    print("🧪 Testing DeleteTopics by name and by topic id...")
+    client = EventorTestClient()
+    connection = EventorConnection(client)
+    
+    def exchange(request, corr_id):
+        connection.send(request, corr_id)
+        return connection.read_response()
+    
+    def describe(name):
+        topics, _ = parse_describe_topic_partitions_response(
+            exchange(*client.build_describe_topic_partitions_request(name)))
+        error_code, _, topic_id, partitions, _ = topics[0]
+        return error_code, topic_id, len(partitions)
+    
+    try:
+        # create, describe, delete, describe over one connection
+        created = parse_create_topics_response(
+            exchange(*client.build_create_topics_request([("deleted-by-name", 2, 1)])), 7)
+        topic_id = created["deleted-by-name"][2]
+        assert describe("deleted-by-name") == (0, topic_id, 2), "Created topic not described"
+        assert produce_one(client, "deleted-by-name", 1, [b"gone soon"]) == 0
+        results = parse_delete_topics_response(
+            exchange(*client.build_delete_topics_request(["deleted-by-name", "never-existed"])), 6)
+        assert results == [("deleted-by-name", topic_id, 0), ("never-existed", bytes(16), 3)], \
+            f"Delete by name: {results}"
+        assert describe("deleted-by-name") == (3, bytes(16), 0), "Deleted topic still described"
+        
+        # Created again under the same name, the topic has a new id and none of the old data
+        created = parse_create_topics_response(
+            exchange(*client.build_create_topics_request([("deleted-by-name", 2, 1)])), 7)
+        assert created["deleted-by-name"][2] not in (topic_id, bytes(16)), "Recreated topic reused its id"
+        assert produce_one(client, "deleted-by-name", 1, [b"fresh"]) == 0, "Old data survived the delete"
+        
+        # v6 deletes by id; an unknown id has no name to report
+        created = parse_create_topics_response(
+            exchange(*client.build_create_topics_request([("deleted-by-id", 1, 1)])), 7)
+        topic_id = created["deleted-by-id"][2]
+        unknown_id = bytes(range(16, 32))
+        results = parse_delete_topics_response(
+            exchange(*client.build_delete_topics_request([topic_id, unknown_id])), 6)
+        assert results == [("deleted-by-id", topic_id, 0), (None, unknown_id, 100)], f"Delete by id: {results}"
+        assert describe("deleted-by-id")[0] == 3, "Topic deleted by id still described"
+        
+        for api_version in (1, 4, 5):
+            name = f"deleted-v{api_version}"
+            exchange(*client.build_create_topics_request([(name, 1, 1)]))
+            results = parse_delete_topics_response(
+                exchange(*client.build_delete_topics_request([name, name], api_version)), api_version)
+            assert [(result[0], result[2]) for result in results] == [(name, 0), (name, 3)], \
+                f"v{api_version}: {results}"
+    finally:
+        connection.close()
+    
+    # Describes and produces racing creates and deletes of the same topic get an
+    # answer every time, either the topic or UNKNOWN_TOPIC_OR_PARTITION
+    failures = []
+    stop = threading.Event()
+    
+    def churn():
+        churner = EventorTestClient()
+        try:
+            while not stop.is_set():
+                request, corr_id = churner.build_create_topics_request([("churned", 4, 1)])
+                churner.send_request_and_get_response(request, corr_id)
+                request, corr_id = churner.build_delete_topics_request(["churned"])
+                churner.send_request_and_get_response(request, corr_id)
+        except Exception as e:
+            failures.append(f"churn: {e}")
+    
+    def observe():
+        observer = EventorTestClient()
+        try:
+            for _ in range(50):
+                request, corr_id = observer.build_describe_topic_partitions_request("churned")
+                topics, _ = parse_describe_topic_partitions_response(
+                    observer.send_request_and_get_response(request, corr_id))
+                error_code, _, _, partitions, _ = topics[0]
+                assert (error_code, len(partitions)) in ((0, 4), (3, 0)), f"Describe saw {topics}"
+                request, corr_id = observer.build_produce_request(
+                    [("churned", [(3, build_record_batch([b"racing"]))])])
+                results = parse_produce_response(observer.send_request_and_get_response(request, corr_id), 9)
+                assert results[("churned", 3)][0] in (0, 3), f"Produce saw {results}"
+        except Exception as e:
+            failures.append(f"observe: {e}")
+    
+    churner = threading.Thread(target=churn)
+    observers = [threading.Thread(target=observe) for _ in range(3)]
+    churner.start()
+    for thread in observers:
+        thread.start()
+    for thread in observers:
+        thread.join()
+    stop.set()
+    churner.join()
+    assert not failures, f"Concurrent create/delete: {failures}"
+    print("✅ DeleteTopics test passed")
+
+def test_deletes_wake_parked_fetches():
+    print("🧪 Testing topic deletions wake the fetches waiting on them...")
+    client = EventorTestClient()
+    names = [f"parked-{index}" for index in range(8)]
+    request, corr_id = client.build_create_topics_request([(name, 1, 1) for name in names])
+    created = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7)
+    assert all(created[name][0] == 0 for name in names), f"Creating parked topics: {created}"
+    
+    # One long poll per topic, each parked with nothing to return
+    connections = []
+    for name in names:
+        connection = EventorConnection(client)
+        request, corr_id = client.build_fetch_request([(name, [(0, 0, 1 << 20)])], max_wait_ms=10000)
+        connection.send(request, corr_id)
+        connections.append((name, connection))
+    time.sleep(0.3)
+    
+    # Deleted from several connections at once, with unrelated creates and
+    # deletes churning alongside; not one parked fetch may miss its wake-up
+    failures = []
+    
+    def delete(batch):
+        deleter = EventorTestClient()
+        try:
+            request, corr_id = deleter.build_delete_topics_request(batch)
+            results = parse_delete_topics_response(deleter.send_request_and_get_response(request, corr_id), 6)
+            assert all(error_code == 0 for _, _, error_code in results), f"Deleting {batch}: {results}"
+            request, corr_id = deleter.build_create_topics_request([(f"{batch[0]}-neighbour", 1, 1)])
+            deleter.send_request_and_get_response(request, corr_id)
+            request, corr_id = deleter.build_delete_topics_request([f"{batch[0]}-neighbour"])
+            deleter.send_request_and_get_response(request, corr_id)
+        except Exception as e:
+            failures.append(str(e))
+    
+    started = time.monotonic()
+    deleters = [threading.Thread(target=delete, args=(names[index:index + 2],)) for index in range(0, len(names), 2)]
+    for thread in deleters:
+        thread.start()
+    for thread in deleters:
+        thread.join()
+    assert not failures, f"Concurrent deletes: {failures}"
+    try:
+        for name, connection in connections:
+            results = parse_fetch_response(connection.read_response(), 12)
+            assert results[(name, 0)][0] == 3, f"Parked fetch on {name} returned {results}"
+    finally:
+        for _, connection in connections:
+            connection.close()
+    elapsed = time.monotonic() - started
+    assert elapsed < 3, f"Parked fetches took {elapsed:.2f}s to notice their topics were deleted"
+    
+    # An unknown partition in the request answers at once too, known ones included
+    log_end = produce_one(client, "bar", 0, [b"not-waited-for"]) + 1
+    request, corr_id = client.build_fetch_request(
+        [("bar", [(0, log_end, 1 << 20)]), ("no-such-topic", [(0, 0, 1 << 20)])], max_wait_ms=5000)
+    started = time.monotonic()
+    results = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 12)
+    elapsed = time.monotonic() - started
+    assert results[("no-such-topic", 0)][0] == 3 and results[("bar", 0)] == (0, log_end, b""), f"{results}"
+    assert elapsed < 2, f"Fetch with an unknown partition waited {elapsed:.2f}s"
+    print("✅ Parked fetch wake-up test passed")
+
+def check_topic_registry(client, deleted_ids=()):
+    """Asserts the registry's invariants as clients see them: every live topic has one
+    name and one id, both unique, and each resolves to the other; ids of deleted topics
+    resolve to nothing anywhere. Returns {name: topic_id}."""
+    request, corr_id = client.build_metadata_request(api_version=12)
+    topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)[3]
+    names = [name for _, name, _, _ in topics]
+    ids = [topic_id for _, _, topic_id, _ in topics]
+    assert len(set(names)) == len(names), f"Two live topics share a name: {sorted(names)}"
+    assert len(set(ids)) == len(ids) and bytes(16) not in ids, f"Live topic ids not unique: {ids}"
+    live = dict(zip(names, ids))
+    
+    # Looked up by id, every topic has the name it's listed under; by name, the id
+    request, corr_id = client.build_metadata_request(topics=ids, api_version=12)
+    by_id = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)[3]
+    assert [(error_code, name, topic_id) for error_code, name, topic_id, _ in by_id] == \
+        [(0, name, topic_id) for name, topic_id in live.items()], f"Lookups by id: {by_id}"
+    request, corr_id = client.build_metadata_request(topics=names, api_version=12)
+    by_name = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)[3]
+    assert [(name, topic_id) for _, name, topic_id, _ in by_name] == list(live.items()), f"Lookups by name: {by_name}"
+    
+    # A deleted topic's id answers UNKNOWN_TOPIC_ID from every API that takes one
+    deleted_ids = [topic_id for topic_id in deleted_ids if topic_id not in ids]
+    if deleted_ids:
+        request, corr_id = client.build_metadata_request(topics=deleted_ids, api_version=12)
+        gone = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)[3]
+        assert all(error_code == 100 for error_code, _, _, _ in gone), f"Deleted ids described: {gone}"
+        request, corr_id = client.build_fetch_request([(topic_id, [(0, 0, 1 << 20)]) for topic_id in deleted_ids],
+                                                      api_version=13)
+        fetched = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 13)
+        assert all(result[0] == 100 for result in fetched.values()), f"Deleted ids fetched: {fetched}"
+        request, corr_id = client.build_delete_topics_request(deleted_ids)
+        results = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6)
+        assert all(error_code == 100 for _, _, error_code in results), f"Deleted ids deleted again: {results}"
+    return live
+
+def test_topic_registry_invariants():
+    print("🧪 Testing the topic registry keeps names and ids one to one...")
+    client = EventorTestClient()
+    names = [f"registry-{index}" for index in range(6)]
+    check_topic_registry(client)
+    
+    # Creates, deletes by name and by id, and recreates of the same names, racing
+    # each other from several connections
+    deleted_ids = []
+    failures = []
+    
+    def churn(worker):
+        churner = EventorTestClient()
+        try:
+            for round_number in range(15):
+                name = names[(worker + round_number) % len(names)]
+                request, corr_id = churner.build_create_topics_request([(name, 2, 1)])
+                created = parse_create_topics_response(churner.send_request_and_get_response(request, corr_id), 7)
+                assert created[name][0] in (0, 36), f"Create {name}: {created}"
+                if round_number % 2:
+                    if created[name][0] != 0:
+                        continue
+                    target = created[name][2]  # by id: only ever the topic this worker made
+                else:
+                    target = name
+                request, corr_id = churner.build_delete_topics_request([target])
+                results = parse_delete_topics_response(churner.send_request_and_get_response(request, corr_id), 6)
+                assert results[0][2] in (0, 3, 100), f"Delete {name}: {results}"
+                if results[0][2] == 0:
+                    deleted_ids.append(results[0][1])
+        except Exception as e:
+            failures.append(f"worker {worker}: {e}")
+    
+    workers = [threading.Thread(target=churn, args=(worker,)) for worker in range(4)]
+    for thread in workers:
+        thread.start()
+    for thread in workers:
+        thread.join()
+    assert not failures, f"Churning the registry: {failures}"
+    assert deleted_ids, "No topic was ever deleted"
+    assert len(set(deleted_ids)) == len(deleted_ids), "A topic id was deleted twice"
+    live = check_topic_registry(client, deleted_ids)
+    
+    # Whatever survived, a name created again has a new id and nothing of the old topic
+    for name in names:
+        request, corr_id = client.build_create_topics_request([(name, 2, 1)])
+        client.send_request_and_get_response(request, corr_id)
+        produce_one(client, name, 1, [b"before"])
+        request, corr_id = client.build_offset_commit_request("registry-group", [(name, [(1, 1, None)])])
+        assert set(parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), 8).values()) == {0}
+        request, corr_id = client.build_delete_topics_request([name])
+        results = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6)
+        deleted_ids.append(results[0][1])
+        request, corr_id = client.build_create_topics_request([(name, 2, 1)])
+        created = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7)
+        assert created[name][2] not in deleted_ids, f"{name} was given a deleted topic's id"
+        # Both straight away: neither may wait on the deletion's clean-up
+        request, corr_id = client.build_offset_fetch_request([("registry-group", [(name, [1])])])
+        fetched = parse_offset_fetch_response(client.send_request_and_get_response(request, corr_id), 8)
+        assert fetched["registry-group"][1][(name, 1)][0] == -1, f"{name} inherited an offset: {fetched}"
+        assert produce_one(client, name, 1, [b"after"]) == 0, f"{name} inherited its predecessor's log"
+    live = check_topic_registry(client, deleted_ids)
+    assert set(names) <= live.keys(), f"Recreated topics missing: {sorted(live)}"
+    
+    request, corr_id = client.build_delete_topics_request(names)
+    client.send_request_and_get_response(request, corr_id)
+    check_topic_registry(client, deleted_ids + [live[name] for name in names])
+    print("✅ Topic registry invariants test passed")
+
+def test_recreate_under_load():
+    print("🧪 Testing a topic deleted and recreated under produce, fetch and commit load...")
+    client = EventorTestClient()
+    topic = "reborn"
+    
+    def create():
+        request, corr_id = client.build_create_topics_request([(topic, 2, 1)])
+        created = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7)
+        assert created[topic][0] == 0, f"Creating {topic}: {created}"
+        return created[topic][2]
+    
+    generations = [create()]
+    failures = []
+    stop = threading.Event()
+    acknowledged = []
+    
+    def producer(partition):
+        own = EventorTestClient()
+        connection = EventorConnection(own)
+        try:
+            while not stop.is_set():
+                request, corr_id = own.build_produce_request([(topic, [(partition, build_record_batch([b"load"]))])])
+                connection.send(request, corr_id)
+                error_code, base_offset, _ = parse_produce_response(connection.read_response(), 9)[(topic, partition)]
+                assert error_code in (0, 3), f"Produce answered {error_code}"
+                if error_code == 0:
+                    acknowledged.append((partition, base_offset))
+        except Exception as e:
+            failures.append(f"producer {partition}: {e}")
+        finally:
+            connection.close()
+    
+    def consumer(by_id):
+        own = EventorTestClient()
+        try:
+            while not stop.is_set():
+                topic_id = generations[-1]
+                fetch_topic, api_version = (topic_id, 13) if by_id else (topic, 12)
+                request, corr_id = own.build_fetch_request([(fetch_topic, [(0, 0, 1 << 20), (1, 0, 1 << 20)])],
+                                                           api_version=api_version, max_wait_ms=200)
+                results = parse_fetch_response(own.send_request_and_get_response(request, corr_id), api_version)
+                for (_, partition), (error_code, high_watermark, records) in results.items():
+                    assert error_code in ((0, 100) if by_id else (0, 3)), f"Fetch answered {error_code}"
+                    for base_offset, _ in decode_record_batches(records):
+                        assert base_offset < high_watermark, f"Batch at {base_offset} past the high watermark"
+        except Exception as e:
+            failures.append(f"consumer (by {'id' if by_id else 'name'}): {e}")
+    
+    def committer():
+        own = EventorTestClient()
+        try:
+            while not stop.is_set():
+                request, corr_id = own.build_offset_commit_request("reborn-group", [(topic, [(0, 7, None)])])
+                results = parse_offset_commit_response(own.send_request_and_get_response(request, corr_id), 8)
+                assert set(results.values()) <= {0, 3}, f"Commit answered {results}"
+        except Exception as e:
+            failures.append(f"committer: {e}")
+    
+    threads = ([threading.Thread(target=producer, args=(0,)) for _ in range(2)] +
+               [threading.Thread(target=consumer, args=(by_id,)) for by_id in (False, True)] +
+               [threading.Thread(target=committer)])
+    for thread in threads:
+        thread.start()
+    try:
+        for _ in range(10):
+            time.sleep(0.05)
+            # Fetches parked on the partition nobody produces to, by name and by id
+            old_id = generations[-1]
+            parked = []
+            for fetch_topic, api_version in ((topic, 12), (old_id, 13)):
+                connection = EventorConnection(client)
+                request, corr_id = client.build_fetch_request([(fetch_topic, [(1, 0, 1 << 20)])],
+                                                              api_version=api_version, max_wait_ms=5000)
+                connection.send(request, corr_id)
+                parked.append((connection, api_version))
+            time.sleep(0.1)
+            request, corr_id = client.build_delete_topics_request([topic])
+            results = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6)
+            assert results == [(topic, old_id, 0)], f"Deleting generation {len(generations)}: {results}"
+            generations.append(create())
+            # The parked fetches belong to the deleted topic, whatever the new one gets
+            for connection, api_version in parked:
+                try:
+                    results = parse_fetch_response(connection.read_response(), api_version)
+                finally:
+                    connection.close()
+                assert [result[0] for result in results.values()] == [3 if api_version == 12 else 100], \
+                    f"Parked v{api_version} fetch across a recreate: {results}"
+    finally:
+        stop.set()
+        for thread in threads:
+            thread.join()
+    assert not failures, f"Recreating under load: {failures}"
+    assert acknowledged, "No produce was acknowledged"
+    assert len(set(generations)) == len(generations), "A recreated topic reused an id"
+    
+    # Nothing of the last generation predates it: its own offsets only, committed after it
+    request, corr_id = client.build_list_offsets_request([(topic, [(0, -1), (1, -1)])])
+    offsets = parse_list_offsets_response(client.send_request_and_get_response(request, corr_id), 7)
+    request, corr_id = client.build_fetch_request([(generations[-1], [(0, 0, 1 << 20), (1, 0, 1 << 20)])],
+                                                  api_version=13)
+    fetched = parse_fetch_response(client.send_request_and_get_response(request, corr_id), 13)
+    for partition in (0, 1):
+        error_code, high_watermark, records = fetched[(generations[-1], partition)]
+        assert error_code == 0 and high_watermark == offsets[(topic, partition)][2], \
+            f"Partition {partition}: fetch {fetched}, list offsets {offsets}"
+        # Contiguous from offset 0, up to as much as one fetch returns
+        next_offset = 0
+        for base_offset, values in decode_record_batches(records):
+            assert base_offset == next_offset, f"Partition {partition} batch at {base_offset}, expected {next_offset}"
+            next_offset += len(values)
+        assert next_offset <= high_watermark, f"Partition {partition} read {next_offset} of {high_watermark}"
+        assert (next_offset > 0) == (partition == 0), f"Partition {partition} read {next_offset} record(s)"
+    live = check_topic_registry(client, generations[:-1])
+    assert live[topic] == generations[-1], f"{topic} resolves to {live.get(topic)}"
+    request, corr_id = client.build_delete_topics_request([topic])
+    client.send_request_and_get_response(request, corr_id)
+    print("✅ Recreate under load test passed")
+
+def test_error_messages():
+    print("🧪 Testing error_message fields explain each failure...")
+    client = EventorTestClient()
+    
+    def assert_safe(message):
+        # Messages reach clients as is: no server paths in them
+        assert "/" not in message and "fixtures" not in message, f"Message leaks a path: {message!r}"
+    
+    for api_version in (2, 5, 7):
+        request, corr_id = client.build_create_topics_request(
+            [("messages-three-replicas", 1, 3), (f"messages-ok-v{api_version}", 1, 1)], api_version)
+        results = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), api_version)
+        error_code, message = results["messages-three-replicas"][:2]
+        assert error_code == 38, f"v{api_version}: {results}"
+        assert "single-broker" in message and "3" in message, f"v{api_version}: bare RF error {message!r}"
+        assert results[f"messages-ok-v{api_version}"][:2] == (0, None), f"v{api_version}: {results}"
+    
+    request, corr_id = client.build_create_topics_request(
+        [("messages-zero", 0, 1), ("messages/slash", 1, 1), ("messages-assigned", -1, -1, [(0, [7])], []),
+         ("bar", 1, 1)])
+    results = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7)
+    for name, (error_code, message, *_) in results.items():
+        assert error_code != 0 and message, f"{name}: error {error_code} without a message"
+        assert_safe(message)
+    
+    messages = {}
+    request, corr_id = client.build_produce_request(
+        [("no-such-topic", [(0, build_record_batch([b"lost"]))]), ("bar", [(0, b"not a batch")])])
+    parse_produce_response(client.send_request_and_get_response(request, corr_id), 9, messages)
+    # Unknown partitions can fill a response, so they carry just the code
+    assert messages[("no-such-topic", 0)] is None, f"Unknown topic message: {messages}"
+    assert messages[("bar", 0)], f"Corrupt batch without a message: {messages}"
+    assert_safe(messages[("bar", 0)])
+    request, corr_id = client.build_produce_request([("bar", [(0, build_record_batch([b"x"]))])], acks=5)
+    parse_produce_response(client.send_request_and_get_response(request, corr_id), 9, messages)
+    assert "acks" in messages[("bar", 0)], f"Invalid acks message: {messages}"
+    
+    messages = []
+    request, corr_id = client.build_delete_topics_request(["no-such-topic", bytes(range(16))], 6)
+    results = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6, messages)
+    assert [error_code for _, _, error_code in results] == [3, 100], f"Deletes: {results}"
+    assert all(message and "does not host" in message for message in messages), f"Delete messages: {messages}"
+    print("✅ Error message test passed")
+
+def parse_offset_commit_response(response, api_version, entries=None):
+    """Decodes an OffsetCommitResponse into {(topic, partition): error_code}. Given a list,
+    entries collects every partition's (topic, partition, error_code) in response order."""
+    flexible = api_version >= 8
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        # Every length in these responses fits in one varint byte
+        return read(">B") - 1 if flexible else read(">i")
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    if api_version >= 3:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = {}
+    for _ in range(read_len()):
+        length = read(">B") - 1 if flexible else read(">h")
+        name = response[offset:offset + length].decode("utf-8")
+        offset += length
+        for _ in range(read_len()):
+            partition, error_code = read(">ih")
+            read_tags()
+            results[(name, partition)] = error_code
+            if entries is not None:
+                entries.append((name, partition, error_code))
+        read_tags()
+    read_tags()
+    assert offset == len(response), f"OffsetCommit response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_offset_commit():
+    print("🧪 Testing OffsetCommit per-partition results...")
+    client = EventorTestClient()
+    
+    def commit(group_id, topics, api_version=8, **kwargs):
+        request, corr_id = client.build_offset_commit_request(group_id, topics, api_version, **kwargs)
+        return parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    for api_version in range(2, 9):
+        results = commit("commit-group", [("bar", [(0, 5, "meta"), (1, 7, None), (9, 1, None)]),
+                                          ("no-such-topic", [(0, 3, "")])],
+                         api_version, generation_id=3, member_id="member-1")
+        assert results == {("bar", 0): 0, ("bar", 1): 0, ("bar", 9): 3, ("no-such-topic", 0): 3}, \
+            f"v{api_version}: {results}"
+    
+    results = commit("commit-group", [("bar", [(0, 6, "m" * 5000), (1, 8, "m" * 4096)])])
+    assert results == {("bar", 0): 12, ("bar", 1): 0}, f"Oversized metadata: {results}"
+    results = commit("", [("bar", [(0, 1, None)])])
+    assert results == {("bar", 0): 24}, f"Empty group id: {results}"
+    
+    # Many groups committing at once from separate connections
+    failures = []
+    
+    def committer(group):
+        own = EventorTestClient()
+        for offset in range(20):
+            request, corr_id = own.build_offset_commit_request(group, [("bar", [(0, offset, None)]), ("baz", [(0, offset, None)])])
+            results = parse_offset_commit_response(own.send_request_and_get_response(request, corr_id), 8)
+            if set(results.values()) != {0}:
+                failures.append((group, results))
+    
+    threads = [threading.Thread(target=committer, args=(f"commit-group-{index}",)) for index in range(5)]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join()
+    assert not failures, f"Concurrent commits failed: {failures}"
+    print("✅ OffsetCommit test passed")
+
+def parse_offset_fetch_response(response, api_version):
+    """Decodes an OffsetFetchResponse into {group_id: (error_code, {(topic, partition):
+    (offset, leader_epoch, metadata, error_code)})}; before v8 the one group is under None.
+    leader_epoch is None before v5 and the group error_code None before v2."""
+    flexible = api_version >= 6
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        # Every length in these responses fits in one varint byte
+        return read(">B") - 1 if flexible else read(">i")
+    
+    def read_string():
+        nonlocal offset
+        length = read(">B") - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    def read_topics():
+        partitions = {}
+        for _ in range(read_len()):
+            name = read_string()
+            for _ in range(read_len()):
+                partition, committed_offset = read(">iq")
+                leader_epoch = read(">i") if api_version >= 5 else None
+                metadata = read_string()
+                error_code = read(">h")
+                read_tags()
+                partitions[(name, partition)] = (committed_offset, leader_epoch, metadata, error_code)
+            read_tags()
+        return partitions
+    
+    if api_version >= 3:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = {}
+    if api_version >= 8:
+        for _ in range(read_len()):
+            group_id = read_string()
+            partitions = read_topics()
+            results[group_id] = (read(">h"), partitions)
+            read_tags()
+    else:
+        partitions = read_topics()
+        results[None] = (read(">h") if api_version >= 2 else None, partitions)
+    read_tags()
+    assert offset == len(response), f"OffsetFetch response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_offset_fetch():
+    print("🧪 Testing OffsetFetch returns offsets committed on another connection...")
+    committer = EventorTestClient()
+    
+    def commit(group_id, topics):
+        request, corr_id = committer.build_offset_commit_request(group_id, topics)
+        results = parse_offset_commit_response(committer.send_request_and_get_response(request, corr_id), 8)
+        assert set(results.values()) == {0}, f"Commit for {group_id}: {results}"
+    
+    def fetch(groups, api_version=8):
+        # A fresh connection every time: commits must outlive the one that made them
+        client = EventorTestClient()
+        request, corr_id = client.build_offset_fetch_request(groups, api_version)
+        return parse_offset_fetch_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    commit("fetch-group", [("bar", [(0, 42, "checkpoint"), (1, 7, None)]), ("baz", [(0, 3, "")])])
+    commit("fetch-other", [("bar", [(0, 99, "other")])])
+    
+    for api_version in range(1, 9):
+        results = fetch([("fetch-group", [("bar", [0, 1, 9]), ("no-such-topic", [0])])], api_version)
+        group_error, partitions = results["fetch-group" if api_version >= 8 else None]
+        assert group_error == (0 if api_version >= 2 else None), f"v{api_version} group error: {results}"
+        epoch = lambda value: value if api_version >= 5 else None
+        assert partitions == {("bar", 0): (42, epoch(0), "checkpoint", 0), ("bar", 1): (7, epoch(0), None, 0),
+                              ("bar", 9): (-1, epoch(-1), "", 0), ("no-such-topic", 0): (-1, epoch(-1), "", 0)}, \
+            f"v{api_version}: {partitions}"
+    
+    # Null topics: everything the group committed, and only that group's
+    for api_version in (2, 6, 8):
+        group_error, partitions = fetch([("fetch-group", None)], api_version)["fetch-group" if api_version >= 8 else None]
+        assert (group_error, sorted(partitions)) == (0, [("bar", 0), ("bar", 1), ("baz", 0)]), \
+            f"v{api_version} all topics: {partitions}"
+        assert partitions[("bar", 0)][0] == 42, f"v{api_version}: another group's offset leaked in: {partitions}"
+    
+    results = fetch([("fetch-group", [("baz", [0])]), ("fetch-other", None), ("fetch-nobody", None), ("", None)])
+    assert results["fetch-group"] == (0, {("baz", 0): (3, 0, "", 0)}), f"Batched fetch-group: {results}"
+    assert results["fetch-other"] == (0, {("bar", 0): (99, 0, "other", 0)}), f"Batched fetch-other: {results}"
+    assert results["fetch-nobody"] == (0, {}), f"Group without commits: {results}"
+    assert results[""][0] == 24, f"Empty group id: {results}"
+    
+    # Offsets of a deleted topic go with it, and don't come back if it's recreated
+    request, corr_id = committer.build_create_topics_request([("fetch-doomed", 1, 1)])
+    parse_create_topics_response(committer.send_request_and_get_response(request, corr_id), 7)
+    commit("fetch-group", [("fetch-doomed", [(0, 5, None)])])
+    request, corr_id = committer.build_delete_topics_request(["fetch-doomed"])
+    parse_delete_topics_response(committer.send_request_and_get_response(request, corr_id), 6)
+    request, corr_id = committer.build_create_topics_request([("fetch-doomed", 1, 1)])
+    parse_create_topics_response(committer.send_request_and_get_response(request, corr_id), 7)
+    for _ in range(50):
+        _, partitions = fetch([("fetch-group", [("fetch-doomed", [0])])])["fetch-group"]
+        if partitions[("fetch-doomed", 0)][0] == -1:
+            break
+        time.sleep(0.02)
+    assert partitions[("fetch-doomed", 0)][0] == -1, f"Offset survived its topic: {partitions}"
+    print("✅ OffsetFetch test passed")
+
+def test_offset_consistency_under_concurrent_commits():
+    print("🧪 Testing OffsetFetch sees every answered commit, on any connection...")
+    slots = [("bar", 0), ("bar", 1), ("baz", 0)]
+    commits_per_slot = 150
+    failures = []
+    committing = threading.Event()
+    committing.set()
+    
+    def fetch_slots(client, connection, group):
+        request, corr_id = client.build_offset_fetch_request([(group, [("bar", [0, 1]), ("baz", [0])])])
+        connection.send(request, corr_id)
+        _, partitions = parse_offset_fetch_response(connection.read_response(), 8)[group]
+        for slot, (offset, _, metadata, _) in partitions.items():
+            # Offset and metadata come from the same commit, never two
+            if offset != -1 and metadata != f"at-{offset}":
+                failures.append((group, slot, "torn", offset, metadata))
+        return {slot: values[0] for slot, values in partitions.items()}
+    
+    def committer(group):
+        client = EventorTestClient()
+        commits, reads = EventorConnection(client), EventorConnection(client)
+        try:
+            for offset in range(commits_per_slot):
+                request, corr_id = client.build_offset_commit_request(
+                    group, [(topic, [(partition, offset, f"at-{offset}")]) for topic, partition in slots])
+                commits.send(request, corr_id)
+                results = parse_offset_commit_response(commits.read_response(), 8)
+                if set(results.values()) != {0}:
+                    failures.append((group, offset, "commit", results))
+                    return
+                # Once answered, the commit is visible on any other connection
+                seen = fetch_slots(client, reads, group)
+                if any(seen[slot] < offset for slot in slots):
+                    failures.append((group, offset, "stale read", seen))
+        finally:
+            commits.close()
+            reads.close()
+    
+    def fetcher(group):
+        client = EventorTestClient()
+        connection = EventorConnection(client)
+        last = {slot: -1 for slot in slots}
+        try:
+            while committing.is_set():
+                seen = fetch_slots(client, connection, group)
+                for slot in slots:
+                    if seen[slot] < last[slot]:
+                        failures.append((group, slot, "went back", last[slot], seen[slot]))
+                    last[slot] = max(last[slot], seen[slot])
+        finally:
+            connection.close()
+    
+    groups = [f"stress-group-{index}" for index in range(3)]
+    committers = [threading.Thread(target=committer, args=(group,)) for group in groups]
+    fetchers = [threading.Thread(target=fetcher, args=(group,)) for group in groups for _ in range(2)]
+    for thread in committers + fetchers:
+        thread.start()
+    for thread in committers:
+        thread.join()
+    committing.clear()
+    for thread in fetchers:
+        thread.join()
+    assert not failures, f"Inconsistent offsets: {failures[:5]}"
+    print("✅ Concurrent commit and fetch consistency test passed")
+
+def parse_join_group_response(response, api_version):
+    """Decodes a JoinGroupResponse into a dict of its fields; members are
+    [(member_id, group_instance_id, metadata)]."""
+    flexible = api_version >= 6
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        nonlocal offset
+        if not flexible:
+            return read(">i")
+        value, size = decode_uvarint_at(response, offset)
+        offset += size
+        return value - 1
+    
+    def read_string():
+        nonlocal offset
+        length = read_len() if flexible else read(">h")
+        if length < 0:
+            return None
+        value = response[offset:offset + length]
+        offset += length
+        return value.decode("utf-8")
+    
+    def read_bytes():
+        nonlocal offset
+        length = read_len()
+        value = response[offset:offset + length]
+        offset += length
+        return bytes(value)
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    assert read(">i") == 0, "Expected throttle_time_ms 0"
+    result = {"error_code": read(">h"), "generation_id": read(">i")}
+    result["protocol_type"] = read_string() if api_version >= 7 else None
+    result["protocol_name"] = read_string()
+    result["leader"] = read_string()
+    if api_version >= 9:
+        assert read(">B") == 0, "Expected skip_assignment false"
+    result["member_id"] = read_string()
+    members = []
+    for _ in range(read_len()):
+        member_id = read_string()
+        group_instance_id = read_string() if api_version >= 5 else None
+        members.append((member_id, group_instance_id, read_bytes()))
+        read_tags()
+    result["members"] = members
+    read_tags()
+    assert offset == len(response), f"JoinGroup response has {len(response) - offset} trailing bytes"
+    return result
+
+def test_duplicate_partitions():
+    print("🧪 Testing every duplicated topic and partition is answered...")
+    client = EventorTestClient()
+    request, corr_id = client.build_create_topics_request([("duplicates", 3, 1)])
+    created = parse_create_topics_response(client.send_request_and_get_response(request, corr_id), 7)
+    assert created["duplicates"][0] == 0, f"Create duplicates: {created}"
+    topic_id = created["duplicates"][2]
+    batch = build_record_batch([b"dup"])
+    
+    # Produce: every copy of a duplicated partition is refused and none appended; a
+    # topic listed again with other partitions is answered as listed
+    for api_version in (3, 9):
+        request, corr_id = client.build_produce_request([
+            ("duplicates", [(0, batch), (0, batch), (1, batch)]),
+            ("duplicates", [(2, batch), (0, batch)]),
+        ], api_version=api_version)
+        entries = []
+        parse_produce_response(client.send_request_and_get_response(request, corr_id), api_version, entries=entries)
+        assert entries == [("duplicates", 0, 42), ("duplicates", 0, 42), ("duplicates", 1, 0),
+                           ("duplicates", 2, 0), ("duplicates", 0, 42)], f"Produce v{api_version}: {entries}"
+    
+    # ListOffsets: every copy refused with INVALID_REQUEST, as Kafka does
+    for api_version in (1, 7):
+        request, corr_id = client.build_list_offsets_request([
+            ("duplicates", [(1, -1), (1, -2), (0, -1)]),
+            ("duplicates", [(2, -1)]),
+        ], api_version=api_version)
+        entries = []
+        results = parse_list_offsets_response(client.send_request_and_get_response(request, corr_id),
+                                              api_version, entries=entries)
+        assert [(partition, error_code) for _, partition, error_code in entries] == [(1, 42), (1, 42), (0, 0), (2, 0)], \
+            f"ListOffsets v{api_version}: {entries}"
+        assert results[("duplicates", 0)][2] == 0, f"Refused produces reached duplicates-0: {results}"
+        assert results[("duplicates", 2)][2] == 2, f"ListOffsets v{api_version}: {results}"
+    
+    # Fetch: every copy read on its own, from its own offset
+    for api_version in (4, 12, 13):
+        name = topic_id if api_version >= 13 else "duplicates"
+        request, corr_id = client.build_fetch_request([
+            (name, [(1, 0, 1 << 20), (1, 1, 1 << 20)]),
+            (name, [(1, 0, 1 << 20)]),
+        ], api_version=api_version)
+        entries = []
+        parse_fetch_response(client.send_request_and_get_response(request, corr_id), api_version, entries=entries)
+        assert [(partition, error_code) for _, partition, error_code, _ in entries] == [(1, 0)] * 3, \
+            f"Fetch v{api_version}: {entries}"
+        first_offsets = [decode_record_batches(records)[0][0] for _, _, _, records in entries]
+        assert first_offsets == [0, 1, 0], f"Fetch v{api_version} read from {first_offsets}"
+    
+    # OffsetCommit: every copy committed in request order, so the last one stands
+    for api_version in (2, 8):
+        request, corr_id = client.build_offset_commit_request("duplicates-group", [
+            ("duplicates", [(0, 5, None), (0, 9, None)]),
+            ("duplicates", [(1, 3, None)]),
+        ], api_version)
+        entries = []
+        parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), api_version, entries=entries)
+        assert entries == [("duplicates", 0, 0), ("duplicates", 0, 0), ("duplicates", 1, 0)], \
+            f"OffsetCommit v{api_version}: {entries}"
+        request, corr_id = client.build_offset_fetch_request([("duplicates-group", [("duplicates", [0, 1])])])
+        fetched = parse_offset_fetch_response(client.send_request_and_get_response(request, corr_id), 8)
+        committed = fetched["duplicates-group"][1]
+        assert (committed[("duplicates", 0)][0], committed[("duplicates", 1)][0]) == (9, 3), \
+            f"Committed after OffsetCommit v{api_version}: {committed}"
+    
+    request, corr_id = client.build_delete_topics_request(["duplicates"])
+    deleted = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6)
+    assert deleted[0][2] == 0, f"Delete duplicates: {deleted}"
+    print("✅ Duplicate partitions test passed")
+
+def test_join_group():
+    print("🧪 Testing JoinGroup membership and generations...")
+    client = EventorTestClient()
+    
+    def join(group_id, api_version=9, own_client=None, **kwargs):
+        sender = own_client or client
+        request, corr_id = sender.build_join_group_request(group_id, api_version=api_version, **kwargs)
+        return parse_join_group_response(sender.send_request_and_get_response(request, corr_id), api_version)
+    
+    def join_two_step(group_id, api_version=9, own_client=None, **kwargs):
+        first = join(group_id, api_version, own_client, **kwargs)
+        assert first["error_code"] == 79, f"v{api_version}: expected MEMBER_ID_REQUIRED, got {first}"
+        assert first["member_id"].startswith("test-client-"), f"Assigned member id: {first}"
+        assert first["generation_id"] == -1 and first["members"] == [], f"MEMBER_ID_REQUIRED: {first}"
+        return join(group_id, api_version, own_client, member_id=first["member_id"], **kwargs)
+    
+    # Every version: a lone member leads generation 1 and sees its own metadata
+    for api_version in range(2, 10):
+        group_id = f"join-v{api_version}"
+        if api_version >= 4:
+            result = join_two_step(group_id, api_version)
+        else:
+            result = join(group_id, api_version)
+        assert result["error_code"] == 0 and result["generation_id"] == 1, f"v{api_version}: {result}"
+        assert result["leader"] == result["member_id"], f"v{api_version}: leader {result}"
+        assert result["protocol_name"] == "range", f"v{api_version}: {result}"
+        assert result["protocol_type"] == ("consumer" if api_version >= 7 else None), f"v{api_version}: {result}"
+        assert result["members"] == [(result["member_id"], None, b"meta")], f"v{api_version}: {result}"
+        
+        # Rejoining with the same id bumps the generation without a second member
+        again = join(group_id, api_version, member_id=result["member_id"])
+        assert again["generation_id"] == 2 and len(again["members"]) == 1, f"v{api_version} rejoin: {again}"
+    
+    # Ids the group never handed out, and joins that can't be accepted
+    assert join("join-v9", member_id="made-up")["error_code"] == 25, "Unknown member id"
+    assert join("", member_id="")["error_code"] == 24, "Empty group id"
+    assert join("join-bad", session_timeout_ms=100)["error_code"] == 26, "Session timeout too short"
+    assert join("join-bad", protocols=())["error_code"] == 23, "No protocols"
+    assert join("join-bad", protocols=(("range", b"m" * 70000),))["error_code"] == 10, "Oversized metadata"
+    
+    # Two members on their own connections end up in the same generation, and
+    # only the leader (the first to join) sees everyone's metadata
+    first = join_two_step("join-pair", protocols=(("range", b"first"), ("roundrobin", b"first-rr")))
+    assert first["generation_id"] == 1, f"First member: {first}"
+    second_result = {}
+    
+    def second_member():
+        second_result.update(join_two_step("join-pair", own_client=EventorTestClient(),
+                                           protocols=(("roundrobin", b"second-rr"), ("range", b"second"))))
+    
+    second = threading.Thread(target=second_member)
+    second.start()
+    time.sleep(0.5)
+    assert second.is_alive(), f"Second member answered before the first rejoined: {second_result}"
+    rejoined = join("join-pair", member_id=first["member_id"],
+                    protocols=(("range", b"first"), ("roundrobin", b"first-rr")))
+    second.join(timeout=10)
+    assert not second.is_alive(), "Second member still waiting after the first rejoined"
+    assert rejoined["generation_id"] == 2 and second_result["generation_id"] == 2, f"{rejoined} / {second_result}"
+    assert rejoined["leader"] == first["member_id"] == second_result["leader"], f"{rejoined} / {second_result}"
+    # One vote each, so the leader's preference wins
+    assert rejoined["protocol_name"] == second_result["protocol_name"] == "range", f"{rejoined} / {second_result}"
+    assert rejoined["members"] == [(first["member_id"], None, b"first"),
+                                   (second_result["member_id"], None, b"second")], f"Leader: {rejoined}"
+    assert second_result["members"] == [], f"Follower: {second_result}"
+    
+    assert join("join-pair", protocol_type="connect")["error_code"] == 23, "Mismatched protocol type"
+    assert join("join-pair", protocols=(("sticky", b""),))["error_code"] == 23, "No common protocol"
+    
+    # A member that doesn't rejoin within the rebalance timeout is dropped
+    stale = join_two_step("join-straggler", rebalance_timeout_ms=1000)
+    started = time.time()
+    newcomer = join_two_step("join-straggler", rebalance_timeout_ms=1000)
+    waited = time.time() - started
+    assert 0.8 < waited < 5, f"Round closed after {waited:.2f}s"
+    assert newcomer["generation_id"] == 2 and newcomer["leader"] == newcomer["member_id"], f"Newcomer: {newcomer}"
+    assert [member[0] for member in newcomer["members"]] == [newcomer["member_id"]], f"Newcomer: {newcomer}"
+    assert join("join-straggler", member_id=stale["member_id"])["error_code"] == 25, "Dropped member rejoining"
+    print("✅ JoinGroup test passed")
+
+def parse_sync_group_response(response, api_version):
+    """Decodes a SyncGroupResponse into (error_code, protocol_type, protocol_name,
+    assignment); the protocol fields are None before v5."""
+    flexible = api_version >= 4
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_compact_string():
+        nonlocal offset
+        length = decode_uvarint_at(response, offset)
+        offset += length[1]
+        if length[0] == 0:
+            return None
+        value = response[offset:offset + length[0] - 1]
+        offset += length[0] - 1
+        return value.decode("utf-8")
+    
+    if api_version >= 1:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    error_code = read(">h")
+    protocol_type = protocol_name = None
+    if api_version >= 5:
+        protocol_type = read_compact_string()
+        protocol_name = read_compact_string()
+    if flexible:
+        length, size = decode_uvarint_at(response, offset)
+        offset += size
+        length -= 1
+    else:
+        length = read(">i")
+    assignment = bytes(response[offset:offset + length])
+    offset += length
+    if flexible:
+        assert read(">B") == 0, "Expected an empty tag buffer"
+    assert offset == len(response), f"SyncGroup response has {len(response) - offset} trailing bytes"
+    return error_code, protocol_type, protocol_name, assignment
+
+def decode_uvarint_at(data, offset):
+    """(value, size) of the unsigned varint at offset."""
+    value = shift = size = 0
+    while True:
+        byte = data[offset + size]
+        size += 1
+        value |= (byte & 0x7F) << shift
+        if not byte & 0x80:
+            return value, size
+        shift += 7
+
+def run_group_round(group_id, members, assignments, client_factory=EventorTestClient, **join_kwargs):
+    """Rejoins every member id in members concurrently, then syncs them all, the
+    leader with assignments {member_id: bytes}. Like a real client, a member told
+    a rebalance is in progress joins again. Returns {member_id: (join result,
+    sync result)}."""
+    results = {}
+    
+    def member(member_id):
+        client = client_factory()
+        for _ in range(5):
+            request, corr_id = client.build_join_group_request(group_id, member_id=member_id, **join_kwargs)
+            joined = parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)
+            leader = joined["leader"] == member_id
+            request, corr_id = client.build_sync_group_request(
+                group_id, joined["generation_id"], member_id,
+                list(assignments.items()) if leader else ())
+            synced = parse_sync_group_response(client.send_request_and_get_response(request, corr_id), 5)
+            results[member_id] = (joined, synced)
+            if synced[0] != 27:
+                break
+    
+    threads = [threading.Thread(target=member, args=(member_id,)) for member_id in members]
+    for thread in threads:
+        thread.start()
+    for thread in threads:
+        thread.join(timeout=15)
+    return results
+
+def test_sync_group():
+    print("🧪 Testing SyncGroup assignment distribution...")
+    client = EventorTestClient()
+    
+    def join(group_id, member_id="", **kwargs):
+        request, corr_id = client.build_join_group_request(group_id, member_id=member_id, **kwargs)
+        return parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)
+    
+    def join_new(group_id, **kwargs):
+        return join(group_id, join(group_id, **kwargs)["member_id"], **kwargs)
+    
+    def sync(group_id, generation_id, member_id, assignments=(), api_version=5, own_client=None, **kwargs):
+        sender = own_client or client
+        request, corr_id = sender.build_sync_group_request(group_id, generation_id, member_id, assignments,
+                                                           api_version, **kwargs)
+        return parse_sync_group_response(sender.send_request_and_get_response(request, corr_id), api_version)
+    
+    def commit(group_id, generation_id, member_id):
+        request, corr_id = client.build_offset_commit_request(group_id, [("bar", [(0, 1, None)])],
+                                                              generation_id=generation_id, member_id=member_id)
+        return parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), 8)[("bar", 0)]
+    
+    # A lone leader gets back what it assigned itself, in every version
+    for api_version in range(0, 6):
+        group_id = f"sync-v{api_version}"
+        joined = join_new(group_id)
+        member_id = joined["member_id"]
+        result = sync(group_id, 1, member_id, [(member_id, b"assignment"), ("gone", b"x")], api_version)
+        expected_protocol = ("consumer", "range") if api_version >= 5 else (None, None)
+        assert result == (0, *expected_protocol, b"assignment"), f"v{api_version}: {result}"
+        # Syncing again returns the stored assignment rather than replacing it
+        assert sync(group_id, 1, member_id, [(member_id, b"other")], api_version)[3] == b"assignment", \
+            f"v{api_version}: resync"
+    
+    # A follower syncing before its leader waits for the leader's assignments
+    leader = join_new("sync-pair", rebalance_timeout_ms=2000)["member_id"]
+    follower_id = join("sync-pair", rebalance_timeout_ms=2000)["member_id"]
+    rounds = run_group_round("sync-pair", [leader], {leader: b"solo"}, rebalance_timeout_ms=2000)
+    assert rounds[leader][1] == (0, "consumer", "range", b"solo"), f"Leader alone: {rounds}"
+    joined = {}
+    
+    def follower_join():
+        own = EventorTestClient()
+        request, corr_id = own.build_join_group_request("sync-pair", member_id=follower_id, rebalance_timeout_ms=2000)
+        joined.update(parse_join_group_response(own.send_request_and_get_response(request, corr_id), 9))
+    
+    thread = threading.Thread(target=follower_join)
+    thread.start()
+    time.sleep(0.3)
+    rejoined = join("sync-pair", leader, rebalance_timeout_ms=2000)
+    thread.join(timeout=10)
+    generation = rejoined["generation_id"]
+    assert generation == joined["generation_id"] == 3, f"{rejoined} / {joined}"
+    
+    followed = []
+    follower = threading.Thread(target=lambda: followed.append(
+        sync("sync-pair", generation, follower_id, own_client=EventorTestClient())))
+    follower.start()
+    time.sleep(0.3)
+    assert follower.is_alive(), f"Follower answered before the leader synced: {followed}"
+    result = sync("sync-pair", generation, leader, [(leader, b"for-leader"), (follower_id, b"for-follower")])
+    follower.join(timeout=10)
+    assert result[3] == b"for-leader" and followed and followed[0][3] == b"for-follower", f"{result} / {followed}"
+    
+    # Stale generations, unknown members and mismatched protocols
+    assert sync("sync-pair", generation - 1, leader)[0] == 22, "Stale generation"
+    assert sync("sync-pair", generation, "made-up")[0] == 25, "Unknown member"
+    assert sync("sync-nobody", 1, "made-up")[0] == 25, "Unknown group"
+    assert sync("sync-pair", generation, leader, protocol_name="roundrobin")[0] == 23, "Mismatched protocol"
+    
+    # OffsetCommit only from current members of a joined group
+    assert commit("sync-pair", generation, leader) == 0, "Commit from the current generation"
+    assert commit("sync-pair", generation - 1, leader) == 22, "Commit from a stale generation"
+    assert commit("sync-pair", generation, "made-up") == 25, "Commit from an unknown member"
+    assert commit("sync-unjoined", -1, "") == 0, "Commit to a group nobody joined"
+    
+    # A new member joining opens a round: syncs for the old generation are told to rejoin
+    newcomer = threading.Thread(target=lambda: join_new("sync-pair", rebalance_timeout_ms=2000))
+    newcomer.start()
+    time.sleep(0.3)
+    assert sync("sync-pair", generation, leader)[0] == 27, "Sync during a rebalance"
+    assert commit("sync-pair", generation, leader) == 27, "Commit during a rebalance"
+    newcomer.join(timeout=10)
+    print("✅ SyncGroup test passed")
+
+def parse_heartbeat_response(response, api_version):
+    """The error_code of a HeartbeatResponse, checking the rest of its layout."""
+    flexible = api_version >= 4
+    offset = 9 if flexible else 8
+    if api_version >= 1:
+        assert struct.unpack_from(">i", response, offset)[0] == 0, "Expected throttle_time_ms 0"
+        offset += 4
+    error_code = struct.unpack_from(">h", response, offset)[0]
+    offset += 2
+    if flexible:
+        assert response[offset] == 0, "Expected an empty tag buffer"
+        offset += 1
+    assert offset == len(response), f"Heartbeat response has {len(response) - offset} trailing bytes"
+    return error_code
+
+def test_heartbeat():
+    print("🧪 Testing Heartbeat and session expiry...")
+    client = EventorTestClient()
+    group_kwargs = {"session_timeout_ms": 1000, "rebalance_timeout_ms": 3000}
+    
+    def heartbeat(group_id, generation_id, member_id, api_version=4, own_client=None):
+        sender = own_client or client
+        request, corr_id = sender.build_heartbeat_request(group_id, generation_id, member_id, api_version)
+        return parse_heartbeat_response(sender.send_request_and_get_response(request, corr_id), api_version)
+    
+    def new_member_id(group_id):
+        request, corr_id = client.build_join_group_request(group_id, **group_kwargs)
+        joined = parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)
+        assert joined["error_code"] == 79, f"First join: {joined}"
+        return joined["member_id"]
+    
+    first, second = new_member_id("hb-group"), new_member_id("hb-group")
+    rounds = run_group_round("hb-group", [first, second], {first: b"a", second: b"b"}, **group_kwargs)
+    generation = rounds[first][0]["generation_id"]
+    assert all(synced[0] == 0 for _, synced in rounds.values()), f"Round: {rounds}"
+    assert rounds[second][0]["generation_id"] == generation, f"Round: {rounds}"
+    
+    # Current members at the current generation are fine, in every version
+    for api_version in range(0, 5):
+        assert heartbeat("hb-group", generation, first, api_version) == 0, f"v{api_version}"
+    assert heartbeat("hb-group", generation - 1, first) == 22, "Stale generation"
+    assert heartbeat("hb-group", generation, "made-up") == 25, "Unknown member"
+    assert heartbeat("hb-nobody", 1, "made-up") == 25, "Unknown group"
+    
+    # The first member goes quiet; once its session lapses the second is told to rejoin
+    started = time.time()
+    while heartbeat("hb-group", generation, second) == 0:
+        assert time.time() - started < 5, "The silent member was never expired"
+        time.sleep(0.2)
+    waited = time.time() - started
+    assert 0.8 < waited < 3, f"Rebalance began {waited:.2f}s after the last heartbeat"
+    assert heartbeat("hb-group", generation, second) == 27, "Heartbeat during the rebalance"
+    assert heartbeat("hb-group", generation, first) == 25, "Expired member"
+    
+    # The survivor rejoins and has the next generation to itself
+    request, corr_id = client.build_join_group_request("hb-group", member_id=second, **group_kwargs)
+    rejoined = parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)
+    assert rejoined["error_code"] == 0 and rejoined["generation_id"] == generation + 1, f"Rejoin: {rejoined}"
+    assert rejoined["leader"] == second and [member[0] for member in rejoined["members"]] == [second], \
+        f"Rejoin: {rejoined}"
+    assert heartbeat("hb-group", generation + 1, first) == 25, "Expired member after the rebalance"
+    print("✅ Heartbeat test passed")
+
+def parse_leave_group_response(response, api_version):
+    """Decodes a LeaveGroupResponse into (error_code, [(member_id, group_instance_id,
+    error_code)]); the members are None before v3."""
+    flexible = api_version >= 4
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        nonlocal offset
+        if flexible:
+            length, size = decode_uvarint_at(response, offset)
+            offset += size
+            length -= 1
+        else:
+            length = read(">h")
+        if length < 0:
+            return None
+        value = response[offset:offset + length]
+        offset += length
+        return value.decode("utf-8")
+    
+    if api_version >= 1:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    error_code = read(">h")
+    members = None
+    if api_version >= 3:
+        if flexible:
+            count, size = decode_uvarint_at(response, offset)
+            offset += size
+            count -= 1
+        else:
+            count = read(">i")
+        members = []
+        for _ in range(count):
+            member_id = read_string()
+            group_instance_id = read_string()
+            members.append((member_id, group_instance_id, read(">h")))
+            if flexible:
+                assert read(">B") == 0, "Expected an empty member tag buffer"
+    if flexible:
+        assert read(">B") == 0, "Expected an empty tag buffer"
+    assert offset == len(response), f"LeaveGroup response has {len(response) - offset} trailing bytes"
+    return error_code, members
+
+def parse_list_groups_response(response, api_version):
+    """Decodes a ListGroupsResponse into (error_code, [(group_id, protocol_type, state)]);
+    state is None before v4."""
+    flexible = api_version >= 3
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len():
+        nonlocal offset
+        if not flexible:
+            return read(">i")
+        length, size = decode_uvarint_at(response, offset)
+        offset += size
+        return length - 1
+    
+    def read_string():
+        nonlocal offset
+        length = read_len() if flexible else read(">h")
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    if api_version >= 1:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    error_code = read(">h")
+    groups = []
+    for _ in range(read_len()):
+        group_id = read_string()
+        protocol_type = read_string()
+        state = read_string() if api_version >= 4 else None
+        groups.append((group_id, protocol_type, state))
+        if flexible:
+            assert read(">B") == 0, "Expected an empty group tag buffer"
+    if flexible:
+        assert read(">B") == 0, "Expected an empty tag buffer"
+    assert offset == len(response), f"ListGroups response has {len(response) - offset} trailing bytes"
+    return error_code, groups
+
+def parse_describe_groups_response(response, api_version):
+    """Decodes a DescribeGroupsResponse into {group_id: (error_code, state, protocol_type,
+    protocol_data, members, authorized_operations)} with members [(member_id,
+    group_instance_id, client_id, client_host, metadata, assignment)]; group_instance_id
+    is None before v4 and authorized_operations None before v3."""
+    flexible = api_version >= 5
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_len(classic_fmt):
+        nonlocal offset
+        if not flexible:
+            return read(classic_fmt)
+        length, size = decode_uvarint_at(response, offset)
+        offset += size
+        return length - 1
+    
+    def read_blob(classic_fmt):
+        nonlocal offset
+        length = read_len(classic_fmt)
+        if length < 0:
+            return None
+        value = response[offset:offset + length]
+        offset += length
+        return value
+    
+    def read_string():
+        value = read_blob(">h")
+        return None if value is None else value.decode("utf-8")
+    
+    if api_version >= 1:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    groups = {}
+    for _ in range(read_len(">i")):
+        error_code = read(">h")
+        group_id = read_string()
+        state, protocol_type, protocol_data = read_string(), read_string(), read_string()
+        members = []
+        for _ in range(read_len(">i")):
+            member_id = read_string()
+            group_instance_id = read_string() if api_version >= 4 else None
+            client_id, client_host = read_string(), read_string()
+            members.append((member_id, group_instance_id, client_id, client_host,
+                            read_blob(">i"), read_blob(">i")))
+            if flexible:
+                assert read(">B") == 0, "Expected an empty member tag buffer"
+        authorized_operations = read(">i") if api_version >= 3 else None
+        if flexible:
+            assert read(">B") == 0, "Expected an empty group tag buffer"
+        groups[group_id] = (error_code, state, protocol_type, protocol_data, members, authorized_operations)
+    if flexible:
+        assert read(">B") == 0, "Expected an empty tag buffer"
+    assert offset == len(response), f"DescribeGroups response has {len(response) - offset} trailing bytes"
+    return groups
+
+def test_leave_group():
+    print("🧪 Testing LeaveGroup on clean consumer shutdown...")
+    client = EventorTestClient()
+    group_kwargs = {"session_timeout_ms": 10000, "rebalance_timeout_ms": 3000}
+    
+    def leave(group_id, members, api_version=5):
+        request, corr_id = client.build_leave_group_request(group_id, members, api_version)
+        return parse_leave_group_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    def heartbeat(group_id, generation_id, member_id):
+        request, corr_id = client.build_heartbeat_request(group_id, generation_id, member_id)
+        return parse_heartbeat_response(client.send_request_and_get_response(request, corr_id), 4)
+    
+    def new_member_id(group_id):
+        request, corr_id = client.build_join_group_request(group_id, **group_kwargs)
+        return parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)["member_id"]
+    
+    # Every version's layout, naming members of a group nobody has joined
+    for api_version in range(0, 6):
+        expected = (25, None) if api_version < 3 else (0, [("nobody", None, 25), ("", "static-1", 25)])
+        members = [("nobody", None), ("", "static-1")][:1 if api_version < 3 else 2]
+        assert leave("leave-nobody", members, api_version) == expected, f"v{api_version}"
+    assert leave("", [("nobody", None)]) == (24, []), "Empty group id"
+    
+    first, second = new_member_id("leave-group"), new_member_id("leave-group")
+    rounds = run_group_round("leave-group", [first, second], {first: b"a", second: b"b"}, **group_kwargs)
+    generation = rounds[first][0]["generation_id"]
+    assert all(synced[0] == 0 for _, synced in rounds.values()), f"Round: {rounds}"
+    request, corr_id = client.build_offset_commit_request("leave-group", [("bar", [(0, 7, None)])],
+                                                          generation_id=generation, member_id=first)
+    assert parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), 8) == \
+        {("bar", 0): 0}, "Commit before leaving"
+    
+    # One member leaves: only the unknown entry fails, and the other is told to rejoin at once
+    error_code, members = leave("leave-group", [(first, None), ("made-up", None)])
+    assert (error_code, members) == (0, [(first, None, 0), ("made-up", None, 25)]), f"Leave: {members}"
+    assert heartbeat("leave-group", generation, second) == 27, "Heartbeat after a member left"
+    assert heartbeat("leave-group", generation, first) == 25, "Heartbeat from the member that left"
+    request, corr_id = client.build_join_group_request("leave-group", member_id=second, **group_kwargs)
+    rejoined = parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)
+    assert rejoined["generation_id"] == generation + 1 and rejoined["leader"] == second, f"Rejoin: {rejoined}"
+    assert [member[0] for member in rejoined["members"]] == [second], f"Rejoin: {rejoined}"
+    
+    # The last member leaving empties the group but keeps its offsets
+    assert leave("leave-group", [(second, None)], api_version=0) == (0, None), "Last member leaving"
+    assert heartbeat("leave-group", generation + 1, second) == 25, "Heartbeat after the group emptied"
+    request, corr_id = client.build_offset_fetch_request([("leave-group", [("bar", [0])])])
+    fetched = parse_offset_fetch_response(client.send_request_and_get_response(request, corr_id), 8)
+    assert fetched["leave-group"][1][("bar", 0)][0] == 7, f"Offsets after the group emptied: {fetched}"
+    print("✅ LeaveGroup test passed")
+
+def test_group_offset_reset():
+    print("🧪 Testing a group offset reset through ListOffsets and admin commits...")
+    client = EventorTestClient()
+    group_kwargs = {"session_timeout_ms": 10000, "rebalance_timeout_ms": 3000}
+    base = 1_700_000_000_000
+    
+    def exchange(request, corr_id):
+        return client.send_request_and_get_response(request, corr_id)
+    
+    def list_offsets(timestamp):
+        results = parse_list_offsets_response(
+            exchange(*client.build_list_offsets_request([("reset", [(0, timestamp), (1, timestamp)])])), 7)
+        assert all(result[0] == 0 for result in results.values()), f"ListOffsets {timestamp}: {results}"
+        return {partition: result[2] for (_, partition), result in results.items()}
+    
+    def commit(group_id, offsets, **kwargs):
+        request, corr_id = client.build_offset_commit_request(
+            group_id, [("reset", [(partition, offset, None) for partition, offset in sorted(offsets.items())])],
+            **kwargs)
+        return set(parse_offset_commit_response(exchange(request, corr_id), 8).values())
+    
+    def committed(group_id):
+        request, corr_id = client.build_offset_fetch_request([(group_id, [("reset", [0, 1])])])
+        _, partitions = parse_offset_fetch_response(exchange(request, corr_id), 8)[group_id]
+        return {partition: fetched[0] for (_, partition), fetched in partitions.items()}
+    
+    def new_member_id(group_id):
+        request, corr_id = client.build_join_group_request(group_id, **group_kwargs)
+        return parse_join_group_response(exchange(request, corr_id), 9)["member_id"]
+    
+    exchange(*client.build_create_topics_request([("reset", 2, 1)]))
+    for partition, count in ((0, 3), (1, 2)):
+        for index in range(count):
+            request, corr_id = client.build_produce_request([("reset", [(partition, build_record_batch(
+                [b"r%d" % index], timestamps=[base + 1000 * (index + 1)]))])])
+            assert parse_produce_response(exchange(request, corr_id), 9)[("reset", partition)][0] == 0
+    
+    # While a member holds the group an admin reset is refused, leaving its offsets alone
+    member = new_member_id("reset-group")
+    rounds = run_group_round("reset-group", [member], {member: b""}, **group_kwargs)
+    generation = rounds[member][0]["generation_id"]
+    assert commit("reset-group", {0: 3, 1: 2}, generation_id=generation, member_id=member) == {0}
+    assert commit("reset-group", {0: 0, 1: 0}) == {25}, "Reset while the group has a member"
+    assert committed("reset-group") == {0: 3, 1: 2}, "A refused reset moved the offsets"
+    # So is one while the first members are still joining
+    new_member_id("reset-joining")
+    assert commit("reset-joining", {0: 0, 1: 0}) == {25}, "Reset while members are joining"
+    
+    # Once the group is Empty: to earliest, which DeleteRecords has moved on partition 0
+    request, corr_id = client.build_leave_group_request("reset-group", [(member, None)])
+    assert parse_leave_group_response(exchange(request, corr_id), 5)[0] == 0
+    exchange(*client.build_delete_records_request([("reset", [(0, 1)])]))
+    earliest = list_offsets(-2)
+    assert earliest == {0: 1, 1: 0}, f"Earliest: {earliest}"
+    assert commit("reset-group", earliest) == {0}, "Reset to earliest"
+    assert committed("reset-group") == {0: 1, 1: 0}, f"After resetting to earliest: {committed('reset-group')}"
+    
+    # To a datetime: the first offset at or after it, or latest where nothing is that late
+    at = list_offsets(base + 2500)
+    assert at == {0: 2, 1: -1}, f"Offsets for the datetime: {at}"
+    latest = list_offsets(-1)
+    targets = {partition: offset if offset >= 0 else latest[partition] for partition, offset in at.items()}
+    assert commit("reset-group", targets) == {0}, "Reset to a datetime"
+    assert committed("reset-group") == {0: 2, 1: 2}, f"After resetting to a datetime: {committed('reset-group')}"
+    print("✅ Group offset reset test passed")
+
+def test_list_and_describe_groups():
+    print("🧪 Testing ListGroups and DescribeGroups group introspection...")
+    client = EventorTestClient()
+    group_kwargs = {"session_timeout_ms": 10000, "rebalance_timeout_ms": 3000}
+    
+    def list_groups(api_version=4, states_filter=()):
+        request, corr_id = client.build_list_groups_request(api_version, states_filter)
+        error_code, groups = parse_list_groups_response(client.send_request_and_get_response(request, corr_id), api_version)
+        assert error_code == 0, f"ListGroups v{api_version} failed with {error_code}"
+        return {group_id: (protocol_type, state) for group_id, protocol_type, state in groups
+                if group_id.startswith("introspect-")}
+    
+    def describe(group_ids, api_version=5, **kwargs):
+        request, corr_id = client.build_describe_groups_request(group_ids, api_version, **kwargs)
+        return parse_describe_groups_response(client.send_request_and_get_response(request, corr_id), api_version)
+    
+    def new_member_id(group_id):
+        request, corr_id = client.build_join_group_request(group_id, **group_kwargs)
+        return parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)["member_id"]
+    
+    # A group with only committed offsets is Empty; one nobody has heard of is Dead, not an error
+    request, corr_id = client.build_offset_commit_request("introspect-offsets", [("bar", [(0, 1, None)])])
+    assert parse_offset_commit_response(client.send_request_and_get_response(request, corr_id), 8) == {("bar", 0): 0}
+    described = describe(["introspect-offsets", "introspect-nobody", ""])
+    assert described == {
+        "introspect-offsets": (0, "Empty", "", "", [], -2147483648),
+        "introspect-nobody": (0, "Dead", "", "", [], -2147483648),
+        "": (24, "Dead", "", "", [], -2147483648),
+    }, f"Groups without members: {described}"
+    
+    # A Stable group: every member with its client, metadata for the chosen protocol and assignment
+    first, second = new_member_id("introspect-group"), new_member_id("introspect-group")
+    assignments = {first: b"assigned-first", second: b"assigned-second"}
+    rounds = run_group_round("introspect-group", [first, second], assignments,
+                             protocols=(("range", b"range-meta"), ("roundrobin", b"rr-meta")), **group_kwargs)
+    assert all(synced[0] == 0 for _, synced in rounds.values()), f"Round: {rounds}"
+    for api_version in range(0, 6):
+        error_code, state, protocol_type, protocol_data, members, operations = \
+            describe(["introspect-group"], api_version, include_authorized_operations=True)["introspect-group"]
+        context = f"DescribeGroups v{api_version}"
+        assert (error_code, state, protocol_type, protocol_data) == (0, "Stable", "consumer", "range"), \
+            f"{context}: {(error_code, state, protocol_type, protocol_data)}"
+        assert members == [(member_id, None, "test-client", "/127.0.0.1", b"range-meta", assignments[member_id])
+                           for member_id in (first, second)], f"{context}: members {members}"
+        assert operations == (328 if api_version >= 3 else None), f"{context}: authorized operations {operations}"
+    
+    for api_version in range(0, 5):
+        listed = list_groups(api_version)
+        assert listed == {"introspect-offsets": ("", "Empty" if api_version >= 4 else None),
+                          "introspect-group": ("consumer", "Stable" if api_version >= 4 else None)}, \
+            f"ListGroups v{api_version}: {listed}"
+    assert list_groups(states_filter=["stable"]) == {"introspect-group": ("consumer", "Stable")}, "Stable filter"
+    assert list_groups(states_filter=["Empty", "Dead"]) == {"introspect-offsets": ("", "Empty")}, "Empty filter"
+    assert list_groups(states_filter=["PreparingRebalance"]) == {}, "PreparingRebalance filter"
+    
+    # A joiner opens a round: the group rebalances and stops showing metadata and assignments
+    third = new_member_id("introspect-group")
+    
+    def join_third():
+        own = EventorTestClient()
+        own.send_request_and_get_response(*own.build_join_group_request("introspect-group", member_id=third,
+                                                                        **group_kwargs))
+    
+    joiner = threading.Thread(target=join_third)
+    joiner.start()
+    deadline = time.time() + 2
+    while describe(["introspect-group"])["introspect-group"][1] != "PreparingRebalance":
+        assert time.time() < deadline, "The group never started rebalancing"
+        time.sleep(0.05)
+    _, _, _, protocol_data, members, _ = describe(["introspect-group"])["introspect-group"]
+    assert protocol_data == "" and all(member[4:] == (b"", b"") for member in members), \
+        f"Rebalancing group: {protocol_data} {members}"
+    assert list_groups(states_filter=["PreparingRebalance"]) == \
+        {"introspect-group": ("consumer", "PreparingRebalance")}, "PreparingRebalance filter while rebalancing"
+    
+    # The others never rejoin, so the round closes with the joiner alone, waiting for its leader's sync
+    joiner.join(timeout=10)
+    _, state, _, _, members, _ = describe(["introspect-group"])["introspect-group"]
+    assert state == "CompletingRebalance" and [member[0] for member in members] == [third], \
+        f"After the round: {state} {members}"
+    request, corr_id = client.build_leave_group_request("introspect-group", [(third, None)])
+    assert parse_leave_group_response(client.send_request_and_get_response(request, corr_id), 5)[0] == 0
+    assert describe(["introspect-group"])["introspect-group"][1] == "Dead", "After the last member left"
+    print("✅ ListGroups and DescribeGroups test passed")
+
+def test_bench_smoke():
+    print("🧪 Testing the bench subcommand's smoke workload...")
+    workdir = tempfile.mkdtemp(prefix="eventor-bench-")
+    report_path = os.path.join(workdir, "report.json")
+    
+    def bench(extra):
+        workload = os.path.join(workdir, "workload.properties")
+        with open("fixtures/bench-smoke.properties") as f:
+            base = f.read()
+        with open(workload, "w") as f:
+            f.write(base + f"report.json={report_path}\nduration.ms=1000\n" + extra)
+        return subprocess.run([EVENTOR_BINARY, "bench", workload, "fixtures/server.properties"],
+                              stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True, timeout=30)
+    
+    result = bench("")
+    assert result.returncode == 0, f"Bench failed: {result.stderr}"
+    assert "Broker appended" in result.stdout, "Expected the human report on stdout"
+    with open(report_path) as f:
+        report = json.load(f)
+    roles = report["roles"]
+    assert sorted(roles) == ["admin", "api_versions", "consumer", "producer"], f"Roles: {roles}"
+    for name, role in roles.items():
+        assert role["requests"] > 0 and role["errors"] == 0, f"{name}: {role}"
+        latency = role["latency_ms"]
+        assert 0 < latency["p50"] <= latency["p99"] <= latency["max"], f"{name}: {latency}"
+    # What producers were told was appended is what the broker holds, and consumers read it back
+    assert report["broker"]["records_appended"] == roles["producer"]["records"] > 0, f"Report: {report}"
+    assert 0 < roles["consumer"]["records"] <= roles["producer"]["records"], f"Report: {report}"
+    assert roles["api_versions"]["latency_ms"]["p99"] < 50, f"ApiVersions p99: {roles['api_versions']}"
+    
+    # A threshold no run can meet fails the bench
+    result = bench("max.api.versions.p99.us=1\n")
+    assert result.returncode != 0 and "max.api.versions.p99.us" in result.stderr, \
+        f"Expected the threshold to fail the run: {result.returncode} {result.stderr}"
+    print("✅ Bench smoke test passed")
+
+def test_response_buffer_pool():
+    print("🧪 Testing the response buffer pools against Metadata- and Fetch-heavy workloads...")
+    workdir = tempfile.mkdtemp(prefix="eventor-buffers-")
+    report_path = os.path.join(workdir, "report.json")
+    
+    def bench(fixture, pool):
+        workload = os.path.join(workdir, "workload.properties")
+        with open(fixture) as f:
+            base = f.read()
+        with open(workload, "w") as f:
+            f.write(base + f"report.json={report_path}\nduration.ms=1000\nresponse.buffer.pool={pool}\n")
+        result = subprocess.run([EVENTOR_BINARY, "bench", workload, "fixtures/server.properties"],
+                                stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True, timeout=30)
+        assert result.returncode == 0, f"Bench of {fixture} failed: {result.stderr}"
+        with open(report_path) as f:
+            return json.load(f)
+    
+    for fixture in ["fixtures/bench-metadata-heavy.properties", "fixtures/bench-fetch-heavy.properties"]:
+        pooled = bench(fixture, "true")
+        unpooled = bench(fixture, "false")
+        buffers = pooled["response_buffers"]
+        # Estimates are upper bounds, so a pooled response never outgrows its buffer
+        assert buffers["pool"] and buffers["hits"] > 0 and buffers["reallocations"] == 0, f"{fixture}: {buffers}"
+        assert unpooled["response_buffers"]["hits"] == 0, f"{fixture}: {unpooled['response_buffers']}"
+        assert unpooled["response_buffers"]["reallocations"] > 0, f"{fixture}: {unpooled['response_buffers']}"
+        assert pooled["allocations"]["per_request"] < unpooled["allocations"]["per_request"], \
+            f"{fixture}: {pooled['allocations']} pooled against {unpooled['allocations']}"
+    print("✅ Response buffer pool test passed")
+
+def test_response_coalescing():
+    print("🧪 Testing responses to pipelined requests go out together, in order...")
+    client = EventorTestClient()
+    
+    # Forty requests in one send, with an unanswered acks=0 produce among them: every
+    # response comes back in request order, whole, however they were written
+    connection = EventorConnection(client)
+    try:
+        frames = bytearray()
+        expected = []
+        for index in range(40):
+            if index == 20:
+                request, _ = client.build_produce_request(
+                    [("bar", [(0, build_record_batch([b"coalesced"]))])], acks=0)
+                frames.extend(request)
+            request, corr_id = (client.build_offset_commit_request("coalescing-group", [("foo", [(0, index, None)])])
+                                if index % 2 else client.build_api_versions_request(api_version=3))
+            frames.extend(request)
+            connection.outstanding.append(corr_id)
+            expected.append("OffsetCommit" if index % 2 else "ApiVersions")
+        connection.sock.sendall(frames)
+        for index, api in enumerate(expected):
+            response = connection.read_response()
+            if api == "OffsetCommit":
+                assert parse_offset_commit_response(response, 8) == {("foo", 0): 0}, \
+                    f"Pipelined OffsetCommit {index}: {response.hex()}"
+        # The commits were made in request order, so the last one stands
+        request, corr_id = client.build_offset_fetch_request([("coalescing-group", [("foo", [0])])])
+        connection.send(request, corr_id)
+        (_, partitions), = parse_offset_fetch_response(connection.read_response(), 8).values()
+        assert partitions[("foo", 0)][0] == 39, f"Committed after the pipeline: {partitions}"
+    finally:
+        connection.close()
+    
+    # The pipelined OffsetCommit bench, with responses coalesced and then written one by one
+    workdir = tempfile.mkdtemp(prefix="eventor-coalescing-")
+    report_path = os.path.join(workdir, "report.json")
+    
+    def bench(server_extra):
+        workload = os.path.join(workdir, "workload.properties")
+        with open("fixtures/bench-offset-commit.properties") as f:
+            base = f.read()
+        with open(workload, "w") as f:
+            f.write(base + f"report.json={report_path}\nduration.ms=1000\n")
+        server = os.path.join(workdir, "server.properties")
+        with open("fixtures/server.properties") as f:
+            base = f.read()
+        with open(server, "w") as f:
+            f.write(base + server_extra)
+        result = subprocess.run([EVENTOR_BINARY, "bench", workload, server],
+                                stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True, timeout=30)
+        assert result.returncode == 0, f"Bench with {server_extra!r} failed: {result.stderr}"
+        assert "Broker sent" in result.stdout, "Expected the response writes in the human report"
+        with open(report_path) as f:
+            report = json.load(f)
+        commits = report["roles"]["offset_commit"]
+        assert commits["requests"] > 0 and commits["errors"] == 0, f"OffsetCommit role: {commits}"
+        return report
+    
+    coalesced = bench("")
+    separate = bench("response.coalesce.max.bytes=1\n")
+    broker = coalesced["broker"]
+    assert broker["response_writes"] * 2 < broker["responses"], f"Coalesced: {broker}"
+    broker = separate["broker"]
+    assert broker["response_writes"] == broker["responses"], f"Uncoalesced: {broker}"
+    assert coalesced["roles"]["offset_commit"]["requests"] > separate["roles"]["offset_commit"]["requests"], \
+        f"Coalesced {coalesced['roles']} against uncoalesced {separate['roles']}"
+    print("✅ Response coalescing test passed")
+
+def parse_sasl_handshake_response(response):
+    """Decodes a SaslHandshakeResponse into (error_code, [mechanism])."""
+    error_code, count = struct.unpack_from(">hi", response, 8)
+    offset = 14
+    mechanisms = []
+    for _ in range(count):
+        length = struct.unpack_from(">h", response, offset)[0]
+        mechanisms.append(response[offset + 2:offset + 2 + length].decode("utf-8"))
+        offset += 2 + length
+    assert offset == len(response), f"SaslHandshake response has {len(response) - offset} trailing bytes"
+    return error_code, mechanisms
+
+def parse_sasl_authenticate_response(response, api_version):
+    """Decodes a SaslAuthenticateResponse into (error_code, error_message, auth_bytes,
+    session_lifetime_ms); the lifetime is None before v1."""
+    flexible = api_version >= 2
+    offset = 9 if flexible else 8
+    error_code = struct.unpack_from(">h", response, offset)[0]
+    offset += 2
+    
+    def read_length(nullable):
+        nonlocal offset
+        if flexible:
+            length, size = decode_uvarint_at(response, offset)
+            offset += size
+            return length - 1
+        length = struct.unpack_from(">h" if nullable else ">i", response, offset)[0]
+        offset += 2 if nullable else 4
+        return length
+    
+    length = read_length(True)
+    error_message = None if length < 0 else response[offset:offset + length].decode("utf-8")
+    offset += max(length, 0)
+    length = read_length(False)
+    auth_bytes = bytes(response[offset:offset + length])
+    offset += length
+    session_lifetime_ms = None
+    if api_version >= 1:
+        session_lifetime_ms = struct.unpack_from(">q", response, offset)[0]
+        offset += 8
+    if flexible:
+        assert response[offset] == 0, "Expected an empty tag buffer"
+        offset += 1
+    assert offset == len(response), f"SaslAuthenticate response has {len(response) - offset} trailing bytes"
+    return error_code, error_message, auth_bytes, session_lifetime_ms
+
+def test_sasl_plain():
+    print("🧪 Testing SASL/PLAIN authentication...")
+    # Without SASL configured, no mechanism is offered
+    client = EventorTestClient()
+    request, corr_id = client.build_sasl_handshake_request("PLAIN")
+    assert parse_sasl_handshake_response(client.send_request_and_get_response(request, corr_id)) == (33, []), \
+        "Handshake on a broker without SASL"
+    
+    workdir = tempfile.mkdtemp(prefix="eventor-sasl-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=SASL_PLAINTEXT://:{SASL_TEST_PORT}\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                f"readiness.file={readiness_file}\n"
+                "sasl.enabled.mechanisms=PLAIN\n"
+                "sasl.plain.users=alice:alice-secret,bob:b0b\n")
+    log_path = os.path.join(workdir, "server.log")
+    log = open(log_path, "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        client = EventorTestClient(port=SASL_TEST_PORT)
+        
+        def exchange(connection, built, parse=None):
+            request, corr_id = built
+            connection.send(request, corr_id)
+            response = connection.read_response()
+            return parse(response) if parse else response
+        
+        def handshake(connection, mechanism="PLAIN"):
+            return exchange(connection, client.build_sasl_handshake_request(mechanism), parse_sasl_handshake_response)
+        
+        def authenticate(connection, auth_bytes, api_version=2):
+            return exchange(connection, client.build_sasl_authenticate_request(auth_bytes, api_version),
+                            lambda response: parse_sasl_authenticate_response(response, api_version))
+        
+        def metadata_error(connection):
+            response = exchange(connection, client.build_metadata_request(api_version=12))
+            # Header v1: size, correlation id and tag buffer precede throttle_time_ms or the error code
+            return struct.unpack(">h", response[9:11])[0]
+        
+        # ApiVersions is answered before authenticating, and advertises both APIs
+        connection = EventorConnection(client)
+        api_keys = exchange(connection, client.build_api_versions_request(), parse_api_versions_response)[1]
+        assert api_keys[17][:2] == (1, 1) and api_keys[36][:2] == (0, 2), f"SASL entries: {api_keys}"
+        # Anything else is refused until then, and the connection stays usable
+        assert metadata_error(connection) == 34, "Metadata before authenticating"
+        assert authenticate(connection, b"\x00alice\x00alice-secret")[0] == 34, "Authenticate before the handshake"
+        assert handshake(connection, "SCRAM-SHA-256") == (33, ["PLAIN"]), "Unsupported mechanism"
+        assert handshake(connection) == (0, ["PLAIN"]), "PLAIN handshake"
+        assert authenticate(connection, b"\x00alice\x00alice-secret") == (0, None, b"", 0), "Good credentials"
+        assert metadata_error(connection) == 0, "Metadata once authenticated"
+        # The users are described as sensitive, without their passwords
+        response = exchange(connection, client.build_describe_configs_request([(4, "1", None)], include_synonyms=True))
+        assert b"secret" not in response and b"b0b" not in response, "SASL passwords described"
+        configs = parse_describe_configs_response(response, 4)[0][4]
+        assert (configs["sasl.plain.users"]["value"], configs["sasl.plain.users"]["is_sensitive"]) == (None, True), \
+            f"sasl.plain.users: {configs['sasl.plain.users']}"
+        assert configs["sasl.enabled.mechanisms"]["value"] == "PLAIN", f"Mechanisms: {configs}"
+        assert handshake(connection) == (34, ["PLAIN"]), "Handshake once authenticated"
+        connection.close()
+        
+        # Every SaslAuthenticate version, with and without an authorization id
+        for api_version in range(0, 3):
+            connection = EventorConnection(client)
+            handshake(connection)
+            result = authenticate(connection, b"bob\x00bob\x00b0b", api_version)
+            assert result == (0, None, b"", 0 if api_version >= 1 else None), f"v{api_version}: {result}"
+            connection.close()
+        
+        # Wrong passwords, unknown users, borrowed identities and garbage all fail the same way and close
+        for auth_bytes in (b"\x00alice\x00wrong", b"\x00mallory\x00alice-secret", b"bob\x00alice\x00alice-secret",
+                           b"alice-secret", b"\x00alice\x00alice-secret\x00"):
+            connection = EventorConnection(client)
+            handshake(connection)
+            error_code, message, _, _ = authenticate(connection, auth_bytes)
+            assert error_code == 58 and message == "Authentication failed: Invalid username or password", \
+                f"{auth_bytes}: {error_code} {message}"
+            connection.sock.settimeout(2)
+            assert connection.sock.recv(1) == b"", f"{auth_bytes}: connection left open after failing"
+            connection.close()
+        
+        server.terminate()
+        server.wait(timeout=5)
+        with open(log_path) as f:
+            server_log = f.read()
+        assert "alice-secret" not in server_log and "b0b" not in server_log, "Passwords in the broker log"
+    finally:
+        if server.poll() is None:
+            server.kill()
+            server.wait()
+        log.close()
+    print("✅ SASL/PLAIN test passed")
+
+def test_listener_policy():
+    print("🧪 Testing per-listener protocols and policies...")
+    internal_port, external_port, restricted_port, locked_port = LISTENER_TEST_PORTS
+    workdir = tempfile.mkdtemp(prefix="eventor-listeners-")
+    
+    def write_properties(name, contents):
+        path = os.path.join(workdir, name)
+        with open(path, "w") as f:
+            f.write("log.dirs=fixtures/kraft-combined-logs\nlog.store=memory\n" + contents)
+        return path
+    
+    # Listeners that can't be served as configured stop the broker before it binds anything
+    for contents, expected in (
+            (f"listeners=SSL://:{internal_port}\n", "there is no TLS"),
+            (f"listeners=PLAINTEXT://:{internal_port}\nsasl.enabled.mechanisms=PLAIN\nsasl.plain.users=a:b\n",
+             "no listener is SASL_PLAINTEXT"),
+            (f"listeners=SASL_PLAINTEXT://:{internal_port}\n", "needs sasl.enabled.mechanisms"),
+            (f"listeners=INTERNAL://:{internal_port}\n", "no listener.security.protocol.map entry"),
+            (f"listeners=PLAINTEXT://:{internal_port}\nlistener.name.plaintext.allowed.apis=Produce,Nope\n",
+             "unknown API 'Nope'"),
+            (f"listeners=PLAINTEXT://:{internal_port}\nlistener.name.plaintext.allowed.cidrs=10.0.0.0/33\n",
+             "10.0.0.0/33")):
+        result = subprocess.run([EVENTOR_BINARY, write_properties("bad.properties", contents)],
+                                capture_output=True, text=True, timeout=10)
+        assert result.returncode != 0 and expected in result.stderr, \
+            f"{contents!r}: exit {result.returncode}, {result.stderr}"
+    
+    readiness_file = os.path.join(workdir, "ready")
+    properties = write_properties("server.properties", (
+        f"listeners=INTERNAL://:{internal_port},EXTERNAL://:{external_port},"
+        f"RESTRICTED://:{restricted_port},LOCKED://:{locked_port},CONTROLLER://:{locked_port}\n"
+        "listener.security.protocol.map=INTERNAL:PLAINTEXT,EXTERNAL:SASL_PLAINTEXT,RESTRICTED:PLAINTEXT,"
+        "LOCKED:PLAINTEXT,CONTROLLER:PLAINTEXT,SSL:SSL\n"
+        # Never bound, or it would clash with LOCKED
+        "controller.listener.names=CONTROLLER\n"
+        "sasl.enabled.mechanisms=PLAIN\n"
+        "sasl.plain.users=ops:ops-secret\n"
+        "listener.name.external.admin.apis.enabled=false\n"
+        "listener.name.restricted.allowed.apis=ApiVersions,Metadata\n"
+        "listener.name.locked.allowed.cidrs=10.0.0.0/8,fd00::/8\n"
+        f"readiness.file={readiness_file}\n"))
+    log_path = os.path.join(workdir, "server.log")
+    log = open(log_path, "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        internal = EventorTestClient(port=internal_port)
+        external = EventorTestClient(port=external_port)
+        restricted = EventorTestClient(port=restricted_port)
+        
+        def exchange(connection, built):
+            request, corr_id = built
+            connection.send(request, corr_id)
+            return connection.read_response()
+        
+        def body_error(response):
+            # Refused before their handler, so just a flexible header and the error code
+            return struct.unpack(">h", response[9:11])[0]
+        
+        def produce(client, connection):
+            response = exchange(connection, client.build_produce_request(
+                [("foo", [(0, build_record_batch([b"via listener"]))])]))
+            return response
+        
+        def topic_names(client, connection):
+            response = exchange(connection, client.build_metadata_request(api_version=12))
+            return {topic[1] for topic in parse_metadata_response(response, 12)[3]}
+        
+        # INTERNAL is plaintext and serves everything, admin APIs included
+        connection = EventorConnection(internal)
+        assert parse_produce_response(produce(internal, connection), 9)[("foo", 0)][0] == 0, "Produce on INTERNAL"
+        response = exchange(connection, internal.build_create_topics_request([("listener-internal", 1, 1)]))
+        assert parse_create_topics_response(response, 7)["listener-internal"][0] == 0, "CreateTopics on INTERNAL"
+        handshake = exchange(connection, internal.build_sasl_handshake_request("PLAIN"))
+        assert parse_sasl_handshake_response(handshake) == (33, []), "INTERNAL offers no SASL mechanism"
+        connection.close()
+        
+        # EXTERNAL has to authenticate first, then serves Produce but refuses CreateTopics
+        connection = EventorConnection(external)
+        assert body_error(produce(external, connection)) == 34, "Produce on EXTERNAL before authenticating"
+        assert parse_sasl_handshake_response(exchange(connection, external.build_sasl_handshake_request("PLAIN"))) \
+            == (0, ["PLAIN"]), "Handshake on EXTERNAL"
+        assert parse_sasl_authenticate_response(exchange(
+            connection, external.build_sasl_authenticate_request(b"\x00ops\x00ops-secret")), 2)[0] == 0, \
+            "Authenticating on EXTERNAL"
+        assert parse_produce_response(produce(external, connection), 9)[("foo", 0)][0] == 0, "Produce on EXTERNAL"
+        api_keys = parse_api_versions_response(exchange(connection, external.build_api_versions_request()))[1]
+        assert 19 in api_keys, "EXTERNAL still advertises CreateTopics, to refuse it plainly"
+        for built in (external.build_create_topics_request([("listener-external", 1, 1)]),
+                      external.build_delete_topics_request(["listener-internal"]),
+                      external.build_delete_records_request([("foo", [(0, -1)])])):
+            assert body_error(exchange(connection, built)) == 31, "Admin API on EXTERNAL"
+        # Nothing the refused requests asked for happened
+        names = topic_names(external, connection)
+        assert "listener-external" not in names and "listener-internal" in names, f"Topics: {names}"
+        connection.close()
+        
+        # RESTRICTED serves and advertises only its two APIs
+        connection = EventorConnection(restricted)
+        api_keys = parse_api_versions_response(exchange(connection, restricted.build_api_versions_request()))[1]
+        assert sorted(api_keys) == [3, 18], f"RESTRICTED advertises {sorted(api_keys)}"
+        assert "foo" in topic_names(restricted, connection), "Metadata on RESTRICTED"
+        assert body_error(produce(restricted, connection)) == 35, "Produce on RESTRICTED"
+        response = exchange(connection, restricted.build_api_versions_request(api_version=9))
+        assert parse_legacy_api_versions_response(response, 0)[0] == 35, "ApiVersions v9 on RESTRICTED"
+        connection.close()
+        
+        # LOCKED only takes connections from 10.0.0.0/8, so this one is closed unanswered
+        sock = socket.create_connection(("localhost", locked_port), timeout=2)
+        request, _ = internal.build_api_versions_request()
+        try:
+            sock.sendall(request)
+            assert sock.recv(1) == b"", "LOCKED answered a connection from outside its networks"
+        except ConnectionResetError:
+            pass
+        sock.close()
+        
+        request, corr_id = internal.build_delete_topics_request(["listener-internal"])
+        internal.send_request_and_get_response(request, corr_id)
+        time.sleep(0.2)
+    finally:
+        server.terminate()
+        server.wait(timeout=5)
+        log.close()
+    with open(log_path) as f:
+        server_log = f.read()
+    assert "Handling CreateTopics request" in server_log, "INTERNAL CreateTopics never handled"
+    assert server_log.count("Handling CreateTopics request") == 1, "A refused CreateTopics reached its handler"
+    assert "Admin APIs are disabled on listener EXTERNAL, rejecting CreateTopics" in server_log, \
+        "EXTERNAL refusal not logged"
+    # Traffic is attributed to the listener it came in on
+    assert re.search(r"Listener EXTERNAL: connections=1 refused_connections=0 requests=\d+ refused_requests=4",
+                     server_log), "EXTERNAL stats"
+    assert "Listener RESTRICTED: connections=1 refused_connections=0 requests=4 refused_requests=2" in server_log, \
+        "RESTRICTED stats"
+    # Each API's version checks are counted; Produce was refused before its version was looked at
+    assert re.search(r"Listener RESTRICTED: .* version_checks=Metadata:1/0,ApiVersions:1/1\n", server_log), \
+        "RESTRICTED version checks"
+    assert "Listener LOCKED: connections=1 refused_connections=1 requests=0 refused_requests=0 " \
+        "responses=0 response_writes=0 version_checks=none" in server_log, "LOCKED version checks"
+    assert "Listener LOCKED: connections=1 refused_connections=1 requests=0 refused_requests=0" in server_log, \
+        "LOCKED stats"
+    print("✅ Listener policy test passed")
+
+def test_group_memory_stays_flat():
+    print("🧪 Testing group coordinator memory over many rebalances...")
+    workdir = tempfile.mkdtemp(prefix="eventor-groups-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{GROUP_MEMORY_TEST_PORT}\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                f"readiness.file={readiness_file}\n"
+                "group.max.member.metadata.bytes=4096\n")
+    log_path = os.path.join(workdir, "server.log")
+    log = open(log_path, "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        client_factory = lambda: EventorTestClient(port=GROUP_MEMORY_TEST_PORT)
+        client = client_factory()
+        members = []
+        for _ in range(2):
+            request, corr_id = client.build_join_group_request("memory-group", api_version=9)
+            members.append(parse_join_group_response(client.send_request_and_get_response(request, corr_id), 9)["member_id"])
+        assignments = {member_id: bytes(2000) for member_id in members}
+        for round_number in range(30):
+            results = run_group_round("memory-group", members, assignments, client_factory,
+                                      protocols=(("range", bytes(1000)),))
+            assert sorted(results) == sorted(members), f"Round {round_number}: {results}"
+            for member_id, (joined, synced) in results.items():
+                assert synced[0] == 0 and synced[3] == assignments[member_id], f"Round {round_number}: {synced}"
+        
+        server.terminate()
+        server.wait(timeout=5)
+        with open(log_path) as f:
+            held = [int(line.split()[3]) for line in f if line.startswith("Group coordinator holds")]
+        assert len(held) >= 60, f"Expected a usage line per round close and sync, got {len(held)}"
+        # Each generation's assignments replace the last's rather than adding to them
+        assert max(held) == max(held[:6]), f"Group memory grew over rebalances: {held}"
+        assert held[-1] == 2 * (len("range") + 1000) + sum(len(m) + 2000 for m in members), \
+            f"Unexpected bytes held: {held[-1]}"
+    finally:
+        if server.poll() is None:
+            server.kill()
+            server.wait()
+        log.close()
+    print("✅ Group memory test passed")
+
+def test_file_log_store():
+    print("🧪 Testing the file log store: restarts and damaged segments...")
+    # A broker on a copy of the metadata fixture, keeping its logs in files there
+    workdir = tempfile.mkdtemp(prefix="eventor-file-logs-")
+    log_dir = os.path.join(workdir, "logs")
+    shutil.copytree("fixtures/kraft-combined-logs", log_dir)
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{FILE_LOG_STORE_TEST_PORT}\n"
+                f"log.dirs={log_dir}\n"
+                "log.store=file\n"
+                f"readiness.file={readiness_file}\n")
+    log_path = os.path.join(workdir, "server.log")
+    
+    def run_broker(check):
+        log = open(log_path, "w")
+        server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+        try:
+            deadline = time.monotonic() + 5
+            while not os.path.exists(readiness_file):
+                assert server.poll() is None, f"Broker exited during startup:\n{open(log_path).read()}"
+                assert time.monotonic() < deadline, "Broker never became ready"
+                time.sleep(0.05)
+            check(EventorTestClient(port=FILE_LOG_STORE_TEST_PORT))
+            server.terminate()
+            assert server.wait(timeout=5) == 0, "Broker did not shut down cleanly on SIGTERM"
+        finally:
+            if server.poll() is None:
+                server.kill()
+                server.wait()
+            log.close()
+        with open(log_path) as f:
+            return f.read()
+    
+    def fetch(client, topic, partition):
+        request, corr_id = client.build_fetch_request([(topic, [(partition, 0, 1 << 20)])])
+        error_code, high_watermark, records = parse_fetch_response(
+            client.send_request_and_get_response(request, corr_id), 12)[(topic, partition)]
+        assert error_code == 0, f"Fetch from {topic}-{partition} failed with {error_code}"
+        return high_watermark, decode_record_batches(records)
+    
+    def partition_dirs():
+        return {name for name in os.listdir(log_dir)
+                if name != "__cluster_metadata-0" and os.path.isdir(os.path.join(log_dir, name))}
+    
+    # An idempotent producer still connected across the restart retries its batch
+    producer = {}
+    
+    def init_producer_id(client):
+        request, corr_id = client.build_init_producer_id_request()
+        error_code, producer_id, _ = parse_init_producer_id_response(
+            client.send_request_and_get_response(request, corr_id), 5)
+        assert error_code == 0, f"InitProducerId failed with {error_code}"
+        return producer_id
+    
+    def produce_idempotent(client):
+        batch = build_record_batch([b"kept-once"], producer_id=producer["id"], producer_epoch=0, base_sequence=0)
+        request, corr_id = client.build_produce_request([("baz", [(0, batch)])])
+        return parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[("baz", 0)][:2]
+    
+    def first_run(client):
+        assert produce_one(client, "foo", 0, [b"kept-a", b"kept-b"]) == 0
+        assert produce_one(client, "bar", 1, [b"kept-c"]) == 0
+        producer["id"] = init_producer_id(client)
+        assert produce_idempotent(client) == (0, 0)
+        request, corr_id = client.build_create_topics_request([("not-in-metadata-log", 1, 1)])
+        client.send_request_and_get_response(request, corr_id)
+        assert produce_one(client, "not-in-metadata-log", 0, [b"dropped"]) == 0
+    
+    run_broker(first_run)
+    # foo has 1 partition, bar 2 and baz 1, plus the created topic's
+    dirs = partition_dirs()
+    assert len(dirs) == 5, f"Partition directories after the first run: {sorted(dirs)}"
+    
+    def after_restart(client):
+        assert fetch(client, "foo", 0) == (2, [(0, [b"kept-a", b"kept-b"])]), f"foo-0: {fetch(client, 'foo', 0)}"
+        assert fetch(client, "bar", 1) == (1, [(0, [b"kept-c"])]), f"bar-1: {fetch(client, 'bar', 1)}"
+        assert fetch(client, "bar", 0) == (0, []), f"bar-0: {fetch(client, 'bar', 0)}"
+        # Offsets carry on from the recovered log end
+        assert produce_one(client, "foo", 0, [b"kept-d"]) == 2
+        # The retry is recognised, and no new producer is handed the old one's id
+        assert produce_idempotent(client) == (0, 0), f"Retry after restart: {produce_idempotent(client)}"
+        assert fetch(client, "baz", 0) == (1, [(0, [b"kept-once"])]), f"baz-0: {fetch(client, 'baz', 0)}"
+        assert init_producer_id(client) > producer["id"], "Producer id handed out again after restart"
+    
+    output = run_broker(after_restart)
+    assert "Recovered partition" in output, output
+    # baz-0's producer state is reloaded with its batches
+    assert "with log end offset 1 and 1 producer(s)" in output, output
+    assert "a partition the registry doesn't have" in output, output
+    assert len(partition_dirs()) == 4, f"The created topic's directory outlived it: {sorted(partition_dirs())}"
+    
+    # A torn write at the end of foo's segment is cut off; the batches before it are served
+    segments = [os.path.join(log_dir, name, "00000000000000000000.log") for name in partition_dirs()]
+    foo_segment, = [path for path in segments if os.path.getsize(path) > 0 and
+                    b"kept-a" in open(path, "rb").read()]
+    intact = os.path.getsize(foo_segment)
+    with open(foo_segment, "ab") as f:
+        f.write(build_record_batch([b"torn"])[:-5])
+    
+    def after_damage(client):
+        high_watermark, batches = fetch(client, "foo", 0)
+        assert (high_watermark, batches) == (3, [(0, [b"kept-a", b"kept-b"]), (2, [b"kept-d"])]), \
+            f"foo-0 after damage: {high_watermark} {batches}"
+        assert os.path.getsize(foo_segment) == intact, "Damaged tail left in the segment"
+        assert produce_one(client, "foo", 0, [b"kept-e"]) == 3
+    
+    output = run_broker(after_damage)
+    assert "is damaged from byte" in output, output
+    
+    # The layout's version is recorded; a directory from before the marker is upgraded, a newer one refused
+    format_file = os.path.join(log_dir, "eventor-log-format")
+    assert open(format_file).read() == "version=1\n", open(format_file).read()
+    os.remove(format_file)
+    
+    def after_upgrade(client):
+        assert fetch(client, "foo", 0)[0] == 4, f"foo-0 after upgrade: {fetch(client, 'foo', 0)}"
+    
+    output = run_broker(after_upgrade)
+    assert "from format 0 to 1" in output, output
+    assert open(format_file).read() == "version=1\n", "Upgrade left no format marker"
+    with open(format_file, "w") as f:
+        f.write("version=2\n")
+    with open(log_path, "w") as log:
+        result = subprocess.run([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT, timeout=10)
+    output = open(log_path).read()
+    assert result.returncode != 0 and "holds log format 'version=2'" in output, output
+    print("✅ Log store test passed")
+
+def test_parallel_log_recovery():
+    print("🧪 Testing log recovery across partitions in parallel, with one partition failing...")
+    workdir = tempfile.mkdtemp(prefix="eventor-recovery-")
+    log_dir = os.path.join(workdir, "logs")
+    shutil.copytree("fixtures/kraft-combined-logs", log_dir)
+    readiness_file = os.path.join(workdir, "ready")
+    log_path = os.path.join(workdir, "server.log")
+    client = EventorTestClient(port=RECOVERY_TEST_PORT)
+    
+    def run_broker(threads, check):
+        properties = os.path.join(workdir, "server.properties")
+        with open(properties, "w") as f:
+            f.write(f"listeners=PLAINTEXT://:{RECOVERY_TEST_PORT}\n"
+                    f"log.dirs={log_dir}\n"
+                    "log.store=file\n"
+                    f"num.recovery.threads.per.data.dir={threads}\n"
+                    f"readiness.file={readiness_file}\n")
+        log = open(log_path, "w")
+        server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+        try:
+            deadline = time.monotonic() + 10
+            while not os.path.exists(readiness_file):
+                assert server.poll() is None, f"Broker exited during startup:\n{open(log_path).read()}"
+                assert time.monotonic() < deadline, "Broker never became ready"
+                time.sleep(0.05)
+            check()
+            server.terminate()
+            assert server.wait(timeout=5) == 0, "Broker did not shut down cleanly on SIGTERM"
+        finally:
+            if server.poll() is None:
+                server.kill()
+                server.wait()
+            log.close()
+        with open(log_path) as f:
+            return f.read()
+    
+    def produce(topic, partition, values):
+        request, corr_id = client.build_produce_request([(topic, [(partition, build_record_batch(values))])])
+        return parse_produce_response(client.send_request_and_get_response(request, corr_id), 9)[(topic, partition)]
+    
+    # A first run creates every partition's directory, and gives three of them records
+    healthy = [("foo", 0, "00000000000040008000000000000f00-0"),
+               ("bar", 0, "00000000000040008000000000000ba7-0"),
+               ("bar", 1, "00000000000040008000000000000ba7-1")]
+    def fill():
+        for topic, partition, _ in healthy:
+            assert produce(topic, partition, [f"{topic}-{partition}".encode()])[0] == 0, f"Produce to {topic}"
+    run_broker(1, fill)
+    
+    # Each healthy partition's log-start-offset becomes a FIFO the test answers
+    # half a second after the broker opens it, standing in for a slow disk;
+    # baz's is a directory, which can't be read at all
+    SLOW_RECOVERY_S = 0.5
+    fifos = []
+    for _, _, name in healthy:
+        path = os.path.join(log_dir, name, "log-start-offset")
+        if os.path.exists(path):
+            os.remove(path)
+        os.mkfifo(path)
+        fifos.append(path)
+    os.makedirs(os.path.join(log_dir, "00000000000040008000000000000ba2-0", "log-start-offset"))
+    
+    def recover(threads, check):
+        """Runs a broker recovering on `threads` threads; returns its log and the
+        most partitions that were being recovered at once."""
+        waiting, most_waiting = [0], [0]
+        lock = threading.Lock()
+        
+        def answer(path):
+            while True:
+                try:
+                    fd = os.open(path, os.O_WRONLY | os.O_NONBLOCK)
+                    break
+                except OSError as e:
+                    assert e.errno == errno.ENXIO, f"Opening {path}: {e}"
+                    time.sleep(0.01)  # The broker hasn't got to it yet
+            with lock:
+                waiting[0] += 1
+                most_waiting[0] = max(most_waiting[0], waiting[0])
+            time.sleep(SLOW_RECOVERY_S)
+            with lock:
+                waiting[0] -= 1
+            os.write(fd, b"0\n")
+            os.close(fd)
+        
+        feeders = [threading.Thread(target=answer, args=(path,)) for path in fifos]
+        for feeder in feeders:
+            feeder.start()
+        server_log = run_broker(threads, check)
+        for feeder in feeders:
+            feeder.join(timeout=5)
+        match = re.search(r"Recovered 3 partition\(s\) in \S+ in (\d+) ms, 1 offline", server_log)
+        assert match, f"No recovery summary:\n{server_log}"
+        return server_log, int(match.group(1)), most_waiting[0]
+    
+    def check_served():
+        # The healthy partitions keep their records; baz is offline, not the broker
+        for topic, partition, _ in healthy:
+            request, corr_id = client.build_fetch_request([(topic, [(partition, 0, 1 << 20)])])
+            error_code, _, records = parse_fetch_response(
+                client.send_request_and_get_response(request, corr_id), 12)[(topic, partition)]
+            values = [value for _, batch in decode_record_batches(records) for value in batch]
+            assert (error_code, values) == (0, [f"{topic}-{partition}".encode()]), \
+                f"Fetch from {topic}-{partition}: {error_code} {values}"
+        assert produce("baz", 0, [b"lost"])[0] == 56, "Produce to the offline partition"
+    
+    server_log, serial_ms, serial_most = recover(1, check_served)
+    assert serial_most == 1, f"{serial_most} partitions recovered at once on one thread"
+    assert serial_ms >= 3 * SLOW_RECOVERY_S * 1000, f"Serial recovery took {serial_ms} ms"
+    assert "Recovering 4 partition(s)" in server_log and "(4/4)" in server_log, "Recovery progress not logged"
+    assert re.search(r"Error recovering partition 00000000000040008000000000000ba2-0: .*; leaving it offline",
+                     server_log), "The failed partition not logged"
+    
+    def check_then_delete():
+        check_served()
+        # Deleting the offline partition's topic takes its directory with it
+        request, corr_id = client.build_delete_topics_request(["baz"])
+        [(_, _, error_code)] = parse_delete_topics_response(client.send_request_and_get_response(request, corr_id), 6)
+        assert error_code == 0, f"DeleteTopics of the offline topic: {error_code}"
+        assert not os.path.exists(os.path.join(log_dir, "00000000000040008000000000000ba2-0")), \
+            "The offline partition's directory outlived its topic"
+    
+    server_log, parallel_ms, parallel_most = recover(4, check_then_delete)
+    assert parallel_most == 3, f"Only {parallel_most} partitions recovered at once on four threads"
+    assert parallel_ms < serial_ms / 2, f"Recovery on four threads took {parallel_ms} ms, on one {serial_ms} ms"
+    assert "on 4 thread(s)" in server_log, "Thread count not logged"
+    print(f"✅ Parallel log recovery test passed ({serial_ms} ms on one thread, {parallel_ms} ms on four)")
+
+def test_flush_on_acks_all():
+    print("🧪 Testing acks=-1 produces answered once flushed, shared flushes and a crash...")
+    workdir = tempfile.mkdtemp(prefix="eventor-flush-")
+    server_properties = os.path.join(workdir, "bench-server.properties")
+    with open(server_properties, "w") as f:
+        f.write("log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=file\n"
+                "log.flush.on.acks.all=true\n")
+    report_path = os.path.join(workdir, "report.json")
+    
+    def bench(acks, producers):
+        workload = os.path.join(workdir, "workload.properties")
+        with open(workload, "w") as f:
+            f.write(f"duration.ms=1000\ntopic=bench-flush\ntopic.partitions=4\nproducers={producers}\n"
+                    f"producer.acks={acks}\nconsumers=0\napi.versions.connections=0\nreport.json={report_path}\n")
+        result = subprocess.run([EVENTOR_BINARY, "bench", workload, server_properties],
+                                stdout=subprocess.PIPE, stderr=subprocess.PIPE, text=True, timeout=30)
+        assert result.returncode == 0, f"Bench failed: {result.stderr}"
+        with open(report_path) as f:
+            report = json.load(f)
+        producer = report["roles"]["producer"]
+        assert producer["errors"] == 0 and report["broker"]["records_appended"] == producer["records"] > 0, \
+            f"Report: {report}"
+        return producer["requests"], report["broker"]["flushes"]
+    
+    # Concurrent acks=-1 producers share flushes rather than taking one each
+    requests, flushes = bench(-1, 8)
+    assert 0 < flushes < requests, f"{flushes} flush(es) for {requests} acks=-1 produces"
+    # acks=1 doesn't wait, and with no log.flush.interval.ms nothing else flushes
+    requests, flushes = bench(1, 8)
+    assert flushes == 0, f"{flushes} flush(es) for {requests} acks=1 produces"
+    
+    # A broker on a copy of the metadata fixture, killed while producers are mid-stream
+    log_dir = os.path.join(workdir, "logs")
+    shutil.copytree("fixtures/kraft-combined-logs", log_dir)
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{FLUSH_TEST_PORT}\n"
+                f"log.dirs={log_dir}\n"
+                "log.store=file\n"
+                "log.flush.on.acks.all=true\n"
+                f"readiness.file={readiness_file}\n")
+    log_path = os.path.join(workdir, "server.log")
+    
+    def start_broker():
+        server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=open(log_path, "a"), stderr=subprocess.STDOUT)
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert server.poll() is None, f"Broker exited during startup:\n{open(log_path).read()}"
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        return server
+    
+    partitions = [("foo", 0), ("bar", 0), ("bar", 1)]
+    acknowledged = []
+    refused = []
+    lock = threading.Lock()
+    
+    def produce(worker):
+        client = EventorTestClient(port=FLUSH_TEST_PORT)
+        try:
+            for sequence in range(50):
+                topic, partition = partitions[(worker + sequence) % len(partitions)]
+                values = [f"w{worker}-{sequence}-{i}".encode() for i in range(3)]
+                request, corr_id = client.build_produce_request([(topic, [(partition, build_record_batch(values))])])
+                error_code, base_offset, _ = parse_produce_response(
+                    client.send_request_and_get_response(request, corr_id), 9)[(topic, partition)]
+                with lock:
+                    if error_code != 0:
+                        refused.append((topic, partition, error_code))
+                        return
+                    acknowledged.append((topic, partition, base_offset, values))
+        except (OSError, FramingError):
+            pass  # The broker was killed under it
+    
+    server = start_broker()
+    try:
+        workers = [threading.Thread(target=produce, args=(worker,)) for worker in range(4)]
+        for worker in workers:
+            worker.start()
+        deadline = time.monotonic() + 10
+        while True:
+            with lock:
+                if len(acknowledged) >= 60:
+                    break
+            assert time.monotonic() < deadline, f"Only {len(acknowledged)} produces acknowledged"
+            time.sleep(0.01)
+        # No shutdown flush: whatever was acknowledged has to be on disk already
+        server.kill()
+        server.wait()
+        for worker in workers:
+            worker.join()
+        assert not refused, f"acks=-1 produces refused: {refused}"
+        os.remove(readiness_file)
+        
+        server = start_broker()
+        client = EventorTestClient(port=FLUSH_TEST_PORT)
+        recovered = {}
+        for topic, partition in partitions:
+            request, corr_id = client.build_fetch_request([(topic, [(partition, 0, 1 << 20)])])
+            error_code, _, records = parse_fetch_response(
+                client.send_request_and_get_response(request, corr_id), 12)[(topic, partition)]
+            assert error_code == 0, f"Fetch from {topic}-{partition} failed with {error_code}"
+            recovered[(topic, partition)] = dict(decode_record_batches(records))
+        for topic, partition, base_offset, values in acknowledged:
+            assert recovered[(topic, partition)].get(base_offset) == values, \
+                f"Acknowledged batch at {topic}-{partition}@{base_offset} lost in the crash"
+    finally:
+        if server.poll() is None:
+            server.kill()
+            server.wait()
+    print("✅ Flush on acks=-1 test passed")
+
+def test_client_inventory():
+    print("🧪 Testing the client inventory of software names, versions and API versions...")
+    workdir = tempfile.mkdtemp(prefix="eventor-clients-")
+    readiness_file = os.path.join(workdir, "ready")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{CLIENT_INVENTORY_TEST_PORT}\n"
+                "log.dirs=fixtures/kraft-combined-logs\n"
+                "log.store=memory\n"
+                "client.inventory.max.entries=4\n"
+                "client.inventory.retention.ms=2000\n"
+                f"readiness.file={readiness_file}\n")
+    log_path = os.path.join(workdir, "server.log")
+    client = EventorTestClient(port=CLIENT_INVENTORY_TEST_PORT)
+    
+    def server_log():
+        with open(log_path) as f:
+            return f.read()
+    
+    def connect(software=None, metadata_versions=(12,)):
+        connection = EventorConnection(client)
+        if software is not None:
+            request, corr_id = client.build_api_versions_request(
+                software_name=software[0], software_version=software[1])
+            connection.send(request, corr_id)
+            assert parse_api_versions_response(connection.read_response())[0] == 0, f"ApiVersions from {software}"
+        for api_version in metadata_versions:
+            connection.send(*client.build_metadata_request(api_version=api_version))
+            connection.read_response()
+        return connection
+    
+    def wait_for_log(text):
+        deadline = time.monotonic() + 5
+        while text not in server_log():
+            assert time.monotonic() < deadline, f"Never logged {text!r}:\n{server_log()}"
+            time.sleep(0.05)
+    
+    log = open(log_path, "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert server.poll() is None, f"Broker exited during startup:\n{server_log()}"
+            assert time.monotonic() < deadline, "Broker never became ready"
+            time.sleep(0.05)
+        
+        # Two connections from one software version, at different Metadata
+        # versions, and one from another
+        connect(("app-a", "1.0"), (12,)).close()
+        wait_for_log("Client app-a/1.0 client_id=test-client: connections=1 open=0 ")
+        connect(("app-a", "1.0"), (9, 10)).close()
+        connect(("app-b", "2.5")).close()
+        wait_for_log("Client app-a/1.0 client_id=test-client: connections=2 open=0 ")
+        wait_for_log("Client app-b/2.5 client_id=test-client: connections=1 open=0 ")
+        lines = [line for line in server_log().splitlines() if line.startswith("Client app-")]
+        assert lines[-2].endswith("versions=Metadata:9-12,ApiVersions:3-3"), f"app-a versions: {lines}"
+        assert lines[-1].endswith("versions=Metadata:12-12,ApiVersions:3-3"), f"app-b versions: {lines}"
+        # A client that never sends ApiVersions v3 is unknown, not left out
+        connect(None).close()
+        wait_for_log("Client unknown/unknown client_id=test-client: connections=1 open=0 ")
+        assert server_log().rstrip().endswith("versions=Metadata:12-12"), "unknown versions"
+        
+        # Software names come from the client: control characters are escaped
+        # and long ones cut, so they can't forge or flood log lines
+        connect(("evil\nClient forged/1.0\x1b[31m" + "x" * 200, "1.0")).close()
+        wait_for_log("Client evil\\nClient forged/1.0\\u{1b}[31m" + "x" * 37 + "/1.0 ")
+        assert not re.search(r"^Client forged", server_log(), re.M), "A software name forged a log line"
+        
+        # The inventory is full, so a fifth client is counted but not kept
+        connect(("app-c", "1.0")).close()
+        wait_for_log("Client inventory is full, not keeping app-c/1.0 client_id=test-client")
+        
+        # Clients with no open connection age out, making room again, but one
+        # with a connection open outlives the retention time
+        for software in ("app-a/1.0", "app-b/2.5", "unknown/unknown", "evil"):
+            wait_for_log(f"Client inventory aged out {software}")
+        open_connection = connect(("app-d", "3.0"))
+        time.sleep(2.5)
+        assert "not keeping app-d" not in server_log(), "app-d not kept after the rest aged out"
+        assert "aged out app-d" not in server_log(), "A client with an open connection aged out"
+        
+        server.terminate()
+        assert server.wait(timeout=5) == 0, "Broker did not shut down cleanly on SIGTERM"
+        open_connection.close()
+    finally:
+        if server.poll() is None:
+            server.kill()
+            server.wait()
+        log.close()
+    # Shutdown dumps the whole inventory
+    dump = server_log().split("Shutdown requested")[1]
+    assert "Client inventory: 1 client(s), 1 connection(s) from clients not kept" in dump, f"Dump: {dump}"
+    assert "Client app-d/3.0 client_id=test-client: connections=1 open=1 " in dump, f"Dump: {dump}"
+    print("✅ Client inventory test passed")
+
+def parse_find_coordinator_response(response, api_version):
+    """Decodes a FindCoordinatorResponse into [(key, node_id, host, port, error_code,
+    error_message)]; key is None before v4, which answer about a single key."""
+    flexible = api_version >= 3
+    offset = 9 if flexible else 8
+    
+    def read(fmt):
+        nonlocal offset
+        values = struct.unpack_from(fmt, response, offset)
+        offset += struct.calcsize(fmt)
+        return values if len(values) > 1 else values[0]
+    
+    def read_string():
+        # Every length in these responses fits in one varint byte
+        length = read(">B") - 1 if flexible else read(">h")
+        if length < 0:
+            return None
+        nonlocal offset
+        value = response[offset:offset + length].decode("utf-8")
+        offset += length
+        return value
+    
+    def read_tags():
+        if flexible:
+            assert read(">B") == 0, "Expected an empty tag buffer"
+    
+    if api_version >= 1:
+        assert read(">i") == 0, "Expected throttle_time_ms 0"
+    results = []
+    if api_version < 4:
+        error_code = read(">h")
+        error_message = read_string() if api_version >= 1 else None
+        node_id = read(">i")
+        host = read_string()
+        port = read(">i")
+        results.append((None, node_id, host, port, error_code, error_message))
+    else:
+        for _ in range(read(">B") - 1):
+            key = read_string()
+            node_id = read(">i")
+            host = read_string()
+            port = read(">i")
+            error_code = read(">h")
+            error_message = read_string()
+            read_tags()
+            results.append((key, node_id, host, port, error_code, error_message))
+    read_tags()
+    assert offset == len(response), f"FindCoordinator response has {len(response) - offset} trailing bytes"
+    return results
+
+def test_find_coordinator():
+    print("🧪 Testing FindCoordinator answers with this broker...")
+    client = EventorTestClient()
+    
+    def find(keys, key_type=0, api_version=5):
+        request, corr_id = client.build_find_coordinator_request(keys, key_type, api_version)
+        response = client.send_request_and_get_response(request, corr_id)
+        return parse_find_coordinator_response(response, api_version)
+    
+    for api_version in range(0, 4):
+        key_types = (0, 1) if api_version >= 1 else (0,)
+        for key_type in key_types:
+            results = find(["group-a"], key_type, api_version)
+            assert results == [(None, 1, "localhost", 9092, 0, None)], \
+                f"v{api_version} key_type {key_type}: {results}"
+    
+    for api_version in (4, 5):
+        for key_type in (0, 1):
+            keys = ["group-a", "group-b", "tx-1"]
+            results = find(keys, key_type, api_version)
+            assert results == [(key, 1, "localhost", 9092, 0, None) for key in keys], \
+                f"v{api_version} key_type {key_type}: {results}"
+        assert find([], 0, api_version) == [], f"v{api_version}: empty batch"
+    
+    for api_version in (1, 3, 5):
+        results = find(["group-a"], 5, api_version)
+        expected_key = "group-a" if api_version >= 4 else None
+        assert results == [(expected_key, -1, "", -1, 42, "Unknown coordinator key type")], \
+            f"v{api_version} unknown key type: {results}"
+    print("✅ FindCoordinator test passed")
+
+def write_session_file(path, exchanges):
+    """A session file in the format src/session.rs records, from (request, response) frames."""
+    peer = b"127.0.0.1:0"
+    data = bytearray(b"EVSS" + struct.pack(">HH", 1, len(peer)) + peer + struct.pack(">Q", 0))
+    for request, response in exchanges:
+        frame = request[4:]  # Requests are stored without their size prefix
+        data += struct.pack(">BQI", 1, 0, len(frame)) + frame
+        data += struct.pack(">BQI", 2, 0, len(response)) + response
+    with open(path, "wb") as f:
+        f.write(data)
+
+def test_snapshot_only_metadata():
+    print("🧪 Testing a metadata snapshot with no later log records...")
+    client = EventorTestClient()
+    request, corr_id = client.build_produce_request(
+        [("snap", [(0, build_record_batch([b"from-snapshot"]))])], api_version=3)
+    
+    body = bytearray(struct.pack(">I", corr_id))
+    body.extend(struct.pack(">i", 1) + struct.pack(">h", 4) + b"snap")
+    body.extend(struct.pack(">iihqq", 1, 0, 0, 0, -1))  # partition 0: no error, base_offset 0
+    body.extend(struct.pack(">i", 0))                    # throttle_time_ms
+    expected = struct.pack(">I", len(body)) + bytes(body)
+    
+    os.makedirs(SESSION_DIR, exist_ok=True)
+    path = f"{SESSION_DIR}/snapshot-only.session"
+    write_session_file(path, [(request, expected)])
+    result = subprocess.run([EVENTOR_BINARY, "replay", path, "fixtures/snapshot-only.properties"],
+                            capture_output=True, text=True)
+    assert result.returncode == 0, f"Topic from the snapshot was not loaded:\n{result.stdout}{result.stderr}"
+    assert "Loaded topic 'snap' with 1 partition(s)" in result.stdout, result.stdout
+    print("✅ Snapshot-only metadata test passed")
+
+def test_requests_before_readiness():
+    print("🧪 Testing requests that arrive before startup completes...")
+    workdir = tempfile.mkdtemp(prefix="eventor-startup-")
+    metadata_dir = os.path.join(workdir, "__cluster_metadata-0")
+    os.makedirs(metadata_dir)
+    # Loading blocks reading the segment until the test writes it, holding startup open
+    segment = os.path.join(metadata_dir, "00000000000000000000.log")
+    os.mkfifo(segment)
+    readiness_file = os.path.join(workdir, "ready")
+    liveness_file = os.path.join(workdir, "alive")
+    properties = os.path.join(workdir, "server.properties")
+    with open(properties, "w") as f:
+        f.write(f"listeners=PLAINTEXT://:{STARTUP_TEST_PORT}\n"
+                f"log.dirs={workdir}\n"
+                f"readiness.file={readiness_file}\n"
+                f"liveness.file={liveness_file}\n"
+                "liveness.interval.ms=100\n")
+    
+    log_path = os.path.join(workdir, "server.log")
+    log = open(log_path, "w")
+    server = subprocess.Popen([EVENTOR_BINARY, properties], stdout=log, stderr=subprocess.STDOUT)
+    try:
+        client = EventorTestClient(port=STARTUP_TEST_PORT)
+        deadline = time.monotonic() + 5
+        while True:
+            try:
+                client.connect().close()
+                break
+            except ConnectionRefusedError:
+                assert time.monotonic() < deadline, "Listener was never bound"
+                time.sleep(0.05)
+        
+        assert not os.path.exists(readiness_file), "Readiness file written before the metadata log was replayed"
+        request, corr_id = client.build_api_versions_request()
+        response = client.send_request_and_get_response(request, corr_id)
+        assert parse_api_versions_response(response)[0] == 0, "ApiVersions should be answered during startup"
+        # Header v1: size, correlation id and tag buffer precede the error code
+        for request, corr_id in (client.build_metadata_request(api_version=12),
+                                 client.build_produce_request([("foo", [(0, build_record_batch([b"early"]))])])):
+            response = client.send_request_and_get_response(request, corr_id)
+            error_code = struct.unpack(">h", response[9:11])[0]
+            assert error_code == 14, f"Expected COORDINATOR_LOAD_IN_PROGRESS before readiness, got {error_code}"
+        
+        deadline = time.monotonic() + 5
+        while not os.path.exists(liveness_file):
+            assert time.monotonic() < deadline, "Liveness file not written during startup"
+            time.sleep(0.05)
+        
+        with open("fixtures/kraft-combined-logs/__cluster_metadata-0/00000000000000000000.log", "rb") as f:
+            fixture = f.read()
+        with open(segment, "wb") as f:
+            f.write(fixture)
+        deadline = time.monotonic() + 5
+        while not os.path.exists(readiness_file):
+            assert time.monotonic() < deadline, "Readiness file not written after startup completed"
+            time.sleep(0.05)
+        
+        request, corr_id = client.build_metadata_request(api_version=12)
+        _, _, _, topics = parse_metadata_response(client.send_request_and_get_response(request, corr_id), 12)
+        assert {name for _, name, _, _ in topics} == {"foo", "bar", "baz"}, f"Topics after readiness: {topics}"
+        
+        touched = os.path.getmtime(liveness_file)
+        deadline = time.monotonic() + 5
+        while os.path.getmtime(liveness_file) == touched:
+            assert time.monotonic() < deadline, "Liveness file not refreshed"
+            time.sleep(0.05)
+        
+        server.terminate()
+        assert server.wait(timeout=5) == 0, "Server did not shut down cleanly on SIGTERM"
+        assert not os.path.exists(readiness_file), "Readiness file left behind after shutdown"
+        with open(log_path) as f:
+            output = f.read()
+        phases = ["configuration loaded", "listener bound", "metadata log replayed",
+                  "log directories recovered", "coordinators loaded"]
+        positions = [output.find(f"Startup phase complete: {phase}") for phase in phases]
+        assert -1 not in positions and positions == sorted(positions), f"Startup phases out of order:\n{output}"
+        assert output.find("Broker is ready") > positions[-1], "Ready before every phase completed"
+    finally:
+        if server.poll() is None:
+            server.kill()
+            server.wait()
+        log.close()
+    print("✅ Readiness test passed")
+
+def assert_bytes_equal(expected, actual, what):
+    if expected != actual:
+        raise AssertionError(
+            f"{what} differs from the expected layout\n"
+            f"expected:\n{hexdump(expected)}\nactual:\n{hexdump(actual)}")
+
+def parse_api_versions_response(response):
+    """Decodes an ApiVersions v3+ response into (error_code, {api_key: (min, max)})."""
+    error_code = struct.unpack(">h", response[8:10])[0]
+    api_count = response[10] - 1
+    offset = 11
+    api_keys = {}
+    for _ in range(api_count):
+        api_key, min_version, max_version = struct.unpack(">hhh", response[offset:offset + 6])
+        api_keys[api_key] = (min_version, max_version)
+        offset += 7  # key, min, max and an empty tag buffer
+    throttle_time_ms = struct.unpack(">i", response[offset:offset + 4])[0]
+    assert throttle_time_ms == 0, f"Expected throttle_time_ms 0, got {throttle_time_ms}"
+    assert response[offset + 4] == 0, "Expected an empty response tag buffer"
+    assert len(response) == offset + 5, "ApiVersions response has bytes past its tag buffer"
+    return error_code, api_keys
+
+# CodeCrafters stage expectations. Each test pins exactly what the remote harness
+# checks for that stage, so a refactor that breaks an earlier stage fails here first.
+
+def test_stage_correlation_id_echo():
+    print("🧪 Stage: correlation id is echoed back...")
+    client = EventorTestClient()
+    client.correlation_id_counter = 0x6f7fc661  # Arbitrary large id, like the harness uses
+    request, expected_correlation_id = client.build_api_versions_request(api_version=4)
+    response = client.send_request_and_get_response(request, expected_correlation_id)
+    assert_bytes_equal(struct.pack(">I", expected_correlation_id), response[4:8], "Correlation id")
+    print("✅ Correlation id echo stage passed")
+
+def test_stage_unsupported_api_version():
+    print("🧪 Stage: ApiVersions rejects unsupported versions...")
+    client = EventorTestClient()
+    for api_version in (5, 0x7fff):
+        request, expected_correlation_id = client.build_api_versions_request(api_version=api_version)
+        response = client.send_request_and_get_response(request, expected_correlation_id)
+        error_code = struct.unpack(">h", response[8:10])[0]
+        assert error_code == 35, f"Expected UNSUPPORTED_VERSION for v{api_version}, got {error_code}"
+    print("✅ Unsupported version stage passed")
+
+def test_stage_api_versions_entries():
+    print("🧪 Stage: ApiVersions advertises ApiVersions and DescribeTopicPartitions...")
+    client = EventorTestClient()
+    request, expected_correlation_id = client.build_api_versions_request(api_version=4)
+    response = client.send_request_and_get_response(request, expected_correlation_id)
+    error_code, api_keys = parse_api_versions_response(response)
+    assert error_code == 0, f"Expected error code 0, got {error_code}"
+    assert 18 in api_keys and api_keys[18][0] == 0 and api_keys[18][1] >= 4, \
+        f"ApiVersions entry must cover v0-v4, got {api_keys.get(18)}"
+    assert 75 in api_keys and api_keys[75][0] == 0 and api_keys[75][1] >= 0, \
+        f"DescribeTopicPartitions entry missing, got {api_keys.get(75)}"
+    print("✅ ApiVersions entries stage passed")
+
+def test_stage_describe_unknown_topic_layout():
+    print("🧪 Stage: DescribeTopicPartitions unknown-topic byte layout...")
+    client = EventorTestClient()
+    topic_name = "unknown-topic-saz"
+    request, expected_correlation_id = client.build_describe_topic_partitions_request(topic_name)
+    response = client.send_request_and_get_response(request, expected_correlation_id)
+    
+    body = bytearray()
+    body.extend(struct.pack(">I", expected_correlation_id))
+    body.extend(b"\x00")                          # Response header v1 tag buffer
+    body.extend(struct.pack(">i", 0))             # throttle_time_ms
+    body.extend(b"\x02")                          # topics: 1 entry (compact)
+    body.extend(struct.pack(">h", 3))             # UNKNOWN_TOPIC_OR_PARTITION
+    body.extend(bytes([len(topic_name) + 1]))
+    body.extend(topic_name.encode("utf-8"))
+    body.extend(bytes(16))                        # Null topic id
+    body.extend(b"\x00")                          # is_internal
+    body.extend(b"\x01")                          # partitions: empty (compact)
+    body.extend(struct.pack(">i", 0))             # topic_authorized_operations
+    body.extend(b"\x00")                          # Topic tag buffer
+    body.extend(b"\xff")                          # next_cursor: null
+    body.extend(b"\x00")                          # Response tag buffer
+    expected = struct.pack(">I", len(body)) + bytes(body)
+    
+    assert_bytes_equal(expected, response, "DescribeTopicPartitions response")
+    print("✅ Unknown topic layout stage passed")
+
+def main():
+    print("🚀 Running Eventor Server Tests")
+    print("=" * 50)
+    
+    try:
+        test_api_versions()
+        test_describe_topic_partitions()
+        test_client_id_edge_cases()
+        test_concurrent_connections()
+        test_multiple_requests()
+        test_pipelined_requests()
+        test_malformed_frames_keep_connection()
+        test_multi_byte_varints()
+        test_tagged_fields()
+        test_api_versions_legacy_layouts()
+        test_version_range_rejections()
+        test_response_size_prefixes()
+        test_stalled_reader_is_disconnected()
+        test_mid_request_stall_is_reaped()
+        test_session_record_and_replay()
+        test_produce_assigns_offsets()
+        test_produce_unknown_and_invalid_partitions()
+        test_produce_acks_zero_gets_no_response()
+        test_produce_retry_after_unclean_disconnect()
+        test_init_producer_id()
+        test_fetch_returns_produced_records()
+        test_fetch_waits_for_new_data()
+        test_fetch_leader_hints()
+        test_throttle_time_layouts()
+        test_metadata_api()
+        test_describe_cluster()
+        test_describe_configs()
+        test_incremental_alter_configs()
+        test_config_overrides_survive_restart()
+        test_list_offsets()
+        test_error_echo_limits_fuzz()
+        test_metadata_snapshot_and_log_topics()
+        test_snapshot_only_metadata()
+        test_create_topics()
+        test_describe_topic_partitions_paging()
+        test_create_partitions()
+        test_delete_records()
+        test_delete_topics()
+        test_deletes_wake_parked_fetches()
+        test_topic_registry_invariants()
+        test_recreate_under_load()
+        test_find_coordinator()
+        test_error_messages()
+        test_offset_commit()
+        test_offset_fetch()
+        test_offset_consistency_under_concurrent_commits()
+        test_duplicate_partitions()
+        test_join_group()
+        test_sync_group()
+        test_heartbeat()
+        test_leave_group()
+        test_group_offset_reset()
+        test_list_and_describe_groups()
+        test_group_memory_stays_flat()
+        test_file_log_store()
+        test_parallel_log_recovery()
+        test_flush_on_acks_all()
+        test_bench_smoke()
+        test_response_buffer_pool()
+        test_response_coalescing()
+        test_sasl_plain()
+        test_listener_policy()
+        test_client_inventory()
+        test_requests_before_readiness()
+        test_stage_correlation_id_echo()
+        test_stage_unsupported_api_version()
+        test_stage_api_versions_entries()
+        test_stage_describe_unknown_topic_layout()
+        
+        print("\n🎉 All tests passed!")
+        print("📊 Test Summary:")
+        print("  ✅ APIVersions request handling")
+        print("  ✅ DescribeTopicPartitions request handling")
+        print("  ✅ Null, empty, oversized and non-UTF-8 client ids, with the body found past them and header tags")
+        print("  ✅ Concurrent connections (5 clients)")
+        print("  ✅ Multiple requests per connection")
+        print("  ✅ Pipelined responses in request order with exact framing")
+        print("  ✅ Malformed bodies answered INVALID_REQUEST mid-pipeline; untrustworthy frames close")
+        print("  ✅ Multi-byte varint lengths, counts, tags and record fields; broken varints refused")
+        print("  ✅ Known and unknown tagged fields stepped over; misordered or repeated tags refused")
+        print("  ✅ ApiVersions v0-v2 layouts and version range rejections")
+        print("  ✅ Size prefixes of ApiVersions, DescribeTopicPartitions and unknown-API responses match their bytes")
+        print("  ✅ Stalled readers disconnected without affecting others")
+        print("  ✅ Stalled request bodies time out on schedule")
+        print("  ✅ Session recording and replay")
+        print("  ✅ Produce offsets, per-partition errors and acks=0")
+        print("  ✅ Idempotent resends after a dropped connection are deduplicated")
+        print("  ✅ InitProducerId ids for idempotent producers, transactional ids refused")
+        print("  ✅ Fetch reads back produced batches and waits for new ones")
+        print("  ✅ Fetch by topic id, leader epoch checks and current-leader hints")
+        print("  ✅ throttle_time_ms placed and zeroed per version in ApiVersions v1-v3 and Fetch v11/v16")
+        print("  ✅ Metadata brokers, cluster id and topic selection by name and id")
+        print("  ✅ DescribeCluster node, cluster id, endpoints and rack from configuration, as Metadata reports them")
+        print("  ✅ DescribeConfigs topic overrides over defaults, broker configs, key filters and per-resource errors")
+        print("  ✅ IncrementalAlterConfigs SET, DELETE, APPEND and SUBTRACT, validation, and retention.ms applied")
+        print("  ✅ Altered topic configs saved, surviving restarts and kill -9; interrupted saves and unknown formats detected")
+        print("  ✅ ListOffsets latest, earliest and timestamp lookups agree with Fetch")
+        print("  ✅ Echoed names and error partitions capped (fuzzed)")
+        print("  ✅ Topics loaded from metadata snapshots and the log after them")
+        print("  ✅ CreateTopics validation, validate_only and describing created topics")
+        print("  ✅ DescribeTopicPartitions name order and cursor paging")
+        print("  ✅ CreatePartitions growth, validate_only and per-topic rejections")
+        print("  ✅ DeleteRecords log start offsets seen by ListOffsets and Fetch, per-partition rejections")
+        print("  ✅ DeleteTopics by name and id, racing describes and produces")
+        print("  ✅ Topic deletions wake every fetch parked on them")
+        print("  ✅ Topic names and ids stay one to one; deleted ids resolve nowhere")
+        print("  ✅ Topics recreated under load start empty, with parked fetches and offsets left behind")
+        print("  ✅ FindCoordinator single and batched keys for groups and transactions")
+        print("  ✅ error_message fields explain failures without leaking server details")
+        print("  ✅ OffsetCommit per-partition errors across versions and connections")
+        print("  ✅ OffsetFetch of committed offsets, single and batched groups, null topics")
+        print("  ✅ Duplicated topics and partitions each answered in Produce, ListOffsets, Fetch and OffsetCommit")
+        print("  ✅ Answered commits visible to every later fetch, never torn or rolled back")
+        print("  ✅ JoinGroup two-step joins, shared generations, leader metadata and dropped stragglers")
+        print("  ✅ SyncGroup assignments to waiting followers, stale generations and member-only commits")
+        print("  ✅ Heartbeat generation checks and expiry of members whose sessions lapse")
+        print("  ✅ LeaveGroup single and batched leaves, rebalancing the rest and emptying groups")
+        print("  ✅ Group offset resets to earliest and to a datetime, refused while the group has members")
+        print("  ✅ ListGroups and DescribeGroups states, filters, members and unknown groups as Dead")
+        print("  ✅ Group coordinator memory flat over many rebalances")
+        print("  ✅ File-backed logs survive restarts, damaged tails cut off")
+        print("  ✅ Partitions recovered in parallel at startup; one that can't be is left offline")
+        print("  ✅ acks=-1 produces wait on shared flushes; acknowledged batches survive a crash")
+        print("  ✅ Bench smoke workload: consistent counts and an ApiVersions p99 threshold")
+        print("  ✅ Response buffer pools: no reallocations and fewer allocations per request")
+        print("  ✅ Pipelined responses coalesced into shared writes, in order, past acks=0 produces")
+        print("  ✅ SASL/PLAIN handshake, authentication, pre-auth refusals and closing on bad credentials")
+        print("  ✅ Listener protocols, admin API and API allowlists, CIDR allowlists and per-listener stats")
+        print("  ✅ Client inventory counts per software, API version ranges, sanitized names, cap, aging and dump")
+        print("  ✅ Requests before readiness told to retry; readiness and liveness files")
+        print("  ✅ Correlation ID handling")
+        print("  ✅ CodeCrafters stage expectations")
+        
+    except Exception as e:
+        print(f"\n❌ Test failed: {e}")
+        import traceback
+        traceback.print_exc()
+        exit(1)
+
+if __name__ == "__main__":
+    main()